@@ -1,3 +1,121 @@
+/// The app's commands, each of which gets `allow-` and `deny-` permissions that
+/// `capabilities/` grants to windows. Has to list everything in `generate_handler!`.
+const COMMANDS: &[&str] = &[
+  "add_overlay",
+  "set_overlay_position",
+  "set_overlay_follow_cursor",
+  "animate_overlay",
+  "create_timeline",
+  "remove_timeline",
+  "play_timeline",
+  "pause_timeline",
+  "seek_timeline",
+  "get_timeline_status",
+  "set_clear_color",
+  "set_clip_path",
+  "set_panes",
+  "set_layers",
+  "update_layer",
+  "set_uniform",
+  "get_shader_inputs",
+  "set_output_filters",
+  "set_render_graph",
+  "load_render_graph",
+  "load_map",
+  "clear_map",
+  "get_map_viewport",
+  "set_map_view",
+  "load_terrain",
+  "clear_terrain",
+  "set_terrain_models",
+  "set_terrain_camera",
+  "get_terrain_camera",
+  "create_volume",
+  "upload_volume_slab",
+  "set_transfer_function",
+  "get_transfer_function",
+  "set_volume_camera",
+  "get_volume_camera",
+  "clear_volume",
+  "create_scatterplot",
+  "upload_scatter_points",
+  "select_scatter_points",
+  "clear_scatter_selection",
+  "clear_scatterplot",
+  "set_frame_sharing",
+  "get_frame_sharing",
+  "set_ndi_output",
+  "get_ndi_output",
+  "set_overlay_attachment",
+  "set_overlay_visible",
+  "set_overlay_hud",
+  "reset_overlay_layout",
+  "set_overlay_paused",
+  "set_overlay_frame_events",
+  "set_overlay_stats_events",
+  "get_frame_stats",
+  "get_gpu_memory_usage",
+  "set_overlay_always_on_top",
+  "set_overlay_capture_excluded",
+  "set_overlay_backdrop",
+  "set_overlay_input_mode",
+  "focus_overlay",
+  "get_navigation",
+  "set_navigation",
+  "get_gamepads",
+  "set_ink_brush",
+  "clear_ink",
+  "spawn_physics_body",
+  "apply_physics_impulse",
+  "remove_physics_body",
+  "clear_physics",
+  "set_physics_gravity",
+  "get_physics_bodies",
+  "sample_pixel",
+  "get_histogram",
+  "set_overlay_drag_region",
+  "set_overlay_resizable",
+  "set_overlay_snapping",
+  "register_hotkey",
+  "unregister_hotkey",
+  "detach_overlay",
+  "reattach_overlay",
+  "get_gpu_info",
+  "get_fallback_overlays",
+  "read_fallback_image",
+  "load_asset",
+  "load_shader",
+  "filter_asset",
+  "unload_asset",
+  "get_asset",
+  "open_video",
+  "close_video",
+  "play_video",
+  "pause_video",
+  "seek_video",
+  "set_video_rate",
+  "set_video_volume",
+  "get_video_status",
+  "get_playback_stats",
+  "load_subtitles",
+  "get_subtitle_tracks",
+  "select_subtitle_track",
+  "set_subtitle_style",
+  "get_midi_ports",
+  "open_midi_port",
+  "close_midi_port",
+  "get_midi_port",
+  "learn_midi_mapping",
+  "cancel_midi_learning",
+  "get_midi_mappings",
+  "set_midi_mappings",
+  "remove_midi_mapping",
+];
+
 fn main() {
-  tauri_build::build()
+  tauri_build::try_build(
+    tauri_build::Attributes::new()
+      .app_manifest(tauri_build::AppManifest::new().commands(COMMANDS)),
+  )
+  .expect("failed to run tauri-build")
 }
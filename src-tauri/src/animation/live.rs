@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tauri::{AppHandle, Manager};
+
+use super::Property;
+use crate::Overlays;
+
+/// Property values set from outside the app, like OSC or MIDI controllers, waiting for the
+/// main thread. They can come much faster than frames, so only the latest value of each
+/// property is applied.
+#[derive(Default)]
+pub struct LiveValues {
+    values: Mutex<HashMap<(Option<String>, Property), f64>>,
+    scheduled: AtomicBool,
+}
+
+impl LiveValues {
+    /// Set `property` of the overlay called `overlay`, or the main overlay, soon.
+    pub fn set(
+        self: &Arc<Self>,
+        handle: &AppHandle,
+        overlay: Option<String>,
+        property: Property,
+        value: f64,
+    ) {
+        self.values
+            .lock()
+            .unwrap()
+            .insert((overlay, property), value);
+        if !self.scheduled.swap(true, Ordering::AcqRel) {
+            let live = self.clone();
+            let flush_handle = handle.clone();
+            crate::overlay::run_on_main_thread(handle, move || live.flush(&flush_handle));
+        }
+    }
+
+    fn flush(&self, handle: &AppHandle) {
+        // Cleared first, so values that arrive while this runs get another flush
+        self.scheduled.store(false, Ordering::Release);
+        let values = std::mem::take(&mut *self.values.lock().unwrap());
+        let mut by_overlay: HashMap<Option<String>, Vec<(Property, f64)>> = HashMap::new();
+        for ((overlay, property), value) in values {
+            by_overlay
+                .entry(overlay)
+                .or_default()
+                .push((property, value));
+        }
+        let overlays: tauri::State<Overlays> = handle.state();
+        for (id, values) in by_overlay {
+            match overlays.get(id) {
+                Ok(overlay) => crate::apply_animated(&overlay, &values),
+                Err(e) => println!("Failed to apply live values: {}", e),
+            }
+        }
+    }
+}
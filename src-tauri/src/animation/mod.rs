@@ -0,0 +1,184 @@
+//! Native animation of overlay properties. Values are stepped with every frame the overlay
+//! renders, rather than sent over IPC a step at a time.
+
+use serde::{Deserialize, Serialize};
+use tauri::{LogicalPosition, Position};
+
+use crate::overlay::{OverlayFrame, OverlayView};
+use crate::renderer::{LayerUpdate, RenderHandle, WgpuState};
+
+mod live;
+pub use live::LiveValues;
+
+mod timeline;
+pub use timeline::{Timeline, TimelineDescriptor, TimelinePayload, TimelineStatus};
+
+mod tween;
+pub use tween::Tweens;
+
+/// Something about an overlay that can be animated. In props they're named `"x"`, `"y"`,
+/// `"width"`, `"height"`, `"opacity"`, `"clearColor.r"` (or `.g`, `.b`, `.a`) and
+/// `"layers.<id>.opacity"`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Property {
+    /// The overlay's frame, in logical pixels.
+    X,
+    Y,
+    Width,
+    Height,
+    /// The whole overlay's opacity, from 0 to 1.
+    Opacity,
+    /// A channel of the clear color, from 0 (red) to 3 (alpha).
+    ClearColor(usize),
+    /// A layer's opacity, by the layer's id.
+    LayerOpacity(String),
+}
+
+impl TryFrom<String> for Property {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        let property = match name.as_str() {
+            "x" => Property::X,
+            "y" => Property::Y,
+            "width" => Property::Width,
+            "height" => Property::Height,
+            "opacity" => Property::Opacity,
+            "clearColor.r" => Property::ClearColor(0),
+            "clearColor.g" => Property::ClearColor(1),
+            "clearColor.b" => Property::ClearColor(2),
+            "clearColor.a" => Property::ClearColor(3),
+            _ => match name
+                .strip_prefix("layers.")
+                .and_then(|name| name.strip_suffix(".opacity"))
+            {
+                Some(id) if !id.is_empty() => Property::LayerOpacity(id.to_string()),
+                _ => return Err(format!("{:?} is not a property that can be animated", name)),
+            },
+        };
+        Ok(property)
+    }
+}
+
+impl From<Property> for String {
+    fn from(property: Property) -> Self {
+        match property {
+            Property::X => "x".into(),
+            Property::Y => "y".into(),
+            Property::Width => "width".into(),
+            Property::Height => "height".into(),
+            Property::Opacity => "opacity".into(),
+            Property::ClearColor(channel) => {
+                format!("clearColor.{}", ["r", "g", "b", "a"][channel])
+            }
+            Property::LayerOpacity(id) => format!("layers.{}.opacity", id),
+        }
+    }
+}
+
+impl Property {
+    /// Whether this is part of the overlay's frame, which is saved with its layout.
+    pub fn is_frame(&self) -> bool {
+        matches!(
+            self,
+            Property::X | Property::Y | Property::Width | Property::Height
+        )
+    }
+
+    /// The property's current value, or `None` if the overlay doesn't have it, like a layer
+    /// that doesn't exist. The view's `frame` and `opacity` are read beforehand, since this
+    /// runs on the render thread.
+    pub fn get(&self, frame: OverlayFrame, opacity: f64, wgpu: &WgpuState) -> Option<f64> {
+        let value = match self {
+            Property::X => frame.x,
+            Property::Y => frame.y,
+            Property::Width => frame.width,
+            Property::Height => frame.height,
+            Property::Opacity => opacity,
+            Property::ClearColor(channel) => {
+                let color = wgpu.clear_color();
+                [color.r, color.g, color.b, color.a][*channel]
+            }
+            Property::LayerOpacity(id) => wgpu.layer(id)?.opacity as f64,
+        };
+        Some(value)
+    }
+}
+
+/// How a value moves from where it starts to where it ends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    /// How far along the value is when `t` of the time has passed, both from 0 to 1.
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            // Cubic, the way CSS's keywords roughly are
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut if t < 0.5 => 4.0 * t * t * t,
+            Easing::EaseInOut => 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0,
+        }
+    }
+}
+
+/// Set animated properties to `values`. Frames are changed all at once, resizing the
+/// surface only if the size changed. The renderer's properties change on its thread.
+pub fn apply(view: &mut dyn OverlayView, renderer: &RenderHandle, values: &[(Property, f64)]) {
+    let current = view.frame();
+    let mut frame = current;
+    for (property, value) in values {
+        let value = *value;
+        match property {
+            Property::X => frame.x = value,
+            Property::Y => frame.y = value,
+            Property::Width => frame.width = value.max(1.0),
+            Property::Height => frame.height = value.max(1.0),
+            Property::Opacity => {
+                if let Err(e) = view.set_opacity(value) {
+                    println!("Failed to animate overlay opacity: {}", e);
+                }
+            }
+            Property::ClearColor(channel) => {
+                let channel = *channel;
+                renderer.post(move |wgpu| {
+                    let mut color = wgpu.clear_color();
+                    let channels = [&mut color.r, &mut color.g, &mut color.b, &mut color.a];
+                    *channels[channel] = value.clamp(0.0, 1.0);
+                    wgpu.set_clear_color(color);
+                });
+            }
+            Property::LayerOpacity(id) => {
+                let id = id.clone();
+                let update = LayerUpdate {
+                    opacity: Some(value as f32),
+                    ..LayerUpdate::default()
+                };
+                renderer.post(move |wgpu| {
+                    if let Err(e) = wgpu.update_layer(&id, update) {
+                        println!("Failed to animate layer opacity: {}", e);
+                    }
+                });
+            }
+        }
+    }
+
+    if frame.width != current.width || frame.height != current.height {
+        crate::place(view, renderer, frame);
+    } else if frame.x != current.x || frame.y != current.y {
+        view.set_origin(Position::Logical(LogicalPosition {
+            x: frame.x,
+            y: frame.y,
+        }));
+    }
+}
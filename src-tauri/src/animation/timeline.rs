@@ -0,0 +1,278 @@
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Easing, Property};
+
+/// A named, scripted animation: keyframed tracks across any number of overlays, played,
+/// paused and seeked as one.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimelineDescriptor {
+    pub id: String,
+    pub tracks: Vec<Track>,
+    /// Start over from the beginning at the end, rather than stopping.
+    #[serde(default, rename = "loop")]
+    pub looping: bool,
+}
+
+/// The overlay a track moves, its property and the value it's at.
+pub type TrackValue = (String, Property, f64);
+
+/// One property of one overlay, moving through keyframes.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Track {
+    pub overlay: String,
+    pub property: Property,
+    pub keyframes: Vec<Keyframe>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Keyframe {
+    /// Seconds from the start of the timeline.
+    pub time: f64,
+    pub value: f64,
+    /// How the value gets here from the keyframe before.
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+impl Track {
+    /// The value at `time`, holding the first and last keyframes' values outside of them.
+    fn value_at(&self, time: f64) -> f64 {
+        let next = self
+            .keyframes
+            .iter()
+            .position(|keyframe| keyframe.time > time);
+        match next {
+            Some(0) => self.keyframes[0].value,
+            Some(i) => {
+                let (from, to) = (self.keyframes[i - 1], self.keyframes[i]);
+                let t = to.easing.apply((time - from.time) / (to.time - from.time));
+                from.value + (to.value - from.value) * t
+            }
+            None => self.keyframes[self.keyframes.len() - 1].value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimelineStatus {
+    /// Seconds from the start.
+    pub position: f64,
+    pub duration: f64,
+    pub playing: bool,
+}
+
+/// Emitted as `timeline://ended` when a timeline that doesn't loop reaches its end.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelinePayload {
+    pub timeline: String,
+}
+
+/// A timeline's tracks and where its playback is.
+pub struct Timeline {
+    descriptor: TimelineDescriptor,
+    duration: f64,
+    /// Where playback was when it was last played, paused or seeked.
+    position: f64,
+    /// When playback last started, while it's playing.
+    playing_since: Option<Instant>,
+    /// Set when the values need applying even though the timeline isn't playing.
+    dirty: bool,
+}
+
+impl Timeline {
+    /// Check and sort the keyframes. The timeline starts paused at the beginning, leaving
+    /// its properties alone until it's played or seeked.
+    pub fn new(mut descriptor: TimelineDescriptor) -> Result<Self, String> {
+        for track in &mut descriptor.tracks {
+            if track.keyframes.is_empty() {
+                return Err(format!("the {:?} track has no keyframes", track.property));
+            }
+            if let Some(keyframe) = track
+                .keyframes
+                .iter()
+                .find(|keyframe| !(keyframe.time >= 0.0 && keyframe.time.is_finite()))
+            {
+                return Err(format!("invalid keyframe time {}", keyframe.time));
+            }
+            track
+                .keyframes
+                .sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+        }
+        let duration = descriptor
+            .tracks
+            .iter()
+            .flat_map(|track| track.keyframes.last())
+            .fold(0.0, |duration: f64, keyframe| duration.max(keyframe.time));
+        Ok(Timeline {
+            descriptor,
+            duration,
+            position: 0.0,
+            playing_since: None,
+            dirty: false,
+        })
+    }
+
+    /// Play from where the timeline is, or from the beginning if it ended.
+    pub fn play(&mut self) {
+        if self.playing_since.is_some() {
+            return;
+        }
+        if !self.descriptor.looping && self.position >= self.duration {
+            self.position = 0.0;
+        }
+        self.playing_since = Some(Instant::now());
+    }
+
+    pub fn pause(&mut self) {
+        self.position = self.position_at(Instant::now());
+        self.playing_since = None;
+    }
+
+    /// Jump to `time` seconds from the start, carrying on playing if it was.
+    pub fn seek(&mut self, time: f64) {
+        self.position = time.clamp(0.0, self.duration);
+        if self.playing_since.is_some() {
+            self.playing_since = Some(Instant::now());
+        }
+        self.dirty = true;
+    }
+
+    pub fn status(&self) -> TimelineStatus {
+        TimelineStatus {
+            position: self.position_at(Instant::now()),
+            duration: self.duration,
+            playing: self.playing_since.is_some(),
+        }
+    }
+
+    fn position_at(&self, now: Instant) -> f64 {
+        let position = match self.playing_since {
+            Some(since) => self.position + (now - since).as_secs_f64(),
+            None => self.position,
+        };
+        if self.descriptor.looping && self.duration > 0.0 {
+            position % self.duration
+        } else {
+            position.min(self.duration)
+        }
+    }
+
+    /// Every track's overlay, property and value now, if they need applying, and whether
+    /// the timeline has just ended.
+    pub fn step(&mut self) -> Option<(Vec<TrackValue>, bool)> {
+        if self.playing_since.is_none() && !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+        let now = Instant::now();
+        let position = self.position_at(now);
+        let ended = match self.playing_since {
+            Some(since) if !self.descriptor.looping => {
+                self.position + (now - since).as_secs_f64() >= self.duration
+            }
+            _ => false,
+        };
+        if ended {
+            self.position = self.duration;
+            self.playing_since = None;
+        }
+        let values = self
+            .descriptor
+            .tracks
+            .iter()
+            .map(|track| {
+                (
+                    track.overlay.clone(),
+                    track.property.clone(),
+                    track.value_at(position),
+                )
+            })
+            .collect();
+        Some((values, ended))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(time: f64, value: f64) -> Keyframe {
+        Keyframe {
+            time,
+            value,
+            easing: Easing::Linear,
+        }
+    }
+
+    fn timeline(keyframes: Vec<Keyframe>, looping: bool) -> Result<Timeline, String> {
+        Timeline::new(TimelineDescriptor {
+            id: "intro".into(),
+            tracks: vec![Track {
+                overlay: "main".into(),
+                property: Property::Opacity,
+                keyframes,
+            }],
+            looping,
+        })
+    }
+
+    #[test]
+    fn holds_the_first_and_last_values_outside_the_keyframes() {
+        let timeline = timeline(vec![keyframe(1.0, 0.0), keyframe(3.0, 1.0)], false).unwrap();
+        let track = &timeline.descriptor.tracks[0];
+        assert_eq!(track.value_at(0.0), 0.0);
+        assert_eq!(track.value_at(2.0), 0.5);
+        assert_eq!(track.value_at(4.0), 1.0);
+    }
+
+    #[test]
+    fn sorts_keyframes_and_takes_the_last_as_the_duration() {
+        let timeline = timeline(vec![keyframe(4.0, 1.0), keyframe(0.0, 0.0)], false).unwrap();
+        assert_eq!(timeline.duration, 4.0);
+        assert_eq!(timeline.descriptor.tracks[0].value_at(1.0), 0.25);
+    }
+
+    #[test]
+    fn rejects_empty_tracks_and_bad_times() {
+        assert!(timeline(Vec::new(), false).is_err());
+        assert!(timeline(vec![keyframe(-1.0, 0.0)], false).is_err());
+        assert!(timeline(vec![keyframe(f64::NAN, 0.0)], false).is_err());
+    }
+
+    #[test]
+    fn applies_values_once_when_seeked_while_paused() {
+        let mut timeline = timeline(vec![keyframe(0.0, 0.0), keyframe(2.0, 1.0)], false).unwrap();
+        assert!(timeline.step().is_none());
+        timeline.seek(1.0);
+        let (values, ended) = timeline.step().unwrap();
+        assert_eq!(values, [("main".to_string(), Property::Opacity, 0.5)]);
+        assert!(!ended);
+        assert!(timeline.step().is_none());
+    }
+
+    #[test]
+    fn ends_at_the_end_unless_it_loops() {
+        let mut once = timeline(vec![keyframe(0.0, 0.0), keyframe(1.0, 1.0)], false).unwrap();
+        once.seek(1.0);
+        once.play();
+        // Played from the start again, since it had ended
+        assert!(once.status().position < 0.5);
+        once.seek(1.0);
+        let (_, ended) = once.step().unwrap();
+        assert!(ended);
+        assert!(!once.status().playing);
+
+        let mut looping = timeline(vec![keyframe(0.0, 0.0), keyframe(1.0, 1.0)], true).unwrap();
+        looping.play();
+        looping.seek(1.0);
+        let (_, ended) = looping.step().unwrap();
+        assert!(!ended);
+        assert!(looping.status().playing);
+    }
+}
@@ -0,0 +1,141 @@
+use std::time::{Duration, Instant};
+
+use super::{Easing, Property};
+
+/// A property moving from one value to another.
+#[derive(Debug)]
+struct Tween {
+    property: Property,
+    from: f64,
+    to: f64,
+    start: Instant,
+    duration: Duration,
+    easing: Easing,
+}
+
+impl Tween {
+    /// The value at `now`, and whether the tween is over.
+    fn value_at(&self, now: Instant) -> (f64, bool) {
+        let elapsed = now.saturating_duration_since(self.start);
+        if elapsed >= self.duration {
+            return (self.to, true);
+        }
+        let t = self
+            .easing
+            .apply(elapsed.as_secs_f64() / self.duration.as_secs_f64());
+        (self.from + (self.to - self.from) * t, false)
+    }
+}
+
+/// An overlay's running tweens, stepped with every frame until they're over.
+#[derive(Debug, Default)]
+pub struct Tweens {
+    tweens: Vec<Tween>,
+    /// Set while a step is waiting for the main thread, so slow frames don't queue up more.
+    pub pending: bool,
+}
+
+impl Tweens {
+    /// Move `property` from `from` to `to` over `duration`, taking over from any tween that
+    /// was moving it already.
+    pub fn start(
+        &mut self,
+        property: Property,
+        from: f64,
+        to: f64,
+        duration: Duration,
+        easing: Easing,
+    ) {
+        self.tweens.retain(|tween| tween.property != property);
+        self.tweens.push(Tween {
+            property,
+            from,
+            to,
+            start: Instant::now(),
+            duration,
+            easing,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tweens.is_empty()
+    }
+
+    /// Where each tweened property should be now. Tweens that have reached the end are
+    /// dropped, after this last step.
+    pub fn step(&mut self) -> Vec<(Property, f64)> {
+        let now = Instant::now();
+        let mut values = Vec::with_capacity(self.tweens.len());
+        self.tweens.retain(|tween| {
+            let (value, finished) = tween.value_at(now);
+            values.push((tween.property.clone(), value));
+            !finished
+        });
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tween(easing: Easing) -> Tween {
+        Tween {
+            property: Property::Opacity,
+            from: 10.0,
+            to: 20.0,
+            start: Instant::now(),
+            duration: Duration::from_secs(2),
+            easing,
+        }
+    }
+
+    #[test]
+    fn moves_along_its_easing() {
+        let linear = tween(Easing::Linear);
+        assert_eq!(linear.value_at(linear.start), (10.0, false));
+        assert_eq!(
+            linear.value_at(linear.start + Duration::from_millis(500)),
+            (12.5, false)
+        );
+        let ease_in = tween(Easing::EaseIn);
+        assert_eq!(
+            ease_in.value_at(ease_in.start + Duration::from_secs(1)),
+            (11.25, false)
+        );
+    }
+
+    #[test]
+    fn ends_exactly_at_its_target() {
+        let tween = tween(Easing::EaseInOut);
+        assert_eq!(tween.value_at(tween.start + tween.duration), (20.0, true));
+        assert_eq!(
+            tween.value_at(tween.start + Duration::from_secs(5)),
+            (20.0, true)
+        );
+    }
+
+    #[test]
+    fn takes_over_from_a_tween_of_the_same_property() {
+        let mut tweens = Tweens::default();
+        let long = Duration::from_secs(60);
+        tweens.start(Property::X, 0.0, 100.0, long, Easing::Linear);
+        tweens.start(Property::Y, 0.0, 100.0, long, Easing::Linear);
+        tweens.start(Property::X, 50.0, 60.0, long, Easing::Linear);
+        let values = tweens.step();
+        assert_eq!(values.len(), 2);
+        let (_, x) = values
+            .iter()
+            .find(|(property, _)| *property == Property::X)
+            .unwrap();
+        assert!((50.0..51.0).contains(x));
+    }
+
+    #[test]
+    fn drops_tweens_after_their_last_step() {
+        let mut tweens = Tweens::default();
+        tweens.start(Property::Width, 1.0, 2.0, Duration::ZERO, Easing::Linear);
+        assert_eq!(tweens.step(), [(Property::Width, 2.0)]);
+        assert!(tweens.is_empty());
+    }
+}
@@ -0,0 +1,7 @@
+use ab_glyph::FontArc;
+
+/// Parse a TrueType or OpenType font. Glyphs are rasterized by whatever draws text with it,
+/// so nothing goes to the GPU yet.
+pub fn decode(bytes: Vec<u8>) -> Result<FontArc, String> {
+    FontArc::try_from_vec(bytes).map_err(|e| format!("failed to parse font: {}", e))
+}
@@ -0,0 +1,524 @@
+//! ISF (Interactive Shader Format) shaders: GLSL fragment shaders with a JSON header that
+//! declares their inputs. They're translated to WGSL when they're loaded, with the inputs
+//! gathered into a uniform buffer after ISF's built-in uniforms.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::renderer::GpuContext;
+
+/// Where ISF's built-in uniforms are in the uniform buffer. Matches the block in
+/// [`GLSL_PRELUDE`].
+const DATE_OFFSET: usize = 0;
+const RENDERSIZE_OFFSET: usize = 16;
+const TIME_OFFSET: usize = 24;
+const TIMEDELTA_OFFSET: usize = 28;
+const PASSINDEX_OFFSET: usize = 32;
+const FRAMEINDEX_OFFSET: usize = 36;
+/// Where the declared inputs start.
+const INPUTS_OFFSET: usize = 40;
+/// The uniform buffer is binding 0 and the sampler binding 1, and then each image input
+/// gets a binding of its own.
+pub const FIRST_IMAGE_BINDING: u32 = 2;
+
+const GLSL_PRELUDE: &str = "#version 450
+layout(set = 0, binding = 0) uniform IsfUniforms {
+    vec4 DATE;
+    vec2 RENDERSIZE;
+    float TIME;
+    float TIMEDELTA;
+    int PASSINDEX;
+    int FRAMEINDEX;
+";
+
+/// ISF's image functions, written against separate textures and a shared sampler, and
+/// the coordinates its shaders expect.
+const GLSL_MACROS: &str = "layout(set = 0, binding = 1) uniform sampler isf_sampler;
+layout(location = 0) in vec2 isf_FragNormCoord;
+layout(location = 0) out vec4 isf_FragColor;
+vec4 isf_FragCoord;
+#define IMG_NORM_PIXEL(image, coord) texture(sampler2D(image, isf_sampler), coord)
+#define IMG_SIZE(image) vec2(textureSize(sampler2D(image, isf_sampler), 0))
+#define IMG_PIXEL(image, coord) IMG_NORM_PIXEL(image, (coord) / IMG_SIZE(image))
+#define IMG_THIS_NORM_PIXEL(image) IMG_NORM_PIXEL(image, isf_FragNormCoord)
+#define IMG_THIS_PIXEL(image) IMG_THIS_NORM_PIXEL(image)
+#define vv_FragNormCoord isf_FragNormCoord
+";
+
+/// ISF puts the origin at the bottom left, where WebGPU puts it at the top left.
+const GLSL_MAIN: &str = "
+void main() {
+    isf_FragCoord = vec4(gl_FragCoord.x, RENDERSIZE.y - gl_FragCoord.y, gl_FragCoord.z, gl_FragCoord.w);
+    isf_main();
+}
+";
+
+/// The types of input an ISF shader can declare. Audio inputs aren't supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IsfInputType {
+    Float,
+    Bool,
+    Long,
+    /// A bool that's true for the one frame after it's set.
+    Event,
+    Color,
+    Point2D,
+    /// Set to the id of a texture asset.
+    Image,
+}
+
+impl IsfInputType {
+    /// Size and alignment in the uniform buffer, which are the same for every type.
+    fn size(self) -> Option<usize> {
+        match self {
+            IsfInputType::Float | IsfInputType::Bool | IsfInputType::Long | IsfInputType::Event => {
+                Some(4)
+            }
+            IsfInputType::Point2D => Some(8),
+            IsfInputType::Color => Some(16),
+            IsfInputType::Image => None,
+        }
+    }
+}
+
+/// An input from an ISF shader's header, as `get_shader_inputs` reports it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IsfInput {
+    #[serde(rename(deserialize = "NAME"))]
+    pub name: String,
+    #[serde(rename(deserialize = "TYPE"), rename(serialize = "type"))]
+    pub kind: IsfInputType,
+    #[serde(rename(deserialize = "LABEL"), default)]
+    pub label: Option<String>,
+    #[serde(rename(deserialize = "DEFAULT"), default)]
+    pub default: Option<Value>,
+    #[serde(rename(deserialize = "MIN"), default)]
+    pub min: Option<Value>,
+    #[serde(rename(deserialize = "MAX"), default)]
+    pub max: Option<Value>,
+    /// The choices for a long input, which is usually shown as a menu.
+    #[serde(rename(deserialize = "VALUES"), default)]
+    pub values: Option<Vec<i32>>,
+    #[serde(rename(deserialize = "LABELS"), default)]
+    pub labels: Option<Vec<String>>,
+}
+
+impl IsfInput {
+    /// The input's value before it's set, from its header.
+    pub fn initial_value(&self) -> Value {
+        if let Some(default) = &self.default {
+            return default.clone();
+        }
+        match self.kind {
+            IsfInputType::Float => Value::from(0.0),
+            IsfInputType::Bool | IsfInputType::Event => Value::from(false),
+            IsfInputType::Long => Value::from(
+                self.values
+                    .as_ref()
+                    .map_or(0, |values| values.first().copied().unwrap_or(0)),
+            ),
+            IsfInputType::Color => Value::from(vec![0.0, 0.0, 0.0, 1.0]),
+            IsfInputType::Point2D => Value::from(vec![0.0, 0.0]),
+            IsfInputType::Image => Value::Null,
+        }
+    }
+
+    /// `value` as it's laid out in the uniform buffer, or an error if it isn't a value of
+    /// this input's type. Image inputs aren't in the buffer.
+    pub fn encode(&self, value: &Value) -> Result<Vec<u8>, String> {
+        let numbers = |count: usize| -> Result<Vec<u8>, String> {
+            let numbers = value
+                .as_array()
+                .filter(|numbers| numbers.len() == count)
+                .and_then(|numbers| {
+                    numbers
+                        .iter()
+                        .map(|number| number.as_f64().map(|number| number as f32))
+                        .collect::<Option<Vec<f32>>>()
+                })
+                .ok_or_else(|| format!("input '{}' takes {} numbers", self.name, count))?;
+            Ok(bytemuck::cast_slice(&numbers).to_vec())
+        };
+        match self.kind {
+            IsfInputType::Float => value
+                .as_f64()
+                .map(|number| (number as f32).to_ne_bytes().to_vec())
+                .ok_or_else(|| format!("input '{}' takes a number", self.name)),
+            IsfInputType::Bool | IsfInputType::Event => value
+                .as_bool()
+                .or_else(|| value.as_f64().map(|number| number != 0.0))
+                .map(|flag| (flag as i32).to_ne_bytes().to_vec())
+                .ok_or_else(|| format!("input '{}' takes true or false", self.name)),
+            IsfInputType::Long => value
+                .as_f64()
+                .map(|number| (number.round() as i32).to_ne_bytes().to_vec())
+                .ok_or_else(|| format!("input '{}' takes a whole number", self.name)),
+            IsfInputType::Color => numbers(4),
+            IsfInputType::Point2D => numbers(2),
+            IsfInputType::Image => Err(format!("input '{}' is an image", self.name)),
+        }
+    }
+}
+
+/// ISF's built-in uniforms for a frame.
+#[derive(Debug, Clone, Copy)]
+pub struct IsfFrame {
+    /// Year, month, day and seconds since midnight.
+    pub date: [f32; 4],
+    /// The size of what's drawn to, in physical pixels.
+    pub render_size: [f32; 2],
+    /// Seconds since the shader started.
+    pub time: f32,
+    /// Seconds since the last frame.
+    pub time_delta: f32,
+    pub frame_index: i32,
+}
+
+impl IsfFrame {
+    /// Write the built-ins over the start of a uniform buffer's contents. There's only
+    /// ever one pass, so `PASSINDEX` is always 0.
+    pub fn write(&self, uniforms: &mut [u8]) {
+        let mut put = |offset: usize, bytes: &[u8]| {
+            uniforms[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+        put(DATE_OFFSET, bytemuck::cast_slice(&self.date));
+        put(RENDERSIZE_OFFSET, bytemuck::cast_slice(&self.render_size));
+        put(TIME_OFFSET, &self.time.to_ne_bytes());
+        put(TIMEDELTA_OFFSET, &self.time_delta.to_ne_bytes());
+        put(PASSINDEX_OFFSET, &0i32.to_ne_bytes());
+        put(FRAMEINDEX_OFFSET, &self.frame_index.to_ne_bytes());
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct IsfHeader {
+    #[serde(rename = "INPUTS", default)]
+    inputs: Vec<IsfInput>,
+    #[serde(rename = "PASSES", default)]
+    passes: Vec<Value>,
+    #[serde(rename = "IMPORTED", default)]
+    imported: Value,
+}
+
+/// A loaded ISF shader, translated to WGSL and compiled.
+pub struct IsfAsset {
+    pub module: wgpu::ShaderModule,
+    /// What `module` was compiled from, which its pipelines are keyed on.
+    pub wgsl: String,
+    pub inputs: Vec<IsfInput>,
+    /// Where each input is in the uniform buffer, and `None` for images.
+    pub offsets: Vec<Option<usize>>,
+    /// How big the uniform buffer is.
+    pub uniforms_size: usize,
+}
+
+impl IsfAsset {
+    /// The binding of each image input, in the order they're declared.
+    pub fn image_bindings(&self) -> impl Iterator<Item = (usize, u32)> + '_ {
+        self.inputs
+            .iter()
+            .enumerate()
+            .filter(|(_, input)| input.kind == IsfInputType::Image)
+            .zip(FIRST_IMAGE_BINDING..)
+            .map(|((index, _), binding)| (index, binding))
+    }
+}
+
+/// An ISF shader's WGSL, and where its inputs ended up.
+struct Translation {
+    wgsl: String,
+    inputs: Vec<IsfInput>,
+    offsets: Vec<Option<usize>>,
+    uniforms_size: usize,
+}
+
+pub fn decode(gpu: &GpuContext, label: &str, bytes: &[u8]) -> Result<IsfAsset, String> {
+    let source = std::str::from_utf8(bytes).map_err(|e| format!("shader isn't UTF-8: {}", e))?;
+    let translation = translate(source)?;
+
+    let scope = gpu.device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let module = gpu
+        .device
+        .create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(translation.wgsl.as_str().into()),
+        });
+    if let Some(e) = pollster::block_on(scope.pop()) {
+        return Err(format!("invalid shader: {}", e));
+    }
+    Ok(IsfAsset {
+        module,
+        wgsl: translation.wgsl,
+        inputs: translation.inputs,
+        offsets: translation.offsets,
+        uniforms_size: translation.uniforms_size,
+    })
+}
+
+fn translate(source: &str) -> Result<Translation, String> {
+    let (header, body) = split_header(source)?;
+    if header.passes.len() > 1 {
+        return Err("ISF shaders with more than one pass aren't supported".into());
+    }
+    if header
+        .imported
+        .as_object()
+        .is_some_and(|imported| !imported.is_empty())
+    {
+        return Err("ISF shaders with imported images aren't supported".into());
+    }
+
+    let mut glsl = String::from(GLSL_PRELUDE);
+    let mut defines = String::new();
+    let mut images = String::new();
+    let mut offsets = Vec::with_capacity(header.inputs.len());
+    let mut offset = INPUTS_OFFSET;
+    let mut binding = FIRST_IMAGE_BINDING;
+    for input in &header.inputs {
+        let name = &input.name;
+        if !is_identifier(name) || name.starts_with("isf_") {
+            return Err(format!("'{}' can't be the name of an input", name));
+        }
+        match input.kind.size() {
+            Some(size) => {
+                offset = offset.div_ceil(size) * size;
+                offsets.push(Some(offset));
+                offset += size;
+            }
+            None => offsets.push(None),
+        }
+        match input.kind {
+            IsfInputType::Float => glsl.push_str(&format!("    float {};\n", name)),
+            IsfInputType::Long => glsl.push_str(&format!("    int {};\n", name)),
+            // Uniform buffers can't hold bools, so they're ints compared when used
+            IsfInputType::Bool | IsfInputType::Event => {
+                glsl.push_str(&format!("    int isf_flag_{};\n", name));
+                defines.push_str(&format!("#define {} (isf_flag_{} != 0)\n", name, name));
+            }
+            IsfInputType::Color => glsl.push_str(&format!("    vec4 {};\n", name)),
+            IsfInputType::Point2D => glsl.push_str(&format!("    vec2 {};\n", name)),
+            IsfInputType::Image => {
+                images.push_str(&format!(
+                    "layout(set = 0, binding = {}) uniform texture2D {};\n",
+                    binding, name
+                ));
+                binding += 1;
+            }
+        }
+    }
+    glsl.push_str("};\n");
+    glsl.push_str(&images);
+    glsl.push_str(GLSL_MACROS);
+    glsl.push_str(&defines);
+
+    // The shader's own main is called from ours, after the coordinates are set up
+    let body: String = body
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("#version"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let body = rename_identifier(&body, "main", "isf_main");
+    let body = rename_identifier(&body, "gl_FragColor", "isf_FragColor");
+    let body = rename_identifier(&body, "gl_FragCoord", "isf_FragCoord");
+    glsl.push_str(&body);
+    glsl.push_str(GLSL_MAIN);
+
+    // Translated here rather than by wgpu, which panics on GLSL it can't parse
+    let options = naga::front::glsl::Options::from(naga::ShaderStage::Fragment);
+    let module = naga::front::glsl::Frontend::default()
+        .parse(&options, &glsl)
+        .map_err(|e| {
+            let errors: Vec<String> = e.errors.iter().map(|e| e.to_string()).collect();
+            format!("invalid shader: {}", errors.join("; "))
+        })?;
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::empty(),
+    )
+    .validate(&module)
+    .map_err(|e| format!("invalid shader: {}", e))?;
+    let wgsl =
+        naga::back::wgsl::write_string(&module, &info, naga::back::wgsl::WriterFlags::empty())
+            .map_err(|e| format!("failed to translate shader: {}", e))?;
+
+    Ok(Translation {
+        wgsl,
+        inputs: header.inputs,
+        offsets,
+        // Uniform buffers are allocated in 16 byte blocks
+        uniforms_size: offset.div_ceil(16) * 16,
+    })
+}
+
+/// The JSON header in the comment that starts an ISF shader, and the GLSL after it.
+fn split_header(source: &str) -> Result<(IsfHeader, &str), String> {
+    let start = source
+        .find("/*")
+        .filter(|start| source[..*start].trim().is_empty())
+        .ok_or("ISF shaders start with a comment holding their JSON header")?;
+    let end = source[start..]
+        .find("*/")
+        .map(|end| start + end)
+        .ok_or("the ISF header's comment doesn't end")?;
+    let header = serde_json::from_str(&source[start + 2..end])
+        .map_err(|e| format!("invalid ISF header: {}", e))?;
+    Ok((header, &source[end + 2..]))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `source` with every whole identifier `from` replaced with `to`.
+fn rename_identifier(source: &str, from: &str, to: &str) -> String {
+    let mut renamed = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        renamed.push_str(&rest[..start]);
+        let identifier = &rest[start..];
+        let end = identifier
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(identifier.len());
+        // Digits before it make it part of a number, like the e in 1e5
+        let in_number = renamed
+            .chars()
+            .last()
+            .is_some_and(|c| c.is_ascii_digit() || c == '.');
+        if &identifier[..end] == from && !in_number {
+            renamed.push_str(to);
+        } else {
+            renamed.push_str(&identifier[..end]);
+        }
+        rest = &identifier[end..];
+    }
+    renamed.push_str(rest);
+    renamed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &str = "void main() { gl_FragColor = vec4(isf_FragNormCoord, 0.0, 1.0); }";
+
+    fn shader(header: &str) -> String {
+        format!("/*{}*/\n{}", header, BODY)
+    }
+
+    #[test]
+    fn translates_without_inputs() {
+        let translation = translate(&shader("{}")).unwrap();
+        assert!(translation.inputs.is_empty());
+        assert_eq!(translation.uniforms_size, 48);
+        assert!(translation.wgsl.contains("fn main"));
+    }
+
+    #[test]
+    fn lays_out_inputs_after_the_built_ins() {
+        let translation = translate(&shader(
+            r#"{"INPUTS": [
+                {"NAME": "amount", "TYPE": "float"},
+                {"NAME": "tint", "TYPE": "color"},
+                {"NAME": "source", "TYPE": "image"},
+                {"NAME": "center", "TYPE": "point2D"},
+                {"NAME": "invert", "TYPE": "bool"}
+            ]}"#,
+        ))
+        .unwrap();
+        assert_eq!(
+            translation.offsets,
+            [Some(40), Some(48), None, Some(64), Some(72)]
+        );
+        assert_eq!(translation.uniforms_size, 80);
+    }
+
+    #[test]
+    fn starts_inputs_at_their_defaults() {
+        let (header, _) = split_header(&shader(
+            r#"{"INPUTS": [
+                {"NAME": "amount", "TYPE": "float", "DEFAULT": 0.5},
+                {"NAME": "mode", "TYPE": "long", "VALUES": [3, 4]},
+                {"NAME": "tint", "TYPE": "color"},
+                {"NAME": "source", "TYPE": "image"}
+            ]}"#,
+        ))
+        .unwrap();
+        let values: Vec<Value> = header.inputs.iter().map(IsfInput::initial_value).collect();
+        assert_eq!(
+            values,
+            [
+                Value::from(0.5),
+                Value::from(3),
+                Value::from(vec![0.0, 0.0, 0.0, 1.0]),
+                Value::Null,
+            ]
+        );
+    }
+
+    #[test]
+    fn takes_one_pass_but_not_more() {
+        assert!(translate(&shader(r#"{"PASSES": [{}]}"#)).is_ok());
+        assert!(translate(&shader(r#"{"PASSES": [{}, {"TARGET": "buffer"}]}"#)).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_headers_and_names() {
+        assert!(translate(BODY).is_err());
+        assert!(translate("/* {} ").is_err());
+        assert!(translate(&shader("{not json}")).is_err());
+        assert!(translate(&shader(
+            r#"{"INPUTS": [{"NAME": "isf_x", "TYPE": "float"}]}"#
+        ))
+        .is_err());
+        assert!(translate(&shader(r#"{"INPUTS": [{"NAME": "1x", "TYPE": "float"}]}"#)).is_err());
+        assert!(translate(&shader(r#"{"IMPORTED": {"noise": {"PATH": "noise.png"}}}"#)).is_err());
+    }
+
+    #[test]
+    fn encodes_values_by_type() {
+        let input = |kind| IsfInput {
+            name: "input".into(),
+            kind,
+            label: None,
+            default: None,
+            min: None,
+            max: None,
+            values: None,
+            labels: None,
+        };
+        let bool_input = input(IsfInputType::Bool);
+        assert_eq!(
+            bool_input.encode(&Value::from(true)).unwrap(),
+            1i32.to_ne_bytes()
+        );
+        assert_eq!(
+            input(IsfInputType::Long).encode(&Value::from(2.6)).unwrap(),
+            3i32.to_ne_bytes()
+        );
+        assert_eq!(
+            input(IsfInputType::Point2D)
+                .encode(&Value::from(vec![1.0, 2.0]))
+                .unwrap()
+                .len(),
+            8
+        );
+        assert!(input(IsfInputType::Color)
+            .encode(&Value::from(vec![1.0, 2.0]))
+            .is_err());
+        assert!(input(IsfInputType::Image).encode(&Value::Null).is_err());
+    }
+
+    #[test]
+    fn renames_whole_identifiers_only() {
+        assert_eq!(
+            rename_identifier("void main() { mainly(); }", "main", "isf_main"),
+            "void isf_main() { mainly(); }"
+        );
+        assert_eq!(rename_identifier("1e5 + e", "e", "f"), "1e5 + f");
+    }
+}
@@ -0,0 +1,470 @@
+//! Textures, shaders, models and fonts, loaded from files on a background pool and kept
+//! ready on the shared GPU for renderers to use.
+
+mod font;
+mod isf;
+mod model;
+mod shader;
+mod texture;
+
+pub use isf::{IsfAsset, IsfFrame, IsfInput, IsfInputType};
+pub use model::{ModelAsset, ModelVertex};
+pub use shader::ShaderDiagnostic;
+use texture::TextureAsset;
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::renderer::{self, Filter, GpuContext, MemoryUsage};
+use crate::Gpu;
+
+/// Files are read in chunks of this many bytes, with a progress event after each.
+const CHUNK_SIZE: usize = 256 * 1024;
+/// How often the files of watched assets are checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AssetKind {
+    /// A PNG or JPEG image.
+    Texture,
+    /// WGSL source.
+    Shader,
+    /// A Wavefront OBJ file.
+    Model,
+    /// A TrueType or OpenType font.
+    Font,
+    /// An ISF fragment shader, with the JSON header declaring its inputs.
+    Isf,
+}
+
+impl AssetKind {
+    /// Whether edits to the file are loaded as they're saved.
+    fn is_watched(self) -> bool {
+        matches!(self, AssetKind::Texture | AssetKind::Model)
+    }
+}
+
+/// A loaded asset, ready to draw with.
+pub enum Asset {
+    Texture(TextureAsset),
+    Shader(wgpu::ShaderModule),
+    Model(ModelAsset),
+    Font(ab_glyph::FontArc),
+    Isf(IsfAsset),
+}
+
+impl Asset {
+    fn decode(
+        kind: AssetKind,
+        gpu: &GpuContext,
+        label: &str,
+        bytes: Vec<u8>,
+    ) -> Result<Self, LoadError> {
+        let asset = match kind {
+            AssetKind::Texture => texture::decode(gpu, label, &bytes).map(Asset::Texture),
+            AssetKind::Shader => return shader::decode(gpu, label, &bytes).map(Asset::Shader),
+            AssetKind::Model => model::decode(gpu, label, &bytes).map(Asset::Model),
+            AssetKind::Font => font::decode(bytes).map(Asset::Font),
+            AssetKind::Isf => isf::decode(gpu, label, &bytes).map(Asset::Isf),
+        };
+        Ok(asset?)
+    }
+
+    pub fn texture(&self) -> Option<&TextureAsset> {
+        match self {
+            Asset::Texture(texture) => Some(texture),
+            _ => None,
+        }
+    }
+
+    pub fn model(&self) -> Option<&ModelAsset> {
+        match self {
+            Asset::Model(model) => Some(model),
+            _ => None,
+        }
+    }
+
+    pub fn font(&self) -> Option<&ab_glyph::FontArc> {
+        match self {
+            Asset::Font(font) => Some(font),
+            _ => None,
+        }
+    }
+
+    pub fn isf(&self) -> Option<&IsfAsset> {
+        match self {
+            Asset::Isf(isf) => Some(isf),
+            _ => None,
+        }
+    }
+
+    fn memory(&self, usage: &mut MemoryUsage) {
+        match self {
+            Asset::Texture(texture) => texture.memory(usage),
+            Asset::Model(model) => model.memory(usage),
+            Asset::Shader(_) | Asset::Font(_) | Asset::Isf(_) => {}
+        }
+    }
+}
+
+/// Emitted as `asset://progress` while an asset's file is read.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AssetProgress {
+    id: String,
+    loaded: u64,
+    total: u64,
+}
+
+/// Emitted as `asset://loaded`, and what `get_asset` returns.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetInfo {
+    pub id: String,
+    pub kind: AssetKind,
+    pub path: PathBuf,
+    /// BLAKE3 hash of the file's contents, in hex.
+    pub hash: String,
+    /// Whether an already loaded asset with the same contents was reused.
+    pub cached: bool,
+    /// A texture's width and height in pixels.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<[u32; 2]>,
+    /// For a filtered texture, the id of the asset it was filtered from. Its path and
+    /// hash are that asset's.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// Emitted as `asset://failed`.
+#[derive(Debug, Clone, Serialize)]
+struct AssetError {
+    id: String,
+    #[serde(flatten)]
+    error: LoadError,
+}
+
+/// Why an asset couldn't be loaded.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadError {
+    pub error: String,
+    /// Where a shader's source is wrong, when that's why.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<ShaderDiagnostic>,
+}
+
+impl From<String> for LoadError {
+    fn from(error: String) -> Self {
+        LoadError {
+            error,
+            diagnostics: Vec::new(),
+        }
+    }
+}
+
+/// What `load_shader` returns: the loaded shader, or where its source is wrong.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShaderLoad {
+    pub asset: Option<AssetInfo>,
+    pub diagnostics: Vec<ShaderDiagnostic>,
+}
+
+struct LoadedAsset {
+    info: AssetInfo,
+    asset: Arc<Asset>,
+    /// When the file was last changed, as of when it was loaded.
+    modified: Option<SystemTime>,
+}
+
+#[derive(Default)]
+struct AssetStore {
+    loaded: HashMap<String, LoadedAsset>,
+    /// Bumped whenever an asset is loaded, so renderers can tell when to look for new ones.
+    generation: u64,
+    /// Assets by their contents, so a file is only decoded and uploaded once while it's
+    /// in use, whatever it's loaded as.
+    by_content: HashMap<(AssetKind, blake3::Hash), Weak<Asset>>,
+}
+
+/// Every loaded asset, by id.
+#[derive(Default)]
+pub struct Assets(Mutex<AssetStore>);
+
+impl Assets {
+    pub fn get(&self, id: &str) -> Option<Arc<Asset>> {
+        let store = self.0.lock().unwrap();
+        store.loaded.get(id).map(|loaded| loaded.asset.clone())
+    }
+
+    pub fn info(&self, id: &str) -> Option<AssetInfo> {
+        let store = self.0.lock().unwrap();
+        store.loaded.get(id).map(|loaded| loaded.info.clone())
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.0.lock().unwrap().generation
+    }
+
+    /// The buffers and textures of every asset that's still around: those that are loaded,
+    /// and those that were unloaded or reloaded but are still in use. Each is counted once,
+    /// however many ids it's loaded under.
+    pub fn memory(&self) -> MemoryUsage {
+        let store = self.0.lock().unwrap();
+        let alive = store
+            .loaded
+            .values()
+            .map(|loaded| loaded.asset.clone())
+            .chain(store.by_content.values().filter_map(Weak::upgrade));
+        let mut counted = HashSet::new();
+        let mut usage = MemoryUsage::default();
+        for asset in alive {
+            if counted.insert(Arc::as_ptr(&asset)) {
+                asset.memory(&mut usage);
+            }
+        }
+        usage
+    }
+
+    /// Forget the asset called `id`. Renderers already using it keep it until they're done.
+    pub fn unload(&self, id: &str) -> bool {
+        let mut store = self.0.lock().unwrap();
+        let unloaded = store.loaded.remove(id).is_some();
+        store.by_content.retain(|_, asset| asset.strong_count() > 0);
+        unloaded
+    }
+
+    fn cached(&self, kind: AssetKind, hash: blake3::Hash) -> Option<Arc<Asset>> {
+        let store = self.0.lock().unwrap();
+        store.by_content.get(&(kind, hash))?.upgrade()
+    }
+
+    /// Add an asset that didn't come from a file of its own, so it's never reused for
+    /// one that did.
+    fn insert_derived(&self, info: AssetInfo, asset: Arc<Asset>) {
+        let mut store = self.0.lock().unwrap();
+        store.loaded.insert(
+            info.id.clone(),
+            LoadedAsset {
+                info,
+                asset,
+                modified: None,
+            },
+        );
+        store.generation += 1;
+    }
+
+    fn insert(
+        &self,
+        info: AssetInfo,
+        hash: blake3::Hash,
+        asset: Arc<Asset>,
+        modified: Option<SystemTime>,
+    ) {
+        let mut store = self.0.lock().unwrap();
+        store
+            .by_content
+            .insert((info.kind, hash), Arc::downgrade(&asset));
+        store.loaded.insert(
+            info.id.clone(),
+            LoadedAsset {
+                info,
+                asset,
+                modified,
+            },
+        );
+        store.generation += 1;
+    }
+
+    /// Watched assets whose files have changed since they were loaded. They're marked as
+    /// up to date, so that each change is only reported once.
+    fn take_changed(&self) -> Vec<AssetInfo> {
+        let mut store = self.0.lock().unwrap();
+        let mut changed = Vec::new();
+        for loaded in store.loaded.values_mut() {
+            // Filtered textures are redone by filtering again, not by reloading
+            if !loaded.info.kind.is_watched() || loaded.info.source.is_some() {
+                continue;
+            }
+            let modified = modified_time(&loaded.info.path);
+            if modified.is_some() && modified != loaded.modified {
+                loaded.modified = modified;
+                changed.push(loaded.info.clone());
+            }
+        }
+        changed
+    }
+}
+
+/// Reload texture and model assets when their files change, so edits show up in
+/// overlays without restarting. Renderers pick the new assets up on their next frame.
+pub fn watch(handle: AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(WATCH_INTERVAL);
+        let assets: tauri::State<Assets> = handle.state();
+        let changed = assets.take_changed();
+        if changed.is_empty() {
+            continue;
+        }
+        let gpu = match handle.state::<Gpu>().get() {
+            Ok(gpu) => gpu,
+            Err(_) => continue,
+        };
+        for info in changed {
+            println!("Reloading asset {:?} from {:?}", info.id, info.path);
+            load(handle.clone(), gpu.clone(), info.id, info.kind, info.path);
+        }
+    });
+}
+
+/// Read, decode and upload an asset on the background pool, emitting `asset://progress`
+/// as the file is read and then `asset://loaded` or `asset://failed`. Loading over an
+/// existing id replaces that asset once the new one is ready.
+pub fn load(handle: AppHandle, gpu: Arc<GpuContext>, id: String, kind: AssetKind, path: PathBuf) {
+    tauri::async_runtime::spawn_blocking(move || {
+        let loaded = load_blocking(&handle, &gpu, &id, kind, &path);
+        emit_loaded(&handle, id, loaded);
+    });
+}
+
+/// Load a WGSL shader right away, rather than in the background, so mistakes in it can be
+/// shown as it's edited. Emits `asset://loaded` or `asset://failed` as `load` does, but
+/// only failing to read the file is an error.
+pub fn load_shader(
+    handle: &AppHandle,
+    gpu: &GpuContext,
+    id: String,
+    path: &Path,
+) -> Result<ShaderLoad, String> {
+    let loaded = load_blocking(handle, gpu, &id, AssetKind::Shader, path);
+    emit_loaded(handle, id, loaded.clone());
+    match loaded {
+        Ok(info) => Ok(ShaderLoad {
+            asset: Some(info),
+            diagnostics: Vec::new(),
+        }),
+        Err(e) if !e.diagnostics.is_empty() => Ok(ShaderLoad {
+            asset: None,
+            diagnostics: e.diagnostics,
+        }),
+        Err(e) => Err(e.error),
+    }
+}
+
+fn emit_loaded(handle: &AppHandle, id: String, loaded: Result<AssetInfo, LoadError>) {
+    let emitted = match loaded {
+        Ok(info) => handle.emit("asset://loaded", info),
+        Err(error) => handle.emit("asset://failed", AssetError { id, error }),
+    };
+    if let Err(e) = emitted {
+        println!("Failed to emit asset event: {:?}", e);
+    }
+}
+
+/// Run `filters` over the texture asset `source` on the GPU and add the result as a
+/// texture asset called `id`, which panes and layers pick up like a reloaded one. It's a
+/// snapshot of `source`, so filter again after that reloads.
+pub fn filter(
+    gpu: &GpuContext,
+    assets: &Assets,
+    id: String,
+    source: &str,
+    filters: &[Filter],
+) -> Result<AssetInfo, String> {
+    let source_info = assets
+        .info(source)
+        .ok_or_else(|| format!("no asset called {:?}", source))?;
+    let source_asset = assets
+        .get(source)
+        .ok_or_else(|| format!("no asset called {:?}", source))?;
+    let texture = source_asset
+        .texture()
+        .ok_or_else(|| format!("asset {:?} is not a texture", source))?;
+
+    let (filtered, view) =
+        renderer::filter_texture(gpu, assets, filters, &texture.view, texture.size)?;
+    let info = AssetInfo {
+        id,
+        kind: AssetKind::Texture,
+        path: source_info.path,
+        hash: source_info.hash,
+        cached: false,
+        size: Some(texture.size),
+        source: Some(source.to_string()),
+    };
+    let asset = Asset::Texture(TextureAsset::new(filtered, view, texture.size));
+    assets.insert_derived(info.clone(), Arc::new(asset));
+    Ok(info)
+}
+
+fn load_blocking(
+    handle: &AppHandle,
+    gpu: &GpuContext,
+    id: &str,
+    kind: AssetKind,
+    path: &Path,
+) -> Result<AssetInfo, LoadError> {
+    // Read before the contents, so a change made while loading isn't missed
+    let modified = modified_time(path);
+    let bytes = read_with_progress(handle, id, path)?;
+    let hash = blake3::hash(&bytes);
+
+    let assets: tauri::State<Assets> = handle.state();
+    let (asset, cached) = match assets.cached(kind, hash) {
+        Some(asset) => (asset, true),
+        None => (Arc::new(Asset::decode(kind, gpu, id, bytes)?), false),
+    };
+    let info = AssetInfo {
+        id: id.to_string(),
+        kind,
+        path: path.to_path_buf(),
+        hash: hash.to_hex().to_string(),
+        cached,
+        size: asset.texture().map(|texture| texture.size),
+        source: None,
+    };
+    assets.insert(info.clone(), hash, asset, modified);
+    Ok(info)
+}
+
+fn read_with_progress(handle: &AppHandle, id: &str, path: &Path) -> Result<Vec<u8>, String> {
+    let mut file = File::open(path).map_err(|e| format!("failed to open {:?}: {}", path, e))?;
+    let total = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+
+    let mut bytes = Vec::with_capacity(total as usize);
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        let read = file
+            .read(&mut chunk)
+            .map_err(|e| format!("failed to read {:?}: {}", path, e))?;
+        if read == 0 {
+            return Ok(bytes);
+        }
+        bytes.extend_from_slice(&chunk[..read]);
+
+        let progress = AssetProgress {
+            id: id.to_string(),
+            loaded: bytes.len() as u64,
+            total,
+        };
+        if let Err(e) = handle.emit("asset://progress", progress) {
+            println!("Failed to emit asset://progress: {:?}", e);
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::renderer::{GpuContext, MemoryUsage};
+
+/// Levels of detail a mesh can have, counting the mesh itself.
+const MESH_LEVELS: usize = 4;
+/// The first simplified level merges vertices in a grid this many cells across the mesh's
+/// longest side, and each level after has half as many.
+const SIMPLIFIED_CELLS: u32 = 128;
+/// A level is only kept if it has at most this fraction of the triangles of the one
+/// before, since drawing it wouldn't save much otherwise.
+const MIN_REDUCTION: f32 = 0.75;
+
+/// The vertex layout of every model mesh. Missing normals and texture coordinates are zero.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct ModelVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+impl ModelVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2];
+
+    pub fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ModelVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// One indexed triangle list of a model.
+pub struct Mesh {
+    pub name: String,
+    pub vertices: wgpu::Buffer,
+    pub indices: wgpu::Buffer,
+    pub index_count: u32,
+    /// The corners of the box around the mesh, lowest first, in the model's units.
+    pub bounds: [[f32; 3]; 2],
+    /// Simplified index buffers, coarsest last, and their lengths. They share the mesh's
+    /// vertices.
+    pub levels: Vec<(wgpu::Buffer, u32)>,
+    /// How big the mesh's smallest features are taken to be, in the model's units. Each
+    /// simplified level's are twice the size of the one before's.
+    pub detail: f32,
+}
+
+impl Mesh {
+    /// How many levels of detail there are, counting the mesh itself as level 0.
+    pub fn level_count(&self) -> u32 {
+        self.levels.len() as u32 + 1
+    }
+
+    /// Draw the mesh at `level` of detail as `instance` with whatever pipeline is set,
+    /// which must take [`ModelVertex`]es from the first vertex buffer.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, level: u32, instance: u32) {
+        let (indices, count) = match (level as usize).min(self.levels.len()) {
+            0 => (&self.indices, self.index_count),
+            level => {
+                let (indices, count) = &self.levels[level - 1];
+                (indices, *count)
+            }
+        };
+        render_pass.set_vertex_buffer(0, self.vertices.slice(..));
+        render_pass.set_index_buffer(indices.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..count, 0, instance..instance + 1);
+    }
+}
+
+/// The meshes of a Wavefront OBJ file. Materials aren't loaded.
+pub struct ModelAsset {
+    pub meshes: Vec<Mesh>,
+}
+
+impl ModelAsset {
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        for mesh in &self.meshes {
+            usage.add_buffer(&mesh.vertices);
+            usage.add_buffer(&mesh.indices);
+            for (indices, _) in &mesh.levels {
+                usage.add_buffer(indices);
+            }
+        }
+    }
+}
+
+pub fn decode(gpu: &GpuContext, label: &str, bytes: &[u8]) -> Result<ModelAsset, String> {
+    let (models, _materials) = tobj::load_obj_buf(&mut &bytes[..], &tobj::GPU_LOAD_OPTIONS, |_| {
+        Err(tobj::LoadError::OpenFileFailed)
+    })
+    .map_err(|e| format!("failed to parse model: {}", e))?;
+
+    let meshes = models
+        .into_iter()
+        .map(|model| {
+            let mesh = model.mesh;
+            let vertices: Vec<ModelVertex> = (0..mesh.positions.len() / 3)
+                .map(|i| ModelVertex {
+                    position: [
+                        mesh.positions[i * 3],
+                        mesh.positions[i * 3 + 1],
+                        mesh.positions[i * 3 + 2],
+                    ],
+                    normal: match mesh.normals.get(i * 3..i * 3 + 3) {
+                        Some(normal) => [normal[0], normal[1], normal[2]],
+                        None => [0.0; 3],
+                    },
+                    uv: match mesh.texcoords.get(i * 2..i * 2 + 2) {
+                        Some(uv) => [uv[0], uv[1]],
+                        None => [0.0; 2],
+                    },
+                })
+                .collect();
+
+            let label = format!("{} ({})", label, model.name);
+            let bounds = bounds(&vertices);
+            let extent = (0..3)
+                .map(|i| bounds[1][i] - bounds[0][i])
+                .fold(0.0, f32::max);
+            let mut levels = Vec::new();
+            let mut triangles = mesh.indices.len();
+            for level in 1..MESH_LEVELS {
+                // Flat meshes have nothing to simplify
+                if extent <= 0.0 {
+                    break;
+                }
+                let cells = SIMPLIFIED_CELLS >> (level - 1);
+                let indices = simplify(&vertices, &mesh.indices, bounds, extent / cells as f32);
+                if indices.is_empty() || indices.len() as f32 > triangles as f32 * MIN_REDUCTION {
+                    break;
+                }
+                triangles = indices.len();
+                let buffer = gpu
+                    .device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some(&format!("{} (level {})", label, level)),
+                        contents: bytemuck::cast_slice(&indices),
+                        usage: wgpu::BufferUsages::INDEX,
+                    });
+                levels.push((buffer, indices.len() as u32));
+            }
+            Mesh {
+                vertices: gpu
+                    .device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some(&label),
+                        contents: bytemuck::cast_slice(&vertices),
+                        usage: wgpu::BufferUsages::VERTEX,
+                    }),
+                indices: gpu
+                    .device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some(&label),
+                        contents: bytemuck::cast_slice(&mesh.indices),
+                        usage: wgpu::BufferUsages::INDEX,
+                    }),
+                index_count: mesh.indices.len() as u32,
+                bounds,
+                levels,
+                detail: extent / (SIMPLIFIED_CELLS * 2) as f32,
+                name: model.name,
+            }
+        })
+        .collect();
+
+    Ok(ModelAsset { meshes })
+}
+
+/// The box around `vertices`, or an empty one at the origin if there aren't any.
+fn bounds(vertices: &[ModelVertex]) -> [[f32; 3]; 2] {
+    if vertices.is_empty() {
+        return [[0.0; 3]; 2];
+    }
+    let mut bounds = [[f32::INFINITY; 3], [f32::NEG_INFINITY; 3]];
+    for vertex in vertices {
+        for (i, &value) in vertex.position.iter().enumerate() {
+            bounds[0][i] = bounds[0][i].min(value);
+            bounds[1][i] = bounds[1][i].max(value);
+        }
+    }
+    bounds
+}
+
+/// `indices` with the vertices in each cube `cell` across, from the lowest corner of
+/// `bounds`, merged into the first of them, leaving out the triangles that collapse.
+fn simplify(
+    vertices: &[ModelVertex],
+    indices: &[u32],
+    bounds: [[f32; 3]; 2],
+    cell: f32,
+) -> Vec<u32> {
+    let mut merged = HashMap::new();
+    let representatives: Vec<u32> = vertices
+        .iter()
+        .zip(0..)
+        .map(|(vertex, index)| {
+            let key = [0, 1, 2].map(|i| ((vertex.position[i] - bounds[0][i]) / cell) as u32);
+            *merged.entry(key).or_insert(index)
+        })
+        .collect();
+    indices
+        .chunks_exact(3)
+        .filter_map(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|i| representatives[triangle[i] as usize]);
+            (a != b && b != c && c != a).then_some([a, b, c])
+        })
+        .flatten()
+        .collect()
+}
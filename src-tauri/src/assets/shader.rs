@@ -0,0 +1,102 @@
+use serde::Serialize;
+
+use super::LoadError;
+use crate::renderer::GpuContext;
+
+/// A problem with a shader's source, and where it is.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShaderDiagnostic {
+    pub message: String,
+    /// 1-based, and missing when the problem isn't at any one place in the source.
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// The line of source the problem is on.
+    pub snippet: Option<String>,
+}
+
+impl ShaderDiagnostic {
+    /// A diagnostic for the 1-based `location` in `source`, if it's anywhere.
+    fn new(message: String, source: &str, location: Option<(usize, usize)>) -> Self {
+        ShaderDiagnostic {
+            message,
+            line: location.map(|(line, _)| line),
+            column: location.map(|(_, column)| column),
+            snippet: location
+                .and_then(|(line, _)| source.lines().nth(line - 1).map(|text| text.to_string())),
+        }
+    }
+}
+
+/// Compile WGSL source, catching errors instead of letting the device's uncaptured error
+/// handler panic on them. The source is checked with naga first, so errors in it come
+/// back with where they are.
+pub fn decode(
+    gpu: &GpuContext,
+    label: &str,
+    bytes: &[u8],
+) -> Result<wgpu::ShaderModule, LoadError> {
+    let source = std::str::from_utf8(bytes).map_err(|e| format!("shader isn't UTF-8: {}", e))?;
+    check(source).map_err(|diagnostic| LoadError {
+        error: format!("invalid shader: {}", diagnostic.message),
+        diagnostics: vec![diagnostic],
+    })?;
+
+    // Anything left is down to what this device supports
+    let scope = gpu.device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let module = gpu
+        .device
+        .create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+    match pollster::block_on(scope.pop()) {
+        Some(e) => Err(LoadError {
+            error: format!("invalid shader: {}", e),
+            diagnostics: vec![ShaderDiagnostic::new(e.to_string(), source, None)],
+        }),
+        None => Ok(module),
+    }
+}
+
+/// Parse and validate `source` as any device would.
+fn check(source: &str) -> Result<(), ShaderDiagnostic> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|e| {
+        let at = e
+            .location(source)
+            .map(|at| location(source, at.offset as usize));
+        ShaderDiagnostic::new(e.to_string(), source, at)
+    })?;
+
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|e| {
+        // The outer error only says which function or global is invalid
+        let mut message = e.to_string();
+        let mut cause = std::error::Error::source(&e);
+        while let Some(inner) = cause {
+            message.push_str(": ");
+            message.push_str(&inner.to_string());
+            cause = inner.source();
+        }
+        // The last span is the innermost, like the expression that's wrong
+        let at = e
+            .spans()
+            .filter_map(|(span, _)| span.to_range())
+            .last()
+            .map(|range| location(source, range.start));
+        ShaderDiagnostic::new(message, source, at)
+    })?;
+    Ok(())
+}
+
+/// The 1-based line and column of byte `offset` in `source`.
+fn location(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    let line = before.matches('\n').count() + 1;
+    (line, before[line_start..].chars().count() + 1)
+}
@@ -0,0 +1,59 @@
+use wgpu::util::DeviceExt;
+
+use crate::renderer::{GpuContext, MemoryUsage};
+
+/// An image uploaded as an sRGB texture with straight alpha.
+pub struct TextureAsset {
+    texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub size: [u32; 2],
+}
+
+impl TextureAsset {
+    /// Wrap a texture made some other way, such as by filtering another one.
+    pub fn new(texture: wgpu::Texture, view: wgpu::TextureView, size: [u32; 2]) -> Self {
+        TextureAsset {
+            texture,
+            view,
+            size,
+        }
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        usage.add_texture(&self.texture);
+    }
+}
+
+pub fn decode(gpu: &GpuContext, label: &str, bytes: &[u8]) -> Result<TextureAsset, String> {
+    let image = image::load_from_memory(bytes)
+        .map_err(|e| format!("failed to decode image: {}", e))?
+        .to_rgba8();
+    let (width, height) = image.dimensions();
+
+    let texture = gpu.device.create_texture_with_data(
+        &gpu.queue,
+        &wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::LayerMajor,
+        &image,
+    );
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    Ok(TextureAsset {
+        texture,
+        view,
+        size: [width, height],
+    })
+}
@@ -0,0 +1,1397 @@
+//! Commands the frontend uses to control overlays. Each takes an optional overlay `id`,
+//! which defaults to the "main" overlay.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use tauri::ipc::Response;
+use tauri::path::BaseDirectory;
+use tauri::{AppHandle, LogicalPosition, Manager, Position, State};
+
+use crate::animation::{Easing, Property, Timeline, TimelineDescriptor, TimelineStatus};
+use crate::assets::{self, AssetInfo, AssetKind, Assets, ShaderLoad};
+use crate::config::OverlayConfig;
+use crate::fallback::{FallbackOverlay, Fallbacks};
+use crate::gamepad::{GamepadStatus, Gamepads};
+use crate::hotkeys::{self, Hotkey};
+use crate::input::{FocusPolicy, InputMode};
+use crate::midi::{self, Control, Midi, MidiMapping};
+use crate::overlay::{
+    Attachment, Backdrop, CursorFollow, DragRegion, FollowOptions, OverlayFrame, ResizeOptions,
+    SnapOptions,
+};
+use crate::renderer::{
+    self, BodyDescriptor, BodyState, ClipPath, Filter, FrameStats, GpuInfo, GpuMemoryUsage,
+    GraphDescriptor, Histogram, InkBrush, LayerDescriptor, LayerUpdate, MapDescriptor, MapViewport,
+    MemoryUsage, Navigation, PaneDescriptor, ScatterBrush, ScatterDescriptor, ScatterSelection,
+    ShaderInput, SubtitleStyle, TerrainCamera, TerrainDescriptor, TerrainModel, TransferPoint,
+    VolumeCamera, VolumeDescriptor, DEFAULT_SELECTION_LIMIT,
+};
+use crate::video::{PlaybackStats, SubtitleTrackInfo, VideoOverlay, VideoStatus};
+use crate::{pip, Gpu, Overlays, Timelines};
+
+/// Create an overlay at runtime, e.g. in a window that was opened after startup. It goes
+/// away along with its window. Resolves once the overlay is ready, as `overlay://ready` is
+/// emitted.
+#[tauri::command]
+pub async fn add_overlay(config: OverlayConfig, handle: AppHandle) -> Result<(), String> {
+    crate::add_overlay(&handle, &config).await
+}
+
+#[tauri::command]
+pub fn set_overlay_position(
+    x: f64,
+    y: f64,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    println!("mouse moved to {}, {}", x, y);
+    let overlay = overlays.get(id)?;
+    let others = overlays.frames_except(&overlay.id);
+    let mut view = overlay.view.lock().unwrap();
+    // Mouse coordinates from the page are CSS pixels, i.e. logical
+    let frame = OverlayFrame {
+        x,
+        y,
+        ..view.frame()
+    };
+    let frame = overlay.snapping.lock().unwrap().snap(frame, &others);
+    view.set_origin(Position::Logical(LogicalPosition {
+        x: frame.x,
+        y: frame.y,
+    }));
+    overlay.save_layout(|layout| layout.frame = Some(frame));
+    Ok(())
+}
+
+/// Keep the overlay next to the mouse cursor, which the backend tracks itself so the page
+/// doesn't have to send every mouse move, or stop with `None` and leave it where it is.
+#[tauri::command]
+pub fn set_overlay_follow_cursor(
+    options: Option<FollowOptions>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    if options.is_some() {
+        if overlay.is_detached() {
+            return Err(format!("overlay {:?} is detached", overlay.id));
+        }
+        if overlay.view.lock().unwrap().cursor_position().is_none() {
+            return Err("this overlay backend can't track the cursor".into());
+        }
+    }
+    *overlay.follow.lock().unwrap() = options.map(CursorFollow::new);
+    Ok(())
+}
+
+/// Animate overlay properties from where they are to the values in `props`, e.g.
+/// `{ "x": 200, "opacity": 0 }`, over `duration` seconds. Properties that are already
+/// animating carry on from wherever they've got to.
+#[tauri::command]
+pub fn animate_overlay(
+    props: HashMap<Property, f64>,
+    duration: f64,
+    easing: Option<Easing>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    if !(duration >= 0.0 && duration.is_finite()) {
+        return Err(format!("invalid animation duration {}", duration));
+    }
+    let overlay = overlays.get(id)?;
+    if overlay.is_detached() && props.keys().any(Property::is_frame) {
+        return Err(format!("overlay {:?} is detached", overlay.id));
+    }
+    let view = overlay.view.lock().unwrap();
+    let (frame, opacity) = (view.frame(), view.opacity());
+    drop(view);
+    let overlay_id = overlay.id.clone();
+    let starts = overlay.renderer.call(move |wgpu| {
+        props
+            .into_iter()
+            .map(|(property, to)| match property.get(frame, opacity, wgpu) {
+                Some(from) => Ok((property, from, to)),
+                None => Err(format!("overlay {:?} has no {:?}", overlay_id, property)),
+            })
+            .collect::<Result<Vec<_>, _>>()
+    })??;
+    let mut tweens = overlay.tweens.lock().unwrap();
+    for (property, from, to) in starts {
+        tweens.start(
+            property,
+            from,
+            to,
+            Duration::from_secs_f64(duration),
+            easing.unwrap_or_default(),
+        );
+    }
+    Ok(())
+}
+
+/// Add a timeline, paused at its start, replacing any with the same id. Its tracks can
+/// name overlays that haven't been added yet; they're skipped until they are.
+#[tauri::command]
+pub fn create_timeline(
+    timeline: TimelineDescriptor,
+    timelines: State<Timelines>,
+) -> Result<(), String> {
+    let id = timeline.id.clone();
+    let timeline = Timeline::new(timeline)?;
+    timelines.0.lock().unwrap().insert(id, timeline);
+    Ok(())
+}
+
+/// Stop and forget a timeline, leaving its properties where they are. Returns whether
+/// there was one.
+#[tauri::command]
+pub fn remove_timeline(id: String, timelines: State<Timelines>) -> bool {
+    timelines.0.lock().unwrap().remove(&id).is_some()
+}
+
+/// Play a timeline from where it is, or from the start if it's ended. `timeline://ended`
+/// is emitted when one that doesn't loop gets to the end.
+#[tauri::command]
+pub fn play_timeline(id: String, timelines: State<Timelines>) -> Result<(), String> {
+    with_timeline(&timelines, &id, Timeline::play)
+}
+
+#[tauri::command]
+pub fn pause_timeline(id: String, timelines: State<Timelines>) -> Result<(), String> {
+    with_timeline(&timelines, &id, Timeline::pause)
+}
+
+/// Jump to `time` seconds into a timeline, putting its properties where they are then.
+#[tauri::command]
+pub fn seek_timeline(id: String, time: f64, timelines: State<Timelines>) -> Result<(), String> {
+    with_timeline(&timelines, &id, |timeline| timeline.seek(time))
+}
+
+#[tauri::command]
+pub fn get_timeline_status(
+    id: String,
+    timelines: State<Timelines>,
+) -> Result<TimelineStatus, String> {
+    with_timeline(&timelines, &id, |timeline| timeline.status())
+}
+
+fn with_timeline<T>(
+    timelines: &Timelines,
+    id: &str,
+    f: impl FnOnce(&mut Timeline) -> T,
+) -> Result<T, String> {
+    let mut timelines = timelines.0.lock().unwrap();
+    let timeline = timelines
+        .get_mut(id)
+        .ok_or_else(|| format!("timeline {:?} has not been created", id))?;
+    Ok(f(timeline))
+}
+
+#[tauri::command]
+pub fn set_clear_color(
+    color: [f64; 4],
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    let [r, g, b, a] = color;
+    overlay
+        .renderer
+        .post(move |wgpu| wgpu.set_clear_color(wgpu::Color { r, g, b, a }));
+    overlay.save_layout(|layout| layout.clear_color = Some(color));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_clip_path(
+    path: Option<ClipPath>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call(move |wgpu| wgpu.set_clip_path(path))?
+}
+
+#[tauri::command]
+pub fn set_panes(
+    panes: Vec<PaneDescriptor>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call_with_assets(move |wgpu, assets| wgpu.set_panes(panes, assets))?
+}
+
+/// Replace the overlay's layers, which are composited over its panes in `zIndex` order.
+#[tauri::command]
+pub fn set_layers(
+    layers: Vec<LayerDescriptor>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call_with_assets(move |wgpu, assets| wgpu.set_layers(layers, assets))?
+}
+
+/// Run `filters` over everything the overlay draws, in order, or stop with an empty list.
+#[tauri::command]
+pub fn set_output_filters(
+    filters: Vec<Filter>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call_with_assets(move |wgpu, assets| wgpu.set_output_filters(filters, assets))?
+}
+
+/// Draw the overlay with `graph`'s passes instead of the standard ones, or go back to the
+/// standard graph with `null`.
+#[tauri::command]
+pub fn set_render_graph(
+    graph: Option<GraphDescriptor>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call_with_assets(move |wgpu, assets| wgpu.set_render_graph(graph, assets))?
+}
+
+/// Draw the overlay with the render graph in a JSON file. Relative paths are resolved
+/// against the app's resources.
+#[tauri::command]
+pub fn load_render_graph(
+    path: PathBuf,
+    id: Option<String>,
+    handle: AppHandle,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let graph = GraphDescriptor::load(&resolve_path(&handle, path)?)?;
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call_with_assets(move |wgpu, assets| wgpu.set_render_graph(Some(graph), assets))?
+}
+
+/// Where map tiles are kept on disk, under the app's directory.
+const MAP_TILE_DIR: &str = "map-tiles";
+
+/// Draw a slippy map under the overlay's content, replacing any there was. The overlay's
+/// navigation pans, zooms and rotates it, and `map://viewport` is emitted as it moves.
+#[tauri::command]
+pub fn load_map(
+    map: MapDescriptor,
+    id: Option<String>,
+    handle: AppHandle,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let cache_dir = handle
+        .path()
+        .app_config_dir()
+        .ok()
+        .map(|dir| dir.join(MAP_TILE_DIR));
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call(move |wgpu| wgpu.load_map(map, cache_dir))?
+}
+
+#[tauri::command]
+pub fn clear_map(id: Option<String>, overlays: State<Overlays>) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.post(|wgpu| wgpu.clear_map());
+    Ok(())
+}
+
+/// What part of the map is showing, or `None` without a map.
+#[tauri::command]
+pub fn get_map_viewport(
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<Option<MapViewport>, String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.call(|wgpu| wgpu.map_viewport())
+}
+
+/// Show `center`, a longitude and latitude, in the overlay's middle at `zoom`. This sets
+/// the overlay's navigation, which moves its other navigable content too.
+#[tauri::command]
+pub fn set_map_view(
+    center: [f64; 2],
+    zoom: f64,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call(move |wgpu| wgpu.set_map_view(center, zoom))?
+}
+
+/// Draw terrain under the overlay's content, replacing any there was. A relative
+/// `heightmap` path is resolved against the app's resources.
+#[tauri::command]
+pub fn load_terrain(
+    mut terrain: TerrainDescriptor,
+    id: Option<String>,
+    handle: AppHandle,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    terrain.heightmap = match terrain.heightmap {
+        Some(path) => Some(resolve_path(&handle, path)?),
+        None => None,
+    };
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call_with_assets(move |wgpu, assets| wgpu.load_terrain(terrain, assets))?
+}
+
+#[tauri::command]
+pub fn clear_terrain(id: Option<String>, overlays: State<Overlays>) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.post(|wgpu| wgpu.clear_terrain());
+    Ok(())
+}
+
+/// Place model assets on the terrain, replacing any there were. Reloading a model's file
+/// swaps it in where it's placed.
+#[tauri::command]
+pub fn set_terrain_models(
+    models: Vec<TerrainModel>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call_with_assets(move |wgpu, assets| wgpu.set_terrain_models(models, assets))?
+}
+
+/// Look at the terrain from `camera`, or over all of it with `None`. The overlay's
+/// navigation pans, zooms and turns the camera from there.
+#[tauri::command]
+pub fn set_terrain_camera(
+    camera: Option<TerrainCamera>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .post(move |wgpu| wgpu.set_terrain_camera(camera));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_terrain_camera(
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<Option<TerrainCamera>, String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.call(|wgpu| wgpu.terrain_camera())
+}
+
+/// Make room for a volume of scalar values, replacing any there was. It's drawn once slabs
+/// of it are uploaded with `upload_volume_slab`.
+#[tauri::command]
+pub fn create_volume(
+    volume: VolumeDescriptor,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call(move |wgpu| wgpu.create_volume(volume))?
+}
+
+/// Fill in the volume's slices from `first_slice` on with `values`, a whole number of
+/// slices of them, each row by row. Large volumes go a few slices at a time.
+#[tauri::command]
+pub fn upload_volume_slab(
+    first_slice: u32,
+    values: Vec<f32>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call(move |wgpu| wgpu.upload_volume_slab(first_slice, &values))?
+}
+
+/// Color and light up the volume's values with `points`. With none, values go from
+/// transparent black to opaque white across the volume's range.
+#[tauri::command]
+pub fn set_transfer_function(
+    points: Vec<TransferPoint>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .post(move |wgpu| wgpu.set_transfer_function(points));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_transfer_function(
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<Vec<TransferPoint>, String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.call(|wgpu| wgpu.transfer_function())
+}
+
+/// Look at the volume from `camera`, or from in front of it with `None`. The overlay's
+/// navigation turns and zooms the camera from there.
+#[tauri::command]
+pub fn set_volume_camera(
+    camera: Option<VolumeCamera>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .post(move |wgpu| wgpu.set_volume_camera(camera));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_volume_camera(
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<VolumeCamera, String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.call(|wgpu| wgpu.volume_camera())
+}
+
+#[tauri::command]
+pub fn clear_volume(id: Option<String>, overlays: State<Overlays>) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.post(|wgpu| wgpu.clear_volume());
+    Ok(())
+}
+
+/// Make room for a scatterplot of `scatterplot.capacity` points, replacing any there was.
+/// It's drawn once points are uploaded with `upload_scatter_points`.
+#[tauri::command]
+pub fn create_scatterplot(
+    scatterplot: ScatterDescriptor,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call(move |wgpu| wgpu.create_scatterplot(scatterplot))?
+}
+
+/// Write `points` into the scatterplot from index `first` on. Millions of points go a
+/// chunk at a time.
+#[tauri::command]
+pub fn upload_scatter_points(
+    first: u32,
+    points: Vec<[f64; 2]>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call(move |wgpu| wgpu.upload_scatter_points(first, &points))?
+}
+
+/// Select and highlight the scatterplot's points under `brush`, replacing any selection
+/// there was. Returns how many there are, with the indices of up to `limit` of them.
+#[tauri::command]
+pub fn select_scatter_points(
+    brush: ScatterBrush,
+    limit: Option<u32>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<ScatterSelection, String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.call(move |wgpu| {
+        wgpu.select_scatter_points(brush, limit.unwrap_or(DEFAULT_SELECTION_LIMIT))
+    })?
+}
+
+#[tauri::command]
+pub fn clear_scatter_selection(
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.post(|wgpu| wgpu.clear_scatter_selection());
+    Ok(())
+}
+
+#[tauri::command]
+pub fn clear_scatterplot(id: Option<String>, overlays: State<Overlays>) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.post(|wgpu| wgpu.clear_scatterplot());
+    Ok(())
+}
+
+/// Share the overlay's frames with other apps, like OBS, as `name`: a Syphon server on
+/// macOS or a Spout sender on Windows. `None` stops sharing.
+#[tauri::command]
+pub fn set_frame_sharing(
+    name: Option<String>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call(move |wgpu| wgpu.set_frame_sharing(name))?
+}
+
+#[tauri::command]
+pub fn get_frame_sharing(
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<Option<String>, String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.call(|wgpu| wgpu.frame_sharing())
+}
+
+/// Send the overlay's frames over the network as the NDI source `name`, or stop with
+/// `None`. The NDI runtime has to be installed.
+#[tauri::command]
+pub fn set_ndi_output(
+    name: Option<String>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call(move |wgpu| wgpu.set_ndi_output(name))?
+}
+
+#[tauri::command]
+pub fn get_ndi_output(
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<Option<String>, String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.call(|wgpu| wgpu.ndi_output())
+}
+
+/// Change how one layer is composited, keeping its content.
+#[tauri::command]
+pub fn update_layer(
+    layer: String,
+    update: LayerUpdate,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call(move |wgpu| wgpu.update_layer(&layer, update))?
+}
+
+/// Set an input of the ISF shader on `layer`. Image inputs take the id of a texture asset.
+#[tauri::command]
+pub fn set_uniform(
+    layer: String,
+    name: String,
+    value: serde_json::Value,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.set_uniform(layer, name, value)
+}
+
+/// The inputs the ISF shader on `layer` declares, with what they're set to.
+#[tauri::command]
+pub fn get_shader_inputs(
+    layer: String,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<Vec<ShaderInput>, String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call(move |wgpu| wgpu.shader_inputs(&layer))?
+}
+
+#[tauri::command]
+pub fn set_overlay_attachment(
+    attachment: Attachment,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    let mut view = overlay.view.lock().unwrap();
+    view.set_attachment(attachment.clone())?;
+    if let Some(size) = view.attached_size() {
+        overlay.renderer.resize(size);
+    }
+    overlay.save_layout(|layout| layout.attachment = Some(attachment));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_overlay_visible(
+    visible: bool,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    overlays.get(id)?.set_visible(visible);
+    Ok(())
+}
+
+/// Show or hide a frame rate readout in the overlay's top-left corner.
+#[tauri::command]
+pub fn set_overlay_hud(
+    visible: bool,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    overlays.get(id)?.set_hud_visible(visible);
+    Ok(())
+}
+
+/// Forget everything saved about the overlay's layout and set it up as its config says,
+/// emitting `overlay://reset` with where it ends up.
+#[tauri::command]
+pub fn reset_overlay_layout(
+    id: Option<String>,
+    handle: AppHandle,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    crate::reset_layout(&handle, &overlays.get(id)?)
+}
+
+/// Keep the overlay above every app, or only above its own window. Detached overlays start
+/// out pinned, and unpinning one lasts until it's reattached. Otherwise this only works for
+/// Windows' owned-window overlays, since the others are part of their window's content.
+#[tauri::command]
+pub fn set_overlay_always_on_top(
+    always_on_top: bool,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    let detached = overlay.detached.lock().unwrap();
+    if let Some(detached) = detached.as_ref() {
+        return detached
+            .window
+            .set_always_on_top(always_on_top)
+            .map_err(|e| format!("failed to pin the picture-in-picture window: {:?}", e));
+    }
+    drop(detached);
+    overlay
+        .view
+        .lock()
+        .unwrap()
+        .set_always_on_top(always_on_top)?;
+    overlay.save_layout(|layout| layout.always_on_top = always_on_top);
+    Ok(())
+}
+
+/// Keep the overlay out of screenshots and screen sharing, while it still shows on screen.
+/// On Windows this needs Windows 10 2004 or later. On macOS it hides the overlay's whole
+/// window from capture, since AppKit can't leave out part of one.
+#[tauri::command]
+pub fn set_overlay_capture_excluded(
+    excluded: bool,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    let mut view = overlay.view.lock().unwrap();
+    view.set_capture_excluded(excluded)
+}
+
+/// Stop rendering the overlay, leaving its last frame up, or start again.
+#[tauri::command]
+pub fn set_overlay_paused(
+    paused: bool,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.paused.store(paused, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Emit `overlay://frame` after every `divisor`th frame the overlay presents, so the page
+/// can keep its animations in step with the overlay's, or stop with zero.
+#[tauri::command]
+pub fn set_overlay_frame_events(
+    divisor: u32,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.frame_events.store(divisor, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Emit `overlay://stats` with the overlay's frame statistics every `interval`
+/// milliseconds, or stop with zero.
+#[tauri::command]
+pub fn set_overlay_stats_events(
+    interval: u32,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.stats_events.store(interval, Ordering::Relaxed);
+    Ok(())
+}
+
+/// How long the overlay's latest frames took on the CPU and GPU, how evenly they were
+/// presented, and how many were dropped, for showing quality metrics or spotting jank.
+#[tauri::command]
+pub fn get_frame_stats(
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<FrameStats, String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.call(|wgpu| wgpu.frame_stats())
+}
+
+/// The buffers and textures the overlay holds, the ones loaded assets hold, and what the
+/// GPU has allocated in all, for catching leaks from reloading assets or recreating
+/// overlays.
+#[tauri::command]
+pub fn get_gpu_memory_usage(
+    id: Option<String>,
+    overlays: State<Overlays>,
+    assets: State<Assets>,
+    gpu: State<Gpu>,
+) -> Result<GpuMemoryUsage, String> {
+    let overlay = overlays.get(id)?;
+    Ok(GpuMemoryUsage {
+        overlay: overlay.renderer.call(|wgpu| wgpu.gpu_memory())?,
+        assets: assets.memory(),
+        device: MemoryUsage::of_device(&gpu.get()?.device),
+    })
+}
+
+/// Blur what's behind the overlay, or stop with `None`. The blur shows wherever the
+/// overlay isn't opaque, so give it a translucent clear color.
+#[tauri::command]
+pub fn set_overlay_backdrop(
+    backdrop: Option<Backdrop>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.view.lock().unwrap().set_backdrop(backdrop)?;
+    overlay.save_layout(|layout| layout.backdrop = backdrop);
+    Ok(())
+}
+
+/// `mirror_gestures` also emits navigation gestures to the frontend, which otherwise only
+/// go to the renderer, and `focus` says whether clicks give the overlay keyboard focus.
+#[tauri::command]
+pub fn set_overlay_input_mode(
+    mode: InputMode,
+    mirror_gestures: Option<bool>,
+    focus: Option<FocusPolicy>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    if let Some(focus) = focus {
+        overlay.view.lock().unwrap().set_focus_policy(focus)?;
+        overlay.save_layout(|layout| layout.focus = focus);
+    }
+    overlay.set_input_mode(mode)?;
+    if let Some(mirror_gestures) = mirror_gestures {
+        overlay
+            .mirror_gestures
+            .store(mirror_gestures, Ordering::Relaxed);
+        overlay.save_layout(|layout| layout.mirror_gestures = mirror_gestures);
+    }
+    Ok(())
+}
+
+/// Give an interactive overlay keyboard focus, e.g. for native widgets driven from the
+/// keyboard, whatever its focus policy.
+#[tauri::command]
+pub fn focus_overlay(id: Option<String>, overlays: State<Overlays>) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    let mut view = overlay.view.lock().unwrap();
+    view.focus()
+}
+
+#[tauri::command]
+pub fn get_navigation(id: Option<String>, overlays: State<Overlays>) -> Result<Navigation, String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.call(|wgpu| wgpu.navigation())
+}
+
+/// The gamepads that are connected and their controls' values. Empty unless gamepads are
+/// turned on in the app's config.
+#[tauri::command]
+pub fn get_gamepads(gamepads: State<Gamepads>) -> Vec<GamepadStatus> {
+    gamepads.statuses()
+}
+
+/// Replace the gesture-driven view transform, or reset it with `None`.
+#[tauri::command]
+pub fn set_navigation(
+    navigation: Option<Navigation>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    let navigation = navigation.unwrap_or_default();
+    overlay
+        .renderer
+        .post(move |wgpu| wgpu.set_navigation(navigation));
+    Ok(())
+}
+
+/// Draw pen input on the overlay with `brush`, or stop with `None`. Pen events are still
+/// emitted to the frontend either way.
+#[tauri::command]
+pub fn set_ink_brush(
+    brush: Option<InkBrush>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.post(move |wgpu| wgpu.set_ink_brush(brush));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn clear_ink(id: Option<String>, overlays: State<Overlays>) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.post(|wgpu| wgpu.clear_ink());
+    Ok(())
+}
+
+/// Add a body to the overlay's physics world, replacing any with the same id. Bodies fall
+/// and collide on their own from then on, inside walls along the overlay's edges.
+#[tauri::command]
+pub fn spawn_physics_body(
+    body: BodyDescriptor,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call(move |wgpu| wgpu.spawn_physics_body(body))?
+}
+
+/// Push the body called `body` with `impulse`, and spin it clockwise with `torque` if
+/// there's one.
+#[tauri::command]
+pub fn apply_physics_impulse(
+    body: String,
+    impulse: [f64; 2],
+    torque: Option<f64>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call(move |wgpu| wgpu.apply_physics_impulse(&body, impulse, torque.unwrap_or(0.0)))?
+}
+
+#[tauri::command]
+pub fn remove_physics_body(
+    body: String,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call(move |wgpu| wgpu.remove_physics_body(&body))?
+}
+
+#[tauri::command]
+pub fn clear_physics(id: Option<String>, overlays: State<Overlays>) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.post(|wgpu| wgpu.clear_physics());
+    Ok(())
+}
+
+/// Set the physics world's gravity in logical pixels per second squared, or go back to
+/// the default (down, at 980) with `None`.
+#[tauri::command]
+pub fn set_physics_gravity(
+    gravity: Option<[f64; 2]>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    let gravity = gravity.unwrap_or(renderer::DEFAULT_GRAVITY);
+    overlay
+        .renderer
+        .post(move |wgpu| wgpu.set_physics_gravity(gravity));
+    Ok(())
+}
+
+/// Where each body in the overlay's physics world is, in the order they were spawned.
+#[tauri::command]
+pub fn get_physics_bodies(
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<Vec<BodyState>, String> {
+    let overlay = overlays.get(id)?;
+    overlay.renderer.call(|wgpu| wgpu.physics_bodies())
+}
+
+/// The color at (`x`, `y`) in the overlay's logical pixels, as straight alpha RGBA. With a
+/// `radius`, it's the average of the pixels within that many logical pixels of the point.
+#[tauri::command]
+pub fn sample_pixel(
+    x: f64,
+    y: f64,
+    radius: Option<u32>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<[f64; 4], String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call(move |wgpu| wgpu.sample_pixel(x, y, radius.unwrap_or(0)))?
+}
+
+/// Count the pixels of the texture asset `asset` by color, or of the overlay's frame if
+/// there isn't one, for drawing scopes.
+#[tauri::command]
+pub fn get_histogram(
+    asset: Option<String>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+    assets: State<Assets>,
+    gpu: State<Gpu>,
+) -> Result<Histogram, String> {
+    let asset = match asset {
+        Some(asset) => asset,
+        None => {
+            let overlay = overlays.get(id)?;
+            return overlay.renderer.call(|wgpu| wgpu.histogram())?;
+        }
+    };
+    let loaded = assets
+        .get(&asset)
+        .ok_or_else(|| format!("no asset called {:?}", asset))?;
+    let texture = loaded
+        .texture()
+        .ok_or_else(|| format!("asset {:?} is not a texture", asset))?;
+    let gpu = gpu.get()?;
+    renderer::texture_histogram(&gpu, &texture.view, texture.size)
+}
+
+/// Let the overlay be moved around the window by dragging `region`, or stop with `None`.
+/// Only takes effect while the overlay is interactive.
+#[tauri::command]
+pub fn set_overlay_drag_region(
+    region: Option<DragRegion>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.drag.lock().unwrap().set_region(region);
+    overlay.save_layout(|layout| layout.drag_region = region);
+    Ok(())
+}
+
+/// Let users resize the overlay by dragging its edges, or stop with `None`. Only takes
+/// effect while the overlay is interactive.
+#[tauri::command]
+pub fn set_overlay_resizable(
+    resize: Option<ResizeOptions>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.drag.lock().unwrap().set_resize(resize);
+    overlay.save_layout(|layout| layout.resize = resize);
+    Ok(())
+}
+
+/// Snap the overlay to the window's edges and other overlays when it's moved, or stop
+/// with `None`.
+#[tauri::command]
+pub fn set_overlay_snapping(
+    options: Option<SnapOptions>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.snapping.lock().unwrap().set_options(options);
+    overlay.save_layout(|layout| layout.snapping = options);
+    Ok(())
+}
+
+/// Control the overlay with a system-wide shortcut, which works while the app isn't focused.
+#[tauri::command]
+pub fn register_hotkey(
+    hotkey: Hotkey,
+    id: Option<String>,
+    handle: AppHandle,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    hotkeys::register(&handle, &overlay, hotkey)
+}
+
+#[tauri::command]
+pub fn unregister_hotkey(shortcut: String, handle: AppHandle) -> Result<(), String> {
+    hotkeys::unregister(&handle, &shortcut)
+}
+
+/// Move the overlay into a small always-on-top window of its own, picture-in-picture style.
+/// Closing that window puts it back, as does `reattach_overlay`.
+#[tauri::command]
+pub fn detach_overlay(
+    id: Option<String>,
+    handle: AppHandle,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    pip::detach(&handle, &overlay)
+}
+
+/// Put a detached overlay back where it was in its window. Emits `overlay://reattached`
+/// with that frame, which also happens when the picture-in-picture window is closed.
+#[tauri::command]
+pub fn reattach_overlay(
+    id: Option<String>,
+    handle: AppHandle,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    pip::reattach(&handle, &overlay)
+}
+
+/// The shared GPU's adapter, features and limits, and what the `gpu` config asked for that
+/// it couldn't give.
+#[tauri::command]
+pub fn get_gpu_info(gpu: State<Gpu>) -> Result<GpuInfo, String> {
+    Ok(gpu.get()?.info())
+}
+
+/// The overlays that couldn't render natively, which their pages draw instead.
+#[tauri::command]
+pub fn get_fallback_overlays(fallbacks: State<Fallbacks>) -> Vec<FallbackOverlay> {
+    fallbacks.list()
+}
+
+/// Read an image file for a page to draw in its fallback overlays, which can't use the
+/// texture assets the GPU loads. Relative paths are resolved against the app's resources.
+#[tauri::command]
+pub fn read_fallback_image(path: PathBuf, handle: AppHandle) -> Result<Response, String> {
+    let path = resolve_path(&handle, path)?;
+    std::fs::read(&path)
+        .map(Response::new)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))
+}
+
+/// Start loading an asset in the background; `asset://loaded` or `asset://failed` says how
+/// it went. Relative paths are resolved against the app's resources.
+#[tauri::command]
+pub fn load_asset(
+    id: String,
+    kind: AssetKind,
+    path: PathBuf,
+    handle: AppHandle,
+    gpu: State<Gpu>,
+) -> Result<(), String> {
+    let gpu = gpu.get()?;
+    let path = resolve_path(&handle, path)?;
+    assets::load(handle, gpu, id, kind, path);
+    Ok(())
+}
+
+/// Load a WGSL shader asset and wait for it to compile. If it doesn't, nothing is loaded and
+/// the diagnostics say where its source is wrong. Relative paths are resolved against the
+/// app's resources.
+#[tauri::command]
+pub fn load_shader(
+    id: String,
+    path: PathBuf,
+    handle: AppHandle,
+    gpu: State<Gpu>,
+) -> Result<ShaderLoad, String> {
+    let gpu = gpu.get()?;
+    let path = resolve_path(&handle, path)?;
+    assets::load_shader(&handle, &gpu, id, &path)
+}
+
+/// Relative paths are resolved against the app's resources.
+pub fn resolve_path(handle: &AppHandle, path: PathBuf) -> Result<PathBuf, String> {
+    if path.is_absolute() {
+        return Ok(path);
+    }
+    handle
+        .path()
+        .resolve(&path, BaseDirectory::Resource)
+        .map_err(|_| format!("can't resolve {:?} against the app's resources", path))
+}
+
+/// Filter the texture asset `source` into a texture asset called `id`. Filtering over an
+/// existing id replaces it, so a preview can be redone as its parameters change.
+#[tauri::command]
+pub fn filter_asset(
+    id: String,
+    source: String,
+    filters: Vec<Filter>,
+    gpu: State<Gpu>,
+    assets: State<Assets>,
+) -> Result<AssetInfo, String> {
+    let gpu = gpu.get()?;
+    assets::filter(&gpu, &assets, id, &source, &filters)
+}
+
+/// Returns false if there was no asset called `id`.
+#[tauri::command]
+pub fn unload_asset(id: String, assets: State<Assets>) -> bool {
+    assets.unload(&id)
+}
+
+#[tauri::command]
+pub fn get_asset(id: String, assets: State<Assets>) -> Option<AssetInfo> {
+    assets.info(&id)
+}
+
+/// Open a video file in the overlay, paused at its start, replacing any that's open.
+/// Relative paths are resolved against the app's resources.
+#[tauri::command]
+pub fn open_video(
+    path: PathBuf,
+    id: Option<String>,
+    handle: AppHandle,
+    overlays: State<Overlays>,
+) -> Result<VideoStatus, String> {
+    let overlay = overlays.get(id)?;
+    let video = VideoOverlay::open(resolve_path(&handle, path)?)?;
+    let status = video.status();
+    *overlay.video.lock().unwrap() = Some(video);
+    Ok(status)
+}
+
+#[tauri::command]
+pub fn close_video(id: Option<String>, overlays: State<Overlays>) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay.video.lock().unwrap().take();
+    overlay.renderer.post(|wgpu| wgpu.clear_video());
+    Ok(())
+}
+
+#[tauri::command]
+pub fn play_video(id: Option<String>, overlays: State<Overlays>) -> Result<(), String> {
+    with_video(id, &overlays, |video| {
+        video.play();
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn pause_video(id: Option<String>, overlays: State<Overlays>) -> Result<(), String> {
+    with_video(id, &overlays, |video| {
+        video.pause();
+        Ok(())
+    })
+}
+
+/// Jump to `position` in seconds.
+#[tauri::command]
+pub fn seek_video(
+    position: f64,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    with_video(id, &overlays, |video| {
+        video.seek(position);
+        Ok(())
+    })
+}
+
+/// Play at `rate` times normal speed. Audio is silent at any rate but 1.
+#[tauri::command]
+pub fn set_video_rate(
+    rate: f64,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    with_video(id, &overlays, |video| video.set_rate(rate))
+}
+
+/// From 0 for silent to 1 for full volume.
+#[tauri::command]
+pub fn set_video_volume(
+    volume: f32,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    with_video(id, &overlays, |video| {
+        video.set_volume(volume);
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn get_video_status(
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<VideoStatus, String> {
+    with_video(id, &overlays, |video| Ok(video.status()))
+}
+
+/// How well the video, its audio and its subtitles are keeping in sync.
+#[tauri::command]
+pub fn get_playback_stats(
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<PlaybackStats, String> {
+    with_video(id, &overlays, |video| Ok(video.playback_stats()))
+}
+
+/// Load a SubRip (.srt) or WebVTT (.vtt) file as a subtitle track for the open video and
+/// show it. Relative paths are resolved against the app's resources, and the track is
+/// labelled with its file name unless given a `label`.
+#[tauri::command]
+pub fn load_subtitles(
+    path: PathBuf,
+    label: Option<String>,
+    id: Option<String>,
+    handle: AppHandle,
+    overlays: State<Overlays>,
+) -> Result<SubtitleTrackInfo, String> {
+    let path = resolve_path(&handle, path)?;
+    let source =
+        std::fs::read_to_string(&path).map_err(|e| format!("failed to read {:?}: {}", path, e))?;
+    let label = label.unwrap_or_else(|| {
+        path.file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+    });
+    with_video(id, &overlays, |video| video.add_subtitles(label, &source))
+}
+
+#[tauri::command]
+pub fn get_subtitle_tracks(
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<Vec<SubtitleTrackInfo>, String> {
+    with_video(id, &overlays, |video| Ok(video.subtitle_tracks()))
+}
+
+/// Show the subtitle track at `track`, or hide subtitles with `null`.
+#[tauri::command]
+pub fn select_subtitle_track(
+    track: Option<usize>,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    with_video(id, &overlays, |video| video.select_subtitle_track(track))
+}
+
+/// Set how subtitles look. They're drawn with the font asset named in `style`, so nothing
+/// shows until one is set.
+#[tauri::command]
+pub fn set_subtitle_style(
+    style: SubtitleStyle,
+    id: Option<String>,
+    overlays: State<Overlays>,
+) -> Result<(), String> {
+    let overlay = overlays.get(id)?;
+    overlay
+        .renderer
+        .call_with_assets(move |wgpu, assets| wgpu.set_subtitle_style(style, assets))?
+}
+
+fn with_video<T>(
+    id: Option<String>,
+    overlays: &Overlays,
+    f: impl FnOnce(&mut VideoOverlay) -> Result<T, String>,
+) -> Result<T, String> {
+    let overlay = overlays.get(id)?;
+    let mut video = overlay.video.lock().unwrap();
+    let video = video.as_mut().ok_or("the overlay has no video open")?;
+    f(video)
+}
+
+/// The names of the MIDI input ports that are plugged in.
+#[tauri::command]
+pub fn get_midi_ports() -> Result<Vec<String>, String> {
+    midi::ports()
+}
+
+/// Listen to the MIDI port called `port`, or the first one, returning its name. It's
+/// opened again when the app next starts.
+#[tauri::command]
+pub fn open_midi_port(
+    port: Option<String>,
+    handle: AppHandle,
+    midi: State<Midi>,
+) -> Result<String, String> {
+    midi.open(&handle, port)
+}
+
+#[tauri::command]
+pub fn close_midi_port(midi: State<Midi>) {
+    midi.close()
+}
+
+/// The MIDI port being listened to, if there is one.
+#[tauri::command]
+pub fn get_midi_port(midi: State<Midi>) -> Option<String> {
+    midi.port()
+}
+
+/// Map the next knob or fader that moves to `property` of the overlay, with its travel
+/// covering `range` (0 to 1 by default). `midi://learned` says which control it was.
+#[tauri::command]
+pub fn learn_midi_mapping(
+    property: Property,
+    range: Option<[f64; 2]>,
+    id: Option<String>,
+    midi: State<Midi>,
+) {
+    midi.learn(id, property, range)
+}
+
+#[tauri::command]
+pub fn cancel_midi_learning(midi: State<Midi>) {
+    midi.cancel_learning()
+}
+
+#[tauri::command]
+pub fn get_midi_mappings(midi: State<Midi>) -> Vec<MidiMapping> {
+    midi.mappings()
+}
+
+/// Replace every MIDI mapping, e.g. after editing their ranges.
+#[tauri::command]
+pub fn set_midi_mappings(mappings: Vec<MidiMapping>, midi: State<Midi>) {
+    midi.set_mappings(mappings)
+}
+
+/// Forget what `control` is mapped to. Returns false if it wasn't mapped.
+#[tauri::command]
+pub fn remove_midi_mapping(control: Control, midi: State<Midi>) -> bool {
+    midi.remove_mapping(control)
+}
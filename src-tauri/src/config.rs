@@ -0,0 +1,181 @@
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tauri::Config;
+
+use crate::control::ControlServerConfig;
+use crate::gamepad::GamepadConfig;
+use crate::hotkeys::Hotkey;
+use crate::input::FocusPolicy;
+use crate::osc::OscConfig;
+use crate::overlay::{Attachment, Backdrop, OverlayFrame, WindowsBackend};
+use crate::renderer::GpuConfig;
+
+/// The key under `plugins` in `tauri.conf.json` that lists the overlays to create.
+const CONFIG_KEY: &str = "overlays";
+/// The key under `plugins` that turns on the control server.
+const CONTROL_SERVER_KEY: &str = "controlServer";
+/// The key under `plugins` that turns on OSC input.
+const OSC_KEY: &str = "osc";
+/// The key under `plugins` that turns on gamepad input.
+const GAMEPAD_KEY: &str = "gamepad";
+/// The key under `plugins` that asks for GPU features and limits.
+const GPU_KEY: &str = "gpu";
+
+/// An overlay declared in `tauri.conf.json`, created when the app is ready:
+///
+/// ```json
+/// "plugins": {
+///   "overlays": [{ "id": "main", "window": "main", "passthrough": true }]
+/// }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OverlayConfig {
+    pub id: String,
+    /// Label of the window the overlay goes in.
+    #[serde(default = "default_window")]
+    pub window: String,
+    /// Where the overlay starts, in logical pixels. Without one, it's centered near the
+    /// top of the window and follows the window's size.
+    #[serde(default)]
+    pub rect: Option<OverlayFrame>,
+    /// How the overlay is anchored to the window's content.
+    #[serde(default)]
+    pub attachment: Option<Attachment>,
+    /// Let input through to the page, rather than handling it natively.
+    #[serde(default = "default_true")]
+    pub passthrough: bool,
+    /// Whether clicks give the overlay keyboard focus while it isn't passing input through.
+    #[serde(default)]
+    pub focus: FocusPolicy,
+    #[serde(default = "default_true")]
+    pub transparent: bool,
+    /// Blur what's behind the overlay.
+    #[serde(default)]
+    pub backdrop: Option<Backdrop>,
+    /// Keep the overlay out of screenshots and screen sharing, as
+    /// `set_overlay_capture_excluded` does.
+    #[serde(default)]
+    pub exclude_from_capture: bool,
+    /// Pan, zoom and rotate the overlay's navigation with gamepad sticks, if gamepads are
+    /// turned on.
+    #[serde(default)]
+    pub gamepad: bool,
+    #[serde(default)]
+    pub renderer: RendererKind,
+    #[serde(default)]
+    pub windows_backend: Option<WindowsBackend>,
+    /// System-wide shortcuts that control the overlay.
+    #[serde(default)]
+    pub hotkeys: Vec<Hotkey>,
+    /// Emit `overlay://frame` after every this many frames, or never if it's zero.
+    #[serde(default)]
+    pub frame_events: u32,
+    /// Emit `overlay://stats` with the overlay's frame statistics every this many
+    /// milliseconds, or never if it's zero.
+    #[serde(default)]
+    pub stats_events: u32,
+    /// A JSON render graph to draw the overlay with, instead of the standard passes.
+    /// Relative paths are resolved against the app's resources.
+    #[serde(default)]
+    pub render_graph: Option<PathBuf>,
+    /// Share the overlay's frames with other apps under this name from the start, as
+    /// `set_frame_sharing` would.
+    #[serde(default)]
+    pub frame_sharing: Option<String>,
+    /// Send the overlay's frames over NDI as a source with this name from the start.
+    #[serde(default)]
+    pub ndi_output: Option<String>,
+}
+
+/// Which renderer draws an overlay's content.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RendererKind {
+    #[default]
+    Wgpu,
+}
+
+fn default_window() -> String {
+    "main".into()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl OverlayConfig {
+    /// The overlay apps get when they don't configure any.
+    fn default_main() -> Self {
+        OverlayConfig {
+            id: "main".into(),
+            window: default_window(),
+            rect: None,
+            attachment: None,
+            passthrough: true,
+            focus: FocusPolicy::default(),
+            transparent: true,
+            backdrop: None,
+            exclude_from_capture: false,
+            gamepad: false,
+            renderer: RendererKind::default(),
+            windows_backend: None,
+            hotkeys: Vec::new(),
+            frame_events: 0,
+            stats_events: 0,
+            render_graph: None,
+            frame_sharing: None,
+            ndi_output: None,
+        }
+    }
+}
+
+/// The overlays declared in the app's config, or a single "main" overlay if there are none.
+pub fn overlay_configs(config: &Config) -> Vec<OverlayConfig> {
+    let declared = match config.plugins.0.get(CONFIG_KEY) {
+        Some(declared) => declared.clone(),
+        None => return vec![OverlayConfig::default_main()],
+    };
+    match serde_json::from_value(declared) {
+        Ok(configs) => configs,
+        Err(e) => {
+            println!("Invalid overlay config, using the default overlay: {}", e);
+            vec![OverlayConfig::default_main()]
+        }
+    }
+}
+
+/// How to run the control server, if the app's config turns it on.
+pub fn control_server_config(config: &Config) -> Option<ControlServerConfig> {
+    plugin_config(config, CONTROL_SERVER_KEY, "control server")
+}
+
+/// How to listen for OSC, if the app's config turns it on.
+pub fn osc_config(config: &Config) -> Option<OscConfig> {
+    plugin_config(config, OSC_KEY, "OSC")
+}
+
+/// How to read gamepads, if the app's config turns them on.
+pub fn gamepad_config(config: &Config) -> Option<GamepadConfig> {
+    plugin_config(config, GAMEPAD_KEY, "gamepad")
+}
+
+/// The features and limits to ask the GPU for, which are none beyond the defaults if
+/// there's no config for them or it's invalid.
+pub fn gpu_config(config: &Config) -> GpuConfig {
+    plugin_config(config, GPU_KEY, "GPU").unwrap_or_default()
+}
+
+/// The optional service configured under `key` in `plugins`, which stays off if it's invalid.
+fn plugin_config<T: DeserializeOwned>(config: &Config, key: &str, name: &str) -> Option<T> {
+    let declared = config.plugins.0.get(key)?;
+    match serde_json::from_value(declared.clone()) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            println!("Invalid {} config, leaving it off: {}", name, e);
+            None
+        }
+    }
+}
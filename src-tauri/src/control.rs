@@ -0,0 +1,412 @@
+//! A WebSocket server on localhost with the same API as the Tauri commands, so programs
+//! outside the app, like OBS scripts or show controllers, can drive overlays too. It's off
+//! unless `tauri.conf.json` turns it on:
+//!
+//! ```json
+//! "plugins": { "controlServer": { "port": 7357 } }
+//! ```
+//!
+//! Each request is a text message naming a command and its arguments, as the page would
+//! `invoke` it, and gets a reply with the same `id`:
+//!
+//! ```json
+//! { "id": 1, "command": "set_clear_color", "args": { "color": [0, 0, 0, 0.5] } }
+//! { "id": 1, "result": null }
+//! { "id": 2, "error": "there is no overlay called \"side\"" }
+//! ```
+//!
+//! Browsers let any page open a WebSocket to localhost, so connections that say which page
+//! they're from, with an `Origin` header, are turned away. Programs don't send one.
+
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::mpsc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager};
+use tungstenite::handshake::server::{ErrorResponse, Request as Handshake, Response};
+use tungstenite::http::StatusCode;
+use tungstenite::Message;
+
+use crate::commands;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ControlServerConfig {
+    #[serde(default = "default_port")]
+    pub port: u16,
+}
+
+fn default_port() -> u16 {
+    7357
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    /// Echoed back in the reply, so clients can have several requests in flight.
+    #[serde(default)]
+    id: Value,
+    command: String,
+    #[serde(default)]
+    args: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct Reply {
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Listen on `port` on the loopback interface only, handling each client on its own thread.
+pub fn start(handle: AppHandle, config: ControlServerConfig) {
+    let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, config.port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!(
+                "Failed to start control server on port {}: {}",
+                config.port, e
+            );
+            return;
+        }
+    };
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let handle = handle.clone();
+                    std::thread::spawn(move || serve(&handle, stream));
+                }
+                Err(e) => println!("Failed to accept control connection: {}", e),
+            }
+        }
+    });
+}
+
+fn serve(handle: &AppHandle, stream: TcpStream) {
+    let mut socket = match tungstenite::accept_hdr(stream, reject_browsers) {
+        Ok(socket) => socket,
+        Err(e) => {
+            println!("Failed to accept control connection: {}", e);
+            return;
+        }
+    };
+    loop {
+        let text = match socket.read_message() {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) | Err(_) => return,
+            Ok(_) => continue,
+        };
+        let reply = match serde_json::from_str::<Request>(&text) {
+            Ok(request) => match dispatch(handle, &request.command, request.args) {
+                Ok(result) => Reply {
+                    id: request.id,
+                    result: Some(result),
+                    error: None,
+                },
+                Err(e) => Reply {
+                    id: request.id,
+                    result: None,
+                    error: Some(e),
+                },
+            },
+            Err(e) => Reply {
+                id: Value::Null,
+                result: None,
+                error: Some(format!("invalid request: {}", e)),
+            },
+        };
+        let reply = serde_json::to_string(&reply).expect("replies are plain JSON");
+        if socket.write_message(Message::Text(reply)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Turn away handshakes from web pages, which browsers mark with the page's origin.
+// The error is tungstenite's, and its callback has to return it as it is
+#[allow(clippy::result_large_err)]
+fn reject_browsers(request: &Handshake, response: Response) -> Result<Response, ErrorResponse> {
+    if !request.headers().contains_key("origin") {
+        return Ok(response);
+    }
+    let mut rejected = ErrorResponse::new(Some(
+        "the control server doesn't take connections from web pages".into(),
+    ));
+    *rejected.status_mut() = StatusCode::FORBIDDEN;
+    Err(rejected)
+}
+
+/// What a command returns, as the JSON it would reach the page as.
+trait IntoResult {
+    fn into_result(self) -> Result<Value, String>;
+}
+
+impl<T: Serialize> IntoResult for Result<T, String> {
+    fn into_result(self) -> Result<Value, String> {
+        self.and_then(|value| serde_json::to_value(value).map_err(|e| e.to_string()))
+    }
+}
+
+impl IntoResult for bool {
+    fn into_result(self) -> Result<Value, String> {
+        Ok(Value::Bool(self))
+    }
+}
+
+impl IntoResult for () {
+    fn into_result(self) -> Result<Value, String> {
+        Ok(Value::Null)
+    }
+}
+
+impl<T: Serialize> IntoResult for Vec<T> {
+    fn into_result(self) -> Result<Value, String> {
+        serde_json::to_value(self).map_err(|e| e.to_string())
+    }
+}
+
+impl<T: Serialize> IntoResult for Option<T> {
+    fn into_result(self) -> Result<Value, String> {
+        serde_json::to_value(self).map_err(|e| e.to_string())
+    }
+}
+
+/// The app's handle or managed state, for the parameters Tauri fills in itself.
+macro_rules! inject {
+    ($handle:expr, handle) => {
+        $handle.clone()
+    };
+    ($handle:expr, state) => {
+        $handle.state()
+    };
+}
+
+/// Match command names to the functions in `commands`, taking their arguments from `args`
+/// by name, in camelCase as Tauri does.
+macro_rules! dispatch {
+    ($handle:expr, $command:expr, $args:expr;
+     $($name:ident($($arg:ident: $ty:ty),*) [$($injected:ident),*];)*) => {
+        match $command {
+            $(stringify!($name) => {
+                #[derive(Deserialize)]
+                #[serde(rename_all = "camelCase")]
+                struct Args {
+                    $($arg: $ty,)*
+                }
+                let Args { $($arg,)* } = serde_json::from_value($args)
+                    .map_err(|e| format!("invalid arguments for {}: {}", $command, e))?;
+                commands::$name($($arg,)* $(inject!($handle, $injected),)*).into_result()
+            })*
+            _ => Err(format!("there is no command called {:?}", $command)),
+        }
+    };
+}
+
+/// Run `command` with `args`, as though the page had invoked it, and wait for what it returns.
+/// Commands run on the main thread, as Tauri runs them, since many touch views. Only
+/// `add_overlay` runs on the calling thread, because it waits for the main thread itself,
+/// so this mustn't be called from the main thread.
+pub fn dispatch(handle: &AppHandle, command: &str, args: Value) -> Result<Value, String> {
+    if command == "add_overlay" {
+        return add_overlay(handle, args);
+    }
+    let (sender, receiver) = mpsc::channel();
+    let main = handle.clone();
+    let command = command.to_string();
+    handle
+        .run_on_main_thread(move || {
+            let _ = sender.send(run(&main, &command, args));
+        })
+        .map_err(|e| e.to_string())?;
+    receiver
+        .recv()
+        .map_err(|_| "the main thread dropped the command".to_string())?
+}
+
+/// Run `command` with `args` like [`dispatch`], but without waiting for it, for senders that
+/// don't get a reply. Commands that fail are logged.
+pub fn post(handle: &AppHandle, command: String, args: Value) {
+    let main = handle.clone();
+    if command == "add_overlay" {
+        tauri::async_runtime::spawn_blocking(move || report(&command, add_overlay(&main, args)));
+        return;
+    }
+    let posted = handle.run_on_main_thread(move || {
+        let result = run(&main, &command, args);
+        report(&command, result);
+    });
+    if let Err(e) = posted {
+        println!("Failed to run command on the main thread: {}", e);
+    }
+}
+
+fn report(command: &str, result: Result<Value, String>) {
+    if let Err(e) = result {
+        println!("Failed to run command {}: {}", command, e);
+    }
+}
+
+/// The only async command, which is waited for on the calling thread.
+fn add_overlay(handle: &AppHandle, args: Value) -> Result<Value, String> {
+    #[derive(Deserialize)]
+    struct Args {
+        config: crate::config::OverlayConfig,
+    }
+    let Args { config } = serde_json::from_value(args)
+        .map_err(|e| format!("invalid arguments for add_overlay: {}", e))?;
+    tauri::async_runtime::block_on(commands::add_overlay(config, handle.clone())).into_result()
+}
+
+/// Run any command but `add_overlay`, on the main thread.
+fn run(handle: &AppHandle, command: &str, args: Value) -> Result<Value, String> {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use crate::animation::{Easing, Property, TimelineDescriptor};
+    use crate::assets::AssetKind;
+    use crate::hotkeys::Hotkey;
+    use crate::input::{FocusPolicy, InputMode};
+    use crate::midi::{Control, MidiMapping};
+    use crate::overlay::{
+        Attachment, Backdrop, DragRegion, FollowOptions, ResizeOptions, SnapOptions,
+    };
+    use crate::renderer::{
+        BodyDescriptor, ClipPath, Filter, GraphDescriptor, InkBrush, LayerDescriptor, LayerUpdate,
+        MapDescriptor, Navigation, PaneDescriptor, ScatterBrush, ScatterDescriptor, SubtitleStyle,
+        TerrainCamera, TerrainDescriptor, TerrainModel, TransferPoint, VolumeCamera,
+        VolumeDescriptor,
+    };
+
+    // Commands without arguments are sent without any
+    let args = match args {
+        Value::Null => Value::Object(Default::default()),
+        args => args,
+    };
+    dispatch!(handle, command, args;
+        set_overlay_position(x: f64, y: f64, id: Option<String>) [state];
+        set_overlay_follow_cursor(options: Option<FollowOptions>, id: Option<String>) [state];
+        animate_overlay(
+            props: HashMap<Property, f64>,
+            duration: f64,
+            easing: Option<Easing>,
+            id: Option<String>
+        ) [state];
+        create_timeline(timeline: TimelineDescriptor) [state];
+        remove_timeline(id: String) [state];
+        play_timeline(id: String) [state];
+        pause_timeline(id: String) [state];
+        seek_timeline(id: String, time: f64) [state];
+        get_timeline_status(id: String) [state];
+        set_clear_color(color: [f64; 4], id: Option<String>) [state];
+        set_clip_path(path: Option<ClipPath>, id: Option<String>) [state];
+        set_panes(panes: Vec<PaneDescriptor>, id: Option<String>) [state];
+        set_layers(layers: Vec<LayerDescriptor>, id: Option<String>) [state];
+        update_layer(layer: String, update: LayerUpdate, id: Option<String>) [state];
+        set_uniform(layer: String, name: String, value: serde_json::Value, id: Option<String>) [state];
+        get_shader_inputs(layer: String, id: Option<String>) [state];
+        set_output_filters(filters: Vec<Filter>, id: Option<String>) [state];
+        set_render_graph(graph: Option<GraphDescriptor>, id: Option<String>) [state];
+        load_render_graph(path: PathBuf, id: Option<String>) [handle, state];
+        load_map(map: MapDescriptor, id: Option<String>) [handle, state];
+        clear_map(id: Option<String>) [state];
+        get_map_viewport(id: Option<String>) [state];
+        set_map_view(center: [f64; 2], zoom: f64, id: Option<String>) [state];
+        load_terrain(terrain: TerrainDescriptor, id: Option<String>) [handle, state];
+        clear_terrain(id: Option<String>) [state];
+        set_terrain_models(models: Vec<TerrainModel>, id: Option<String>) [state];
+        set_terrain_camera(camera: Option<TerrainCamera>, id: Option<String>) [state];
+        get_terrain_camera(id: Option<String>) [state];
+        create_volume(volume: VolumeDescriptor, id: Option<String>) [state];
+        upload_volume_slab(first_slice: u32, values: Vec<f32>, id: Option<String>) [state];
+        set_transfer_function(points: Vec<TransferPoint>, id: Option<String>) [state];
+        get_transfer_function(id: Option<String>) [state];
+        set_volume_camera(camera: Option<VolumeCamera>, id: Option<String>) [state];
+        get_volume_camera(id: Option<String>) [state];
+        clear_volume(id: Option<String>) [state];
+        create_scatterplot(scatterplot: ScatterDescriptor, id: Option<String>) [state];
+        upload_scatter_points(first: u32, points: Vec<[f64; 2]>, id: Option<String>) [state];
+        select_scatter_points(brush: ScatterBrush, limit: Option<u32>, id: Option<String>) [state];
+        clear_scatter_selection(id: Option<String>) [state];
+        clear_scatterplot(id: Option<String>) [state];
+        set_frame_sharing(name: Option<String>, id: Option<String>) [state];
+        get_frame_sharing(id: Option<String>) [state];
+        set_ndi_output(name: Option<String>, id: Option<String>) [state];
+        get_ndi_output(id: Option<String>) [state];
+        set_overlay_attachment(attachment: Attachment, id: Option<String>) [state];
+        set_overlay_visible(visible: bool, id: Option<String>) [state];
+        set_overlay_hud(visible: bool, id: Option<String>) [state];
+        reset_overlay_layout(id: Option<String>) [handle, state];
+        set_overlay_paused(paused: bool, id: Option<String>) [state];
+        set_overlay_frame_events(divisor: u32, id: Option<String>) [state];
+        set_overlay_stats_events(interval: u32, id: Option<String>) [state];
+        get_frame_stats(id: Option<String>) [state];
+        get_gpu_memory_usage(id: Option<String>) [state, state, state];
+        set_overlay_always_on_top(always_on_top: bool, id: Option<String>) [state];
+        set_overlay_capture_excluded(excluded: bool, id: Option<String>) [state];
+        set_overlay_backdrop(backdrop: Option<Backdrop>, id: Option<String>) [state];
+        set_overlay_input_mode(
+            mode: InputMode,
+            mirror_gestures: Option<bool>,
+            focus: Option<FocusPolicy>,
+            id: Option<String>
+        ) [state];
+        focus_overlay(id: Option<String>) [state];
+        get_navigation(id: Option<String>) [state];
+        set_navigation(navigation: Option<Navigation>, id: Option<String>) [state];
+        get_gamepads() [state];
+        set_ink_brush(brush: Option<InkBrush>, id: Option<String>) [state];
+        clear_ink(id: Option<String>) [state];
+        spawn_physics_body(body: BodyDescriptor, id: Option<String>) [state];
+        apply_physics_impulse(
+            body: String,
+            impulse: [f64; 2],
+            torque: Option<f64>,
+            id: Option<String>
+        ) [state];
+        remove_physics_body(body: String, id: Option<String>) [state];
+        clear_physics(id: Option<String>) [state];
+        set_physics_gravity(gravity: Option<[f64; 2]>, id: Option<String>) [state];
+        get_physics_bodies(id: Option<String>) [state];
+        sample_pixel(x: f64, y: f64, radius: Option<u32>, id: Option<String>) [state];
+        get_histogram(asset: Option<String>, id: Option<String>) [state, state, state];
+        set_overlay_drag_region(region: Option<DragRegion>, id: Option<String>) [state];
+        set_overlay_resizable(resize: Option<ResizeOptions>, id: Option<String>) [state];
+        set_overlay_snapping(options: Option<SnapOptions>, id: Option<String>) [state];
+        register_hotkey(hotkey: Hotkey, id: Option<String>) [handle, state];
+        unregister_hotkey(shortcut: String) [handle];
+        detach_overlay(id: Option<String>) [handle, state];
+        reattach_overlay(id: Option<String>) [handle, state];
+        get_gpu_info() [state];
+        get_fallback_overlays() [state];
+        load_asset(id: String, kind: AssetKind, path: PathBuf) [handle, state];
+        load_shader(id: String, path: PathBuf) [handle, state];
+        filter_asset(id: String, source: String, filters: Vec<Filter>) [state, state];
+        unload_asset(id: String) [state];
+        get_asset(id: String) [state];
+        open_video(path: PathBuf, id: Option<String>) [handle, state];
+        close_video(id: Option<String>) [state];
+        play_video(id: Option<String>) [state];
+        pause_video(id: Option<String>) [state];
+        seek_video(position: f64, id: Option<String>) [state];
+        set_video_rate(rate: f64, id: Option<String>) [state];
+        set_video_volume(volume: f32, id: Option<String>) [state];
+        get_video_status(id: Option<String>) [state];
+        get_playback_stats(id: Option<String>) [state];
+        load_subtitles(path: PathBuf, label: Option<String>, id: Option<String>) [handle, state];
+        get_subtitle_tracks(id: Option<String>) [state];
+        select_subtitle_track(track: Option<usize>, id: Option<String>) [state];
+        set_subtitle_style(style: SubtitleStyle, id: Option<String>) [state];
+        get_midi_ports() [];
+        open_midi_port(port: Option<String>) [handle, state];
+        close_midi_port() [state];
+        get_midi_port() [state];
+        learn_midi_mapping(property: Property, range: Option<[f64; 2]>, id: Option<String>) [state];
+        cancel_midi_learning() [state];
+        get_midi_mappings() [state];
+        set_midi_mappings(mappings: Vec<MidiMapping>) [state];
+        remove_midi_mapping(control: Control) [state];
+    )
+}
@@ -0,0 +1,59 @@
+//! Overlays the page draws instead, for when there's no GPU a native overlay can render
+//! with, as in some VMs. Each one is announced to its window with `overlay://fallback`,
+//! and `src/fallback.ts` draws its background, panes, layers and HUD into a `<canvas>` with
+//! WebGPU, or WebGL where there's no WebGPU. Images come from `read_fallback_image`.
+
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::config::OverlayConfig;
+use crate::overlay::OverlayFrame;
+
+/// Emitted as `overlay://fallback`, and what `get_fallback_overlays` returns.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FallbackOverlay {
+    pub overlay: String,
+    /// Label of the window the overlay is drawn in.
+    pub window: String,
+    /// Why it couldn't be drawn natively.
+    pub reason: String,
+    /// Where the overlay goes, in logical pixels. Without one, it's centered near the top
+    /// of the window as native overlays are.
+    pub rect: Option<OverlayFrame>,
+}
+
+/// The overlays being drawn by their pages.
+#[derive(Default)]
+pub struct Fallbacks(Mutex<Vec<FallbackOverlay>>);
+
+impl Fallbacks {
+    /// Hand the overlay `config` describes to its page, which is told with
+    /// `overlay://fallback`. Pages that load later find it with `get_fallback_overlays`.
+    pub fn add(&self, handle: &AppHandle, config: &OverlayConfig, reason: String) {
+        let fallback = FallbackOverlay {
+            overlay: config.id.clone(),
+            window: config.window.clone(),
+            reason,
+            rect: config.rect,
+        };
+        println!(
+            "Overlay {:?} will be drawn by its page: {}",
+            fallback.overlay, fallback.reason
+        );
+        if let Some(window) = handle.get_webview_window(&fallback.window) {
+            if let Err(e) = window.emit_to(window.label(), "overlay://fallback", fallback.clone()) {
+                println!("Failed to emit overlay://fallback: {:?}", e);
+            }
+        }
+        let mut fallbacks = self.0.lock().unwrap();
+        fallbacks.retain(|existing| existing.overlay != fallback.overlay);
+        fallbacks.push(fallback);
+    }
+
+    pub fn list(&self) -> Vec<FallbackOverlay> {
+        self.0.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,202 @@
+//! Gamepad input, read natively so controllers drive overlays without waiting on the
+//! browser's Gamepad API. Gamepads are only read if `tauri.conf.json` turns them on. Then
+//! overlays with `gamepad` set in their config are navigated with the sticks, and the page
+//! can be sent the raw input too:
+//!
+//! ```json
+//! "plugins": { "gamepad": { "events": true } }
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use gilrs::{Axis, EventType, Gilrs};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::renderer::NavigationInput;
+
+/// How often gamepads are polled. They're only read once a frame, but events go out as
+/// soon as they're seen.
+const POLL_INTERVAL: Duration = Duration::from_millis(4);
+/// How far sticks move before they navigate, since they rarely rest at exactly zero.
+const DEAD_ZONE: f64 = 0.15;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GamepadConfig {
+    /// Emit `gamepad://` events to the page as well.
+    #[serde(default)]
+    pub events: bool,
+}
+
+/// A connected gamepad's controls, as `get_gamepads` reports them. Names are gilrs's, in
+/// camelCase, like `leftStickX` and `south`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GamepadStatus {
+    pub id: usize,
+    pub name: String,
+    /// From -1 to 1, with up and right positive.
+    pub axes: BTreeMap<String, f64>,
+    /// From 0 to 1, for analog triggers, or exactly one of them for plain buttons.
+    pub buttons: BTreeMap<String, f64>,
+}
+
+/// Emitted as `gamepad://connected` and `gamepad://disconnected`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionPayload {
+    pub id: usize,
+    pub name: String,
+}
+
+/// Emitted as `gamepad://button` and `gamepad://axis` when a control changes.
+#[derive(Debug, Clone, Serialize)]
+pub struct ControlPayload {
+    pub id: usize,
+    pub control: String,
+    pub value: f64,
+}
+
+/// The gamepads that are connected, managed by the app. Empty unless polling was started.
+#[derive(Default)]
+pub struct Gamepads {
+    pads: Mutex<HashMap<usize, GamepadStatus>>,
+}
+
+impl Gamepads {
+    pub fn statuses(&self) -> Vec<GamepadStatus> {
+        let mut statuses: Vec<_> = self.pads.lock().unwrap().values().cloned().collect();
+        statuses.sort_by_key(|status| status.id);
+        statuses
+    }
+
+    /// What the sticks of every gamepad together say to do, or `None` if they're at rest.
+    /// The left stick pans, and the right one zooms with up and down and rotates with left
+    /// and right.
+    pub fn navigation(&self) -> Option<NavigationInput> {
+        let pads = self.pads.lock().unwrap();
+        let axis = |name: &str| -> f64 {
+            let value: f64 = pads
+                .values()
+                .filter_map(|pad| pad.axes.get(name))
+                .sum::<f64>()
+                .clamp(-1.0, 1.0);
+            // Rescaled past the dead zone, so movement starts from zero
+            match value.abs() > DEAD_ZONE {
+                true => value.signum() * (value.abs() - DEAD_ZONE) / (1.0 - DEAD_ZONE),
+                false => 0.0,
+            }
+        };
+        let input = NavigationInput {
+            pan: [axis("leftStickX"), axis("leftStickY")],
+            zoom: axis("rightStickY"),
+            rotate: axis("rightStickX"),
+        };
+        let idle = input.pan == [0.0, 0.0] && input.zoom == 0.0 && input.rotate == 0.0;
+        (!idle).then_some(input)
+    }
+}
+
+/// Poll gamepads on a thread of their own, keeping the managed [`Gamepads`] up to date.
+pub fn start(handle: AppHandle, config: GamepadConfig) {
+    std::thread::spawn(move || {
+        // Gilrs has to stay on the thread that made it on some platforms
+        let mut gilrs = match Gilrs::new() {
+            Ok(gilrs) => gilrs,
+            Err(e) => {
+                println!("Failed to read gamepads: {}", e);
+                return;
+            }
+        };
+        let gamepads: tauri::State<Gamepads> = handle.state();
+        {
+            let mut pads = gamepads.pads.lock().unwrap();
+            for (id, gamepad) in gilrs.gamepads() {
+                let id: usize = id.into();
+                pads.insert(id, new_status(id, gamepad.name()));
+            }
+        }
+        loop {
+            while let Some(event) = gilrs.next_event() {
+                let id: usize = event.id.into();
+                let name = gilrs.gamepad(event.id).name().to_string();
+                let mut pads = gamepads.pads.lock().unwrap();
+                let (is_axis, control, value) = match event.event {
+                    EventType::Connected => {
+                        pads.insert(id, new_status(id, &name));
+                        drop(pads);
+                        let payload = ConnectionPayload { id, name };
+                        emit(&handle, &config, "gamepad://connected", payload);
+                        continue;
+                    }
+                    EventType::Disconnected => {
+                        pads.remove(&id);
+                        drop(pads);
+                        let payload = ConnectionPayload { id, name };
+                        emit(&handle, &config, "gamepad://disconnected", payload);
+                        continue;
+                    }
+                    EventType::ButtonPressed(button, _) => (false, camel_case(button), 1.0),
+                    EventType::ButtonReleased(button, _) => (false, camel_case(button), 0.0),
+                    EventType::ButtonChanged(button, value, _) => {
+                        (false, camel_case(button), value as f64)
+                    }
+                    EventType::AxisChanged(axis, value, _) if axis != Axis::Unknown => {
+                        (true, camel_case(axis), value as f64)
+                    }
+                    _ => continue,
+                };
+                let status = pads.entry(id).or_insert_with(|| new_status(id, &name));
+                let controls = match is_axis {
+                    true => &mut status.axes,
+                    false => &mut status.buttons,
+                };
+                // Presses come with a change to the same value, which only needs sending once
+                if controls.insert(control.clone(), value) == Some(value) {
+                    continue;
+                }
+                drop(pads);
+                let event_name = match is_axis {
+                    true => "gamepad://axis",
+                    false => "gamepad://button",
+                };
+                emit(
+                    &handle,
+                    &config,
+                    event_name,
+                    ControlPayload { id, control, value },
+                );
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
+
+fn new_status(id: usize, name: &str) -> GamepadStatus {
+    GamepadStatus {
+        id,
+        name: name.to_string(),
+        axes: BTreeMap::new(),
+        buttons: BTreeMap::new(),
+    }
+}
+
+fn emit<S: Serialize + Clone>(handle: &AppHandle, config: &GamepadConfig, event: &str, payload: S) {
+    if config.events {
+        if let Err(e) = handle.emit(event, payload) {
+            println!("Failed to emit {}: {:?}", event, e);
+        }
+    }
+}
+
+/// The name gilrs gives a button or axis, like `LeftStickX`, in camelCase.
+fn camel_case(control: impl std::fmt::Debug) -> String {
+    let name = format!("{:?}", control);
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => name,
+    }
+}
@@ -0,0 +1,90 @@
+//! System-wide keyboard shortcuts that control overlays, so they work while the app isn't
+//! focused, e.g. when it's providing a streaming overlay behind other apps.
+
+use std::sync::atomic::Ordering;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
+
+use crate::OverlayHandle;
+
+/// A shortcut, like `"CmdOrCtrl+Shift+O"`, and what it does to its overlay.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Hotkey {
+    pub shortcut: String,
+    #[serde(flatten)]
+    pub action: HotkeyAction,
+}
+
+/// Every hotkey also emits `hotkey://pressed` once it's done its part, so the page can
+/// follow along, or do things of its own, like cycling through presets, with `emit`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum HotkeyAction {
+    /// Show the overlay if it's hidden, or hide it.
+    ToggleVisible,
+    /// Stop rendering the overlay, leaving its last frame up, or start again.
+    TogglePaused,
+    /// Only emit `hotkey://pressed`.
+    Emit,
+}
+
+/// Emitted as `hotkey://pressed` to the overlay's window.
+#[derive(Debug, Clone, Serialize)]
+pub struct HotkeyPayload {
+    pub overlay: String,
+    pub shortcut: String,
+    #[serde(flatten)]
+    pub action: HotkeyAction,
+}
+
+/// Register `hotkey` system-wide for `overlay`, failing if another app or hotkey has the
+/// shortcut already.
+pub fn register(handle: &AppHandle, overlay: &OverlayHandle, hotkey: Hotkey) -> Result<(), String> {
+    let shortcut = hotkey.shortcut.clone();
+    let overlay = overlay.clone();
+    handle
+        .global_shortcut()
+        .on_shortcut(shortcut.as_str(), move |handle, _, event| {
+            // Releasing the keys is an event too
+            if event.state == ShortcutState::Pressed {
+                press(handle, &overlay, &hotkey);
+            }
+        })
+        .map_err(|e| format!("failed to register hotkey {:?}: {}", shortcut, e))
+}
+
+pub fn unregister(handle: &AppHandle, shortcut: &str) -> Result<(), String> {
+    handle
+        .global_shortcut()
+        .unregister(shortcut)
+        .map_err(|e| format!("failed to unregister hotkey {:?}: {}", shortcut, e))
+}
+
+fn press(handle: &AppHandle, overlay: &OverlayHandle, hotkey: &Hotkey) {
+    if overlay.closed.load(Ordering::Relaxed) {
+        return;
+    }
+    match hotkey.action {
+        HotkeyAction::ToggleVisible => overlay.toggle_visible(),
+        HotkeyAction::TogglePaused => {
+            overlay.paused.fetch_xor(true, Ordering::Relaxed);
+        }
+        HotkeyAction::Emit => {}
+    }
+
+    let payload = HotkeyPayload {
+        overlay: overlay.id.clone(),
+        shortcut: hotkey.shortcut.clone(),
+        action: hotkey.action.clone(),
+    };
+    let emitted = match handle.get_webview_window(&overlay.window) {
+        Some(window) => window.emit_to(window.label(), "hotkey://pressed", payload),
+        None => return,
+    };
+    if let Err(e) = emitted {
+        println!("Failed to emit hotkey://pressed: {:?}", e);
+    }
+}
@@ -0,0 +1,231 @@
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+/// Whether an overlay lets input through to the page underneath or handles it itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InputMode {
+    /// Clicks and keys go to the webview as if the overlay weren't there.
+    #[default]
+    Passthrough,
+    /// The overlay receives input natively and reports it through an [`InputHandler`].
+    Interactive,
+}
+
+/// Whether clicking an interactive overlay gives it keyboard focus.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FocusPolicy {
+    /// Clicks focus the overlay, as clicking into a page element would.
+    #[default]
+    OnClick,
+    /// Clicks leave the keyboard where it was, so the overlay only gets key events once
+    /// it's given focus with `focus_overlay`.
+    Manual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    PointerDown,
+    PointerUp,
+    PointerMove,
+    Wheel,
+    KeyDown,
+    KeyUp,
+    /// Two finger zoom, with `scale` as the change since the previous event.
+    Pinch,
+    /// Two finger (or touch) drag, with `delta_x`/`delta_y` as the distance moved.
+    Pan,
+    /// Two finger rotation, with `rotation` in radians clockwise since the previous event.
+    Rotate,
+}
+
+impl InputKind {
+    /// The Tauri event the frontend listens to for this kind of input.
+    pub fn event_name(self) -> &'static str {
+        match self {
+            InputKind::PointerDown => "overlay://pointerdown",
+            InputKind::PointerUp => "overlay://pointerup",
+            InputKind::PointerMove => "overlay://pointermove",
+            InputKind::Wheel => "overlay://wheel",
+            InputKind::KeyDown => "overlay://keydown",
+            InputKind::KeyUp => "overlay://keyup",
+            InputKind::Pinch => "overlay://pinch",
+            InputKind::Pan => "overlay://pan",
+            InputKind::Rotate => "overlay://rotate",
+        }
+    }
+
+    pub fn is_gesture(self) -> bool {
+        matches!(self, InputKind::Pinch | InputKind::Pan | InputKind::Rotate)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PointerType {
+    Mouse,
+    Pen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GesturePhase {
+    Begin,
+    Update,
+    End,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub meta: bool,
+}
+
+/// One input event, shaped like its DOM counterpart. Positions are logical pixels
+/// relative to the overlay's top-left corner.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InputEvent {
+    #[serde(skip)]
+    pub kind: InputKind,
+    pub x: f64,
+    pub y: f64,
+    /// DOM button numbering: 0 = primary, 1 = middle, 2 = secondary (or a pen's barrel
+    /// button), 5 = pen eraser.
+    pub button: Option<u16>,
+    pub pointer_type: Option<PointerType>,
+    /// Pen pressure from 0 to 1.
+    pub pressure: Option<f64>,
+    /// Pen tilt in degrees, towards the right and towards the user respectively.
+    pub tilt_x: Option<f64>,
+    pub tilt_y: Option<f64>,
+    pub delta_x: f64,
+    pub delta_y: f64,
+    pub key: Option<String>,
+    /// The platform's virtual key code.
+    pub key_code: Option<u32>,
+    pub repeat: bool,
+    pub phase: Option<GesturePhase>,
+    pub scale: Option<f64>,
+    pub rotation: Option<f64>,
+    pub modifiers: Modifiers,
+}
+
+impl InputEvent {
+    pub fn pointer(
+        kind: InputKind,
+        x: f64,
+        y: f64,
+        button: Option<u16>,
+        modifiers: Modifiers,
+    ) -> Self {
+        InputEvent {
+            kind,
+            x,
+            y,
+            button,
+            pointer_type: Some(PointerType::Mouse),
+            pressure: None,
+            tilt_x: None,
+            tilt_y: None,
+            delta_x: 0.0,
+            delta_y: 0.0,
+            key: None,
+            key_code: None,
+            repeat: false,
+            phase: None,
+            scale: None,
+            rotation: None,
+            modifiers,
+        }
+    }
+
+    pub fn wheel(x: f64, y: f64, delta_x: f64, delta_y: f64, modifiers: Modifiers) -> Self {
+        InputEvent {
+            delta_x,
+            delta_y,
+            ..InputEvent::pointer(InputKind::Wheel, x, y, None, modifiers)
+        }
+    }
+
+    /// Mark a pointer event as coming from a pen.
+    pub fn with_pen(self, pressure: f64, tilt_x: f64, tilt_y: f64) -> Self {
+        InputEvent {
+            pointer_type: Some(PointerType::Pen),
+            pressure: Some(pressure),
+            tilt_x: Some(tilt_x),
+            tilt_y: Some(tilt_y),
+            ..self
+        }
+    }
+
+    /// A gesture centred on `x`, `y` that hasn't moved, scaled or rotated anything yet.
+    pub fn gesture(
+        kind: InputKind,
+        x: f64,
+        y: f64,
+        phase: GesturePhase,
+        modifiers: Modifiers,
+    ) -> Self {
+        InputEvent {
+            pointer_type: None,
+            phase: Some(phase),
+            scale: Some(1.0),
+            rotation: Some(0.0),
+            ..InputEvent::pointer(kind, x, y, None, modifiers)
+        }
+    }
+
+    pub fn key(
+        kind: InputKind,
+        key: Option<String>,
+        key_code: u32,
+        repeat: bool,
+        modifiers: Modifiers,
+    ) -> Self {
+        InputEvent {
+            pointer_type: None,
+            key,
+            key_code: Some(key_code),
+            repeat,
+            ..InputEvent::pointer(kind, 0.0, 0.0, None, modifiers)
+        }
+    }
+}
+
+/// Receives input from an interactive overlay. Called on the platform's UI thread.
+pub type InputHandler = Arc<dyn Fn(InputEvent) + Send + Sync>;
+
+/// What gets emitted to the frontend for each input event.
+#[derive(Debug, Clone, Serialize)]
+pub struct InputPayload {
+    pub overlay: String,
+    #[serde(flatten)]
+    pub event: InputEvent,
+}
+
+/// Input settings shared between an overlay view and the native callbacks that receive
+/// its events, which run outside of the view's lock.
+#[derive(Default)]
+pub struct InputState {
+    pub mode: InputMode,
+    pub focus: FocusPolicy,
+    pub handler: Option<InputHandler>,
+}
+
+pub type SharedInputState = Arc<Mutex<InputState>>;
+
+impl InputState {
+    /// The handler native events should go to, or `None` while they should pass through.
+    pub fn active_handler(&self) -> Option<InputHandler> {
+        match self.mode {
+            InputMode::Interactive => self.handler.clone(),
+            InputMode::Passthrough => None,
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::{fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::input::{FocusPolicy, InputMode};
+use crate::overlay::{Attachment, Backdrop, DragRegion, OverlayFrame, ResizeOptions, SnapOptions};
+
+const LAYOUT_FILE: &str = "overlays.json";
+
+/// Everything about an overlay that users can arrange and that should survive a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OverlayLayout {
+    pub id: String,
+    /// Where the overlay was last put, or `None` to use the default placement.
+    #[serde(default)]
+    pub frame: Option<OverlayFrame>,
+    #[serde(default = "default_visible")]
+    pub visible: bool,
+    #[serde(default)]
+    pub always_on_top: bool,
+    #[serde(default)]
+    pub clear_color: Option<[f64; 4]>,
+    #[serde(default)]
+    pub attachment: Option<Attachment>,
+    #[serde(default)]
+    pub input_mode: InputMode,
+    #[serde(default)]
+    pub focus: FocusPolicy,
+    #[serde(default)]
+    pub mirror_gestures: bool,
+    #[serde(default)]
+    pub drag_region: Option<DragRegion>,
+    #[serde(default)]
+    pub resize: Option<ResizeOptions>,
+    #[serde(default)]
+    pub snapping: Option<SnapOptions>,
+    #[serde(default)]
+    pub backdrop: Option<Backdrop>,
+    /// Show the frame rate readout.
+    #[serde(default)]
+    pub hud: bool,
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+impl OverlayLayout {
+    fn new(id: &str) -> Self {
+        OverlayLayout {
+            id: id.to_string(),
+            frame: None,
+            visible: true,
+            always_on_top: false,
+            clear_color: None,
+            attachment: None,
+            input_mode: InputMode::default(),
+            focus: FocusPolicy::default(),
+            mirror_gestures: false,
+            drag_region: None,
+            resize: None,
+            snapping: None,
+            backdrop: None,
+            hud: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LayoutFile {
+    overlays: Vec<OverlayLayout>,
+}
+
+/// Overlay layouts, kept in a JSON file in the app data directory. Changes are written
+/// back by `save_if_dirty`, so that a drag doesn't write the file on every move.
+pub struct LayoutStore {
+    path: Option<PathBuf>,
+    file: LayoutFile,
+    dirty: bool,
+}
+
+impl LayoutStore {
+    /// Read the saved layouts, starting over if there are none or they can't be read.
+    pub fn load(handle: &AppHandle) -> Self {
+        let path = handle
+            .path()
+            .app_config_dir()
+            .ok()
+            .map(|dir| dir.join(LAYOUT_FILE));
+        let file = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|json| match serde_json::from_str(&json) {
+                Ok(file) => Some(file),
+                Err(e) => {
+                    println!("Ignoring unreadable overlay layout: {}", e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        LayoutStore {
+            path,
+            file,
+            dirty: false,
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&OverlayLayout> {
+        self.file.overlays.iter().find(|layout| layout.id == id)
+    }
+
+    /// Change an overlay's layout, adding it if it wasn't saved before.
+    pub fn update(&mut self, id: &str, f: impl FnOnce(&mut OverlayLayout)) {
+        let index = match self.file.overlays.iter().position(|layout| layout.id == id) {
+            Some(index) => index,
+            None => {
+                self.file.overlays.push(OverlayLayout::new(id));
+                self.file.overlays.len() - 1
+            }
+        };
+        f(&mut self.file.overlays[index]);
+        self.dirty = true;
+    }
+
+    /// Forget an overlay's layout, so it goes back to its defaults next time.
+    pub fn remove(&mut self, id: &str) {
+        let count = self.file.overlays.len();
+        self.file.overlays.retain(|layout| layout.id != id);
+        self.dirty |= self.file.overlays.len() != count;
+    }
+
+    pub fn save_if_dirty(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        let result = serde_json::to_string_pretty(&self.file)
+            .map_err(|e| e.to_string())
+            .and_then(|json| {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                }
+                fs::write(path, json).map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            println!("Failed to save overlay layout to {:?}: {}", path, e);
+        }
+    }
+}
@@ -0,0 +1,1308 @@
+mod animation;
+mod assets;
+mod commands;
+mod config;
+mod control;
+mod fallback;
+mod gamepad;
+mod hotkeys;
+mod input;
+mod layout;
+mod menu;
+mod midi;
+mod osc;
+mod overlay;
+mod pip;
+mod renderer;
+mod tiles;
+mod tray;
+mod video;
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use animation::{Property, Timeline, TimelinePayload, Tweens};
+use config::{OverlayConfig, RendererKind};
+use input::{FocusPolicy, InputEvent, InputHandler, InputMode, InputPayload, PointerType};
+use layout::{LayoutStore, OverlayLayout};
+use overlay::{
+    Attachment, CursorFollow, DragHandle, FramePayload, OverlayFrame, OverlayOptions, OverlayView,
+    Snapping,
+};
+use renderer::{
+    FrameStats, GpuConfig, GpuContext, GraphDescriptor, MapViewport, MapViewportPayload,
+    RenderHandle, WgpuState,
+};
+use serde::Serialize;
+use tauri::menu::{Menu, PredefinedMenuItem, Submenu};
+use tauri::{
+    AppHandle, Emitter, LogicalPosition, LogicalSize, Manager, PhysicalPosition, PhysicalSize,
+    Position, Size, WebviewWindow, WindowEvent, Wry,
+};
+use video::{VideoOverlay, VideoPayload};
+
+/// The overlay commands use when they aren't given an id.
+const MAIN_OVERLAY: &str = "main";
+
+#[derive(Clone)]
+struct OverlayHandle {
+    id: String,
+    handle: AppHandle,
+    /// Label of the window the overlay is in.
+    window: String,
+    view: Arc<Mutex<dyn OverlayView + Send>>,
+    /// Sends commands to the thread that owns the overlay's renderer.
+    renderer: RenderHandle,
+    /// The video playing in the overlay, if one's open.
+    video: Arc<Mutex<Option<VideoOverlay>>>,
+    mirror_gestures: Arc<AtomicBool>,
+    drag: Arc<Mutex<DragHandle>>,
+    snapping: Arc<Mutex<Snapping>>,
+    layouts: Arc<Mutex<LayoutStore>>,
+    /// The config the overlay was created from, which resetting its layout goes back to.
+    config: Arc<OverlayConfig>,
+    /// Set once the overlay's window is gone.
+    closed: Arc<AtomicBool>,
+    /// Set to stop rendering for a while, leaving the last frame up.
+    paused: Arc<AtomicBool>,
+    /// Emit `overlay://frame` after every this many frames, or never if it's zero.
+    frame_events: Arc<AtomicU32>,
+    /// Emit `overlay://stats` every this many milliseconds, or never if it's zero.
+    stats_events: Arc<AtomicU32>,
+    /// The picture-in-picture window the overlay has been moved into, if it has.
+    detached: Arc<Mutex<Option<pip::Detached>>>,
+    /// Set while the overlay follows the mouse cursor.
+    follow: Arc<Mutex<Option<CursorFollow>>>,
+    tweens: Arc<Mutex<Tweens>>,
+}
+
+impl OverlayHandle {
+    /// Record a change to the overlay's saved layout.
+    fn save_layout(&self, f: impl FnOnce(&mut OverlayLayout)) {
+        self.layouts.lock().unwrap().update(&self.id, f);
+    }
+
+    /// Whether the overlay has been put somewhere, rather than following the default placement.
+    fn is_placed(&self) -> bool {
+        self.config.rect.is_some()
+            || self
+                .layouts
+                .lock()
+                .unwrap()
+                .get(&self.id)
+                .is_some_and(|layout| layout.frame.is_some())
+    }
+
+    fn is_detached(&self) -> bool {
+        self.detached.lock().unwrap().is_some()
+    }
+
+    fn set_visible(&self, visible: bool) {
+        self.view.lock().unwrap().set_visible(visible);
+        self.save_layout(|layout| layout.visible = visible);
+        menu::sync(&self.handle, self);
+    }
+
+    /// Show the overlay if it's hidden, or hide it.
+    fn toggle_visible(&self) {
+        // set_visible always saves, so the layout has the current visibility
+        let visible = self
+            .layouts
+            .lock()
+            .unwrap()
+            .get(&self.id)
+            .is_none_or(|layout| layout.visible);
+        self.set_visible(!visible);
+    }
+
+    /// Switch between passing input through to the page and handling it natively.
+    fn set_input_mode(&self, mode: InputMode) -> Result<(), String> {
+        self.view.lock().unwrap().set_input_mode(mode)?;
+        self.save_layout(|layout| layout.input_mode = mode);
+        menu::sync(&self.handle, self);
+        Ok(())
+    }
+
+    /// Let input through to the page if the overlay is handling it, or start handling it.
+    fn toggle_click_through(&self) -> Result<(), String> {
+        let mode = match self.view.lock().unwrap().input_mode() {
+            InputMode::Passthrough => InputMode::Interactive,
+            InputMode::Interactive => InputMode::Passthrough,
+        };
+        self.set_input_mode(mode)
+    }
+
+    /// Show or hide the frame rate readout.
+    fn set_hud_visible(&self, visible: bool) {
+        self.renderer
+            .post(move |wgpu| wgpu.set_hud_visible(visible));
+        self.save_layout(|layout| layout.hud = visible);
+        menu::sync(&self.handle, self);
+    }
+
+    /// Which of the overlay's toggles are on, as its menu items show. `set_visible` and
+    /// `set_hud_visible` always save, so the layout has those.
+    fn toggles(&self) -> menu::Toggles {
+        // The view is let go first, since other code locks the layouts while it's locked
+        let click_through = self.view.lock().unwrap().input_mode() == InputMode::Passthrough;
+        let layouts = self.layouts.lock().unwrap();
+        let layout = layouts.get(&self.id);
+        menu::Toggles {
+            visible: layout.is_none_or(|layout| layout.visible),
+            click_through,
+            hud: layout.is_some_and(|layout| layout.hud),
+        }
+    }
+}
+
+/// Every overlay in the app, by id.
+struct Overlays(Mutex<HashMap<String, OverlayHandle>>);
+
+impl Overlays {
+    /// The overlay called `id`, or the main overlay if there's no id.
+    fn get(&self, id: Option<String>) -> Result<OverlayHandle, String> {
+        let id = id.unwrap_or_else(|| MAIN_OVERLAY.to_string());
+        self.0
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| format!("overlay {:?} has not been created", id))
+    }
+
+    /// Frames of the other overlays in `id`'s window, to snap to.
+    fn frames_except(&self, id: &str) -> Vec<OverlayFrame> {
+        let overlays = self.0.lock().unwrap();
+        let window = match overlays.get(id) {
+            Some(overlay) if !overlay.is_detached() => overlay.window.clone(),
+            _ => return Vec::new(),
+        };
+        overlays
+            .values()
+            .filter(|overlay| {
+                overlay.id != id && overlay.window == window && !overlay.is_detached()
+            })
+            .map(|overlay| overlay.view.lock().unwrap().frame())
+            .collect()
+    }
+
+    fn insert(&self, overlay: OverlayHandle) -> Result<(), String> {
+        let mut overlays = self.0.lock().unwrap();
+        if overlays.contains_key(&overlay.id) {
+            return Err(format!("overlay {:?} already exists", overlay.id));
+        }
+        overlays.insert(overlay.id.clone(), overlay);
+        Ok(())
+    }
+
+    /// Forget the overlays in a window that's gone, and stop rendering them. Returns their
+    /// ids.
+    fn remove_window(&self, window: &str) -> Vec<String> {
+        let mut removed = Vec::new();
+        self.0.lock().unwrap().retain(|_, overlay| {
+            if overlay.window != window {
+                return true;
+            }
+            overlay.closed.store(true, Ordering::Relaxed);
+            overlay.renderer.shutdown();
+            if let Some(detached) = overlay.detached.lock().unwrap().take() {
+                let _ = detached.window.close();
+            }
+            removed.push(overlay.id.clone());
+            false
+        });
+        removed
+    }
+}
+
+struct Layouts(Arc<Mutex<LayoutStore>>);
+
+/// Timelines by id, stepped by their own thread.
+struct Timelines(Mutex<HashMap<String, Timeline>>);
+
+/// The GPU context all overlays share, set up along with the first one.
+struct Gpu(tokio::sync::OnceCell<Arc<GpuContext>>);
+
+impl Gpu {
+    fn get(&self) -> Result<Arc<GpuContext>, String> {
+        self.0
+            .get()
+            .cloned()
+            .ok_or_else(|| "the GPU is set up along with the first overlay".into())
+    }
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    let context = tauri::generate_context!();
+    let configs = config::overlay_configs(context.config());
+    let control_server = config::control_server_config(context.config());
+    let osc = config::osc_config(context.config());
+    let gamepad = config::gamepad_config(context.config());
+    let gpu_config = config::gpu_config(context.config());
+    let app = tauri::Builder::default()
+        .plugin(tauri_plugin_global_shortcut::Builder::new().build())
+        .menu(build_menu)
+        .on_menu_event(menu::handle_event)
+        .manage(Overlays(Mutex::new(HashMap::new())))
+        .manage(Gpu(tokio::sync::OnceCell::new()))
+        .manage(gpu_config)
+        .manage(fallback::Fallbacks::default())
+        .manage(assets::Assets::default())
+        .manage(Timelines(Mutex::new(HashMap::new())))
+        .manage(gamepad::Gamepads::default())
+        .setup(move |app| {
+            tray::build(app.handle())?;
+            let layouts = Arc::new(Mutex::new(LayoutStore::load(app.handle())));
+            // Saving is batched, so that drags don't rewrite the file on every move
+            let saver = layouts.clone();
+            std::thread::spawn(move || loop {
+                std::thread::sleep(Duration::from_millis(500));
+                saver.lock().unwrap().save_if_dirty();
+            });
+            app.manage(Layouts(layouts));
+            let handle = app.handle().clone();
+            let pending = Arc::new(AtomicBool::new(false));
+            std::thread::spawn(move || loop {
+                std::thread::sleep(Duration::from_millis(15));
+                step_timelines(&handle, &pending);
+            });
+            assets::watch(app.handle().clone());
+            app.manage(midi::Midi::load(app.handle()));
+            app.state::<midi::Midi>().reopen(app.handle());
+            if let Some(config) = control_server {
+                control::start(app.handle().clone(), config);
+            }
+            if let Some(config) = osc {
+                osc::start(app.handle().clone(), config);
+            }
+            if let Some(config) = gamepad {
+                gamepad::start(app.handle().clone(), config);
+            }
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            commands::add_overlay,
+            commands::set_overlay_position,
+            commands::set_overlay_follow_cursor,
+            commands::animate_overlay,
+            commands::create_timeline,
+            commands::remove_timeline,
+            commands::play_timeline,
+            commands::pause_timeline,
+            commands::seek_timeline,
+            commands::get_timeline_status,
+            commands::set_clear_color,
+            commands::set_clip_path,
+            commands::set_panes,
+            commands::set_layers,
+            commands::update_layer,
+            commands::set_uniform,
+            commands::get_shader_inputs,
+            commands::set_output_filters,
+            commands::set_render_graph,
+            commands::load_render_graph,
+            commands::load_map,
+            commands::clear_map,
+            commands::get_map_viewport,
+            commands::set_map_view,
+            commands::load_terrain,
+            commands::clear_terrain,
+            commands::set_terrain_models,
+            commands::set_terrain_camera,
+            commands::get_terrain_camera,
+            commands::create_volume,
+            commands::upload_volume_slab,
+            commands::set_transfer_function,
+            commands::get_transfer_function,
+            commands::set_volume_camera,
+            commands::get_volume_camera,
+            commands::clear_volume,
+            commands::create_scatterplot,
+            commands::upload_scatter_points,
+            commands::select_scatter_points,
+            commands::clear_scatter_selection,
+            commands::clear_scatterplot,
+            commands::set_frame_sharing,
+            commands::get_frame_sharing,
+            commands::set_ndi_output,
+            commands::get_ndi_output,
+            commands::set_overlay_attachment,
+            commands::set_overlay_visible,
+            commands::set_overlay_hud,
+            commands::reset_overlay_layout,
+            commands::set_overlay_paused,
+            commands::set_overlay_frame_events,
+            commands::set_overlay_stats_events,
+            commands::get_frame_stats,
+            commands::get_gpu_memory_usage,
+            commands::set_overlay_always_on_top,
+            commands::set_overlay_capture_excluded,
+            commands::set_overlay_backdrop,
+            commands::set_overlay_input_mode,
+            commands::focus_overlay,
+            commands::get_navigation,
+            commands::set_navigation,
+            commands::get_gamepads,
+            commands::set_ink_brush,
+            commands::clear_ink,
+            commands::spawn_physics_body,
+            commands::apply_physics_impulse,
+            commands::remove_physics_body,
+            commands::clear_physics,
+            commands::set_physics_gravity,
+            commands::get_physics_bodies,
+            commands::sample_pixel,
+            commands::get_histogram,
+            commands::set_overlay_drag_region,
+            commands::set_overlay_resizable,
+            commands::set_overlay_snapping,
+            commands::register_hotkey,
+            commands::unregister_hotkey,
+            commands::detach_overlay,
+            commands::reattach_overlay,
+            commands::get_gpu_info,
+            commands::get_fallback_overlays,
+            commands::read_fallback_image,
+            commands::load_asset,
+            commands::load_shader,
+            commands::filter_asset,
+            commands::unload_asset,
+            commands::get_asset,
+            commands::open_video,
+            commands::close_video,
+            commands::play_video,
+            commands::pause_video,
+            commands::seek_video,
+            commands::set_video_rate,
+            commands::set_video_volume,
+            commands::get_video_status,
+            commands::get_playback_stats,
+            commands::load_subtitles,
+            commands::get_subtitle_tracks,
+            commands::select_subtitle_track,
+            commands::set_subtitle_style,
+            commands::get_midi_ports,
+            commands::open_midi_port,
+            commands::close_midi_port,
+            commands::get_midi_port,
+            commands::learn_midi_mapping,
+            commands::cancel_midi_learning,
+            commands::get_midi_mappings,
+            commands::set_midi_mappings,
+            commands::remove_midi_mapping
+        ])
+        .build(context)
+        .expect("failed to build app");
+
+    app.run(move |handle, event| match event {
+        tauri::RunEvent::Ready => {
+            // Set up off the main thread, which keeps running the app in the meantime
+            let handle = handle.clone();
+            let configs = configs.clone();
+            tauri::async_runtime::spawn(async move {
+                for config in &configs {
+                    if let Err(e) = add_overlay(&handle, config).await {
+                        println!("Failed to add overlay {:?}: {}", config.id, e);
+                    }
+                }
+            });
+        }
+        tauri::RunEvent::Exit => {
+            let layouts: tauri::State<Layouts> = handle.state();
+            layouts.0.lock().unwrap().save_if_dirty();
+            if let Ok(gpu) = handle.state::<Gpu>().get() {
+                if let Err(e) = gpu.pipelines.save() {
+                    println!("Failed to save the pipeline cache: {}", e);
+                }
+            }
+        }
+        _ => {}
+    });
+}
+
+/// Create an overlay as its config describes, with any saved layout applied on top. The GPU
+/// is set up off the main thread, and `overlay://ready` goes to the overlay's window once
+/// commands can use it.
+async fn add_overlay(handle: &AppHandle, config: &OverlayConfig) -> Result<(), String> {
+    let window = handle
+        .get_webview_window(&config.window)
+        .ok_or_else(|| format!("there is no window labelled {:?}", config.window))?;
+    let overlays: tauri::State<Overlays> = handle.state();
+    if overlays.get(Some(config.id.clone())).is_ok() {
+        return Err(format!("overlay {:?} already exists", config.id));
+    }
+
+    let mut options = OverlayOptions {
+        transparent: config.transparent,
+        ..OverlayOptions::default()
+    };
+    if let Some(backend) = config.windows_backend {
+        options.windows_backend = backend;
+    }
+    let view_handle = handle.clone();
+    let view_window = window.clone();
+    let overlay_view = on_main_thread(handle, move || unsafe {
+        overlay::add_overlay(&view_handle, &view_window, options)
+    })
+    .await?;
+    let created = match config.renderer {
+        RendererKind::Wgpu => create_wgpu_state(handle, &overlay_view).await,
+    };
+    let wgpu_state = match created {
+        Ok(wgpu_state) => wgpu_state,
+        // The page draws the overlay instead, over the native view that can't
+        Err(e) => {
+            let hidden = overlay_view.clone();
+            on_main_thread(handle, move || hidden.lock().unwrap().set_visible(false)).await?;
+            let fallbacks: tauri::State<fallback::Fallbacks> = handle.state();
+            fallbacks.add(handle, config, e.clone());
+            return Err(format!(
+                "can't render natively, so the page draws it: {}",
+                e
+            ));
+        }
+    };
+
+    let start_handle = handle.clone();
+    let start_config = config.clone();
+    let start_window = window.clone();
+    let overlay = on_main_thread(handle, move || {
+        start_overlay(
+            &start_handle,
+            &start_config,
+            &start_window,
+            overlay_view,
+            wgpu_state,
+        )
+    })
+    .await?;
+    overlays.insert(overlay.clone())?;
+    menu::add_overlay(handle, &overlay);
+
+    let payload = OverlayReadyPayload {
+        overlay: config.id.clone(),
+    };
+    if let Err(e) = window.emit_to(window.label(), "overlay://ready", payload) {
+        println!("Failed to emit overlay://ready: {:?}", e);
+    }
+    Ok(())
+}
+
+/// Run `f` on the main thread, where the platform wants views touched, and wait for its result.
+async fn on_main_thread<T: Send + 'static>(
+    handle: &AppHandle,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, String> {
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    handle
+        .run_on_main_thread(move || {
+            let _ = sender.send(f());
+        })
+        .map_err(|e| e.to_string())?;
+    receiver
+        .await
+        .map_err(|_| "the main thread dropped the task".to_string())
+}
+
+/// Load what the config asks the renderer for, hook the overlay up to its window, and start
+/// rendering it. Runs on the main thread.
+fn start_overlay(
+    handle: &AppHandle,
+    config: &OverlayConfig,
+    window: &WebviewWindow,
+    overlay_view: Arc<Mutex<dyn OverlayView + Send>>,
+    mut wgpu_state: WgpuState,
+) -> OverlayHandle {
+    if let Some(path) = &config.render_graph {
+        let graph = commands::resolve_path(handle, path.clone())
+            .and_then(|path| GraphDescriptor::load(&path))
+            .and_then(|graph| {
+                wgpu_state.set_render_graph(Some(graph), &handle.state::<assets::Assets>())
+            });
+        if let Err(e) = graph {
+            println!(
+                "Failed to load render graph for overlay {:?}: {}",
+                config.id, e
+            );
+        }
+    }
+    if let Err(e) = wgpu_state.set_frame_sharing(config.frame_sharing.clone()) {
+        println!("Failed to share frames of overlay {:?}: {}", config.id, e);
+    }
+    if let Err(e) = wgpu_state.set_ndi_output(config.ndi_output.clone()) {
+        println!("Failed to send overlay {:?} over NDI: {}", config.id, e);
+    }
+
+    let (renderer, render_commands) = RenderHandle::new(wgpu_state.scale_factor());
+    let layouts: tauri::State<Layouts> = handle.state();
+    let overlay = OverlayHandle {
+        id: config.id.clone(),
+        handle: handle.clone(),
+        window: config.window.clone(),
+        view: overlay_view.clone(),
+        renderer: renderer.clone(),
+        video: Arc::new(Mutex::new(None)),
+        mirror_gestures: Arc::new(AtomicBool::new(false)),
+        drag: Arc::new(Mutex::new(DragHandle::default())),
+        snapping: Arc::new(Mutex::new(Snapping::default())),
+        layouts: layouts.0.clone(),
+        config: Arc::new(config.clone()),
+        closed: Arc::new(AtomicBool::new(false)),
+        paused: Arc::new(AtomicBool::new(false)),
+        frame_events: Arc::new(AtomicU32::new(config.frame_events)),
+        stats_events: Arc::new(AtomicU32::new(config.stats_events)),
+        detached: Arc::new(Mutex::new(None)),
+        follow: Arc::new(Mutex::new(None)),
+        tweens: Arc::new(Mutex::new(Tweens::default())),
+    };
+    overlay_view
+        .lock()
+        .unwrap()
+        .set_input_handler(route_input(handle, window, &overlay));
+    if let Ok(scale_factor) = window.scale_factor() {
+        renderer.set_scale_factor(scale_factor);
+    }
+    apply_config(&overlay, config);
+    restore_layout(&overlay);
+    let snapping = overlay.snapping.clone();
+    if let Ok(size) = window.inner_size() {
+        let scale = renderer.scale_factor();
+        snapping
+            .lock()
+            .unwrap()
+            .set_bounds(logical_size(size, scale));
+    }
+
+    let local_overlay = overlay_view.clone();
+    let local_renderer = renderer.clone();
+    let placement = overlay.clone();
+    let window_handle = handle.clone();
+    let label = config.window.clone();
+    window.on_window_event(move |event| match event {
+        // Detached overlays follow their picture-in-picture window instead
+        WindowEvent::Moved(_)
+        | WindowEvent::Resized(_)
+        | WindowEvent::ScaleFactorChanged { .. }
+            if placement.is_detached() => {}
+        WindowEvent::Moved(pos) => {
+            let mut overlay = local_overlay.lock().unwrap();
+            let pos = Position::Physical(*pos);
+            overlay.set_parent_position(pos);
+        }
+        WindowEvent::Resized(size) => {
+            let scale = local_renderer.scale_factor();
+            snapping
+                .lock()
+                .unwrap()
+                .set_bounds(logical_size(*size, scale));
+
+            let mut overlay = local_overlay.lock().unwrap();
+            let overlay_size = match overlay.attached_size() {
+                // The platform already moved and sized the view
+                Some(attached_size) => attached_size,
+                // Leave overlays that were moved or restored where they are
+                None if placement.is_placed() => physical_size(overlay.size(), scale),
+                None => place_default(&mut *overlay, *size),
+            };
+            local_renderer.resize(overlay_size);
+            local_renderer.redraw();
+        }
+        WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+            local_overlay
+                .lock()
+                .unwrap()
+                .set_scale_factor(*scale_factor);
+            local_renderer.set_scale_factor(*scale_factor);
+        }
+        WindowEvent::Destroyed => {
+            let overlays: tauri::State<Overlays> = window_handle.state();
+            let removed = overlays.remove_window(&label);
+            menu::remove_overlays(&window_handle, &removed);
+        }
+        _ => {}
+    });
+
+    let render_overlay = overlay_view.clone();
+    let render_handle = handle.clone();
+    let render_window = window.clone();
+    let render_video = overlay.video.clone();
+    let render_id = overlay.id.clone();
+    let render_follow = overlay.follow.clone();
+    let paused = overlay.paused.clone();
+    let frame_events = overlay.frame_events.clone();
+    let stats_events = overlay.stats_events.clone();
+    let mut stats_emitted = Instant::now();
+    let animated = overlay.clone();
+    let gamepad_navigation = config.gamepad;
+    let mut last_frame = Instant::now();
+    let mut presented: u64 = 0;
+    render_commands.start(
+        wgpu_state,
+        overlay_view.clone(),
+        handle.clone(),
+        move |wgpu| {
+            if paused.load(Ordering::Relaxed) {
+                wgpu.frame_skipped();
+                return;
+            }
+            follow_cursor(&render_handle, &render_overlay, &render_follow);
+            animate(&render_handle, &animated);
+            let mut video = render_video.lock().unwrap();
+            let (video_frame, subtitle, video_status, video_ended) = match video.as_mut() {
+                Some(video) => {
+                    let frame = video.frame_due();
+                    let ended = video.take_ended().then(|| video.status());
+                    (frame, video.subtitle_due(), video.status_due(), ended)
+                }
+                None => (None, None, None, None),
+            };
+            drop(video);
+            if let Some(status) = video_status {
+                emit_video(&render_window, "video://position", &render_id, status);
+            }
+            if let Some(status) = video_ended {
+                emit_video(&render_window, "video://ended", &render_id, status);
+            }
+            // wgpu_state.resize(PhysicalSize {
+            //     width: 200,
+            //     height: 200,
+            // });
+            let now = Instant::now();
+            let elapsed = now.duration_since(last_frame).as_secs_f64();
+            last_frame = now;
+            wgpu.refresh_assets(&render_handle.state::<assets::Assets>());
+            wgpu.step_physics(elapsed);
+            if gamepad_navigation {
+                let gamepads: tauri::State<gamepad::Gamepads> = render_handle.state();
+                if let Some(input) = gamepads.navigation() {
+                    wgpu.drive_navigation(&input, elapsed);
+                }
+            }
+            if let Some(frame) = video_frame {
+                wgpu.set_video_frame(frame);
+            }
+            if let Some(text) = subtitle {
+                wgpu.set_subtitle(text);
+            }
+            // Dropped, rather than stopping the overlay, so one bad frame doesn't end it
+            let frame = match wgpu.render() {
+                Ok(frame) => frame,
+                Err(e) => {
+                    println!("Failed to render overlay {:?}: {}", render_id, e);
+                    None
+                }
+            };
+            let map_viewport = wgpu.take_map_viewport_change();
+            if let Some(viewport) = map_viewport {
+                emit_map_viewport(&render_window, &render_id, viewport);
+            }
+            // Only frames that made it to the screen are counted
+            if let Some(frame) = frame {
+                if let Some(pixels) = frame.frame() {
+                    render_overlay.lock().unwrap().present_frame(pixels);
+                }
+                let divisor = frame_events.load(Ordering::Relaxed) as u64;
+                if divisor > 0 && presented.is_multiple_of(divisor) {
+                    emit_frame_presented(&render_window, &render_id, presented);
+                }
+                presented += 1;
+            }
+            let interval = stats_events.load(Ordering::Relaxed);
+            if interval > 0 && stats_emitted.elapsed() >= Duration::from_millis(interval as u64) {
+                stats_emitted = Instant::now();
+                emit_frame_stats(&render_window, &render_id, wgpu.frame_stats());
+            }
+        },
+    );
+
+    for hotkey in &config.hotkeys {
+        if let Err(e) = hotkeys::register(handle, &overlay, hotkey.clone()) {
+            println!("Failed to add hotkey to overlay {:?}: {}", config.id, e);
+        }
+    }
+
+    overlay
+}
+
+async fn create_wgpu_state(
+    handle: &AppHandle,
+    overlay_view: &Arc<Mutex<dyn OverlayView + Send>>,
+) -> Result<WgpuState, String> {
+    let gpu: tauri::State<Gpu> = handle.state();
+    let config: tauri::State<GpuConfig> = handle.state();
+    let data_dir = handle.path().app_data_dir().ok();
+    // The first overlay's surface comes out of setting up the GPU with it
+    let mut first_surface = None;
+    let first = &mut first_surface;
+    let gpu = gpu
+        .0
+        .get_or_try_init(move || async move {
+            let (gpu, surface) =
+                GpuContext::new(overlay_view, &config, data_dir.as_deref()).await?;
+            *first = Some(surface);
+            Ok::<_, String>(Arc::new(gpu))
+        })
+        .await?
+        .clone();
+    let surface = match first_surface {
+        Some(surface) => surface,
+        None => gpu.create_surface(&*overlay_view.lock().unwrap())?,
+    };
+    let wgpu_state = WgpuState::new(
+        gpu,
+        surface,
+        PhysicalSize {
+            width: 200,
+            height: 200,
+        },
+    );
+
+    overlay_view.lock().unwrap().surface_configured();
+    Ok(wgpu_state)
+}
+
+/// Send navigation gestures to the renderer and emit everything else (and the gestures
+/// too, if mirrored) to the overlay's window as `overlay://` events. Pen input also goes to the
+/// renderer's ink layer, and drags in the drag region move the overlay instead.
+fn route_input(
+    handle: &AppHandle,
+    window: &WebviewWindow,
+    overlay: &OverlayHandle,
+) -> InputHandler {
+    let handle = handle.clone();
+    let window = window.clone();
+    let id = overlay.id.clone();
+    // The view owns this handler, so it can only hold on to the view weakly
+    let view: Weak<Mutex<dyn OverlayView + Send>> = Arc::downgrade(&overlay.view);
+    let renderer = overlay.renderer.clone();
+    let mirror_gestures = overlay.mirror_gestures.clone();
+    let drag = overlay.drag.clone();
+    let snapping = overlay.snapping.clone();
+    let layouts = overlay.layouts.clone();
+    let detached = overlay.detached.clone();
+    Arc::new(move |event: InputEvent| {
+        let pip_window = detached
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|detached| detached.window.clone());
+        if let Some(view) = view.upgrade() {
+            let mut view = view.lock().unwrap();
+            // A detached overlay fills its window, so drags move and size the window
+            let frame = pip_window
+                .as_ref()
+                .and_then(pip::window_frame)
+                .unwrap_or_else(|| view.frame());
+            if let Some(mut dragged) = drag.lock().unwrap().apply(&event, frame) {
+                if let Some(pip_window) = &pip_window {
+                    // Resizing the window calls back into the view
+                    drop(view);
+                    if dragged != frame {
+                        pip::set_window_frame(pip_window, dragged);
+                    }
+                    return;
+                }
+                if dragged.width != frame.width || dragged.height != frame.height {
+                    view.set_size(Size::Logical(LogicalSize {
+                        width: dragged.width,
+                        height: dragged.height,
+                    }));
+                    // Reconfigure right away, so the content keeps up with the drag
+                    let scale = renderer.scale_factor();
+                    let size = view.attached_size().unwrap_or_else(|| {
+                        physical_size(
+                            LogicalSize {
+                                width: dragged.width,
+                                height: dragged.height,
+                            },
+                            scale,
+                        )
+                    });
+                    renderer.resize(size);
+                    renderer.redraw();
+                    emit_frame(&window, "overlay://resized", &id, dragged);
+                }
+                if dragged.x != frame.x || dragged.y != frame.y {
+                    if dragged.width == frame.width && dragged.height == frame.height {
+                        let overlays: tauri::State<Overlays> = handle.state();
+                        let others = overlays.frames_except(&id);
+                        dragged = snapping.lock().unwrap().snap(dragged, &others);
+                    }
+                    view.set_origin(Position::Logical(LogicalPosition {
+                        x: dragged.x,
+                        y: dragged.y,
+                    }));
+                    emit_frame(&window, "overlay://moved", &id, dragged);
+                }
+                if dragged != frame {
+                    layouts
+                        .lock()
+                        .unwrap()
+                        .update(&id, |layout| layout.frame = Some(dragged));
+                }
+                return;
+            }
+        }
+
+        if event.kind.is_gesture() {
+            let gesture = event.clone();
+            renderer.post(move |wgpu| wgpu.handle_gesture(&gesture));
+            if !mirror_gestures.load(Ordering::Relaxed) {
+                return;
+            }
+        }
+        if event.pointer_type == Some(PointerType::Pen) {
+            let pen = event.clone();
+            renderer.post(move |wgpu| {
+                wgpu.handle_pen(&pen);
+            });
+        }
+
+        let name = event.kind.event_name();
+        let payload = InputPayload {
+            overlay: id.clone(),
+            event,
+        };
+        if let Err(e) = window.emit_to(window.label(), name, payload) {
+            println!("Failed to emit {}: {:?}", name, e);
+        }
+    })
+}
+
+/// Set the overlay up the way its config says to.
+fn apply_config(overlay: &OverlayHandle, config: &OverlayConfig) {
+    let mut view = overlay.view.lock().unwrap();
+    if let Some(frame) = config.rect {
+        place(&mut *view, &overlay.renderer, frame);
+    }
+    if let Some(attachment) = config.attachment.clone() {
+        if let Err(e) = view.set_attachment(attachment) {
+            println!("Failed to attach overlay {:?}: {}", config.id, e);
+        }
+        if let Some(size) = view.attached_size() {
+            overlay.renderer.resize(size);
+        }
+    }
+    view.surface_configured();
+    if let Some(backdrop) = config.backdrop {
+        if let Err(e) = view.set_backdrop(Some(backdrop)) {
+            println!("Failed to add a backdrop to overlay {:?}: {}", config.id, e);
+        }
+    }
+    if config.exclude_from_capture {
+        if let Err(e) = view.set_capture_excluded(true) {
+            println!(
+                "Failed to exclude overlay {:?} from capture: {}",
+                config.id, e
+            );
+        }
+    }
+    if let Err(e) = view.set_focus_policy(config.focus) {
+        println!("Failed to set overlay {:?} focus policy: {}", config.id, e);
+    }
+    if !config.passthrough {
+        if let Err(e) = view.set_input_mode(InputMode::Interactive) {
+            println!("Failed to make overlay {:?} interactive: {}", config.id, e);
+        }
+    }
+}
+
+/// Put the overlay back the way it was saved, if it was.
+fn restore_layout(overlay: &OverlayHandle) {
+    let layout = match overlay.layouts.lock().unwrap().get(&overlay.id) {
+        Some(layout) => layout.clone(),
+        None => return,
+    };
+
+    let mut view = overlay.view.lock().unwrap();
+    let renderer = &overlay.renderer;
+    if let Some([r, g, b, a]) = layout.clear_color {
+        renderer.post(move |wgpu| wgpu.set_clear_color(wgpu::Color { r, g, b, a }));
+    }
+    // The frame goes first, since autoresizing attachments take their margins from it
+    if let Some(frame) = layout.frame {
+        place(&mut *view, renderer, frame);
+    }
+    if let Some(attachment) = layout.attachment {
+        if let Err(e) = view.set_attachment(attachment) {
+            println!("Failed to restore overlay attachment: {}", e);
+        }
+    }
+    if let Some(size) = view.attached_size() {
+        renderer.resize(size);
+    }
+    view.surface_configured();
+    view.set_visible(layout.visible);
+    let hud = layout.hud;
+    renderer.post(move |wgpu| wgpu.set_hud_visible(hud));
+    if layout.always_on_top {
+        if let Err(e) = view.set_always_on_top(true) {
+            println!("Failed to restore overlay always-on-top: {}", e);
+        }
+    }
+    if let Some(backdrop) = layout.backdrop {
+        if let Err(e) = view.set_backdrop(Some(backdrop)) {
+            println!("Failed to restore overlay backdrop: {}", e);
+        }
+    }
+    if let Err(e) = view.set_focus_policy(layout.focus) {
+        println!("Failed to restore overlay focus policy: {}", e);
+    }
+    if let Err(e) = view.set_input_mode(layout.input_mode) {
+        println!("Failed to restore overlay input mode: {}", e);
+    }
+    overlay
+        .mirror_gestures
+        .store(layout.mirror_gestures, Ordering::Relaxed);
+
+    let mut drag = overlay.drag.lock().unwrap();
+    drag.set_region(layout.drag_region);
+    drag.set_resize(layout.resize);
+    overlay
+        .snapping
+        .lock()
+        .unwrap()
+        .set_options(layout.snapping);
+}
+
+/// Forget the overlay's saved layout and set it up again from its config, back where the
+/// config put it or in the default placement, and emit `overlay://reset` with its frame.
+fn reset_layout(handle: &AppHandle, overlay: &OverlayHandle) -> Result<(), String> {
+    if overlay.is_detached() {
+        return Err(format!("overlay {:?} is detached", overlay.id));
+    }
+    let window = handle
+        .get_webview_window(&overlay.window)
+        .ok_or_else(|| format!("there is no window labelled {:?}", overlay.window))?;
+    overlay.layouts.lock().unwrap().remove(&overlay.id);
+    *overlay.follow.lock().unwrap() = None;
+    *overlay.tweens.lock().unwrap() = Tweens::default();
+
+    overlay.renderer.post(|wgpu| {
+        wgpu.set_clear_color(renderer::DEFAULT_CLEAR_COLOR);
+        wgpu.set_hud_visible(false);
+    });
+    let mut view = overlay.view.lock().unwrap();
+    if let Err(e) = view.set_attachment(Attachment::Absolute) {
+        println!("Failed to reset overlay attachment: {}", e);
+    }
+    view.set_visible(true);
+    if let Err(e) = view.set_opacity(1.0) {
+        println!("Failed to reset overlay opacity: {}", e);
+    }
+    if let Err(e) = view.set_always_on_top(false) {
+        println!("Failed to reset overlay always-on-top: {}", e);
+    }
+    if let Err(e) = view.set_backdrop(None) {
+        println!("Failed to reset overlay backdrop: {}", e);
+    }
+    if let Err(e) = view.set_input_mode(InputMode::default()) {
+        println!("Failed to reset overlay input mode: {}", e);
+    }
+    if let Err(e) = view.set_focus_policy(FocusPolicy::default()) {
+        println!("Failed to reset overlay focus policy: {}", e);
+    }
+    drop(view);
+    overlay.mirror_gestures.store(false, Ordering::Relaxed);
+    let mut drag = overlay.drag.lock().unwrap();
+    drag.set_region(None);
+    drag.set_resize(None);
+    drop(drag);
+    overlay.snapping.lock().unwrap().set_options(None);
+
+    apply_config(overlay, &overlay.config);
+    let mut view = overlay.view.lock().unwrap();
+    if overlay.config.rect.is_none() && view.attached_size().is_none() {
+        let size = window.inner_size().map_err(|e| format!("{:?}", e))?;
+        let size = place_default(&mut *view, size);
+        overlay.renderer.resize(size);
+    }
+    let frame = view.frame();
+    drop(view);
+    menu::sync(handle, overlay);
+    emit_frame(&window, "overlay://reset", &overlay.id, frame);
+    Ok(())
+}
+
+/// Move a cursor-following overlay a step closer to the cursor, if it's following it. This
+/// runs with every frame, but the move happens on the main thread.
+fn follow_cursor(
+    handle: &AppHandle,
+    view: &Arc<Mutex<dyn OverlayView + Send>>,
+    follow: &Arc<Mutex<Option<CursorFollow>>>,
+) {
+    match follow.lock().unwrap().as_mut() {
+        Some(follow) if !follow.pending => follow.pending = true,
+        _ => return,
+    }
+    let view = view.clone();
+    let follow = follow.clone();
+    overlay::run_on_main_thread(handle, move || {
+        let mut view = view.lock().unwrap();
+        let mut follow = follow.lock().unwrap();
+        let follow = match follow.as_mut() {
+            Some(follow) => follow,
+            None => return,
+        };
+        follow.pending = false;
+        if let Some(origin) = view
+            .cursor_position()
+            .and_then(|cursor| follow.step(cursor))
+        {
+            view.set_origin(Position::Logical(origin));
+        }
+    });
+}
+
+/// Center the overlay near the top of a window that's `size`, at a fraction of its size,
+/// returning the overlay's new size.
+fn place_default(view: &mut dyn OverlayView, size: PhysicalSize<u32>) -> PhysicalSize<u32> {
+    let overlay_width = size.width as f64 * 0.3;
+    let overlay_height = size.height as f64 * 0.1;
+    let overlay_y = 100;
+    let x = (size.width as f64 - overlay_width) / 2.0;
+    let y = overlay_y as f64;
+    let overlay_size = PhysicalSize {
+        width: overlay_width as u32,
+        height: overlay_height as u32,
+    };
+    view.set_origin(Position::Physical(PhysicalPosition {
+        x: x as i32,
+        y: y as i32,
+    }));
+    view.set_size(Size::Physical(overlay_size));
+    overlay_size
+}
+
+/// Step the overlay's tweens, if it has any. Like `follow_cursor`, this runs with every
+/// frame but the changes happen on the main thread.
+fn animate(handle: &AppHandle, overlay: &OverlayHandle) {
+    let mut tweens = overlay.tweens.lock().unwrap();
+    if tweens.is_empty() || tweens.pending {
+        return;
+    }
+    tweens.pending = true;
+    drop(tweens);
+    let overlay = overlay.clone();
+    overlay::run_on_main_thread(handle, move || {
+        let mut tweens = overlay.tweens.lock().unwrap();
+        tweens.pending = false;
+        let values = tweens.step();
+        drop(tweens);
+        apply_animated(&overlay, &values);
+    });
+}
+
+/// Step the timelines that are playing, or were just seeked, and emit `timeline://ended`
+/// for any that ended. Runs on its own thread, since timelines span overlays, with the
+/// changes happening on the main thread like `animate`'s. `pending` is set while they are.
+fn step_timelines(handle: &AppHandle, pending: &Arc<AtomicBool>) {
+    if pending.load(Ordering::Relaxed) {
+        return;
+    }
+    let timelines: tauri::State<Timelines> = handle.state();
+    let mut values: HashMap<String, Vec<(Property, f64)>> = HashMap::new();
+    for (id, timeline) in timelines.0.lock().unwrap().iter_mut() {
+        let (stepped, ended) = match timeline.step() {
+            Some(step) => step,
+            None => continue,
+        };
+        for (overlay, property, value) in stepped {
+            values.entry(overlay).or_default().push((property, value));
+        }
+        if ended {
+            let payload = TimelinePayload {
+                timeline: id.clone(),
+            };
+            if let Err(e) = handle.emit("timeline://ended", payload) {
+                println!("Failed to emit timeline://ended: {:?}", e);
+            }
+        }
+    }
+    if values.is_empty() {
+        return;
+    }
+
+    // Overlays that don't exist (yet) are skipped
+    let overlays: tauri::State<Overlays> = handle.state();
+    let animated: Vec<_> = values
+        .into_iter()
+        .filter_map(|(id, values)| Some((overlays.get(Some(id)).ok()?, values)))
+        .collect();
+    pending.store(true, Ordering::Relaxed);
+    let pending = pending.clone();
+    overlay::run_on_main_thread(handle, move || {
+        for (overlay, values) in &animated {
+            apply_animated(overlay, values);
+        }
+        pending.store(false, Ordering::Relaxed);
+    });
+}
+
+/// Set animated properties, saving the frame and clear color if they changed.
+fn apply_animated(overlay: &OverlayHandle, values: &[(Property, f64)]) {
+    let mut view = overlay.view.lock().unwrap();
+    animation::apply(&mut *view, &overlay.renderer, values);
+    let frame = view.frame();
+    drop(view);
+    if values.iter().any(|(property, _)| property.is_frame()) {
+        overlay.save_layout(|layout| layout.frame = Some(frame));
+    }
+    if values
+        .iter()
+        .any(|(property, _)| matches!(property, Property::ClearColor(_)))
+    {
+        // Saved once the renderer has the new color
+        let saved = overlay.clone();
+        overlay.renderer.post(move |wgpu| {
+            let color = wgpu.clear_color();
+            saved.save_layout(|layout| {
+                layout.clear_color = Some([color.r, color.g, color.b, color.a])
+            });
+        });
+    }
+}
+
+/// Move and size an absolutely positioned overlay, resizing its surface to match.
+fn place(view: &mut dyn OverlayView, renderer: &RenderHandle, frame: OverlayFrame) {
+    let size = LogicalSize {
+        width: frame.width,
+        height: frame.height,
+    };
+    view.set_size(Size::Logical(size));
+    view.set_origin(Position::Logical(LogicalPosition {
+        x: frame.x,
+        y: frame.y,
+    }));
+    renderer.resize(physical_size(size, renderer.scale_factor()));
+}
+
+fn physical_size(size: LogicalSize<f64>, scale: f64) -> PhysicalSize<u32> {
+    PhysicalSize {
+        width: (size.width * scale).round() as u32,
+        height: (size.height * scale).round() as u32,
+    }
+}
+
+fn logical_size(size: PhysicalSize<u32>, scale: f64) -> LogicalSize<f64> {
+    LogicalSize {
+        width: size.width as f64 / scale,
+        height: size.height as f64 / scale,
+    }
+}
+
+fn emit_frame(window: &WebviewWindow, name: &str, id: &str, frame: OverlayFrame) {
+    let payload = FramePayload {
+        overlay: id.to_string(),
+        frame,
+    };
+    if let Err(e) = window.emit_to(window.label(), name, payload) {
+        println!("Failed to emit {}: {:?}", name, e);
+    }
+}
+
+/// Emitted as `overlay://frame` once a frame is on screen, for syncing the page's
+/// animations with the overlay's.
+#[derive(Debug, Clone, Serialize)]
+struct FramePresentedPayload {
+    overlay: String,
+    /// Counts up from zero with every frame the overlay presents.
+    frame: u64,
+    /// When the frame was presented, in milliseconds since the Unix epoch like `Date.now()`.
+    timestamp: f64,
+}
+
+/// Emitted as `overlay://ready` to the overlay's window once it's rendering and commands can
+/// use it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OverlayReadyPayload {
+    overlay: String,
+}
+
+/// Emitted as `overlay://stats` every so often, if the overlay was asked to.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FrameStatsPayload {
+    overlay: String,
+    stats: FrameStats,
+}
+
+fn emit_frame_stats(window: &WebviewWindow, id: &str, stats: FrameStats) {
+    let payload = FrameStatsPayload {
+        overlay: id.to_string(),
+        stats,
+    };
+    if let Err(e) = window.emit_to(window.label(), "overlay://stats", payload) {
+        println!("Failed to emit overlay://stats: {:?}", e);
+    }
+}
+
+fn emit_frame_presented(window: &WebviewWindow, id: &str, frame: u64) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |since| since.as_secs_f64() * 1000.0);
+    let payload = FramePresentedPayload {
+        overlay: id.to_string(),
+        frame,
+        timestamp,
+    };
+    if let Err(e) = window.emit_to(window.label(), "overlay://frame", payload) {
+        println!("Failed to emit overlay://frame: {:?}", e);
+    }
+}
+
+fn emit_video(window: &WebviewWindow, name: &str, id: &str, status: video::VideoStatus) {
+    let payload = VideoPayload {
+        overlay: id.to_string(),
+        status,
+    };
+    if let Err(e) = window.emit_to(window.label(), name, payload) {
+        println!("Failed to emit {}: {:?}", name, e);
+    }
+}
+
+fn emit_map_viewport(window: &WebviewWindow, id: &str, viewport: MapViewport) {
+    let payload = MapViewportPayload {
+        overlay: id.to_string(),
+        viewport,
+    };
+    if let Err(e) = window.emit_to(window.label(), "map://viewport", payload) {
+        println!("Failed to emit map://viewport: {:?}", e);
+    }
+}
+
+fn build_menu(handle: &AppHandle) -> tauri::Result<Menu<Wry>> {
+    let app = Submenu::with_items(
+        handle,
+        "app",
+        true,
+        &[
+            &PredefinedMenuItem::hide(handle, None)?,
+            &PredefinedMenuItem::quit(handle, None)?,
+        ],
+    )?;
+    let edit = Submenu::with_items(
+        handle,
+        "Edit",
+        true,
+        &[
+            &PredefinedMenuItem::copy(handle, None)?,
+            &PredefinedMenuItem::cut(handle, None)?,
+            &PredefinedMenuItem::paste(handle, None)?,
+            &PredefinedMenuItem::separator(handle)?,
+            &PredefinedMenuItem::undo(handle, None)?,
+            &PredefinedMenuItem::redo(handle, None)?,
+            &PredefinedMenuItem::separator(handle)?,
+            &PredefinedMenuItem::select_all(handle, None)?,
+        ],
+    )?;
+    Menu::with_items(handle, &[&app, &edit, &menu::overlays_submenu(handle)?])
+}
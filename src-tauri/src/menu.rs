@@ -0,0 +1,237 @@
+//! The "Overlays" menu, with a submenu per overlay for the controls that are handy while
+//! developing: visibility, the frame rate readout and resetting its layout. Overlays are
+//! listed as they're added and go when their window does, and check marks follow the
+//! overlays however their toggles change.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use tauri::menu::{CheckMenuItem, MenuEvent, MenuItem, MenuItemKind, Submenu};
+use tauri::{AppHandle, Manager, Wry};
+
+use crate::{tray, OverlayHandle, Overlays};
+
+/// Menu item ids are `overlay:<action>:<overlay id>`.
+const ID_PREFIX: &str = "overlay:";
+
+/// The actions in each overlay's submenu of the app menu.
+const ACTIONS: [MenuAction; 3] = [
+    MenuAction::ToggleVisible,
+    MenuAction::ToggleHud,
+    MenuAction::ResetLayout,
+];
+
+/// Which of an overlay's toggles are on, for its items' check marks.
+#[derive(Debug, Clone, Copy)]
+pub struct Toggles {
+    pub visible: bool,
+    pub click_through: bool,
+    pub hud: bool,
+}
+
+/// What an overlay's menu items do, in the app menu or the tray's.
+#[derive(Debug, Clone, Copy)]
+pub enum MenuAction {
+    ToggleVisible,
+    ToggleClickThrough,
+    ToggleHud,
+    ResetLayout,
+}
+
+impl MenuAction {
+    fn name(self) -> &'static str {
+        match self {
+            MenuAction::ToggleVisible => "visible",
+            MenuAction::ToggleClickThrough => "clickThrough",
+            MenuAction::ToggleHud => "hud",
+            MenuAction::ResetLayout => "reset",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<MenuAction> {
+        match name {
+            "visible" => Some(MenuAction::ToggleVisible),
+            "clickThrough" => Some(MenuAction::ToggleClickThrough),
+            "hud" => Some(MenuAction::ToggleHud),
+            "reset" => Some(MenuAction::ResetLayout),
+            _ => None,
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            MenuAction::ToggleVisible => "Visible",
+            MenuAction::ToggleClickThrough => "Click-Through",
+            MenuAction::ToggleHud => "FPS HUD",
+            MenuAction::ResetLayout => "Reset Layout",
+        }
+    }
+
+    /// Whether the item is checked, or `None` if it isn't a toggle.
+    fn checked(self, toggles: Toggles) -> Option<bool> {
+        match self {
+            MenuAction::ToggleVisible => Some(toggles.visible),
+            MenuAction::ToggleClickThrough => Some(toggles.click_through),
+            MenuAction::ToggleHud => Some(toggles.hud),
+            MenuAction::ResetLayout => None,
+        }
+    }
+
+    /// An item that does this to `overlay`, checked as `toggles` say if it's a toggle.
+    pub fn item(
+        self,
+        handle: &AppHandle,
+        overlay: &str,
+        toggles: Toggles,
+    ) -> tauri::Result<MenuItemKind<Wry>> {
+        let id = format!("{}{}:{}", ID_PREFIX, self.name(), overlay);
+        Ok(match self.checked(toggles) {
+            Some(checked) => MenuItemKind::Check(CheckMenuItem::with_id(
+                handle,
+                id,
+                self.title(),
+                true,
+                checked,
+                None::<&str>,
+            )?),
+            None => MenuItemKind::MenuItem(MenuItem::with_id(
+                handle,
+                id,
+                self.title(),
+                true,
+                None::<&str>,
+            )?),
+        })
+    }
+}
+
+/// The app menu's "Overlays" submenu, and what it has for each overlay by id.
+pub struct OverlaysMenu {
+    menu: Submenu<Wry>,
+    overlays: Mutex<HashMap<String, OverlayItems>>,
+}
+
+/// An overlay's submenu and the items in it.
+struct OverlayItems {
+    submenu: Submenu<Wry>,
+    items: Vec<(MenuAction, MenuItemKind<Wry>)>,
+}
+
+/// The "Overlays" submenu, empty until overlays are added. It's managed too, so that
+/// `add_overlay` and `remove_overlays` can find it.
+pub fn overlays_submenu(handle: &AppHandle) -> tauri::Result<Submenu<Wry>> {
+    let menu = Submenu::new(handle, "Overlays", true)?;
+    handle.manage(OverlaysMenu {
+        menu: menu.clone(),
+        overlays: Mutex::new(HashMap::new()),
+    });
+    Ok(menu)
+}
+
+/// List a newly added overlay in the app menu and the tray's.
+pub fn add_overlay(handle: &AppHandle, overlay: &OverlayHandle) {
+    if let Some(menu) = handle.try_state::<OverlaysMenu>() {
+        let toggles = overlay.toggles();
+        let added = Submenu::new(handle, &overlay.id, true).and_then(|submenu| {
+            let mut items = Vec::new();
+            for action in ACTIONS {
+                let item = action.item(handle, &overlay.id, toggles)?;
+                submenu.append(&item)?;
+                items.push((action, item));
+            }
+            menu.menu.append(&submenu)?;
+            Ok(OverlayItems { submenu, items })
+        });
+        match added {
+            Ok(added) => {
+                menu.overlays
+                    .lock()
+                    .unwrap()
+                    .insert(overlay.id.clone(), added);
+            }
+            Err(e) => println!(
+                "Failed to add overlay {:?} to the menu: {:?}",
+                overlay.id, e
+            ),
+        }
+    }
+    tray::rebuild(handle);
+}
+
+/// Take overlays that are gone out of the app menu and the tray's.
+pub fn remove_overlays(handle: &AppHandle, ids: &[String]) {
+    if let Some(menu) = handle.try_state::<OverlaysMenu>() {
+        let mut overlays = menu.overlays.lock().unwrap();
+        for id in ids {
+            if let Some(removed) = overlays.remove(id) {
+                if let Err(e) = menu.menu.remove(&removed.submenu) {
+                    println!("Failed to remove overlay {:?} from the menu: {:?}", id, e);
+                }
+            }
+        }
+    }
+    tray::rebuild(handle);
+}
+
+/// Check `overlay`'s items, in the app menu and the tray's, as its toggles are now.
+pub fn sync(handle: &AppHandle, overlay: &OverlayHandle) {
+    if let Some(menu) = handle.try_state::<OverlaysMenu>() {
+        let toggles = overlay.toggles();
+        if let Some(listed) = menu.overlays.lock().unwrap().get(&overlay.id) {
+            for (action, item) in &listed.items {
+                if let (Some(checked), Some(item)) =
+                    (action.checked(toggles), item.as_check_menuitem())
+                {
+                    let _ = item.set_checked(checked);
+                }
+            }
+        }
+    }
+    tray::rebuild(handle);
+}
+
+/// Menu events come here from the app menu and the tray's alike.
+pub fn handle_event(handle: &AppHandle, event: MenuEvent) {
+    let id = event.id().as_ref();
+    if !handle_item(handle, id) {
+        tray::handle_item(handle, id);
+    }
+}
+
+/// Do what an overlay's menu item says, returning false if `item_id` isn't an overlay's.
+pub fn handle_item(handle: &AppHandle, item_id: &str) -> bool {
+    let (action, id) = match item_id
+        .strip_prefix(ID_PREFIX)
+        .and_then(|item| item.split_once(':'))
+        .and_then(|(action, id)| Some((MenuAction::from_name(action)?, id)))
+    {
+        Some(item) => item,
+        None => return false,
+    };
+    let overlays: tauri::State<Overlays> = handle.state();
+    let overlay = match overlays.get(Some(id.to_string())) {
+        Ok(overlay) => overlay,
+        Err(e) => {
+            println!("Failed to {:?} overlay {:?}: {}", action, id, e);
+            return true;
+        }
+    };
+    let result = match action {
+        MenuAction::ToggleVisible => {
+            overlay.toggle_visible();
+            Ok(())
+        }
+        MenuAction::ToggleClickThrough => overlay.toggle_click_through(),
+        MenuAction::ToggleHud => {
+            overlay.set_hud_visible(!overlay.toggles().hud);
+            Ok(())
+        }
+        MenuAction::ResetLayout => crate::reset_layout(handle, &overlay),
+    };
+    if let Err(e) = result {
+        println!("Failed to {:?} overlay {:?}: {}", action, id, e);
+        // Clicking a check item flips its mark, which has to go back as it was
+        sync(handle, &overlay);
+    }
+    true
+}
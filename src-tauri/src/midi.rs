@@ -0,0 +1,345 @@
+//! MIDI controller input. Knobs and faders are mapped to overlay properties, like layer
+//! opacities or clear color channels, by moving them while learning, and the mappings are
+//! kept in a JSON file in the app data directory along with the port they came from.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+
+use midir::{MidiInput, MidiInputConnection};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::animation::{LiveValues, Property};
+
+const MAPPING_FILE: &str = "midi.json";
+/// What the app shows up as to other MIDI software.
+const CLIENT_NAME: &str = "Overlays";
+/// The status byte of a control change, without its channel.
+const CONTROL_CHANGE: u8 = 0xB0;
+
+/// A knob or fader: a control change controller on a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Control {
+    /// From 0 to 15.
+    pub channel: u8,
+    pub controller: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MidiMapping {
+    pub control: Control,
+    /// The overlay whose property the control sets, or the main overlay.
+    #[serde(default)]
+    pub overlay: Option<String>,
+    pub property: Property,
+    /// What the control's lowest and highest positions set the property to. Without one,
+    /// they go from 0 to 1.
+    #[serde(default)]
+    pub range: Option<[f64; 2]>,
+}
+
+impl MidiMapping {
+    /// The property's value with the control at `value`, from 0 to 127.
+    fn value(&self, value: u8) -> f64 {
+        let [min, max] = self.range.unwrap_or([0.0, 1.0]);
+        min + (max - min) * value as f64 / 127.0
+    }
+}
+
+/// Emitted as `midi://control` whenever a control moves, mapped or not, so the page can
+/// show it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ControlPayload {
+    #[serde(flatten)]
+    pub control: Control,
+    /// From 0 to 1.
+    pub value: f64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MappingFile {
+    /// The port that was last opened, to open again at startup.
+    #[serde(default)]
+    port: Option<String>,
+    #[serde(default)]
+    mappings: Vec<MidiMapping>,
+}
+
+/// A property waiting for the next control that moves, which `midi://learned` reports.
+struct Learning {
+    overlay: Option<String>,
+    property: Property,
+    range: Option<[f64; 2]>,
+}
+
+struct Connection {
+    port: String,
+    /// Dropped to close the port. The connection itself stays on the thread that opened it.
+    _close: Sender<()>,
+}
+
+#[derive(Default)]
+struct MidiState {
+    path: Option<PathBuf>,
+    file: MappingFile,
+    connection: Option<Connection>,
+    learning: Option<Learning>,
+}
+
+impl MidiState {
+    fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        let result = serde_json::to_string_pretty(&self.file)
+            .map_err(|e| e.to_string())
+            .and_then(|json| {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                }
+                fs::write(path, json).map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            println!("Failed to save MIDI mappings to {:?}: {}", path, e);
+        }
+    }
+}
+
+/// MIDI input and its mappings, managed by the app.
+pub struct Midi {
+    state: Mutex<MidiState>,
+    live: Arc<LiveValues>,
+}
+
+impl Midi {
+    /// Read the saved mappings. Their port is opened again by `reopen`.
+    pub fn load(handle: &AppHandle) -> Self {
+        let path = handle
+            .path()
+            .app_config_dir()
+            .ok()
+            .map(|dir| dir.join(MAPPING_FILE));
+        let file: MappingFile = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|json| match serde_json::from_str(&json) {
+                Ok(file) => Some(file),
+                Err(e) => {
+                    println!("Ignoring unreadable MIDI mappings: {}", e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        Midi {
+            state: Mutex::new(MidiState {
+                path,
+                file,
+                ..MidiState::default()
+            }),
+            live: Arc::default(),
+        }
+    }
+
+    /// Open the port that was open last time, if there was one. Ports that aren't plugged
+    /// in are skipped quietly.
+    pub fn reopen(&self, handle: &AppHandle) {
+        let port = self.state.lock().unwrap().file.port.clone();
+        if let Some(port) = port {
+            if ports().is_ok_and(|ports| ports.contains(&port)) {
+                if let Err(e) = self.open(handle, Some(port)) {
+                    println!("Failed to reopen MIDI port: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Listen to the port called `name`, or the first one, instead of any that was open.
+    /// Returns the port's name.
+    pub fn open(&self, handle: &AppHandle, name: Option<String>) -> Result<String, String> {
+        // The connection lives on its own thread until it's told to close, since MIDI
+        // objects can't be sent between threads everywhere
+        let (close, closed) = mpsc::channel::<()>();
+        let (opened, result) = mpsc::channel();
+        let handle = handle.clone();
+        std::thread::spawn(move || {
+            let connection = connect(handle, name);
+            let connection = match connection {
+                Ok((connection, port_name)) => {
+                    opened.send(Ok(port_name)).ok();
+                    connection
+                }
+                Err(e) => {
+                    opened.send(Err(e)).ok();
+                    return;
+                }
+            };
+            // Returns once the sender is dropped
+            closed.recv().ok();
+            connection.close();
+        });
+        let port_name = result.recv().map_err(|e| e.to_string())??;
+
+        let mut state = self.state.lock().unwrap();
+        state.connection = Some(Connection {
+            port: port_name.clone(),
+            _close: close,
+        });
+        state.file.port = Some(port_name.clone());
+        state.save();
+        Ok(port_name)
+    }
+
+    /// Stop listening, and don't open the port again at startup.
+    pub fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.connection = None;
+        state.file.port = None;
+        state.save();
+    }
+
+    /// The port being listened to.
+    pub fn port(&self) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .connection
+            .as_ref()
+            .map(|connection| connection.port.clone())
+    }
+
+    /// Map the next control that moves to `property`, replacing any mapping it had.
+    pub fn learn(&self, overlay: Option<String>, property: Property, range: Option<[f64; 2]>) {
+        self.state.lock().unwrap().learning = Some(Learning {
+            overlay,
+            property,
+            range,
+        });
+    }
+
+    pub fn cancel_learning(&self) {
+        self.state.lock().unwrap().learning = None;
+    }
+
+    pub fn mappings(&self) -> Vec<MidiMapping> {
+        self.state.lock().unwrap().file.mappings.clone()
+    }
+
+    pub fn set_mappings(&self, mappings: Vec<MidiMapping>) {
+        let mut state = self.state.lock().unwrap();
+        state.file.mappings = mappings;
+        state.save();
+    }
+
+    /// Forget `control`'s mapping. Returns false if it didn't have one.
+    pub fn remove_mapping(&self, control: Control) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let count = state.file.mappings.len();
+        state
+            .file
+            .mappings
+            .retain(|mapping| mapping.control != control);
+        let removed = state.file.mappings.len() != count;
+        if removed {
+            state.save();
+        }
+        removed
+    }
+}
+
+/// The names of the MIDI input ports that are plugged in.
+pub fn ports() -> Result<Vec<String>, String> {
+    let input = MidiInput::new(CLIENT_NAME).map_err(|e| e.to_string())?;
+    Ok(input
+        .ports()
+        .iter()
+        .filter_map(|port| input.port_name(port).ok())
+        .collect())
+}
+
+/// Connect to the port called `name`, or the first one, returning the connection and the
+/// port's name.
+fn connect(
+    handle: AppHandle,
+    name: Option<String>,
+) -> Result<(MidiInputConnection<()>, String), String> {
+    let input = MidiInput::new(CLIENT_NAME).map_err(|e| e.to_string())?;
+    let port = input
+        .ports()
+        .into_iter()
+        .find(|port| name.is_none() || input.port_name(port).ok() == name)
+        .ok_or_else(|| match &name {
+            Some(name) => format!("there is no MIDI port called {:?}", name),
+            None => "there are no MIDI ports".to_string(),
+        })?;
+    let port_name = input.port_name(&port).map_err(|e| e.to_string())?;
+    let connection = input
+        .connect(
+            &port,
+            "overlay-input",
+            move |_, message, _| handle_message(&handle, message),
+            (),
+        )
+        .map_err(|e| e.to_string())?;
+    Ok((connection, port_name))
+}
+
+/// Handle a message from the open port, on MIDI's own thread. Only control changes do
+/// anything.
+fn handle_message(handle: &AppHandle, message: &[u8]) {
+    let (status, controller, value) = match message {
+        [status, controller, value] if status & 0xF0 == CONTROL_CHANGE => {
+            (*status, *controller, *value)
+        }
+        _ => return,
+    };
+    let control = Control {
+        channel: status & 0x0F,
+        controller,
+    };
+    let payload = ControlPayload {
+        control,
+        value: value as f64 / 127.0,
+    };
+    if let Err(e) = handle.emit("midi://control", payload) {
+        println!("Failed to emit midi://control: {:?}", e);
+    }
+
+    let midi: tauri::State<Midi> = handle.state();
+    let mut state = midi.state.lock().unwrap();
+    if let Some(learning) = state.learning.take() {
+        let mapping = MidiMapping {
+            control,
+            overlay: learning.overlay,
+            property: learning.property,
+            range: learning.range,
+        };
+        state
+            .file
+            .mappings
+            .retain(|existing| existing.control != control);
+        state.file.mappings.push(mapping.clone());
+        state.save();
+        if let Err(e) = handle.emit("midi://learned", mapping) {
+            println!("Failed to emit midi://learned: {:?}", e);
+        }
+        return;
+    }
+    for mapping in state
+        .file
+        .mappings
+        .iter()
+        .filter(|mapping| mapping.control == control)
+    {
+        midi.live.set(
+            handle,
+            mapping.overlay.clone(),
+            mapping.property.clone(),
+            mapping.value(value),
+        );
+    }
+}
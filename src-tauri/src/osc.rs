@@ -0,0 +1,300 @@
+//! Open Sound Control input, for driving overlays from VJ and live performance tools. A
+//! mappings file says what each incoming address does:
+//!
+//! ```json
+//! "plugins": { "osc": { "port": 9000, "mappings": "osc.json" } }
+//! ```
+//!
+//! ```json
+//! [
+//!   { "address": "/fader/1", "type": "property", "property": "opacity" },
+//!   { "address": "/fader/2", "type": "property", "overlay": "side",
+//!     "property": "clearColor.r", "range": [0.2, 0.8] },
+//!   { "address": "/intro/go", "type": "timeline", "timeline": "intro", "action": "play" },
+//!   { "address": "/hud", "type": "command", "command": "set_overlay_hud", "arg": "visible" }
+//! ]
+//! ```
+
+use std::collections::HashMap;
+use std::net::UdpSocket;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::Value;
+use tauri::{AppHandle, Manager};
+
+use crate::animation::{LiveValues, Property};
+use crate::{commands, control, Timelines};
+
+/// The largest packet that's read. Anything bigger is cut off and fails to decode.
+const MAX_PACKET_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OscConfig {
+    /// The UDP port to listen on, on every interface, so controllers on other devices can
+    /// reach it.
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// A JSON file of [`Mapping`]s. Relative paths are resolved against the app's resources.
+    pub mappings: PathBuf,
+}
+
+fn default_port() -> u16 {
+    9000
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Mapping {
+    /// Matched exactly, so address patterns from senders aren't expanded.
+    pub address: String,
+    #[serde(flatten)]
+    pub target: Target,
+}
+
+/// What a message to a mapped address does. Values are taken from its first argument.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Target {
+    /// Set an animatable property of an overlay, the "main" one by default.
+    Property {
+        #[serde(default)]
+        overlay: Option<String>,
+        property: Property,
+        /// Scale values from 0 to 1, as faders send, onto this range. Without one, values
+        /// are used as they are.
+        #[serde(default)]
+        range: Option<[f64; 2]>,
+    },
+    Timeline {
+        timeline: String,
+        action: TimelineAction,
+    },
+    /// Run a command, as the control server would. The value goes in `args` under `arg`,
+    /// if it's given.
+    Command {
+        command: String,
+        #[serde(default)]
+        args: Value,
+        #[serde(default)]
+        arg: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TimelineAction {
+    Play,
+    Pause,
+    /// Pause if it's playing, or play if not.
+    Toggle,
+    /// Jump to the value, in seconds.
+    Seek,
+}
+
+/// Load the mappings and start listening, or log why not.
+pub fn start(handle: AppHandle, config: OscConfig) {
+    let mappings =
+        commands::resolve_path(&handle, config.mappings).and_then(|path| load_mappings(&path));
+    let mappings = match mappings {
+        Ok(mappings) => mappings,
+        Err(e) => {
+            println!("Failed to load OSC mappings: {}", e);
+            return;
+        }
+    };
+    let socket = match UdpSocket::bind(("0.0.0.0", config.port)) {
+        Ok(socket) => socket,
+        Err(e) => {
+            println!("Failed to listen for OSC on port {}: {}", config.port, e);
+            return;
+        }
+    };
+
+    let mut by_address: HashMap<String, Vec<Target>> = HashMap::new();
+    for mapping in mappings {
+        by_address
+            .entry(mapping.address)
+            .or_default()
+            .push(mapping.target);
+    }
+    let live = Arc::new(LiveValues::default());
+    std::thread::spawn(move || {
+        let mut buffer = vec![0; MAX_PACKET_SIZE];
+        loop {
+            let size = match socket.recv(&mut buffer) {
+                Ok(size) => size,
+                Err(e) => {
+                    println!("Failed to receive OSC: {}", e);
+                    continue;
+                }
+            };
+            let mut messages = Vec::new();
+            if let Err(e) = decode(&buffer[..size], &mut messages) {
+                println!("Failed to decode OSC packet: {}", e);
+                continue;
+            }
+            for (address, args) in messages {
+                for target in by_address.get(&address).into_iter().flatten() {
+                    if let Err(e) = apply(&handle, &live, target, args.first()) {
+                        println!("Failed to handle OSC message {}: {}", address, e);
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn load_mappings(path: &std::path::Path) -> Result<Vec<Mapping>, String> {
+    let json =
+        std::fs::read_to_string(path).map_err(|e| format!("failed to read {:?}: {}", path, e))?;
+    serde_json::from_str(&json).map_err(|e| format!("invalid OSC mappings {:?}: {}", path, e))
+}
+
+fn apply(
+    handle: &AppHandle,
+    live: &Arc<LiveValues>,
+    target: &Target,
+    value: Option<&Value>,
+) -> Result<(), String> {
+    let number = || {
+        value
+            .and_then(|value| value.as_f64().or_else(|| value.as_bool().map(f64::from)))
+            .ok_or_else(|| "it needs a number".to_string())
+    };
+    match target {
+        Target::Property {
+            overlay,
+            property,
+            range,
+        } => {
+            let value = match range {
+                Some([min, max]) => min + (max - min) * number()?,
+                None => number()?,
+            };
+            live.set(handle, overlay.clone(), property.clone(), value);
+            Ok(())
+        }
+        Target::Timeline { timeline, action } => {
+            let timelines: tauri::State<Timelines> = handle.state();
+            let mut timelines = timelines.0.lock().unwrap();
+            let timeline = timelines
+                .get_mut(timeline)
+                .ok_or_else(|| format!("timeline {:?} has not been created", timeline))?;
+            match action {
+                TimelineAction::Play => timeline.play(),
+                TimelineAction::Pause => timeline.pause(),
+                TimelineAction::Toggle if timeline.status().playing => timeline.pause(),
+                TimelineAction::Toggle => timeline.play(),
+                TimelineAction::Seek => timeline.seek(number()?),
+            }
+            Ok(())
+        }
+        Target::Command { command, args, arg } => {
+            let mut args = args.clone();
+            if let Some(arg) = arg {
+                if args.is_null() {
+                    args = Value::Object(Default::default());
+                }
+                let object = args
+                    .as_object_mut()
+                    .ok_or_else(|| "a command's args must be an object".to_string())?;
+                object.insert(arg.clone(), value.cloned().unwrap_or(Value::Null));
+            }
+            // Run on the main thread like properties are, without holding up the next message
+            control::post(handle, command.clone(), args);
+            Ok(())
+        }
+    }
+}
+
+/// Decode a message or bundle into each message's address and arguments. Arguments that
+/// don't have a JSON equivalent, like blobs, are `null`.
+fn decode(packet: &[u8], messages: &mut Vec<(String, Vec<Value>)>) -> Result<(), String> {
+    let mut reader = Reader { data: packet };
+    if packet.starts_with(b"#bundle\0") {
+        reader.take(16)?; // The tag and time tag, which are ignored
+        while !reader.data.is_empty() {
+            let size = reader.int()? as usize;
+            decode(reader.take(size)?, messages)?;
+        }
+        return Ok(());
+    }
+
+    let address = reader.string()?;
+    let tags = reader.string()?;
+    let tags = tags
+        .strip_prefix(',')
+        .ok_or_else(|| format!("{} has no type tags", address))?;
+    let mut args = Vec::with_capacity(tags.len());
+    for tag in tags.chars() {
+        let arg = match tag {
+            'i' => Value::from(reader.int()?),
+            'h' => Value::from(i64::from_be_bytes(reader.array()?)),
+            'f' => Value::from(f32::from_be_bytes(reader.array()?) as f64),
+            'd' => Value::from(f64::from_be_bytes(reader.array()?)),
+            's' | 'S' => Value::from(reader.string()?),
+            'T' => Value::Bool(true),
+            'F' => Value::Bool(false),
+            'N' | 'I' => Value::Null,
+            'b' => {
+                let size = reader.int()? as usize;
+                reader.take(padded(size))?;
+                Value::Null
+            }
+            'c' | 'r' | 'm' => Value::from(reader.int()?),
+            't' => {
+                reader.take(8)?;
+                Value::Null
+            }
+            // Arrays are flattened into the arguments around them
+            '[' | ']' => continue,
+            _ => return Err(format!("unsupported type tag {:?} in {}", tag, address)),
+        };
+        args.push(arg);
+    }
+    messages.push((address, args));
+    Ok(())
+}
+
+/// OSC aligns everything to 4 bytes.
+fn padded(size: usize) -> usize {
+    size.div_ceil(4) * 4
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, size: usize) -> Result<&'a [u8], String> {
+        if size > self.data.len() {
+            return Err("the packet is cut short".into());
+        }
+        let (taken, rest) = self.data.split_at(size);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn int(&mut self) -> Result<i32, String> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    /// A null terminated string, padded to 4 bytes.
+    fn string(&mut self) -> Result<String, String> {
+        let end = self
+            .data
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or("a string isn't terminated")?;
+        let string = String::from_utf8_lossy(&self.data[..end]).into_owned();
+        self.take(padded(end + 1))?;
+        Ok(string)
+    }
+}
@@ -0,0 +1,264 @@
+use std::ffi::c_void;
+use std::num::NonZeroIsize;
+
+use crate::overlay::{windows::window_hwnd, OverlayView};
+use raw_window_handle::{
+    HandleError, HasWindowHandle, RawWindowHandle, Win32WindowHandle, WindowHandle,
+};
+use tauri::{LogicalPosition, LogicalSize, Position, Size, WebviewWindow};
+use windows::{
+    core::{IUnknown, Interface},
+    Win32::{
+        Foundation::{BOOL, HWND, POINT},
+        Graphics::{
+            Direct2D::Common::D2D_RECT_F,
+            DirectComposition::{
+                DCompositionCreateDevice2, IDCompositionDevice, IDCompositionEffectGroup,
+                IDCompositionTarget, IDCompositionVisual,
+            },
+            Gdi::ScreenToClient,
+        },
+        UI::WindowsAndMessaging::{GetCursorPos, GetWindowLongPtrW, GWLP_HINSTANCE},
+    },
+};
+
+/// An overlay hosted as a DirectComposition visual inside the main window.
+///
+/// Unlike the owned-window backend, the visual is part of the parent's own composition
+/// tree: it moves with the window for free, always sits directly above the webview, and
+/// gets per-pixel alpha from the premultiplied swapchain wgpu creates for it.
+pub struct DCompOverlayView {
+    hwnd: HWND,
+    device: IDCompositionDevice,
+    target: IDCompositionTarget,
+    visual: IDCompositionVisual,
+    scale_factor: f64,
+    /// The visual's offset in physical pixels.
+    offset: (f64, f64),
+    /// The visual's clip in physical pixels.
+    size: (f64, f64),
+    /// Whether the visual is the target's root.
+    visible: bool,
+    /// Fades the visual, once it's been given an opacity.
+    effect: Option<IDCompositionEffectGroup>,
+    opacity: f64,
+}
+
+// The COM objects are only used while the view is locked, and DirectComposition
+// devices are free-threaded.
+unsafe impl Send for DCompOverlayView {}
+
+impl DCompOverlayView {
+    fn commit(&self) {
+        if let Err(e) = unsafe { self.device.Commit() } {
+            println!("DirectComposition commit failed: {:?}", e);
+        }
+    }
+}
+
+impl OverlayView for DCompOverlayView {
+    fn set_parent_position(&mut self, _: Position) {
+        // The visual lives inside the parent window, so it moves with it
+    }
+
+    fn set_origin(&mut self, pos: Position) {
+        let (x, y) = match pos {
+            Position::Physical(pos) => (pos.x as f64, pos.y as f64),
+            Position::Logical(pos) => (pos.x * self.scale_factor, pos.y * self.scale_factor),
+        };
+        unsafe {
+            let _ = self.visual.SetOffsetX2(x as f32);
+            let _ = self.visual.SetOffsetY2(y as f32);
+        }
+        self.offset = (x, y);
+        self.commit();
+    }
+
+    fn origin(&self) -> LogicalPosition<f64> {
+        LogicalPosition {
+            x: self.offset.0 / self.scale_factor,
+            y: self.offset.1 / self.scale_factor,
+        }
+    }
+
+    fn cursor_position(&self) -> Option<LogicalPosition<f64>> {
+        let mut cursor = POINT::default();
+        unsafe {
+            if !GetCursorPos(&mut cursor).as_bool()
+                || !ScreenToClient(self.hwnd, &mut cursor).as_bool()
+            {
+                return None;
+            }
+        }
+        Some(LogicalPosition {
+            x: cursor.x as f64 / self.scale_factor,
+            y: cursor.y as f64 / self.scale_factor,
+        })
+    }
+
+    fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+    }
+
+    fn set_size(&mut self, size: Size) {
+        // The swapchain decides how big the content is; clip so that a stale, larger
+        // frame can't spill outside of the overlay while a resize is in flight.
+        let (width, height) = match size {
+            Size::Physical(size) => (size.width as f64, size.height as f64),
+            Size::Logical(size) => (
+                size.width * self.scale_factor,
+                size.height * self.scale_factor,
+            ),
+        };
+        let clip = D2D_RECT_F {
+            left: 0.0,
+            top: 0.0,
+            right: width as f32,
+            bottom: height as f32,
+        };
+        unsafe {
+            let _ = self.visual.SetClip2(&clip);
+        }
+        self.size = (width, height);
+        self.commit();
+    }
+
+    fn set_opacity(&mut self, opacity: f64) -> Result<(), String> {
+        let opacity = opacity.clamp(0.0, 1.0);
+        let effect = match &self.effect {
+            Some(effect) => effect.clone(),
+            None => unsafe {
+                let effect = self
+                    .device
+                    .CreateEffectGroup()
+                    .map_err(|e| format!("failed to create a DirectComposition effect: {:?}", e))?;
+                self.visual
+                    .SetEffect(&effect)
+                    .map_err(|e| format!("failed to apply a DirectComposition effect: {:?}", e))?;
+                self.effect = Some(effect.clone());
+                effect
+            },
+        };
+        unsafe { effect.SetOpacity2(opacity as f32) }
+            .map_err(|e| format!("failed to set DirectComposition opacity: {:?}", e))?;
+        self.opacity = opacity;
+        self.commit();
+        Ok(())
+    }
+
+    fn opacity(&self) -> f64 {
+        self.opacity
+    }
+
+    fn set_visible(&mut self, visible: bool) {
+        // Detaching the visual from the target takes it off screen without losing it
+        let result = unsafe {
+            if visible {
+                self.target.SetRoot(&self.visual)
+            } else {
+                self.target.SetRoot(None::<IDCompositionVisual>)
+            }
+        };
+        if let Err(e) = result {
+            println!("Failed to change DirectComposition visibility: {:?}", e);
+        }
+        self.visible = visible;
+        self.commit();
+    }
+
+    fn size(&self) -> LogicalSize<f64> {
+        LogicalSize {
+            width: self.size.0 / self.scale_factor,
+            height: self.size.1 / self.scale_factor,
+        }
+    }
+
+    fn move_to_window(&mut self, window: &WebviewWindow) -> Result<(), String> {
+        let hwnd = window_hwnd(window)?;
+        // The visual, and the swapchain that's its content, carry over to a target on the
+        // new window as they are
+        let target = unsafe {
+            let target = self
+                .device
+                .CreateTargetForHwnd(hwnd, BOOL::from(true))
+                .map_err(|e| format!("failed to create DirectComposition target: {:?}", e))?;
+            let _ = self.target.SetRoot(None::<IDCompositionVisual>);
+            if self.visible {
+                let _ = target.SetRoot(&self.visual);
+            }
+            target
+        };
+        self.target = target;
+        self.hwnd = hwnd;
+        self.scale_factor = window.scale_factor().unwrap_or(self.scale_factor);
+        self.commit();
+        Ok(())
+    }
+
+    unsafe fn create_surface(
+        &self,
+        instance: &wgpu::Instance,
+    ) -> Result<wgpu::Surface<'static>, String> {
+        let visual: *mut c_void = std::mem::transmute_copy(&self.visual);
+        let surface = instance
+            .create_surface_unsafe(wgpu::SurfaceTargetUnsafe::CompositionVisual(visual))
+            .map_err(|e| e.to_string())?;
+        // wgpu sets the swapchain as the visual's content, which only shows up once committed
+        self.commit();
+        Ok(surface)
+    }
+}
+
+impl HasWindowHandle for DCompOverlayView {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        let hwnd = NonZeroIsize::new(self.hwnd.0).ok_or(HandleError::Unavailable)?;
+        let mut handle = Win32WindowHandle::new(hwnd);
+        handle.hinstance =
+            NonZeroIsize::new(unsafe { GetWindowLongPtrW(self.hwnd, GWLP_HINSTANCE) });
+        // The visual lives in the parent window, so that's the window it draws into
+        Ok(unsafe { WindowHandle::borrow_raw(RawWindowHandle::Win32(handle)) })
+    }
+}
+
+pub fn add_overlay(window: &WebviewWindow) -> DCompOverlayView {
+    let hwnd = window_hwnd(window).expect("failed to get HWND");
+    let scale_factor = window.scale_factor().unwrap_or(1.0);
+
+    unsafe {
+        let mut device: Option<IDCompositionDevice> = None;
+        DCompositionCreateDevice2(
+            None::<IUnknown>,
+            &IDCompositionDevice::IID,
+            &mut device as *mut _ as *mut *mut c_void,
+        )
+        .expect("failed to create DirectComposition device");
+        let device = device.expect("DirectComposition returned no device");
+
+        // Topmost puts the visual tree above the window's children, i.e. the webview
+        let target = device
+            .CreateTargetForHwnd(hwnd, BOOL::from(true))
+            .expect("failed to create DirectComposition target");
+        let visual = device
+            .CreateVisual()
+            .expect("failed to create DirectComposition visual");
+        target
+            .SetRoot(&visual)
+            .expect("failed to attach DirectComposition visual");
+        device
+            .Commit()
+            .expect("failed to commit DirectComposition tree");
+
+        DCompOverlayView {
+            hwnd,
+            device,
+            target,
+            visual,
+            scale_factor,
+            offset: (0.0, 0.0),
+            size: (0.0, 0.0),
+            visible: true,
+            effect: None,
+            opacity: 1.0,
+        }
+    }
+}
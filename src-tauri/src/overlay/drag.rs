@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+
+use crate::input::{InputEvent, InputKind};
+
+/// The DOM button number of the primary (usually left) button.
+const PRIMARY_BUTTON: u16 = 0;
+
+/// A rectangle of the overlay, in logical pixels from its top-left corner, that moves the
+/// overlay around the window when dragged with the primary button.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DragRegion {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl DragRegion {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+/// Lets users resize the overlay by dragging its edges and corners. Sizes are logical pixels.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResizeOptions {
+    /// How far in from each edge a press starts a resize.
+    #[serde(default = "default_border")]
+    pub border: f64,
+    pub min_size: Option<[f64; 2]>,
+    pub max_size: Option<[f64; 2]>,
+}
+
+fn default_border() -> f64 {
+    6.0
+}
+
+/// The overlay's place in the window, in logical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OverlayFrame {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Emitted as `overlay://moved` or `overlay://resized` after a drag changes the overlay's frame.
+#[derive(Debug, Clone, Serialize)]
+pub struct FramePayload {
+    pub overlay: String,
+    #[serde(flatten)]
+    pub frame: OverlayFrame,
+}
+
+/// The edges a resize moves.
+#[derive(Debug, Clone, Copy)]
+struct Edges {
+    left: bool,
+    top: bool,
+    right: bool,
+    bottom: bool,
+}
+
+impl Edges {
+    fn any(&self) -> bool {
+        self.left || self.top || self.right || self.bottom
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Grab {
+    /// `None` moves the whole overlay.
+    edges: Option<Edges>,
+    /// Where the press happened, in window coordinates.
+    pointer: [f64; 2],
+    frame: OverlayFrame,
+}
+
+/// Tracks drags that move the overlay from its drag region or resize it from its edges.
+#[derive(Debug, Default)]
+pub struct DragHandle {
+    region: Option<DragRegion>,
+    resize: Option<ResizeOptions>,
+    grab: Option<Grab>,
+}
+
+impl DragHandle {
+    pub fn set_region(&mut self, region: Option<DragRegion>) {
+        self.region = region;
+        self.grab = None;
+    }
+
+    pub fn set_resize(&mut self, resize: Option<ResizeOptions>) {
+        self.resize = resize;
+        self.grab = None;
+    }
+
+    /// Feed pointer input through the drag handle, given the overlay's current `frame`.
+    /// Returns `None` if the event isn't part of a drag, or the frame the overlay should
+    /// have if it is (which is unchanged for the press and release).
+    pub fn apply(&mut self, event: &InputEvent, frame: OverlayFrame) -> Option<OverlayFrame> {
+        // Event positions are relative to the overlay, which moves during the drag
+        let pointer = [frame.x + event.x, frame.y + event.y];
+        match (event.kind, self.grab) {
+            (InputKind::PointerDown, None) if event.button == Some(PRIMARY_BUTTON) => {
+                let edges = self.edges_at(event.x, event.y, &frame);
+                let in_region = self
+                    .region
+                    .is_some_and(|region| region.contains(event.x, event.y));
+                if edges.is_none() && !in_region {
+                    return None;
+                }
+                self.grab = Some(Grab {
+                    edges,
+                    pointer,
+                    frame,
+                });
+                Some(frame)
+            }
+            (InputKind::PointerMove, Some(grab)) => Some(self.dragged(
+                &grab,
+                pointer[0] - grab.pointer[0],
+                pointer[1] - grab.pointer[1],
+            )),
+            (InputKind::PointerUp, Some(_)) if event.button == Some(PRIMARY_BUTTON) => {
+                self.grab = None;
+                Some(frame)
+            }
+            // Other buttons used mid-drag don't reach the frontend either
+            (InputKind::PointerDown | InputKind::PointerUp, Some(_)) => Some(frame),
+            _ => None,
+        }
+    }
+
+    fn edges_at(&self, x: f64, y: f64, frame: &OverlayFrame) -> Option<Edges> {
+        let border = self.resize?.border;
+        let edges = Edges {
+            left: x < border,
+            top: y < border,
+            right: x >= frame.width - border,
+            bottom: y >= frame.height - border,
+        };
+        edges.any().then_some(edges)
+    }
+
+    fn dragged(&self, grab: &Grab, dx: f64, dy: f64) -> OverlayFrame {
+        let start = grab.frame;
+        let edges = match grab.edges {
+            Some(edges) => edges,
+            None => {
+                return OverlayFrame {
+                    x: start.x + dx,
+                    y: start.y + dy,
+                    ..start
+                }
+            }
+        };
+
+        let resize = self.resize.as_ref();
+        let min = resize.and_then(|r| r.min_size).unwrap_or([1.0, 1.0]);
+        let max = resize
+            .and_then(|r| r.max_size)
+            .unwrap_or([f64::INFINITY, f64::INFINITY]);
+        // Resize along one axis, keeping the opposite edge in place
+        let axis = |position: f64, length: f64, near: bool, far: bool, delta: f64, i: usize| {
+            let resized = if near {
+                length - delta
+            } else if far {
+                length + delta
+            } else {
+                length
+            };
+            let resized = resized.max(min[i]).min(max[i].max(min[i]));
+            if near {
+                (position + length - resized, resized)
+            } else {
+                (position, resized)
+            }
+        };
+        let (x, width) = axis(start.x, start.width, edges.left, edges.right, dx, 0);
+        let (y, height) = axis(start.y, start.height, edges.top, edges.bottom, dy, 1);
+        OverlayFrame {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
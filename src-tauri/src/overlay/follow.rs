@@ -0,0 +1,82 @@
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tauri::LogicalPosition;
+
+/// Keeps the overlay next to the mouse cursor, tracked natively rather than through the
+/// page's mouse events.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FollowOptions {
+    /// Where the overlay's top-left corner sits relative to the cursor, in logical pixels.
+    #[serde(default = "default_offset")]
+    pub offset: [f64; 2],
+    /// Roughly how long the overlay takes to catch up with the cursor, in seconds. Zero
+    /// keeps it glued to the cursor.
+    #[serde(default)]
+    pub easing: f64,
+}
+
+fn default_offset() -> [f64; 2] {
+    [16.0, 16.0]
+}
+
+/// Where a cursor-following overlay is, as it eases towards the cursor.
+#[derive(Debug)]
+pub struct CursorFollow {
+    options: FollowOptions,
+    position: Option<[f64; 2]>,
+    stepped: Option<Instant>,
+    /// Set while a step is waiting for the main thread, so slow frames don't queue up more.
+    pub pending: bool,
+}
+
+impl CursorFollow {
+    pub fn new(options: FollowOptions) -> Self {
+        CursorFollow {
+            options,
+            position: None,
+            stepped: None,
+            pending: false,
+        }
+    }
+
+    /// Move towards `cursor`, returning where the overlay's top-left should be now, or
+    /// `None` if it's already there.
+    pub fn step(&mut self, cursor: LogicalPosition<f64>) -> Option<LogicalPosition<f64>> {
+        let target = [
+            cursor.x + self.options.offset[0],
+            cursor.y + self.options.offset[1],
+        ];
+        let now = Instant::now();
+        let elapsed = self
+            .stepped
+            .map_or(0.0, |stepped| (now - stepped).as_secs_f64());
+        self.stepped = Some(now);
+
+        let position = match self.position {
+            // Exponential easing, so it's the same however often this is called
+            Some(position) if self.options.easing > 0.0 => {
+                let t = 1.0 - (-elapsed / self.options.easing).exp();
+                let mut eased = [
+                    position[0] + (target[0] - position[0]) * t,
+                    position[1] + (target[1] - position[1]) * t,
+                ];
+                // Settle rather than creeping by fractions of a pixel forever
+                if (target[0] - eased[0]).abs() < 0.1 && (target[1] - eased[1]).abs() < 0.1 {
+                    eased = target;
+                }
+                eased
+            }
+            _ => target,
+        };
+        if self.position == Some(position) {
+            return None;
+        }
+        self.position = Some(position);
+        Some(LogicalPosition {
+            x: position[0],
+            y: position[1],
+        })
+    }
+}
@@ -1,25 +1,169 @@
 use std::ffi::c_void;
+use std::ptr::NonNull;
 
-use crate::OverlayView;
+use crate::input::{FocusPolicy, InputHandler, InputMode, SharedInputState};
+use crate::overlay::{macos_backdrop, macos_input, Attachment, Backdrop, OverlayView};
 use cocoa::{
     appkit::NSView,
-    base::nil,
-    foundation::{NSPoint, NSRect, NSSize},
+    base::{id, nil, BOOL, NO, YES},
+    foundation::{NSPoint, NSRect, NSSize, NSString},
 };
 
-use objc::{msg_send, runtime::Object, sel, sel_impl};
-use raw_window_handle::{AppKitHandle, HasRawWindowHandle, RawWindowHandle};
-use tauri::{AppHandle, Manager};
+use objc::{class, msg_send, runtime::Object, sel, sel_impl};
+use raw_window_handle::{
+    AppKitWindowHandle, HandleError, HasWindowHandle, RawWindowHandle, WindowHandle,
+};
+use tauri::{LogicalPosition, LogicalSize, PhysicalSize, WebviewWindow};
+
+// NSAutoresizingMaskOptions
+const NS_VIEW_MIN_X_MARGIN: u64 = 1;
+const NS_VIEW_WIDTH_SIZABLE: u64 = 2;
+const NS_VIEW_MAX_X_MARGIN: u64 = 4;
+const NS_VIEW_MIN_Y_MARGIN: u64 = 8;
+const NS_VIEW_HEIGHT_SIZABLE: u64 = 16;
+const NS_VIEW_MAX_Y_MARGIN: u64 = 32;
+
+// NSWindowSharingType
+const NS_WINDOW_SHARING_NONE: u64 = 0;
+const NS_WINDOW_SHARING_READ_ONLY: u64 = 1;
+
+#[repr(C)]
+struct DispatchQueue {
+    _private: [u8; 0],
+}
+
+extern "C" {
+    /// What `dispatch_get_main_queue()` returns; the function itself is inline.
+    static _dispatch_main_q: DispatchQueue;
+    fn dispatch_async_f(
+        queue: *const DispatchQueue,
+        context: *mut c_void,
+        work: extern "C" fn(*mut c_void),
+    );
+}
+
+/// Queue `f` on the main dispatch queue, which the app's run loop drains.
+pub fn run_on_main_thread(f: Box<dyn FnOnce() + Send>) {
+    extern "C" fn run(context: *mut c_void) {
+        let f = unsafe { Box::from_raw(context as *mut Box<dyn FnOnce() + Send>) };
+        f();
+    }
+    let context = Box::into_raw(Box::new(f)) as *mut c_void;
+    unsafe { dispatch_async_f(&_dispatch_main_q, context, run) };
+}
+
+/// Settings for the CAMetalLayer backing the overlay view.
+pub struct MetalLayerOptions {
+    /// Present inside the CoreAnimation transaction, so a frame drawn for a new size
+    /// appears in the same screen update as the view's new frame.
+    pub presents_with_transaction: bool,
+    /// Sync presentation to the display refresh.
+    pub display_sync_enabled: bool,
+}
+
+impl Default for MetalLayerOptions {
+    fn default() -> Self {
+        MetalLayerOptions {
+            presents_with_transaction: true,
+            display_sync_enabled: true,
+        }
+    }
+}
 
 pub struct MacosOverlayView {
     ns_window: *mut Object,
     ns_view: *mut Object,
+    layer: *mut Object,
+    layer_options: MetalLayerOptions,
+    /// Top-left of the view in points, as Tauri measures it. AppKit's origin is the
+    /// bottom-left, so this is kept to re-derive the frame when the height changes.
+    origin: (f64, f64),
+    attachment: Attachment,
+    constraints: Vec<*mut Object>,
+    /// The blur view behind this one, or null.
+    backdrop: *mut Object,
+    input: SharedInputState,
+    /// Whether the view's window is kept out of screen capture on its behalf.
+    capture_excluded: bool,
 }
 
 unsafe impl Send for MacosOverlayView {}
 impl MacosOverlayView {
-    fn new(ns_window: *mut Object, ns_view: *mut Object) -> Self {
-        MacosOverlayView { ns_window, ns_view }
+    fn new(
+        ns_window: *mut Object,
+        ns_view: *mut Object,
+        layer: *mut Object,
+        input: SharedInputState,
+    ) -> Self {
+        MacosOverlayView {
+            ns_window,
+            ns_view,
+            layer,
+            layer_options: MetalLayerOptions::default(),
+            origin: (0.0, 0.0),
+            attachment: Attachment::Absolute,
+            constraints: Vec::new(),
+            backdrop: std::ptr::null_mut(),
+            input,
+            capture_excluded: false,
+        }
+    }
+
+    unsafe fn apply_sharing_type(&self) {
+        let sharing_type = match self.capture_excluded {
+            true => NS_WINDOW_SHARING_NONE,
+            false => NS_WINDOW_SHARING_READ_ONLY,
+        };
+        let _: () = msg_send![self.ns_window, setSharingType: sharing_type];
+    }
+
+    unsafe fn superview(&self) -> *mut Object {
+        msg_send![self.ns_view, superview]
+    }
+
+    /// Place the view so that its top-left corner is at `self.origin`.
+    unsafe fn apply_origin(&self) {
+        let superview = self.superview();
+        let parent: NSRect = msg_send![superview, bounds];
+        let frame: NSRect = msg_send![self.ns_view, frame];
+        let flipped: BOOL = msg_send![superview, isFlipped];
+        let y = if flipped == YES {
+            self.origin.1
+        } else {
+            parent.size.height - self.origin.1 - frame.size.height
+        };
+        let _: () = msg_send![self.ns_view, setFrameOrigin: NSPoint::new(self.origin.0, y)];
+    }
+
+    unsafe fn remove_constraints(&mut self) {
+        for constraint in self.constraints.drain(..) {
+            let _: () = msg_send![constraint, setActive: NO];
+        }
+    }
+
+    unsafe fn add_constraint(&mut self, constraint: *mut Object) {
+        let _: () = msg_send![constraint, setActive: YES];
+        self.constraints.push(constraint);
+    }
+
+    unsafe fn constrain(&mut self, anchor: *mut Object, to: *mut Object, constant: Option<f64>) {
+        if let Some(constant) = constant {
+            let constraint: *mut Object =
+                msg_send![anchor, constraintEqualToAnchor: to constant: constant];
+            self.add_constraint(constraint);
+        }
+    }
+
+    fn scale_factor(&self) -> f64 {
+        unsafe { msg_send![self.ns_window, backingScaleFactor] }
+    }
+
+    /// wgpu resets some of these whenever it configures the surface, so they're
+    /// re-applied after every configure.
+    unsafe fn apply_layer_options(&self) {
+        let options = &self.layer_options;
+        let _: () = msg_send![self.layer, setPresentsWithTransaction: if options.presents_with_transaction { YES } else { NO }];
+        let _: () = msg_send![self.layer, setDisplaySyncEnabled: if options.display_sync_enabled { YES } else { NO }];
     }
 }
 impl OverlayView for MacosOverlayView {
@@ -28,19 +172,95 @@ impl OverlayView for MacosOverlayView {
     }
 
     fn set_origin(&mut self, pos: tauri::Position) {
-        let (x, y) = match pos {
-            tauri::Position::Physical(pos) => (pos.x as f64, pos.y as f64),
+        if let Attachment::Constraints { .. } = self.attachment {
+            // Auto Layout owns the frame
+            return;
+        }
+        let scale = self.scale_factor();
+        self.origin = match pos {
+            tauri::Position::Physical(pos) => (pos.x as f64 / scale, pos.y as f64 / scale),
             tauri::Position::Logical(pos) => (pos.x, pos.y),
         };
+        unsafe { self.apply_origin() };
+    }
+
+    fn origin(&self) -> LogicalPosition<f64> {
+        // Read back from the frame, since attachments can move the view on their own
         unsafe {
-            let _: () = msg_send![self.ns_view, setFrameOrigin: NSPoint::new(x, y)];
+            let superview = self.superview();
+            let parent: NSRect = msg_send![superview, bounds];
+            let frame: NSRect = msg_send![self.ns_view, frame];
+            let flipped: BOOL = msg_send![superview, isFlipped];
+            let y = if flipped == YES {
+                frame.origin.y
+            } else {
+                parent.size.height - frame.origin.y - frame.size.height
+            };
+            LogicalPosition {
+                x: frame.origin.x,
+                y,
+            }
+        }
+    }
+
+    fn cursor_position(&self) -> Option<LogicalPosition<f64>> {
+        unsafe {
+            let screen: NSPoint = msg_send![class!(NSEvent), mouseLocation];
+            let rect: NSRect = msg_send![self.ns_window, convertRectFromScreen: NSRect::new(screen, NSSize::new(0.0, 0.0))];
+            let superview = self.superview();
+            let point: NSPoint = msg_send![superview, convertPoint: rect.origin fromView: nil];
+            let flipped: BOOL = msg_send![superview, isFlipped];
+            let y = if flipped == YES {
+                point.y
+            } else {
+                let parent: NSRect = msg_send![superview, bounds];
+                parent.size.height - point.y
+            };
+            Some(LogicalPosition { x: point.x, y })
+        }
+    }
+
+    fn set_visible(&mut self, visible: bool) {
+        let hidden = if visible { NO } else { YES };
+        unsafe {
+            let _: () = msg_send![self.ns_view, setHidden: hidden];
+            if !self.backdrop.is_null() {
+                let _: () = msg_send![self.backdrop, setHidden: hidden];
+            }
+        }
+    }
+
+    fn set_opacity(&mut self, opacity: f64) -> Result<(), String> {
+        let opacity = opacity.clamp(0.0, 1.0);
+        unsafe {
+            let _: () = msg_send![self.ns_view, setAlphaValue: opacity];
+            if !self.backdrop.is_null() {
+                let _: () = msg_send![self.backdrop, setAlphaValue: opacity];
+            }
+        }
+        Ok(())
+    }
+
+    fn opacity(&self) -> f64 {
+        unsafe { msg_send![self.ns_view, alphaValue] }
+    }
+
+    fn size(&self) -> LogicalSize<f64> {
+        let frame: NSRect = unsafe { msg_send![self.ns_view, frame] };
+        LogicalSize {
+            width: frame.size.width,
+            height: frame.size.height,
         }
     }
 
     fn set_size(&mut self, size: tauri::Size) {
+        if let Attachment::Constraints { .. } = self.attachment {
+            return;
+        }
+        let scale = self.scale_factor();
         let (width, height) = match size {
-            tauri::Size::Physical(size) => (size.width as f64, size.width as f64),
-            tauri::Size::Logical(size) => (size.width, size.width),
+            tauri::Size::Physical(size) => (size.width as f64 / scale, size.height as f64 / scale),
+            tauri::Size::Logical(size) => (size.width, size.height),
         };
 
         unsafe {
@@ -48,33 +268,293 @@ impl OverlayView for MacosOverlayView {
                 width,
                 height,
             }];
+            // Size the drawable along with the view rather than waiting for the surface to
+            // be reconfigured, so CoreAnimation never scales a stale drawable to fit.
+            let _: () = msg_send![self.layer, setContentsScale: scale];
+            let _: () =
+                msg_send![self.layer, setDrawableSize: NSSize::new(width * scale, height * scale)];
+            // Keep the top edge where it was
+            self.apply_origin();
+        }
+    }
+
+    fn set_attachment(&mut self, attachment: Attachment) -> Result<(), String> {
+        unsafe {
+            self.remove_constraints();
+            let superview = self.superview();
+
+            match &attachment {
+                Attachment::Absolute => {
+                    let _: () = msg_send![self.ns_view, setAutoresizingMask: 0u64];
+                    let _: () =
+                        msg_send![self.ns_view, setTranslatesAutoresizingMaskIntoConstraints: YES];
+                }
+                Attachment::Autoresizing {
+                    flexible_width,
+                    flexible_height,
+                    flexible_left,
+                    flexible_right,
+                    flexible_top,
+                    flexible_bottom,
+                } => {
+                    let flipped: BOOL = msg_send![superview, isFlipped];
+                    // In an unflipped superview the "max Y" margin is the top one
+                    let (top_margin, bottom_margin) = if flipped == YES {
+                        (NS_VIEW_MIN_Y_MARGIN, NS_VIEW_MAX_Y_MARGIN)
+                    } else {
+                        (NS_VIEW_MAX_Y_MARGIN, NS_VIEW_MIN_Y_MARGIN)
+                    };
+                    let mask = [
+                        (*flexible_width, NS_VIEW_WIDTH_SIZABLE),
+                        (*flexible_height, NS_VIEW_HEIGHT_SIZABLE),
+                        (*flexible_left, NS_VIEW_MIN_X_MARGIN),
+                        (*flexible_right, NS_VIEW_MAX_X_MARGIN),
+                        (*flexible_top, top_margin),
+                        (*flexible_bottom, bottom_margin),
+                    ]
+                    .iter()
+                    .filter(|(enabled, _)| *enabled)
+                    .fold(0, |mask, (_, bit)| mask | bit);
+
+                    let _: () =
+                        msg_send![self.ns_view, setTranslatesAutoresizingMaskIntoConstraints: YES];
+                    let _: () = msg_send![self.ns_view, setAutoresizingMask: mask];
+                    let _: () = msg_send![superview, setAutoresizesSubviews: YES];
+                }
+                Attachment::Constraints {
+                    left,
+                    top,
+                    right,
+                    bottom,
+                    width,
+                    height,
+                } => {
+                    let _: () =
+                        msg_send![self.ns_view, setTranslatesAutoresizingMaskIntoConstraints: NO];
+                    let view = self.ns_view;
+
+                    // Anchors are visual, so "top" means top whether or not the superview is flipped
+                    self.constrain(
+                        msg_send![view, leadingAnchor],
+                        msg_send![superview, leadingAnchor],
+                        *left,
+                    );
+                    self.constrain(
+                        msg_send![view, topAnchor],
+                        msg_send![superview, topAnchor],
+                        *top,
+                    );
+                    self.constrain(
+                        msg_send![superview, trailingAnchor],
+                        msg_send![view, trailingAnchor],
+                        *right,
+                    );
+                    self.constrain(
+                        msg_send![superview, bottomAnchor],
+                        msg_send![view, bottomAnchor],
+                        *bottom,
+                    );
+                    let width_anchor: *mut Object = msg_send![view, widthAnchor];
+                    let height_anchor: *mut Object = msg_send![view, heightAnchor];
+                    for (anchor, constant) in [(width_anchor, width), (height_anchor, height)] {
+                        if let Some(constant) = constant {
+                            let constraint: *mut Object =
+                                msg_send![anchor, constraintEqualToConstant: *constant];
+                            self.add_constraint(constraint);
+                        }
+                    }
+                    let _: () = msg_send![superview, layoutSubtreeIfNeeded];
+                }
+            }
+        }
+
+        self.attachment = attachment;
+        Ok(())
+    }
+
+    fn attachment(&self) -> Attachment {
+        self.attachment.clone()
+    }
+
+    fn attached_size(&self) -> Option<PhysicalSize<u32>> {
+        if let Attachment::Absolute = self.attachment {
+            return None;
+        }
+        unsafe {
+            // Window events can arrive before AppKit's layout pass has run
+            let _: () = msg_send![self.superview(), layoutSubtreeIfNeeded];
+            let frame: NSRect = msg_send![self.ns_view, frame];
+            let scale = self.scale_factor();
+            Some(PhysicalSize {
+                width: (frame.size.width * scale).round() as u32,
+                height: (frame.size.height * scale).round() as u32,
+            })
+        }
+    }
+
+    /// AppKit only keeps whole windows out of capture, so this hides the overlay's window
+    /// along with everything else in it, like the page.
+    fn set_capture_excluded(&mut self, excluded: bool) -> Result<(), String> {
+        self.capture_excluded = excluded;
+        unsafe { self.apply_sharing_type() };
+        Ok(())
+    }
+
+    fn move_to_window(&mut self, window: &WebviewWindow) -> Result<(), String> {
+        let ns_window = window
+            .ns_window()
+            .map_err(|e| format!("the window has no NSWindow: {}", e))?
+            as *mut Object;
+        unsafe {
+            let content_view: *mut Object = msg_send![ns_window, contentView];
+            let responder: *mut Object = msg_send![self.ns_window, firstResponder];
+            if responder == self.ns_view {
+                let _: BOOL = msg_send![self.ns_window, makeFirstResponder: nil];
+            }
+
+            // Constraints to the old content view go with it. The view keeps its frame,
+            // and the reference taken when it was made keeps it alive while it's moved.
+            self.remove_constraints();
+            let _: () = msg_send![self.ns_view, setAutoresizingMask: 0u64];
+            let _: () = msg_send![self.ns_view, setTranslatesAutoresizingMaskIntoConstraints: YES];
+            self.attachment = Attachment::Absolute;
+            let _: () = msg_send![self.ns_view, removeFromSuperview];
+            let _: () = msg_send![content_view, addSubview: self.ns_view];
+            if !self.backdrop.is_null() {
+                macos_backdrop::place_backdrop(self.backdrop, self.ns_view);
+            }
+
+            // The old window goes back to being captured, and the new one stops
+            if self.capture_excluded {
+                let _: () = msg_send![self.ns_window, setSharingType: NS_WINDOW_SHARING_READ_ONLY];
+            }
+            self.ns_window = ns_window;
+            if self.capture_excluded {
+                self.apply_sharing_type();
+            }
+            let _: () = msg_send![self.layer, setContentsScale: self.scale_factor()];
+            self.apply_origin();
+        }
+        Ok(())
+    }
+
+    fn set_backdrop(&mut self, backdrop: Option<Backdrop>) -> Result<(), String> {
+        unsafe {
+            match backdrop {
+                Some(backdrop) => {
+                    if self.backdrop.is_null() {
+                        self.backdrop = macos_backdrop::add_backdrop(self.ns_view);
+                        let hidden: BOOL = msg_send![self.ns_view, isHidden];
+                        let _: () = msg_send![self.backdrop, setHidden: hidden];
+                    }
+                    macos_backdrop::set_appearance(self.backdrop, backdrop.appearance);
+                }
+                None => {
+                    if !self.backdrop.is_null() {
+                        macos_backdrop::remove_backdrop(self.backdrop);
+                        self.backdrop = std::ptr::null_mut();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn set_input_handler(&mut self, handler: InputHandler) {
+        self.input.lock().unwrap().handler = Some(handler);
+    }
+
+    fn set_input_mode(&mut self, mode: InputMode) -> Result<(), String> {
+        self.input.lock().unwrap().mode = mode;
+        if mode == InputMode::Passthrough {
+            unsafe {
+                // Hand the keyboard back to the page
+                let responder: *mut Object = msg_send![self.ns_window, firstResponder];
+                if responder == self.ns_view {
+                    let _: BOOL = msg_send![self.ns_window, makeFirstResponder: nil];
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn input_mode(&self) -> InputMode {
+        self.input.lock().unwrap().mode
+    }
+
+    fn set_focus_policy(&mut self, policy: FocusPolicy) -> Result<(), String> {
+        // Read by the view's mouseDown:
+        self.input.lock().unwrap().focus = policy;
+        Ok(())
+    }
+
+    fn focus(&mut self) -> Result<(), String> {
+        if self.input.lock().unwrap().mode != InputMode::Interactive {
+            return Err("only interactive overlays can take keyboard focus".into());
         }
+        unsafe {
+            let _: () = msg_send![self.ns_window, makeKeyWindow];
+            let focused: BOOL = msg_send![self.ns_window, makeFirstResponder: self.ns_view];
+            if focused == NO {
+                return Err("the window refused to focus the overlay".into());
+            }
+        }
+        Ok(())
+    }
+
+    unsafe fn create_surface(
+        &self,
+        instance: &wgpu::Instance,
+    ) -> Result<wgpu::Surface<'static>, String> {
+        instance
+            .create_surface_unsafe(wgpu::SurfaceTargetUnsafe::CoreAnimationLayer(
+                self.layer as *mut c_void,
+            ))
+            .map_err(|e| e.to_string())
+    }
+
+    fn surface_configured(&mut self) {
+        unsafe { self.apply_layer_options() };
     }
 }
 
-unsafe impl HasRawWindowHandle for MacosOverlayView {
-    fn raw_window_handle(&self) -> raw_window_handle::RawWindowHandle {
-        let mut handle = AppKitHandle::empty();
-        handle.ns_window = self.ns_window as *mut c_void;
-        handle.ns_view = self.ns_view as *mut c_void;
-        raw_window_handle::RawWindowHandle::AppKit(handle)
+impl HasWindowHandle for MacosOverlayView {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        let ns_view = NonNull::new(self.ns_view as *mut c_void).ok_or(HandleError::Unavailable)?;
+        let handle = AppKitWindowHandle::new(ns_view);
+        // The view stays in a window for as long as the overlay exists
+        Ok(unsafe { WindowHandle::borrow_raw(RawWindowHandle::AppKit(handle)) })
     }
 }
 
-pub fn add_overlay(handle: &AppHandle) -> impl OverlayView {
-    let window = handle
-        .get_window("main")
-        .expect("failed to get main window");
-    if let RawWindowHandle::AppKit(handle) = window.raw_window_handle() {
+pub fn add_overlay(window: &WebviewWindow) -> impl OverlayView {
+    if let Ok(ns_window) = window.ns_window() {
         unsafe {
-            let ns_window = handle.ns_window as *mut Object;
+            let ns_window = ns_window as *mut Object;
             let content_view: *mut Object = msg_send![ns_window, contentView];
 
-            // Make a new view
-            let new_view = NSView::alloc(nil).initWithFrame_(NSRect::new(
+            // Make a new view, of a class that can switch between passing input through
+            // to the webview and handling it itself
+            let new_view: id = msg_send![macos_input::overlay_view_class(), alloc];
+            let new_view = new_view.initWithFrame_(NSRect::new(
                 NSPoint::new(100.0, 0.0),
                 NSSize::new(200.0, 200.0),
             ));
+            let input = SharedInputState::default();
+            macos_input::attach_input_state(new_view, input.clone());
+
+            // Host our own CAMetalLayer rather than taking whatever AppKit provides, so
+            // its size, scale and presentation can be managed explicitly. Setting the
+            // layer before wantsLayer makes this a layer-hosting view.
+            let layer: *mut Object = msg_send![class!(CAMetalLayer), new];
+            let scale: f64 = msg_send![ns_window, backingScaleFactor];
+            let _: () = msg_send![layer, setContentsScale: scale];
+            let _: () = msg_send![layer, setOpaque: NO];
+            // Pin stale content to the top-left during a resize instead of stretching it
+            let gravity = NSString::alloc(nil).init_str("topLeft");
+            let _: () = msg_send![layer, setContentsGravity: gravity];
+            let _: () = msg_send![layer, setNeedsDisplayOnBoundsChange: YES];
+            let _: () = msg_send![new_view, setLayer: layer];
             new_view.setWantsLayer(true);
 
             // Add it to the contentView, as a sibling of webview, so that it appears on top
@@ -84,7 +564,9 @@ pub fn add_overlay(handle: &AppHandle) -> impl OverlayView {
             let subviews: *mut Object = msg_send![content_view, subviews];
             let count: usize = msg_send![subviews, count];
             println!("contentView now has {} views", count);
-            MacosOverlayView::new(ns_window, new_view)
+            let view = MacosOverlayView::new(ns_window, new_view, layer, input);
+            view.apply_layer_options();
+            view
         }
     } else {
         unreachable!("only runs on windows")
@@ -0,0 +1,112 @@
+use std::sync::Once;
+
+use cocoa::{
+    base::{id, nil, NO, YES},
+    foundation::{NSPoint, NSRect, NSString},
+};
+use objc::{
+    class,
+    declare::ClassDecl,
+    msg_send,
+    runtime::{Class, Object, Sel},
+    sel, sel_impl,
+};
+
+use super::BackdropAppearance;
+
+// NSVisualEffectMaterialPopover, which follows the view's appearance
+const MATERIAL_POPOVER: i64 = 6;
+// NSVisualEffectBlendingModeWithinWindow: blur the window's content, i.e. the page
+const BLENDING_MODE_WITHIN_WINDOW: i64 = 1;
+// NSVisualEffectStateActive, so the blur doesn't go flat when the window is inactive
+const STATE_ACTIVE: i64 = 1;
+// NSWindowBelow
+const ORDER_BELOW: i64 = -1;
+
+fn backdrop_view_class() -> &'static Class {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| unsafe {
+        let mut decl = ClassDecl::new("WgpuOverlayBackdropView", class!(NSVisualEffectView))
+            .expect("failed to declare WgpuOverlayBackdropView");
+        decl.add_method(
+            sel!(hitTest:),
+            hit_test as extern "C" fn(&Object, Sel, NSPoint) -> id,
+        );
+        decl.register();
+    });
+    class!(WgpuOverlayBackdropView)
+}
+
+extern "C" fn hit_test(_: &Object, _: Sel, _: NSPoint) -> id {
+    // The backdrop is only decoration, so input goes to whatever is under it
+    nil
+}
+
+/// Put a blur view right behind `view`, pinned to its edges so that it follows the view
+/// however the view is laid out.
+pub unsafe fn add_backdrop(view: *mut Object) -> *mut Object {
+    let frame: NSRect = msg_send![view, frame];
+    let backdrop: id = msg_send![backdrop_view_class(), alloc];
+    let backdrop: id = msg_send![backdrop, initWithFrame: frame];
+    let _: () = msg_send![backdrop, setTranslatesAutoresizingMaskIntoConstraints: NO];
+    let _: () = msg_send![backdrop, setMaterial: MATERIAL_POPOVER];
+    let _: () = msg_send![backdrop, setBlendingMode: BLENDING_MODE_WITHIN_WINDOW];
+    let _: () = msg_send![backdrop, setState: STATE_ACTIVE];
+    place_backdrop(backdrop, view);
+    backdrop
+}
+
+/// Put `backdrop` right behind `view` in the view's current superview, e.g. after the
+/// view has moved to another window.
+pub unsafe fn place_backdrop(backdrop: *mut Object, view: *mut Object) {
+    let superview: *mut Object = msg_send![view, superview];
+    // Constraints to the old neighbours go along with the old superview
+    let _: () = msg_send![backdrop, removeFromSuperview];
+    let _: () = msg_send![superview, addSubview: backdrop positioned: ORDER_BELOW relativeTo: view];
+    // Fade along with the view
+    let opacity: f64 = msg_send![view, alphaValue];
+    let _: () = msg_send![backdrop, setAlphaValue: opacity];
+
+    let pins: [(*mut Object, *mut Object); 4] = [
+        (
+            msg_send![backdrop, leadingAnchor],
+            msg_send![view, leadingAnchor],
+        ),
+        (
+            msg_send![backdrop, trailingAnchor],
+            msg_send![view, trailingAnchor],
+        ),
+        (msg_send![backdrop, topAnchor], msg_send![view, topAnchor]),
+        (
+            msg_send![backdrop, bottomAnchor],
+            msg_send![view, bottomAnchor],
+        ),
+    ];
+    for (anchor, to) in pins {
+        let constraint: *mut Object = msg_send![anchor, constraintEqualToAnchor: to];
+        let _: () = msg_send![constraint, setActive: YES];
+    }
+}
+
+pub unsafe fn set_appearance(backdrop: *mut Object, appearance: BackdropAppearance) {
+    let name = match appearance {
+        BackdropAppearance::Auto => None,
+        BackdropAppearance::Light => Some("NSAppearanceNameAqua"),
+        BackdropAppearance::Dark => Some("NSAppearanceNameDarkAqua"),
+    };
+    let appearance: id = match name {
+        Some(name) => {
+            let name = NSString::alloc(nil).init_str(name);
+            msg_send![class!(NSAppearance), appearanceNamed: name]
+        }
+        // Inherit the window's
+        None => nil,
+    };
+    let _: () = msg_send![backdrop, setAppearance: appearance];
+}
+
+/// Take the blur view out of the window, which also removes its constraints.
+pub unsafe fn remove_backdrop(backdrop: *mut Object) {
+    let _: () = msg_send![backdrop, removeFromSuperview];
+    let _: () = msg_send![backdrop, release];
+}
@@ -0,0 +1,399 @@
+use std::{
+    ffi::{c_void, CStr},
+    os::raw::c_char,
+    sync::{Arc, Mutex, Once},
+};
+
+use cocoa::{
+    base::{id, nil, BOOL, NO, YES},
+    foundation::{NSPoint, NSRect, NSSize},
+};
+use objc::{
+    class,
+    declare::ClassDecl,
+    msg_send,
+    runtime::{Class, Object, Sel},
+    sel, sel_impl,
+};
+
+use crate::input::{
+    FocusPolicy, GesturePhase, InputEvent, InputHandler, InputKind, InputState, Modifiers,
+    SharedInputState,
+};
+
+const INPUT_STATE_IVAR: &str = "overlayInputState";
+
+// NSEventModifierFlags
+const NS_EVENT_MODIFIER_FLAG_SHIFT: u64 = 1 << 17;
+const NS_EVENT_MODIFIER_FLAG_CONTROL: u64 = 1 << 18;
+const NS_EVENT_MODIFIER_FLAG_OPTION: u64 = 1 << 19;
+const NS_EVENT_MODIFIER_FLAG_COMMAND: u64 = 1 << 20;
+
+// NSEventSubtype
+const NS_EVENT_SUBTYPE_TABLET_POINT: i16 = 1;
+
+// NSEventPhase
+const NS_EVENT_PHASE_NONE: u64 = 0;
+const NS_EVENT_PHASE_BEGAN: u64 = 0x1;
+const NS_EVENT_PHASE_ENDED: u64 = 0x8;
+const NS_EVENT_PHASE_CANCELLED: u64 = 0x10;
+
+// NSTrackingAreaOptions
+const NS_TRACKING_MOUSE_MOVED: u64 = 0x02;
+const NS_TRACKING_ACTIVE_ALWAYS: u64 = 0x80;
+const NS_TRACKING_IN_VISIBLE_RECT: u64 = 0x200;
+
+/// Roughly how far browsers scroll per wheel "line", for mice without precise deltas.
+const LINE_HEIGHT: f64 = 16.0;
+
+/// An NSView subclass that hides from hit testing while the overlay is passthrough, and
+/// reports mouse and keyboard events to the overlay's input handler while it's interactive.
+pub fn overlay_view_class() -> &'static Class {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| unsafe {
+        let mut decl = ClassDecl::new("WgpuOverlayView", class!(NSView))
+            .expect("failed to declare WgpuOverlayView");
+        decl.add_ivar::<*mut c_void>(INPUT_STATE_IVAR);
+
+        decl.add_method(
+            sel!(hitTest:),
+            hit_test as extern "C" fn(&Object, Sel, NSPoint) -> id,
+        );
+        decl.add_method(
+            sel!(acceptsFirstMouse:),
+            accepts_first_mouse as extern "C" fn(&Object, Sel, id) -> BOOL,
+        );
+        decl.add_method(
+            sel!(acceptsFirstResponder),
+            accepts_first_responder as extern "C" fn(&Object, Sel) -> BOOL,
+        );
+        for sel in [
+            sel!(mouseDown:),
+            sel!(rightMouseDown:),
+            sel!(otherMouseDown:),
+        ] {
+            decl.add_method(sel, mouse_down as extern "C" fn(&Object, Sel, id));
+        }
+        for sel in [sel!(mouseUp:), sel!(rightMouseUp:), sel!(otherMouseUp:)] {
+            decl.add_method(sel, mouse_up as extern "C" fn(&Object, Sel, id));
+        }
+        for sel in [
+            sel!(mouseMoved:),
+            sel!(mouseDragged:),
+            sel!(rightMouseDragged:),
+            sel!(otherMouseDragged:),
+        ] {
+            decl.add_method(sel, mouse_moved as extern "C" fn(&Object, Sel, id));
+        }
+        decl.add_method(
+            sel!(scrollWheel:),
+            scroll_wheel as extern "C" fn(&Object, Sel, id),
+        );
+        decl.add_method(
+            sel!(magnifyWithEvent:),
+            magnify as extern "C" fn(&Object, Sel, id),
+        );
+        decl.add_method(
+            sel!(rotateWithEvent:),
+            rotate as extern "C" fn(&Object, Sel, id),
+        );
+        decl.add_method(sel!(keyDown:), key_down as extern "C" fn(&Object, Sel, id));
+        decl.add_method(sel!(keyUp:), key_up as extern "C" fn(&Object, Sel, id));
+        decl.add_method(sel!(dealloc), dealloc as extern "C" fn(&Object, Sel));
+
+        decl.register();
+    });
+    class!(WgpuOverlayView)
+}
+
+/// Give a `WgpuOverlayView` the input state it reports through, and start tracking
+/// mouse movement over it. The view keeps its own reference until it's deallocated.
+pub unsafe fn attach_input_state(view: id, state: SharedInputState) {
+    let state = Arc::into_raw(state) as *mut c_void;
+    (*view).set_ivar::<*mut c_void>(INPUT_STATE_IVAR, state);
+
+    // mouseMoved: is only sent to views with a tracking area asking for it. The rect is
+    // ignored for NSTrackingInVisibleRect, which follows the view as it resizes.
+    let options = NS_TRACKING_MOUSE_MOVED | NS_TRACKING_ACTIVE_ALWAYS | NS_TRACKING_IN_VISIBLE_RECT;
+    let area: id = msg_send![class!(NSTrackingArea), alloc];
+    let area: id = msg_send![area,
+        initWithRect: NSRect::new(NSPoint::new(0.0, 0.0), NSSize::new(0.0, 0.0))
+        options: options
+        owner: view
+        userInfo: nil];
+    let _: () = msg_send![view, addTrackingArea: area];
+    let _: () = msg_send![area, release];
+}
+
+unsafe fn input_state(this: &Object) -> Option<&Mutex<InputState>> {
+    let state = *this.get_ivar::<*mut c_void>(INPUT_STATE_IVAR);
+    (state as *const Mutex<InputState>).as_ref()
+}
+
+fn active_handler(this: &Object) -> Option<InputHandler> {
+    // Cloned out so the lock isn't held while the handler runs
+    unsafe { input_state(this) }.and_then(|state| state.lock().unwrap().active_handler())
+}
+
+extern "C" fn hit_test(this: &Object, _: Sel, point: NSPoint) -> id {
+    if active_handler(this).is_none() {
+        // Let the click fall through to the webview underneath
+        return nil;
+    }
+    unsafe { msg_send![super(this, class!(NSView)), hitTest: point] }
+}
+
+extern "C" fn accepts_first_mouse(_: &Object, _: Sel, _: id) -> BOOL {
+    // Clicks on an inactive window should reach the overlay straight away, like they
+    // would reach the page
+    YES
+}
+
+extern "C" fn accepts_first_responder(this: &Object, _: Sel) -> BOOL {
+    if active_handler(this).is_some() {
+        YES
+    } else {
+        NO
+    }
+}
+
+fn focuses_on_click(this: &Object) -> bool {
+    unsafe { input_state(this) }.map_or(false, |state| {
+        let state = state.lock().unwrap();
+        state.active_handler().is_some() && state.focus == FocusPolicy::OnClick
+    })
+}
+
+extern "C" fn mouse_down(this: &Object, _: Sel, event: id) {
+    unsafe {
+        if focuses_on_click(this) {
+            // Take keyboard focus from the webview, as clicking into a page element would
+            let window: id = msg_send![this, window];
+            let _: BOOL = msg_send![window, makeFirstResponder: this];
+        }
+        send_pointer(this, event, InputKind::PointerDown);
+    }
+}
+
+extern "C" fn mouse_up(this: &Object, _: Sel, event: id) {
+    unsafe { send_pointer(this, event, InputKind::PointerUp) }
+}
+
+extern "C" fn mouse_moved(this: &Object, _: Sel, event: id) {
+    unsafe { send_pointer(this, event, InputKind::PointerMove) }
+}
+
+extern "C" fn scroll_wheel(this: &Object, _: Sel, event: id) {
+    let handler = match active_handler(this) {
+        Some(handler) => handler,
+        None => return,
+    };
+    unsafe {
+        let (x, y) = local_position(this, event);
+        let delta_x: f64 = msg_send![event, scrollingDeltaX];
+        let delta_y: f64 = msg_send![event, scrollingDeltaY];
+        let precise: BOOL = msg_send![event, hasPreciseScrollingDeltas];
+        let scale = if precise == YES { 1.0 } else { LINE_HEIGHT };
+        // AppKit reports how far the content moves, the DOM how far the viewport does
+        handler(InputEvent::wheel(
+            x,
+            y,
+            -delta_x * scale,
+            -delta_y * scale,
+            modifiers(event),
+        ));
+
+        // Trackpad scrolls (and their momentum) have phases, wheel mice don't. Those are
+        // also reported as a pan that moves the content along with the fingers.
+        let phase: u64 = msg_send![event, phase];
+        let momentum_phase: u64 = msg_send![event, momentumPhase];
+        if precise == YES && (phase != NS_EVENT_PHASE_NONE || momentum_phase != NS_EVENT_PHASE_NONE)
+        {
+            let phase = if phase != NS_EVENT_PHASE_NONE {
+                gesture_phase(phase)
+            } else {
+                gesture_phase(momentum_phase)
+            };
+            handler(InputEvent {
+                delta_x,
+                delta_y,
+                ..InputEvent::gesture(InputKind::Pan, x, y, phase, modifiers(event))
+            });
+        }
+    }
+}
+
+extern "C" fn magnify(this: &Object, _: Sel, event: id) {
+    let handler = match active_handler(this) {
+        Some(handler) => handler,
+        None => return,
+    };
+    unsafe {
+        let (x, y) = local_position(this, event);
+        let phase: u64 = msg_send![event, phase];
+        let magnification: f64 = msg_send![event, magnification];
+        handler(InputEvent {
+            scale: Some(1.0 + magnification),
+            ..InputEvent::gesture(
+                InputKind::Pinch,
+                x,
+                y,
+                gesture_phase(phase),
+                modifiers(event),
+            )
+        });
+    }
+}
+
+extern "C" fn rotate(this: &Object, _: Sel, event: id) {
+    let handler = match active_handler(this) {
+        Some(handler) => handler,
+        None => return,
+    };
+    unsafe {
+        let (x, y) = local_position(this, event);
+        let phase: u64 = msg_send![event, phase];
+        // Degrees counterclockwise, as AppKit's y axis points up
+        let rotation: f32 = msg_send![event, rotation];
+        handler(InputEvent {
+            rotation: Some(-(rotation as f64).to_radians()),
+            ..InputEvent::gesture(
+                InputKind::Rotate,
+                x,
+                y,
+                gesture_phase(phase),
+                modifiers(event),
+            )
+        });
+    }
+}
+
+extern "C" fn key_down(this: &Object, _: Sel, event: id) {
+    unsafe {
+        if !send_key(this, event, InputKind::KeyDown) {
+            let _: () = msg_send![super(this, class!(NSView)), keyDown: event];
+        }
+    }
+}
+
+extern "C" fn key_up(this: &Object, _: Sel, event: id) {
+    unsafe {
+        if !send_key(this, event, InputKind::KeyUp) {
+            let _: () = msg_send![super(this, class!(NSView)), keyUp: event];
+        }
+    }
+}
+
+extern "C" fn dealloc(this: &Object, _: Sel) {
+    unsafe {
+        let state = *this.get_ivar::<*mut c_void>(INPUT_STATE_IVAR);
+        if !state.is_null() {
+            drop(Arc::from_raw(state as *const Mutex<InputState>));
+        }
+        let _: () = msg_send![super(this, class!(NSView)), dealloc];
+    }
+}
+
+unsafe fn send_pointer(this: &Object, event: id, kind: InputKind) {
+    let handler = match active_handler(this) {
+        Some(handler) => handler,
+        None => return,
+    };
+    let (x, y) = local_position(this, event);
+    let button = match kind {
+        InputKind::PointerMove => None,
+        _ => {
+            let number: i64 = msg_send![event, buttonNumber];
+            Some(dom_button(number))
+        }
+    };
+    let event_data = InputEvent::pointer(kind, x, y, button, modifiers(event));
+
+    // Pens send ordinary mouse events, with tablet data attached
+    let subtype: i16 = msg_send![event, subtype];
+    if subtype == NS_EVENT_SUBTYPE_TABLET_POINT {
+        let pressure: f32 = msg_send![event, pressure];
+        // -1 to 1 in each direction, y pointing down
+        let tilt: NSPoint = msg_send![event, tilt];
+        handler(event_data.with_pen(pressure as f64, tilt.x * 90.0, tilt.y * 90.0));
+    } else {
+        handler(event_data);
+    }
+}
+
+/// Returns false if the overlay isn't interactive and the event should go up the responder chain.
+unsafe fn send_key(this: &Object, event: id, kind: InputKind) -> bool {
+    let handler = match active_handler(this) {
+        Some(handler) => handler,
+        None => return false,
+    };
+    let characters: id = msg_send![event, characters];
+    let key_code: u16 = msg_send![event, keyCode];
+    let repeat: BOOL = msg_send![event, isARepeat];
+    handler(InputEvent::key(
+        kind,
+        string_from_nsstring(characters),
+        key_code as u32,
+        repeat == YES,
+        modifiers(event),
+    ));
+    true
+}
+
+/// The event's location in points from the view's top-left corner.
+unsafe fn local_position(this: &Object, event: id) -> (f64, f64) {
+    let location: NSPoint = msg_send![event, locationInWindow];
+    let local: NSPoint = msg_send![this, convertPoint: location fromView: nil];
+    let flipped: BOOL = msg_send![this, isFlipped];
+    if flipped == YES {
+        (local.x, local.y)
+    } else {
+        let bounds: NSRect = msg_send![this, bounds];
+        (local.x, bounds.size.height - local.y)
+    }
+}
+
+fn gesture_phase(phase: u64) -> GesturePhase {
+    if phase & NS_EVENT_PHASE_BEGAN != 0 {
+        GesturePhase::Begin
+    } else if phase & (NS_EVENT_PHASE_ENDED | NS_EVENT_PHASE_CANCELLED) != 0 {
+        GesturePhase::End
+    } else {
+        GesturePhase::Update
+    }
+}
+
+/// AppKit numbers buttons left, right, middle; the DOM numbers them left, middle, right.
+fn dom_button(number: i64) -> u16 {
+    match number {
+        1 => 2,
+        2 => 1,
+        n => n.max(0) as u16,
+    }
+}
+
+unsafe fn modifiers(event: id) -> Modifiers {
+    let flags: u64 = msg_send![event, modifierFlags];
+    Modifiers {
+        shift: flags & NS_EVENT_MODIFIER_FLAG_SHIFT != 0,
+        ctrl: flags & NS_EVENT_MODIFIER_FLAG_CONTROL != 0,
+        alt: flags & NS_EVENT_MODIFIER_FLAG_OPTION != 0,
+        meta: flags & NS_EVENT_MODIFIER_FLAG_COMMAND != 0,
+    }
+}
+
+unsafe fn string_from_nsstring(string: id) -> Option<String> {
+    if string == nil {
+        return None;
+    }
+    let utf8: *const c_char = msg_send![string, UTF8String];
+    if utf8.is_null() {
+        return None;
+    }
+    let string = CStr::from_ptr(utf8).to_string_lossy().into_owned();
+    if string.is_empty() {
+        None
+    } else {
+        Some(string)
+    }
+}
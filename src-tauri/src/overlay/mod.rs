@@ -1,24 +1,309 @@
-use raw_window_handle::HasRawWindowHandle;
-use tauri::{AppHandle, Position, Size};
+use std::sync::{Arc, Mutex};
+
+use raw_window_handle::HasWindowHandle;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, LogicalPosition, LogicalSize, PhysicalSize, Position, Size, WebviewWindow};
+
+use crate::input::{FocusPolicy, InputHandler, InputMode};
+use crate::renderer::{Frame, Presentation};
+
+mod drag;
+pub use drag::{DragHandle, DragRegion, FramePayload, OverlayFrame, ResizeOptions};
+
+mod snap;
+pub use snap::{SnapOptions, Snapping};
+
+mod follow;
+pub use follow::{CursorFollow, FollowOptions};
 
 #[cfg(target_os = "macos")]
 pub mod macos;
 
+#[cfg(target_os = "macos")]
+mod macos_input;
+
+#[cfg(target_os = "macos")]
+mod macos_backdrop;
+
 #[cfg(target_os = "windows")]
 pub mod windows;
 
-pub trait OverlayView: HasRawWindowHandle {
+#[cfg(target_os = "windows")]
+pub mod dcomp;
+
+#[cfg(target_os = "windows")]
+mod windows_input;
+
+pub trait OverlayView: HasWindowHandle {
     fn set_parent_position(&mut self, pos: Position);
     fn set_origin(&mut self, pos: Position);
     fn set_size(&mut self, size: Size);
+
+    /// Tell the view its window's new scale factor, for backends that keep it instead of
+    /// asking the window.
+    fn set_scale_factor(&mut self, _scale_factor: f64) {}
+
+    /// Show or hide the view, keeping its surface and settings.
+    fn set_visible(&mut self, visible: bool);
+
+    /// Fade the whole view, from transparent at 0 to opaque at 1.
+    fn set_opacity(&mut self, opacity: f64) -> Result<(), String> {
+        match opacity >= 1.0 {
+            true => Ok(()),
+            false => Err("opacity is not supported by this overlay backend".into()),
+        }
+    }
+
+    fn opacity(&self) -> f64 {
+        1.0
+    }
+
+    /// Keep the view above every other app's windows, or only above its own window.
+    fn set_always_on_top(&mut self, always_on_top: bool) -> Result<(), String> {
+        match always_on_top {
+            false => Ok(()),
+            true => Err("always-on-top is not supported by this overlay backend".into()),
+        }
+    }
+
+    /// Keep the view out of screenshots, recordings and screen sharing, while it still
+    /// shows on screen.
+    fn set_capture_excluded(&mut self, excluded: bool) -> Result<(), String> {
+        match excluded {
+            false => Ok(()),
+            true => Err("capture exclusion is not supported by this overlay backend".into()),
+        }
+    }
+
+    /// Where the view's top-left corner currently is in the window's content.
+    fn origin(&self) -> LogicalPosition<f64>;
+
+    /// The view's current size.
+    fn size(&self) -> LogicalSize<f64>;
+
+    /// Where the mouse cursor is, in the coordinates `set_origin` takes, or `None` if the
+    /// backend can't tell.
+    fn cursor_position(&self) -> Option<LogicalPosition<f64>> {
+        None
+    }
+
+    /// The view's current place in the window's content.
+    fn frame(&self) -> OverlayFrame {
+        let origin = self.origin();
+        let size = self.size();
+        OverlayFrame {
+            x: origin.x,
+            y: origin.y,
+            width: size.width,
+            height: size.height,
+        }
+    }
+
+    /// Let the platform lay the view out, so it resizes natively with the window instead
+    /// of waiting for `set_origin`/`set_size` calls from a window event handler.
+    fn set_attachment(&mut self, attachment: Attachment) -> Result<(), String> {
+        match attachment {
+            Attachment::Absolute => Ok(()),
+            _ => Err("native attachment is not supported on this platform".into()),
+        }
+    }
+
+    /// How the view is laid out at the moment.
+    fn attachment(&self) -> Attachment {
+        Attachment::Absolute
+    }
+
+    /// The view's natively laid out size in physical pixels, or `None` for absolutely
+    /// positioned views whose size is whatever was last passed to `set_size`.
+    fn attached_size(&self) -> Option<PhysicalSize<u32>> {
+        None
+    }
+
+    /// Move the view into `window`'s content, keeping its surface. It comes out absolutely
+    /// positioned, so the caller places it afterwards.
+    fn move_to_window(&mut self, _window: &WebviewWindow) -> Result<(), String> {
+        Err("moving overlays between windows is not supported by this overlay backend".into())
+    }
+
+    /// Blur whatever is behind the view, or stop with `None`.
+    fn set_backdrop(&mut self, backdrop: Option<Backdrop>) -> Result<(), String> {
+        match backdrop {
+            None => Ok(()),
+            Some(_) => Err("backdrop blur is not supported by this overlay backend".into()),
+        }
+    }
+
+    /// Set where input goes while the view is in [`InputMode::Interactive`].
+    fn set_input_handler(&mut self, _handler: InputHandler) {}
+
+    /// Switch between letting input through to the page and handling it natively.
+    fn set_input_mode(&mut self, mode: InputMode) -> Result<(), String> {
+        match mode {
+            InputMode::Passthrough => Ok(()),
+            InputMode::Interactive => {
+                Err("interactive input is not supported by this overlay backend".into())
+            }
+        }
+    }
+
+    /// Whether the view passes input through or handles it at the moment.
+    fn input_mode(&self) -> InputMode {
+        InputMode::Passthrough
+    }
+
+    /// Choose whether clicks give the view keyboard focus while it's interactive.
+    fn set_focus_policy(&mut self, policy: FocusPolicy) -> Result<(), String> {
+        match policy {
+            FocusPolicy::OnClick => Ok(()),
+            FocusPolicy::Manual => {
+                Err("keyboard focus is not supported by this overlay backend".into())
+            }
+        }
+    }
+
+    /// Give the view keyboard focus. Only interactive views can have it.
+    fn focus(&mut self) -> Result<(), String> {
+        Err("keyboard focus is not supported by this overlay backend".into())
+    }
+
+    /// Create the wgpu surface that presents into this view. The surface borrows the view's
+    /// native window or layer without holding on to it, so it has to be dropped before the
+    /// view is.
+    unsafe fn create_surface(
+        &self,
+        instance: &wgpu::Instance,
+    ) -> Result<wgpu::Surface<'static>, String>;
+
+    /// Called after the renderer (re)configures this view's surface, so native settings
+    /// that wgpu overwrites can be re-applied.
+    fn surface_configured(&mut self) {}
+
+    /// How the renderer should get frames onto this view.
+    fn presentation(&self) -> Presentation {
+        Presentation::Surface
+    }
+
+    /// Put a read back frame on screen. Only called for [`Presentation::Readback`] views.
+    fn present_frame(&mut self, _frame: &Frame) {}
+}
+
+/// How the overlay keeps its place in the window's content as the window resizes.
+/// Distances are logical pixels measured from the content view's edges.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Attachment {
+    /// Positioned with absolute frames, only moving when told to.
+    Absolute,
+    /// Fixed margins to the edges that aren't flexible, stretching along flexible
+    /// dimensions (an `NSAutoresizingMask`). The current frame provides the margins.
+    #[serde(rename_all = "camelCase")]
+    Autoresizing {
+        #[serde(default)]
+        flexible_width: bool,
+        #[serde(default)]
+        flexible_height: bool,
+        #[serde(default)]
+        flexible_left: bool,
+        #[serde(default)]
+        flexible_right: bool,
+        #[serde(default)]
+        flexible_top: bool,
+        #[serde(default)]
+        flexible_bottom: bool,
+    },
+    /// Auto Layout constraints to the content view. Unset edges are left unconstrained,
+    /// so e.g. `left` + `top` + `width` + `height` pins a fixed-size view to a corner.
+    Constraints {
+        left: Option<f64>,
+        top: Option<f64>,
+        right: Option<f64>,
+        bottom: Option<f64>,
+        width: Option<f64>,
+        height: Option<f64>,
+    },
+}
+
+/// A frosted glass effect behind the overlay. It shows through wherever the overlay isn't
+/// opaque, so a translucent clear color tints it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Backdrop {
+    #[serde(default)]
+    pub appearance: BackdropAppearance,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BackdropAppearance {
+    /// Follow the system's light or dark mode.
+    #[default]
+    Auto,
+    Light,
+    Dark,
+}
+
+/// How the overlay is hosted on Windows. Ignored on macOS, where it's always a subview.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WindowsBackend {
+    /// A borderless window owned by the overlay's window, moved along with it.
+    OwnedWindow,
+    /// A DirectComposition visual composited into the window above the webview.
+    DirectComposition,
+}
+
+pub struct OverlayOptions {
+    /// Let the page show through wherever the overlay doesn't draw opaque pixels.
+    pub transparent: bool,
+    pub windows_backend: WindowsBackend,
+}
+
+impl Default for OverlayOptions {
+    fn default() -> Self {
+        OverlayOptions {
+            transparent: true,
+            windows_backend: WindowsBackend::OwnedWindow,
+        }
+    }
+}
+
+/// Run `f` on the main thread without waiting for it, for work that touches views from
+/// elsewhere. Render threads must never block on the main thread, since commands running
+/// there block on the render thread in turn.
+pub fn run_on_main_thread(handle: &AppHandle, f: impl FnOnce() + Send + 'static) {
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "macos")] {
+            let _ = handle;
+            macos::run_on_main_thread(Box::new(f));
+        } else {
+            if let Err(e) = handle.run_on_main_thread(f) {
+                println!("Failed to run on the main thread: {:?}", e);
+            }
+        }
+    }
 }
 
-pub unsafe fn add_overlay(handle: &AppHandle) -> impl OverlayView {
+/// Add an overlay view to `window`'s content.
+pub unsafe fn add_overlay(
+    handle: &AppHandle,
+    window: &WebviewWindow,
+    options: OverlayOptions,
+) -> Arc<Mutex<dyn OverlayView + Send>> {
     cfg_if::cfg_if! {
         if #[cfg(target_os = "macos")] {
-            macos::add_overlay(handle)
+            // CoreAnimation composites the view's layer with alpha already
+            let _ = (handle, options);
+            Arc::new(Mutex::new(macos::add_overlay(window)))
         } else if #[cfg(target_os = "windows")] {
-            windows::add_overlay(handle)
+            match options.windows_backend {
+                WindowsBackend::OwnedWindow => {
+                    Arc::new(Mutex::new(windows::add_overlay(handle, window, options)))
+                }
+                // Composition swapchains are always premultiplied, so no readback is needed
+                WindowsBackend::DirectComposition => {
+                    Arc::new(Mutex::new(dcomp::add_overlay(window)))
+                }
+            }
         }
     }
 }
@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+use tauri::LogicalSize;
+
+use super::OverlayFrame;
+
+/// Pulls a moved overlay into line with the window's edges and other overlays when it
+/// comes within `threshold` logical pixels of them.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapOptions {
+    #[serde(default = "default_threshold")]
+    pub threshold: f64,
+    /// Snap to the edges (and so the corners) of the window's content.
+    #[serde(default = "default_true")]
+    pub window_edges: bool,
+    /// Snap to the edges of other overlays in the same window.
+    #[serde(default = "default_true")]
+    pub overlays: bool,
+}
+
+fn default_threshold() -> f64 {
+    8.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Snapping settings along with what there is to snap to.
+#[derive(Debug)]
+pub struct Snapping {
+    options: Option<SnapOptions>,
+    /// The window's content size.
+    bounds: LogicalSize<f64>,
+}
+
+impl Default for Snapping {
+    fn default() -> Self {
+        Snapping {
+            options: None,
+            bounds: LogicalSize {
+                width: 0.0,
+                height: 0.0,
+            },
+        }
+    }
+}
+
+impl Snapping {
+    pub fn set_options(&mut self, options: Option<SnapOptions>) {
+        self.options = options;
+    }
+
+    pub fn set_bounds(&mut self, bounds: LogicalSize<f64>) {
+        self.bounds = bounds;
+    }
+
+    /// Move `frame` onto the nearest edges within the threshold, keeping its size.
+    pub fn snap(&self, frame: OverlayFrame, others: &[OverlayFrame]) -> OverlayFrame {
+        let options = match self.options {
+            Some(options) => options,
+            None => return frame,
+        };
+
+        let mut xs = Vec::new();
+        let mut ys = Vec::new();
+        if options.window_edges {
+            xs.extend([0.0, self.bounds.width]);
+            ys.extend([0.0, self.bounds.height]);
+        }
+        if options.overlays {
+            for other in others {
+                xs.extend([other.x, other.x + other.width]);
+                ys.extend([other.y, other.y + other.height]);
+            }
+        }
+
+        OverlayFrame {
+            x: frame.x + nearest_shift(frame.x, frame.width, &xs, options.threshold),
+            y: frame.y + nearest_shift(frame.y, frame.height, &ys, options.threshold),
+            ..frame
+        }
+    }
+}
+
+/// The smallest shift that puts either end of `start..start + length` on a target, or zero
+/// if none are within `threshold`.
+fn nearest_shift(start: f64, length: f64, targets: &[f64], threshold: f64) -> f64 {
+    let mut best: Option<f64> = None;
+    for &target in targets {
+        for edge in [start, start + length] {
+            let shift = target - edge;
+            if shift.abs() <= threshold && best.is_none_or(|best| shift.abs() < best.abs()) {
+                best = Some(shift);
+            }
+        }
+    }
+    best.unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(x: f64, y: f64, width: f64, height: f64) -> OverlayFrame {
+        OverlayFrame {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn snapping(window_edges: bool, overlays: bool) -> Snapping {
+        let mut snapping = Snapping::default();
+        snapping.set_bounds(LogicalSize {
+            width: 800.0,
+            height: 600.0,
+        });
+        snapping.set_options(Some(SnapOptions {
+            threshold: 8.0,
+            window_edges,
+            overlays,
+        }));
+        snapping
+    }
+
+    #[test]
+    fn leaves_frames_alone_without_options() {
+        let moved = frame(3.0, 5.0, 100.0, 100.0);
+        assert_eq!(Snapping::default().snap(moved, &[]), moved);
+    }
+
+    #[test]
+    fn snaps_to_the_window_edges() {
+        let snapping = snapping(true, false);
+        assert_eq!(
+            snapping.snap(frame(5.0, 496.0, 100.0, 100.0), &[]),
+            frame(0.0, 500.0, 100.0, 100.0)
+        );
+        assert_eq!(
+            snapping.snap(frame(20.0, 20.0, 100.0, 100.0), &[]),
+            frame(20.0, 20.0, 100.0, 100.0)
+        );
+    }
+
+    #[test]
+    fn snaps_to_the_nearest_edge_of_other_overlays() {
+        let snapping = snapping(false, true);
+        let other = frame(200.0, 200.0, 100.0, 100.0);
+        // Its right edge is 4 from the other's left, and its left edge 6 from the other's
+        // right
+        assert_eq!(
+            snapping.snap(frame(106.0, 250.0, 90.0, 50.0), &[other]),
+            frame(110.0, 250.0, 90.0, 50.0)
+        );
+        assert_eq!(
+            snapping.snap(frame(5.0, 5.0, 90.0, 50.0), &[other]),
+            frame(5.0, 5.0, 90.0, 50.0)
+        );
+    }
+
+    #[test]
+    fn picks_the_smallest_shift() {
+        assert_eq!(nearest_shift(10.0, 50.0, &[4.0, 63.0], 8.0), 3.0);
+        assert_eq!(nearest_shift(10.0, 50.0, &[0.0, 100.0], 8.0), 0.0);
+    }
+}
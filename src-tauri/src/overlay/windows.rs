@@ -1,29 +1,74 @@
-use std::sync::Weak;
+use std::{
+    ffi::c_void,
+    num::NonZeroIsize,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use crate::overlay::OverlayView;
-use raw_window_handle::{HasRawWindowHandle, Win32Handle};
-use tao::platform::windows::{WindowBuilderExtWindows, WindowExtWindows};
-use tauri::{AppHandle, Manager, PhysicalPosition, Position, Size};
+use crate::input::{FocusPolicy, InputHandler, InputMode, SharedInputState};
+use crate::overlay::{windows_input, Backdrop, BackdropAppearance, OverlayOptions, OverlayView};
+use crate::renderer::{Frame, Presentation};
+use raw_window_handle::{
+    HandleError, HasWindowHandle, RawWindowHandle, Win32WindowHandle, WindowHandle,
+};
+use tauri::{
+    AppHandle, LogicalPosition, LogicalSize, PhysicalPosition, Position, Size, WebviewWindow,
+    Window,
+};
 use windows::Win32::{
-    Foundation::HWND,
+    Foundation::{BOOL, HANDLE, HWND, POINT, SIZE},
+    Graphics::Gdi::{
+        CreateCompatibleDC, CreateDIBSection, CreatedHDC, DeleteDC, DeleteObject, SelectObject,
+        AC_SRC_ALPHA, AC_SRC_OVER, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, BLENDFUNCTION,
+        DIB_RGB_COLORS, HBITMAP, HDC, HGDIOBJ,
+    },
+    UI::Input::KeyboardAndMouse::SetFocus,
     UI::WindowsAndMessaging::{
-        GetWindowLongW, SetWindowLongW, GWL_EXSTYLE, WS_EX_LAYERED, WS_EX_NOACTIVATE,
-        WS_EX_TRANSPARENT,
+        GetCursorPos, GetWindowLongPtrW, GetWindowLongW, SetForegroundWindow,
+        SetLayeredWindowAttributes, SetWindowDisplayAffinity, SetWindowLongPtrW, SetWindowLongW,
+        UpdateLayeredWindow, GWLP_HINSTANCE, GWLP_HWNDPARENT, GWL_EXSTYLE, LWA_ALPHA, ULW_ALPHA,
+        WDA_EXCLUDEFROMCAPTURE, WDA_NONE, WS_EX_LAYERED, WS_EX_NOACTIVATE, WS_EX_TRANSPARENT,
     },
 };
 
+/// Overlay windows need labels of their own, which nothing ever looks up.
+static NEXT_LABEL: AtomicUsize = AtomicUsize::new(0);
+
 pub struct WindowsOverlayView {
-    overlay: Weak<tao::window::Window>,
+    overlay: Window,
+    /// The overlay window's handle and scale factor, kept here because Tauri's getters wait
+    /// on the main thread, which the render thread must never do.
+    hwnd: HWND,
+    scale_factor: f64,
     parent_pos: Position,
     last_origin: Position,
+    transparent: bool,
+    /// Applied as the layered window's constant alpha.
+    opacity: f64,
+    bitmap: Option<LayeredBitmap>,
+    input: SharedInputState,
 }
 
+// The GDI handles in `bitmap` are only ever touched while the view is locked
+unsafe impl Send for WindowsOverlayView {}
+
 impl WindowsOverlayView {
-    pub fn new(overlay: Weak<tao::window::Window>) -> Self {
+    pub fn new(
+        overlay: Window,
+        hwnd: HWND,
+        scale_factor: f64,
+        transparent: bool,
+        input: SharedInputState,
+    ) -> Self {
         WindowsOverlayView {
             overlay,
+            hwnd,
+            scale_factor,
             parent_pos: Position::Physical(PhysicalPosition { x: 0, y: 0 }),
             last_origin: Position::Physical(PhysicalPosition { x: 0, y: 0 }),
+            transparent,
+            opacity: 1.0,
+            bitmap: None,
+            input,
         }
     }
 }
@@ -35,90 +80,435 @@ impl OverlayView for WindowsOverlayView {
     }
 
     fn set_origin(&mut self, pos: Position) {
-        if let Some(overlay) = self.overlay.upgrade() {
-            self.last_origin = pos;
-
-            // Translate the origin by the parent window position
-            let translated = match (&self.last_origin, &self.parent_pos) {
-                (Position::Physical(origin), Position::Physical(parent)) => {
-                    tao::dpi::PhysicalPosition {
-                        x: origin.x + parent.x,
-                        y: origin.y + parent.y,
-                    }
+        self.last_origin = pos;
+
+        // Translate the origin by the parent window position
+        let scale = self.scale_factor;
+        let origin = match &self.last_origin {
+            Position::Physical(origin) => (origin.x, origin.y),
+            Position::Logical(origin) => ((origin.x * scale) as i32, (origin.y * scale) as i32),
+        };
+        let translated = match &self.parent_pos {
+            Position::Physical(parent) => PhysicalPosition {
+                x: origin.0 + parent.x,
+                y: origin.1 + parent.y,
+            },
+            Position::Logical(parent) => PhysicalPosition {
+                x: origin.0 + (parent.x * scale) as i32,
+                y: origin.1 + (parent.y * scale) as i32,
+            },
+        };
+        let _ = self.overlay.set_position(Position::Physical(translated));
+    }
+
+    fn origin(&self) -> LogicalPosition<f64> {
+        match &self.last_origin {
+            Position::Physical(origin) => {
+                let scale = self.scale_factor;
+                LogicalPosition {
+                    x: origin.x as f64 / scale,
+                    y: origin.y as f64 / scale,
                 }
-                _ => unimplemented!("set_origin does not support Logical positions yet"),
-            };
-            overlay.set_outer_position(translated);
+            }
+            Position::Logical(origin) => *origin,
+        }
+    }
+
+    fn cursor_position(&self) -> Option<LogicalPosition<f64>> {
+        let mut cursor = POINT::default();
+        if !unsafe { GetCursorPos(&mut cursor) }.as_bool() {
+            return None;
+        }
+        // Origins are relative to the parent's position, as in `set_origin`
+        let scale = self.scale_factor;
+        let parent = match &self.parent_pos {
+            Position::Physical(parent) => (parent.x as f64, parent.y as f64),
+            Position::Logical(parent) => (parent.x * scale, parent.y * scale),
+        };
+        Some(LogicalPosition {
+            x: (cursor.x as f64 - parent.0) / scale,
+            y: (cursor.y as f64 - parent.1) / scale,
+        })
+    }
+
+    fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+    }
+
+    fn set_visible(&mut self, visible: bool) {
+        let _ = match visible {
+            true => self.overlay.show(),
+            false => self.overlay.hide(),
+        };
+    }
+
+    fn set_opacity(&mut self, opacity: f64) -> Result<(), String> {
+        let hwnd = self.hwnd;
+        self.opacity = opacity.clamp(0.0, 1.0);
+        // Read back frames carry it to UpdateLayeredWindow instead, and a window can only
+        // use one or the other
+        if !self.transparent {
+            let alpha = (self.opacity * 255.0).round() as u8;
+            unsafe { SetLayeredWindowAttributes(hwnd, 0, alpha, LWA_ALPHA) };
+        }
+        Ok(())
+    }
+
+    fn opacity(&self) -> f64 {
+        self.opacity
+    }
+
+    fn set_always_on_top(&mut self, always_on_top: bool) -> Result<(), String> {
+        // Either way it stays above its owner, which is what owned windows do
+        self.overlay
+            .set_always_on_top(always_on_top)
+            .map_err(|e| format!("failed to set the overlay window always-on-top: {}", e))
+    }
+
+    fn set_capture_excluded(&mut self, excluded: bool) -> Result<(), String> {
+        let hwnd = self.hwnd;
+        let affinity = if excluded {
+            WDA_EXCLUDEFROMCAPTURE
+        } else {
+            WDA_NONE
+        };
+        // Excluding needs Windows 10 2004 or later, and fails on anything older
+        match unsafe { SetWindowDisplayAffinity(hwnd, affinity) }.as_bool() {
+            true => Ok(()),
+            false => Err("failed to set the overlay window's display affinity".into()),
+        }
+    }
+
+    fn set_backdrop(&mut self, backdrop: Option<Backdrop>) -> Result<(), String> {
+        let hwnd = self.hwnd;
+        unsafe { set_accent(hwnd, backdrop) }
+    }
+
+    fn size(&self) -> LogicalSize<f64> {
+        match self.overlay.inner_size() {
+            Ok(size) => LogicalSize {
+                width: size.width as f64 / self.scale_factor,
+                height: size.height as f64 / self.scale_factor,
+            },
+            Err(_) => LogicalSize {
+                width: 0.0,
+                height: 0.0,
+            },
         }
     }
 
     fn set_size(&mut self, size: Size) {
-        if let Some(overlay) = self.overlay.upgrade() {
-            match size {
-                Size::Physical(size) => overlay.set_inner_size(tao::dpi::PhysicalSize {
-                    width: size.width,
-                    height: size.height,
-                }),
-                Size::Logical(size) => overlay.set_inner_size(tao::dpi::LogicalSize {
-                    width: size.width,
-                    height: size.height,
-                }),
-            }
+        let _ = self.overlay.set_size(size);
+    }
+
+    fn move_to_window(&mut self, window: &WebviewWindow) -> Result<(), String> {
+        // Owned windows stay above their owner and hide along with it, so taking the new
+        // window as owner is all it takes. The caller passes on the new parent position.
+        set_owner(self.hwnd, window_hwnd(window)?);
+        Ok(())
+    }
+
+    fn set_input_handler(&mut self, handler: InputHandler) {
+        self.input.lock().unwrap().handler = Some(handler);
+    }
+
+    fn set_input_mode(&mut self, mode: InputMode) -> Result<(), String> {
+        let hwnd = self.hwnd;
+        let mut input = self.input.lock().unwrap();
+        input.mode = mode;
+        set_window_input_style(hwnd, mode, input.focus);
+        Ok(())
+    }
+
+    fn input_mode(&self) -> InputMode {
+        self.input.lock().unwrap().mode
+    }
+
+    fn set_focus_policy(&mut self, policy: FocusPolicy) -> Result<(), String> {
+        let hwnd = self.hwnd;
+        let mut input = self.input.lock().unwrap();
+        input.focus = policy;
+        set_window_input_style(hwnd, input.mode, policy);
+        Ok(())
+    }
+
+    fn focus(&mut self) -> Result<(), String> {
+        let hwnd = self.hwnd;
+        if self.input.lock().unwrap().mode != InputMode::Interactive {
+            return Err("only interactive overlays can take keyboard focus".into());
         }
+        // WS_EX_NOACTIVATE only stops clicks from activating the window, not this
+        unsafe {
+            SetForegroundWindow(hwnd);
+            SetFocus(hwnd);
+        }
+        Ok(())
     }
-}
 
-unsafe impl HasRawWindowHandle for WindowsOverlayView {
-    fn raw_window_handle(&self) -> raw_window_handle::RawWindowHandle {
-        let window = self.overlay.upgrade().expect("window was deallocated?");
-        let mut handle = Win32Handle::empty();
-        handle.hwnd = window.hwnd();
-        handle.hinstance = window.hinstance();
+    unsafe fn create_surface(
+        &self,
+        instance: &wgpu::Instance,
+    ) -> Result<wgpu::Surface<'static>, String> {
+        let target = wgpu::SurfaceTargetUnsafe::from_window(self).map_err(|e| e.to_string())?;
+        instance
+            .create_surface_unsafe(target)
+            .map_err(|e| e.to_string())
+    }
+
+    fn presentation(&self) -> Presentation {
+        // A swapchain can't be combined with per-pixel alpha on an owned window, so
+        // transparent overlays are read back and pushed through UpdateLayeredWindow.
+        if self.transparent {
+            Presentation::Readback
+        } else {
+            Presentation::Surface
+        }
+    }
+
+    fn present_frame(&mut self, frame: &Frame) {
+        let hwnd = self.hwnd;
+
+        let needs_bitmap = match &self.bitmap {
+            Some(bitmap) => bitmap.width != frame.width || bitmap.height != frame.height,
+            None => true,
+        };
+        if needs_bitmap {
+            self.bitmap = unsafe { LayeredBitmap::new(frame.width, frame.height) };
+        }
+        let bitmap = match &self.bitmap {
+            Some(bitmap) => bitmap,
+            None => return,
+        };
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                frame.pixels.as_ptr(),
+                bitmap.bits,
+                (frame.width * frame.height * 4) as usize,
+            );
 
-        raw_window_handle::RawWindowHandle::Win32(handle)
+            let size = SIZE {
+                cx: frame.width as i32,
+                cy: frame.height as i32,
+            };
+            let source = POINT { x: 0, y: 0 };
+            let blend = BLENDFUNCTION {
+                BlendOp: AC_SRC_OVER as u8,
+                BlendFlags: 0,
+                SourceConstantAlpha: (self.opacity * 255.0).round() as u8,
+                AlphaFormat: AC_SRC_ALPHA as u8,
+            };
+            UpdateLayeredWindow(
+                hwnd,
+                None::<HDC>,
+                std::ptr::null(),
+                &size,
+                bitmap.dc,
+                &source,
+                0,
+                &blend,
+                ULW_ALPHA,
+            );
+        }
     }
 }
 
-pub fn add_overlay(app_handle: &AppHandle) -> impl OverlayView {
-    let window = app_handle
-        .get_window("main")
-        .expect("failed to get main window");
-
-    let hwnd = HWND(window.hwnd().expect("failed to get HWND") as _);
-    let overlay = app_handle
-        .create_tao_window(move || {
-            let window_builder = tao::window::WindowBuilder::new()
-                .with_always_on_top(false)
-                .with_decorations(false)
-                .with_resizable(false)
-                .with_visible(true)
-                .with_position(tao::dpi::LogicalPosition::<u32>::new(30, 30))
-                .with_owner_window(hwnd)
-                .with_inner_size(tao::dpi::LogicalSize::<u32>::new(200, 200));
-
-            ("WGPU Target".to_string(), window_builder)
+/// A memory DC with a top-down 32-bit DIB selected into it, reused between frames.
+struct LayeredBitmap {
+    dc: CreatedHDC,
+    bitmap: HBITMAP,
+    previous: HGDIOBJ,
+    bits: *mut u8,
+    width: u32,
+    height: u32,
+}
+
+impl LayeredBitmap {
+    unsafe fn new(width: u32, height: u32) -> Option<Self> {
+        let dc = CreateCompatibleDC(None::<HDC>);
+        let info = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+                biWidth: width as i32,
+                // Negative height makes the rows top-down, matching the read back frame
+                biHeight: -(height as i32),
+                biPlanes: 1,
+                biBitCount: 32,
+                biCompression: BI_RGB as u32,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut bits: *mut c_void = std::ptr::null_mut();
+        let bitmap = CreateDIBSection(dc, &info, DIB_RGB_COLORS, &mut bits, None::<HANDLE>, 0);
+        if bitmap.is_invalid() || bits.is_null() {
+            println!(
+                "Failed to create a {}x{} DIB for the overlay",
+                width, height
+            );
+            DeleteDC(dc);
+            return None;
+        }
+        let previous = SelectObject(dc, bitmap);
+
+        Some(LayeredBitmap {
+            dc,
+            bitmap,
+            previous,
+            bits: bits as *mut u8,
+            width,
+            height,
         })
+    }
+}
+
+impl Drop for LayeredBitmap {
+    fn drop(&mut self) {
+        unsafe {
+            SelectObject(self.dc, self.previous);
+            DeleteObject(self.bitmap);
+            DeleteDC(self.dc);
+        }
+    }
+}
+
+impl HasWindowHandle for WindowsOverlayView {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        let hwnd = NonZeroIsize::new(self.hwnd.0).ok_or(HandleError::Unavailable)?;
+        let mut handle = Win32WindowHandle::new(hwnd);
+        handle.hinstance =
+            NonZeroIsize::new(unsafe { GetWindowLongPtrW(self.hwnd, GWLP_HINSTANCE) });
+        Ok(unsafe { WindowHandle::borrow_raw(RawWindowHandle::Win32(handle)) })
+    }
+}
+
+pub fn add_overlay(
+    app_handle: &AppHandle,
+    window: &WebviewWindow,
+    options: OverlayOptions,
+) -> impl OverlayView {
+    let owner = window_hwnd(window).expect("failed to get HWND");
+    let label = format!("overlay-{}", NEXT_LABEL.fetch_add(1, Ordering::Relaxed));
+    let overlay = tauri::window::WindowBuilder::new(app_handle, label)
+        .title("WGPU Target")
+        .always_on_top(false)
+        .decorations(false)
+        .resizable(false)
+        .skip_taskbar(true)
+        .visible(true)
+        .position(30.0, 30.0)
+        .inner_size(200.0, 200.0)
+        .build()
         .expect("failed to create overlay window");
-    make_window_passthrough_events(
-        overlay
-            .upgrade()
-            .expect("failed to get Arc<Window>")
-            .as_ref(),
-    );
-
-    WindowsOverlayView::new(overlay)
+    let overlay_hwnd = window_hwnd(&overlay).expect("failed to get the overlay window's HWND");
+    set_owner(overlay_hwnd, owner);
+    set_window_input_style(overlay_hwnd, InputMode::Passthrough, FocusPolicy::default());
+
+    let input = SharedInputState::default();
+    unsafe { windows_input::subclass_window(overlay_hwnd, input.clone()) };
+
+    let scale_factor = overlay.scale_factor().unwrap_or(1.0);
+    WindowsOverlayView::new(
+        overlay,
+        overlay_hwnd,
+        scale_factor,
+        options.transparent,
+        input,
+    )
 }
 
-/// Make it so that mouse events pass through the window and it's excluded from tab order
-fn make_window_passthrough_events(window: &tao::window::Window) {
-    let hwnd = HWND(window.hwnd() as _);
+/// The Win32 handle of a Tauri window, which comes through raw-window-handle so it doesn't
+/// depend on the `windows` version Tauri uses.
+pub fn window_hwnd(window: &impl HasWindowHandle) -> Result<HWND, String> {
+    let handle = window
+        .window_handle()
+        .map_err(|e| format!("failed to get the window's HWND: {}", e))?;
+    match handle.as_raw() {
+        RawWindowHandle::Win32(handle) => Ok(HWND(handle.hwnd.get())),
+        _ => Err("the window isn't a Win32 window".into()),
+    }
+}
+
+/// Make `owner` own `hwnd`, keeping it above `owner` and hiding it along with it.
+fn set_owner(hwnd: HWND, owner: HWND) {
+    unsafe { SetWindowLongPtrW(hwnd, GWLP_HWNDPARENT, owner.0) };
+}
+
+/// Make it so that mouse events pass through the window and it's excluded from tab order,
+/// or, for interactive overlays, that the window takes clicks itself, and keyboard focus
+/// too if `focus` lets clicks give it that.
+fn set_window_input_style(hwnd: HWND, mode: InputMode, focus: FocusPolicy) {
     unsafe {
         // Based on https://stackoverflow.com/a/50245502
-        let cur_style = GetWindowLongW(hwnd, GWL_EXSTYLE) as u32;
-        SetWindowLongW(
-            hwnd,
-            GWL_EXSTYLE,
-            (cur_style | WS_EX_TRANSPARENT | WS_EX_LAYERED | WS_EX_NOACTIVATE) as i32,
-        );
+        let cur_style = GetWindowLongW(hwnd, GWL_EXSTYLE) as u32 | WS_EX_LAYERED;
+        let style = match (mode, focus) {
+            (InputMode::Passthrough, _) => cur_style | WS_EX_TRANSPARENT | WS_EX_NOACTIVATE,
+            // Clicks reach the window without activating it, so the page keeps the keyboard
+            (InputMode::Interactive, FocusPolicy::Manual) => {
+                (cur_style & !WS_EX_TRANSPARENT) | WS_EX_NOACTIVATE
+            }
+            // Keyboard messages only reach a window that can be activated
+            (InputMode::Interactive, FocusPolicy::OnClick) => {
+                cur_style & !(WS_EX_TRANSPARENT | WS_EX_NOACTIVATE)
+            }
+        };
+        SetWindowLongW(hwnd, GWL_EXSTYLE, style as i32);
+    }
+}
+
+// SetWindowCompositionAttribute is undocumented, but it's how DWM blur is applied to
+// windows without a frame. These follow the layout Windows 10 uses.
+const WCA_ACCENT_POLICY: u32 = 19;
+const ACCENT_DISABLED: u32 = 0;
+const ACCENT_ENABLE_BLURBEHIND: u32 = 3;
+/// Acrylic, which needs Windows 10 1803 or later.
+const ACCENT_ENABLE_ACRYLICBLURBEHIND: u32 = 4;
+
+#[repr(C)]
+struct AccentPolicy {
+    accent_state: u32,
+    accent_flags: u32,
+    /// ABGR, tinting acrylic.
+    gradient_color: u32,
+    animation_id: u32,
+}
+
+#[repr(C)]
+struct WindowCompositionAttribData {
+    attribute: u32,
+    data: *mut c_void,
+    size: usize,
+}
+
+#[link(name = "user32")]
+extern "system" {
+    fn SetWindowCompositionAttribute(hwnd: HWND, data: *mut WindowCompositionAttribData) -> BOOL;
+}
+
+/// Have DWM blur what's behind the window. Light and dark backdrops use acrylic for its
+/// tint; otherwise it's a plain blur.
+unsafe fn set_accent(hwnd: HWND, backdrop: Option<Backdrop>) -> Result<(), String> {
+    let (accent_state, gradient_color) = match backdrop.map(|backdrop| backdrop.appearance) {
+        None => (ACCENT_DISABLED, 0),
+        Some(BackdropAppearance::Auto) => (ACCENT_ENABLE_BLURBEHIND, 0),
+        Some(BackdropAppearance::Light) => (ACCENT_ENABLE_ACRYLICBLURBEHIND, 0x40ff_ffff),
+        Some(BackdropAppearance::Dark) => (ACCENT_ENABLE_ACRYLICBLURBEHIND, 0x4000_0000),
+    };
+    let mut policy = AccentPolicy {
+        accent_state,
+        accent_flags: 0,
+        gradient_color,
+        animation_id: 0,
+    };
+    let mut data = WindowCompositionAttribData {
+        attribute: WCA_ACCENT_POLICY,
+        data: &mut policy as *mut AccentPolicy as *mut c_void,
+        size: std::mem::size_of::<AccentPolicy>(),
+    };
+    if SetWindowCompositionAttribute(hwnd, &mut data).as_bool() {
+        Ok(())
+    } else {
+        Err("this version of Windows can't blur behind the overlay".into())
     }
 }
@@ -0,0 +1,383 @@
+use std::cell::Cell;
+
+use windows::Win32::{
+    Foundation::{HWND, LPARAM, LRESULT, POINT, PWSTR, WPARAM},
+    Graphics::Gdi::ScreenToClient,
+    UI::{
+        HiDpi::GetDpiForWindow,
+        Input::{
+            KeyboardAndMouse::{
+                GetKeyState, GetKeyboardState, ReleaseCapture, SetCapture, ToUnicode, VK_CONTROL,
+                VK_LWIN, VK_MENU, VK_RWIN, VK_SHIFT,
+            },
+            Pointer::{GetPointerPenInfo, GetPointerType, POINTER_PEN_INFO},
+            Touch::{
+                CloseGestureInfoHandle, GetGestureInfo, SetGestureConfig, GESTURECONFIG,
+                GESTUREINFO, GID_PAN, GID_ROTATE, GID_ZOOM, HGESTUREINFO,
+            },
+        },
+        Shell::{DefSubclassProc, RemoveWindowSubclass, SetWindowSubclass},
+        WindowsAndMessaging::{
+            GF_BEGIN, GF_END, MK_LBUTTON, MK_MBUTTON, MK_RBUTTON, PEN_FLAG_BARREL, PEN_FLAG_ERASER,
+            PEN_FLAG_INVERTED, PEN_MASK_PRESSURE, PEN_MASK_TILT_X, PEN_MASK_TILT_Y,
+            POINTER_INPUT_TYPE, PT_PEN, WHEEL_DELTA, WM_GESTURE, WM_KEYDOWN, WM_KEYUP,
+            WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MOUSEHWHEEL,
+            WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_NCDESTROY, WM_POINTERDOWN, WM_POINTERUP,
+            WM_POINTERUPDATE, WM_RBUTTONDOWN, WM_RBUTTONUP, WM_SYSKEYDOWN, WM_SYSKEYUP,
+        },
+    },
+};
+
+use crate::input::{
+    GesturePhase, InputEvent, InputHandler, InputKind, Modifiers, SharedInputState,
+};
+
+const SUBCLASS_ID: usize = 1;
+
+/// How far browsers on Windows scroll for one notch of the wheel.
+const PIXELS_PER_NOTCH: f64 = 100.0;
+
+const GC_ALLGESTURES: u32 = 1;
+
+/// Don't let `ToUnicode` consume a pending dead key (Windows 10 1607 and later).
+const TO_UNICODE_NO_STATE_CHANGE: u32 = 1 << 2;
+
+/// What the subclass procedure keeps for its window.
+struct WindowInput {
+    state: SharedInputState,
+    /// Gesture messages report running totals, so the previous message of the gesture in
+    /// progress is kept to turn them into changes.
+    gesture: Cell<Option<GestureProgress>>,
+}
+
+#[derive(Clone, Copy)]
+struct GestureProgress {
+    id: u32,
+    argument: u64,
+    location: POINT,
+}
+
+/// Report the window's mouse, keyboard and gesture messages through `state` while it's
+/// interactive. The window procedure keeps its own reference until the window is destroyed.
+pub unsafe fn subclass_window(hwnd: HWND, state: SharedInputState) {
+    // Rotation is off unless asked for
+    let config = GESTURECONFIG {
+        dwID: 0,
+        dwWant: GC_ALLGESTURES,
+        dwBlock: 0,
+    };
+    SetGestureConfig(
+        hwnd,
+        0,
+        1,
+        &config,
+        std::mem::size_of::<GESTURECONFIG>() as u32,
+    );
+
+    let data = Box::into_raw(Box::new(WindowInput {
+        state,
+        gesture: Cell::new(None),
+    })) as usize;
+    if !SetWindowSubclass(hwnd, Some(subclass_proc), SUBCLASS_ID, data).as_bool() {
+        println!("Failed to subclass the overlay window, input won't be forwarded");
+        drop(Box::from_raw(data as *mut WindowInput));
+    }
+}
+
+unsafe extern "system" fn subclass_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+    _: usize,
+    data: usize,
+) -> LRESULT {
+    if msg == WM_NCDESTROY {
+        RemoveWindowSubclass(hwnd, Some(subclass_proc), SUBCLASS_ID);
+        drop(Box::from_raw(data as *mut WindowInput));
+        return DefSubclassProc(hwnd, msg, wparam, lparam);
+    }
+
+    let input = &*(data as *const WindowInput);
+    // Cloned out so the lock isn't held while the handler runs
+    let handler = match input.state.lock().unwrap().active_handler() {
+        Some(handler) => handler,
+        None => return DefSubclassProc(hwnd, msg, wparam, lparam),
+    };
+
+    let event = match msg {
+        WM_LBUTTONDOWN | WM_MBUTTONDOWN | WM_RBUTTONDOWN => {
+            // Keep receiving moves and the button up if the pointer leaves the overlay
+            SetCapture(hwnd);
+            Some(pointer_event(hwnd, InputKind::PointerDown, msg, lparam))
+        }
+        WM_LBUTTONUP | WM_MBUTTONUP | WM_RBUTTONUP => {
+            if wparam.0 as u32 & (MK_LBUTTON | MK_MBUTTON | MK_RBUTTON) == 0 {
+                ReleaseCapture();
+            }
+            Some(pointer_event(hwnd, InputKind::PointerUp, msg, lparam))
+        }
+        WM_MOUSEMOVE => Some(pointer_event(hwnd, InputKind::PointerMove, msg, lparam)),
+        WM_MOUSEWHEEL | WM_MOUSEHWHEEL => {
+            // Wheel messages carry screen coordinates
+            let mut point = POINT {
+                x: signed_low_word(lparam.0),
+                y: signed_high_word(lparam.0),
+            };
+            ScreenToClient(hwnd, &mut point);
+            let scale = scale_factor(hwnd);
+            let notches = signed_high_word(wparam.0 as isize) as f64 / WHEEL_DELTA as f64;
+            let (delta_x, delta_y) = if msg == WM_MOUSEWHEEL {
+                // Positive is away from the user, which scrolls up
+                (0.0, -notches * PIXELS_PER_NOTCH)
+            } else {
+                (notches * PIXELS_PER_NOTCH, 0.0)
+            };
+            Some(InputEvent::wheel(
+                point.x as f64 / scale,
+                point.y as f64 / scale,
+                delta_x,
+                delta_y,
+                modifiers(),
+            ))
+        }
+        WM_GESTURE => return handle_gesture(hwnd, wparam, lparam, input, &handler),
+        // Anything other than a pen is left to become mouse messages
+        WM_POINTERDOWN | WM_POINTERUPDATE | WM_POINTERUP => pen_event(hwnd, msg, wparam),
+        WM_KEYDOWN | WM_SYSKEYDOWN | WM_KEYUP | WM_SYSKEYUP => {
+            let kind = if msg == WM_KEYDOWN || msg == WM_SYSKEYDOWN {
+                InputKind::KeyDown
+            } else {
+                InputKind::KeyUp
+            };
+            let virtual_key = wparam.0 as u32;
+            // Bit 30 is the previous key state, so it's set on auto-repeat
+            let repeat = kind == InputKind::KeyDown && lparam.0 & (1 << 30) != 0;
+            Some(InputEvent::key(
+                kind,
+                key_text(virtual_key, lparam),
+                virtual_key,
+                repeat,
+                modifiers(),
+            ))
+        }
+        _ => None,
+    };
+
+    match event {
+        Some(event) => {
+            handler(event);
+            LRESULT(0)
+        }
+        None => DefSubclassProc(hwnd, msg, wparam, lparam),
+    }
+}
+
+/// Windows Ink pen input, with pressure and tilt. Returns `None` for other pointer types.
+unsafe fn pen_event(hwnd: HWND, msg: u32, wparam: WPARAM) -> Option<InputEvent> {
+    let pointer_id = (wparam.0 & 0xffff) as u32;
+    let mut pointer_type: POINTER_INPUT_TYPE = 0;
+    if !GetPointerType(pointer_id, &mut pointer_type).as_bool() || pointer_type != PT_PEN {
+        return None;
+    }
+    let mut info = POINTER_PEN_INFO::default();
+    if !GetPointerPenInfo(pointer_id, &mut info).as_bool() {
+        return None;
+    }
+
+    let mut location = info.pointerInfo.ptPixelLocation;
+    ScreenToClient(hwnd, &mut location);
+    let scale = scale_factor(hwnd);
+    let kind = match msg {
+        WM_POINTERDOWN => InputKind::PointerDown,
+        WM_POINTERUP => InputKind::PointerUp,
+        _ => InputKind::PointerMove,
+    };
+    let button = match kind {
+        InputKind::PointerMove => None,
+        _ if info.penFlags & (PEN_FLAG_ERASER | PEN_FLAG_INVERTED) != 0 => Some(5),
+        _ if info.penFlags & PEN_FLAG_BARREL != 0 => Some(2),
+        _ => Some(0),
+    };
+    let masked = |mask: u32, value: f64| if info.penMask & mask != 0 { value } else { 0.0 };
+
+    let event = InputEvent::pointer(
+        kind,
+        location.x as f64 / scale,
+        location.y as f64 / scale,
+        button,
+        modifiers(),
+    );
+    Some(event.with_pen(
+        // Reported from 0 to 1024
+        masked(PEN_MASK_PRESSURE, info.pressure as f64 / 1024.0),
+        masked(PEN_MASK_TILT_X, info.tiltX as f64),
+        masked(PEN_MASK_TILT_Y, info.tiltY as f64),
+    ))
+}
+
+unsafe fn handle_gesture(
+    hwnd: HWND,
+    wparam: WPARAM,
+    lparam: LPARAM,
+    input: &WindowInput,
+    handler: &InputHandler,
+) -> LRESULT {
+    let mut info = GESTUREINFO {
+        cbSize: std::mem::size_of::<GESTUREINFO>() as u32,
+        ..Default::default()
+    };
+    if !GetGestureInfo(HGESTUREINFO(lparam.0), &mut info).as_bool() {
+        return DefSubclassProc(hwnd, WM_GESTURE, wparam, lparam);
+    }
+
+    let mut location = POINT {
+        x: info.ptsLocation.x as i32,
+        y: info.ptsLocation.y as i32,
+    };
+    ScreenToClient(hwnd, &mut location);
+    let scale = scale_factor(hwnd);
+    let (x, y) = (location.x as f64 / scale, location.y as f64 / scale);
+    let phase = if info.dwFlags & GF_BEGIN != 0 {
+        GesturePhase::Begin
+    } else if info.dwFlags & GF_END != 0 {
+        GesturePhase::End
+    } else {
+        GesturePhase::Update
+    };
+    let previous = match input.gesture.get() {
+        Some(previous) if previous.id == info.dwID && phase != GesturePhase::Begin => {
+            Some(previous)
+        }
+        _ => None,
+    };
+
+    let gesture = |kind| InputEvent::gesture(kind, x, y, phase, modifiers());
+    let event = match info.dwID {
+        GID_ZOOM => {
+            // The argument is the distance between the two fingers
+            let scale = match previous {
+                Some(previous) if previous.argument > 0 => {
+                    info.ullArguments as f64 / previous.argument as f64
+                }
+                _ => 1.0,
+            };
+            Some(InputEvent {
+                scale: Some(scale),
+                ..gesture(InputKind::Pinch)
+            })
+        }
+        GID_PAN => {
+            let (delta_x, delta_y) = match previous {
+                Some(previous) => (
+                    (location.x - previous.location.x) as f64 / scale,
+                    (location.y - previous.location.y) as f64 / scale,
+                ),
+                None => (0.0, 0.0),
+            };
+            Some(InputEvent {
+                delta_x,
+                delta_y,
+                ..gesture(InputKind::Pan)
+            })
+        }
+        GID_ROTATE => {
+            // Counterclockwise from where the gesture started
+            let rotation = match previous {
+                Some(previous) => rotate_angle(previous.argument) - rotate_angle(info.ullArguments),
+                None => 0.0,
+            };
+            Some(InputEvent {
+                rotation: Some(rotation),
+                ..gesture(InputKind::Rotate)
+            })
+        }
+        _ => None,
+    };
+
+    input.gesture.set(match phase {
+        GesturePhase::End => None,
+        _ => Some(GestureProgress {
+            id: info.dwID,
+            argument: info.ullArguments,
+            location,
+        }),
+    });
+
+    match event {
+        Some(event) => {
+            handler(event);
+            CloseGestureInfoHandle(HGESTUREINFO(lparam.0));
+            LRESULT(0)
+        }
+        // Two finger taps and the like are left for DefWindowProc to turn into mouse messages
+        None => DefSubclassProc(hwnd, WM_GESTURE, wparam, lparam),
+    }
+}
+
+/// `GID_ROTATE_ANGLE_FROM_ARGUMENT`, in radians.
+fn rotate_angle(argument: u64) -> f64 {
+    let argument = argument & 0xffff;
+    argument as f64 / 65535.0 * 4.0 * std::f64::consts::PI - 2.0 * std::f64::consts::PI
+}
+
+unsafe fn pointer_event(hwnd: HWND, kind: InputKind, msg: u32, lparam: LPARAM) -> InputEvent {
+    let scale = scale_factor(hwnd);
+    let x = signed_low_word(lparam.0) as f64 / scale;
+    let y = signed_high_word(lparam.0) as f64 / scale;
+    let button = match msg {
+        WM_LBUTTONDOWN | WM_LBUTTONUP => Some(0),
+        WM_MBUTTONDOWN | WM_MBUTTONUP => Some(1),
+        WM_RBUTTONDOWN | WM_RBUTTONUP => Some(2),
+        _ => None,
+    };
+    InputEvent::pointer(kind, x, y, button, modifiers())
+}
+
+unsafe fn scale_factor(hwnd: HWND) -> f64 {
+    match GetDpiForWindow(hwnd) {
+        0 => 1.0,
+        dpi => dpi as f64 / 96.0,
+    }
+}
+
+fn signed_low_word(value: isize) -> i32 {
+    (value & 0xffff) as i16 as i32
+}
+
+fn signed_high_word(value: isize) -> i32 {
+    ((value >> 16) & 0xffff) as i16 as i32
+}
+
+unsafe fn modifiers() -> Modifiers {
+    let pressed = |key: u16| GetKeyState(key as i32) < 0;
+    Modifiers {
+        shift: pressed(VK_SHIFT),
+        ctrl: pressed(VK_CONTROL),
+        alt: pressed(VK_MENU),
+        meta: pressed(VK_LWIN) || pressed(VK_RWIN),
+    }
+}
+
+/// The text the key produces with the current keyboard state, if any.
+unsafe fn key_text(virtual_key: u32, lparam: LPARAM) -> Option<String> {
+    let mut keys = [0u8; 256];
+    if !GetKeyboardState(keys.as_mut_ptr()).as_bool() {
+        return None;
+    }
+    let scan_code = ((lparam.0 >> 16) & 0xff) as u32;
+    let mut buffer = [0u16; 8];
+    let len = ToUnicode(
+        virtual_key,
+        scan_code,
+        keys.as_ptr(),
+        PWSTR(buffer.as_mut_ptr()),
+        buffer.len() as i32,
+        TO_UNICODE_NO_STATE_CHANGE,
+    );
+    if len > 0 {
+        String::from_utf16(&buffer[..len as usize]).ok()
+    } else {
+        None
+    }
+}
@@ -0,0 +1,209 @@
+//! Picture-in-picture: overlays moved out of their window into a small floating window of
+//! their own, surface and all, and back again.
+
+use tauri::{
+    AppHandle, LogicalPosition, LogicalSize, Manager, Position, Size, WebviewUrl, WebviewWindow,
+    WebviewWindowBuilder, WindowEvent,
+};
+
+use crate::animation::Tweens;
+use crate::overlay::{Attachment, OverlayFrame};
+use crate::{emit_frame, place, OverlayHandle};
+
+/// The widest a picture-in-picture window opens, in logical pixels. Smaller overlays keep
+/// their size.
+const MAX_WIDTH: f64 = 360.0;
+/// How far the window opens from the bottom-right corner of the screen.
+const SCREEN_MARGIN: f64 = 24.0;
+
+/// An overlay that's in a picture-in-picture window, and where it was before.
+pub struct Detached {
+    pub window: WebviewWindow,
+    frame: OverlayFrame,
+    attachment: Attachment,
+}
+
+/// Move `overlay` into a new borderless, always-on-top window in the corner of its screen.
+pub fn detach(handle: &AppHandle, overlay: &OverlayHandle) -> Result<(), String> {
+    let mut detached = overlay.detached.lock().unwrap();
+    if detached.is_some() {
+        return Err(format!("overlay {:?} is already detached", overlay.id));
+    }
+    let window = handle
+        .get_webview_window(&overlay.window)
+        .ok_or_else(|| format!("there is no window labelled {:?}", overlay.window))?;
+
+    // There's nowhere to follow the cursor to, or animate the frame to, in a window the
+    // overlay fills
+    *overlay.follow.lock().unwrap() = None;
+    *overlay.tweens.lock().unwrap() = Tweens::default();
+    let mut view = overlay.view.lock().unwrap();
+    let frame = view.frame();
+    let size = window_size(frame);
+    let mut builder = WebviewWindowBuilder::new(
+        handle,
+        format!("pip-{}", overlay.id),
+        WebviewUrl::App("pip.html".into()),
+    )
+    .title(overlay.id.clone())
+    .inner_size(size.width, size.height)
+    .decorations(false)
+    .always_on_top(true)
+    .resizable(true)
+    .skip_taskbar(true);
+    if let Some(corner) = screen_corner(&window, size) {
+        builder = builder.position(corner.x, corner.y);
+    }
+    let pip = builder
+        .build()
+        .map_err(|e| format!("failed to open a picture-in-picture window: {:?}", e))?;
+
+    let attachment = view.attachment();
+    if let Err(e) = view.move_to_window(&pip) {
+        let _ = pip.close();
+        return Err(e);
+    }
+    if let Ok(position) = pip.outer_position() {
+        view.set_parent_position(Position::Physical(position));
+    }
+    if let Ok(scale_factor) = pip.scale_factor() {
+        overlay.renderer.set_scale_factor(scale_factor);
+    }
+    place(
+        &mut *view,
+        &overlay.renderer,
+        OverlayFrame {
+            x: 0.0,
+            y: 0.0,
+            width: size.width,
+            height: size.height,
+        },
+    );
+    drop(view);
+    *detached = Some(Detached {
+        window: pip.clone(),
+        frame,
+        attachment,
+    });
+    drop(detached);
+
+    // The overlay fills the window, so it follows the window from here on
+    let handle = handle.clone();
+    let overlay = overlay.clone();
+    pip.on_window_event(move |event| {
+        if !overlay.is_detached() {
+            return;
+        }
+        match event {
+            WindowEvent::Moved(position) => {
+                let mut view = overlay.view.lock().unwrap();
+                view.set_parent_position(Position::Physical(*position));
+            }
+            WindowEvent::Resized(size) => {
+                let mut view = overlay.view.lock().unwrap();
+                view.set_size(Size::Physical(*size));
+                overlay.renderer.resize(*size);
+            }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                overlay.renderer.set_scale_factor(*scale_factor);
+            }
+            WindowEvent::CloseRequested { .. } | WindowEvent::Destroyed => {
+                if let Err(e) = reattach(&handle, &overlay) {
+                    println!("Failed to reattach overlay {:?}: {}", overlay.id, e);
+                }
+            }
+            _ => {}
+        }
+    });
+    Ok(())
+}
+
+/// Put a detached overlay back where it was in its window, and close the picture-in-picture
+/// window.
+pub fn reattach(handle: &AppHandle, overlay: &OverlayHandle) -> Result<(), String> {
+    let mut detached = overlay.detached.lock().unwrap();
+    let window = handle
+        .get_webview_window(&overlay.window)
+        .ok_or_else(|| format!("there is no window labelled {:?}", overlay.window))?;
+    let pip = detached
+        .take()
+        .ok_or_else(|| format!("overlay {:?} is not detached", overlay.id))?;
+
+    let mut view = overlay.view.lock().unwrap();
+    if let Err(e) = view.move_to_window(&window) {
+        *detached = Some(pip);
+        return Err(e);
+    }
+    if let Ok(position) = window.outer_position() {
+        view.set_parent_position(Position::Physical(position));
+    }
+    if let Ok(scale_factor) = window.scale_factor() {
+        overlay.renderer.set_scale_factor(scale_factor);
+    }
+    // The frame goes first, since autoresizing attachments take their margins from it
+    place(&mut *view, &overlay.renderer, pip.frame);
+    if let Err(e) = view.set_attachment(pip.attachment) {
+        println!("Failed to restore overlay attachment: {}", e);
+    }
+    if let Some(size) = view.attached_size() {
+        overlay.renderer.resize(size);
+    }
+    view.surface_configured();
+    drop(view);
+    drop(detached);
+
+    if let Err(e) = pip.window.close() {
+        println!("Failed to close picture-in-picture window: {:?}", e);
+    }
+    emit_frame(&window, "overlay://reattached", &overlay.id, pip.frame);
+    Ok(())
+}
+
+/// Where a picture-in-picture window is on screen, in logical pixels, for dragging it
+/// around by the overlay.
+pub fn window_frame(window: &WebviewWindow) -> Option<OverlayFrame> {
+    let scale = window.scale_factor().ok()?;
+    let position = window.outer_position().ok()?;
+    let size = window.inner_size().ok()?;
+    Some(OverlayFrame {
+        x: position.x as f64 / scale,
+        y: position.y as f64 / scale,
+        width: size.width as f64 / scale,
+        height: size.height as f64 / scale,
+    })
+}
+
+pub fn set_window_frame(window: &WebviewWindow, frame: OverlayFrame) {
+    let moved = window.set_position(Position::Logical(LogicalPosition {
+        x: frame.x,
+        y: frame.y,
+    }));
+    let sized = window.set_size(Size::Logical(LogicalSize {
+        width: frame.width,
+        height: frame.height,
+    }));
+    if let Err(e) = moved.and(sized) {
+        println!("Failed to move picture-in-picture window: {:?}", e);
+    }
+}
+
+/// The overlay's size, scaled down to fit `MAX_WIDTH`.
+fn window_size(frame: OverlayFrame) -> LogicalSize<f64> {
+    let scale = (MAX_WIDTH / frame.width.max(1.0)).min(1.0);
+    LogicalSize {
+        width: (frame.width * scale).round().max(1.0),
+        height: (frame.height * scale).round().max(1.0),
+    }
+}
+
+/// The top-left of a window of `size` in the bottom-right corner of `window`'s screen.
+fn screen_corner(window: &WebviewWindow, size: LogicalSize<f64>) -> Option<LogicalPosition<f64>> {
+    let monitor = window.current_monitor().ok()??;
+    let scale = monitor.scale_factor();
+    let position = monitor.position();
+    let area = monitor.size();
+    Some(LogicalPosition {
+        x: (position.x as f64 + area.width as f64) / scale - size.width - SCREEN_MARGIN,
+        y: (position.y as f64 + area.height as f64) / scale - size.height - SCREEN_MARGIN,
+    })
+}
@@ -0,0 +1,220 @@
+use serde::Deserialize;
+use wgpu::util::DeviceExt;
+
+use super::pipelines::Pipelines;
+use super::{path, MemoryUsage, DEPTH_STENCIL_FORMAT};
+
+/// Pixels inside the clip have this stencil value; clipped pipelines test against it.
+pub const STENCIL_REFERENCE: u32 = 1;
+
+/// The shape that drawing is confined to, in physical pixels relative to the overlay's top-left.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ClipPath {
+    Polygon { points: Vec<[f32; 2]> },
+    Svg { d: String },
+}
+
+impl ClipPath {
+    /// Flatten the path into closed polygons.
+    pub fn contours(&self) -> Result<Vec<Vec<[f32; 2]>>, String> {
+        match self {
+            ClipPath::Polygon { points } => {
+                if points.len() < 3 {
+                    return Err("a clip polygon needs at least 3 points".into());
+                }
+                Ok(vec![points.clone()])
+            }
+            ClipPath::Svg { d } => path::parse_svg_path(d),
+        }
+    }
+}
+
+/// The stencil value the attachment is cleared to. Without a clip every pixel starts
+/// out "inside", so clipped pipelines don't need to know whether a clip is active.
+pub fn clear_value(clipped: bool) -> u32 {
+    if clipped {
+        0
+    } else {
+        STENCIL_REFERENCE
+    }
+}
+
+/// Depth/stencil state for content pipelines: only draw where the clip mask was written.
+pub fn clipped_depth_stencil() -> wgpu::DepthStencilState {
+    let face = wgpu::StencilFaceState {
+        compare: wgpu::CompareFunction::Equal,
+        fail_op: wgpu::StencilOperation::Keep,
+        depth_fail_op: wgpu::StencilOperation::Keep,
+        pass_op: wgpu::StencilOperation::Keep,
+    };
+    wgpu::DepthStencilState {
+        format: DEPTH_STENCIL_FORMAT,
+        depth_write_enabled: Some(false),
+        depth_compare: Some(wgpu::CompareFunction::Always),
+        stencil: wgpu::StencilState {
+            front: face,
+            back: face,
+            read_mask: STENCIL_REFERENCE,
+            write_mask: 0,
+        },
+        bias: wgpu::DepthBiasState::default(),
+    }
+}
+
+/// Writes a clip path into the stencil buffer.
+///
+/// Each contour is drawn as a triangle fan that inverts the stencil bit it covers, which
+/// fills arbitrary (including concave and self-intersecting) paths with the even-odd rule.
+pub struct ClipMask {
+    pipeline: wgpu::RenderPipeline,
+    contours: Option<Vec<Vec<[f32; 2]>>>,
+    vertices: Option<(wgpu::Buffer, u32)>,
+    dirty: bool,
+}
+
+impl ClipMask {
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        pipelines: &Pipelines,
+    ) -> Self {
+        let shader = pipelines.shader(device, "Clip Shader", include_str!("shaders/clip.wgsl"));
+
+        let layout = pipelines.pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Clip Pipeline Layout"),
+                bind_group_layouts: &[],
+                immediate_size: 0,
+            },
+        );
+
+        let face = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::Always,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op: wgpu::StencilOperation::Invert,
+        };
+
+        let pipeline = pipelines.render_pipeline(
+            device,
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Clip Pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[Some(wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &wgpu::vertex_attr_array![0 => Float32x2],
+                    })],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: color_format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::empty(),
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_STENCIL_FORMAT,
+                    depth_write_enabled: Some(false),
+                    depth_compare: Some(wgpu::CompareFunction::Always),
+                    stencil: wgpu::StencilState {
+                        front: face,
+                        back: face,
+                        read_mask: 0xff,
+                        write_mask: STENCIL_REFERENCE,
+                    },
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview_mask: None,
+                cache: pipelines.cache(),
+            },
+        );
+
+        ClipMask {
+            pipeline,
+            contours: None,
+            vertices: None,
+            dirty: false,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.contours.is_some()
+    }
+
+    pub fn set_contours(&mut self, contours: Option<Vec<Vec<[f32; 2]>>>) {
+        self.contours = contours;
+        self.dirty = true;
+    }
+
+    /// The vertices are stored in clip space, so they need rebuilding when the surface resizes.
+    pub fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn prepare(&mut self, device: &wgpu::Device, size: tauri::PhysicalSize<u32>) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        let contours = match &self.contours {
+            Some(contours) => contours,
+            None => {
+                self.vertices = None;
+                return;
+            }
+        };
+
+        let (width, height) = (size.width as f32, size.height as f32);
+        let to_ndc = |[x, y]: [f32; 2]| [x / width * 2.0 - 1.0, 1.0 - y / height * 2.0];
+
+        let mut vertices: Vec<[f32; 2]> = Vec::new();
+        for contour in contours.iter().filter(|c| c.len() >= 3) {
+            let anchor = to_ndc(contour[0]);
+            for pair in contour[1..].windows(2) {
+                vertices.push(anchor);
+                vertices.push(to_ndc(pair[0]));
+                vertices.push(to_ndc(pair[1]));
+            }
+        }
+
+        if vertices.is_empty() {
+            // Nothing to keep: an empty buffer leaves the whole stencil cleared, hiding everything
+            self.vertices = None;
+            return;
+        }
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Clip Vertices"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        self.vertices = Some((buffer, vertices.len() as u32));
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        if let Some((vertices, _)) = &self.vertices {
+            usage.add_buffer(vertices);
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if let Some((buffer, count)) = &self.vertices {
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_vertex_buffer(0, buffer.slice(..));
+            render_pass.draw(0..*count, 0..1);
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::overlay::OverlayView;
+
+use super::filters::FilterPipeline;
+use super::histogram::HistogramPipeline;
+use super::pipelines::Pipelines;
+use super::video::VideoPipeline;
+use super::Presentation;
+
+/// Optional device features, and limits above wgpu's defaults, to ask the adapter for under
+/// `plugins.gpu` in `tauri.conf.json`. Whatever the adapter can't give is left out rather
+/// than failing, and reported by `get_gpu_info`:
+///
+/// ```json
+/// "gpu": { "features": ["timestampQuery"], "limits": { "maxTextureDimension2d": 16384 } }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GpuConfig {
+    #[serde(default)]
+    pub features: Vec<GpuFeature>,
+    #[serde(default)]
+    pub limits: GpuLimits,
+}
+
+/// The wgpu features that can be asked for, in camelCase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GpuFeature {
+    DepthClipControl,
+    TextureCompressionBc,
+    TextureCompressionEtc2,
+    TextureCompressionAstc,
+    IndirectFirstInstance,
+    TimestampQuery,
+    PipelineStatisticsQuery,
+    MultiDrawIndirectCount,
+    Immediates,
+    TextureBindingArray,
+    AddressModeClampToBorder,
+    PolygonModePoint,
+    /// Lets textures do what the adapter supports beyond WebGPU's baseline for their
+    /// format, like filtering 32-bit float textures.
+    TextureAdapterSpecificFormatFeatures,
+    ShaderF64,
+    ConservativeRasterization,
+    ClearTexture,
+    TextureFormat16bitNorm,
+}
+
+impl GpuFeature {
+    const ALL: [GpuFeature; 17] = [
+        GpuFeature::DepthClipControl,
+        GpuFeature::TextureCompressionBc,
+        GpuFeature::TextureCompressionEtc2,
+        GpuFeature::TextureCompressionAstc,
+        GpuFeature::IndirectFirstInstance,
+        GpuFeature::TimestampQuery,
+        GpuFeature::PipelineStatisticsQuery,
+        GpuFeature::MultiDrawIndirectCount,
+        GpuFeature::Immediates,
+        GpuFeature::TextureBindingArray,
+        GpuFeature::AddressModeClampToBorder,
+        GpuFeature::PolygonModePoint,
+        GpuFeature::TextureAdapterSpecificFormatFeatures,
+        GpuFeature::ShaderF64,
+        GpuFeature::ConservativeRasterization,
+        GpuFeature::ClearTexture,
+        GpuFeature::TextureFormat16bitNorm,
+    ];
+
+    fn flag(self) -> wgpu::Features {
+        match self {
+            GpuFeature::DepthClipControl => wgpu::Features::DEPTH_CLIP_CONTROL,
+            GpuFeature::TextureCompressionBc => wgpu::Features::TEXTURE_COMPRESSION_BC,
+            GpuFeature::TextureCompressionEtc2 => wgpu::Features::TEXTURE_COMPRESSION_ETC2,
+            GpuFeature::TextureCompressionAstc => wgpu::Features::TEXTURE_COMPRESSION_ASTC,
+            GpuFeature::IndirectFirstInstance => wgpu::Features::INDIRECT_FIRST_INSTANCE,
+            GpuFeature::TimestampQuery => wgpu::Features::TIMESTAMP_QUERY,
+            GpuFeature::PipelineStatisticsQuery => wgpu::Features::PIPELINE_STATISTICS_QUERY,
+            GpuFeature::MultiDrawIndirectCount => wgpu::Features::MULTI_DRAW_INDIRECT_COUNT,
+            GpuFeature::Immediates => wgpu::Features::IMMEDIATES,
+            GpuFeature::TextureBindingArray => wgpu::Features::TEXTURE_BINDING_ARRAY,
+            GpuFeature::AddressModeClampToBorder => wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER,
+            GpuFeature::PolygonModePoint => wgpu::Features::POLYGON_MODE_POINT,
+            GpuFeature::TextureAdapterSpecificFormatFeatures => {
+                wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+            }
+            GpuFeature::ShaderF64 => wgpu::Features::SHADER_F64,
+            GpuFeature::ConservativeRasterization => wgpu::Features::CONSERVATIVE_RASTERIZATION,
+            GpuFeature::ClearTexture => wgpu::Features::CLEAR_TEXTURE,
+            GpuFeature::TextureFormat16bitNorm => wgpu::Features::TEXTURE_FORMAT_16BIT_NORM,
+        }
+    }
+}
+
+/// Defines `GpuLimits`, with an optional value for each of these wgpu limits.
+macro_rules! gpu_limits {
+    ($($name:ident: $ty:ty),* $(,)?) => {
+        /// Limits to ask for, named as in wgpu but in camelCase. They're all maximums, and
+        /// anything left out keeps wgpu's default.
+        #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        pub struct GpuLimits {
+            $(
+                #[serde(default, skip_serializing_if = "Option::is_none")]
+                pub $name: Option<$ty>,
+            )*
+        }
+
+        impl GpuLimits {
+            /// Every limit's value in `limits`.
+            fn from_limits(limits: &wgpu::Limits) -> Self {
+                GpuLimits {
+                    $($name: Some(limits.$name),)*
+                }
+            }
+
+            /// Set `limits` to what's asked for, as far as `supported` goes, adding what
+            /// had to be lowered to `denied`.
+            fn apply(
+                &self,
+                limits: &mut wgpu::Limits,
+                supported: &wgpu::Limits,
+                denied: &mut Vec<DeniedRequest>,
+            ) {
+                $(
+                    if let Some(requested) = self.$name {
+                        limits.$name = requested.min(supported.$name);
+                        if requested > supported.$name {
+                            denied.push(DeniedRequest {
+                                name: camel_case(stringify!($name)),
+                                supported: Some(supported.$name.into()),
+                            });
+                        }
+                    }
+                )*
+            }
+        }
+    };
+}
+
+gpu_limits!(
+    max_texture_dimension_1d: u32,
+    max_texture_dimension_2d: u32,
+    max_texture_dimension_3d: u32,
+    max_texture_array_layers: u32,
+    max_bind_groups: u32,
+    max_sampled_textures_per_shader_stage: u32,
+    max_samplers_per_shader_stage: u32,
+    max_storage_buffers_per_shader_stage: u32,
+    max_storage_textures_per_shader_stage: u32,
+    max_uniform_buffers_per_shader_stage: u32,
+    max_uniform_buffer_binding_size: u64,
+    max_storage_buffer_binding_size: u64,
+    max_vertex_buffers: u32,
+    max_vertex_attributes: u32,
+    max_immediate_size: u32,
+    max_compute_workgroup_storage_size: u32,
+    max_compute_invocations_per_workgroup: u32,
+);
+
+/// A feature or limit in the `gpu` config that the adapter couldn't give.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeniedRequest {
+    /// As it's named in the config.
+    pub name: String,
+    /// The most a limit could be raised to, which it was instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supported: Option<u64>,
+}
+
+/// The shared GPU, as `get_gpu_info` reports it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GpuInfo {
+    pub adapter: String,
+    pub backend: String,
+    /// The features the device has that can be asked for.
+    pub features: Vec<GpuFeature>,
+    pub limits: GpuLimits,
+    /// What was asked for in the config and couldn't be had.
+    pub denied: Vec<DeniedRequest>,
+    /// The backends that didn't work before `backend` did, in the order they were tried.
+    pub failed_backends: Vec<BackendFailure>,
+}
+
+/// Backends that were tried for the GPU and didn't work.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendFailure {
+    pub backends: String,
+    pub error: String,
+}
+
+/// The GPU every overlay renders with. It's set up for the first overlay's surface and
+/// shared by the rest, so textures and buffers made on its device can be used by any of
+/// them.
+pub struct GpuContext {
+    pub instance: wgpu::Instance,
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub filters: FilterPipeline,
+    pub histogram: HistogramPipeline,
+    pub video: VideoPipeline,
+    pub pipelines: Pipelines,
+    /// Features and limits from the `gpu` config that the adapter couldn't give.
+    pub denied: Vec<DeniedRequest>,
+    /// The backends that were tried first and didn't work.
+    pub failed_backends: Vec<BackendFailure>,
+}
+
+impl GpuContext {
+    /// Pick an adapter that can present to `view`, returning its surface along with the
+    /// context since the surface had to be created to find one. The device gets what
+    /// `config` asks for as far as the adapter allows. Backends are tried in turn until
+    /// one works, so a broken driver for one doesn't leave the app without a GPU. The view is
+    /// only locked while a surface is created, never across waiting for the GPU. The pipeline
+    /// cache is kept in `data_dir`, if there is one.
+    pub async fn new(
+        view: &Mutex<dyn OverlayView + Send>,
+        config: &GpuConfig,
+        data_dir: Option<&Path>,
+    ) -> Result<(Self, Option<wgpu::Surface<'static>>), String> {
+        let mut failures: Vec<BackendFailure> = Vec::new();
+        for backends in fallback_backends() {
+            println!("Setting up the GPU with {:?}", backends);
+            match Self::with_backends(backends, view, config, data_dir).await {
+                Ok((mut context, surface)) => {
+                    context.failed_backends = failures;
+                    return Ok((context, surface));
+                }
+                Err(error) => {
+                    println!("Failed to set up the GPU with {:?}: {}", backends, error);
+                    failures.push(BackendFailure {
+                        backends: format!("{:?}", backends),
+                        error,
+                    });
+                }
+            }
+        }
+        let errors: Vec<String> = failures
+            .iter()
+            .map(|failure| format!("{}: {}", failure.backends, failure.error))
+            .collect();
+        Err(format!("no graphics backend works ({})", errors.join("; ")))
+    }
+
+    async fn with_backends(
+        backends: wgpu::Backends,
+        view: &Mutex<dyn OverlayView + Send>,
+        config: &GpuConfig,
+        data_dir: Option<&Path>,
+    ) -> Result<(Self, Option<wgpu::Surface<'static>>), String> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends,
+            ..wgpu::InstanceDescriptor::new_without_display_handle()
+        });
+        // Broken drivers can panic rather than fail, and the next backend may still work
+        let surface = {
+            let view = view.lock().unwrap();
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                create_surface(&instance, &*view)
+            }))
+            .unwrap_or_else(|_| Err("creating the surface panicked".into()))?
+        };
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: surface.as_ref(),
+                force_fallback_adapter: false,
+                apply_limit_buckets: false,
+            })
+            .await
+            .map_err(|e| format!("no graphics adapter can present to the overlay: {}", e))?;
+
+        let mut denied = Vec::new();
+        let mut features = wgpu::Features::empty();
+        for &feature in &config.features {
+            if adapter.features().contains(feature.flag()) {
+                features |= feature.flag();
+            } else {
+                // Named as it was configured, in camelCase
+                let name = format!("{:?}", feature);
+                denied.push(DeniedRequest {
+                    name: name[..1].to_lowercase() + &name[1..],
+                    supported: None,
+                });
+            }
+        }
+        // Not something to configure, since it only saves compiling shaders again
+        features |= adapter.features() & wgpu::Features::PIPELINE_CACHE;
+        let mut limits = wgpu::Limits::default();
+        config
+            .limits
+            .apply(&mut limits, &adapter.limits(), &mut denied);
+        for request in &denied {
+            println!("The graphics adapter doesn't support {:?}", request);
+        }
+
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                label: None,
+                required_features: features,
+                required_limits: limits,
+                experimental_features: wgpu::ExperimentalFeatures::disabled(),
+                memory_hints: wgpu::MemoryHints::default(),
+                // wgpu::Trace::Directory("trace".into()) with wgpu's `trace` feature
+                trace: wgpu::Trace::Off,
+            })
+            .await
+            .map_err(|e| format!("failed to create the device: {}", e))?;
+
+        let pipelines = Pipelines::new(&device, &adapter.get_info(), data_dir);
+        let filters = FilterPipeline::new(&device, &pipelines);
+        let histogram = HistogramPipeline::new(&device, &pipelines);
+        let video = VideoPipeline::new(&device, &pipelines);
+        let context = GpuContext {
+            instance,
+            adapter,
+            device,
+            queue,
+            filters,
+            histogram,
+            video,
+            pipelines,
+            denied,
+            failed_backends: Vec::new(),
+        };
+        Ok((context, surface))
+    }
+
+    pub fn info(&self) -> GpuInfo {
+        let adapter = self.adapter.get_info();
+        let features = self.device.features();
+        GpuInfo {
+            adapter: adapter.name,
+            backend: format!("{:?}", adapter.backend),
+            features: GpuFeature::ALL
+                .iter()
+                .copied()
+                .filter(|feature| features.contains(feature.flag()))
+                .collect(),
+            limits: GpuLimits::from_limits(&self.device.limits()),
+            denied: self.denied.clone(),
+            failed_backends: self.failed_backends.clone(),
+        }
+    }
+
+    /// Create the surface for another overlay's view, checking that the shared adapter can
+    /// present to it.
+    pub fn create_surface(
+        &self,
+        view: &dyn OverlayView,
+    ) -> Result<Option<wgpu::Surface<'static>>, String> {
+        let surface = create_surface(&self.instance, view)?;
+        if let Some(surface) = &surface {
+            if surface.get_capabilities(&self.adapter).formats.is_empty() {
+                return Err("the shared graphics adapter can't present to the overlay".into());
+            }
+        }
+        Ok(surface)
+    }
+}
+
+/// The backends to try the GPU with, best first. Any backend comes first, and then the
+/// ones most likely to work when the preferred one's driver is broken.
+fn fallback_backends() -> Vec<wgpu::Backends> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "windows")] {
+            vec![wgpu::Backends::all(), wgpu::Backends::DX12, wgpu::Backends::GL]
+        } else if #[cfg(target_os = "macos")] {
+            vec![wgpu::Backends::all(), wgpu::Backends::METAL]
+        } else {
+            vec![wgpu::Backends::all(), wgpu::Backends::VULKAN, wgpu::Backends::GL]
+        }
+    }
+}
+
+fn create_surface(
+    instance: &wgpu::Instance,
+    view: &dyn OverlayView,
+) -> Result<Option<wgpu::Surface<'static>>, String> {
+    // Read back overlays present the pixels themselves, so they must not get a swapchain
+    match view.presentation() {
+        Presentation::Surface => unsafe { view.create_surface(instance) }
+            .map(Some)
+            .map_err(|e| format!("failed to create the surface: {}", e)),
+        Presentation::Readback => Ok(None),
+    }
+}
+
+/// `snake_case` as `camelCase`.
+fn camel_case(name: &str) -> String {
+    let mut words = name.split('_');
+    let mut camel = words.next().unwrap_or_default().to_string();
+    for word in words {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            camel.extend(first.to_uppercase());
+            camel.push_str(chars.as_str());
+        }
+    }
+    camel
+}
@@ -0,0 +1,148 @@
+use wgpu::util::DeviceExt;
+
+use super::clip;
+use super::pipelines::Pipelines;
+use super::MemoryUsage;
+
+/// A pipeline that floods the current viewport with a single color, respecting the clip mask.
+pub struct FillPipeline {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+}
+
+/// The uniforms for one use of the fill pipeline.
+pub struct FillColor {
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl FillPipeline {
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        pipelines: &Pipelines,
+    ) -> Self {
+        let shader = pipelines.shader(device, "Fill Shader", include_str!("shaders/fill.wgsl"));
+
+        let bind_group_layout = pipelines.bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Fill Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let layout = pipelines.pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Fill Pipeline Layout"),
+                bind_group_layouts: &[Some(&bind_group_layout)],
+                immediate_size: 0,
+            },
+        );
+
+        let pipeline = pipelines.render_pipeline(
+            device,
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Fill Pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: color_format,
+                        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(clip::clipped_depth_stencil()),
+                multisample: wgpu::MultisampleState::default(),
+                multiview_mask: None,
+                cache: pipelines.cache(),
+            },
+        );
+
+        FillPipeline {
+            pipeline,
+            bind_group_layout,
+        }
+    }
+
+    pub fn create_color(&self, device: &wgpu::Device, color: wgpu::Color) -> FillColor {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Fill Uniforms"),
+            contents: bytemuck::cast_slice(&color_to_array(color)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Fill Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        FillColor { buffer, bind_group }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, color: &'a FillColor) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_stencil_reference(clip::STENCIL_REFERENCE);
+        render_pass.set_bind_group(0, &color.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+impl FillColor {
+    pub fn set(&self, queue: &wgpu::Queue, color: wgpu::Color) {
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(&color_to_array(color)),
+        );
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        usage.add_buffer(&self.buffer);
+    }
+}
+
+/// Everything is composited with premultiplied alpha, which is what both CoreAnimation
+/// and layered windows expect from a transparent overlay.
+pub fn premultiply(color: wgpu::Color) -> wgpu::Color {
+    wgpu::Color {
+        r: color.r * color.a,
+        g: color.g * color.a,
+        b: color.b * color.a,
+        a: color.a,
+    }
+}
+
+fn color_to_array(color: wgpu::Color) -> [f32; 4] {
+    let color = premultiply(color);
+    [
+        color.r as f32,
+        color.g as f32,
+        color.b as f32,
+        color.a as f32,
+    ]
+}
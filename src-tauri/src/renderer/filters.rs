@@ -0,0 +1,490 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use wgpu::util::DeviceExt;
+
+use super::blit::Blit;
+use super::pipelines::Pipelines;
+use super::{GpuContext, MemoryUsage};
+use crate::assets::{Asset, Assets};
+
+/// Filters write to floating point targets, so a chain of them doesn't band.
+const FILTER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// The widest convolution kernel, in pixels.
+const MAX_KERNEL_SIZE: usize = 15;
+/// Matches `workgroup_size` in the shader.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// An image adjustment, run on the GPU. Colors are adjusted as sRGB values, the way image
+/// editors do.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Filter {
+    /// Scale colors around mid grey by `contrast`, then add `brightness` to them. The
+    /// defaults of 0 and 1 leave colors alone.
+    BrightnessContrast {
+        #[serde(default)]
+        brightness: f32,
+        #[serde(default = "default_contrast")]
+        contrast: f32,
+    },
+    /// Map colors through a 3D lookup table: the id of a texture asset holding `n` slices
+    /// of n x n pixels side by side, with red across each slice, green down it and blue
+    /// increasing from slice to slice.
+    Lut { asset: String },
+    /// Convolve with a square kernel, given row by row, such as a blur or a sharpen. Its
+    /// width must be odd. Edge pixels repeat outwards.
+    Convolution { kernel: Vec<f32> },
+}
+
+fn default_contrast() -> f32 {
+    1.0
+}
+
+/// A filter with its assets looked up and its parameters checked.
+#[derive(Clone)]
+enum Stage {
+    BrightnessContrast {
+        brightness: f32,
+        contrast: f32,
+    },
+    Lut {
+        id: String,
+        asset: Arc<Asset>,
+        size: u32,
+    },
+    Convolution {
+        kernel: Vec<f32>,
+        size: u32,
+    },
+}
+
+fn resolve(filters: &[Filter], assets: &Assets) -> Result<Vec<Stage>, String> {
+    filters
+        .iter()
+        .map(|filter| match filter {
+            Filter::BrightnessContrast {
+                brightness,
+                contrast,
+            } => Ok(Stage::BrightnessContrast {
+                brightness: *brightness,
+                contrast: *contrast,
+            }),
+            Filter::Lut { asset: id } => {
+                let asset = assets
+                    .get(id)
+                    .ok_or_else(|| format!("no asset called {:?}", id))?;
+                let [width, height] = asset
+                    .texture()
+                    .ok_or_else(|| format!("asset {:?} is not a texture", id))?
+                    .size;
+                if width != height * height {
+                    return Err(format!(
+                        "lookup table {:?} must be n slices of n x n pixels side by side",
+                        id
+                    ));
+                }
+                Ok(Stage::Lut {
+                    id: id.clone(),
+                    asset,
+                    size: height,
+                })
+            }
+            Filter::Convolution { kernel } => {
+                let size = (kernel.len() as f64).sqrt() as usize;
+                if size * size != kernel.len() || size.is_multiple_of(2) || size > MAX_KERNEL_SIZE {
+                    return Err(format!(
+                        "a convolution kernel must be square, with an odd width up to {}",
+                        MAX_KERNEL_SIZE
+                    ));
+                }
+                Ok(Stage::Convolution {
+                    kernel: kernel.clone(),
+                    size: size as u32,
+                })
+            }
+        })
+        .collect()
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct FilterParams {
+    brightness: f32,
+    contrast: f32,
+    kernel_size: u32,
+    lut_size: u32,
+    premultiplied: u32,
+    linear: u32,
+    _padding: [u32; 2],
+}
+
+/// The compute pipelines for each kind of filter, shared by everything on the GPU.
+pub struct FilterPipeline {
+    brightness_contrast: wgpu::ComputePipeline,
+    lut: wgpu::ComputePipeline,
+    convolve: wgpu::ComputePipeline,
+    lut_sampler: wgpu::Sampler,
+}
+
+impl FilterPipeline {
+    pub fn new(device: &wgpu::Device, pipelines: &Pipelines) -> Self {
+        let shader = pipelines.shader(
+            device,
+            "Filter Shader",
+            include_str!("shaders/filters.wgsl"),
+        );
+        // Each entry point only uses some of the bindings, so the layouts come from the
+        // shader rather than one shared layout
+        let pipeline = |entry_point: &str| {
+            pipelines.compute_pipeline(
+                device,
+                &wgpu::ComputePipelineDescriptor {
+                    label: Some("Filter Pipeline"),
+                    layout: None,
+                    module: &shader,
+                    entry_point: Some(entry_point),
+                    compilation_options: Default::default(),
+                    cache: pipelines.cache(),
+                },
+            )
+        };
+
+        FilterPipeline {
+            brightness_contrast: pipeline("brightness_contrast"),
+            lut: pipeline("apply_lut"),
+            convolve: pipeline("convolve"),
+            lut_sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("Lookup Table Sampler"),
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn pipeline(&self, stage: &Stage) -> &wgpu::ComputePipeline {
+        match stage {
+            Stage::BrightnessContrast { .. } => &self.brightness_contrast,
+            Stage::Lut { .. } => &self.lut,
+            Stage::Convolution { .. } => &self.convolve,
+        }
+    }
+}
+
+/// How the pixels a chain reads are stored. Its output is stored the same way.
+#[derive(Debug, Clone, Copy)]
+struct InputFormat {
+    premultiplied: bool,
+    /// Decoded from an sRGB texture, rather than already sRGB values.
+    linear: bool,
+}
+
+struct Pass {
+    /// Reads the previous pass's output and writes this one's.
+    io: wgpu::BindGroup,
+    /// The lookup table or kernel, for filters that have one.
+    resources: Option<wgpu::BindGroup>,
+}
+
+/// A list of filters bound to an input, ping-ponging between two intermediate targets.
+struct FilterChain {
+    stages: Vec<Stage>,
+    passes: Vec<Pass>,
+    targets: Vec<(wgpu::Texture, wgpu::TextureView)>,
+    size: [u32; 2],
+}
+
+impl FilterChain {
+    fn new(
+        device: &wgpu::Device,
+        pipeline: &FilterPipeline,
+        stages: Vec<Stage>,
+        input: &wgpu::TextureView,
+        format: InputFormat,
+        size: [u32; 2],
+    ) -> Self {
+        let targets: Vec<_> = (0..stages.len().min(2))
+            .map(|_| create_target(device, size))
+            .collect();
+
+        let passes = stages
+            .iter()
+            .enumerate()
+            .map(|(index, stage)| {
+                let compute = pipeline.pipeline(stage);
+                let source = match index {
+                    0 => input,
+                    _ => &targets[(index - 1) % 2].1,
+                };
+                let mut params = FilterParams {
+                    premultiplied: format.premultiplied as u32,
+                    linear: format.linear as u32,
+                    ..Default::default()
+                };
+                match stage {
+                    Stage::BrightnessContrast {
+                        brightness,
+                        contrast,
+                    } => {
+                        params.brightness = *brightness;
+                        params.contrast = *contrast;
+                    }
+                    Stage::Lut { size, .. } => params.lut_size = *size,
+                    Stage::Convolution { size, .. } => params.kernel_size = *size,
+                }
+                let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Filter Params"),
+                    contents: bytemuck::bytes_of(&params),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+
+                let io = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Filter Bind Group"),
+                    layout: &compute.get_bind_group_layout(0),
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(source),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(&targets[index % 2].1),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: params.as_entire_binding(),
+                        },
+                    ],
+                });
+
+                let resources = match stage {
+                    Stage::BrightnessContrast { .. } => None,
+                    Stage::Lut { asset, .. } => {
+                        let texture = asset.texture().expect("lookup tables are textures");
+                        Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                            label: Some("Lookup Table Bind Group"),
+                            layout: &compute.get_bind_group_layout(1),
+                            entries: &[
+                                wgpu::BindGroupEntry {
+                                    binding: 0,
+                                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                                },
+                                wgpu::BindGroupEntry {
+                                    binding: 1,
+                                    resource: wgpu::BindingResource::Sampler(&pipeline.lut_sampler),
+                                },
+                            ],
+                        }))
+                    }
+                    Stage::Convolution { kernel, .. } => {
+                        let weights =
+                            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                                label: Some("Convolution Kernel"),
+                                contents: bytemuck::cast_slice(kernel),
+                                usage: wgpu::BufferUsages::STORAGE,
+                            });
+                        Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                            label: Some("Convolution Bind Group"),
+                            layout: &compute.get_bind_group_layout(1),
+                            entries: &[wgpu::BindGroupEntry {
+                                binding: 2,
+                                resource: weights.as_entire_binding(),
+                            }],
+                        }))
+                    }
+                };
+
+                Pass { io, resources }
+            })
+            .collect();
+
+        FilterChain {
+            stages,
+            passes,
+            targets,
+            size,
+        }
+    }
+
+    fn dispatch(&self, pipeline: &FilterPipeline, encoder: &mut wgpu::CommandEncoder) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Filter Pass"),
+            timestamp_writes: None,
+        });
+        for (stage, pass) in self.stages.iter().zip(&self.passes) {
+            compute_pass.set_pipeline(pipeline.pipeline(stage));
+            compute_pass.set_bind_group(0, &pass.io, &[]);
+            if let Some(resources) = &pass.resources {
+                compute_pass.set_bind_group(1, resources, &[]);
+            }
+            compute_pass.dispatch_workgroups(
+                self.size[0].div_ceil(WORKGROUP_SIZE),
+                self.size[1].div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+    }
+
+    /// The target the last filter writes to.
+    fn output(&self) -> &(wgpu::Texture, wgpu::TextureView) {
+        &self.targets[(self.stages.len() - 1) % 2]
+    }
+
+    fn memory(&self, usage: &mut MemoryUsage) {
+        for (texture, _) in &self.targets {
+            usage.add_texture(texture);
+        }
+    }
+
+    /// Whether every lookup table is still the asset loaded under its id.
+    fn is_current(&self, assets: &Assets) -> bool {
+        self.stages.iter().all(|stage| match stage {
+            Stage::Lut { id, asset, .. } => assets
+                .get(id)
+                .is_some_and(|current| Arc::ptr_eq(asset, &current)),
+            _ => true,
+        })
+    }
+}
+
+fn create_target(device: &wgpu::Device, size: [u32; 2]) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Filter Target"),
+        size: wgpu::Extent3d {
+            width: size[0],
+            height: size[1],
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FILTER_FORMAT,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+/// Run `filters` over a straight alpha sRGB texture, such as a texture asset, returning a
+/// new texture of the same size that holds the result in the same way.
+pub fn filter_texture(
+    gpu: &GpuContext,
+    assets: &Assets,
+    filters: &[Filter],
+    input: &wgpu::TextureView,
+    size: [u32; 2],
+) -> Result<(wgpu::Texture, wgpu::TextureView), String> {
+    if filters.is_empty() {
+        return Err("there are no filters to apply".into());
+    }
+    let stages = resolve(filters, assets)?;
+    let format = InputFormat {
+        premultiplied: false,
+        linear: true,
+    };
+    let mut chain = FilterChain::new(&gpu.device, &gpu.filters, stages, input, format, size);
+
+    let mut encoder = gpu
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Filter Encoder"),
+        });
+    chain.dispatch(&gpu.filters, &mut encoder);
+    gpu.queue.submit(std::iter::once(encoder.finish()));
+
+    let output = (chain.stages.len() - 1) % 2;
+    Ok(chain.targets.swap_remove(output))
+}
+
+/// Filters over an overlay's output: the scene, or whatever a render graph pass reads,
+/// filtered and then copied onto the pass's target.
+pub struct OutputFilters {
+    stages: Vec<Stage>,
+    format: wgpu::TextureFormat,
+    chain: FilterChain,
+    blit_bind_group: wgpu::BindGroup,
+}
+
+impl OutputFilters {
+    /// Filter `input`, a texture of `format` and `size`, or fail if a filter is invalid.
+    pub fn new(
+        gpu: &GpuContext,
+        assets: &Assets,
+        blit: &Blit,
+        filters: &[Filter],
+        input: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+        size: tauri::PhysicalSize<u32>,
+    ) -> Result<Self, String> {
+        let stages = resolve(filters, assets)?;
+        let (chain, blit_bind_group) = bind(gpu, blit, stages.clone(), input, format, size);
+        Ok(OutputFilters {
+            stages,
+            format,
+            chain,
+            blit_bind_group,
+        })
+    }
+
+    /// Filter a new input, after it was reallocated at `size`.
+    pub fn rebind(
+        &mut self,
+        gpu: &GpuContext,
+        blit: &Blit,
+        input: &wgpu::TextureView,
+        size: tauri::PhysicalSize<u32>,
+    ) {
+        let (chain, blit_bind_group) =
+            bind(gpu, blit, self.stages.clone(), input, self.format, size);
+        self.chain = chain;
+        self.blit_bind_group = blit_bind_group;
+    }
+
+    /// Whether every lookup table is still the asset loaded under its id.
+    pub fn is_current(&self, assets: &Assets) -> bool {
+        self.chain.is_current(assets)
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        self.chain.memory(usage);
+    }
+
+    /// Filter the input and draw the result over all of `view`.
+    pub fn apply(
+        &self,
+        pipeline: &FilterPipeline,
+        blit: &Blit,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+    ) {
+        self.chain.dispatch(pipeline, encoder);
+        blit.draw(encoder, &self.blit_bind_group, view);
+    }
+}
+
+fn bind(
+    gpu: &GpuContext,
+    blit: &Blit,
+    stages: Vec<Stage>,
+    input: &wgpu::TextureView,
+    format: wgpu::TextureFormat,
+    size: tauri::PhysicalSize<u32>,
+) -> (FilterChain, wgpu::BindGroup) {
+    // The input is premultiplied, and reads back linear if it's sRGB
+    let input_format = InputFormat {
+        premultiplied: true,
+        linear: format.is_srgb(),
+    };
+    let chain = FilterChain::new(
+        &gpu.device,
+        &gpu.filters,
+        stages,
+        input,
+        input_format,
+        [size.width, size.height],
+    );
+    let blit_bind_group = blit.bind(&gpu.device, &chain.output().1);
+    (chain, blit_bind_group)
+}
@@ -0,0 +1,406 @@
+//! The passes that make up a frame, declared with the textures they read and write, so
+//! multi-pass setups are data rather than code in `render()`. Targets are allocated at the
+//! surface's size and the passes ordered by what they depend on.
+//!
+//! ```json
+//! { "passes": [
+//!   { "name": "scene", "type": "scene", "output": "scene" },
+//!   { "name": "grade", "type": "filters", "inputs": ["scene"], "output": "frame",
+//!     "filters": [{ "type": "brightnessContrast", "contrast": 1.2 }] },
+//!   { "name": "hud", "type": "hud", "output": "frame" }
+//! ] }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::blit::Blit;
+use super::filters::{Filter, OutputFilters};
+use super::{GpuContext, MemoryUsage, DEPTH_STENCIL_FORMAT};
+use crate::assets::Assets;
+
+/// The texture the overlay presents. Passes can write it, but not read it.
+pub const FRAME: &str = "frame";
+/// The depth/stencil buffer that passes which draw use, unless they name another.
+const DEFAULT_DEPTH: &str = "depth";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphDescriptor {
+    pub passes: Vec<PassDescriptor>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PassDescriptor {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: PassKind,
+    /// The textures the pass reads, by name.
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    /// The texture the pass writes, which is [`FRAME`] or allocated for the graph.
+    pub output: String,
+    /// The depth/stencil buffer, for passes that draw. They share one unless they're given
+    /// their own.
+    #[serde(default)]
+    pub depth: Option<String>,
+}
+
+/// What a pass does.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum PassKind {
+    /// Draw the overlay's content: layers, video, panes and ink, over the clear color.
+    Scene,
+    /// Run filters over the one input.
+    Filters { filters: Vec<Filter> },
+    /// Copy the one input.
+    Blit,
+    /// Draw the frame rate readout over what's already in the output, when it's shown.
+    Hud,
+}
+
+impl PassKind {
+    fn input_count(&self) -> usize {
+        match self {
+            PassKind::Scene | PassKind::Hud => 0,
+            PassKind::Filters { .. } | PassKind::Blit => 1,
+        }
+    }
+
+    fn uses_depth(&self) -> bool {
+        matches!(self, PassKind::Scene | PassKind::Hud)
+    }
+
+    /// Whether the pass draws over its output rather than replacing all of it.
+    fn loads_output(&self) -> bool {
+        matches!(self, PassKind::Hud)
+    }
+}
+
+impl GraphDescriptor {
+    /// Read a graph from a JSON file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {:?}: {}", path, e))?;
+        serde_json::from_str(&json).map_err(|e| format!("invalid render graph {:?}: {}", path, e))
+    }
+
+    /// The graph overlays use unless they're given one: the scene, through `filters` if
+    /// there are any, and then the HUD.
+    pub fn standard(filters: &[Filter]) -> Self {
+        let pass = |name: &str, kind, inputs: &[&str], output: &str| PassDescriptor {
+            name: name.into(),
+            kind,
+            inputs: inputs.iter().map(|input| input.to_string()).collect(),
+            output: output.into(),
+            depth: None,
+        };
+        let mut passes = Vec::new();
+        if filters.is_empty() {
+            passes.push(pass("scene", PassKind::Scene, &[], FRAME));
+        } else {
+            passes.push(pass("scene", PassKind::Scene, &[], "scene"));
+            let filters = PassKind::Filters {
+                filters: filters.to_vec(),
+            };
+            passes.push(pass("filters", filters, &["scene"], FRAME));
+        }
+        passes.push(pass("hud", PassKind::Hud, &[], FRAME));
+        GraphDescriptor { passes }
+    }
+
+    /// Check the passes against each other, and order them so every pass runs after the
+    /// ones that write its inputs. Passes that write the same texture run in the order
+    /// they're declared.
+    fn order(&self) -> Result<Vec<usize>, String> {
+        let passes = &self.passes;
+        let mut names = HashSet::new();
+        let mut writers: HashMap<&str, Vec<usize>> = HashMap::new();
+        let mut depths = HashSet::new();
+        for (index, pass) in passes.iter().enumerate() {
+            if !names.insert(pass.name.as_str()) {
+                return Err(format!("duplicate pass name {:?}", pass.name));
+            }
+            if pass.inputs.len() != pass.kind.input_count() {
+                return Err(format!(
+                    "pass {:?} needs {} input(s), not {}",
+                    pass.name,
+                    pass.kind.input_count(),
+                    pass.inputs.len()
+                ));
+            }
+            if pass.inputs.iter().any(|input| input == FRAME) {
+                return Err(format!("pass {:?} can't read {:?}", pass.name, FRAME));
+            }
+            if pass.inputs.contains(&pass.output) {
+                return Err(format!("pass {:?} reads its own output", pass.name));
+            }
+            if pass.kind.uses_depth() {
+                depths.insert(pass.depth.as_deref().unwrap_or(DEFAULT_DEPTH));
+            } else if pass.depth.is_some() {
+                return Err(format!("pass {:?} doesn't use a depth buffer", pass.name));
+            }
+            let earlier = writers.entry(pass.output.as_str()).or_default();
+            if pass.kind.loads_output() && earlier.is_empty() {
+                return Err(format!(
+                    "pass {:?} draws over {:?}, which no pass before it writes",
+                    pass.name, pass.output
+                ));
+            }
+            earlier.push(index);
+        }
+        if !writers.contains_key(FRAME) {
+            return Err(format!("no pass writes {:?}", FRAME));
+        }
+        if let Some(depth) = depths.iter().find(|depth| writers.contains_key(**depth)) {
+            return Err(format!("{:?} is both a texture and a depth buffer", depth));
+        }
+
+        let mut dependencies: Vec<HashSet<usize>> = vec![HashSet::new(); passes.len()];
+        for writers in writers.values() {
+            for pair in writers.windows(2) {
+                dependencies[pair[1]].insert(pair[0]);
+            }
+        }
+        for (index, pass) in passes.iter().enumerate() {
+            for input in &pass.inputs {
+                let writers = writers.get(input.as_str()).ok_or_else(|| {
+                    format!(
+                        "pass {:?} reads {:?}, which no pass writes",
+                        pass.name, input
+                    )
+                })?;
+                dependencies[index].extend(writers);
+            }
+        }
+
+        // Take the first pass in declaration order that's ready, so independent passes
+        // run as they're written
+        let mut order = Vec::with_capacity(passes.len());
+        let mut done = vec![false; passes.len()];
+        while order.len() < passes.len() {
+            let next = (0..passes.len())
+                .find(|index| !done[*index] && dependencies[*index].iter().all(|dep| done[*dep]))
+                .ok_or_else(|| {
+                    let stuck: Vec<&str> = (0..passes.len())
+                        .filter(|index| !done[*index])
+                        .map(|index| passes[index].name.as_str())
+                        .collect();
+                    format!("passes {:?} depend on each other", stuck)
+                })?;
+            done[next] = true;
+            order.push(next);
+        }
+        Ok(order)
+    }
+}
+
+/// What a compiled pass records.
+pub enum Operation {
+    Scene,
+    Filters(OutputFilters),
+    Blit(wgpu::BindGroup),
+    Hud,
+}
+
+pub struct Pass {
+    pub operation: Operation,
+    input: Option<String>,
+    output: String,
+    depth: Option<String>,
+}
+
+/// A graph with its targets allocated and its passes in the order they run.
+pub struct RenderGraph {
+    passes: Vec<Pass>,
+    textures: HashMap<String, wgpu::TextureView>,
+    depths: HashMap<String, wgpu::TextureView>,
+}
+
+impl RenderGraph {
+    /// Check and order `descriptor`'s passes, and allocate what they need at `size`, with
+    /// textures in `format`.
+    pub fn new(
+        gpu: &GpuContext,
+        assets: &Assets,
+        blit: &Blit,
+        descriptor: &GraphDescriptor,
+        format: wgpu::TextureFormat,
+        size: tauri::PhysicalSize<u32>,
+    ) -> Result<Self, String> {
+        let order = descriptor.order()?;
+        let mut graph = RenderGraph {
+            passes: Vec::with_capacity(order.len()),
+            textures: HashMap::new(),
+            depths: HashMap::new(),
+        };
+        for index in order {
+            let pass = &descriptor.passes[index];
+            let depth = match pass.kind.uses_depth() {
+                true => Some(pass.depth.as_deref().unwrap_or(DEFAULT_DEPTH).to_string()),
+                false => None,
+            };
+            if let Some(depth) = &depth {
+                graph
+                    .depths
+                    .entry(depth.clone())
+                    .or_insert_with(|| create_depth_stencil(&gpu.device, size));
+            }
+            if pass.output != FRAME {
+                graph
+                    .textures
+                    .entry(pass.output.clone())
+                    .or_insert_with(|| create_target(&gpu.device, format, size));
+            }
+            let input = pass.inputs.first().cloned();
+            let operation = match &pass.kind {
+                PassKind::Scene => Operation::Scene,
+                PassKind::Hud => Operation::Hud,
+                PassKind::Blit => Operation::Blit(blit.bind(&gpu.device, graph.input(&input))),
+                PassKind::Filters { filters } => Operation::Filters(
+                    OutputFilters::new(
+                        gpu,
+                        assets,
+                        blit,
+                        filters,
+                        graph.input(&input),
+                        format,
+                        size,
+                    )
+                    .map_err(|e| format!("pass {:?}: {}", pass.name, e))?,
+                ),
+            };
+            graph.passes.push(Pass {
+                operation,
+                input,
+                output: pass.output.clone(),
+                depth,
+            });
+        }
+        Ok(graph)
+    }
+
+    /// Reallocate every target at `size`, keeping their formats.
+    pub fn resize(
+        &mut self,
+        gpu: &GpuContext,
+        blit: &Blit,
+        format: wgpu::TextureFormat,
+        size: tauri::PhysicalSize<u32>,
+    ) {
+        for texture in self.textures.values_mut() {
+            *texture = create_target(&gpu.device, format, size);
+        }
+        for depth in self.depths.values_mut() {
+            *depth = create_depth_stencil(&gpu.device, size);
+        }
+        for pass in &mut self.passes {
+            let input = match &pass.input {
+                Some(input) => &self.textures[input],
+                None => continue,
+            };
+            match &mut pass.operation {
+                Operation::Blit(bind_group) => *bind_group = blit.bind(&gpu.device, input),
+                Operation::Filters(filters) => filters.rebind(gpu, blit, input, size),
+                Operation::Scene | Operation::Hud => {}
+            }
+        }
+    }
+
+    /// Whether every filter pass's lookup tables are still the assets loaded under their ids.
+    pub fn is_current(&self, assets: &Assets) -> bool {
+        self.passes.iter().all(|pass| match &pass.operation {
+            Operation::Filters(filters) => filters.is_current(assets),
+            _ => true,
+        })
+    }
+
+    /// The graph's textures and depth buffers, and its filter passes' targets.
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        for view in self.textures.values().chain(self.depths.values()) {
+            usage.add_view(view);
+        }
+        for pass in &self.passes {
+            if let Operation::Filters(filters) = &pass.operation {
+                filters.memory(usage);
+            }
+        }
+    }
+
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    /// Where `pass` draws, with `frame` standing in for [`FRAME`].
+    pub fn output<'a>(
+        &'a self,
+        pass: &Pass,
+        frame: &'a wgpu::TextureView,
+    ) -> &'a wgpu::TextureView {
+        match pass.output.as_str() {
+            FRAME => frame,
+            output => &self.textures[output],
+        }
+    }
+
+    /// The depth/stencil buffer `pass` draws with. Only passes that draw have one.
+    pub fn depth(&self, pass: &Pass) -> &wgpu::TextureView {
+        let depth = pass
+            .depth
+            .as_ref()
+            .expect("only passes that draw use depth");
+        &self.depths[depth]
+    }
+
+    fn input(&self, input: &Option<String>) -> &wgpu::TextureView {
+        let input = input.as_ref().expect("checked against the pass's kind");
+        &self.textures[input]
+    }
+}
+
+fn create_target(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    size: tauri::PhysicalSize<u32>,
+) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Render Graph Target"),
+        size: wgpu::Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn create_depth_stencil(
+    device: &wgpu::Device,
+    size: tauri::PhysicalSize<u32>,
+) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Depth Stencil"),
+        size: wgpu::Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: DEPTH_STENCIL_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
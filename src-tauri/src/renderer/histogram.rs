@@ -0,0 +1,163 @@
+use serde::Serialize;
+use wgpu::util::DeviceExt;
+
+use super::pipelines::Pipelines;
+use super::{target, GpuContext};
+
+/// Bins per channel, one for each 8-bit value. Matches the shader.
+const BINS: usize = 256;
+/// Red, green, blue and luminance.
+const CHANNELS: usize = 4;
+/// Matches `workgroup_size` in the shader.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// How many pixels have each 8-bit value of red, green, blue and luminance, as sRGB values
+/// with straight alpha. Fully transparent pixels aren't counted.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Histogram {
+    pub red: Vec<u32>,
+    pub green: Vec<u32>,
+    pub blue: Vec<u32>,
+    /// Rec. 709 luma.
+    pub luminance: Vec<u32>,
+    /// How many pixels were counted.
+    pub pixels: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct HistogramParams {
+    premultiplied: u32,
+    linear: u32,
+    _padding: [u32; 2],
+}
+
+/// Counts a texture's pixels into bins on the GPU.
+pub struct HistogramPipeline {
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl HistogramPipeline {
+    pub fn new(device: &wgpu::Device, pipelines: &Pipelines) -> Self {
+        let shader = pipelines.shader(
+            device,
+            "Histogram Shader",
+            include_str!("shaders/histogram.wgsl"),
+        );
+        let pipeline = pipelines.compute_pipeline(
+            device,
+            &wgpu::ComputePipelineDescriptor {
+                label: Some("Histogram Pipeline"),
+                layout: None,
+                module: &shader,
+                entry_point: Some("count"),
+                compilation_options: Default::default(),
+                cache: pipelines.cache(),
+            },
+        );
+        HistogramPipeline { pipeline }
+    }
+
+    /// Count the pixels of `input` after whatever `encoder` already does, such as drawing
+    /// into it, and block until the GPU is done. `premultiplied` and `linear` say how the
+    /// input's colors are stored.
+    pub fn count(
+        &self,
+        gpu: &GpuContext,
+        mut encoder: wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        size: [u32; 2],
+        premultiplied: bool,
+        linear: bool,
+    ) -> Result<Histogram, String> {
+        let device = &gpu.device;
+        let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Histogram Params"),
+            contents: bytemuck::bytes_of(&HistogramParams {
+                premultiplied: premultiplied as u32,
+                linear: linear as u32,
+                _padding: [0; 2],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        // New buffers start zeroed, so the bins don't need clearing
+        let bins_size = (BINS * CHANNELS * std::mem::size_of::<u32>()) as wgpu::BufferAddress;
+        let bins = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Histogram Bins"),
+            size: bins_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Histogram Readback"),
+            size: bins_size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Histogram Bind Group"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: bins.as_entire_binding(),
+                },
+            ],
+        });
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Histogram Pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(
+                size[0].div_ceil(WORKGROUP_SIZE),
+                size[1].div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+        encoder.copy_buffer_to_buffer(&bins, 0, &readback, 0, bins_size);
+        gpu.queue.submit(std::iter::once(encoder.finish()));
+
+        let data = target::map_read(device, readback.slice(..))
+            .map_err(|e| format!("failed to read back histogram: {}", e))?;
+        let counts: Vec<u32> = bytemuck::cast_slice(&data).to_vec();
+        drop(data);
+        readback.unmap();
+
+        let mut channels = counts.chunks(BINS).map(|bins| bins.to_vec());
+        let red = channels.next().unwrap_or_default();
+        Ok(Histogram {
+            pixels: red.iter().sum(),
+            red,
+            green: channels.next().unwrap_or_default(),
+            blue: channels.next().unwrap_or_default(),
+            luminance: channels.next().unwrap_or_default(),
+        })
+    }
+}
+
+/// The histogram of a straight alpha sRGB texture, such as a texture asset.
+pub fn texture_histogram(
+    gpu: &GpuContext,
+    input: &wgpu::TextureView,
+    size: [u32; 2],
+) -> Result<Histogram, String> {
+    let encoder = gpu
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Histogram Encoder"),
+        });
+    gpu.histogram.count(gpu, encoder, input, size, false, true)
+}
@@ -0,0 +1,168 @@
+//! A frame rate readout in the overlay's top-left corner, for checking performance without
+//! a profiler. It has its own tiny bitmap font, so it works before any font is loaded.
+
+use std::time::{Duration, Instant};
+
+use super::image::ImagePipeline;
+use super::text::{TextImage, TextSurface};
+use super::MemoryUsage;
+
+/// How often the readout changes. Text is uploaded as a texture, so not every frame.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+/// Logical pixels per font pixel.
+const PIXEL_SIZE: f64 = 2.0;
+/// From the overlay's top-left corner, in logical pixels.
+const MARGIN: f64 = 8.0;
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+/// Font pixels between the text and the edge of its background.
+const PADDING: usize = 1;
+const TEXT_COLOR: [u8; 4] = [255, 255, 255, 255];
+const BACKGROUND_COLOR: [u8; 4] = [0, 0, 0, 160];
+
+#[derive(Default)]
+pub struct Hud {
+    visible: bool,
+    frames: u32,
+    counted_since: Option<Instant>,
+    fps: Option<f64>,
+    surface: Option<TextSurface>,
+    /// The text and scale factor the surface was set for.
+    set_for: Option<(String, f64)>,
+}
+
+impl Hud {
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Show or hide the readout. It starts counting again when it's shown.
+    pub fn set_visible(&mut self, visible: bool) {
+        if visible && !self.visible {
+            self.frames = 0;
+            self.counted_since = None;
+            self.fps = None;
+        }
+        self.visible = visible;
+    }
+
+    /// Count a frame that's been rendered.
+    pub fn frame_rendered(&mut self) {
+        if !self.visible {
+            return;
+        }
+        let now = Instant::now();
+        let since = *self.counted_since.get_or_insert(now);
+        self.frames += 1;
+        let elapsed = now - since;
+        if elapsed >= UPDATE_INTERVAL {
+            self.fps = Some(self.frames as f64 / elapsed.as_secs_f64());
+            self.frames = 0;
+            self.counted_since = Some(now);
+        }
+    }
+
+    /// Set the readout's text, if it changed.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        images: &ImagePipeline,
+        scale_factor: f64,
+    ) {
+        if !self.visible {
+            self.surface = None;
+            self.set_for = None;
+            return;
+        }
+        let text = match self.fps {
+            Some(fps) => format!("{:.0} FPS", fps),
+            None => "-- FPS".to_string(),
+        };
+        if self.set_for.as_ref() == Some(&(text.clone(), scale_factor)) {
+            return;
+        }
+        let scale = (PIXEL_SIZE * scale_factor).round().max(1.0) as usize;
+        self.surface = Some(TextSurface::new(
+            device,
+            queue,
+            images,
+            &rasterize(&text, scale),
+        ));
+        self.set_for = Some((text, scale_factor));
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        if let Some(surface) = &self.surface {
+            surface.memory(usage);
+        }
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        images: &'a ImagePipeline,
+        scale_factor: f64,
+        target: tauri::PhysicalSize<u32>,
+    ) {
+        if let Some(surface) = &self.surface {
+            let margin = (MARGIN * scale_factor) as f32;
+            surface.draw(render_pass, images, margin, margin, target);
+        }
+    }
+}
+
+/// Set `text` in the bitmap font, each font pixel `scale` physical pixels square.
+fn rasterize(text: &str, scale: usize) -> TextImage {
+    let chars: Vec<char> = text.chars().collect();
+    let columns = (chars.len() * (GLYPH_WIDTH + 1)).saturating_sub(1) + 2 * PADDING;
+    let rows = GLYPH_HEIGHT + 2 * PADDING;
+    let width = columns * scale;
+    let height = rows * scale;
+
+    let mut pixels = BACKGROUND_COLOR.repeat(width * height);
+    for (i, c) in chars.iter().enumerate() {
+        let left = PADDING + i * (GLYPH_WIDTH + 1);
+        for (row, bits) in glyph(*c).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                    continue;
+                }
+                let x = (left + column) * scale;
+                let y = (PADDING + row) * scale;
+                for py in y..y + scale {
+                    for px in x..x + scale {
+                        let offset = (py * width + px) * 4;
+                        pixels[offset..offset + 4].copy_from_slice(&TEXT_COLOR);
+                    }
+                }
+            }
+        }
+    }
+    TextImage {
+        pixels,
+        size: [width as u32, height as u32],
+    }
+}
+
+/// Rows of a character, top to bottom, with the leftmost pixel in the highest bit.
+/// Anything outside of what the readout uses is blank.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'P' => [0b111, 0b101, 0b111, 0b100, 0b100],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        _ => [0; GLYPH_HEIGHT],
+    }
+}
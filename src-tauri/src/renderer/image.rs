@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use super::clip;
+use super::pipelines::Pipelines;
+use crate::assets::Asset;
+
+/// A pipeline that stretches a texture asset over the current viewport, respecting the
+/// clip mask.
+pub struct ImagePipeline {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+}
+
+/// A texture bound for drawing with the image pipeline.
+pub struct Image {
+    bind_group: wgpu::BindGroup,
+    asset: Arc<Asset>,
+}
+
+impl Image {
+    /// Whether this is bound to `asset`, rather than some other version of it.
+    pub fn is_bound_to(&self, asset: &Arc<Asset>) -> bool {
+        Arc::ptr_eq(&self.asset, asset)
+    }
+}
+
+impl ImagePipeline {
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        pipelines: &Pipelines,
+    ) -> Self {
+        let shader = pipelines.shader(device, "Image Shader", include_str!("shaders/image.wgsl"));
+
+        let bind_group_layout = pipelines.bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Image Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let layout = pipelines.pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Image Pipeline Layout"),
+                bind_group_layouts: &[Some(&bind_group_layout)],
+                immediate_size: 0,
+            },
+        );
+
+        let pipeline = pipelines.render_pipeline(
+            device,
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Image Pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: color_format,
+                        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(clip::clipped_depth_stencil()),
+                multisample: wgpu::MultisampleState::default(),
+                multiview_mask: None,
+                cache: pipelines.cache(),
+            },
+        );
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Image Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        ImagePipeline {
+            pipeline,
+            bind_group_layout,
+            sampler,
+        }
+    }
+
+    /// Bind a texture asset, failing if `asset` is some other kind.
+    pub fn create_image(&self, device: &wgpu::Device, asset: Arc<Asset>) -> Result<Image, String> {
+        let texture = asset.texture().ok_or("asset is not a texture")?;
+        let bind_group = self.bind(device, &texture.view);
+        Ok(Image { bind_group, asset })
+    }
+
+    /// Bind any straight alpha texture, for textures that aren't assets.
+    pub fn bind(&self, device: &wgpu::Device, view: &wgpu::TextureView) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Image Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        })
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, image: &'a Image) {
+        self.draw_bound(render_pass, &image.bind_group);
+    }
+
+    pub fn draw_bound<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_stencil_reference(clip::STENCIL_REFERENCE);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,359 @@
+use serde::Deserialize;
+use wgpu::util::DeviceExt;
+
+use super::pipelines::Pipelines;
+use super::upload::Uploads;
+use super::{clip, fill, MemoryUsage};
+use crate::input::{InputEvent, InputKind, PointerType};
+
+/// Segments used to round off each stroke point.
+const JOIN_SEGMENTS: usize = 8;
+/// Strokes get their own depth so that a stroke's overlapping segments are only drawn
+/// once, instead of doubling up where a translucent ink overlaps itself.
+const DEPTH_STEP: f32 = 1.0 / 65536.0;
+/// The DOM button number of a pen's eraser end.
+const ERASER_BUTTON: u16 = 5;
+
+/// How pen input is drawn.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InkBrush {
+    /// Straight (not premultiplied) alpha.
+    pub color: [f64; 4],
+    /// Stroke width at full pressure, in logical pixels.
+    pub width: f64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct InkVertex {
+    position: [f32; 2],
+    depth: f32,
+    color: [f32; 4],
+}
+
+/// What the pen is doing while it touches the surface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Contact {
+    None,
+    /// Extending the last stroke.
+    Drawing,
+    Erasing,
+}
+
+struct Stroke {
+    color: [f32; 4],
+    width: f32,
+    depth: f32,
+    /// x, y and width at each point
+    points: Vec<[f32; 3]>,
+    /// Tessellated incrementally as points arrive, so extending a stroke is cheap.
+    vertices: Vec<InkVertex>,
+}
+
+impl Stroke {
+    fn push(&mut self, x: f32, y: f32, pressure: f32) {
+        let point = [x, y, self.width * pressure.clamp(0.05, 1.0)];
+        if let Some(&previous) = self.points.last() {
+            if previous[0] == point[0] && previous[1] == point[1] {
+                return;
+            }
+            self.push_segment(previous, point);
+        }
+        self.push_join(point);
+        self.points.push(point);
+    }
+
+    /// A quad from `a` to `b`, tapering between their widths.
+    fn push_segment(&mut self, a: [f32; 3], b: [f32; 3]) {
+        let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+        let length = (dx * dx + dy * dy).sqrt();
+        let (nx, ny) = (-dy / length, dx / length);
+        let corner =
+            |p: [f32; 3], side: f32| [p[0] + nx * p[2] * 0.5 * side, p[1] + ny * p[2] * 0.5 * side];
+        let quad = [
+            corner(a, 1.0),
+            corner(a, -1.0),
+            corner(b, 1.0),
+            corner(b, 1.0),
+            corner(a, -1.0),
+            corner(b, -1.0),
+        ];
+        for position in quad {
+            self.push_vertex(position);
+        }
+    }
+
+    /// A disc at the point, rounding off joins and the stroke's ends.
+    fn push_join(&mut self, p: [f32; 3]) {
+        let radius = p[2] * 0.5;
+        let step = std::f32::consts::TAU / JOIN_SEGMENTS as f32;
+        for i in 0..JOIN_SEGMENTS {
+            let (a, b) = (i as f32 * step, (i + 1) as f32 * step);
+            self.push_vertex([p[0], p[1]]);
+            self.push_vertex([p[0] + radius * a.cos(), p[1] + radius * a.sin()]);
+            self.push_vertex([p[0] + radius * b.cos(), p[1] + radius * b.sin()]);
+        }
+    }
+
+    fn push_vertex(&mut self, position: [f32; 2]) {
+        self.vertices.push(InkVertex {
+            position,
+            depth: self.depth,
+            color: self.color,
+        });
+    }
+
+    fn touches(&self, x: f32, y: f32, radius: f32) -> bool {
+        self.points.iter().any(|p| {
+            let reach = radius + p[2] * 0.5;
+            (p[0] - x).powi(2) + (p[1] - y).powi(2) <= reach * reach
+        })
+    }
+}
+
+/// A freehand drawing layer fed directly by pen input, so strokes show up on the next
+/// frame without a round trip through the webview.
+pub struct InkLayer {
+    pipeline: wgpu::RenderPipeline,
+    uniforms: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    brush: Option<InkBrush>,
+    strokes: Vec<Stroke>,
+    contact: Contact,
+    next_depth: f32,
+    /// Grown as strokes are drawn, and written whenever they change. Kept with its size
+    /// in bytes.
+    vertices: Option<(wgpu::Buffer, wgpu::BufferAddress)>,
+    vertex_count: u32,
+    dirty: bool,
+}
+
+impl InkLayer {
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        pipelines: &Pipelines,
+    ) -> Self {
+        let shader = pipelines.shader(device, "Ink Shader", include_str!("shaders/ink.wgsl"));
+
+        let uniforms = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Ink Uniforms"),
+            contents: bytemuck::cast_slice(&[1.0f32, 1.0]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = pipelines.bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Ink Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Ink Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniforms.as_entire_binding(),
+            }],
+        });
+
+        let layout = pipelines.pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Ink Pipeline Layout"),
+                bind_group_layouts: &[Some(&bind_group_layout)],
+                immediate_size: 0,
+            },
+        );
+
+        let pipeline = pipelines.render_pipeline(device, &wgpu::RenderPipelineDescriptor {
+            label: Some("Ink Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[Some(wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<InkVertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32, 2 => Float32x4],
+                })],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                depth_write_enabled: Some(true),
+                depth_compare: Some(wgpu::CompareFunction::Less),
+                ..clip::clipped_depth_stencil()
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview_mask: None,
+            cache: pipelines.cache(),
+        });
+
+        InkLayer {
+            pipeline,
+            uniforms,
+            bind_group,
+            brush: None,
+            strokes: Vec::new(),
+            contact: Contact::None,
+            next_depth: 1.0 - DEPTH_STEP,
+            vertices: None,
+            vertex_count: 0,
+            dirty: false,
+        }
+    }
+
+    /// Start drawing pen input with `brush`, or stop with `None`. Existing strokes stay.
+    pub fn set_brush(&mut self, brush: Option<InkBrush>) {
+        self.brush = brush;
+        self.contact = Contact::None;
+    }
+
+    pub fn clear(&mut self) {
+        self.strokes.clear();
+        self.contact = Contact::None;
+        self.next_depth = 1.0 - DEPTH_STEP;
+        self.dirty = true;
+    }
+
+    /// Draw (or erase) with a pen event. Returns false if the event isn't ink input.
+    pub fn apply(&mut self, event: &InputEvent) -> bool {
+        let brush = match (&self.brush, event.pointer_type) {
+            (Some(brush), Some(PointerType::Pen)) => brush,
+            _ => return false,
+        };
+        let (x, y) = (event.x as f32, event.y as f32);
+        let pressure = event.pressure.unwrap_or(1.0) as f32;
+        let eraser_radius = brush.width as f32 * 0.5;
+
+        match (event.kind, self.contact) {
+            (InputKind::PointerDown, _) if event.button == Some(ERASER_BUTTON) => {
+                self.contact = Contact::Erasing;
+                self.erase(x, y, eraser_radius);
+            }
+            (InputKind::PointerDown, _) => {
+                let color = fill::premultiply(wgpu::Color {
+                    r: brush.color[0],
+                    g: brush.color[1],
+                    b: brush.color[2],
+                    a: brush.color[3],
+                });
+                let mut stroke = Stroke {
+                    color: [
+                        color.r as f32,
+                        color.g as f32,
+                        color.b as f32,
+                        color.a as f32,
+                    ],
+                    width: brush.width as f32,
+                    depth: self.next_depth,
+                    points: Vec::new(),
+                    vertices: Vec::new(),
+                };
+                // Later strokes are drawn in front; once out of depth values they share one
+                self.next_depth = (self.next_depth - DEPTH_STEP).max(DEPTH_STEP);
+                stroke.push(x, y, pressure);
+                self.strokes.push(stroke);
+                self.contact = Contact::Drawing;
+                self.dirty = true;
+            }
+            (InputKind::PointerMove, Contact::Drawing) => {
+                if let Some(stroke) = self.strokes.last_mut() {
+                    stroke.push(x, y, pressure);
+                    self.dirty = true;
+                }
+            }
+            (InputKind::PointerMove, Contact::Erasing) => self.erase(x, y, eraser_radius),
+            (InputKind::PointerUp, _) => self.contact = Contact::None,
+            _ => {}
+        }
+        true
+    }
+
+    fn erase(&mut self, x: f32, y: f32, radius: f32) {
+        let count = self.strokes.len();
+        self.strokes.retain(|stroke| !stroke.touches(x, y, radius));
+        if self.strokes.len() != count {
+            self.dirty = true;
+        }
+    }
+
+    /// Upload strokes that changed since the last frame. `size` is the surface in logical pixels.
+    pub fn prepare(&mut self, device: &wgpu::Device, uploads: &mut Uploads, size: [f32; 2]) {
+        uploads.write(&self.uniforms, 0, bytemuck::cast_slice(&size));
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        let vertices: Vec<InkVertex> = self
+            .strokes
+            .iter()
+            .flat_map(|stroke| stroke.vertices.iter().copied())
+            .collect();
+        self.vertex_count = vertices.len() as u32;
+        if vertices.is_empty() {
+            return;
+        }
+
+        let needed = (vertices.len() * std::mem::size_of::<InkVertex>()) as wgpu::BufferAddress;
+        if self
+            .vertices
+            .as_ref()
+            .is_none_or(|(_, capacity)| *capacity < needed)
+        {
+            // Room for the stroke to go on a while before growing again
+            let capacity = needed.next_power_of_two();
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Ink Vertices"),
+                size: capacity,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            self.vertices = Some((buffer, capacity));
+        }
+        if let Some((buffer, _)) = &self.vertices {
+            uploads.write(buffer, 0, bytemuck::cast_slice(&vertices));
+        }
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        usage.add_buffer(&self.uniforms);
+        if let Some((vertices, _)) = &self.vertices {
+            usage.add_buffer(vertices);
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if let Some((buffer, _)) = self.vertices.as_ref().filter(|_| self.vertex_count > 0) {
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_stencil_reference(clip::STENCIL_REFERENCE);
+            render_pass.set_bind_group(0, &self.bind_group, &[]);
+            render_pass.set_vertex_buffer(0, buffer.slice(..));
+            render_pass.draw(0..self.vertex_count, 0..1);
+        }
+    }
+}
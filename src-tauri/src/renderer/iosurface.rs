@@ -0,0 +1,96 @@
+//! Wraps the planes of VideoToolbox frames as Metal textures, so hardware decoded video is
+//! drawn without ever leaving the GPU.
+
+use objc2::runtime::ProtocolObject;
+use objc2_io_surface::IOSurfaceRef;
+use objc2_metal::{
+    MTLDevice, MTLPixelFormat, MTLTextureDescriptor, MTLTextureType, MTLTextureUsage,
+};
+use wgpu_hal::api::Metal;
+
+use crate::video::PixelBuffer;
+
+/// Luma and chroma textures sharing `pixel_buffer`'s memory, or `None` if the device isn't
+/// using Metal. The pixel buffer has to outlive the textures' use.
+pub fn import_planes(
+    device: &wgpu::Device,
+    pixel_buffer: &PixelBuffer,
+) -> Option<(wgpu::Texture, wgpu::Texture)> {
+    let raw_device = unsafe { device.as_hal::<Metal>() }?.raw_device().clone();
+    let luma = import_plane(
+        device,
+        &raw_device,
+        pixel_buffer,
+        0,
+        wgpu::TextureFormat::R8Unorm,
+    )?;
+    let chroma = import_plane(
+        device,
+        &raw_device,
+        pixel_buffer,
+        1,
+        wgpu::TextureFormat::Rg8Unorm,
+    )?;
+    Some((luma, chroma))
+}
+
+fn import_plane(
+    device: &wgpu::Device,
+    raw_device: &ProtocolObject<dyn MTLDevice>,
+    pixel_buffer: &PixelBuffer,
+    plane: usize,
+    format: wgpu::TextureFormat,
+) -> Option<wgpu::Texture> {
+    let [width, height] = pixel_buffer.plane_size(plane);
+    let raw_format = match format {
+        wgpu::TextureFormat::R8Unorm => MTLPixelFormat::R8Unorm,
+        _ => MTLPixelFormat::RG8Unorm,
+    };
+    let descriptor = MTLTextureDescriptor::new();
+    descriptor.setTextureType(MTLTextureType::Type2D);
+    descriptor.setPixelFormat(raw_format);
+    descriptor.setUsage(MTLTextureUsage::ShaderRead);
+
+    unsafe {
+        descriptor.setWidth(width as usize);
+        descriptor.setHeight(height as usize);
+        let io_surface = pixel_buffer.io_surface() as *const IOSurfaceRef;
+        let raw = raw_device.newTextureWithDescriptor_iosurface_plane(
+            &descriptor,
+            &*io_surface,
+            plane,
+        )?;
+
+        let texture = wgpu_hal::metal::Device::texture_from_raw(
+            raw,
+            format,
+            MTLTextureType::Type2D,
+            1,
+            1,
+            wgpu_hal::CopyExtent {
+                width,
+                height,
+                depth: 1,
+            },
+            None,
+        );
+        Some(device.create_texture_from_hal::<Metal>(
+            texture,
+            &wgpu::TextureDescriptor {
+                label: Some("Video Plane"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::TextureUses::RESOURCE,
+        ))
+    }
+}
@@ -0,0 +1,420 @@
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use super::pipelines::Pipelines;
+use super::upload::Uploads;
+use super::{clip, MemoryUsage};
+use crate::assets::{Asset, Assets, IsfAsset, IsfFrame, IsfInput, IsfInputType};
+
+/// An input of a layer's shader, as `get_shader_inputs` returns it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShaderInput {
+    #[serde(flatten)]
+    pub input: IsfInput,
+    /// What it's set to, which for an image is the id of a texture asset or null.
+    pub value: Value,
+}
+
+/// An ISF shader asset ready to draw over a layer, with the values of its inputs.
+pub struct IsfShader {
+    asset: Arc<Asset>,
+    pipeline: Arc<IsfPipeline>,
+    /// Bound to image inputs that haven't been set, so they read as transparent.
+    blank: wgpu::TextureView,
+    uniforms: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// One for each input, in the order they're declared.
+    values: Vec<Value>,
+    /// The texture assets bound to image inputs, by the input's index.
+    images: Vec<Option<Arc<Asset>>>,
+    started: Instant,
+    last_frame: Option<Instant>,
+    frame_index: i32,
+}
+
+/// What every layer drawing the same ISF shader can share, which [`Pipelines`] keeps.
+pub struct IsfPipeline {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+}
+
+impl IsfPipeline {
+    /// The uniforms, sampler and image inputs `isf` is bound to.
+    pub fn entries(isf: &IsfAsset) -> Vec<wgpu::BindGroupLayoutEntry> {
+        let mut entries = vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ];
+        entries.extend(
+            isf.image_bindings()
+                .map(|(_, binding)| wgpu::BindGroupLayoutEntry {
+                    binding,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                }),
+        );
+        entries
+    }
+
+    /// Draw `isf` with `vertex`, the shared vertex half of ISF shaders, bound to `entries`.
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &Pipelines,
+        vertex: &wgpu::ShaderModule,
+        color_format: wgpu::TextureFormat,
+        isf: &IsfAsset,
+        entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> Self {
+        let bind_group_layout = pipelines.bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("ISF Bind Group Layout"),
+                entries,
+            },
+        );
+
+        let layout = pipelines.pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("ISF Pipeline Layout"),
+                bind_group_layouts: &[Some(&bind_group_layout)],
+                immediate_size: 0,
+            },
+        );
+
+        // ISF shaders output straight alpha, which is premultiplied as it's blended
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("ISF Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: vertex,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &isf.module,
+                entry_point: Some("main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::SrcAlpha,
+                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::One,
+                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(clip::clipped_depth_stencil()),
+            multisample: wgpu::MultisampleState::default(),
+            multiview_mask: None,
+            cache: pipelines.cache(),
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("ISF Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        IsfPipeline {
+            pipeline,
+            bind_group_layout,
+            sampler,
+        }
+    }
+}
+
+impl IsfShader {
+    /// Set up `asset` to draw with its pipeline from `pipelines`, failing if it's some other
+    /// kind of asset.
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &Pipelines,
+        color_format: wgpu::TextureFormat,
+        asset: Arc<Asset>,
+    ) -> Result<Self, String> {
+        let isf = asset.isf().ok_or("asset is not an ISF shader")?;
+        let pipeline = pipelines.isf(device, color_format, isf);
+
+        // New textures are zeroed, which is transparent
+        let blank = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("ISF Blank Image"),
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ISF Uniforms"),
+            size: isf.uniforms_size as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let values = isf.inputs.iter().map(IsfInput::initial_value).collect();
+        let images = vec![None; isf.inputs.len()];
+        let bind_group = bind(device, isf, &pipeline, &uniforms, &blank, &images);
+
+        Ok(IsfShader {
+            pipeline,
+            blank,
+            uniforms,
+            bind_group,
+            values,
+            images,
+            started: Instant::now(),
+            last_frame: None,
+            frame_index: 0,
+            asset,
+        })
+    }
+
+    /// The shader's inputs and what they're set to.
+    pub fn inputs(&self) -> Vec<ShaderInput> {
+        self.isf()
+            .inputs
+            .iter()
+            .zip(&self.values)
+            .map(|(input, value)| ShaderInput {
+                input: input.clone(),
+                value: value.clone(),
+            })
+            .collect()
+    }
+
+    /// Set the input called `name`. Image inputs take the id of a texture asset, or null
+    /// to unset them.
+    pub fn set_input(
+        &mut self,
+        device: &wgpu::Device,
+        assets: &Assets,
+        name: &str,
+        value: Value,
+    ) -> Result<(), String> {
+        let isf = self.asset.isf().unwrap();
+        let index = isf
+            .inputs
+            .iter()
+            .position(|input| input.name == name)
+            .ok_or_else(|| format!("the shader has no input '{}'", name))?;
+        let input = &isf.inputs[index];
+        if input.kind == IsfInputType::Image {
+            let image = match &value {
+                Value::Null => None,
+                Value::String(id) => Some(texture(assets, id)?),
+                _ => return Err(format!("input '{}' takes the id of a texture asset", name)),
+            };
+            self.images[index] = image;
+            self.rebind(device);
+        } else {
+            // Checked now, and written with the rest of the uniforms each frame
+            input.encode(&value)?;
+        }
+        self.values[index] = value;
+        Ok(())
+    }
+
+    /// Rebind image inputs whose assets have been reloaded.
+    pub fn refresh_images(&mut self, device: &wgpu::Device, assets: &Assets) {
+        let mut reloaded = false;
+        for (value, image) in self.values.iter().zip(&mut self.images) {
+            let (id, bound) = match (value, image.as_ref()) {
+                (Value::String(id), Some(bound)) => (id, bound),
+                _ => continue,
+            };
+            match texture(assets, id) {
+                Ok(asset) if !Arc::ptr_eq(&asset, bound) => {
+                    *image = Some(asset);
+                    reloaded = true;
+                }
+                _ => {}
+            }
+        }
+        if reloaded {
+            self.rebind(device);
+        }
+    }
+
+    /// Write this frame's uniforms, for a target `size` physical pixels big. Events go
+    /// back to false once they've been drawn with.
+    pub fn prepare(&mut self, uploads: &mut Uploads, size: tauri::PhysicalSize<u32>) {
+        let now = Instant::now();
+        let time_delta = self
+            .last_frame
+            .map_or(0.0, |last| (now - last).as_secs_f32());
+        self.last_frame = Some(now);
+
+        let isf = self.asset.isf().unwrap();
+        let mut contents = vec![0; isf.uniforms_size];
+        IsfFrame {
+            date: date(SystemTime::now()),
+            render_size: [size.width as f32, size.height as f32],
+            time: (now - self.started).as_secs_f32(),
+            time_delta,
+            frame_index: self.frame_index,
+        }
+        .write(&mut contents);
+        let inputs = isf.inputs.iter().zip(&isf.offsets);
+        for ((input, offset), value) in inputs.zip(&mut self.values) {
+            // A default from the header that doesn't fit the input's type is left as 0
+            if let (Some(offset), Ok(bytes)) = (offset, input.encode(value)) {
+                contents[*offset..*offset + bytes.len()].copy_from_slice(&bytes);
+            }
+            if input.kind == IsfInputType::Event {
+                *value = Value::from(false);
+            }
+        }
+        uploads.write(&self.uniforms, 0, &contents);
+        self.frame_index = self.frame_index.wrapping_add(1);
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        usage.add_view(&self.blank);
+        usage.add_buffer(&self.uniforms);
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline.pipeline);
+        render_pass.set_stencil_reference(clip::STENCIL_REFERENCE);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn isf(&self) -> &IsfAsset {
+        // Checked when the shader was created
+        self.asset.isf().unwrap()
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device) {
+        self.bind_group = bind(
+            device,
+            self.isf(),
+            &self.pipeline,
+            &self.uniforms,
+            &self.blank,
+            &self.images,
+        );
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    isf: &IsfAsset,
+    pipeline: &IsfPipeline,
+    uniforms: &wgpu::Buffer,
+    blank: &wgpu::TextureView,
+    images: &[Option<Arc<Asset>>],
+) -> wgpu::BindGroup {
+    let mut entries = vec![
+        wgpu::BindGroupEntry {
+            binding: 0,
+            resource: uniforms.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::Sampler(&pipeline.sampler),
+        },
+    ];
+    entries.extend(isf.image_bindings().map(|(index, binding)| {
+        let view = images[index]
+            .as_ref()
+            .and_then(|image| image.texture())
+            .map_or(blank, |texture| &texture.view);
+        wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::TextureView(view),
+        }
+    }));
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("ISF Bind Group"),
+        layout: &pipeline.bind_group_layout,
+        entries: &entries,
+    })
+}
+
+/// The loaded texture asset called `id`.
+fn texture(assets: &Assets, id: &str) -> Result<Arc<Asset>, String> {
+    let asset = assets
+        .get(id)
+        .ok_or_else(|| format!("no asset '{}' has been loaded", id))?;
+    if asset.texture().is_none() {
+        return Err(format!("asset '{}' is not a texture", id));
+    }
+    Ok(asset)
+}
+
+/// ISF's `DATE` for `now`, in UTC: the year, month, day and seconds since midnight.
+fn date(now: SystemTime) -> [f32; 4] {
+    let seconds = now
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |since| since.as_secs_f64());
+    let days = (seconds / 86400.0).floor();
+    let (year, month, day) = civil_from_days(days as i64);
+    [
+        year as f32,
+        month as f32,
+        day as f32,
+        (seconds - days * 86400.0) as f32,
+    ]
+}
+
+/// The Gregorian year, month and day that's `days` after 1970-01-01, from Howard
+/// Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Months counted from March, so February's leap day comes last
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -0,0 +1,573 @@
+use rayon::prelude::*;
+use serde::Deserialize;
+use serde_json::Value;
+use wgpu::util::DeviceExt;
+
+use super::clip;
+use super::fill::{FillColor, FillPipeline};
+use super::image::{Image, ImagePipeline};
+use super::isf::{IsfShader, ShaderInput};
+use super::pipelines::Pipelines;
+use super::upload::Uploads;
+use super::MemoryUsage;
+use crate::assets::Assets;
+
+/// Layers are encoded on several threads once there are this many visible.
+const PARALLEL_LAYERS: usize = 8;
+/// The most threads layers are encoded on.
+const MAX_ENCODERS: usize = 4;
+
+/// How a layer combines with what's beneath it. Colors are premultiplied throughout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Additive,
+    /// Darkens what's beneath by the layer's color. Only exact over an opaque backdrop:
+    /// blending can't read the backdrop's alpha back into its color, so the
+    /// `Sc * (1 - Da)` term is left out. Overlays with a transparent background refuse it,
+    /// and draw it as `Normal` if their background turns transparent afterwards.
+    Multiply,
+}
+
+impl BlendMode {
+    /// In declaration order, so a mode's index is `mode as usize`.
+    const ALL: [BlendMode; 3] = [BlendMode::Normal, BlendMode::Additive, BlendMode::Multiply];
+
+    fn blend_state(self) -> wgpu::BlendState {
+        let over = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+            operation: wgpu::BlendOperation::Add,
+        };
+        match self {
+            BlendMode::Normal => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            BlendMode::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: over,
+            },
+            BlendMode::Multiply => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Dst,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: over,
+            },
+        }
+    }
+}
+
+/// A named layer of the overlay, drawn into its own target and then composited over the
+/// background and panes (but under ink) in `z_index` order.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayerDescriptor {
+    pub id: String,
+    /// Layers with higher indices go on top. Ties keep the order they were given in.
+    #[serde(default)]
+    pub z_index: i32,
+    #[serde(default = "default_visible")]
+    pub visible: bool,
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+    #[serde(default)]
+    pub blend: BlendMode,
+    /// Straight alpha color to fill the layer with.
+    #[serde(default)]
+    pub color: Option<[f64; 4]>,
+    /// Id of a texture asset to stretch over the layer, on top of its color.
+    #[serde(default)]
+    pub image: Option<String>,
+    /// Id of an ISF shader asset to draw over the layer, on top of its image. Its inputs
+    /// are set with `set_uniform`.
+    #[serde(default)]
+    pub shader: Option<String>,
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+/// Changes to a layer's compositing. Anything left out stays as it was.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayerUpdate {
+    pub z_index: Option<i32>,
+    pub visible: Option<bool>,
+    pub opacity: Option<f32>,
+    pub blend: Option<BlendMode>,
+}
+
+struct Layer {
+    descriptor: LayerDescriptor,
+    fill: Option<FillColor>,
+    image: Option<Image>,
+    shader: Option<IsfShader>,
+    target: wgpu::TextureView,
+    uniforms: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Renders layers into intermediate targets and blends them into the frame.
+pub struct Compositor {
+    /// One per blend mode, indexed by `BlendMode as usize`.
+    pipelines: Vec<wgpu::RenderPipeline>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    format: wgpu::TextureFormat,
+    size: tauri::PhysicalSize<u32>,
+    /// Sorted bottom to top.
+    layers: Vec<Layer>,
+}
+
+impl Compositor {
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        size: tauri::PhysicalSize<u32>,
+        pipelines: &Pipelines,
+    ) -> Self {
+        let shader = pipelines.shader(device, "Layer Shader", include_str!("shaders/layers.wgsl"));
+
+        let bind_group_layout = pipelines.bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Layer Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let layout = pipelines.pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Layer Pipeline Layout"),
+                bind_group_layouts: &[Some(&bind_group_layout)],
+                immediate_size: 0,
+            },
+        );
+
+        let blend_pipelines = BlendMode::ALL
+            .iter()
+            .map(|mode| {
+                pipelines.render_pipeline(
+                    device,
+                    &wgpu::RenderPipelineDescriptor {
+                        label: Some(&format!("Layer Pipeline ({:?})", mode)),
+                        layout: Some(&layout),
+                        vertex: wgpu::VertexState {
+                            module: &shader,
+                            entry_point: Some("vs_main"),
+                            buffers: &[],
+                            compilation_options: Default::default(),
+                        },
+                        fragment: Some(wgpu::FragmentState {
+                            module: &shader,
+                            entry_point: Some("fs_main"),
+                            targets: &[Some(wgpu::ColorTargetState {
+                                format: color_format,
+                                blend: Some(mode.blend_state()),
+                                write_mask: wgpu::ColorWrites::ALL,
+                            })],
+                            compilation_options: Default::default(),
+                        }),
+                        primitive: wgpu::PrimitiveState::default(),
+                        depth_stencil: Some(clip::clipped_depth_stencil()),
+                        multisample: wgpu::MultisampleState::default(),
+                        multiview_mask: None,
+                        cache: pipelines.cache(),
+                    },
+                )
+            })
+            .collect();
+
+        Compositor {
+            pipelines: blend_pipelines,
+            bind_group_layout,
+            format: color_format,
+            size,
+            layers: Vec::new(),
+        }
+    }
+
+    pub fn set_layers(
+        &mut self,
+        device: &wgpu::Device,
+        pipelines: &Pipelines,
+        fill: &FillPipeline,
+        images: &ImagePipeline,
+        assets: &Assets,
+        descriptors: Vec<LayerDescriptor>,
+    ) -> Result<(), String> {
+        let mut ids = std::collections::HashSet::new();
+        if let Some(duplicate) = descriptors.iter().find(|d| !ids.insert(d.id.as_str())) {
+            return Err(format!("duplicate layer id '{}'", duplicate.id));
+        }
+
+        let mut layers = Vec::with_capacity(descriptors.len());
+        for descriptor in descriptors {
+            let fill = descriptor
+                .color
+                .map(|[r, g, b, a]| fill.create_color(device, wgpu::Color { r, g, b, a }));
+            let image = match &descriptor.image {
+                Some(id) => {
+                    let asset = assets
+                        .get(id)
+                        .ok_or_else(|| format!("no asset '{}' has been loaded", id))?;
+                    let image = images
+                        .create_image(device, asset)
+                        .map_err(|e| format!("layer '{}': {}", descriptor.id, e))?;
+                    Some(image)
+                }
+                None => None,
+            };
+            let shader = match &descriptor.shader {
+                Some(id) => {
+                    let asset = assets
+                        .get(id)
+                        .ok_or_else(|| format!("no asset '{}' has been loaded", id))?;
+                    let shader = IsfShader::new(device, pipelines, self.format, asset)
+                        .map_err(|e| format!("layer '{}': {}", descriptor.id, e))?;
+                    Some(shader)
+                }
+                None => None,
+            };
+            let target = self.create_target(device);
+            let uniforms = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Layer Uniforms"),
+                contents: bytemuck::cast_slice(&uniforms(descriptor.opacity)),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+            let bind_group = self.create_bind_group(device, &target, &uniforms);
+            layers.push(Layer {
+                descriptor,
+                fill,
+                image,
+                shader,
+                target,
+                uniforms,
+                bind_group,
+            });
+        }
+        layers.sort_by_key(|layer| layer.descriptor.z_index);
+        self.layers = layers;
+        Ok(())
+    }
+
+    pub fn update_layer(
+        &mut self,
+        queue: &wgpu::Queue,
+        id: &str,
+        update: LayerUpdate,
+    ) -> Result<(), String> {
+        let layer = self
+            .layers
+            .iter_mut()
+            .find(|layer| layer.descriptor.id == id)
+            .ok_or_else(|| format!("no layer '{}'", id))?;
+        let descriptor = &mut layer.descriptor;
+        if let Some(visible) = update.visible {
+            descriptor.visible = visible;
+        }
+        if let Some(blend) = update.blend {
+            descriptor.blend = blend;
+        }
+        if let Some(opacity) = update.opacity {
+            descriptor.opacity = opacity;
+            queue.write_buffer(&layer.uniforms, 0, bytemuck::cast_slice(&uniforms(opacity)));
+        }
+        if let Some(z_index) = update.z_index {
+            descriptor.z_index = z_index;
+            self.layers.sort_by_key(|layer| layer.descriptor.z_index);
+        }
+        Ok(())
+    }
+
+    pub fn layer(&self, id: &str) -> Option<&LayerDescriptor> {
+        self.layers
+            .iter()
+            .map(|layer| &layer.descriptor)
+            .find(|descriptor| descriptor.id == id)
+    }
+
+    /// Set an input of the shader on layer `id`.
+    pub fn set_uniform(
+        &mut self,
+        device: &wgpu::Device,
+        assets: &Assets,
+        id: &str,
+        name: &str,
+        value: Value,
+    ) -> Result<(), String> {
+        self.shader_mut(id)?.set_input(device, assets, name, value)
+    }
+
+    /// The inputs of the shader on layer `id`, and what they're set to.
+    pub fn shader_inputs(&self, id: &str) -> Result<Vec<ShaderInput>, String> {
+        let layer = self
+            .layers
+            .iter()
+            .find(|layer| layer.descriptor.id == id)
+            .ok_or_else(|| format!("no layer '{}'", id))?;
+        let shader = layer
+            .shader
+            .as_ref()
+            .ok_or_else(|| format!("layer '{}' has no shader", id))?;
+        Ok(shader.inputs())
+    }
+
+    /// Update the built-in uniforms of the layers' shaders, like the time.
+    pub fn prepare_shaders(&mut self, uploads: &mut Uploads) {
+        let size = self.size;
+        for layer in &mut self.layers {
+            if let Some(shader) = &mut layer.shader {
+                shader.prepare(uploads, size);
+            }
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, size: tauri::PhysicalSize<u32>) {
+        self.size = size;
+        for i in 0..self.layers.len() {
+            let target = self.create_target(device);
+            let bind_group = self.create_bind_group(device, &target, &self.layers[i].uniforms);
+            let layer = &mut self.layers[i];
+            layer.target = target;
+            layer.bind_group = bind_group;
+        }
+    }
+
+    /// Rebind layer images whose assets have been reloaded.
+    pub fn refresh_images(
+        &mut self,
+        device: &wgpu::Device,
+        images: &ImagePipeline,
+        assets: &Assets,
+    ) {
+        for layer in &mut self.layers {
+            if let Some(shader) = &mut layer.shader {
+                shader.refresh_images(device, assets);
+            }
+            let (id, image) = match (&layer.descriptor.image, &mut layer.image) {
+                (Some(id), Some(image)) => (id, image),
+                _ => continue,
+            };
+            let asset = match assets.get(id) {
+                Some(asset) if !image.is_bound_to(&asset) => asset,
+                _ => continue,
+            };
+            match images.create_image(device, asset) {
+                Ok(reloaded) => *image = reloaded,
+                Err(e) => println!(
+                    "Failed to reload image for layer '{}': {}",
+                    layer.descriptor.id, e
+                ),
+            }
+        }
+    }
+
+    /// Draw each visible layer's content into its target, returning the commands to submit
+    /// ahead of the frame's. `depth_stencil` is borrowed for the content pipelines, which
+    /// are all clipped, and left cleared so nothing is. Scenes with many layers are encoded
+    /// on several of rayon's threads at once, a command buffer for each, in order.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        depth_stencil: &wgpu::TextureView,
+        fill: &FillPipeline,
+        images: &ImagePipeline,
+    ) -> Vec<wgpu::CommandBuffer> {
+        let visible: Vec<&Layer> = self
+            .layers
+            .iter()
+            .filter(|layer| layer.descriptor.visible)
+            .collect();
+        if visible.is_empty() {
+            return Vec::new();
+        }
+        let encode = |layers: &[&Layer]| {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Layer Encoder"),
+            });
+            for layer in layers {
+                render_layer(&mut encoder, layer, depth_stencil, fill, images);
+            }
+            encoder.finish()
+        };
+
+        let workers = if visible.len() < PARALLEL_LAYERS {
+            1
+        } else {
+            rayon::current_num_threads().min(MAX_ENCODERS)
+        };
+        if workers == 1 {
+            return vec![encode(&visible)];
+        }
+        // The pool's threads are started once and kept, so a frame only pays for handing
+        // them the work
+        visible
+            .par_chunks(visible.len().div_ceil(workers))
+            .map(encode)
+            .collect()
+    }
+
+    /// Blend the rendered layers into the frame, bottom to top. Without an `opaque` backdrop,
+    /// multiply layers are drawn as normal ones rather than darkening to nothing where the
+    /// backdrop is clear.
+    pub fn composite<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, opaque: bool) {
+        for layer in self.layers.iter().filter(|layer| layer.descriptor.visible) {
+            let blend = match layer.descriptor.blend {
+                BlendMode::Multiply if !opaque => BlendMode::Normal,
+                blend => blend,
+            };
+            render_pass.set_pipeline(&self.pipelines[blend as usize]);
+            render_pass.set_stencil_reference(clip::STENCIL_REFERENCE);
+            render_pass.set_bind_group(0, &layer.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    /// The layers' targets and uniforms, along with their fills and shaders.
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        for layer in &self.layers {
+            usage.add_view(&layer.target);
+            usage.add_buffer(&layer.uniforms);
+            if let Some(fill) = &layer.fill {
+                fill.memory(usage);
+            }
+            if let Some(shader) = &layer.shader {
+                shader.memory(usage);
+            }
+        }
+    }
+
+    fn shader_mut(&mut self, id: &str) -> Result<&mut IsfShader, String> {
+        let layer = self
+            .layers
+            .iter_mut()
+            .find(|layer| layer.descriptor.id == id)
+            .ok_or_else(|| format!("no layer '{}'", id))?;
+        layer
+            .shader
+            .as_mut()
+            .ok_or_else(|| format!("layer '{}' has no shader", id))
+    }
+
+    fn create_target(&self, device: &wgpu::Device) -> wgpu::TextureView {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Layer Target"),
+            size: wgpu::Extent3d {
+                width: self.size.width.max(1),
+                height: self.size.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        target: &wgpu::TextureView,
+        uniforms: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Layer Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(target),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniforms.as_entire_binding(),
+                },
+            ],
+        })
+    }
+}
+
+/// The opacity, padded out to the 16 bytes uniform buffers are allocated in.
+fn uniforms(opacity: f32) -> [f32; 4] {
+    [opacity.clamp(0.0, 1.0), 0.0, 0.0, 0.0]
+}
+
+fn render_layer(
+    encoder: &mut wgpu::CommandEncoder,
+    layer: &Layer,
+    depth_stencil: &wgpu::TextureView,
+    fill: &FillPipeline,
+    images: &ImagePipeline,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Layer Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: &layer.target,
+            depth_slice: None,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: depth_stencil,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: wgpu::StoreOp::Discard,
+            }),
+            stencil_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(clip::clear_value(false)),
+                store: wgpu::StoreOp::Discard,
+            }),
+        }),
+        timestamp_writes: None,
+        occlusion_query_set: None,
+        multiview_mask: None,
+    });
+    if let Some(color) = &layer.fill {
+        fill.draw(&mut render_pass, color);
+    }
+    if let Some(image) = &layer.image {
+        images.draw(&mut render_pass, image);
+    }
+    if let Some(shader) = &layer.shader {
+        shader.draw(&mut render_pass);
+    }
+}
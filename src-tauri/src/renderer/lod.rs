@@ -0,0 +1,55 @@
+//! Picking levels of detail by how big things look on screen, shared by terrain chunks,
+//! model meshes and scatterplot tiers so they all switch the same way.
+
+/// Detail is dropped until its smallest features, like terrain cells, are at most this many
+/// logical pixels across on screen.
+pub const CELL_PIXELS: f32 = 8.0;
+/// How far past the size that picks a level, as a fraction of a level, something has to go
+/// before it changes level, so things near the switch don't pop back and forth.
+const HYSTERESIS: f32 = 0.25;
+
+/// The level to use out of `levels`, moving from `current` only once `ideal` is clearly
+/// outside it. `ideal` counts levels from 0, with each a step coarser than the last, and
+/// needn't be whole. With no levels to pick from, it's 0.
+pub fn pick(current: u32, ideal: f32, levels: u32) -> u32 {
+    if levels == 0 {
+        return 0;
+    }
+    let current = current.min(levels - 1);
+    let level = current as f32;
+    if ideal < level - HYSTERESIS || ideal > level + 1.0 + HYSTERESIS {
+        (ideal.max(0.0) as u32).min(levels - 1)
+    } else {
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stays_within_the_hysteresis_band() {
+        assert_eq!(pick(2, 1.8, 5), 2);
+        assert_eq!(pick(2, 3.2, 5), 2);
+    }
+
+    #[test]
+    fn moves_once_clearly_outside_the_band() {
+        assert_eq!(pick(2, 1.7, 5), 1);
+        assert_eq!(pick(2, 3.3, 5), 3);
+        assert_eq!(pick(0, 9.0, 5), 4);
+        assert_eq!(pick(4, -3.0, 5), 0);
+    }
+
+    #[test]
+    fn clamps_the_current_level_to_the_levels() {
+        assert_eq!(pick(7, 4.5, 5), 4);
+    }
+
+    #[test]
+    fn picks_0_without_levels() {
+        assert_eq!(pick(0, 2.0, 0), 0);
+        assert_eq!(pick(3, -1.0, 0), 0);
+    }
+}
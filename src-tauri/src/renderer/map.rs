@@ -0,0 +1,688 @@
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use super::clip;
+use super::navigation::Navigation;
+use super::pipelines::Pipelines;
+use super::upload::Uploads;
+use super::MemoryUsage;
+use crate::tiles::{TileFetcher, TileKey, TileScheme, TileSource};
+
+/// Roughly how much GPU memory cached tiles take, in bytes.
+const TILE_BUDGET: u32 = 64 * 1024 * 1024;
+/// The fewest tiles kept, however big they are.
+const MIN_CACHED_TILES: u32 = 16;
+/// How far north and south Web Mercator goes, which makes the map square.
+const MAX_LATITUDE: f64 = 85.051_128_78;
+/// The most tiles drawn at once.
+const MAX_VISIBLE_TILES: i64 = 1024;
+
+/// A basemap of raster tiles, drawn under the overlay's other content.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapDescriptor {
+    /// Where tiles come from, with `{z}`, `{x}` and `{y}` in it, such as
+    /// `https://tile.openstreetmap.org/{z}/{x}/{y}.png`.
+    pub url: String,
+    #[serde(default)]
+    pub scheme: TileScheme,
+    /// Pixels on a side of the server's tiles, which are drawn this many logical pixels
+    /// across at whole zooms.
+    #[serde(default = "default_tile_size")]
+    pub tile_size: u32,
+    #[serde(default)]
+    pub min_zoom: u32,
+    /// The most zoomed in tiles the server has. Zooming in further magnifies them.
+    #[serde(default = "default_max_zoom")]
+    pub max_zoom: u32,
+    /// Longitude and latitude in the overlay's middle at the default navigation.
+    #[serde(default)]
+    pub center: [f64; 2],
+    /// The zoom at the default navigation. Each zoom in doubles the map's size.
+    #[serde(default = "default_zoom")]
+    pub zoom: f64,
+}
+
+fn default_tile_size() -> u32 {
+    256
+}
+
+fn default_max_zoom() -> u32 {
+    19
+}
+
+fn default_zoom() -> f64 {
+    2.0
+}
+
+/// What part of the map is showing, for placing markers over it. Emitted as
+/// `map://viewport` whenever it changes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapViewport {
+    /// Longitude and latitude in the overlay's middle.
+    pub center: [f64; 2],
+    pub zoom: f64,
+    /// Degrees clockwise that north is turned from up.
+    pub bearing: f64,
+    /// The westernmost, southernmost, easternmost and northernmost coordinates showing.
+    pub bounds: [f64; 4],
+}
+
+/// Emitted as `map://viewport`.
+#[derive(Debug, Clone, Serialize)]
+pub struct MapViewportPayload {
+    pub overlay: String,
+    #[serde(flatten)]
+    pub viewport: MapViewport,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct TileInstance {
+    origin: [f32; 2],
+    x_axis: [f32; 2],
+    y_axis: [f32; 2],
+    uv_offset: [f32; 2],
+    uv_scale: f32,
+    layer: u32,
+}
+
+/// Where the map's tiles are on the overlay. The map is laid out with its zoom and center
+/// from the descriptor, and then moved by the overlay's navigation.
+struct MapView {
+    navigation: Navigation,
+    /// The descriptor's center, from 0 to 1 across the whole map.
+    center: [f64; 2],
+    /// Logical pixels across the whole map before navigation.
+    world_size: f64,
+    half_size: [f64; 2],
+}
+
+impl MapView {
+    /// Where a point from 0 to 1 across the map lands on the overlay.
+    fn to_screen(&self, point: [f64; 2]) -> [f64; 2] {
+        let x = self.half_size[0] + (point[0] - self.center[0]) * self.world_size;
+        let y = self.half_size[1] + (point[1] - self.center[1]) * self.world_size;
+        let (sin, cos) = self.navigation.rotation.sin_cos();
+        let zoom = self.navigation.zoom;
+        [
+            self.navigation.offset[0] + zoom * (x * cos - y * sin),
+            self.navigation.offset[1] + zoom * (x * sin + y * cos),
+        ]
+    }
+
+    fn to_map(&self, screen: [f64; 2]) -> [f64; 2] {
+        let x = (screen[0] - self.navigation.offset[0]) / self.navigation.zoom;
+        let y = (screen[1] - self.navigation.offset[1]) / self.navigation.zoom;
+        let (sin, cos) = self.navigation.rotation.sin_cos();
+        [
+            self.center[0] + (x * cos + y * sin - self.half_size[0]) / self.world_size,
+            self.center[1] + (-x * sin + y * cos - self.half_size[1]) / self.world_size,
+        ]
+    }
+
+    fn zoom(&self, tile_size: u32) -> f64 {
+        (self.world_size * self.navigation.zoom / tile_size as f64).log2()
+    }
+}
+
+struct Slot {
+    key: TileKey,
+    /// The last frame the tile was drawn in.
+    used: u64,
+}
+
+struct Map {
+    descriptor: MapDescriptor,
+    fetcher: TileFetcher,
+    /// A layer for each cached tile.
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    slots: Vec<Option<Slot>>,
+    cached: HashMap<TileKey, usize>,
+    /// Tiles that couldn't be fetched, which aren't asked for again.
+    failed: HashSet<TileKey>,
+    frame: u64,
+    viewport: Option<MapViewport>,
+    emitted: Option<MapViewport>,
+}
+
+impl Map {
+    /// Put a fetched tile in the least recently drawn slot, unless every slot was showing
+    /// in the last frame.
+    fn insert(&mut self, queue: &wgpu::Queue, key: TileKey, tile: &image::RgbaImage) {
+        let slot = match self.slots.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None => {
+                let oldest = self
+                    .slots
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, slot)| slot.as_ref().map(|slot| (index, slot)))
+                    .filter(|(_, slot)| slot.used + 1 < self.frame)
+                    .min_by_key(|(_, slot)| slot.used);
+                match oldest {
+                    Some((index, slot)) => {
+                        self.cached.remove(&slot.key);
+                        index
+                    }
+                    None => return,
+                }
+            }
+        };
+        let size = self.descriptor.tile_size;
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: slot as u32,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            tile,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size),
+                rows_per_image: Some(size),
+            },
+            wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.slots[slot] = Some(Slot {
+            key,
+            used: self.frame,
+        });
+        self.cached.insert(key, slot);
+    }
+
+    /// The cached tile to draw for `key`: the tile itself, or the closest zoomed out tile
+    /// that covers it. Also returns how many zooms out that is.
+    fn cached_cover(&mut self, key: TileKey) -> Option<(usize, u32)> {
+        let min_zoom = self.descriptor.min_zoom.min(key.zoom);
+        for levels in 0..=(key.zoom - min_zoom) {
+            let cover = TileKey {
+                zoom: key.zoom - levels,
+                x: key.x >> levels,
+                y: key.y >> levels,
+            };
+            if let Some(&slot) = self.cached.get(&cover) {
+                if let Some(slot) = &mut self.slots[slot] {
+                    slot.used = self.frame;
+                }
+                return Some((slot, levels));
+            }
+        }
+        None
+    }
+}
+
+/// A slippy map, drawn from raster tiles that are fetched as they come into view and
+/// cached on disk and on the GPU. The overlay's navigation pans, zooms and rotates it.
+pub struct MapLayer {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniforms: wgpu::Buffer,
+    instances: Option<(wgpu::Buffer, wgpu::BufferAddress)>,
+    instance_count: u32,
+    map: Option<Map>,
+}
+
+impl MapLayer {
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        pipelines: &Pipelines,
+    ) -> Self {
+        let shader = pipelines.shader(device, "Map Shader", include_str!("shaders/map.wgsl"));
+
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Map Uniforms"),
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = pipelines.bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Map Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let layout = pipelines.pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Map Pipeline Layout"),
+                bind_group_layouts: &[Some(&bind_group_layout)],
+                immediate_size: 0,
+            },
+        );
+
+        let pipeline = pipelines.render_pipeline(
+            device,
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Map Pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[Some(wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<TileInstance>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &wgpu::vertex_attr_array![
+                            0 => Float32x2,
+                            1 => Float32x2,
+                            2 => Float32x2,
+                            3 => Float32x2,
+                            4 => Float32,
+                            5 => Uint32
+                        ],
+                    })],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: color_format,
+                        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                depth_stencil: Some(clip::clipped_depth_stencil()),
+                multisample: wgpu::MultisampleState::default(),
+                multiview_mask: None,
+                cache: pipelines.cache(),
+            },
+        );
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Map Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        MapLayer {
+            pipeline,
+            bind_group_layout,
+            sampler,
+            uniforms,
+            instances: None,
+            instance_count: 0,
+            map: None,
+        }
+    }
+
+    /// Show the map from `descriptor`, replacing any there was. Tiles are cached in
+    /// `cache_dir` if there is one.
+    pub fn load(
+        &mut self,
+        device: &wgpu::Device,
+        descriptor: MapDescriptor,
+        cache_dir: Option<PathBuf>,
+    ) -> Result<(), String> {
+        if !["{z}", "{x}", "{y}"]
+            .iter()
+            .all(|part| descriptor.url.contains(part))
+        {
+            return Err("a map's url needs {z}, {x} and {y} in it".into());
+        }
+        let limits = device.limits();
+        if descriptor.tile_size == 0 || descriptor.tile_size > limits.max_texture_dimension_2d {
+            return Err(format!(
+                "map tiles can be at most {} pixels on a side",
+                limits.max_texture_dimension_2d
+            ));
+        }
+        // Tiles are numbered in u32s, with room to shift them
+        if descriptor.min_zoom > descriptor.max_zoom || descriptor.max_zoom > 30 {
+            return Err("a map's zooms have to go from lower to higher, up to 30".into());
+        }
+        let [longitude, latitude] = descriptor.center;
+        if !longitude.is_finite() || !latitude.is_finite() || !descriptor.zoom.is_finite() {
+            return Err("a map's center and zoom have to be numbers".into());
+        }
+
+        let size = descriptor.tile_size;
+        let tiles = (TILE_BUDGET / (size * size * 4).max(1))
+            .max(MIN_CACHED_TILES)
+            .min(limits.max_texture_array_layers);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Map Tiles"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: tiles,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Map Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniforms.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        let fetcher = TileFetcher::new(
+            TileSource {
+                url: descriptor.url.clone(),
+                scheme: descriptor.scheme,
+                tile_size: size,
+            },
+            cache_dir,
+        );
+        self.map = Some(Map {
+            descriptor,
+            fetcher,
+            texture,
+            bind_group,
+            slots: (0..tiles).map(|_| None).collect(),
+            cached: HashMap::new(),
+            failed: HashSet::new(),
+            frame: 0,
+            viewport: None,
+            emitted: None,
+        });
+        self.instance_count = 0;
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.map = None;
+        self.instance_count = 0;
+    }
+
+    /// What part of the map was showing in the last frame, if there's a map.
+    pub fn viewport(&self) -> Option<MapViewport> {
+        self.map.as_ref().and_then(|map| map.viewport)
+    }
+
+    /// The viewport, if it's changed since this was last called.
+    pub fn take_viewport_change(&mut self) -> Option<MapViewport> {
+        let map = self.map.as_mut()?;
+        if map.viewport == map.emitted {
+            return None;
+        }
+        map.emitted = map.viewport;
+        map.viewport
+    }
+
+    /// The navigation that shows `center` in the overlay's middle at `zoom`, keeping
+    /// `navigation`'s rotation.
+    pub fn navigation_to(
+        &self,
+        center: [f64; 2],
+        zoom: f64,
+        navigation: &Navigation,
+        logical_size: [f32; 2],
+    ) -> Result<Navigation, String> {
+        let map = self.map.as_ref().ok_or("there's no map")?;
+        if !center[0].is_finite() || !center[1].is_finite() || !zoom.is_finite() {
+            return Err("a map's center and zoom have to be numbers".into());
+        }
+        let mut navigation = Navigation {
+            offset: [0.0, 0.0],
+            zoom: 2f64.powf(zoom - map.descriptor.zoom),
+            rotation: navigation.rotation,
+        };
+        let view = map_view(&map.descriptor, navigation, logical_size);
+        let [x, y] = view.to_screen(to_mercator(center));
+        navigation.offset = [view.half_size[0] - x, view.half_size[1] - y];
+        Ok(navigation)
+    }
+
+    /// Take in fetched tiles, ask for the ones that have come into view and upload where
+    /// the visible ones are.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        uploads: &mut Uploads,
+        navigation: &Navigation,
+        logical_size: [f32; 2],
+    ) {
+        let map = match &mut self.map {
+            Some(map) => map,
+            None => return,
+        };
+        map.frame += 1;
+        for (key, tile) in map.fetcher.take_fetched() {
+            match tile {
+                Ok(tile) => map.insert(queue, key, &tile),
+                Err(e) => {
+                    println!("Failed to load map tile: {}", e);
+                    map.failed.insert(key);
+                }
+            }
+        }
+        uploads.write(&self.uniforms, 0, bytemuck::cast_slice(&logical_size));
+
+        let descriptor = &map.descriptor;
+        let view = map_view(descriptor, *navigation, logical_size);
+        let zoom = view.zoom(descriptor.tile_size);
+        let [width, height] = [logical_size[0] as f64, logical_size[1] as f64];
+        let corners = [[0.0, 0.0], [width, 0.0], [0.0, height], [width, height]]
+            .iter()
+            .map(|&corner| view.to_map(corner))
+            .collect::<Vec<_>>();
+        let [center_x, center_y] = view.to_map([width / 2.0, height / 2.0]);
+        let bound = |axis: usize, pick: fn(f64, f64) -> f64| {
+            corners
+                .iter()
+                .map(|corner| corner[axis])
+                .fold(corners[0][axis], pick)
+        };
+        let [west, east] = [bound(0, f64::min), bound(0, f64::max)];
+        let [north, south] = [bound(1, f64::min), bound(1, f64::max)];
+        let [west_longitude, south_latitude] = from_mercator([west, south.min(1.0)]);
+        let [east_longitude, north_latitude] = from_mercator([east, north.max(0.0)]);
+        map.viewport = Some(MapViewport {
+            center: from_mercator([center_x, center_y]),
+            zoom,
+            bearing: navigation.rotation.to_degrees(),
+            bounds: [
+                west_longitude,
+                south_latitude,
+                east_longitude,
+                north_latitude,
+            ],
+        });
+
+        // Tiles from the zoom closest to the view's, so they're drawn at about their size
+        let tile_zoom =
+            (zoom.round().max(0.0) as u32).clamp(descriptor.min_zoom, descriptor.max_zoom);
+        let tiles = (1u64 << tile_zoom) as f64;
+        let first = [
+            (west * tiles).floor() as i64,
+            (north * tiles).floor().max(0.0) as i64,
+        ];
+        let last = [
+            (east * tiles).floor() as i64,
+            ((south * tiles).floor() as i64).min(tiles as i64 - 1),
+        ];
+        // Zoomed far out past the tiles there are, there'd be too many to draw
+        let count = (last[0] - first[0] + 1) * (last[1] - first[1] + 1);
+        if count > MAX_VISIBLE_TILES {
+            self.instance_count = 0;
+            map.fetcher.want(Vec::new());
+            return;
+        }
+
+        let mut instances = Vec::new();
+        let mut wanted = Vec::new();
+        for row in first[1]..=last[1] {
+            for column in first[0]..=last[0] {
+                // Columns repeat east and west of the map
+                let key = TileKey {
+                    zoom: tile_zoom,
+                    x: column.rem_euclid(tiles as i64) as u32,
+                    y: row as u32,
+                };
+                let cover = map.cached_cover(key);
+                if cover.is_none_or(|(_, levels)| levels > 0) && !map.failed.contains(&key) {
+                    let distance = (column as f64 + 0.5 - center_x * tiles).powi(2)
+                        + (row as f64 + 0.5 - center_y * tiles).powi(2);
+                    wanted.push((distance, key));
+                }
+                let (slot, levels) = match cover {
+                    Some(cover) => cover,
+                    None => continue,
+                };
+                let corner = |x: f64, y: f64| view.to_screen([x / tiles, y / tiles]);
+                let origin = corner(column as f64, row as f64);
+                let right = corner(column as f64 + 1.0, row as f64);
+                let below = corner(column as f64, row as f64 + 1.0);
+                let scale = 1.0 / (1u64 << levels) as f64;
+                let within = |index: u32| (index % (1 << levels)) as f64 * scale;
+                instances.push(TileInstance {
+                    origin: [origin[0] as f32, origin[1] as f32],
+                    x_axis: [(right[0] - origin[0]) as f32, (right[1] - origin[1]) as f32],
+                    y_axis: [(below[0] - origin[0]) as f32, (below[1] - origin[1]) as f32],
+                    uv_offset: [within(key.x) as f32, within(key.y) as f32],
+                    uv_scale: scale as f32,
+                    layer: slot as u32,
+                });
+            }
+        }
+        wanted.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        map.fetcher
+            .want(wanted.into_iter().map(|(_, key)| key).collect());
+
+        let needed = (instances.len() * std::mem::size_of::<TileInstance>()) as wgpu::BufferAddress;
+        if self
+            .instances
+            .as_ref()
+            .is_none_or(|(_, capacity)| *capacity < needed)
+        {
+            let capacity = needed.next_power_of_two();
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Map Instances"),
+                size: capacity,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            self.instances = Some((buffer, capacity));
+        }
+        if let Some((buffer, _)) = &self.instances {
+            uploads.write(buffer, 0, bytemuck::cast_slice(&instances));
+        }
+        self.instance_count = instances.len() as u32;
+    }
+
+    /// The uniforms and instances, and the map's tile cache.
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        usage.add_buffer(&self.uniforms);
+        if let Some((instances, _)) = &self.instances {
+            usage.add_buffer(instances);
+        }
+        if let Some(map) = &self.map {
+            usage.add_texture(&map.texture);
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let map = match &self.map {
+            Some(map) => map,
+            None => return,
+        };
+        if let Some((buffer, _)) = self.instances.as_ref().filter(|_| self.instance_count > 0) {
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_stencil_reference(clip::STENCIL_REFERENCE);
+            render_pass.set_bind_group(0, &map.bind_group, &[]);
+            render_pass.set_vertex_buffer(0, buffer.slice(..));
+            render_pass.draw(0..4, 0..self.instance_count);
+        }
+    }
+}
+
+fn map_view(descriptor: &MapDescriptor, navigation: Navigation, logical_size: [f32; 2]) -> MapView {
+    MapView {
+        navigation,
+        center: to_mercator(descriptor.center),
+        world_size: descriptor.tile_size as f64 * 2f64.powf(descriptor.zoom),
+        half_size: [logical_size[0] as f64 / 2.0, logical_size[1] as f64 / 2.0],
+    }
+}
+
+/// Longitude and latitude as Web Mercator, from 0 to 1 across the map and from north to
+/// south.
+fn to_mercator([longitude, latitude]: [f64; 2]) -> [f64; 2] {
+    let latitude = latitude.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    [
+        (longitude + 180.0) / 360.0,
+        (1.0 - (latitude.tan() + 1.0 / latitude.cos()).ln() / PI) / 2.0,
+    ]
+}
+
+fn from_mercator([x, y]: [f64; 2]) -> [f64; 2] {
+    [
+        x * 360.0 - 180.0,
+        (PI * (1.0 - 2.0 * y)).sinh().atan().to_degrees(),
+    ]
+}
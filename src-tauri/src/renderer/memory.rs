@@ -0,0 +1,84 @@
+//! GPU memory for `get_gpu_memory_usage`: the buffers and textures each part of a renderer,
+//! or the asset store, is holding on to, so leaks from reloading assets or recreating
+//! overlays show up as totals that keep growing.
+
+use serde::Serialize;
+
+/// How many buffers and textures something holds, and roughly how many bytes they take.
+/// Texture sizes are worked out from their format and extent, so padding and compression
+/// the driver adds aren't counted.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryUsage {
+    pub buffers: u64,
+    pub buffer_bytes: u64,
+    pub textures: u64,
+    pub texture_bytes: u64,
+}
+
+impl MemoryUsage {
+    /// What the device as a whole has allocated, as counted by wgpu. It's all zero on
+    /// backends that don't count allocations.
+    pub fn of_device(device: &wgpu::Device) -> Self {
+        let hal = device.get_internal_counters().hal;
+        let count = |value: isize| value.max(0) as u64;
+        MemoryUsage {
+            buffers: count(hal.buffers.read()),
+            buffer_bytes: count(hal.buffer_memory.read()),
+            textures: count(hal.textures.read()),
+            texture_bytes: count(hal.texture_memory.read()),
+        }
+    }
+
+    pub fn add_buffer(&mut self, buffer: &wgpu::Buffer) {
+        self.buffers += 1;
+        self.buffer_bytes += buffer.size();
+    }
+
+    pub fn add_texture(&mut self, texture: &wgpu::Texture) {
+        let format = texture.format();
+        let (block_width, block_height) = format.block_dimensions();
+        let block_bytes = format
+            .block_copy_size(None)
+            // Combined depth and stencil formats have no single block size
+            .unwrap_or(4) as u64;
+        let size = texture.size();
+        let dimension = texture.dimension();
+        let texels: u64 = (0..texture.mip_level_count())
+            .map(|level| {
+                let level = size.mip_level_size(level, dimension);
+                level.width.div_ceil(block_width) as u64
+                    * level.height.div_ceil(block_height) as u64
+                    * level.depth_or_array_layers as u64
+            })
+            .sum();
+        self.textures += 1;
+        self.texture_bytes += texels * block_bytes * texture.sample_count() as u64;
+    }
+
+    /// Add the texture a view is of, for things that only keep the view.
+    pub fn add_view(&mut self, view: &wgpu::TextureView) {
+        self.add_texture(view.texture());
+    }
+}
+
+impl std::ops::AddAssign for MemoryUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.buffers += other.buffers;
+        self.buffer_bytes += other.buffer_bytes;
+        self.textures += other.textures;
+        self.texture_bytes += other.texture_bytes;
+    }
+}
+
+/// What `get_gpu_memory_usage` returns.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GpuMemoryUsage {
+    /// What the overlay's renderer holds for itself.
+    pub overlay: MemoryUsage,
+    /// Loaded assets, and unloaded ones that are still in use. Every overlay shares them.
+    pub assets: MemoryUsage,
+    /// Everything allocated on the shared GPU.
+    pub device: MemoryUsage,
+}
@@ -0,0 +1,1136 @@
+mod blit;
+mod clip;
+mod context;
+mod fill;
+mod filters;
+mod graph;
+mod histogram;
+mod hud;
+mod image;
+mod ink;
+#[cfg(target_os = "macos")]
+mod iosurface;
+mod isf;
+mod layers;
+mod lod;
+mod map;
+mod memory;
+mod models;
+mod navigation;
+mod ndi;
+mod panes;
+mod path;
+mod physics;
+mod pipelines;
+mod sample;
+mod scatter;
+mod share;
+mod stats;
+mod subtitles;
+mod target;
+mod terrain;
+mod text;
+mod thread;
+mod upload;
+mod video;
+mod volume;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::assets::Assets;
+use crate::input::InputEvent;
+use crate::video::VideoFrame;
+
+pub use clip::ClipPath;
+pub use context::{GpuConfig, GpuContext, GpuInfo};
+pub use filters::{filter_texture, Filter};
+pub use graph::GraphDescriptor;
+pub use histogram::{texture_histogram, Histogram};
+pub use ink::InkBrush;
+pub use isf::ShaderInput;
+pub use layers::{BlendMode, LayerDescriptor, LayerUpdate};
+pub use map::{MapDescriptor, MapViewport, MapViewportPayload};
+pub use memory::{GpuMemoryUsage, MemoryUsage};
+pub use models::TerrainModel;
+pub use navigation::{Navigation, NavigationInput};
+pub use panes::PaneDescriptor;
+pub use physics::{BodyDescriptor, BodyState, DEFAULT_GRAVITY};
+pub use scatter::{ScatterBrush, ScatterDescriptor, ScatterSelection, DEFAULT_SELECTION_LIMIT};
+pub use stats::FrameStats;
+pub use subtitles::SubtitleStyle;
+pub use target::{Frame, Presentation, Presented};
+pub use terrain::{TerrainCamera, TerrainDescriptor};
+pub use thread::RenderHandle;
+pub use volume::{TransferPoint, VolumeCamera, VolumeDescriptor};
+
+/// Every render pass carries a combined depth/stencil attachment so that any
+/// pipeline can be clipped by the stencil mask (and later, depth tested).
+pub const DEPTH_STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+
+/// What overlays are filled with until they're given a clear color.
+pub const DEFAULT_CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.2,
+    b: 0.3,
+    a: 1.0,
+};
+
+pub struct WgpuState {
+    target: target::RenderTarget,
+    gpu: Arc<GpuContext>,
+    size: tauri::PhysicalSize<u32>,
+    /// Converts the logical pixels that input arrives in to the surface's physical ones.
+    scale_factor: f64,
+    clear_color: wgpu::Color,
+    fill: fill::FillPipeline,
+    background: fill::FillColor,
+    images: image::ImagePipeline,
+    clip: clip::ClipMask,
+    panes: Vec<panes::Pane>,
+    video: Option<video::VideoSurface>,
+    subtitles: subtitles::Subtitles,
+    layers: layers::Compositor,
+    /// Filters over everything that's drawn, used by the standard render graph.
+    output_filters: Vec<Filter>,
+    /// The graph the overlay was given, if it doesn't use the standard one.
+    graph_descriptor: Option<GraphDescriptor>,
+    graph: graph::RenderGraph,
+    blit: blit::Blit,
+    /// Where frames are drawn while they're shared with other apps.
+    share: Option<share::FrameShare>,
+    ndi: Option<ndi::NdiOutput>,
+    /// The asset generation that pane images were last bound at.
+    assets_generation: u64,
+    navigation: Navigation,
+    ink: ink::InkLayer,
+    physics: physics::PhysicsLayer,
+    map: map::MapLayer,
+    terrain: terrain::TerrainLayer,
+    volume: volume::VolumeLayer,
+    scatter: scatter::ScatterLayer,
+    hud: hud::Hud,
+    frame_timer: stats::FrameTimer,
+    /// Stages what [`WgpuState::prepare`] writes each frame.
+    uploads: upload::Uploads,
+    /// Where [`WgpuState::sample_pixel`] reads the last frame back to, made the first time
+    /// it's needed at each size.
+    sample_buffer: Option<target::FrameBuffer>,
+}
+
+impl WgpuState {
+    /// Render into `surface`, or offscreen for read back overlays that don't have one.
+    pub fn new(
+        gpu: Arc<GpuContext>,
+        surface: Option<wgpu::Surface<'static>>,
+        size: tauri::PhysicalSize<u32>,
+    ) -> Self {
+        let device = &gpu.device;
+        let pipelines = &gpu.pipelines;
+        let target = match surface {
+            Some(surface) => {
+                let capabilities = surface.get_capabilities(&gpu.adapter);
+                // Everything's drawn premultiplied, which is how overlays composite over
+                // what's behind them where the platform lets them choose
+                let alpha_mode = if capabilities
+                    .alpha_modes
+                    .contains(&wgpu::CompositeAlphaMode::PreMultiplied)
+                {
+                    wgpu::CompositeAlphaMode::PreMultiplied
+                } else {
+                    capabilities.alpha_modes[0]
+                };
+                // Copied from where it can be, so what was presented can be read back
+                let usage = wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | (capabilities.usages & wgpu::TextureUsages::COPY_SRC);
+                let config = wgpu::SurfaceConfiguration {
+                    usage,
+                    format: capabilities.formats[0],
+                    color_space: wgpu::SurfaceColorSpace::Auto,
+                    width: size.width,
+                    height: size.height,
+                    present_mode: wgpu::PresentMode::Fifo,
+                    desired_maximum_frame_latency: 2,
+                    alpha_mode,
+                    view_formats: Vec::new(),
+                };
+                surface.configure(device, &config);
+                target::RenderTarget::surface(device, surface, config)
+            }
+            None => target::RenderTarget::Readback(target::Readback::new(device, size)),
+        };
+
+        let clear_color = DEFAULT_CLEAR_COLOR;
+        let fill = fill::FillPipeline::new(device, target.format(), pipelines);
+        let background = fill.create_color(device, clear_color);
+        let images = image::ImagePipeline::new(device, target.format(), pipelines);
+        let layers = layers::Compositor::new(device, target.format(), size, pipelines);
+        let clip = clip::ClipMask::new(device, target.format(), pipelines);
+        let ink = ink::InkLayer::new(device, target.format(), pipelines);
+        let physics = physics::PhysicsLayer::new(device, target.format(), pipelines);
+        let map = map::MapLayer::new(device, target.format(), pipelines);
+        let terrain =
+            terrain::TerrainLayer::new(device, &gpu.queue, target.format(), size, pipelines);
+        let volume = volume::VolumeLayer::new(device, &gpu.queue, target.format(), pipelines);
+        let scatter = scatter::ScatterLayer::new(device, target.format(), pipelines);
+        let blit = blit::Blit::new(device, target.format(), pipelines);
+        let frame_timer = stats::FrameTimer::new(device, &gpu.queue, thread::FRAME_INTERVAL);
+        let uploads = upload::Uploads::new(device, &gpu.queue);
+        // Without filters, the standard graph doesn't look anything up
+        let graph = graph::RenderGraph::new(
+            &gpu,
+            &Assets::default(),
+            &blit,
+            &GraphDescriptor::standard(&[]),
+            target.format(),
+            size,
+        )
+        .expect("the standard render graph is valid");
+
+        println!("Created State w/ size {:?}", size);
+
+        Self {
+            target,
+            gpu,
+            size,
+            scale_factor: 1.0,
+            clear_color,
+            fill,
+            background,
+            images,
+            clip,
+            panes: Vec::new(),
+            video: None,
+            subtitles: subtitles::Subtitles::default(),
+            layers,
+            output_filters: Vec::new(),
+            graph_descriptor: None,
+            graph,
+            blit,
+            share: None,
+            ndi: None,
+            assets_generation: 0,
+            navigation: Navigation::default(),
+            ink,
+            physics,
+            map,
+            terrain,
+            volume,
+            scatter,
+            hud: hud::Hud::default(),
+            frame_timer,
+            uploads,
+            sample_buffer: None,
+        }
+    }
+
+    pub fn resize(&mut self, new_size: tauri::PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.size = new_size;
+            self.target.resize(&self.gpu.device, new_size);
+            self.sample_buffer = None;
+            self.layers.resize(&self.gpu.device, new_size);
+            self.terrain
+                .resize(&self.gpu.device, &self.images, new_size);
+            self.graph
+                .resize(&self.gpu, &self.blit, self.target.format(), new_size);
+            if let Some(share) = &mut self.share {
+                if let Err(e) = share.resize(&self.gpu, &self.blit, self.target.format(), new_size)
+                {
+                    println!("Failed to resize shared frames, so stopped sharing: {}", e);
+                    self.share = None;
+                }
+            }
+            if let Some(ndi) = &mut self.ndi {
+                ndi.resize(&self.gpu, &self.blit, self.target.format(), new_size);
+            }
+            self.clip.invalidate();
+        }
+    }
+
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+    }
+
+    /// Set the color behind everything else. Colors are straight (not premultiplied) alpha;
+    /// a transparent background lets the web page show through where nothing is drawn.
+    pub fn set_clear_color(&mut self, color: wgpu::Color) {
+        self.clear_color = color;
+        self.background.set(&self.gpu.queue, color);
+    }
+
+    pub fn clear_color(&self) -> wgpu::Color {
+        self.clear_color
+    }
+
+    /// Confine all subsequent drawing to the given path, or remove the clip with `None`.
+    pub fn set_clip_path(&mut self, path: Option<ClipPath>) -> Result<(), String> {
+        let contours = match path {
+            Some(path) => Some(path.contours()?),
+            None => None,
+        };
+        self.clip.set_contours(contours);
+        Ok(())
+    }
+
+    /// Partition the surface into panes, each with its own viewport, scissor and background.
+    /// An empty list goes back to drawing over the whole surface. Pane images come from
+    /// `assets`.
+    pub fn set_panes(
+        &mut self,
+        descriptors: Vec<PaneDescriptor>,
+        assets: &Assets,
+    ) -> Result<(), String> {
+        let mut ids = std::collections::HashSet::new();
+        if let Some(duplicate) = descriptors.iter().find(|d| !ids.insert(d.id.as_str())) {
+            return Err(format!("duplicate pane id '{}'", duplicate.id));
+        }
+
+        self.panes = descriptors
+            .into_iter()
+            .map(|descriptor| {
+                panes::Pane::new(
+                    &self.gpu.device,
+                    &self.fill,
+                    &self.images,
+                    assets,
+                    descriptor,
+                )
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    /// Replace the overlay's layers. An empty list removes them all. Multiply layers are
+    /// refused unless the background is opaque.
+    pub fn set_layers(
+        &mut self,
+        descriptors: Vec<LayerDescriptor>,
+        assets: &Assets,
+    ) -> Result<(), String> {
+        if let Some(layer) = descriptors
+            .iter()
+            .find(|descriptor| descriptor.blend == BlendMode::Multiply)
+        {
+            self.check_multiply(&layer.id)?;
+        }
+        self.layers.set_layers(
+            &self.gpu.device,
+            &self.gpu.pipelines,
+            &self.fill,
+            &self.images,
+            assets,
+            descriptors,
+        )
+    }
+
+    pub fn update_layer(&mut self, id: &str, update: LayerUpdate) -> Result<(), String> {
+        if update.blend == Some(BlendMode::Multiply) {
+            self.check_multiply(id)?;
+        }
+        self.layers.update_layer(&self.gpu.queue, id, update)
+    }
+
+    /// Whether everything the layers are composited over is opaque: the background has no
+    /// transparency and no clip leaves anything outside it clear.
+    fn backdrop_opaque(&self) -> bool {
+        self.clear_color.a >= 1.0 && !self.clip.is_active()
+    }
+
+    fn check_multiply(&self, id: &str) -> Result<(), String> {
+        if self.backdrop_opaque() {
+            Ok(())
+        } else {
+            Err(format!(
+                "layer '{}': multiply needs an opaque background, and this overlay's is transparent",
+                id
+            ))
+        }
+    }
+
+    pub fn layer(&self, id: &str) -> Option<&LayerDescriptor> {
+        self.layers.layer(id)
+    }
+
+    pub fn set_uniform(
+        &mut self,
+        layer: &str,
+        name: &str,
+        value: serde_json::Value,
+        assets: &Assets,
+    ) -> Result<(), String> {
+        self.layers
+            .set_uniform(&self.gpu.device, assets, layer, name, value)
+    }
+
+    pub fn shader_inputs(&self, layer: &str) -> Result<Vec<ShaderInput>, String> {
+        self.layers.shader_inputs(layer)
+    }
+
+    /// Show `frame` as the overlay's video, until the next one.
+    pub fn set_video_frame(&mut self, frame: VideoFrame) {
+        let size = [frame.width, frame.height];
+        if !self.video.as_ref().is_some_and(|video| video.fits(size)) {
+            self.video = Some(video::VideoSurface::new(
+                &self.gpu.device,
+                &self.images,
+                size,
+            ));
+        }
+        if let Some(video) = &mut self.video {
+            video.upload(&self.gpu.device, &self.gpu.queue, &self.gpu.video, frame);
+        }
+    }
+
+    pub fn clear_video(&mut self) {
+        self.video = None;
+        self.subtitles.set_text(None);
+    }
+
+    /// Show `text` over the bottom of the video, or nothing with `None`.
+    pub fn set_subtitle(&mut self, text: Option<String>) {
+        self.subtitles.set_text(text);
+    }
+
+    pub fn set_subtitle_style(
+        &mut self,
+        style: SubtitleStyle,
+        assets: &Assets,
+    ) -> Result<(), String> {
+        self.subtitles.set_style(style, assets)
+    }
+
+    /// Run `filters` over everything the overlay draws, in order, or stop filtering with
+    /// an empty list. A custom render graph has filter passes of its own instead, and these
+    /// wait until it's removed.
+    pub fn set_output_filters(
+        &mut self,
+        filters: Vec<Filter>,
+        assets: &Assets,
+    ) -> Result<(), String> {
+        let descriptor = GraphDescriptor::standard(&filters);
+        if self.graph_descriptor.is_none() {
+            self.graph = self.compile_graph(&descriptor, assets)?;
+        } else {
+            // Still check them, so they don't fail later when the graph is removed
+            self.compile_graph(&descriptor, assets)?;
+        }
+        self.output_filters = filters;
+        Ok(())
+    }
+
+    /// Draw frames with `graph`'s passes, or go back to the standard graph with `None`.
+    pub fn set_render_graph(
+        &mut self,
+        graph: Option<GraphDescriptor>,
+        assets: &Assets,
+    ) -> Result<(), String> {
+        let standard;
+        let descriptor = match &graph {
+            Some(graph) => graph,
+            None => {
+                standard = GraphDescriptor::standard(&self.output_filters);
+                &standard
+            }
+        };
+        self.graph = self.compile_graph(descriptor, assets)?;
+        self.graph_descriptor = graph;
+        Ok(())
+    }
+
+    /// Publish frames to other apps as `name`, a Syphon server on macOS or a Spout sender on
+    /// Windows, or stop with `None`.
+    pub fn set_frame_sharing(&mut self, name: Option<String>) -> Result<(), String> {
+        if self.share.as_ref().map(|share| share.name()) == name.as_deref() {
+            return Ok(());
+        }
+        // Stop first, so a new sender can take the same name
+        self.share = None;
+        if let Some(name) = name {
+            self.share = Some(share::FrameShare::new(
+                &self.gpu,
+                &self.blit,
+                name,
+                self.target.format(),
+                self.size,
+            )?);
+        }
+        Ok(())
+    }
+
+    /// The name frames are being shared as.
+    pub fn frame_sharing(&self) -> Option<String> {
+        self.share.as_ref().map(|share| share.name().to_string())
+    }
+
+    /// Send frames over the network as the NDI source `name`, or stop with `None`.
+    pub fn set_ndi_output(&mut self, name: Option<String>) -> Result<(), String> {
+        if self.ndi.as_ref().map(|ndi| ndi.name()) == name.as_deref() {
+            return Ok(());
+        }
+        self.ndi = None;
+        if let Some(name) = name {
+            let readback = matches!(self.target, target::RenderTarget::Readback(_));
+            self.ndi = Some(ndi::NdiOutput::new(
+                &self.gpu,
+                &self.blit,
+                name,
+                self.target.format(),
+                self.size,
+                readback,
+            )?);
+        }
+        Ok(())
+    }
+
+    /// The name of the NDI source frames are being sent as.
+    pub fn ndi_output(&self) -> Option<String> {
+        self.ndi.as_ref().map(|ndi| ndi.name().to_string())
+    }
+
+    fn compile_graph(
+        &self,
+        descriptor: &GraphDescriptor,
+        assets: &Assets,
+    ) -> Result<graph::RenderGraph, String> {
+        graph::RenderGraph::new(
+            &self.gpu,
+            assets,
+            &self.blit,
+            descriptor,
+            self.target.format(),
+            self.size,
+        )
+    }
+
+    /// Swap reloaded assets in for the ones being drawn.
+    pub fn refresh_assets(&mut self, assets: &Assets) {
+        let generation = assets.generation();
+        if generation == self.assets_generation {
+            return;
+        }
+        self.assets_generation = generation;
+        for pane in &mut self.panes {
+            pane.refresh_image(&self.gpu.device, &self.images, assets);
+        }
+        self.layers
+            .refresh_images(&self.gpu.device, &self.images, assets);
+        self.subtitles.refresh_font(assets);
+        self.terrain.refresh_assets(&self.gpu.device, assets);
+
+        if !self.graph.is_current(assets) {
+            if let Err(e) = self.set_render_graph(self.graph_descriptor.clone(), assets) {
+                println!("Failed to rebuild render graph: {}", e);
+            }
+        }
+    }
+
+    /// Feed a navigation gesture (pinch, pan or rotate) from an interactive overlay into
+    /// the view transform.
+    pub fn handle_gesture(&mut self, event: &InputEvent) {
+        self.navigation.apply(event);
+    }
+
+    /// Navigate with held input, like a gamepad's sticks, for `seconds`. Zooming and
+    /// rotating happen around the middle of the overlay.
+    pub fn drive_navigation(&mut self, input: &NavigationInput, seconds: f64) {
+        let center = [
+            self.size.width as f64 / self.scale_factor / 2.0,
+            self.size.height as f64 / self.scale_factor / 2.0,
+        ];
+        self.navigation.drive(input, center, seconds);
+    }
+
+    pub fn navigation(&self) -> Navigation {
+        self.navigation
+    }
+
+    pub fn set_navigation(&mut self, navigation: Navigation) {
+        self.navigation = navigation;
+    }
+
+    /// Draw pen input from an interactive overlay with `brush`, or stop with `None`.
+    pub fn set_ink_brush(&mut self, brush: Option<InkBrush>) {
+        self.ink.set_brush(brush);
+    }
+
+    pub fn clear_ink(&mut self) {
+        self.ink.clear();
+    }
+
+    /// Feed pointer input to the ink layer. Returns false if it wasn't pen input that
+    /// the ink layer wants.
+    pub fn handle_pen(&mut self, event: &InputEvent) -> bool {
+        self.ink.apply(event)
+    }
+
+    /// Draw the map from `descriptor` under everything but the background, replacing any
+    /// there was. Tiles are cached in `cache_dir` if there is one.
+    pub fn load_map(
+        &mut self,
+        descriptor: MapDescriptor,
+        cache_dir: Option<PathBuf>,
+    ) -> Result<(), String> {
+        self.map.load(&self.gpu.device, descriptor, cache_dir)
+    }
+
+    pub fn clear_map(&mut self) {
+        self.map.clear();
+    }
+
+    /// What part of the map showed in the last frame, if there's a map.
+    pub fn map_viewport(&self) -> Option<MapViewport> {
+        self.map.viewport()
+    }
+
+    /// The map's viewport, if it's changed since this was last called.
+    pub fn take_map_viewport_change(&mut self) -> Option<MapViewport> {
+        self.map.take_viewport_change()
+    }
+
+    /// Navigate so that the map shows `center` in the overlay's middle at `zoom`.
+    pub fn set_map_view(&mut self, center: [f64; 2], zoom: f64) -> Result<(), String> {
+        self.navigation =
+            self.map
+                .navigation_to(center, zoom, &self.navigation, self.logical_size())?;
+        Ok(())
+    }
+
+    /// Draw terrain from `descriptor` under everything but the background, replacing any
+    /// there was. Its images come from `assets`.
+    pub fn load_terrain(
+        &mut self,
+        descriptor: TerrainDescriptor,
+        assets: &Assets,
+    ) -> Result<(), String> {
+        self.terrain
+            .load(&self.gpu.device, &self.images, assets, descriptor)
+    }
+
+    pub fn clear_terrain(&mut self) {
+        self.terrain.clear();
+    }
+
+    /// Place `models` on the terrain, replacing any there were. Their assets come from
+    /// `assets`.
+    pub fn set_terrain_models(
+        &mut self,
+        models: Vec<TerrainModel>,
+        assets: &Assets,
+    ) -> Result<(), String> {
+        self.terrain.set_models(&self.gpu.device, assets, models)
+    }
+
+    /// Look at the terrain from `camera`, or over all of it with `None`. Navigation moves
+    /// the camera from there.
+    pub fn set_terrain_camera(&mut self, camera: Option<TerrainCamera>) {
+        self.terrain.set_camera(camera);
+    }
+
+    /// The terrain's camera before navigation moves it, if there's terrain.
+    pub fn terrain_camera(&self) -> Option<TerrainCamera> {
+        self.terrain.camera()
+    }
+
+    /// Make room for a volume to raymarch, replacing any there was. It's empty until slabs of
+    /// it are uploaded with [`Self::upload_volume_slab`].
+    pub fn create_volume(&mut self, descriptor: VolumeDescriptor) -> Result<(), String> {
+        self.volume
+            .create(&self.gpu.device, &self.gpu.queue, descriptor)
+    }
+
+    pub fn upload_volume_slab(&mut self, first_slice: u32, values: &[f32]) -> Result<(), String> {
+        self.volume
+            .upload_slab(&self.gpu.queue, first_slice, values)
+    }
+
+    pub fn set_transfer_function(&mut self, points: Vec<TransferPoint>) {
+        self.volume.set_transfer_function(&self.gpu.queue, points);
+    }
+
+    pub fn transfer_function(&self) -> Vec<TransferPoint> {
+        self.volume.transfer_function().to_vec()
+    }
+
+    /// Look at the volume from `camera`, or from in front of it with `None`. Navigation
+    /// moves the camera from there.
+    pub fn set_volume_camera(&mut self, camera: Option<VolumeCamera>) {
+        self.volume.set_camera(camera);
+    }
+
+    pub fn volume_camera(&self) -> VolumeCamera {
+        self.volume.camera()
+    }
+
+    pub fn clear_volume(&mut self) {
+        self.volume.clear();
+    }
+
+    /// Make room for a scatterplot, replacing any there was. It's empty until points are
+    /// uploaded with [`Self::upload_scatter_points`].
+    pub fn create_scatterplot(&mut self, descriptor: ScatterDescriptor) -> Result<(), String> {
+        self.scatter.create(&self.gpu.device, descriptor)
+    }
+
+    pub fn upload_scatter_points(&mut self, first: u32, points: &[[f64; 2]]) -> Result<(), String> {
+        self.scatter.upload_points(&self.gpu.queue, first, points)
+    }
+
+    /// Select the scatterplot's points under `brush`, with the indices of up to `limit` of
+    /// them.
+    pub fn select_scatter_points(
+        &mut self,
+        brush: ScatterBrush,
+        limit: u32,
+    ) -> Result<ScatterSelection, String> {
+        self.scatter.select(&self.gpu, brush, limit)
+    }
+
+    pub fn clear_scatter_selection(&mut self) {
+        self.scatter.clear_selection(&self.gpu.device);
+    }
+
+    pub fn clear_scatterplot(&mut self) {
+        self.scatter.clear();
+    }
+
+    /// Add a body to the overlay's physics world, replacing any with the same id.
+    pub fn spawn_physics_body(&mut self, body: BodyDescriptor) -> Result<(), String> {
+        self.physics.spawn(body)
+    }
+
+    pub fn apply_physics_impulse(
+        &mut self,
+        id: &str,
+        impulse: [f64; 2],
+        torque: f64,
+    ) -> Result<(), String> {
+        self.physics.apply_impulse(id, impulse, torque)
+    }
+
+    pub fn remove_physics_body(&mut self, id: &str) -> Result<(), String> {
+        match self.physics.remove(id) {
+            true => Ok(()),
+            false => Err(format!("there is no body called '{}'", id)),
+        }
+    }
+
+    pub fn clear_physics(&mut self) {
+        self.physics.clear();
+    }
+
+    /// Set the physics world's gravity, in logical pixels per second squared.
+    pub fn set_physics_gravity(&mut self, gravity: [f64; 2]) {
+        self.physics.set_gravity(gravity);
+    }
+
+    pub fn physics_gravity(&self) -> [f64; 2] {
+        self.physics.gravity()
+    }
+
+    pub fn physics_bodies(&self) -> Vec<BodyState> {
+        self.physics.bodies()
+    }
+
+    /// Advance the physics world by `seconds`.
+    pub fn step_physics(&mut self, seconds: f64) {
+        let logical_size = self.logical_size();
+        self.physics.step(seconds, logical_size);
+    }
+
+    /// Show or hide the frame rate readout in the top-left corner.
+    pub fn set_hud_visible(&mut self, visible: bool) {
+        self.hud.set_visible(visible);
+    }
+
+    pub fn hud_visible(&self) -> bool {
+        self.hud.is_visible()
+    }
+
+    /// Timings of the latest frames, and counts since the overlay was created.
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_timer.stats()
+    }
+
+    /// The buffers and textures the overlay holds. Assets it draws are shared, and counted
+    /// by [`Assets::memory`] instead.
+    pub fn gpu_memory(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        self.target.memory(&mut usage);
+        if let Some(buffer) = &self.sample_buffer {
+            buffer.memory(&mut usage);
+        }
+        self.background.memory(&mut usage);
+        self.clip.memory(&mut usage);
+        for pane in &self.panes {
+            pane.memory(&mut usage);
+        }
+        if let Some(video) = &self.video {
+            video.memory(&mut usage);
+        }
+        self.subtitles.memory(&mut usage);
+        self.layers.memory(&mut usage);
+        self.graph.memory(&mut usage);
+        if let Some(share) = &self.share {
+            share.memory(&mut usage);
+        }
+        if let Some(ndi) = &self.ndi {
+            ndi.memory(&mut usage);
+        }
+        self.ink.memory(&mut usage);
+        self.physics.memory(&mut usage);
+        self.map.memory(&mut usage);
+        self.terrain.memory(&mut usage);
+        self.volume.memory(&mut usage);
+        self.scatter.memory(&mut usage);
+        self.hud.memory(&mut usage);
+        self.frame_timer.memory(&mut usage);
+        usage
+    }
+
+    /// Note that a frame wasn't drawn on purpose, like while the overlay is paused, so the
+    /// gap isn't counted as dropped frames.
+    pub fn frame_skipped(&mut self) {
+        self.frame_timer.interrupted();
+    }
+
+    /// Draw a frame, or `None` if there wasn't one to present. Overlays using
+    /// [`Presentation::Readback`] get the pixels back to present.
+    pub fn render(&mut self) -> Result<Option<Presented>, String> {
+        let started = Instant::now();
+        self.prepare();
+        let frame = match self.target.acquire(&self.gpu.device)? {
+            Some(frame) => frame,
+            // The overlay can't be shown right now, so there's nothing to draw
+            None => {
+                self.frame_timer.interrupted();
+                return Ok(None);
+            }
+        };
+
+        // The frame is timed from before its layers are drawn
+        let mut start = self
+            .gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Frame Start Encoder"),
+            });
+        self.frame_timer.begin_gpu(&self.gpu.device, &mut start);
+        let layers = self.render_layers();
+        let mut encoder = self
+            .gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+        // Frames that go to other apps are drawn offscreen, then copied onto the overlay
+        let offscreen = match (&self.share, &self.ndi) {
+            (Some(share), _) => Some((share.view(), share.source())),
+            (None, Some(ndi)) => ndi.target(),
+            (None, None) => None,
+        };
+        match offscreen {
+            Some((view, source)) => {
+                self.draw(&mut encoder, view);
+                self.blit.draw(&mut encoder, source, &frame.view);
+            }
+            None => self.draw(&mut encoder, &frame.view),
+        }
+        if let Some(ndi) = &self.ndi {
+            ndi.capture(
+                &mut encoder,
+                self.share.as_ref().map(|share| share.texture()),
+            );
+        }
+        self.target.finish(&mut encoder, &frame);
+        self.frame_timer.end_gpu(&mut encoder);
+        self.gpu.queue.submit(
+            std::iter::once(start.finish())
+                .chain(layers)
+                .chain(std::iter::once(encoder.finish())),
+        );
+        self.frame_timer.submitted();
+        if let Some(share) = &mut self.share {
+            share.publish(&self.gpu);
+        }
+        self.hud.frame_rendered();
+
+        let presented = self.target.present(&self.gpu, frame);
+        self.frame_timer.presented(started);
+        if let Some(ndi) = &self.ndi {
+            ndi.send(
+                &self.gpu.device,
+                presented.as_ref().and_then(Presented::frame),
+            );
+        }
+        Ok(presented)
+    }
+
+    /// The color around (`x`, `y`) in logical pixels: the average of the pixels within
+    /// `radius` of it, as straight alpha RGBA. The last frame presented is read back, so
+    /// this waits for the GPU and is for pickers and probes rather than every frame.
+    pub fn sample_pixel(&mut self, x: f64, y: f64, radius: u32) -> Result<[f64; 4], String> {
+        let (x, y) = (x * self.scale_factor, y * self.scale_factor);
+        let radius = (radius as f64 * self.scale_factor).round() as u32;
+        let region = sample::Region::around(x, y, radius, self.size)?;
+        let texture = last_frame(&self.target)?;
+        let device = &self.gpu.device;
+        let buffer = self
+            .sample_buffer
+            .get_or_insert_with(|| target::FrameBuffer::new(device, self.size));
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Sample Encoder"),
+        });
+        buffer.copy_from(&mut encoder, texture);
+        self.gpu.queue.submit(std::iter::once(encoder.finish()));
+
+        let format = self.target.format();
+        buffer
+            .with_rows(device, |rows| sample::average(rows, format, region))
+            .map_err(|e| format!("failed to read back frame: {}", e))?
+    }
+
+    /// The histogram of the last frame presented, after any output filters. Like
+    /// `sample_pixel`, this waits for the GPU.
+    pub fn histogram(&self) -> Result<Histogram, String> {
+        let texture = last_frame(&self.target)?;
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let encoder = self
+            .gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Histogram Encoder"),
+            });
+        let size = [self.size.width, self.size.height];
+        let linear = self.target.format().is_srgb();
+        self.gpu
+            .histogram
+            .count(&self.gpu, encoder, &view, size, true, linear)
+    }
+
+    /// Upload whatever changed since the last frame.
+    fn prepare(&mut self) {
+        let logical_size = self.logical_size();
+        let uploads = &mut self.uploads;
+        self.clip.prepare(&self.gpu.device, self.size);
+        self.layers.prepare_shaders(uploads);
+        self.ink.prepare(&self.gpu.device, uploads, logical_size);
+        self.physics
+            .prepare(&self.gpu.device, uploads, logical_size);
+        self.map.prepare(
+            &self.gpu.device,
+            &self.gpu.queue,
+            uploads,
+            &self.navigation,
+            logical_size,
+        );
+        self.terrain
+            .prepare(uploads, &self.navigation, logical_size);
+        self.volume.prepare(uploads, &self.navigation, logical_size);
+        self.scatter.prepare(
+            &self.gpu.device,
+            uploads,
+            &self.navigation,
+            logical_size,
+            self.scale_factor,
+        );
+        self.hud.prepare(
+            &self.gpu.device,
+            &self.gpu.queue,
+            &self.images,
+            self.scale_factor,
+        );
+        if let Some(video) = &self.video {
+            self.subtitles.prepare(
+                &self.gpu.device,
+                &self.gpu.queue,
+                &self.images,
+                video.fitted(self.size)[2].round() as u32,
+                self.scale_factor,
+            );
+        }
+        // Submitted on their own, so they land whether or not a frame gets drawn
+        if let Some(uploads) = self.uploads.finish() {
+            self.gpu.queue.submit(std::iter::once(uploads));
+            self.uploads.recall();
+        }
+    }
+
+    /// Draw the layers into their targets, for the scene to composite. They're drawn once
+    /// a frame, however many times the render graph draws the scene, and not at all if it
+    /// never does.
+    fn render_layers(&self) -> Vec<wgpu::CommandBuffer> {
+        let scene = self
+            .graph
+            .passes()
+            .iter()
+            .find(|pass| matches!(pass.operation, graph::Operation::Scene));
+        match scene {
+            Some(scene) => self.layers.render(
+                &self.gpu.device,
+                self.graph.depth(scene),
+                &self.fill,
+                &self.images,
+            ),
+            None => Vec::new(),
+        }
+    }
+
+    fn logical_size(&self) -> [f32; 2] {
+        [
+            (self.size.width as f64 / self.scale_factor) as f32,
+            (self.size.height as f64 / self.scale_factor) as f32,
+        ]
+    }
+
+    /// Draw a frame into `view`, which is the size of the surface, by running the render
+    /// graph's passes.
+    fn draw(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        for pass in self.graph.passes() {
+            let output = self.graph.output(pass, view);
+            match &pass.operation {
+                graph::Operation::Scene => self.draw_scene(encoder, output, self.graph.depth(pass)),
+                graph::Operation::Filters(filters) => {
+                    filters.apply(&self.gpu.filters, &self.blit, encoder, output)
+                }
+                graph::Operation::Blit(input) => self.blit.draw(encoder, input, output),
+                graph::Operation::Hud => self.draw_hud(encoder, output, self.graph.depth(pass)),
+            }
+        }
+    }
+
+    /// Draw the overlay's content into `view`.
+    fn draw_scene(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        depth_stencil: &wgpu::TextureView,
+    ) {
+        self.terrain.render(encoder);
+        self.scatter.render(encoder);
+
+        // With a clip active, everything outside of it stays transparent and the
+        // background is drawn as a stencil-tested fill instead of a clear.
+        let clipped = self.clip.is_active();
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(if clipped {
+                        wgpu::Color::TRANSPARENT
+                    } else {
+                        fill::premultiply(self.clear_color)
+                    }),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_stencil,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clip::clear_value(clipped)),
+                    store: wgpu::StoreOp::Discard,
+                }),
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+
+        if clipped {
+            self.clip.draw(&mut render_pass);
+            self.fill.draw(&mut render_pass, &self.background);
+        }
+
+        self.map.draw(&mut render_pass);
+        self.terrain.draw(&mut render_pass, &self.images);
+        self.volume.draw(&mut render_pass);
+        self.scatter.draw(&mut render_pass);
+
+        if let Some(video) = &self.video {
+            video.draw(&mut render_pass, &self.images, self.size);
+            self.subtitles.draw(
+                &mut render_pass,
+                &self.images,
+                video.fitted(self.size),
+                self.scale_factor,
+                self.size,
+            );
+        }
+
+        for pane in &self.panes {
+            if pane.apply(&mut render_pass, self.size) {
+                pane.draw_background(&mut render_pass, &self.fill, &self.images);
+            }
+        }
+        if !self.panes.is_empty() {
+            panes::reset(&mut render_pass, self.size);
+        }
+
+        self.layers
+            .composite(&mut render_pass, self.backdrop_opaque());
+        self.physics.draw(&mut render_pass);
+
+        // Ink goes over everything else, across the whole surface
+        self.ink.draw(&mut render_pass);
+    }
+
+    /// Draw the frame rate readout over `view`, if it's shown. It isn't clipped, and in the
+    /// standard graph it goes on after the filters, so it isn't filtered either.
+    fn draw_hud(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        depth_stencil: &wgpu::TextureView,
+    ) {
+        if !self.hud.is_visible() {
+            return;
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("HUD Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_stencil,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clip::clear_value(false)),
+                    store: wgpu::StoreOp::Discard,
+                }),
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+        self.hud
+            .draw(&mut render_pass, &self.images, self.scale_factor, self.size);
+    }
+}
+
+/// What the last frame presented to `target` looks like, or an error if it can't be read.
+fn last_frame(target: &target::RenderTarget) -> Result<&wgpu::Texture, String> {
+    target
+        .last_frame()
+        .ok_or_else(|| "this overlay's frames can't be read back".to_string())
+}
@@ -0,0 +1,328 @@
+//! Model assets placed in the terrain's scene, drawn with the terrain's camera and light into
+//! its target, so they sit on the ground and hide behind hills. Meshes outside the camera's
+//! view aren't drawn, and the rest are drawn at a level of detail to suit their size on
+//! screen. Nothing is culled for being hidden: meshes behind hills are drawn and lose the
+//! depth test, as there's no hierarchical-Z occlusion pass.
+
+use std::sync::Arc;
+
+use serde::Deserialize;
+use wgpu::util::DeviceExt;
+
+use super::lod;
+use super::pipelines::Pipelines;
+use super::terrain::in_frustum;
+use super::MemoryUsage;
+use crate::assets::{Asset, Assets, ModelVertex};
+
+/// A model asset standing somewhere in the terrain's scene.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerrainModel {
+    /// Id of a model asset.
+    pub model: String,
+    /// East, up and south of the terrain's middle, in the terrain's units.
+    pub position: [f64; 3],
+    /// How many of the terrain's units each of the model's is.
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    /// Radians clockwise, looking down, that the model is turned.
+    #[serde(default)]
+    pub yaw: f64,
+    /// Multiplies the model's shading.
+    #[serde(default = "default_color")]
+    pub color: [f64; 3],
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+fn default_color() -> [f64; 3] {
+    [0.8, 0.8, 0.8]
+}
+
+/// What each placed model is drawn with, from the second vertex buffer.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ModelInstance {
+    /// Column-major, from the model's units to the terrain's.
+    transform: [[f32; 4]; 4],
+    color: [f32; 4],
+}
+
+impl ModelInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+        3 => Float32x4, 4 => Float32x4, 5 => Float32x4, 6 => Float32x4, 7 => Float32x4
+    ];
+
+    fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ModelInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+
+    fn new(model: &TerrainModel) -> Self {
+        let scale = model.scale as f32;
+        // Turned the other way around y, which is up, to go clockwise looking down
+        let (sin, cos) = (model.yaw as f32).sin_cos();
+        let [x, y, z] = model.position;
+        let [r, g, b] = model.color;
+        ModelInstance {
+            transform: [
+                [scale * cos, 0.0, scale * sin, 0.0],
+                [0.0, scale, 0.0, 0.0],
+                [-scale * sin, 0.0, scale * cos, 0.0],
+                [x as f32, y as f32, z as f32, 1.0],
+            ],
+            color: [r as f32, g as f32, b as f32, 1.0],
+        }
+    }
+}
+
+struct Placed {
+    model: TerrainModel,
+    asset: Arc<Asset>,
+    /// One for each of the model's meshes.
+    meshes: Vec<PlacedMesh>,
+}
+
+/// How one mesh of a placed model is drawn, as of the last `prepare`.
+#[derive(Debug, Clone, Copy, Default)]
+struct PlacedMesh {
+    visible: bool,
+    lod: u32,
+}
+
+/// The models in the terrain's scene, and the pipeline that draws them.
+pub struct SceneModels {
+    pipeline: wgpu::RenderPipeline,
+    /// Binds the terrain's uniforms, for its camera and light.
+    bind_group: wgpu::BindGroup,
+    placed: Vec<Placed>,
+    /// One [`ModelInstance`] for each placed model, in the same order.
+    instances: Option<wgpu::Buffer>,
+}
+
+impl SceneModels {
+    /// Draw into targets of `color_format` with depth in `depth_format`, looking through the
+    /// terrain's `uniforms`.
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        uniforms: &wgpu::Buffer,
+        pipelines: &Pipelines,
+    ) -> Self {
+        let shader = pipelines.shader(device, "Model Shader", include_str!("shaders/models.wgsl"));
+
+        let bind_group_layout = pipelines.bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Model Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Model Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniforms.as_entire_binding(),
+            }],
+        });
+
+        let layout = pipelines.pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Model Pipeline Layout"),
+                bind_group_layouts: &[Some(&bind_group_layout)],
+                immediate_size: 0,
+            },
+        );
+
+        let pipeline = pipelines.render_pipeline(
+            device,
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Model Pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[Some(ModelVertex::layout()), Some(ModelInstance::layout())],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(color_format.into())],
+                    compilation_options: Default::default(),
+                }),
+                // OBJ files don't agree on which way faces wind, so both sides are drawn
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: depth_format,
+                    depth_write_enabled: Some(true),
+                    depth_compare: Some(wgpu::CompareFunction::Less),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview_mask: None,
+                cache: pipelines.cache(),
+            },
+        );
+
+        SceneModels {
+            pipeline,
+            bind_group,
+            placed: Vec::new(),
+            instances: None,
+        }
+    }
+
+    /// Place `models`, replacing any there were. Their assets come from `assets`.
+    pub fn set(
+        &mut self,
+        device: &wgpu::Device,
+        assets: &Assets,
+        models: Vec<TerrainModel>,
+    ) -> Result<(), String> {
+        let placed = models
+            .into_iter()
+            .map(|model| {
+                let asset = model_asset(assets, &model.model)?;
+                Ok(Placed {
+                    model,
+                    asset,
+                    meshes: Vec::new(),
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let instances: Vec<ModelInstance> = placed
+            .iter()
+            .map(|placed| ModelInstance::new(&placed.model))
+            .collect();
+        self.instances = (!instances.is_empty()).then(|| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Model Instances"),
+                contents: bytemuck::cast_slice(&instances),
+                usage: wgpu::BufferUsages::VERTEX,
+            })
+        });
+        self.placed = placed;
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.placed.clear();
+        self.instances = None;
+    }
+
+    /// Swap in models whose assets have been reloaded. Keeps the old ones of any that have
+    /// been unloaded.
+    pub fn refresh(&mut self, assets: &Assets) {
+        for placed in &mut self.placed {
+            match model_asset(assets, &placed.model.model) {
+                Ok(asset) if !Arc::ptr_eq(&asset, &placed.asset) => placed.asset = asset,
+                _ => {}
+            }
+        }
+    }
+
+    /// Leave out the meshes that are entirely outside `frustum`, which is the terrain
+    /// camera's, and pick each other's level of detail by how big it looks from `eye`.
+    /// Something a unit across, a unit away, is `pixels_per_unit` logical pixels across.
+    pub fn prepare(&mut self, frustum: &[[f32; 4]; 6], eye: [f32; 3], pixels_per_unit: f32) {
+        for placed in &mut self.placed {
+            // Checked when it was placed
+            let model = placed.asset.model().unwrap();
+            let transform = ModelInstance::new(&placed.model).transform;
+            let scale = placed.model.scale.abs() as f32;
+            // Reloading the asset can change its meshes
+            placed
+                .meshes
+                .resize(model.meshes.len(), PlacedMesh::default());
+            for (mesh, placed_mesh) in model.meshes.iter().zip(&mut placed.meshes) {
+                let bounds = transform_bounds(&transform, mesh.bounds);
+                placed_mesh.visible = in_frustum(frustum, bounds);
+                // The mesh's closest point sets how big its features look
+                let distance = (0..3)
+                    .map(|i| (eye[i].clamp(bounds[0][i], bounds[1][i]) - eye[i]).powi(2))
+                    .sum::<f32>()
+                    .sqrt()
+                    .max(f32::EPSILON);
+                let detail_pixels = mesh.detail * scale * pixels_per_unit / distance;
+                // Each level doubles the size of features, as terrain cells do
+                let level = (lod::CELL_PIXELS / detail_pixels).max(1.0).log2();
+                placed_mesh.lod = lod::pick(placed_mesh.lod, level, mesh.level_count());
+            }
+        }
+    }
+
+    /// The instances. The models themselves are assets, so they're counted with those.
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        if let Some(instances) = &self.instances {
+            usage.add_buffer(instances);
+        }
+    }
+
+    /// Draw the models into the terrain's pass.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let instances = match &self.instances {
+            Some(instances) => instances,
+            None => return,
+        };
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(1, instances.slice(..));
+        for (instance, placed) in self.placed.iter().enumerate() {
+            // Checked when it was placed
+            let model = placed.asset.model().unwrap();
+            for (mesh, placed_mesh) in model.meshes.iter().zip(&placed.meshes) {
+                if placed_mesh.visible {
+                    mesh.draw(render_pass, placed_mesh.lod, instance as u32);
+                }
+            }
+        }
+    }
+}
+
+/// The loaded model asset called `id`.
+fn model_asset(assets: &Assets, id: &str) -> Result<Arc<Asset>, String> {
+    let asset = assets
+        .get(id)
+        .ok_or_else(|| format!("no asset '{}' has been loaded", id))?;
+    if asset.model().is_none() {
+        return Err(format!("asset '{}' is not a model", id));
+    }
+    Ok(asset)
+}
+
+/// The box around `bounds` once it's been moved by `transform`, which is column-major.
+fn transform_bounds(transform: &[[f32; 4]; 4], bounds: [[f32; 3]; 2]) -> [[f32; 3]; 2] {
+    let mut transformed = [[0.0; 3]; 2];
+    for row in 0..3 {
+        transformed[0][row] = transform[3][row];
+        transformed[1][row] = transform[3][row];
+        // Each column stretches the box along it, toward whichever end is further
+        for (column, axis) in transform.iter().take(3).enumerate() {
+            let low = axis[row] * bounds[0][column];
+            let high = axis[row] * bounds[1][column];
+            transformed[0][row] += low.min(high);
+            transformed[1][row] += low.max(high);
+        }
+    }
+    transformed
+}
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+
+use crate::input::{InputEvent, InputKind};
+
+const MIN_ZOOM: f64 = 0.01;
+const MAX_ZOOM: f64 = 100.0;
+/// How fast [`NavigationInput`] at full tilt pans, in logical pixels a second, zooms, as a
+/// factor a second, and rotates, in radians a second.
+const DRIVE_PAN_SPEED: f64 = 800.0;
+const DRIVE_ZOOM_SPEED: f64 = 2.0;
+const DRIVE_ROTATE_SPEED: f64 = std::f64::consts::PI;
+
+/// A 2D view transform for content that can be panned, zoomed and rotated with gestures,
+/// like maps and scenes. Content at `p` appears at `offset + zoom * rotate(p, rotation)`,
+/// in logical pixels from the overlay's top-left corner.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Navigation {
+    pub offset: [f64; 2],
+    pub zoom: f64,
+    /// Radians clockwise.
+    pub rotation: f64,
+}
+
+impl Default for Navigation {
+    fn default() -> Self {
+        Navigation {
+            offset: [0.0, 0.0],
+            zoom: 1.0,
+            rotation: 0.0,
+        }
+    }
+}
+
+/// Held input that navigates for as long as it's held, like a gamepad's sticks. Each
+/// value is from -1 to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NavigationInput {
+    /// Moves the view over the content, with up and right positive.
+    pub pan: [f64; 2],
+    /// Zooms in when positive.
+    pub zoom: f64,
+    /// Turns the content clockwise when positive.
+    pub rotate: f64,
+}
+
+impl Navigation {
+    /// Navigate as `input` says for `seconds`, zooming and rotating around `center`.
+    pub fn drive(&mut self, input: &NavigationInput, center: [f64; 2], seconds: f64) {
+        // The content moves the opposite way to the view
+        self.offset[0] -= input.pan[0] * DRIVE_PAN_SPEED * seconds;
+        self.offset[1] += input.pan[1] * DRIVE_PAN_SPEED * seconds;
+        let zoom =
+            (self.zoom * DRIVE_ZOOM_SPEED.powf(input.zoom * seconds)).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.transform_about(
+            center,
+            zoom / self.zoom,
+            input.rotate * DRIVE_ROTATE_SPEED * seconds,
+        );
+    }
+
+    /// Apply a pinch, pan or rotate gesture. Other input is ignored.
+    pub fn apply(&mut self, event: &InputEvent) {
+        let center = [event.x, event.y];
+        match event.kind {
+            InputKind::Pan => {
+                self.offset[0] += event.delta_x;
+                self.offset[1] += event.delta_y;
+            }
+            InputKind::Pinch => {
+                let zoom = (self.zoom * event.scale.unwrap_or(1.0)).clamp(MIN_ZOOM, MAX_ZOOM);
+                self.transform_about(center, zoom / self.zoom, 0.0);
+            }
+            InputKind::Rotate => {
+                self.transform_about(center, 1.0, event.rotation.unwrap_or(0.0));
+            }
+            _ => {}
+        }
+    }
+
+    /// Scale and rotate around `center`, so the content under it stays put.
+    fn transform_about(&mut self, center: [f64; 2], scale: f64, rotation: f64) {
+        let (sin, cos) = rotation.sin_cos();
+        let x = self.offset[0] - center[0];
+        let y = self.offset[1] - center[1];
+        self.offset = [
+            center[0] + scale * (x * cos - y * sin),
+            center[1] + scale * (x * sin + y * cos),
+        ];
+        self.zoom *= scale;
+        self.rotation += rotation;
+    }
+}
@@ -0,0 +1,321 @@
+//! NDI output, for picking up an overlay's frames on other machines in a production. The NDI
+//! runtime isn't linked, but loaded when the first sender is made, from where its installer
+//! says it is (`NDI_RUNTIME_DIR_V5`) or the library path.
+//!
+//! Frames have to be in CPU memory to send, so they're read back after each one's drawn: from
+//! the frames read back overlays already have, from the texture frames are shared from, or
+//! from a texture the overlay draws into first. Sending happens on a thread of its own, and
+//! frames are dropped while it's busy rather than holding up drawing.
+
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_void};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+
+use libloading::Library;
+
+use super::blit::Blit;
+use super::target::{Frame, FrameBuffer};
+use super::{GpuContext, MemoryUsage};
+
+const RUNTIME_DIR_VAR: &str = "NDI_RUNTIME_DIR_V5";
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "windows")] {
+        const LIBRARY_NAME: &str = "Processing.NDI.Lib.x64.dll";
+    } else if #[cfg(target_os = "macos")] {
+        const LIBRARY_NAME: &str = "libndi.dylib";
+    } else {
+        const LIBRARY_NAME: &str = "libndi.so.5";
+    }
+}
+
+/// What receivers are told the frame rate is. Frames are sent as they're drawn, and NDI
+/// times them by when they arrive.
+const FRAME_RATE: [c_int; 2] = [60, 1];
+const FRAME_FORMAT_PROGRESSIVE: c_int = 1;
+/// Has NDI fill in the timecode itself.
+const TIMECODE_SYNTHESIZE: i64 = i64::MAX;
+
+#[repr(C)]
+struct SendCreate {
+    ndi_name: *const c_char,
+    groups: *const c_char,
+    clock_video: bool,
+    clock_audio: bool,
+}
+
+#[repr(C)]
+struct VideoFrame {
+    xres: c_int,
+    yres: c_int,
+    four_cc: u32,
+    frame_rate_n: c_int,
+    frame_rate_d: c_int,
+    picture_aspect_ratio: f32,
+    frame_format_type: c_int,
+    timecode: i64,
+    data: *const u8,
+    line_stride_in_bytes: c_int,
+    metadata: *const c_char,
+    timestamp: i64,
+}
+
+type Initialize = unsafe extern "C" fn() -> bool;
+type SendCreateFn = unsafe extern "C" fn(*const SendCreate) -> *mut c_void;
+type SendVideo = unsafe extern "C" fn(*mut c_void, *const VideoFrame);
+type SendDestroy = unsafe extern "C" fn(*mut c_void);
+
+fn four_cc(code: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*code)
+}
+
+/// An NDI source on the network, and the runtime it came from.
+struct Source {
+    send_video: SendVideo,
+    send_destroy: SendDestroy,
+    instance: *mut c_void,
+    // Kept loaded for as long as the functions above are used
+    _library: Library,
+}
+
+// NDI senders can be used from any thread
+unsafe impl Send for Source {}
+
+impl Source {
+    fn new(name: &str) -> Result<Self, String> {
+        let path = match std::env::var_os(RUNTIME_DIR_VAR) {
+            Some(dir) => PathBuf::from(dir).join(LIBRARY_NAME),
+            None => PathBuf::from(LIBRARY_NAME),
+        };
+        let name = CString::new(name).map_err(|_| "NDI names can't contain nulls".to_string())?;
+        unsafe {
+            let library = Library::new(&path)
+                .map_err(|e| format!("the NDI runtime isn't installed ({:?}): {}", path, e))?;
+            let symbol = |e: libloading::Error| format!("the NDI runtime is unusable: {}", e);
+            let initialize = *library
+                .get::<Initialize>(b"NDIlib_initialize\0")
+                .map_err(symbol)?;
+            let send_create = *library
+                .get::<SendCreateFn>(b"NDIlib_send_create\0")
+                .map_err(symbol)?;
+            let send_video = *library
+                .get::<SendVideo>(b"NDIlib_send_send_video_v2\0")
+                .map_err(symbol)?;
+            let send_destroy = *library
+                .get::<SendDestroy>(b"NDIlib_send_destroy\0")
+                .map_err(symbol)?;
+
+            if !initialize() {
+                return Err("NDI isn't supported on this CPU".into());
+            }
+            let instance = send_create(&SendCreate {
+                ndi_name: name.as_ptr(),
+                groups: std::ptr::null(),
+                clock_video: false,
+                clock_audio: false,
+            });
+            if instance.is_null() {
+                return Err("failed to create an NDI sender".into());
+            }
+            Ok(Source {
+                send_video,
+                send_destroy,
+                instance,
+                _library: library,
+            })
+        }
+    }
+
+    fn send(&self, frame: &Frame, four_cc: u32) {
+        unsafe {
+            (self.send_video)(
+                self.instance,
+                &VideoFrame {
+                    xres: frame.width as c_int,
+                    yres: frame.height as c_int,
+                    four_cc,
+                    frame_rate_n: FRAME_RATE[0],
+                    frame_rate_d: FRAME_RATE[1],
+                    picture_aspect_ratio: 0.0, // Square pixels
+                    frame_format_type: FRAME_FORMAT_PROGRESSIVE,
+                    timecode: TIMECODE_SYNTHESIZE,
+                    data: frame.pixels.as_ptr(),
+                    line_stride_in_bytes: (frame.width * 4) as c_int,
+                    metadata: std::ptr::null(),
+                    timestamp: 0,
+                },
+            );
+        }
+    }
+}
+
+impl Drop for Source {
+    fn drop(&mut self) {
+        unsafe { (self.send_destroy)(self.instance) }
+    }
+}
+
+/// A texture the overlay draws into, for surfaces that can't be read back themselves.
+struct Capture {
+    view: wgpu::TextureView,
+    texture: wgpu::Texture,
+    source: wgpu::BindGroup,
+}
+
+impl Capture {
+    fn new(
+        gpu: &GpuContext,
+        blit: &Blit,
+        format: wgpu::TextureFormat,
+        size: tauri::PhysicalSize<u32>,
+    ) -> Self {
+        let texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("NDI Frame"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let source = blit.bind(&gpu.device, &view);
+        Capture {
+            view,
+            texture,
+            source,
+        }
+    }
+}
+
+pub struct NdiOutput {
+    name: String,
+    /// Frames waiting for the sending thread, which stops when this is dropped.
+    frames: SyncSender<Frame>,
+    /// For surfaces. Read back overlays send the frames they read back anyway.
+    capture: Option<(Capture, FrameBuffer)>,
+}
+
+impl NdiOutput {
+    /// Send frames of `format` and `size` as the NDI source `name`. `readback` says the
+    /// overlay reads its frames back anyway, and they're passed to `send`.
+    pub fn new(
+        gpu: &GpuContext,
+        blit: &Blit,
+        name: String,
+        format: wgpu::TextureFormat,
+        size: tauri::PhysicalSize<u32>,
+        readback: bool,
+    ) -> Result<Self, String> {
+        let four_cc = match format {
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+                four_cc(b"BGRA")
+            }
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => {
+                four_cc(b"RGBA")
+            }
+            format => return Err(format!("NDI can't send {:?} frames", format)),
+        };
+        let source = Source::new(&name)?;
+        let (frames, received) = mpsc::sync_channel::<Frame>(1);
+        std::thread::spawn(move || {
+            for mut frame in received {
+                unpremultiply(&mut frame.pixels);
+                source.send(&frame, four_cc);
+            }
+        });
+
+        let capture = if readback {
+            None
+        } else {
+            Some((
+                Capture::new(gpu, blit, format, size),
+                FrameBuffer::new(&gpu.device, size),
+            ))
+        };
+        Ok(NdiOutput {
+            name,
+            frames,
+            capture,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn resize(
+        &mut self,
+        gpu: &GpuContext,
+        blit: &Blit,
+        format: wgpu::TextureFormat,
+        size: tauri::PhysicalSize<u32>,
+    ) {
+        if let Some(capture) = &mut self.capture {
+            *capture = (
+                Capture::new(gpu, blit, format, size),
+                FrameBuffer::new(&gpu.device, size),
+            );
+        }
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        if let Some((capture, buffer)) = &self.capture {
+            usage.add_texture(&capture.texture);
+            buffer.memory(usage);
+        }
+    }
+
+    /// Where to draw frames instead of the overlay's own target, and the bind group for
+    /// copying them onto it, if the overlay's target can't be read back.
+    pub fn target(&self) -> Option<(&wgpu::TextureView, &wgpu::BindGroup)> {
+        self.capture
+            .as_ref()
+            .map(|(capture, _)| (&capture.view, &capture.source))
+    }
+
+    /// Record reading back the frame, from `drawn` if it was drawn somewhere other than
+    /// [`NdiOutput::target`].
+    pub fn capture(&self, encoder: &mut wgpu::CommandEncoder, drawn: Option<&wgpu::Texture>) {
+        if let Some((capture, buffer)) = &self.capture {
+            buffer.copy_from(encoder, drawn.unwrap_or(&capture.texture));
+        }
+    }
+
+    /// Send the frame, once it's been submitted: the one read back by `capture`, or
+    /// `presented` if the overlay read it back itself.
+    pub fn send(&self, device: &wgpu::Device, presented: Option<&Frame>) {
+        let frame = match &self.capture {
+            Some((_, buffer)) => buffer.read(device),
+            None => presented.cloned(),
+        };
+        if let Some(frame) = frame {
+            match self.frames.try_send(frame) {
+                Ok(()) | Err(TrySendError::Full(_)) => {}
+                Err(TrySendError::Disconnected(_)) => {
+                    println!("NDI sender {:?} stopped", self.name)
+                }
+            }
+        }
+    }
+}
+
+/// NDI takes straight alpha.
+fn unpremultiply(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u32;
+        if alpha != 0 && alpha != 255 {
+            for channel in &mut pixel[..3] {
+                *channel = ((*channel as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use serde::Deserialize;
+
+use super::fill::{FillColor, FillPipeline};
+use super::image::{Image, ImagePipeline};
+use super::MemoryUsage;
+use crate::assets::Assets;
+
+/// A rectangle in physical pixels, relative to the top-left of the surface.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// One independently rendered region of the overlay surface.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaneDescriptor {
+    pub id: String,
+    /// The region that the pane's clip space maps onto.
+    pub viewport: Rect,
+    /// Further restricts drawing within the surface. Defaults to the viewport.
+    pub scissor: Option<Rect>,
+    #[serde(default = "default_clear_color")]
+    pub clear_color: [f64; 4],
+    /// Id of a texture asset to stretch over the viewport, on top of the clear color.
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub min_depth: f32,
+    #[serde(default = "default_max_depth")]
+    pub max_depth: f32,
+}
+
+fn default_clear_color() -> [f64; 4] {
+    [0.0, 0.0, 0.0, 0.0]
+}
+
+fn default_max_depth() -> f32 {
+    1.0
+}
+
+pub struct Pane {
+    pub descriptor: PaneDescriptor,
+    background: FillColor,
+    image: Option<Image>,
+}
+
+impl Pane {
+    pub fn new(
+        device: &wgpu::Device,
+        fill: &FillPipeline,
+        images: &ImagePipeline,
+        assets: &Assets,
+        descriptor: PaneDescriptor,
+    ) -> Result<Self, String> {
+        let [r, g, b, a] = descriptor.clear_color;
+        let background = fill.create_color(device, wgpu::Color { r, g, b, a });
+        let image = match &descriptor.image {
+            Some(id) => {
+                let asset = assets
+                    .get(id)
+                    .ok_or_else(|| format!("no asset '{}' has been loaded", id))?;
+                let image = images
+                    .create_image(device, asset)
+                    .map_err(|e| format!("pane '{}': {}", descriptor.id, e))?;
+                Some(image)
+            }
+            None => None,
+        };
+        Ok(Pane {
+            descriptor,
+            background,
+            image,
+        })
+    }
+
+    /// Rebind the pane's image if its asset has been reloaded. Keeps the old one if the new
+    /// one can't be used.
+    pub fn refresh_image(
+        &mut self,
+        device: &wgpu::Device,
+        images: &ImagePipeline,
+        assets: &Assets,
+    ) {
+        let (id, image) = match (&self.descriptor.image, &mut self.image) {
+            (Some(id), Some(image)) => (id, image),
+            _ => return,
+        };
+        let asset = match assets.get(id) {
+            Some(asset) if !image.is_bound_to(&asset) => asset,
+            _ => return,
+        };
+        match images.create_image(device, asset) {
+            Ok(reloaded) => *image = reloaded,
+            Err(e) => println!(
+                "Failed to reload image for pane '{}': {}",
+                self.descriptor.id, e
+            ),
+        }
+    }
+
+    /// Restrict the render pass to this pane. Returns false if the pane is entirely
+    /// outside of the surface, in which case nothing should be drawn for it.
+    pub fn apply(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        size: tauri::PhysicalSize<u32>,
+    ) -> bool {
+        let viewport = &self.descriptor.viewport;
+        let scissor = match clamp_scissor(self.descriptor.scissor.unwrap_or(*viewport), size) {
+            Some(scissor) => scissor,
+            None => return false,
+        };
+        if viewport.width <= 0.0 || viewport.height <= 0.0 {
+            return false;
+        }
+
+        render_pass.set_viewport(
+            viewport.x,
+            viewport.y,
+            viewport.width,
+            viewport.height,
+            self.descriptor.min_depth,
+            self.descriptor.max_depth,
+        );
+        let (x, y, width, height) = scissor;
+        render_pass.set_scissor_rect(x, y, width, height);
+        true
+    }
+
+    /// The pane's background. Its image is an asset, so it's counted with those.
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        self.background.memory(usage);
+    }
+
+    pub fn draw_background<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        fill: &'a FillPipeline,
+        images: &'a ImagePipeline,
+    ) {
+        fill.draw(render_pass, &self.background);
+        if let Some(image) = &self.image {
+            images.draw(render_pass, image);
+        }
+    }
+}
+
+/// Undo any pane's viewport and scissor so later drawing covers the whole surface again.
+pub fn reset(render_pass: &mut wgpu::RenderPass, size: tauri::PhysicalSize<u32>) {
+    render_pass.set_viewport(0.0, 0.0, size.width as f32, size.height as f32, 0.0, 1.0);
+    render_pass.set_scissor_rect(0, 0, size.width, size.height);
+}
+
+/// wgpu rejects scissor rects that extend past the render target, so clamp to the surface.
+fn clamp_scissor(rect: Rect, size: tauri::PhysicalSize<u32>) -> Option<(u32, u32, u32, u32)> {
+    let left = rect.x.max(0.0).floor() as u32;
+    let top = rect.y.max(0.0).floor() as u32;
+    let right = ((rect.x + rect.width).ceil().max(0.0) as u32).min(size.width);
+    let bottom = ((rect.y + rect.height).ceil().max(0.0) as u32).min(size.height);
+    if right <= left || bottom <= top {
+        None
+    } else {
+        Some((left, top, right - left, bottom - top))
+    }
+}
@@ -0,0 +1,270 @@
+//! A small SVG path-data parser that flattens curves into polygons.
+//!
+//! Supports the move/line/curve/close commands (`M L H V C S Q T Z`, absolute and relative).
+//! Elliptical arcs are rejected rather than silently approximated.
+
+/// Line segments used to approximate each bezier curve.
+const CURVE_SEGMENTS: usize = 16;
+
+pub fn parse_svg_path(d: &str) -> Result<Vec<Vec<[f32; 2]>>, String> {
+    let mut tokens = Tokenizer::new(d);
+    let mut contours: Vec<Vec<[f32; 2]>> = Vec::new();
+    let mut current: Vec<[f32; 2]> = Vec::new();
+
+    let mut pen = [0.0f32, 0.0];
+    let mut start = pen;
+    // Reflected control point for the smooth (S/T) variants
+    let mut last_control: Option<[f32; 2]> = None;
+    let mut command: Option<char> = None;
+
+    loop {
+        let cmd = match tokens.next_command() {
+            Some(c) => c,
+            None => match command {
+                // Coordinates without a new letter repeat the previous command
+                Some(c) if tokens.has_number() => match c {
+                    'M' => 'L',
+                    'm' => 'l',
+                    c => c,
+                },
+                _ => break,
+            },
+        };
+        let relative = cmd.is_ascii_lowercase();
+        let offset = |p: [f32; 2], pen: [f32; 2]| {
+            if relative {
+                [p[0] + pen[0], p[1] + pen[1]]
+            } else {
+                p
+            }
+        };
+
+        match cmd.to_ascii_uppercase() {
+            'M' => {
+                flush(&mut contours, &mut current);
+                pen = offset(tokens.point()?, pen);
+                start = pen;
+                current.push(pen);
+                last_control = None;
+            }
+            'L' => {
+                pen = offset(tokens.point()?, pen);
+                current.push(pen);
+                last_control = None;
+            }
+            'H' => {
+                let x = tokens.number()?;
+                pen[0] = if relative { pen[0] + x } else { x };
+                current.push(pen);
+                last_control = None;
+            }
+            'V' => {
+                let y = tokens.number()?;
+                pen[1] = if relative { pen[1] + y } else { y };
+                current.push(pen);
+                last_control = None;
+            }
+            'C' | 'S' => {
+                let c1 = if cmd.eq_ignore_ascii_case(&'C') {
+                    offset(tokens.point()?, pen)
+                } else {
+                    reflect(last_control, pen)
+                };
+                let c2 = offset(tokens.point()?, pen);
+                let end = offset(tokens.point()?, pen);
+                for i in 1..=CURVE_SEGMENTS {
+                    let t = i as f32 / CURVE_SEGMENTS as f32;
+                    current.push(cubic(pen, c1, c2, end, t));
+                }
+                pen = end;
+                last_control = Some(c2);
+            }
+            'Q' | 'T' => {
+                let c = if cmd.eq_ignore_ascii_case(&'Q') {
+                    offset(tokens.point()?, pen)
+                } else {
+                    reflect(last_control, pen)
+                };
+                let end = offset(tokens.point()?, pen);
+                for i in 1..=CURVE_SEGMENTS {
+                    let t = i as f32 / CURVE_SEGMENTS as f32;
+                    current.push(quadratic(pen, c, end, t));
+                }
+                pen = end;
+                last_control = Some(c);
+            }
+            'Z' => {
+                flush(&mut contours, &mut current);
+                pen = start;
+                last_control = None;
+            }
+            'A' => return Err("arc commands are not supported in clip paths".into()),
+            other => return Err(format!("unknown path command '{}'", other)),
+        }
+        command = Some(cmd);
+    }
+    flush(&mut contours, &mut current);
+
+    if contours.is_empty() {
+        return Err("path does not contain any closed areas".into());
+    }
+    Ok(contours)
+}
+
+fn flush(contours: &mut Vec<Vec<[f32; 2]>>, current: &mut Vec<[f32; 2]>) {
+    let contour = std::mem::take(current);
+    if contour.len() >= 3 {
+        contours.push(contour);
+    }
+}
+
+fn reflect(control: Option<[f32; 2]>, pen: [f32; 2]) -> [f32; 2] {
+    match control {
+        Some(c) => [2.0 * pen[0] - c[0], 2.0 * pen[1] - c[1]],
+        None => pen,
+    }
+}
+
+fn quadratic(p0: [f32; 2], p1: [f32; 2], p2: [f32; 2], t: f32) -> [f32; 2] {
+    let u = 1.0 - t;
+    [
+        u * u * p0[0] + 2.0 * u * t * p1[0] + t * t * p2[0],
+        u * u * p0[1] + 2.0 * u * t * p1[1] + t * t * p2[1],
+    ]
+}
+
+fn cubic(p0: [f32; 2], p1: [f32; 2], p2: [f32; 2], p3: [f32; 2], t: f32) -> [f32; 2] {
+    let u = 1.0 - t;
+    let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+    [
+        a * p0[0] + b * p1[0] + c * p2[0] + d * p3[0],
+        a * p0[1] + b * p1[1] + c * p2[1] + d * p3[1],
+    ]
+}
+
+struct Tokenizer<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(src: &'a str) -> Self {
+        Tokenizer { src, pos: 0 }
+    }
+
+    fn skip_separators(&mut self) {
+        let rest = &self.src[self.pos..];
+        let trimmed = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        self.pos += rest.len() - trimmed.len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_separators();
+        self.src[self.pos..].chars().next()
+    }
+
+    fn has_number(&mut self) -> bool {
+        matches!(self.peek(), Some(c) if c.is_ascii_digit() || c == '-' || c == '+' || c == '.')
+    }
+
+    fn next_command(&mut self) -> Option<char> {
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() && c != 'e' && c != 'E' => {
+                self.pos += 1;
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+
+    fn number(&mut self) -> Result<f32, String> {
+        self.skip_separators();
+        let bytes = self.src.as_bytes();
+        let begin = self.pos;
+        let mut end = begin;
+        if end < bytes.len() && (bytes[end] == b'-' || bytes[end] == b'+') {
+            end += 1;
+        }
+        let mut seen_dot = false;
+        while end < bytes.len() {
+            match bytes[end] {
+                b'0'..=b'9' => end += 1,
+                b'.' if !seen_dot => {
+                    seen_dot = true;
+                    end += 1;
+                }
+                b'e' | b'E' => {
+                    end += 1;
+                    if end < bytes.len() && (bytes[end] == b'-' || bytes[end] == b'+') {
+                        end += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+        self.pos = end;
+        self.src[begin..end]
+            .parse()
+            .map_err(|_| format!("expected a number at offset {} in path", begin))
+    }
+
+    fn point(&mut self) -> Result<[f32; 2], String> {
+        Ok([self.number()?, self.number()?])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_absolute_and_relative_lines() {
+        let square = vec![[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]];
+        assert_eq!(
+            parse_svg_path("M0 0 L10 0 L10 10 L0 10 Z").unwrap(),
+            std::slice::from_ref(&square)
+        );
+        assert_eq!(
+            parse_svg_path("M0,0 H10 V10 H0 Z").unwrap(),
+            std::slice::from_ref(&square)
+        );
+        // Coordinates after a move are lines
+        assert_eq!(parse_svg_path("m0 0 10 0 0 10 -10 0z").unwrap(), [square]);
+    }
+
+    #[test]
+    fn reads_numbers_written_without_separators() {
+        assert_eq!(
+            parse_svg_path("M.5.5L1e1-2 3,4z").unwrap(),
+            [vec![[0.5, 0.5], [10.0, -2.0], [3.0, 4.0]]]
+        );
+    }
+
+    #[test]
+    fn keeps_each_closed_contour() {
+        let contours = parse_svg_path("M0 0h4v4h-4z M10 10h4v4h-4z").unwrap();
+        assert_eq!(contours.len(), 2);
+        assert_eq!(contours[1][0], [10.0, 10.0]);
+    }
+
+    #[test]
+    fn flattens_curves_to_their_end_points() {
+        let contours = parse_svg_path("M0 0 C0 10 10 10 10 0 S20 -10 20 0 Z").unwrap();
+        assert_eq!(contours[0].len(), 1 + 2 * CURVE_SEGMENTS);
+        assert_eq!(contours[0][CURVE_SEGMENTS], [10.0, 0.0]);
+        assert_eq!(contours[0][2 * CURVE_SEGMENTS], [20.0, 0.0]);
+
+        let contours = parse_svg_path("M0 0 q5 10 10 0 t10 0 z").unwrap();
+        assert_eq!(contours[0].len(), 1 + 2 * CURVE_SEGMENTS);
+        assert_eq!(contours[0][2 * CURVE_SEGMENTS], [20.0, 0.0]);
+    }
+
+    #[test]
+    fn rejects_what_it_cant_fill() {
+        assert!(parse_svg_path("").is_err());
+        assert!(parse_svg_path("M0 0 L1 1 Z").is_err());
+        assert!(parse_svg_path("M0 0 A5 5 0 0 1 10 0 Z").is_err());
+        assert!(parse_svg_path("M0 0 X10 0").is_err());
+        assert!(parse_svg_path("M0 0 L10").is_err());
+    }
+}
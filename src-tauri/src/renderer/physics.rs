@@ -0,0 +1,530 @@
+use rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::pipelines::Pipelines;
+use super::upload::Uploads;
+use super::{clip, fill, MemoryUsage};
+
+/// Rapier's tolerances are tuned for bodies about a meter across, so a body a hundred
+/// logical pixels across is simulated as one.
+const PIXELS_PER_METER: f32 = 100.0;
+/// The longest step the world takes, so a stalled frame doesn't throw bodies through walls.
+const MAX_STEP: f64 = 1.0 / 30.0;
+/// Half the thickness of the walls around the overlay, in meters.
+const WALL_HALF_THICKNESS: f32 = 1.0;
+/// Down, at about the rate things fall on screen in UI animations, in logical pixels per
+/// second squared.
+pub const DEFAULT_GRAVITY: [f64; 2] = [0.0, 980.0];
+
+/// A body's collision shape, which is also what it's drawn as. Sizes are in logical pixels.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum BodyShape {
+    Circle { radius: f64 },
+    Box { width: f64, height: f64 },
+}
+
+/// A body to spawn into an overlay's physics world.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BodyDescriptor {
+    pub id: String,
+    pub shape: BodyShape,
+    /// The body's center, in logical pixels.
+    pub position: [f64; 2],
+    /// Clockwise, in radians.
+    #[serde(default)]
+    pub rotation: f64,
+    /// In logical pixels per second.
+    #[serde(default)]
+    pub velocity: [f64; 2],
+    /// Clockwise, in radians per second.
+    #[serde(default)]
+    pub angular_velocity: f64,
+    /// Straight (not premultiplied) alpha.
+    pub color: [f64; 4],
+    /// How much of its speed the body keeps when it bounces, from 0 to 1.
+    #[serde(default = "default_restitution")]
+    pub restitution: f64,
+    #[serde(default = "default_friction")]
+    pub friction: f64,
+    /// Fixed bodies don't move, but others collide with them, like shelves to land on.
+    #[serde(default)]
+    pub fixed: bool,
+}
+
+fn default_restitution() -> f64 {
+    0.5
+}
+
+fn default_friction() -> f64 {
+    0.5
+}
+
+/// Where a body is now, as `get_physics_bodies` reports it. Units are as in
+/// [`BodyDescriptor`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BodyState {
+    pub id: String,
+    pub position: [f64; 2],
+    pub rotation: f64,
+    pub velocity: [f64; 2],
+    pub angular_velocity: f64,
+    /// Bodies that come to rest stop being simulated until something disturbs them.
+    pub sleeping: bool,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct BodyInstance {
+    center: [f32; 2],
+    half_size: [f32; 2],
+    rotation: f32,
+    /// 1 for circles, which are rounded off in the fragment shader.
+    circle: f32,
+    color: [f32; 4],
+}
+
+struct Body {
+    id: String,
+    handle: RigidBodyHandle,
+    shape: BodyShape,
+    /// Premultiplied.
+    color: [f32; 4],
+}
+
+/// Rapier's state for one world.
+struct World {
+    gravity: Vector<Real>,
+    parameters: IntegrationParameters,
+    pipeline: PhysicsPipeline,
+    islands: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    joints: JointSet,
+    ccd_solver: CCDSolver,
+}
+
+impl World {
+    fn new() -> Self {
+        World {
+            gravity: to_meters(DEFAULT_GRAVITY),
+            parameters: IntegrationParameters::default(),
+            pipeline: PhysicsPipeline::new(),
+            islands: IslandManager::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            bodies: RigidBodySet::new(),
+            colliders: ColliderSet::new(),
+            joints: JointSet::new(),
+            ccd_solver: CCDSolver::new(),
+        }
+    }
+
+    fn step(&mut self, seconds: f64) {
+        self.parameters.dt = seconds.min(MAX_STEP) as Real;
+        self.pipeline.step(
+            &self.gravity,
+            &self.parameters,
+            &mut self.islands,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.joints,
+            &mut self.ccd_solver,
+            &(),
+            &(),
+        );
+    }
+}
+
+/// A 2D physics world that's simulated and drawn by the overlay, for things like badges that
+/// fall and pile up or notifications that bounce in. Bodies are drawn over layers (but under
+/// ink) in the order they were spawned, and are kept in by walls along the overlay's sides
+/// and bottom. The top is open, so bodies can fall in from above it.
+pub struct PhysicsLayer {
+    world: World,
+    /// In spawn order, which is the order they're drawn in.
+    bodies: Vec<Body>,
+    /// The surface in logical pixels when the walls were put up, and the walls.
+    walls: Option<([f32; 2], Vec<ColliderHandle>)>,
+    pipeline: wgpu::RenderPipeline,
+    uniforms: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// Grown as bodies are spawned, and written every frame. Kept with its size in bytes.
+    instances: Option<(wgpu::Buffer, wgpu::BufferAddress)>,
+    instance_count: u32,
+}
+
+impl PhysicsLayer {
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        pipelines: &Pipelines,
+    ) -> Self {
+        let shader = pipelines.shader(
+            device,
+            "Physics Shader",
+            include_str!("shaders/physics.wgsl"),
+        );
+
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Physics Uniforms"),
+            size: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = pipelines.bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Physics Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Physics Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniforms.as_entire_binding(),
+            }],
+        });
+
+        let layout = pipelines.pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Physics Pipeline Layout"),
+                bind_group_layouts: &[Some(&bind_group_layout)],
+                immediate_size: 0,
+            },
+        );
+
+        let pipeline = pipelines.render_pipeline(
+            device,
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Physics Pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[Some(wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<BodyInstance>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &wgpu::vertex_attr_array![
+                            0 => Float32x2,
+                            1 => Float32x2,
+                            2 => Float32,
+                            3 => Float32,
+                            4 => Float32x4
+                        ],
+                    })],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: color_format,
+                        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                depth_stencil: Some(clip::clipped_depth_stencil()),
+                multisample: wgpu::MultisampleState::default(),
+                multiview_mask: None,
+                cache: pipelines.cache(),
+            },
+        );
+
+        PhysicsLayer {
+            world: World::new(),
+            bodies: Vec::new(),
+            walls: None,
+            pipeline,
+            uniforms,
+            bind_group,
+            instances: None,
+            instance_count: 0,
+        }
+    }
+
+    /// Add a body, replacing any with the same id.
+    pub fn spawn(&mut self, descriptor: BodyDescriptor) -> Result<(), String> {
+        let (collider, half_size) = match descriptor.shape {
+            BodyShape::Circle { radius } if radius > 0.0 => (
+                ColliderBuilder::ball(radius as f32 / PIXELS_PER_METER),
+                radius,
+            ),
+            BodyShape::Box { width, height } if width > 0.0 && height > 0.0 => (
+                ColliderBuilder::cuboid(
+                    width as f32 / 2.0 / PIXELS_PER_METER,
+                    height as f32 / 2.0 / PIXELS_PER_METER,
+                ),
+                width.max(height) / 2.0,
+            ),
+            _ => return Err(format!("body '{}' has no size", descriptor.id)),
+        };
+        self.remove(&descriptor.id);
+
+        let builder = match descriptor.fixed {
+            true => RigidBodyBuilder::new_static(),
+            false => RigidBodyBuilder::new_dynamic(),
+        };
+        let body = builder
+            .translation(to_meters(descriptor.position))
+            .rotation(descriptor.rotation as Real)
+            .linvel(to_meters(descriptor.velocity))
+            .angvel(descriptor.angular_velocity as Real)
+            // Small, fast bodies would otherwise pass right through the walls
+            .ccd_enabled(half_size < 20.0)
+            .build();
+        let handle = self.world.bodies.insert(body);
+        let collider = collider
+            .restitution(descriptor.restitution.clamp(0.0, 1.0) as Real)
+            .friction(descriptor.friction.max(0.0) as Real)
+            .build();
+        self.world
+            .colliders
+            .insert_with_parent(collider, handle, &mut self.world.bodies);
+
+        let [r, g, b, a] = descriptor.color;
+        let color = fill::premultiply(wgpu::Color { r, g, b, a });
+        self.bodies.push(Body {
+            id: descriptor.id,
+            handle,
+            shape: descriptor.shape,
+            color: [
+                color.r as f32,
+                color.g as f32,
+                color.b as f32,
+                color.a as f32,
+            ],
+        });
+        Ok(())
+    }
+
+    /// Push the body with `impulse` and spin it clockwise with `torque`. Heavier bodies move
+    /// less: an impulse of 100 speeds a body a hundred pixels square up by 100 logical pixels
+    /// per second, and a torque of 1 spins it at about six radians per second.
+    pub fn apply_impulse(
+        &mut self,
+        id: &str,
+        impulse: [f64; 2],
+        torque: f64,
+    ) -> Result<(), String> {
+        let handle = self
+            .bodies
+            .iter()
+            .find(|body| body.id == id)
+            .map(|body| body.handle)
+            .ok_or_else(|| format!("there is no body called '{}'", id))?;
+        if let Some(body) = self.world.bodies.get_mut(handle) {
+            body.apply_impulse(to_meters(impulse), true);
+            body.apply_torque_impulse(torque as Real, true);
+        }
+        Ok(())
+    }
+
+    /// Remove the body called `id`, returning whether there was one.
+    pub fn remove(&mut self, id: &str) -> bool {
+        let index = match self.bodies.iter().position(|body| body.id == id) {
+            Some(index) => index,
+            None => return false,
+        };
+        let body = self.bodies.remove(index);
+        self.world.bodies.remove(
+            body.handle,
+            &mut self.world.islands,
+            &mut self.world.colliders,
+            &mut self.world.joints,
+        );
+        true
+    }
+
+    /// Remove every body, keeping the walls and gravity.
+    pub fn clear(&mut self) {
+        for body in std::mem::take(&mut self.bodies) {
+            self.world.bodies.remove(
+                body.handle,
+                &mut self.world.islands,
+                &mut self.world.colliders,
+                &mut self.world.joints,
+            );
+        }
+    }
+
+    /// Set gravity, in logical pixels per second squared.
+    pub fn set_gravity(&mut self, gravity: [f64; 2]) {
+        self.world.gravity = to_meters(gravity);
+    }
+
+    pub fn gravity(&self) -> [f64; 2] {
+        to_pixels(&self.world.gravity)
+    }
+
+    pub fn bodies(&self) -> Vec<BodyState> {
+        self.bodies
+            .iter()
+            .map(|body| {
+                let rigid_body = &self.world.bodies[body.handle];
+                BodyState {
+                    id: body.id.clone(),
+                    position: to_pixels(rigid_body.translation()),
+                    rotation: rigid_body.rotation().angle() as f64,
+                    velocity: to_pixels(rigid_body.linvel()),
+                    angular_velocity: rigid_body.angvel() as f64,
+                    sleeping: rigid_body.is_sleeping(),
+                }
+            })
+            .collect()
+    }
+
+    /// Advance the simulation by `seconds`, if there's anything to simulate, with the walls
+    /// around a surface of `size` logical pixels.
+    pub fn step(&mut self, seconds: f64, size: [f32; 2]) {
+        if self.bodies.is_empty() || seconds <= 0.0 {
+            return;
+        }
+        if self.walls.as_ref().map(|(walls_size, _)| *walls_size) != Some(size) {
+            self.build_walls(size);
+        }
+        self.world.step(seconds);
+    }
+
+    /// Upload where the bodies are. `size` is the surface in logical pixels.
+    pub fn prepare(&mut self, device: &wgpu::Device, uploads: &mut Uploads, size: [f32; 2]) {
+        if self.bodies.is_empty() {
+            self.instance_count = 0;
+            return;
+        }
+        uploads.write(&self.uniforms, 0, bytemuck::cast_slice(&size));
+
+        let instances: Vec<BodyInstance> = self
+            .bodies
+            .iter()
+            .map(|body| {
+                let rigid_body = &self.world.bodies[body.handle];
+                let (half_size, circle) = match body.shape {
+                    BodyShape::Circle { radius } => ([radius as f32; 2], 1.0),
+                    BodyShape::Box { width, height } => {
+                        ([width as f32 / 2.0, height as f32 / 2.0], 0.0)
+                    }
+                };
+                let center = rigid_body.translation();
+                BodyInstance {
+                    center: [center.x * PIXELS_PER_METER, center.y * PIXELS_PER_METER],
+                    half_size,
+                    rotation: rigid_body.rotation().angle(),
+                    circle,
+                    color: body.color,
+                }
+            })
+            .collect();
+
+        let needed = (instances.len() * std::mem::size_of::<BodyInstance>()) as wgpu::BufferAddress;
+        if self
+            .instances
+            .as_ref()
+            .is_none_or(|(_, capacity)| *capacity < needed)
+        {
+            // Room to spawn a few more before growing again
+            let capacity = needed.next_power_of_two();
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Physics Instances"),
+                size: capacity,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            self.instances = Some((buffer, capacity));
+        }
+        if let Some((buffer, _)) = &self.instances {
+            uploads.write(buffer, 0, bytemuck::cast_slice(&instances));
+        }
+        self.instance_count = instances.len() as u32;
+    }
+
+    fn build_walls(&mut self, size: [f32; 2]) {
+        if let Some((_, walls)) = self.walls.take() {
+            for wall in walls {
+                self.world.colliders.remove(
+                    wall,
+                    &mut self.world.islands,
+                    &mut self.world.bodies,
+                    true,
+                );
+            }
+        }
+        let [width, height] = [size[0] / PIXELS_PER_METER, size[1] / PIXELS_PER_METER];
+        let thickness = WALL_HALF_THICKNESS;
+        // The sides go up as far again above the top, to catch bodies thrown upwards
+        let walls = [
+            (
+                [width / 2.0 + thickness * 2.0, thickness],
+                [width / 2.0, height + thickness],
+            ),
+            ([thickness, height], [-thickness, 0.0]),
+            ([thickness, height], [width + thickness, 0.0]),
+        ];
+        let walls = walls
+            .iter()
+            .map(|&([half_width, half_height], [x, y])| {
+                let collider = ColliderBuilder::cuboid(half_width, half_height)
+                    .translation(vector![x, y])
+                    .build();
+                self.world.colliders.insert(collider)
+            })
+            .collect();
+        self.walls = Some((size, walls));
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        usage.add_buffer(&self.uniforms);
+        if let Some((instances, _)) = &self.instances {
+            usage.add_buffer(instances);
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if let Some((buffer, _)) = self.instances.as_ref().filter(|_| self.instance_count > 0) {
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_stencil_reference(clip::STENCIL_REFERENCE);
+            render_pass.set_bind_group(0, &self.bind_group, &[]);
+            render_pass.set_vertex_buffer(0, buffer.slice(..));
+            render_pass.draw(0..4, 0..self.instance_count);
+        }
+    }
+}
+
+fn to_meters(pixels: [f64; 2]) -> Vector<Real> {
+    vector![
+        pixels[0] as f32 / PIXELS_PER_METER,
+        pixels[1] as f32 / PIXELS_PER_METER
+    ]
+}
+
+fn to_pixels(meters: &Vector<Real>) -> [f64; 2] {
+    [
+        (meters.x * PIXELS_PER_METER) as f64,
+        (meters.y * PIXELS_PER_METER) as f64,
+    ]
+}
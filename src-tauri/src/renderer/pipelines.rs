@@ -0,0 +1,292 @@
+//! Pipelines kept between uses, so opening an overlay, loading a shader or setting layers
+//! again doesn't stall a frame compiling what's already been compiled. Everything is keyed on
+//! a hash of what it's made from: shaders on their WGSL, bind group layouts on their entries,
+//! and pipelines on those and the rest of their state, so every overlay drawing the same thing
+//! shares one pipeline. wgpu's pipeline cache is saved to disk where the driver has one, so
+//! the next run starts with the shaders it compiled.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use super::isf::IsfPipeline;
+use crate::assets::IsfAsset;
+
+/// Where the pipeline cache is saved, under the app's data directory.
+const CACHE_DIR: &str = "pipeline-cache";
+
+pub struct Pipelines {
+    /// Passed to every pipeline that's created. `None` unless the device has the
+    /// `PIPELINE_CACHE` feature, which only Vulkan drivers give.
+    cache: Option<wgpu::PipelineCache>,
+    /// Where `cache` is read from and saved to. The file is named for the adapter and driver,
+    /// so one doesn't get another's cache.
+    path: Option<PathBuf>,
+    /// The vertex half of every ISF shader.
+    isf_vertex: wgpu::ShaderModule,
+    /// Held while creating, so overlays asking for the same thing at once only create it once.
+    created: Mutex<Created>,
+    /// By the hash of the shader's WGSL, its bind group layout and the format it draws to.
+    isf: Mutex<HashMap<u64, Arc<IsfPipeline>>>,
+}
+
+/// Everything that's been created, each by the hash of what it was created from. Shaders and
+/// layouts are never dropped, so pipelines look up what they're made from by value.
+#[derive(Default)]
+struct Created {
+    shaders: HashMap<u64, wgpu::ShaderModule>,
+    bind_group_layouts: HashMap<u64, wgpu::BindGroupLayout>,
+    pipeline_layouts: HashMap<u64, wgpu::PipelineLayout>,
+    render_pipelines: HashMap<u64, wgpu::RenderPipeline>,
+    compute_pipelines: HashMap<u64, wgpu::ComputePipeline>,
+}
+
+/// The key `value` is kept under in `created`, panicking if it wasn't created by [`Pipelines`].
+fn key_of<T: PartialEq>(created: &HashMap<u64, T>, value: &T, kind: &str) -> u64 {
+    created
+        .iter()
+        .find(|(_, created)| *created == value)
+        .map(|(key, _)| *key)
+        .unwrap_or_else(|| panic!("{} wasn't created by Pipelines", kind))
+}
+
+fn hash(value: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl Created {
+    fn layout_key(&self, layout: Option<&wgpu::PipelineLayout>) -> Option<u64> {
+        layout.map(|layout| key_of(&self.pipeline_layouts, layout, "pipeline layout"))
+    }
+
+    /// The key of a shader stage: its module's WGSL, the entry point and what it's compiled
+    /// with.
+    fn stage_key(
+        &self,
+        module: &wgpu::ShaderModule,
+        entry_point: Option<&str>,
+        options: &wgpu::PipelineCompilationOptions,
+    ) -> u64 {
+        let constants: Vec<(&str, u64)> = options
+            .constants
+            .iter()
+            .map(|(name, value)| (*name, value.to_bits()))
+            .collect();
+        hash((
+            key_of(&self.shaders, module, "shader module"),
+            entry_point,
+            constants,
+            options.zero_initialize_workgroup_memory,
+        ))
+    }
+}
+
+impl Pipelines {
+    /// Start with the cache saved in `data_dir` by the last run, if there's one for `adapter`.
+    pub fn new(
+        device: &wgpu::Device,
+        adapter: &wgpu::AdapterInfo,
+        data_dir: Option<&Path>,
+    ) -> Self {
+        let path = data_dir
+            .zip(wgpu::util::pipeline_cache_key(adapter))
+            .map(|(dir, key)| dir.join(CACHE_DIR).join(key));
+        let cache = device
+            .features()
+            .contains(wgpu::Features::PIPELINE_CACHE)
+            .then(|| {
+                let data = path.as_ref().and_then(|path| fs::read(path).ok());
+                // Safety: the data is only ever what `save` wrote for this adapter and driver,
+                // and `fallback` starts an empty cache if the driver won't take it
+                unsafe {
+                    device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                        label: Some("Pipeline Cache"),
+                        data: data.as_deref(),
+                        fallback: true,
+                    })
+                }
+            });
+        let isf_vertex = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("ISF Vertex Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/isf.wgsl").into()),
+        });
+        Pipelines {
+            cache,
+            path,
+            isf_vertex,
+            created: Mutex::new(Created::default()),
+            isf: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn cache(&self) -> Option<&wgpu::PipelineCache> {
+        self.cache.as_ref()
+    }
+
+    /// The module compiled from the WGSL `source`, compiled the first time it's asked for.
+    pub fn shader(&self, device: &wgpu::Device, label: &str, source: &str) -> wgpu::ShaderModule {
+        let mut created = self.created.lock().unwrap();
+        created
+            .shaders
+            .entry(hash(source))
+            .or_insert_with(|| {
+                device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: Some(label),
+                    source: wgpu::ShaderSource::Wgsl(source.into()),
+                })
+            })
+            .clone()
+    }
+
+    /// A bind group layout with `descriptor`'s entries, shared with everything else using
+    /// the same ones.
+    pub fn bind_group_layout(
+        &self,
+        device: &wgpu::Device,
+        descriptor: &wgpu::BindGroupLayoutDescriptor,
+    ) -> wgpu::BindGroupLayout {
+        let mut created = self.created.lock().unwrap();
+        created
+            .bind_group_layouts
+            .entry(hash(descriptor.entries))
+            .or_insert_with(|| device.create_bind_group_layout(descriptor))
+            .clone()
+    }
+
+    /// A pipeline layout of bind group layouts from [`Pipelines::bind_group_layout`].
+    pub fn pipeline_layout(
+        &self,
+        device: &wgpu::Device,
+        descriptor: &wgpu::PipelineLayoutDescriptor,
+    ) -> wgpu::PipelineLayout {
+        let mut created = self.created.lock().unwrap();
+        let bind_group_layouts: Vec<Option<u64>> = descriptor
+            .bind_group_layouts
+            .iter()
+            .map(|layout| {
+                layout
+                    .map(|layout| key_of(&created.bind_group_layouts, layout, "bind group layout"))
+            })
+            .collect();
+        let key = hash((bind_group_layouts, descriptor.immediate_size));
+        created
+            .pipeline_layouts
+            .entry(key)
+            .or_insert_with(|| device.create_pipeline_layout(descriptor))
+            .clone()
+    }
+
+    /// The pipeline `descriptor` describes, created the first time one like it is asked for.
+    /// Its shaders and layout must come from [`Pipelines`].
+    pub fn render_pipeline(
+        &self,
+        device: &wgpu::Device,
+        descriptor: &wgpu::RenderPipelineDescriptor,
+    ) -> wgpu::RenderPipeline {
+        let mut created = self.created.lock().unwrap();
+        let vertex = &descriptor.vertex;
+        let fragment = descriptor.fragment.as_ref().map(|fragment| {
+            (
+                created.stage_key(
+                    fragment.module,
+                    fragment.entry_point,
+                    &fragment.compilation_options,
+                ),
+                fragment.targets,
+            )
+        });
+        let key = hash((
+            created.layout_key(descriptor.layout),
+            created.stage_key(
+                vertex.module,
+                vertex.entry_point,
+                &vertex.compilation_options,
+            ),
+            vertex.buffers,
+            fragment,
+            descriptor.primitive,
+            &descriptor.depth_stencil,
+            descriptor.multisample,
+            descriptor.multiview_mask,
+        ));
+        created
+            .render_pipelines
+            .entry(key)
+            .or_insert_with(|| device.create_render_pipeline(descriptor))
+            .clone()
+    }
+
+    /// The compute pipeline `descriptor` describes, like [`Pipelines::render_pipeline`].
+    pub fn compute_pipeline(
+        &self,
+        device: &wgpu::Device,
+        descriptor: &wgpu::ComputePipelineDescriptor,
+    ) -> wgpu::ComputePipeline {
+        let mut created = self.created.lock().unwrap();
+        let key = hash((
+            created.layout_key(descriptor.layout),
+            created.stage_key(
+                descriptor.module,
+                descriptor.entry_point,
+                &descriptor.compilation_options,
+            ),
+        ));
+        created
+            .compute_pipelines
+            .entry(key)
+            .or_insert_with(|| device.create_compute_pipeline(descriptor))
+            .clone()
+    }
+
+    /// The pipeline that draws the ISF shader `isf` into `color_format` targets, created the
+    /// first time it's asked for. ISF shaders are compiled as they're loaded, so these are
+    /// kept apart from other pipelines and dropped once nothing draws them.
+    pub fn isf(
+        &self,
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        isf: &IsfAsset,
+    ) -> Arc<IsfPipeline> {
+        let entries = IsfPipeline::entries(isf);
+        let key = hash((&isf.wgsl, &entries, color_format));
+        let mut pipelines = self.isf.lock().unwrap();
+        if let Some(pipeline) = pipelines.get(&key) {
+            return pipeline.clone();
+        }
+        pipelines.retain(|_, pipeline| Arc::strong_count(pipeline) > 1);
+        let pipeline = Arc::new(IsfPipeline::new(
+            device,
+            self,
+            &self.isf_vertex,
+            color_format,
+            isf,
+            &entries,
+        ));
+        pipelines.insert(key, pipeline.clone());
+        pipeline
+    }
+
+    /// Write the pipeline cache to disk for the next run. Does nothing if there's no cache.
+    pub fn save(&self) -> Result<(), String> {
+        let (cache, path) = match (&self.cache, &self.path) {
+            (Some(cache), Some(path)) => (cache, path),
+            _ => return Ok(()),
+        };
+        let data = match cache.get_data() {
+            Some(data) => data,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        // Written alongside first, so a run that's stopped halfway doesn't leave half a cache
+        let partial = path.with_extension("partial");
+        fs::write(&partial, data).map_err(|e| e.to_string())?;
+        fs::rename(&partial, path).map_err(|e| e.to_string())
+    }
+}
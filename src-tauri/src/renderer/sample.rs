@@ -0,0 +1,106 @@
+/// A square of physical pixels to average, clamped to the frame.
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Region {
+    /// The pixels within `radius` of (`x`, `y`), or an error if that point is outside
+    /// a frame of `size`.
+    pub fn around(
+        x: f64,
+        y: f64,
+        radius: u32,
+        size: tauri::PhysicalSize<u32>,
+    ) -> Result<Self, String> {
+        if x < 0.0 || y < 0.0 || x >= size.width as f64 || y >= size.height as f64 {
+            return Err(format!("({}, {}) is outside the frame", x, y));
+        }
+        let (x, y) = (x as u32, y as u32);
+        // Any bigger already covers the whole frame
+        let radius = radius.min(size.width.max(size.height));
+        let left = x.saturating_sub(radius);
+        let top = y.saturating_sub(radius);
+        let right = x.saturating_add(radius).saturating_add(1).min(size.width);
+        let bottom = y.saturating_add(radius).saturating_add(1).min(size.height);
+        Ok(Region {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        })
+    }
+}
+
+/// The average color of `region` in a frame's `rows`, top to bottom, as straight alpha RGBA
+/// in the same sRGB space as the page's colors. The rows are in `format`, and can be padded
+/// past the frame's width.
+pub fn average<'a>(
+    rows: impl Iterator<Item = &'a [u8]>,
+    format: wgpu::TextureFormat,
+    region: Region,
+) -> Result<[f64; 4], String> {
+    let (bgra, srgb) = match format {
+        wgpu::TextureFormat::Bgra8Unorm => (true, false),
+        wgpu::TextureFormat::Bgra8UnormSrgb => (true, true),
+        wgpu::TextureFormat::Rgba8Unorm => (false, false),
+        wgpu::TextureFormat::Rgba8UnormSrgb => (false, true),
+        format => return Err(format!("can't sample {:?} frames", format)),
+    };
+
+    // Averaged as premultiplied linear values, the way blending would mix them
+    let mut sum = [0.0; 4];
+    let (left, right) = (
+        region.x as usize * 4,
+        (region.x + region.width) as usize * 4,
+    );
+    let rows = rows.skip(region.y as usize).take(region.height as usize);
+    for row in rows {
+        for pixel in row[left..right].chunks(4) {
+            let (r, b) = if bgra {
+                (pixel[2], pixel[0])
+            } else {
+                (pixel[0], pixel[2])
+            };
+            for (channel, byte) in [r, pixel[1], b].into_iter().enumerate() {
+                let value = byte as f64 / 255.0;
+                sum[channel] += if srgb { srgb_to_linear(value) } else { value };
+            }
+            sum[3] += pixel[3] as f64 / 255.0;
+        }
+    }
+
+    let count = (region.width * region.height) as f64;
+    let [r, g, b, a] = sum.map(|channel| channel / count);
+    if a == 0.0 {
+        return Ok([0.0; 4]);
+    }
+    let straight = [r / a, g / a, b / a].map(|channel| {
+        let channel = channel.min(1.0);
+        if srgb {
+            linear_to_srgb(channel)
+        } else {
+            channel
+        }
+    });
+    Ok([straight[0], straight[1], straight[2], a])
+}
+
+fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
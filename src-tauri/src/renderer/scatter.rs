@@ -0,0 +1,789 @@
+use std::cell::Cell;
+
+use bytemuck::Zeroable;
+use serde::{Deserialize, Serialize};
+
+use super::lod;
+use super::navigation::Navigation;
+use super::pipelines::Pipelines;
+use super::upload::Uploads;
+use super::{clip, fill, target, GpuContext, MemoryUsage};
+
+/// Matches `workgroup_size` in the shader.
+const WORKGROUP_SIZE: u32 = 256;
+/// Workgroups in each row of a dispatch, so millions of points fit under the limit on
+/// workgroups along one dimension.
+const ROW_WORKGROUPS: u32 = 1024;
+/// Matches `ROW_INVOCATIONS` in the shader.
+const ROW_INVOCATIONS: u32 = ROW_WORKGROUPS * WORKGROUP_SIZE;
+/// How many selected indices come back when the frontend doesn't say.
+pub const DEFAULT_SELECTION_LIMIT: u32 = 10_000;
+/// Points are kept in blocks of this many, each with the box around its points, so blocks
+/// that are off screen can be left out of binning. Matches `BLOCK_POINTS` in the shader.
+const BLOCK_POINTS: u32 = 65536;
+/// Tiers of decimation: tier `t` bins every `2^t`th point and counts each `2^t` times.
+const TIERS: u32 = 4;
+/// Points are decimated while bins on screen would still get at least about this many
+/// each, where the density scale has already flattened out.
+const TIER_POINTS: f32 = 64.0;
+
+/// A scatterplot with room for `capacity` points, which are uploaded afterwards in chunks.
+/// At the default navigation, `bounds` fills the overlay with y going up.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScatterDescriptor {
+    pub capacity: u32,
+    /// The smallest x and y and then the largest, in the data's units.
+    pub bounds: [f64; 4],
+    /// Straight alpha.
+    #[serde(default = "default_color")]
+    pub color: [f64; 4],
+    /// What selected points are drawn in, straight alpha.
+    #[serde(default = "default_selection_color")]
+    pub selection_color: [f64; 4],
+    /// Points are counted into square bins this many logical pixels across, and each bin
+    /// is drawn by how many points landed in it.
+    #[serde(default = "default_bin_size")]
+    pub bin_size: f64,
+    /// How many points a bin needs to be fully opaque. Fewer fade out on a log scale.
+    #[serde(default = "default_saturation")]
+    pub saturation: f64,
+}
+
+fn default_color() -> [f64; 4] {
+    [0.2, 0.5, 1.0, 1.0]
+}
+
+fn default_selection_color() -> [f64; 4] {
+    [1.0, 0.5, 0.0, 1.0]
+}
+
+fn default_bin_size() -> f64 {
+    1.0
+}
+
+fn default_saturation() -> f64 {
+    100.0
+}
+
+/// A rectangle dragged out over the scatterplot, in logical pixels from the overlay's
+/// top-left corner.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ScatterBrush {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// The points under a brush, as `select_scatter_points` reports them.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScatterSelection {
+    /// How many points were selected, which can be more than there are indices.
+    pub count: u32,
+    /// Indices of selected points in the order they were uploaded, up to the limit asked
+    /// for.
+    pub indices: Vec<u32>,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct ScatterParams {
+    color: [f32; 4],
+    selection_color: [f32; 4],
+    x_axis: [f32; 2],
+    y_axis: [f32; 2],
+    origin: [f32; 2],
+    bins: [u32; 2],
+    brush_min: [f32; 2],
+    brush_max: [f32; 2],
+    bin_size: f32,
+    scale_factor: f32,
+    count: u32,
+    saturation: f32,
+    selected: u32,
+    limit: u32,
+    /// How many points the visible blocks have between them.
+    binned: u32,
+    /// Every this many of those is binned, and counted this many times.
+    stride: u32,
+}
+
+struct Scatterplot {
+    descriptor: ScatterDescriptor,
+    points: wgpu::Buffer,
+    count: u32,
+    /// The box around each block's points, as the smallest x and y and then the largest,
+    /// relative to the bounds. Only ever grows, as points are overwritten.
+    blocks: Vec<[f32; 4]>,
+    /// Indices of the blocks that are on screen, lowest first, as of the last `prepare`.
+    visible: Vec<u32>,
+    /// `visible`, for binning.
+    visible_blocks: wgpu::Buffer,
+    /// The tier of decimation, as of the last `prepare`.
+    tier: u32,
+    /// A bit for each point, set for selected points. Replaced by each selection.
+    selection: wgpu::Buffer,
+    selected: bool,
+    /// Counts for each bin of `grid`, and then the counts of selected points.
+    bins: wgpu::Buffer,
+    grid: [u32; 2],
+    bind_group: wgpu::BindGroup,
+    params: ScatterParams,
+}
+
+/// Scatterplots of millions of points, which stay on the GPU and are binned at the
+/// current zoom each time the view changes, so drawing costs the same however many there
+/// are. Only the blocks of points that are on screen are binned, and only some of those
+/// once there are many to a bin. Scatterplots go over volumes and under everything else.
+pub struct ScatterLayer {
+    clear_pipeline: wgpu::ComputePipeline,
+    bin_pipeline: wgpu::ComputePipeline,
+    select_pipeline: wgpu::ComputePipeline,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    hits_layout: wgpu::BindGroupLayout,
+    uniforms: wgpu::Buffer,
+    plot: Option<Scatterplot>,
+    /// Whether the bins need counting again before drawing.
+    stale: Cell<bool>,
+}
+
+impl ScatterLayer {
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        pipelines: &Pipelines,
+    ) -> Self {
+        let shader = pipelines.shader(
+            device,
+            "Scatter Shader",
+            include_str!("shaders/scatter.wgsl"),
+        );
+
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Scatter Uniforms"),
+            size: std::mem::size_of::<ScatterParams>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let storage_entry = |binding, visibility, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let both = wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT;
+        let bind_group_layout = pipelines.bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Scatter Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: both,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    storage_entry(1, wgpu::ShaderStages::COMPUTE, true),
+                    storage_entry(2, both, false),
+                    storage_entry(3, wgpu::ShaderStages::COMPUTE, false),
+                    storage_entry(4, wgpu::ShaderStages::COMPUTE, true),
+                ],
+            },
+        );
+        let hits_layout = pipelines.bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Scatter Hits Bind Group Layout"),
+                entries: &[storage_entry(0, wgpu::ShaderStages::COMPUTE, false)],
+            },
+        );
+
+        let layout = pipelines.pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Scatter Pipeline Layout"),
+                bind_group_layouts: &[Some(&bind_group_layout)],
+                immediate_size: 0,
+            },
+        );
+        let select_layout = pipelines.pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Scatter Select Pipeline Layout"),
+                bind_group_layouts: &[Some(&bind_group_layout), Some(&hits_layout)],
+                immediate_size: 0,
+            },
+        );
+        let compute_pipeline = |label, layout, entry_point| {
+            pipelines.compute_pipeline(
+                device,
+                &wgpu::ComputePipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(layout),
+                    module: &shader,
+                    entry_point: Some(entry_point),
+                    compilation_options: Default::default(),
+                    cache: pipelines.cache(),
+                },
+            )
+        };
+        let clear_pipeline = compute_pipeline("Scatter Clear Pipeline", &layout, "clear");
+        let bin_pipeline = compute_pipeline("Scatter Bin Pipeline", &layout, "bin");
+        let select_pipeline =
+            compute_pipeline("Scatter Select Pipeline", &select_layout, "select_points");
+
+        let pipeline = pipelines.render_pipeline(
+            device,
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Scatter Pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: color_format,
+                        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(clip::clipped_depth_stencil()),
+                multisample: wgpu::MultisampleState::default(),
+                multiview_mask: None,
+                cache: pipelines.cache(),
+            },
+        );
+
+        ScatterLayer {
+            clear_pipeline,
+            bin_pipeline,
+            select_pipeline,
+            pipeline,
+            bind_group_layout,
+            hits_layout,
+            uniforms,
+            plot: None,
+            stale: Cell::new(false),
+        }
+    }
+
+    /// Make room for a scatterplot, replacing any there was. It's empty until points are
+    /// uploaded.
+    pub fn create(
+        &mut self,
+        device: &wgpu::Device,
+        descriptor: ScatterDescriptor,
+    ) -> Result<(), String> {
+        let [min_x, min_y, max_x, max_y] = descriptor.bounds;
+        if descriptor.bounds.iter().any(|bound| !bound.is_finite())
+            || max_x <= min_x
+            || max_y <= min_y
+        {
+            return Err("a scatterplot's bounds have to go from lower to higher values".into());
+        }
+        if !descriptor.bin_size.is_finite() || descriptor.bin_size <= 0.0 {
+            return Err("a scatterplot's bins have to be bigger than nothing".into());
+        }
+        let points_size = descriptor.capacity as u64 * std::mem::size_of::<[f32; 2]>() as u64;
+        let max = device.limits().max_storage_buffer_binding_size;
+        if descriptor.capacity == 0 || points_size > max {
+            return Err(format!(
+                "scatterplots can have from 1 to {} points on this GPU",
+                max / std::mem::size_of::<[f32; 2]>() as u64
+            ));
+        }
+
+        let points = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Scatter Points"),
+            size: points_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let blocks = descriptor.capacity.div_ceil(BLOCK_POINTS);
+        let visible_blocks = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Scatter Visible Blocks"),
+            size: (blocks as usize * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let selection = create_selection(device, descriptor.capacity);
+        let bins = create_bins(device, [1, 1]);
+        let bind_group = bind(
+            device,
+            &self.bind_group_layout,
+            &self.uniforms,
+            &points,
+            &selection,
+            &bins,
+            &visible_blocks,
+        );
+        self.plot = Some(Scatterplot {
+            descriptor,
+            points,
+            count: 0,
+            blocks: vec![EMPTY_BLOCK; blocks as usize],
+            visible: Vec::new(),
+            visible_blocks,
+            tier: 0,
+            selection,
+            selected: false,
+            bins,
+            grid: [1, 1],
+            bind_group,
+            params: ScatterParams::zeroed(),
+        });
+        self.stale.set(true);
+        Ok(())
+    }
+
+    /// Write `points` from index `first` on, as x and y in the data's units. Later uploads
+    /// can overwrite earlier ones.
+    pub fn upload_points(
+        &mut self,
+        queue: &wgpu::Queue,
+        first: u32,
+        points: &[[f64; 2]],
+    ) -> Result<(), String> {
+        let plot = self.plot.as_mut().ok_or("there's no scatterplot")?;
+        let end = first as u64 + points.len() as u64;
+        if end > plot.descriptor.capacity as u64 {
+            return Err(format!(
+                "the scatterplot only has room for {} points",
+                plot.descriptor.capacity
+            ));
+        }
+
+        // Stored relative to the bounds, since f32 doesn't have the precision for data
+        // like timestamps
+        let [min_x, min_y, max_x, max_y] = plot.descriptor.bounds;
+        let normalized: Vec<[f32; 2]> = points
+            .iter()
+            .map(|[x, y]| {
+                [
+                    ((x - min_x) / (max_x - min_x)) as f32,
+                    ((y - min_y) / (max_y - min_y)) as f32,
+                ]
+            })
+            .collect();
+        for (index, point) in (first..).zip(&normalized) {
+            let block = &mut plot.blocks[(index / BLOCK_POINTS) as usize];
+            *block = [
+                block[0].min(point[0]),
+                block[1].min(point[1]),
+                block[2].max(point[0]),
+                block[3].max(point[1]),
+            ];
+        }
+        let offset = first as u64 * std::mem::size_of::<[f32; 2]>() as u64;
+        queue.write_buffer(&plot.points, offset, bytemuck::cast_slice(&normalized));
+        plot.count = plot.count.max(end as u32);
+        self.stale.set(true);
+        Ok(())
+    }
+
+    /// Select the points under `brush`, replacing any selection there was, and block until
+    /// the GPU has found them.
+    pub fn select(
+        &mut self,
+        gpu: &GpuContext,
+        brush: ScatterBrush,
+        limit: u32,
+    ) -> Result<ScatterSelection, String> {
+        let device = &gpu.device;
+        let selection = match &self.plot {
+            Some(plot) => create_selection(device, plot.descriptor.capacity),
+            None => return Err("there's no scatterplot".into()),
+        };
+        let plot = self.plot.as_mut().unwrap();
+        let limit = limit.min(plot.descriptor.capacity);
+        let bind_group = bind(
+            device,
+            &self.bind_group_layout,
+            &self.uniforms,
+            &plot.points,
+            &selection,
+            &plot.bins,
+            &plot.visible_blocks,
+        );
+        plot.selection = selection;
+        plot.bind_group = bind_group;
+        plot.selected = true;
+        self.stale.set(true);
+
+        let params = ScatterParams {
+            brush_min: [
+                brush.x.min(brush.x + brush.width) as f32,
+                brush.y.min(brush.y + brush.height) as f32,
+            ],
+            brush_max: [
+                brush.x.max(brush.x + brush.width) as f32,
+                brush.y.max(brush.y + brush.height) as f32,
+            ],
+            count: plot.count,
+            limit,
+            ..plot.params
+        };
+        gpu.queue
+            .write_buffer(&self.uniforms, 0, bytemuck::bytes_of(&params));
+
+        let hits_size = ((limit as usize + 1) * std::mem::size_of::<u32>()) as wgpu::BufferAddress;
+        let hits = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Scatter Hits"),
+            size: hits_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Scatter Hits Readback"),
+            size: hits_size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let hits_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Scatter Hits Bind Group"),
+            layout: &self.hits_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: hits.as_entire_binding(),
+            }],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Scatter Select Encoder"),
+        });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Scatter Select Pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&self.select_pipeline);
+            compute_pass.set_bind_group(0, &plot.bind_group, &[]);
+            compute_pass.set_bind_group(1, &hits_bind_group, &[]);
+            dispatch(&mut compute_pass, plot.count);
+        }
+        encoder.copy_buffer_to_buffer(&hits, 0, &readback, 0, hits_size);
+        gpu.queue.submit(std::iter::once(encoder.finish()));
+
+        let data = target::map_read(device, readback.slice(..))
+            .map_err(|e| format!("failed to read back selection: {}", e))?;
+        let words: Vec<u32> = bytemuck::cast_slice(&data).to_vec();
+        drop(data);
+        readback.unmap();
+
+        let count = words[0];
+        // Found in whatever order the GPU got to them
+        let mut indices = words[1..=(count.min(limit) as usize)].to_vec();
+        indices.sort_unstable();
+        Ok(ScatterSelection { count, indices })
+    }
+
+    pub fn clear_selection(&mut self, device: &wgpu::Device) {
+        if let Some(plot) = &mut self.plot {
+            if plot.selected {
+                plot.selection = create_selection(device, plot.descriptor.capacity);
+                plot.bind_group = bind(
+                    device,
+                    &self.bind_group_layout,
+                    &self.uniforms,
+                    &plot.points,
+                    &plot.selection,
+                    &plot.bins,
+                    &plot.visible_blocks,
+                );
+                plot.selected = false;
+                self.stale.set(true);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.plot = None;
+    }
+
+    /// Size the bins for the overlay, find the blocks of points on screen, pick how far
+    /// to decimate them and upload the view, navigated by `navigation`.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        uploads: &mut Uploads,
+        navigation: &Navigation,
+        logical_size: [f32; 2],
+        scale_factor: f64,
+    ) {
+        let plot = match &mut self.plot {
+            Some(plot) => plot,
+            None => return,
+        };
+        let descriptor = &plot.descriptor;
+        let bin_size = descriptor.bin_size;
+        let [width, height] = [logical_size[0] as f64, logical_size[1] as f64];
+        let grid = [
+            ((width / bin_size).ceil() as u32).max(1),
+            ((height / bin_size).ceil() as u32).max(1),
+        ];
+        if grid != plot.grid {
+            plot.bins = create_bins(device, grid);
+            plot.grid = grid;
+            plot.bind_group = bind(
+                device,
+                &self.bind_group_layout,
+                &self.uniforms,
+                &plot.points,
+                &plot.selection,
+                &plot.bins,
+                &plot.visible_blocks,
+            );
+            self.stale.set(true);
+        }
+
+        // The bounds' corners on the overlay, before navigation, are (0, height) at the
+        // smallest x and y and (width, 0) at the largest
+        let (sin, cos) = navigation.rotation.sin_cos();
+        let zoom = navigation.zoom;
+        let transform = |[x, y]: [f64; 2]| [zoom * (x * cos - y * sin), zoom * (x * sin + y * cos)];
+        let origin = transform([0.0, height]);
+        let to_f32 = |[x, y]: [f64; 2]| [x as f32, y as f32];
+        let (x_axis, y_axis) = (
+            to_f32(transform([width, 0.0])),
+            to_f32(transform([0.0, -height])),
+        );
+        let origin = to_f32([
+            navigation.offset[0] + origin[0],
+            navigation.offset[1] + origin[1],
+        ]);
+
+        let visible: Vec<u32> = plot
+            .blocks
+            .iter()
+            .enumerate()
+            .take(plot.count.div_ceil(BLOCK_POINTS) as usize)
+            .filter(|(_, block)| {
+                on_screen(
+                    **block,
+                    origin,
+                    x_axis,
+                    y_axis,
+                    [width as f32, height as f32],
+                )
+            })
+            .map(|(index, _)| index as u32)
+            .collect();
+        // Every block is full but the last
+        let binned = match visible.last() {
+            Some(&last) => {
+                (visible.len() as u32 - 1) * BLOCK_POINTS
+                    + (plot.count - last * BLOCK_POINTS).min(BLOCK_POINTS)
+            }
+            None => 0,
+        };
+        // The bins the bounds cover on screen, which points mostly fill
+        let area = (x_axis[0] * y_axis[1] - x_axis[1] * y_axis[0]).abs();
+        let covered = (area.min((width * height) as f32) / (bin_size * bin_size) as f32).max(1.0);
+        // Each tier halves the points that are binned, so this is the tier where bins
+        // would get exactly the target number
+        let tier = (binned as f32 / covered / TIER_POINTS).max(1.0).log2();
+        plot.tier = lod::pick(plot.tier, tier, TIERS);
+        if visible != plot.visible {
+            if !visible.is_empty() {
+                uploads.write(&plot.visible_blocks, 0, bytemuck::cast_slice(&visible));
+            }
+            plot.visible = visible;
+        }
+
+        let premultiplied = |[r, g, b, a]: [f64; 4]| {
+            let color = fill::premultiply(wgpu::Color { r, g, b, a });
+            [
+                color.r as f32,
+                color.g as f32,
+                color.b as f32,
+                color.a as f32,
+            ]
+        };
+        let params = ScatterParams {
+            color: premultiplied(descriptor.color),
+            selection_color: premultiplied(descriptor.selection_color),
+            x_axis,
+            y_axis,
+            origin,
+            bins: grid,
+            brush_min: [0.0; 2],
+            brush_max: [0.0; 2],
+            bin_size: bin_size as f32,
+            scale_factor: scale_factor as f32,
+            count: plot.count,
+            saturation: descriptor.saturation.max(1.0) as f32,
+            selected: plot.selected as u32,
+            limit: 0,
+            binned,
+            stride: 1 << plot.tier,
+        };
+        if params != plot.params {
+            plot.params = params;
+            self.stale.set(true);
+        }
+        uploads.write(&self.uniforms, 0, bytemuck::bytes_of(&params));
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        usage.add_buffer(&self.uniforms);
+        if let Some(plot) = &self.plot {
+            usage.add_buffer(&plot.points);
+            usage.add_buffer(&plot.visible_blocks);
+            usage.add_buffer(&plot.selection);
+            usage.add_buffer(&plot.bins);
+        }
+    }
+
+    /// Count the points into bins if the view or the points have changed since they were
+    /// last counted.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        let plot = match &self.plot {
+            Some(plot) if self.stale.get() => plot,
+            _ => return,
+        };
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Scatter Bin Pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_bind_group(0, &plot.bind_group, &[]);
+        compute_pass.set_pipeline(&self.clear_pipeline);
+        dispatch(&mut compute_pass, plot.grid[0] * plot.grid[1] * 2);
+        compute_pass.set_pipeline(&self.bin_pipeline);
+        dispatch(
+            &mut compute_pass,
+            plot.params.binned.div_ceil(plot.params.stride),
+        );
+        self.stale.set(false);
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if let Some(plot) = &self.plot {
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_stencil_reference(clip::STENCIL_REFERENCE);
+            render_pass.set_bind_group(0, &plot.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
+
+fn bind(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    uniforms: &wgpu::Buffer,
+    points: &wgpu::Buffer,
+    selection: &wgpu::Buffer,
+    bins: &wgpu::Buffer,
+    visible_blocks: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Scatter Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniforms.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: points.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: bins.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: selection.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: visible_blocks.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+/// The box around a block with no points in it yet, which grows to fit the first.
+const EMPTY_BLOCK: [f32; 4] = [
+    f32::INFINITY,
+    f32::INFINITY,
+    f32::NEG_INFINITY,
+    f32::NEG_INFINITY,
+];
+
+/// Whether any of `block` might be on an overlay `size` logical pixels across, with points
+/// landing at `origin + x * x_axis + y * y_axis`.
+fn on_screen(
+    block: [f32; 4],
+    origin: [f32; 2],
+    x_axis: [f32; 2],
+    y_axis: [f32; 2],
+    size: [f32; 2],
+) -> bool {
+    let [min_x, min_y, max_x, max_y] = block;
+    if min_x > max_x {
+        return false;
+    }
+    // The box around the block's corners on the overlay, which navigation can turn
+    (0..2).all(|i| {
+        let mut low = origin[i];
+        let mut high = origin[i];
+        for (axis, min, max) in [(x_axis, min_x, max_x), (y_axis, min_y, max_y)] {
+            low += (axis[i] * min).min(axis[i] * max);
+            high += (axis[i] * min).max(axis[i] * max);
+        }
+        high >= 0.0 && low <= size[i]
+    })
+}
+
+/// A new, and so zeroed, bit for each of `capacity` points.
+fn create_selection(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+    let words = capacity.div_ceil(32);
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Scatter Selection"),
+        size: (words as usize * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_bins(device: &wgpu::Device, grid: [u32; 2]) -> wgpu::Buffer {
+    let bins = grid[0] as usize * grid[1] as usize * 2;
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Scatter Bins"),
+        size: (bins * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    })
+}
+
+/// Run one invocation for each of `invocations`, in rows of [`ROW_INVOCATIONS`].
+fn dispatch(compute_pass: &mut wgpu::ComputePass, invocations: u32) {
+    let workgroups = invocations.div_ceil(WORKGROUP_SIZE);
+    if workgroups > 0 {
+        compute_pass.dispatch_workgroups(
+            workgroups.min(ROW_WORKGROUPS),
+            workgroups.div_ceil(ROW_WORKGROUPS),
+            1,
+        );
+    }
+}
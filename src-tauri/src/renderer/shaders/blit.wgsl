@@ -0,0 +1,20 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var filtered: texture_2d<f32>;
+
+@vertex
+fn vs_blit(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One oversized triangle that covers the whole target
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_blit(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureLoad(filtered, vec2<i32>(in.position.xy), 0);
+}
@@ -0,0 +1,136 @@
+struct Params {
+    brightness: f32,
+    contrast: f32,
+    kernel_size: u32,
+    lut_size: u32,
+    // Whether the input's color is premultiplied by its alpha
+    premultiplied: u32,
+    // Whether the input holds linear values, decoded from an sRGB texture, rather than
+    // encoded ones
+    linear: u32,
+}
+
+struct Kernel {
+    weights: array<f32>,
+}
+
+@group(0) @binding(0)
+var input: texture_2d<f32>;
+@group(0) @binding(1)
+var output: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+@group(1) @binding(0)
+var lut: texture_2d<f32>;
+@group(1) @binding(1)
+var lut_sampler: sampler;
+
+@group(1) @binding(2)
+var<storage, read> kernel: Kernel;
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(linear: vec3<f32>) -> vec3<f32> {
+    let c = max(linear, vec3<f32>(0.0));
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+// The pixel at `coords` as straight alpha, sRGB encoded color, which is what color
+// adjustments and lookup tables expect
+fn load_color(coords: vec2<i32>) -> vec4<f32> {
+    var color = textureLoad(input, coords, 0);
+    if (params.premultiplied != 0u && color.a > 0.0) {
+        color = vec4<f32>(color.rgb / color.a, color.a);
+    }
+    if (params.linear != 0u) {
+        color = vec4<f32>(linear_to_srgb(color.rgb), color.a);
+    }
+    return color;
+}
+
+// The reverse of `load_color`
+fn store_color(coords: vec2<i32>, unclamped: vec4<f32>) {
+    var color = clamp(unclamped, vec4<f32>(0.0), vec4<f32>(1.0));
+    if (params.linear != 0u) {
+        color = vec4<f32>(srgb_to_linear(color.rgb), color.a);
+    }
+    if (params.premultiplied != 0u) {
+        color = vec4<f32>(color.rgb * color.a, color.a);
+    }
+    textureStore(output, coords, color);
+}
+
+fn in_bounds(id: vec3<u32>) -> bool {
+    let size = textureDimensions(input);
+    return id.x < size.x && id.y < size.y;
+}
+
+@compute @workgroup_size(8, 8)
+fn brightness_contrast(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (!in_bounds(id)) {
+        return;
+    }
+    let coords = vec2<i32>(id.xy);
+    let color = load_color(coords);
+    // Contrast scales around mid grey, then brightness shifts everything
+    let rgb = (color.rgb - 0.5) * params.contrast + 0.5 + params.brightness;
+    store_color(coords, vec4<f32>(rgb, color.a));
+}
+
+@compute @workgroup_size(8, 8)
+fn apply_lut(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (!in_bounds(id)) {
+        return;
+    }
+    let coords = vec2<i32>(id.xy);
+    let color = load_color(coords);
+
+    // The table is `n` slices of n x n side by side, red across each slice, green down
+    // it and blue from slice to slice. Red and green are filtered by the sampler, and
+    // blue by mixing the two nearest slices.
+    let n = f32(params.lut_size);
+    let rgb = clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0)) * (n - 1.0);
+    let slice = floor(rgb.b);
+    let next = min(slice + 1.0, n - 1.0);
+    let x = rgb.r + 0.5;
+    let y = (rgb.g + 0.5) / n;
+    let lower = textureSampleLevel(lut, lut_sampler, vec2<f32>((slice * n + x) / (n * n), y), 0.0);
+    let upper = textureSampleLevel(lut, lut_sampler, vec2<f32>((next * n + x) / (n * n), y), 0.0);
+    // The table is an sRGB texture, so what comes out of it is linear
+    let mapped = linear_to_srgb(mix(lower.rgb, upper.rgb, rgb.b - slice));
+    store_color(coords, vec4<f32>(mapped, color.a));
+}
+
+@compute @workgroup_size(8, 8)
+fn convolve(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (!in_bounds(id)) {
+        return;
+    }
+    let coords = vec2<i32>(id.xy);
+    let last = vec2<i32>(textureDimensions(input)) - vec2<i32>(1);
+    let size = i32(params.kernel_size);
+    let half = size / 2;
+
+    // Premultiplied, so transparent pixels don't bleed their color into their neighbors,
+    // and edges repeat outwards
+    var sum = vec4<f32>(0.0);
+    for (var ky = 0; ky < size; ky = ky + 1) {
+        for (var kx = 0; kx < size; kx = kx + 1) {
+            let at = clamp(coords + vec2<i32>(kx - half, ky - half), vec2<i32>(0), last);
+            var color = textureLoad(input, at, 0);
+            if (params.premultiplied == 0u) {
+                color = vec4<f32>(color.rgb * color.a, color.a);
+            }
+            sum = sum + color * kernel.weights[ky * size + kx];
+        }
+    }
+
+    sum = clamp(sum, vec4<f32>(0.0), vec4<f32>(1.0));
+    if (params.premultiplied == 0u && sum.a > 0.0) {
+        sum = vec4<f32>(sum.rgb / sum.a, sum.a);
+    }
+    textureStore(output, coords, sum);
+}
@@ -0,0 +1,54 @@
+struct Params {
+    // Whether the input's color is premultiplied by its alpha
+    premultiplied: u32,
+    // Whether the input holds linear values, decoded from an sRGB texture
+    linear: u32,
+}
+
+// Red, green, blue and then luminance, 256 bins each
+struct Bins {
+    counts: array<atomic<u32>, 1024>,
+}
+
+@group(0) @binding(0)
+var input: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> params: Params;
+@group(0) @binding(2)
+var<storage, read_write> bins: Bins;
+
+fn linear_to_srgb(linear: vec3<f32>) -> vec3<f32> {
+    let c = max(linear, vec3<f32>(0.0));
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+fn bin(value: f32) -> u32 {
+    return u32(clamp(value, 0.0, 1.0) * 255.0 + 0.5);
+}
+
+@compute @workgroup_size(8, 8)
+fn count(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(input);
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+
+    // Transparent pixels aren't part of the image, so they aren't counted
+    var color = textureLoad(input, vec2<i32>(id.xy), 0);
+    if (color.a <= 0.0) {
+        return;
+    }
+    if (params.premultiplied != 0u) {
+        color = vec4<f32>(color.rgb / color.a, color.a);
+    }
+    // Binned as sRGB values, like the scopes in image editors
+    if (params.linear != 0u) {
+        color = vec4<f32>(linear_to_srgb(color.rgb), color.a);
+    }
+    let luminance = dot(color.rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+
+    atomicAdd(&bins.counts[bin(color.r)], 1u);
+    atomicAdd(&bins.counts[256u + bin(color.g)], 1u);
+    atomicAdd(&bins.counts[512u + bin(color.b)], 1u);
+    atomicAdd(&bins.counts[768u + bin(luminance)], 1u);
+}
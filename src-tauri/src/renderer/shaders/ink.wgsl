@@ -0,0 +1,30 @@
+struct InkUniforms {
+    // The surface size in logical pixels, which stroke points are measured in
+    size: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> ink: InkUniforms;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+    @location(0) position: vec2<f32>,
+    @location(1) depth: f32,
+    @location(2) color: vec4<f32>,
+) -> VertexOutput {
+    let ndc = position / ink.size * 2.0 - 1.0;
+    var out: VertexOutput;
+    out.position = vec4<f32>(ndc.x, -ndc.y, depth, 1.0);
+    out.color = color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
@@ -0,0 +1,18 @@
+// The vertex half of every ISF shader, whose fragment halves are translated from GLSL
+// when they're loaded.
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    // isf_FragNormCoord, from 0 to 1 with the origin at the bottom left
+    @location(0) norm_coord: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One oversized triangle that covers the whole target
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.norm_coord = uv;
+    return out;
+}
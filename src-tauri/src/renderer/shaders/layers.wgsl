@@ -0,0 +1,23 @@
+struct LayerUniforms {
+    opacity: f32,
+}
+
+@group(0) @binding(0)
+var layer_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> layer: LayerUniforms;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // One oversized triangle that covers the whole surface
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    // Layer targets are the same size as the surface, so pixels map one to one
+    let color = textureLoad(layer_texture, vec2<i32>(position.xy), 0);
+    // Colors are premultiplied, so fading scales every channel
+    return color * layer.opacity;
+}
@@ -0,0 +1,48 @@
+struct Uniforms {
+    // The overlay's size in logical pixels
+    size: vec2<f32>,
+}
+
+struct TileInput {
+    // Where the tile's corners land on the overlay, in logical pixels: the top-left one,
+    // and the way to the top-right and bottom-left ones from there
+    @location(0) origin: vec2<f32>,
+    @location(1) x_axis: vec2<f32>,
+    @location(2) y_axis: vec2<f32>,
+    // The part of the cached tile to draw, for drawing part of a zoomed out tile in
+    // place of one that hasn't loaded yet
+    @location(3) uv_offset: vec2<f32>,
+    @location(4) uv_scale: f32,
+    @location(5) layer: u32,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) layer: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+@group(0) @binding(1)
+var tiles: texture_2d_array<f32>;
+@group(0) @binding(2)
+var tile_sampler: sampler;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, tile: TileInput) -> VertexOutput {
+    // A strip of two triangles
+    let corner = vec2<f32>(f32(index & 1u), f32(index >> 1u));
+    let position = tile.origin + corner.x * tile.x_axis + corner.y * tile.y_axis;
+    var out: VertexOutput;
+    out.position = vec4<f32>(position.x / uniforms.size.x * 2.0 - 1.0, 1.0 - position.y / uniforms.size.y * 2.0, 0.0, 1.0);
+    out.uv = tile.uv_offset + corner * tile.uv_scale;
+    out.layer = tile.layer;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(tiles, tile_sampler, in.uv, i32(in.layer));
+    return vec4<f32>(color.rgb * color.a, color.a);
+}
@@ -0,0 +1,48 @@
+// The start of the terrain's uniforms, which models share
+struct SceneUniforms {
+    view_projection: mat4x4<f32>,
+    // Toward the sun
+    light: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> scene: SceneUniforms;
+
+// Matches the terrain, so models are lit like the ground they're on
+const AMBIENT: f32 = 0.35;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) color: vec3<f32>,
+}
+
+@vertex
+fn vs_main(
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(3) transform0: vec4<f32>,
+    @location(4) transform1: vec4<f32>,
+    @location(5) transform2: vec4<f32>,
+    @location(6) transform3: vec4<f32>,
+    @location(7) color: vec4<f32>,
+) -> VertexOutput {
+    let transform = mat4x4<f32>(transform0, transform1, transform2, transform3);
+    var out: VertexOutput;
+    out.position = scene.view_projection * transform * vec4<f32>(position, 1.0);
+    // Models are only scaled evenly and turned, which keeps normals at right angles
+    out.normal = (transform * vec4<f32>(normal, 0.0)).xyz;
+    out.color = color.rgb;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Models without normals are lit evenly
+    var diffuse = 1.0;
+    if (length(in.normal) > 0.0) {
+        diffuse = max(dot(normalize(in.normal), normalize(scene.light.xyz)), 0.0);
+    }
+    let shade = AMBIENT + (1.0 - AMBIENT) * diffuse;
+    return vec4<f32>(in.color * shade, 1.0);
+}
@@ -0,0 +1,47 @@
+struct PhysicsUniforms {
+    // The surface size in logical pixels, which bodies are measured in
+    size: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> physics: PhysicsUniforms;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    // From -1 to 1 across the body, for rounding off circles
+    @location(0) local: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) circle: f32,
+}
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) index: u32,
+    @location(0) center: vec2<f32>,
+    @location(1) half_size: vec2<f32>,
+    @location(2) rotation: f32,
+    @location(3) circle: f32,
+    @location(4) color: vec4<f32>,
+) -> VertexOutput {
+    // A strip of the body's four corners
+    let local = vec2<f32>(f32(index & 1u), f32((index >> 1u) & 1u)) * 2.0 - 1.0;
+    let offset = local * half_size;
+    let c = cos(rotation);
+    let s = sin(rotation);
+    let position = center + vec2<f32>(offset.x * c - offset.y * s, offset.x * s + offset.y * c);
+    let ndc = position / physics.size * 2.0 - 1.0;
+    var out: VertexOutput;
+    out.position = vec4<f32>(ndc.x, -ndc.y, 0.0, 1.0);
+    out.local = local;
+    out.color = color;
+    out.circle = circle;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Circles fade out over a pixel at their edge, instead of being jagged
+    let distance = length(in.local);
+    let edge = clamp((1.0 - distance) / fwidth(distance) + 0.5, 0.0, 1.0);
+    return in.color * select(1.0, edge, in.circle > 0.5);
+}
@@ -0,0 +1,160 @@
+struct Params {
+    // Premultiplied
+    color: vec4<f32>,
+    selection_color: vec4<f32>,
+    // Where a point lands on the overlay, in logical pixels, is
+    // origin + x * x_axis + y * y_axis for its position within the bounds
+    x_axis: vec2<f32>,
+    y_axis: vec2<f32>,
+    origin: vec2<f32>,
+    // Columns and rows of bins
+    bins: vec2<u32>,
+    // The brush's corners, in logical pixels
+    brush_min: vec2<f32>,
+    brush_max: vec2<f32>,
+    // Logical pixels on each side of a bin
+    bin_size: f32,
+    scale_factor: f32,
+    count: u32,
+    // How many points the bins go fully opaque at
+    saturation: f32,
+    // Whether there's a selection to count and highlight
+    selected: u32,
+    // How many selected indices there's room for
+    limit: u32,
+    // How many points the visible blocks have between them
+    binned: u32,
+    // Every this many of those is binned, and counted this many times
+    stride: u32,
+}
+
+struct Points {
+    points: array<vec2<f32>>,
+}
+
+// Counts of all points in each bin, and then of the selected ones
+struct Bins {
+    counts: array<atomic<u32>>,
+}
+
+// A bit for each point, set if it's selected
+struct Selection {
+    words: array<atomic<u32>>,
+}
+
+// Indices of the blocks of points that are on screen, lowest first
+struct VisibleBlocks {
+    blocks: array<u32>,
+}
+
+struct Hits {
+    count: atomic<u32>,
+    indices: array<u32>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+@group(0) @binding(1)
+var<storage, read> points: Points;
+@group(0) @binding(2)
+var<storage, read_write> bins: Bins;
+@group(0) @binding(3)
+var<storage, read_write> selection: Selection;
+@group(0) @binding(4)
+var<storage, read> visible: VisibleBlocks;
+@group(1) @binding(0)
+var<storage, read_write> hits: Hits;
+
+// Invocations in each row of a dispatch. Matches `ROW_INVOCATIONS` in scatter.rs.
+const ROW_INVOCATIONS: u32 = 262144u;
+// Points in each block. Matches `BLOCK_POINTS` in scatter.rs.
+const BLOCK_POINTS: u32 = 65536u;
+// Bins with a single point in them are still visible
+const MIN_OPACITY: f32 = 0.2;
+
+fn invocation(id: vec3<u32>) -> u32 {
+    return id.x + id.y * ROW_INVOCATIONS;
+}
+
+fn on_screen(index: u32) -> vec2<f32> {
+    let point = points.points[index];
+    return params.origin + point.x * params.x_axis + point.y * params.y_axis;
+}
+
+fn is_selected(index: u32) -> bool {
+    return (atomicLoad(&selection.words[index / 32u]) & (1u << (index % 32u))) != 0u;
+}
+
+@compute @workgroup_size(256)
+fn clear(@builtin(global_invocation_id) id: vec3<u32>) {
+    let slot = invocation(id);
+    if (slot < params.bins.x * params.bins.y * 2u) {
+        atomicStore(&bins.counts[slot], 0u);
+    }
+}
+
+@compute @workgroup_size(256)
+fn bin(@builtin(global_invocation_id) id: vec3<u32>) {
+    // Only visible blocks are binned, so invocations go through them in turn. The last
+    // block is the only one that isn't full, and it comes last.
+    let binned = invocation(id) * params.stride;
+    if (binned >= params.binned) {
+        return;
+    }
+    let index = visible.blocks[binned / BLOCK_POINTS] * BLOCK_POINTS + binned % BLOCK_POINTS;
+    let cell = floor(on_screen(index) / params.bin_size);
+    if (cell.x < 0.0 || cell.y < 0.0 || cell.x >= f32(params.bins.x) || cell.y >= f32(params.bins.y)) {
+        return;
+    }
+    let slot = u32(cell.y) * params.bins.x + u32(cell.x);
+    // Standing in for the points that were skipped
+    atomicAdd(&bins.counts[slot], params.stride);
+    if (params.selected != 0u && is_selected(index)) {
+        atomicAdd(&bins.counts[params.bins.x * params.bins.y + slot], params.stride);
+    }
+}
+
+@compute @workgroup_size(256)
+fn select_points(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = invocation(id);
+    if (index >= params.count) {
+        return;
+    }
+    let position = on_screen(index);
+    if (any(position < params.brush_min) || any(position > params.brush_max)) {
+        return;
+    }
+    atomicOr(&selection.words[index / 32u], 1u << (index % 32u));
+    let hit = atomicAdd(&hits.count, 1u);
+    if (hit < params.limit) {
+        hits.indices[hit] = index;
+    }
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // One triangle that covers the whole target
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let cell = vec2<u32>(position.xy / (params.bin_size * params.scale_factor));
+    if (cell.x >= params.bins.x || cell.y >= params.bins.y) {
+        discard;
+    }
+    let slot = cell.y * params.bins.x + cell.x;
+    let count = atomicLoad(&bins.counts[slot]);
+    if (count == 0u) {
+        discard;
+    }
+
+    // Density on a log scale, so sparse outliers show up next to dense clusters
+    let density = clamp(log2(1.0 + f32(count)) / log2(1.0 + params.saturation), 0.0, 1.0);
+    var color = params.color;
+    if (params.selected != 0u && atomicLoad(&bins.counts[params.bins.x * params.bins.y + slot]) > 0u) {
+        color = params.selection_color;
+    }
+    return color * (MIN_OPACITY + (1.0 - MIN_OPACITY) * density);
+}
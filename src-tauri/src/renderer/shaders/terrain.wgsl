@@ -0,0 +1,87 @@
+struct TerrainUniforms {
+    view_projection: mat4x4<f32>,
+    // Toward the sun
+    light: vec4<f32>,
+    colors: array<vec4<f32>, 4>,
+    // Each layer's lowest and highest elevation, how many times its image repeats, and 1 if
+    // it covers only that band
+    bands: array<vec4<f32>, 4>,
+    // The number of layers, 1 if the splat map weights them, and how far bands blend
+    params: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> terrain: TerrainUniforms;
+@group(0) @binding(1)
+var tiled_sampler: sampler;
+@group(0) @binding(2)
+var clamped_sampler: sampler;
+@group(0) @binding(3)
+var layer0: texture_2d<f32>;
+@group(0) @binding(4)
+var layer1: texture_2d<f32>;
+@group(0) @binding(5)
+var layer2: texture_2d<f32>;
+@group(0) @binding(6)
+var layer3: texture_2d<f32>;
+@group(0) @binding(7)
+var splat_map: texture_2d<f32>;
+
+// Dark sides of hills aren't black
+const AMBIENT: f32 = 0.35;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) elevation: f32,
+}
+
+@vertex
+fn vs_main(
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = terrain.view_projection * vec4<f32>(position, 1.0);
+    out.normal = normal;
+    out.uv = uv;
+    out.elevation = position.y;
+    return out;
+}
+
+fn band_weight(band: vec4<f32>, elevation: f32) -> f32 {
+    let margin = max(terrain.params.z, 0.0001);
+    let above_low = clamp((elevation - band.x) / margin + 1.0, 0.0, 1.0);
+    let below_high = clamp((band.y - elevation) / margin + 1.0, 0.0, 1.0);
+    return select(1.0, min(above_low, below_high), band.w > 0.5);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Layers that weren't given don't count
+    let given = step(vec4<f32>(0.5, 1.5, 2.5, 3.5), vec4<f32>(terrain.params.x));
+    var banded = given * vec4<f32>(
+        band_weight(terrain.bands[0], in.elevation),
+        band_weight(terrain.bands[1], in.elevation),
+        band_weight(terrain.bands[2], in.elevation),
+        band_weight(terrain.bands[3], in.elevation),
+    );
+    // Later layers go over earlier ones where their bands overlap
+    banded.z = banded.z * (1.0 - banded.w);
+    banded.y = banded.y * (1.0 - banded.z - banded.w);
+    banded.x = banded.x * (1.0 - banded.y - banded.z - banded.w);
+    let splatted = given * textureSample(splat_map, clamped_sampler, in.uv);
+    var weights = select(banded, splatted, terrain.params.y > 0.5);
+    let total = weights.x + weights.y + weights.z + weights.w;
+    weights = select(weights / total, vec4<f32>(1.0, 0.0, 0.0, 0.0), total < 0.0001);
+
+    let color = textureSample(layer0, tiled_sampler, in.uv * terrain.bands[0].z) * terrain.colors[0] * weights.x
+        + textureSample(layer1, tiled_sampler, in.uv * terrain.bands[1].z) * terrain.colors[1] * weights.y
+        + textureSample(layer2, tiled_sampler, in.uv * terrain.bands[2].z) * terrain.colors[2] * weights.z
+        + textureSample(layer3, tiled_sampler, in.uv * terrain.bands[3].z) * terrain.colors[3] * weights.w;
+    let diffuse = max(dot(normalize(in.normal), normalize(terrain.light.xyz)), 0.0);
+    let shade = AMBIENT + (1.0 - AMBIENT) * diffuse;
+    return vec4<f32>(color.rgb * shade, 1.0);
+}
@@ -0,0 +1,83 @@
+struct VolumeUniforms {
+    // The camera's position, and the tangent of half its field of view
+    eye: vec4<f32>,
+    // The camera's axes. Right has the aspect ratio after it, and forward how far apart
+    // samples are
+    right: vec4<f32>,
+    up: vec4<f32>,
+    forward: vec4<f32>,
+    // Half the volume's size on each side
+    extent: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> volume: VolumeUniforms;
+@group(0) @binding(1)
+var volume_texture: texture_3d<f32>;
+@group(0) @binding(2)
+var transfer_texture: texture_2d<f32>;
+@group(0) @binding(3)
+var volume_sampler: sampler;
+
+// Enough for a step per voxel through the longest diagonal of a large volume
+const MAX_STEPS: i32 = 2048;
+// Rays stop once what's in front hides anything further back
+const OPAQUE: f32 = 0.99;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One oversized triangle that covers the whole viewport
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    out.position = vec4<f32>(out.ndc, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let tangent = volume.eye.w;
+    let eye = volume.eye.xyz;
+    let direction = normalize(
+        volume.forward.xyz
+        + volume.right.xyz * in.ndc.x * tangent * volume.right.w
+        + volume.up.xyz * in.ndc.y * tangent
+    );
+
+    // Where the ray enters and leaves the volume's box
+    let extent = volume.extent.xyz;
+    let inverse = 1.0 / direction;
+    let a = (-extent - eye) * inverse;
+    let b = (extent - eye) * inverse;
+    let near = min(a, b);
+    let far = max(a, b);
+    let enter = max(max(max(near.x, near.y), near.z), 0.0);
+    let leave = min(min(far.x, far.y), far.z);
+    if (leave <= enter) {
+        discard;
+    }
+
+    let step = volume.forward.w;
+    var color = vec4<f32>(0.0);
+    var t = enter + step * 0.5;
+    for (var i: i32 = 0; i < MAX_STEPS; i = i + 1) {
+        if (t >= leave || color.a >= OPAQUE) {
+            break;
+        }
+        let p = eye + direction * t;
+        // The first column, row and slice are at the left, top and front
+        let uvw = vec3<f32>(0.5 - p.x / (2.0 * extent.x), 0.5 - p.y / (2.0 * extent.y), 0.5 + p.z / (2.0 * extent.z));
+        let value = textureSampleLevel(volume_texture, volume_sampler, uvw, 0.0).r;
+        let sample = textureSampleLevel(transfer_texture, volume_sampler, vec2<f32>(value, 0.5), 0.0);
+        // Transfer function opacities are per voxel, which is what a step is
+        let alpha = sample.a;
+        color = color + (1.0 - color.a) * vec4<f32>(sample.rgb * alpha, alpha);
+        t = t + step;
+    }
+    return color;
+}
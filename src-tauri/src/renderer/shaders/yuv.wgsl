@@ -0,0 +1,46 @@
+struct Conversion {
+    // Takes (Y, Cb, Cr, 1) to gamma encoded RGB
+    matrix: mat4x4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var luma: texture_2d<f32>;
+@group(0) @binding(1)
+var chroma: texture_2d<f32>;
+@group(0) @binding(2)
+var planes: sampler;
+@group(0) @binding(3)
+var<uniform> conversion: Conversion;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One oversized triangle that covers the whole frame
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let yuv = vec4<f32>(
+        textureSample(luma, planes, in.uv).r,
+        textureSample(chroma, planes, in.uv).rg,
+        1.0,
+    );
+    let encoded = clamp((conversion.matrix * yuv).rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+    // The frame is stored as sRGB, which encodes what's written. Video's transfer curve is
+    // close enough to sRGB's that decoding here stores the values as they are.
+    let decoded = select(
+        pow((encoded + 0.055) / 1.055, vec3<f32>(2.4)),
+        encoded / 12.92,
+        encoded <= vec3<f32>(0.04045),
+    );
+    return vec4<f32>(decoded, 1.0);
+}
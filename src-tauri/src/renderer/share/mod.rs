@@ -0,0 +1,116 @@
+//! Publishing an overlay's frames to other apps, like OBS or Resolume: as a Syphon server
+//! on macOS and a Spout sender on Windows. Frames are drawn into a texture the sender can
+//! read, and copied from there onto the overlay.
+
+use super::blit::Blit;
+use super::{GpuContext, MemoryUsage};
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "macos")] {
+        mod syphon;
+        use syphon::Sender;
+    } else if #[cfg(target_os = "windows")] {
+        mod spout;
+        use spout::Sender;
+    } else {
+        /// Frame sharing is only for the platforms Syphon and Spout are on.
+        struct Sender;
+
+        impl Sender {
+            fn new(_gpu: &GpuContext, _name: &str) -> Result<Self, String> {
+                Err("frame sharing needs Syphon (macOS) or Spout (Windows)".into())
+            }
+
+            fn create_texture(
+                &mut self,
+                _gpu: &GpuContext,
+                _format: wgpu::TextureFormat,
+                _size: tauri::PhysicalSize<u32>,
+            ) -> Result<wgpu::Texture, String> {
+                unreachable!()
+            }
+
+            fn publish(&mut self, _gpu: &GpuContext, _texture: &wgpu::Texture) {}
+
+            fn memory(&self, _usage: &mut MemoryUsage) {}
+        }
+    }
+}
+
+pub struct FrameShare {
+    name: String,
+    sender: Sender,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    /// The texture bound for copying onto the overlay.
+    source: wgpu::BindGroup,
+}
+
+impl FrameShare {
+    /// Publish frames of `format` and `size` as `name`, which is what other apps list the
+    /// source as.
+    pub fn new(
+        gpu: &GpuContext,
+        blit: &Blit,
+        name: String,
+        format: wgpu::TextureFormat,
+        size: tauri::PhysicalSize<u32>,
+    ) -> Result<Self, String> {
+        let mut sender = Sender::new(gpu, &name)?;
+        let texture = sender.create_texture(gpu, format, size)?;
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let source = blit.bind(&gpu.device, &view);
+        Ok(FrameShare {
+            name,
+            sender,
+            texture,
+            view,
+            source,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Share frames of a new size, keeping the same source.
+    pub fn resize(
+        &mut self,
+        gpu: &GpuContext,
+        blit: &Blit,
+        format: wgpu::TextureFormat,
+        size: tauri::PhysicalSize<u32>,
+    ) -> Result<(), String> {
+        self.texture = self.sender.create_texture(gpu, format, size)?;
+        self.view = self
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.source = blit.bind(&gpu.device, &self.view);
+        Ok(())
+    }
+
+    /// Where to draw frames, instead of the overlay's own target.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// The bind group for copying frames onto the overlay's own target.
+    pub fn source(&self) -> &wgpu::BindGroup {
+        &self.source
+    }
+
+    /// What frames are drawn into, for reading them back.
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        usage.add_texture(&self.texture);
+        self.sender.memory(usage);
+    }
+
+    /// Send the frame, once it's been submitted.
+    pub fn publish(&mut self, gpu: &GpuContext) {
+        self.sender.publish(gpu, &self.texture);
+    }
+}
@@ -0,0 +1,380 @@
+//! Spout senders, for sharing frames with other apps on Windows. Spout receivers open a D3D11
+//! texture by a legacy shared handle, which wgpu can't make, so frames are drawn into a wgpu
+//! texture and uploaded to a shared D3D11 one each frame. That costs a read back, but keeps
+//! sharing working on every wgpu backend.
+//!
+//! Senders are announced the way the Spout SDK does: in the `SpoutSenderNames` shared memory
+//! list, with each sender's texture described in shared memory of its own.
+
+use windows::Win32::Foundation::{CloseHandle, BOOL, HANDLE, INVALID_HANDLE_VALUE, PWSTR};
+use windows::Win32::Graphics::Direct3D::D3D_DRIVER_TYPE_HARDWARE;
+use windows::Win32::Graphics::Direct3D11::{
+    D3D11CreateDevice, ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D,
+    D3D11_BIND_RENDER_TARGET, D3D11_BIND_SHADER_RESOURCE, D3D11_CREATE_DEVICE_BGRA_SUPPORT,
+    D3D11_RESOURCE_MISC_SHARED, D3D11_SDK_VERSION, D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT,
+};
+use windows::Win32::Graphics::Dxgi::Common::{DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_SAMPLE_DESC};
+use windows::Win32::Graphics::Dxgi::IDXGIResource;
+use windows::Win32::System::Memory::{
+    CreateFileMappingW, MapViewOfFile, UnmapViewOfFile, FILE_MAP_ALL_ACCESS, PAGE_READWRITE,
+};
+use windows::Win32::System::Threading::{CreateMutexW, ReleaseMutex, WaitForSingleObject};
+
+use crate::renderer::target::{map_read, padded_bytes_per_row};
+use crate::renderer::{GpuContext, MemoryUsage};
+
+const SENDER_NAMES: &str = "SpoutSenderNames";
+/// The longest name, with its terminator, and the most senders Spout lists.
+const MAX_NAME_LENGTH: usize = 256;
+const MAX_SENDERS: usize = 64;
+/// How long to wait for another process to finish with shared memory, in milliseconds.
+const LOCK_TIMEOUT: u32 = 100;
+
+/// A sender's texture, as Spout describes it in shared memory.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SharedTextureInfo {
+    share_handle: u32,
+    width: u32,
+    height: u32,
+    format: u32,
+    usage: u32,
+    description: [u16; 128],
+    partner_id: u32,
+}
+
+pub struct Sender {
+    name: String,
+    device: ID3D11Device,
+    context: ID3D11DeviceContext,
+    names: SharedMemory,
+    info: SharedMemory,
+    shared: Option<Shared>,
+}
+
+// D3D11 objects are only used from whichever thread is rendering the overlay
+unsafe impl Send for Sender {}
+
+/// The shared texture, and the buffer frames are read back through on their way to it.
+struct Shared {
+    texture: ID3D11Texture2D,
+    buffer: wgpu::Buffer,
+    size: tauri::PhysicalSize<u32>,
+    padded_bytes_per_row: u32,
+}
+
+impl Sender {
+    pub fn new(_gpu: &GpuContext, name: &str) -> Result<Self, String> {
+        if name.is_empty() || name.len() >= MAX_NAME_LENGTH {
+            return Err(format!(
+                "Spout names are 1 to {} bytes long",
+                MAX_NAME_LENGTH - 1
+            ));
+        }
+        let (device, context) = create_device()?;
+        let names = SharedMemory::create(SENDER_NAMES, MAX_SENDERS * MAX_NAME_LENGTH)?;
+        let info = SharedMemory::create(name, std::mem::size_of::<SharedTextureInfo>())?;
+        names.with_lock(|data| add_name(data, name))??;
+        Ok(Sender {
+            name: name.to_string(),
+            device,
+            context,
+            names,
+            info,
+            shared: None,
+        })
+    }
+
+    /// A texture to draw frames into, which `publish` sends on to Spout.
+    pub fn create_texture(
+        &mut self,
+        gpu: &GpuContext,
+        format: wgpu::TextureFormat,
+        size: tauri::PhysicalSize<u32>,
+    ) -> Result<wgpu::Texture, String> {
+        if !matches!(
+            format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            return Err(format!("Spout can't share {:?} frames", format));
+        }
+        let texture = unsafe {
+            self.device.CreateTexture2D(
+                &D3D11_TEXTURE2D_DESC {
+                    Width: size.width,
+                    Height: size.height,
+                    MipLevels: 1,
+                    ArraySize: 1,
+                    Format: DXGI_FORMAT_B8G8R8A8_UNORM,
+                    SampleDesc: DXGI_SAMPLE_DESC {
+                        Count: 1,
+                        Quality: 0,
+                    },
+                    Usage: D3D11_USAGE_DEFAULT,
+                    BindFlags: D3D11_BIND_SHADER_RESOURCE | D3D11_BIND_RENDER_TARGET,
+                    CPUAccessFlags: 0,
+                    MiscFlags: D3D11_RESOURCE_MISC_SHARED,
+                },
+                std::ptr::null(),
+            )
+        }
+        .map_err(|e| format!("failed to create a shared texture: {}", e))?;
+        let handle = unsafe {
+            windows::core::Interface::cast::<IDXGIResource>(&texture)
+                .and_then(|resource| resource.GetSharedHandle())
+        }
+        .map_err(|e| format!("failed to share a texture: {}", e))?;
+
+        let info = SharedTextureInfo {
+            // Legacy shared handles fit in 32 bits, which is all Spout keeps
+            share_handle: handle.0 as u32,
+            width: size.width,
+            height: size.height,
+            format: DXGI_FORMAT_B8G8R8A8_UNORM,
+            usage: 0,
+            description: [0; 128],
+            partner_id: 0,
+        };
+        self.info.with_lock(|data| unsafe {
+            std::ptr::write_unaligned(data.as_mut_ptr() as *mut SharedTextureInfo, info)
+        })?;
+
+        let padded_bytes_per_row = padded_bytes_per_row(size.width);
+        let buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shared Frame Buffer"),
+            size: (padded_bytes_per_row * size.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        self.shared = Some(Shared {
+            texture,
+            buffer,
+            size,
+            padded_bytes_per_row,
+        });
+
+        Ok(gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shared Frame"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        }))
+    }
+
+    /// The buffer frames are read back through. The shared texture is D3D11's.
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        if let Some(shared) = &self.shared {
+            usage.add_buffer(&shared.buffer);
+        }
+    }
+
+    /// Read `texture` back and upload it to the shared texture. Blocks until the GPU is done.
+    pub fn publish(&mut self, gpu: &GpuContext, texture: &wgpu::Texture) {
+        let shared = match &self.shared {
+            Some(shared) => shared,
+            None => return,
+        };
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Share Encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &shared.buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(shared.padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: shared.size.width,
+                height: shared.size.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        gpu.queue.submit(std::iter::once(encoder.finish()));
+
+        {
+            let data = match map_read(&gpu.device, shared.buffer.slice(..)) {
+                Ok(data) => data,
+                Err(e) => {
+                    println!("Failed to read back shared frame: {}", e);
+                    return;
+                }
+            };
+            unsafe {
+                self.context.UpdateSubresource(
+                    &shared.texture,
+                    0,
+                    std::ptr::null(),
+                    data.as_ptr() as *const _,
+                    shared.padded_bytes_per_row,
+                    0,
+                );
+                self.context.Flush();
+            }
+        }
+        shared.buffer.unmap();
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        let name = &self.name;
+        let removed = self
+            .names
+            .with_lock(|data| remove_name(data, name))
+            .and_then(|removed| removed);
+        if let Err(e) = removed {
+            println!("Failed to remove Spout sender {:?}: {}", name, e);
+        }
+    }
+}
+
+fn create_device() -> Result<(ID3D11Device, ID3D11DeviceContext), String> {
+    let mut device = None;
+    let mut context = None;
+    unsafe {
+        D3D11CreateDevice(
+            None,
+            D3D_DRIVER_TYPE_HARDWARE,
+            None,
+            D3D11_CREATE_DEVICE_BGRA_SUPPORT,
+            std::ptr::null(),
+            0,
+            D3D11_SDK_VERSION,
+            &mut device,
+            std::ptr::null_mut(),
+            &mut context,
+        )
+    }
+    .map_err(|e| format!("failed to create a D3D11 device: {}", e))?;
+    device
+        .zip(context)
+        .ok_or_else(|| "failed to create a D3D11 device".to_string())
+}
+
+/// Add `name` to the list of senders, a run of null terminated names ending at an empty one.
+fn add_name(data: &mut [u8], name: &str) -> Result<(), String> {
+    for slot in data.chunks_mut(MAX_NAME_LENGTH) {
+        let length = slot
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(slot.len());
+        if &slot[..length] == name.as_bytes() {
+            return Err(format!("there is already a Spout sender called {:?}", name));
+        }
+        if length == 0 {
+            slot[..name.len()].copy_from_slice(name.as_bytes());
+            slot[name.len()] = 0;
+            return Ok(());
+        }
+    }
+    Err("there are too many Spout senders".into())
+}
+
+/// Take `name` out of the list of senders, moving the ones after it up.
+fn remove_name(data: &mut [u8], name: &str) -> Result<(), String> {
+    let slots = data.len() / MAX_NAME_LENGTH;
+    let index = (0..slots)
+        .find(|index| {
+            let slot = &data[index * MAX_NAME_LENGTH..(index + 1) * MAX_NAME_LENGTH];
+            let length = slot
+                .iter()
+                .position(|byte| *byte == 0)
+                .unwrap_or(slot.len());
+            &slot[..length] == name.as_bytes()
+        })
+        .ok_or_else(|| "it isn't listed".to_string())?;
+    data.copy_within(
+        (index + 1) * MAX_NAME_LENGTH..slots * MAX_NAME_LENGTH,
+        index * MAX_NAME_LENGTH,
+    );
+    data[(slots - 1) * MAX_NAME_LENGTH] = 0;
+    Ok(())
+}
+
+/// Named shared memory, guarded by a mutex named after it as the Spout SDK does.
+struct SharedMemory {
+    mapping: HANDLE,
+    mutex: HANDLE,
+    data: *mut u8,
+    size: usize,
+}
+
+impl SharedMemory {
+    /// Open the shared memory called `name`, creating it if no other process has.
+    fn create(name: &str, size: usize) -> Result<Self, String> {
+        let mut wide_name = wide(name);
+        let mut mutex_name = wide(&format!("{}_mutex", name));
+        unsafe {
+            let mapping = CreateFileMappingW(
+                INVALID_HANDLE_VALUE,
+                std::ptr::null(),
+                PAGE_READWRITE,
+                0,
+                size as u32,
+                PWSTR(wide_name.as_mut_ptr()),
+            );
+            if mapping.is_invalid() {
+                return Err(format!("failed to open shared memory {:?}", name));
+            }
+            let data = MapViewOfFile(mapping, FILE_MAP_ALL_ACCESS, 0, 0, size) as *mut u8;
+            if data.is_null() {
+                CloseHandle(mapping);
+                return Err(format!("failed to map shared memory {:?}", name));
+            }
+            let mutex = CreateMutexW(std::ptr::null(), BOOL(0), PWSTR(mutex_name.as_mut_ptr()));
+            Ok(SharedMemory {
+                mapping,
+                mutex,
+                data,
+                size,
+            })
+        }
+    }
+
+    fn with_lock<R>(&self, f: impl FnOnce(&mut [u8]) -> R) -> Result<R, String> {
+        unsafe {
+            let locked = !self.mutex.is_invalid();
+            if locked && WaitForSingleObject(self.mutex, LOCK_TIMEOUT) != 0 {
+                return Err("shared memory is in use".into());
+            }
+            let result = f(std::slice::from_raw_parts_mut(self.data, self.size));
+            if locked {
+                ReleaseMutex(self.mutex);
+            }
+            Ok(result)
+        }
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        unsafe {
+            UnmapViewOfFile(self.data as *const _);
+            CloseHandle(self.mapping);
+            if !self.mutex.is_invalid() {
+                CloseHandle(self.mutex);
+            }
+        }
+    }
+}
+
+/// A null terminated UTF-16 copy of `string`, for Win32 names.
+fn wide(string: &str) -> Vec<u16> {
+    string.encode_utf16().chain(std::iter::once(0)).collect()
+}
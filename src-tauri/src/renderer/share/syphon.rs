@@ -0,0 +1,202 @@
+//! Syphon servers, for sharing frames with other apps on macOS. Frames are drawn into a Metal
+//! texture that Syphon reads on the GPU, so they never leave it. Syphon isn't linked, but
+//! loaded when the first server is made: from the app's `Frameworks`, where bundles put it,
+//! or wherever it's installed.
+
+use cocoa::base::{id, nil, BOOL, NO};
+use cocoa::foundation::{NSPoint, NSRect, NSSize, NSString};
+use objc::runtime::{Class, Object};
+use objc::{class, msg_send, sel, sel_impl};
+use objc2::rc::Retained;
+use objc2::runtime::ProtocolObject;
+use objc2_metal::{
+    MTLCommandBuffer, MTLCommandQueue, MTLDevice, MTLPixelFormat, MTLStorageMode, MTLTexture,
+    MTLTextureDescriptor, MTLTextureType, MTLTextureUsage,
+};
+use wgpu_hal::api::Metal;
+
+use crate::renderer::{GpuContext, MemoryUsage};
+
+const SERVER_CLASS: &str = "SyphonMetalServer";
+const FRAMEWORK_PATHS: &[&str] = &[
+    "@executable_path/../Frameworks/Syphon.framework",
+    "/Library/Frameworks/Syphon.framework",
+];
+
+pub struct Sender {
+    server: *mut Object,
+    device: Retained<ProtocolObject<dyn MTLDevice>>,
+    queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
+    texture: Option<Retained<ProtocolObject<dyn MTLTexture>>>,
+}
+
+// The server is only used from whichever thread is rendering the overlay
+unsafe impl Send for Sender {}
+
+impl Sender {
+    pub fn new(gpu: &GpuContext, name: &str) -> Result<Self, String> {
+        let device = unsafe { gpu.device.as_hal::<Metal>() }
+            .ok_or("frame sharing needs the Metal backend")?
+            .raw_device()
+            .clone();
+        let class = server_class()?;
+        let server: *mut Object = unsafe {
+            let name = NSString::alloc(nil).init_str(name);
+            let server: id = msg_send![class, alloc];
+            let server: id = msg_send![
+                server,
+                initWithName: name
+                device: Retained::as_ptr(&device) as *mut Object
+                options: nil
+            ];
+            let _: () = msg_send![name, release];
+            server
+        };
+        if server.is_null() {
+            return Err("failed to create a Syphon server".into());
+        }
+        let queue = device
+            .newCommandQueue()
+            .ok_or("failed to create a command queue for Syphon")?;
+        Ok(Sender {
+            server,
+            device,
+            queue,
+            texture: None,
+        })
+    }
+
+    /// A texture Syphon can read, for drawing frames into.
+    pub fn create_texture(
+        &mut self,
+        gpu: &GpuContext,
+        format: wgpu::TextureFormat,
+        size: tauri::PhysicalSize<u32>,
+    ) -> Result<wgpu::Texture, String> {
+        let raw_format = match format {
+            wgpu::TextureFormat::Bgra8Unorm => MTLPixelFormat::BGRA8Unorm,
+            wgpu::TextureFormat::Bgra8UnormSrgb => MTLPixelFormat::BGRA8Unorm_sRGB,
+            wgpu::TextureFormat::Rgba8Unorm => MTLPixelFormat::RGBA8Unorm,
+            wgpu::TextureFormat::Rgba8UnormSrgb => MTLPixelFormat::RGBA8Unorm_sRGB,
+            format => return Err(format!("Syphon can't share {:?} frames", format)),
+        };
+        let descriptor = MTLTextureDescriptor::new();
+        descriptor.setTextureType(MTLTextureType::Type2D);
+        descriptor.setPixelFormat(raw_format);
+        descriptor.setStorageMode(MTLStorageMode::Private);
+        descriptor.setUsage(MTLTextureUsage::RenderTarget | MTLTextureUsage::ShaderRead);
+        let raw = unsafe {
+            descriptor.setWidth(size.width as usize);
+            descriptor.setHeight(size.height as usize);
+            self.device.newTextureWithDescriptor(&descriptor)
+        }
+        .ok_or("failed to create a texture for Syphon")?;
+        self.texture = Some(raw.clone());
+
+        unsafe {
+            let texture = wgpu_hal::metal::Device::texture_from_raw(
+                raw,
+                format,
+                MTLTextureType::Type2D,
+                1,
+                1,
+                wgpu_hal::CopyExtent {
+                    width: size.width,
+                    height: size.height,
+                    depth: 1,
+                },
+                None,
+            );
+            Ok(gpu.device.create_texture_from_hal::<Metal>(
+                texture,
+                &wgpu::TextureDescriptor {
+                    label: Some("Shared Frame"),
+                    size: wgpu::Extent3d {
+                        width: size.width,
+                        height: size.height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_SRC,
+                    view_formats: &[],
+                },
+                wgpu::TextureUses::COLOR_TARGET,
+            ))
+        }
+    }
+
+    /// The shared texture is the overlay's own, so there's nothing more to count.
+    pub fn memory(&self, _usage: &mut MemoryUsage) {}
+
+    pub fn publish(&mut self, gpu: &GpuContext, _texture: &wgpu::Texture) {
+        let texture = match &self.texture {
+            Some(texture) => texture,
+            None => return,
+        };
+        // Syphon reads the texture on a queue of its own, so wgpu has to be done with it
+        let _ = gpu.device.poll(wgpu::PollType::wait_indefinitely());
+        let region = NSRect::new(
+            NSPoint::new(0.0, 0.0),
+            NSSize::new(texture.width() as f64, texture.height() as f64),
+        );
+        let command_buffer = match self.queue.commandBuffer() {
+            Some(command_buffer) => command_buffer,
+            None => return,
+        };
+        unsafe {
+            let _: () = msg_send![
+                self.server,
+                publishFrameTexture: Retained::as_ptr(texture) as *mut Object
+                onCommandBuffer: Retained::as_ptr(&command_buffer) as *mut Object
+                imageRegion: region
+                flipped: NO
+            ];
+        }
+        command_buffer.commit();
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        unsafe {
+            let _: () = msg_send![self.server, stop];
+            let _: () = msg_send![self.server, release];
+        }
+    }
+}
+
+/// The server class, loading Syphon if nothing has yet.
+fn server_class() -> Result<&'static Class, String> {
+    if let Some(class) = Class::get(SERVER_CLASS) {
+        return Ok(class);
+    }
+    for path in FRAMEWORK_PATHS {
+        let path = match path.strip_prefix("@executable_path/") {
+            Some(relative) => match std::env::current_exe() {
+                Ok(exe) => exe.with_file_name(relative),
+                Err(_) => continue,
+            },
+            None => path.into(),
+        };
+        if !path.exists() {
+            continue;
+        }
+        unsafe {
+            let path = NSString::alloc(nil).init_str(&path.to_string_lossy());
+            let bundle: id = msg_send![class!(NSBundle), bundleWithPath: path];
+            let _: () = msg_send![path, release];
+            if bundle != nil {
+                let _: BOOL = msg_send![bundle, load];
+            }
+        }
+        if let Some(class) = Class::get(SERVER_CLASS) {
+            return Ok(class);
+        }
+    }
+    Err("Syphon.framework isn't installed".into())
+}
@@ -0,0 +1,299 @@
+//! Frame statistics for `get_frame_stats`: how long each frame took on the CPU and, when the
+//! device has timestamp queries, on the GPU, and how evenly frames were presented.
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use super::MemoryUsage;
+
+/// How many of the latest frames are kept.
+const HISTORY: usize = 240;
+/// A present interval this many times the expected one means frames were dropped.
+const DROP_THRESHOLD: f64 = 1.5;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameSample {
+    /// Counts up from zero with every frame the overlay draws.
+    pub frame: u64,
+    /// Milliseconds from starting the frame to presenting it.
+    pub cpu_time: f64,
+    /// Milliseconds the GPU spent drawing the frame, if the device can time it. It's filled
+    /// in a frame or two later, once the GPU is done.
+    pub gpu_time: Option<f64>,
+    /// Milliseconds since the frame before was presented, unless drawing was interrupted
+    /// in between.
+    pub present_interval: Option<f64>,
+}
+
+/// How a timing was spread over the frames kept, in milliseconds.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimingSummary {
+    pub average: f64,
+    pub p95: f64,
+    pub max: f64,
+}
+
+impl TimingSummary {
+    fn of(values: impl Iterator<Item = f64>) -> Option<Self> {
+        let mut values: Vec<f64> = values.collect();
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+        let p95 = ((values.len() - 1) as f64 * 0.95).round() as usize;
+        Some(TimingSummary {
+            average: values.iter().sum::<f64>() / values.len() as f64,
+            p95: values[p95],
+            max: values[values.len() - 1],
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameStats {
+    /// Frames drawn since the overlay was created.
+    pub frames: u64,
+    /// Frames that weren't presented when they were due, since the overlay was created.
+    pub dropped_frames: u64,
+    pub cpu_time: Option<TimingSummary>,
+    /// `None` if the device can't time the GPU; ask for the `timestampQuery` feature in the
+    /// `gpu` config.
+    pub gpu_time: Option<TimingSummary>,
+    pub present_interval: Option<TimingSummary>,
+    /// The latest frames, oldest first.
+    pub samples: Vec<FrameSample>,
+}
+
+/// Times frames as they're drawn, keeping the latest ones.
+pub struct FrameTimer {
+    /// How far apart frames are presented when none are dropped.
+    expected_interval: Duration,
+    samples: VecDeque<FrameSample>,
+    frames: u64,
+    dropped: u64,
+    last_present: Option<Instant>,
+    gpu: Option<GpuTimer>,
+}
+
+impl FrameTimer {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, expected_interval: Duration) -> Self {
+        let gpu = device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| GpuTimer::new(device, queue));
+        FrameTimer {
+            expected_interval,
+            samples: VecDeque::with_capacity(HISTORY),
+            frames: 0,
+            dropped: 0,
+            last_present: None,
+            gpu,
+        }
+    }
+
+    /// Start timing the frame's GPU work, which `encoder` records. Timings the GPU has
+    /// finished since the last frame are collected first.
+    pub fn begin_gpu(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        let gpu = match &mut self.gpu {
+            Some(gpu) => gpu,
+            None => return,
+        };
+        if let Some((frame, gpu_time)) = gpu.collect(device) {
+            if let Some(sample) = self.samples.iter_mut().find(|sample| sample.frame == frame) {
+                sample.gpu_time = Some(gpu_time);
+            }
+        }
+        gpu.begin(encoder, self.frames);
+    }
+
+    /// Finish timing the frame's GPU work, if it's being timed.
+    pub fn end_gpu(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if let Some(gpu) = &mut self.gpu {
+            gpu.end(encoder);
+        }
+    }
+
+    /// Read back the GPU's timing once the frame that `end_gpu` finished was submitted.
+    pub fn submitted(&mut self) {
+        if let Some(gpu) = &mut self.gpu {
+            gpu.read_back();
+        }
+    }
+
+    /// Record a frame that was started at `started` and has just been presented.
+    pub fn presented(&mut self, started: Instant) {
+        let now = Instant::now();
+        let present_interval = self.last_present.map(|last| now - last);
+        if let Some(interval) = present_interval {
+            let ratio = interval.as_secs_f64() / self.expected_interval.as_secs_f64();
+            if ratio >= DROP_THRESHOLD {
+                self.dropped += (ratio.round() as u64).saturating_sub(1).max(1);
+            }
+        }
+        self.last_present = Some(now);
+
+        if self.samples.len() == HISTORY {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(FrameSample {
+            frame: self.frames,
+            cpu_time: millis(now - started),
+            gpu_time: None,
+            present_interval: present_interval.map(millis),
+        });
+        self.frames += 1;
+    }
+
+    /// Forget when the last frame was presented, since no frame was drawn on purpose, so the
+    /// gap before the next one isn't counted as dropped frames.
+    pub fn interrupted(&mut self) {
+        self.last_present = None;
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        if let Some(gpu) = &self.gpu {
+            usage.add_buffer(&gpu.resolve);
+            usage.add_buffer(&gpu.readback);
+        }
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        FrameStats {
+            frames: self.frames,
+            dropped_frames: self.dropped,
+            cpu_time: TimingSummary::of(self.samples.iter().map(|sample| sample.cpu_time)),
+            gpu_time: TimingSummary::of(self.samples.iter().filter_map(|sample| sample.gpu_time)),
+            present_interval: TimingSummary::of(
+                self.samples
+                    .iter()
+                    .filter_map(|sample| sample.present_interval),
+            ),
+            samples: self.samples.iter().copied().collect(),
+        }
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Times a frame's GPU work with timestamps written by empty compute passes at its start and
+/// end. One frame is timed at a time; frames that start while the last one's timestamps are
+/// still being read back go untimed.
+struct GpuTimer {
+    queries: wgpu::QuerySet,
+    resolve: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f64,
+    state: GpuTiming,
+}
+
+/// Where the frame being timed has got to.
+enum GpuTiming {
+    Idle,
+    Started(u64),
+    Ended(u64),
+    ReadingBack(u64, Receiver<Result<(), wgpu::BufferAsyncError>>),
+}
+
+impl GpuTimer {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let size = 2 * std::mem::size_of::<u64>() as wgpu::BufferAddress;
+        GpuTimer {
+            queries: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Frame Timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: 2,
+            }),
+            resolve: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Frame Timestamp Resolve Buffer"),
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Frame Timestamp Readback Buffer"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            period: queue.get_timestamp_period() as f64,
+            state: GpuTiming::Idle,
+        }
+    }
+
+    /// The timed frame and its GPU time in milliseconds, once the timestamps are back.
+    fn collect(&mut self, device: &wgpu::Device) -> Option<(u64, f64)> {
+        let (frame, mapped) = match &self.state {
+            GpuTiming::Idle => return None,
+            GpuTiming::ReadingBack(frame, mapped) => (*frame, mapped),
+            // The frame never got submitted, so its timestamps won't come
+            GpuTiming::Started(_) | GpuTiming::Ended(_) => {
+                self.state = GpuTiming::Idle;
+                return None;
+            }
+        };
+        let _ = device.poll(wgpu::PollType::Poll);
+        match mapped.try_recv() {
+            Ok(Ok(())) => {}
+            Err(TryRecvError::Empty) => return None,
+            Ok(Err(_)) | Err(TryRecvError::Disconnected) => {
+                self.state = GpuTiming::Idle;
+                return None;
+            }
+        }
+        let ticks = self.readback.slice(..).get_mapped_range().ok().map(|data| {
+            let start = u64::from_le_bytes(data[0..8].try_into().unwrap());
+            let end = u64::from_le_bytes(data[8..16].try_into().unwrap());
+            end.saturating_sub(start)
+        });
+        self.readback.unmap();
+        self.state = GpuTiming::Idle;
+        Some((frame, ticks? as f64 * self.period / 1_000_000.0))
+    }
+
+    fn begin(&mut self, encoder: &mut wgpu::CommandEncoder, frame: u64) {
+        if let GpuTiming::Idle = self.state {
+            self.write_timestamp(encoder, 0);
+            self.state = GpuTiming::Started(frame);
+        }
+    }
+
+    fn end(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if let GpuTiming::Started(frame) = self.state {
+            self.write_timestamp(encoder, 1);
+            encoder.resolve_query_set(&self.queries, 0..2, &self.resolve, 0);
+            encoder.copy_buffer_to_buffer(&self.resolve, 0, &self.readback, 0, None);
+            self.state = GpuTiming::Ended(frame);
+        }
+    }
+
+    fn read_back(&mut self) {
+        if let GpuTiming::Ended(frame) = self.state {
+            let (sender, receiver) = mpsc::channel();
+            self.readback
+                .map_async(wgpu::MapMode::Read, .., move |result| {
+                    let _ = sender.send(result);
+                });
+            self.state = GpuTiming::ReadingBack(frame, receiver);
+        }
+    }
+
+    fn write_timestamp(&self, encoder: &mut wgpu::CommandEncoder, index: u32) {
+        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Frame Timestamp Pass"),
+            timestamp_writes: Some(wgpu::ComputePassTimestampWrites {
+                query_set: &self.queries,
+                beginning_of_pass_write_index: Some(index),
+                end_of_pass_write_index: None,
+            }),
+        });
+    }
+}
@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use super::image::ImagePipeline;
+use super::text::{self, TextStyle, TextSurface};
+use super::MemoryUsage;
+use crate::assets::{Asset, Assets};
+
+/// Subtitles are wrapped to this fraction of the video's width.
+const MAX_WIDTH: f32 = 0.9;
+
+/// How subtitles are drawn over the video. Sizes are logical pixels, and colors straight
+/// alpha.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubtitleStyle {
+    /// Id of the font asset to set subtitles in. Subtitles aren't drawn until there is one.
+    #[serde(default)]
+    pub font: Option<String>,
+    #[serde(default = "default_size")]
+    pub size: f64,
+    #[serde(default = "default_color")]
+    pub color: [f64; 4],
+    /// Keeps text legible over bright video.
+    #[serde(default = "default_outline")]
+    pub outline: Option<[f64; 4]>,
+    #[serde(default = "default_outline_width")]
+    pub outline_width: f64,
+    /// A box behind the text.
+    #[serde(default)]
+    pub background: Option<[f64; 4]>,
+    /// How far the last line sits above the bottom of the video.
+    #[serde(default = "default_margin")]
+    pub margin: f64,
+}
+
+impl Default for SubtitleStyle {
+    fn default() -> Self {
+        SubtitleStyle {
+            font: None,
+            size: default_size(),
+            color: default_color(),
+            outline: default_outline(),
+            outline_width: default_outline_width(),
+            background: None,
+            margin: default_margin(),
+        }
+    }
+}
+
+fn default_size() -> f64 {
+    32.0
+}
+
+fn default_color() -> [f64; 4] {
+    [1.0, 1.0, 1.0, 1.0]
+}
+
+fn default_outline() -> Option<[f64; 4]> {
+    Some([0.0, 0.0, 0.0, 1.0])
+}
+
+fn default_outline_width() -> f64 {
+    2.0
+}
+
+fn default_margin() -> f64 {
+    24.0
+}
+
+/// The subtitle showing over the video, set again whenever its text, style or size changes.
+#[derive(Default)]
+pub struct Subtitles {
+    style: SubtitleStyle,
+    font: Option<Arc<Asset>>,
+    text: Option<String>,
+    surface: Option<TextSurface>,
+    /// The scale factor and video width the surface was set for, or `None` to set it again.
+    set_for: Option<(f64, u32)>,
+}
+
+impl Subtitles {
+    /// Draw subtitles with `style`, failing if its font isn't a loaded font asset.
+    pub fn set_style(&mut self, style: SubtitleStyle, assets: &Assets) -> Result<(), String> {
+        self.font = match &style.font {
+            Some(id) => Some(font_asset(assets, id)?),
+            None => None,
+        };
+        self.style = style;
+        self.set_for = None;
+        Ok(())
+    }
+
+    /// Pick up the font if it's been loaded again.
+    pub fn refresh_font(&mut self, assets: &Assets) {
+        let reloaded = match (&self.style.font, &self.font) {
+            (Some(id), Some(font)) => assets
+                .get(id)
+                .filter(|asset| !Arc::ptr_eq(asset, font) && asset.font().is_some()),
+            _ => None,
+        };
+        if reloaded.is_some() {
+            self.font = reloaded;
+            self.set_for = None;
+        }
+    }
+
+    pub fn set_text(&mut self, text: Option<String>) {
+        if self.text != text {
+            self.text = text;
+            self.set_for = None;
+        }
+    }
+
+    /// Set the text for a video `video_width` physical pixels wide, if anything changed.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        images: &ImagePipeline,
+        video_width: u32,
+        scale_factor: f64,
+    ) {
+        if self.set_for == Some((scale_factor, video_width)) {
+            return;
+        }
+        self.set_for = Some((scale_factor, video_width));
+        self.surface = None;
+
+        let (text, font) = match (&self.text, self.font.as_ref().and_then(|font| font.font())) {
+            (Some(text), Some(font)) => (text, font),
+            _ => return,
+        };
+        let style = TextStyle {
+            font: font.clone(),
+            size: (self.style.size * scale_factor) as f32,
+            color: self.style.color,
+            outline: self.style.outline,
+            outline_width: (self.style.outline_width * scale_factor) as f32,
+            background: self.style.background,
+            padding: match self.style.background {
+                Some(_) => (self.style.size * scale_factor / 4.0) as f32,
+                None => 0.0,
+            },
+        };
+        if let Some(image) = text::rasterize(text, &style, video_width as f32 * MAX_WIDTH) {
+            self.surface = Some(TextSurface::new(device, queue, images, &image));
+        }
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        if let Some(surface) = &self.surface {
+            surface.memory(usage);
+        }
+    }
+
+    /// Draw centered along the bottom of the video, which is at `video` ([x, y, width,
+    /// height] in physical pixels).
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        images: &'a ImagePipeline,
+        video: [f32; 4],
+        scale_factor: f64,
+        target: tauri::PhysicalSize<u32>,
+    ) {
+        let surface = match &self.surface {
+            Some(surface) => surface,
+            None => return,
+        };
+        let [width, height] = surface.size();
+        let x = video[0] + (video[2] - width as f32) / 2.0;
+        let y = video[1] + video[3] - (self.style.margin * scale_factor) as f32 - height as f32;
+        surface.draw(render_pass, images, x, y, target);
+    }
+}
+
+fn font_asset(assets: &Assets, id: &str) -> Result<Arc<Asset>, String> {
+    let asset = assets
+        .get(id)
+        .ok_or_else(|| format!("no asset called {:?}", id))?;
+    match asset.font() {
+        Some(_) => Ok(asset),
+        None => Err(format!("asset {:?} is not a font", id)),
+    }
+}
@@ -0,0 +1,370 @@
+use super::{GpuContext, MemoryUsage};
+
+/// How rendered frames reach the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Presentation {
+    /// wgpu presents straight to a swapchain on the overlay's native view.
+    Surface,
+    /// Frames are rendered offscreen and read back as premultiplied BGRA for the overlay
+    /// view to put on screen itself (e.g. `UpdateLayeredWindow` on Windows).
+    Readback,
+}
+
+/// Format used for read back frames, matching what GDI expects for per-pixel alpha.
+pub const READBACK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8Unorm;
+
+/// A rendered frame in CPU memory: tightly packed, premultiplied BGRA rows, top to bottom.
+#[derive(Clone)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// A frame that made it to the screen.
+pub enum Presented {
+    /// Straight to the overlay's surface.
+    Surface,
+    /// Read back, for the overlay to present itself.
+    Readback(Frame),
+}
+
+impl Presented {
+    /// The pixels that were read back, if they were.
+    pub fn frame(&self) -> Option<&Frame> {
+        match self {
+            Presented::Surface => None,
+            Presented::Readback(frame) => Some(frame),
+        }
+    }
+}
+
+pub enum RenderTarget {
+    Surface {
+        surface: wgpu::Surface<'static>,
+        config: wgpu::SurfaceConfiguration,
+        /// A copy of the last frame presented, since the surface's textures are gone once
+        /// they're presented. `None` if the surface's textures can't be copied from.
+        last_frame: Option<wgpu::Texture>,
+    },
+    Readback(Readback),
+}
+
+/// The texture being drawn into this frame.
+pub struct FrameTarget {
+    pub view: wgpu::TextureView,
+    surface_texture: Option<wgpu::SurfaceTexture>,
+}
+
+impl RenderTarget {
+    /// Draw into `surface`, which `config` has been applied to.
+    pub fn surface(
+        device: &wgpu::Device,
+        surface: wgpu::Surface<'static>,
+        config: wgpu::SurfaceConfiguration,
+    ) -> Self {
+        let last_frame = last_frame_texture(device, &config);
+        RenderTarget::Surface {
+            surface,
+            config,
+            last_frame,
+        }
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        match self {
+            RenderTarget::Surface { config, .. } => config.format,
+            RenderTarget::Readback(_) => READBACK_FORMAT,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, size: tauri::PhysicalSize<u32>) {
+        match self {
+            RenderTarget::Surface {
+                surface,
+                config,
+                last_frame,
+            } => {
+                config.width = size.width;
+                config.height = size.height;
+                surface.configure(device, config);
+                *last_frame = last_frame_texture(device, config);
+            }
+            RenderTarget::Readback(readback) => *readback = Readback::new(device, size),
+        }
+    }
+
+    /// What's drawn into offscreen. A surface's textures belong to the swapchain, so
+    /// they're not counted.
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        match self {
+            RenderTarget::Surface { last_frame, .. } => {
+                if let Some(last_frame) = last_frame {
+                    usage.add_texture(last_frame);
+                }
+            }
+            RenderTarget::Readback(readback) => {
+                usage.add_texture(&readback.texture);
+                readback.buffer.memory(usage);
+            }
+        }
+    }
+
+    /// What the last frame presented looks like, for reading back or measuring without
+    /// drawing it again. It can be copied from and bound, and is transparent until the
+    /// first frame. `None` if the surface's textures can't be copied from.
+    pub fn last_frame(&self) -> Option<&wgpu::Texture> {
+        match self {
+            RenderTarget::Surface { last_frame, .. } => last_frame.as_ref(),
+            RenderTarget::Readback(readback) => Some(&readback.texture),
+        }
+    }
+
+    /// The texture to draw the next frame into, or `None` if the surface can't take one
+    /// right now, like while its window is minimized or just after it's been changed
+    /// underneath us.
+    pub fn acquire(&self, device: &wgpu::Device) -> Result<Option<FrameTarget>, String> {
+        match self {
+            RenderTarget::Surface {
+                surface, config, ..
+            } => {
+                let output = match surface.get_current_texture() {
+                    wgpu::CurrentSurfaceTexture::Success(output)
+                    | wgpu::CurrentSurfaceTexture::Suboptimal(output) => output,
+                    wgpu::CurrentSurfaceTexture::Timeout
+                    | wgpu::CurrentSurfaceTexture::Occluded => return Ok(None),
+                    // Set up again for the next frame, which happens when the display or
+                    // the view's backing changes
+                    wgpu::CurrentSurfaceTexture::Outdated | wgpu::CurrentSurfaceTexture::Lost => {
+                        surface.configure(device, config);
+                        return Ok(None);
+                    }
+                    other => return Err(format!("failed to get the next frame: {:?}", other)),
+                };
+                let view = output
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                Ok(Some(FrameTarget {
+                    view,
+                    surface_texture: Some(output),
+                }))
+            }
+            RenderTarget::Readback(readback) => Ok(Some(FrameTarget {
+                view: readback
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default()),
+                surface_texture: None,
+            })),
+        }
+    }
+
+    /// Record anything that has to happen after drawing `frame`, before the encoder is
+    /// submitted.
+    pub fn finish(&self, encoder: &mut wgpu::CommandEncoder, frame: &FrameTarget) {
+        match self {
+            RenderTarget::Surface { last_frame, .. } => {
+                if let (Some(last_frame), Some(output)) = (last_frame, &frame.surface_texture) {
+                    encoder.copy_texture_to_texture(
+                        output.texture.as_image_copy(),
+                        last_frame.as_image_copy(),
+                        last_frame.size(),
+                    );
+                }
+            }
+            RenderTarget::Readback(readback) => readback.copy_to_buffer(encoder),
+        }
+    }
+
+    /// Show the frame. Read back targets return the pixels for the caller to present.
+    /// `None` if the frame couldn't be read back.
+    pub fn present(&self, gpu: &GpuContext, frame: FrameTarget) -> Option<Presented> {
+        if let Some(output) = frame.surface_texture {
+            gpu.queue.present(output);
+        }
+        match self {
+            RenderTarget::Surface { .. } => Some(Presented::Surface),
+            RenderTarget::Readback(readback) => readback.read(&gpu.device).map(Presented::Readback),
+        }
+    }
+}
+
+pub struct Readback {
+    texture: wgpu::Texture,
+    buffer: FrameBuffer,
+}
+
+impl Readback {
+    pub fn new(device: &wgpu::Device, size: tauri::PhysicalSize<u32>) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Readback Target"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: READBACK_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        Readback {
+            texture,
+            buffer: FrameBuffer::new(device, size),
+        }
+    }
+
+    fn copy_to_buffer(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.buffer.copy_from(encoder, &self.texture);
+    }
+
+    fn read(&self, device: &wgpu::Device) -> Option<Frame> {
+        self.buffer.read(device)
+    }
+}
+
+/// A buffer to read frames back through, for textures with 4 bytes a pixel.
+pub struct FrameBuffer {
+    buffer: wgpu::Buffer,
+    size: tauri::PhysicalSize<u32>,
+    padded_bytes_per_row: u32,
+}
+
+impl FrameBuffer {
+    pub fn new(device: &wgpu::Device, size: tauri::PhysicalSize<u32>) -> Self {
+        let padded_bytes_per_row = padded_bytes_per_row(size.width);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_bytes_per_row * size.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        FrameBuffer {
+            buffer,
+            size,
+            padded_bytes_per_row,
+        }
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        usage.add_buffer(&self.buffer);
+    }
+
+    /// Record copying `texture`, which is the buffer's size, into the buffer.
+    pub fn copy_from(&self, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture) {
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &self.buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: self.size.width,
+                height: self.size.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    pub fn size(&self) -> tauri::PhysicalSize<u32> {
+        self.size
+    }
+
+    /// Blocks until the GPU has finished the frame and copies it out of the mapped buffer.
+    pub fn read(&self, device: &wgpu::Device) -> Option<Frame> {
+        let unpadded_bytes_per_row = (self.size.width * 4) as usize;
+        let read = self.with_rows(device, |rows| {
+            let mut pixels = Vec::with_capacity(unpadded_bytes_per_row * self.size.height as usize);
+            for row in rows {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row]);
+            }
+            pixels
+        });
+        match read {
+            Ok(pixels) => Some(Frame {
+                width: self.size.width,
+                height: self.size.height,
+                pixels,
+            }),
+            Err(e) => {
+                println!("Failed to read back frame: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Blocks until the GPU has finished the frame, then passes `f` its rows, top to
+    /// bottom, each padded past the frame's width.
+    pub fn with_rows<T>(
+        &self,
+        device: &wgpu::Device,
+        f: impl FnOnce(std::slice::Chunks<u8>) -> T,
+    ) -> Result<T, String> {
+        let data = map_read(device, self.buffer.slice(..))?;
+        let result = f(data.chunks(self.padded_bytes_per_row as usize));
+        drop(data);
+        self.buffer.unmap();
+        Ok(result)
+    }
+}
+
+/// Map `slice` for reading, blocking until the GPU is done with it.
+pub fn map_read(
+    device: &wgpu::Device,
+    slice: wgpu::BufferSlice,
+) -> Result<wgpu::BufferView, String> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device
+        .poll(wgpu::PollType::wait_indefinitely())
+        .map_err(|e| e.to_string())?;
+    receiver
+        .recv()
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+    slice.get_mapped_range().map_err(|e| e.to_string())
+}
+
+/// Buffer copies need each row aligned to `COPY_BYTES_PER_ROW_ALIGNMENT`.
+pub fn padded_bytes_per_row(width: u32) -> u32 {
+    let unpadded = width * 4;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    unpadded.div_ceil(align) * align
+}
+
+/// A texture for the surface's frames to be copied into, if they can be copied from.
+fn last_frame_texture(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> Option<wgpu::Texture> {
+    if !config.usage.contains(wgpu::TextureUsages::COPY_SRC) {
+        return None;
+    }
+    Some(device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Last Frame"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    }))
+}
@@ -0,0 +1,1002 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+use super::image::ImagePipeline;
+use super::lod;
+use super::models::{SceneModels, TerrainModel};
+use super::navigation::Navigation;
+use super::pipelines::Pipelines;
+use super::upload::Uploads;
+use super::MemoryUsage;
+use crate::assets::{Asset, Assets, ModelVertex};
+
+/// Cells along each side of a chunk, which is the unit of level of detail.
+const CHUNK_CELLS: u32 = 64;
+/// Each level skips every other sample of the one before.
+const LOD_LEVELS: u32 = 4;
+/// The most samples along either side of a heightmap.
+const MAX_SAMPLES: u32 = 4097;
+const SPLAT_LAYERS: usize = 4;
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+/// Toward the sun, which is high in the south-west.
+const SUN: [f32; 3] = [-0.4, 0.8, 0.45];
+/// What terrain without any layers is colored.
+const DEFAULT_COLOR: [f64; 4] = [0.45, 0.5, 0.4, 1.0];
+
+/// Elevation data and how to color it. Elevations come from a heightmap image or a list of
+/// `heights`, and are in the same units as `size`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerrainDescriptor {
+    /// A grayscale PNG of elevations, black lowest. 16-bit images keep their precision.
+    #[serde(default)]
+    pub heightmap: Option<PathBuf>,
+    /// Elevations row by row from the north edge, instead of a heightmap.
+    #[serde(default)]
+    pub heights: Option<Vec<f32>>,
+    /// How many of `heights` are in each row.
+    #[serde(default)]
+    pub columns: Option<u32>,
+    /// The terrain's width (east to west) and depth (north to south).
+    pub size: [f64; 2],
+    /// What a heightmap's elevations span from black to white, or what `heights` are
+    /// multiplied by.
+    #[serde(default = "default_elevation_scale")]
+    pub elevation_scale: f64,
+    /// Up to four layers, blended by `splat_map` or, without one, by their elevations.
+    #[serde(default)]
+    pub layers: Vec<SplatLayer>,
+    /// Id of a texture asset stretched over the terrain, whose red, green, blue and alpha
+    /// weight the layers in order.
+    #[serde(default)]
+    pub splat_map: Option<String>,
+    /// How far apart in elevation layers blend into each other, without a splat map.
+    #[serde(default)]
+    pub blend: f64,
+}
+
+fn default_elevation_scale() -> f64 {
+    1.0
+}
+
+/// One of the materials terrain is splatted with.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SplatLayer {
+    /// Straight alpha, multiplying the image if there is one.
+    #[serde(default = "default_layer_color")]
+    pub color: [f64; 4],
+    /// Id of a texture asset tiled over the terrain.
+    #[serde(default)]
+    pub image: Option<String>,
+    /// How many times the image repeats across the terrain.
+    #[serde(default = "default_tiling")]
+    pub tiling: f64,
+    /// The lowest and highest elevation the layer covers without a splat map, or all of
+    /// them with `None`. Later layers go over earlier ones where they overlap.
+    #[serde(default)]
+    pub elevation: Option<[f64; 2]>,
+}
+
+fn default_layer_color() -> [f64; 4] {
+    [1.0, 1.0, 1.0, 1.0]
+}
+
+fn default_tiling() -> f64 {
+    1.0
+}
+
+/// An orbit around a point on the terrain. The overlay's navigation moves it further, so
+/// gestures and gamepads pan, zoom and turn the terrain like a map.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerrainCamera {
+    /// The point looked at, east and south of the terrain's middle.
+    pub target: [f64; 2],
+    /// From the target, in the terrain's units.
+    pub distance: f64,
+    /// Radians above the horizon.
+    pub pitch: f64,
+    /// Radians clockwise from looking north.
+    pub yaw: f64,
+    /// The vertical field of view, in degrees.
+    #[serde(default = "default_fov")]
+    pub fov: f64,
+}
+
+fn default_fov() -> f64 {
+    45.0
+}
+
+impl TerrainCamera {
+    /// Looking down on all of a terrain of `size` from the south.
+    fn fitting(size: [f64; 2]) -> Self {
+        TerrainCamera {
+            target: [0.0, 0.0],
+            distance: size[0].max(size[1]) * 1.2,
+            pitch: 0.6,
+            yaw: 0.0,
+            fov: default_fov(),
+        }
+    }
+
+    /// The camera once the overlay's navigation has moved it, given the surface's
+    /// `logical_size`. Navigation treats the terrain as a map under the camera as it is,
+    /// so it moves as far as content under the middle of the overlay would.
+    fn navigated(&self, navigation: &Navigation, logical_size: [f32; 2]) -> Self {
+        let center = [logical_size[0] as f64 / 2.0, logical_size[1] as f64 / 2.0];
+        let (sin, cos) = (-navigation.rotation).sin_cos();
+        let x = (center[0] - navigation.offset[0]) / navigation.zoom;
+        let y = (center[1] - navigation.offset[1]) / navigation.zoom;
+        let shift = [x * cos - y * sin - center[0], x * sin + y * cos - center[1]];
+
+        let fov = self.fov.to_radians();
+        let units_per_pixel =
+            2.0 * self.distance * (fov / 2.0).tan() / (logical_size[1] as f64).max(1.0);
+        let (yaw_sin, yaw_cos) = self.yaw.sin_cos();
+        // Across the screen is east and down it is south, turned by the yaw
+        let east = shift[0] * yaw_cos - shift[1] * yaw_sin;
+        let south = shift[0] * yaw_sin + shift[1] * yaw_cos;
+        TerrainCamera {
+            target: [
+                self.target[0] + east * units_per_pixel,
+                self.target[1] + south * units_per_pixel,
+            ],
+            distance: self.distance / navigation.zoom,
+            // Turning the camera one way turns the terrain the other
+            yaw: self.yaw - navigation.rotation,
+            ..*self
+        }
+    }
+
+    /// Where the camera is, looking at `elevation` over its target.
+    fn eye(&self, elevation: f32) -> [f32; 3] {
+        let (pitch_sin, pitch_cos) = self.pitch.sin_cos();
+        let (yaw_sin, yaw_cos) = self.yaw.sin_cos();
+        [
+            (self.target[0] - yaw_sin * pitch_cos * self.distance) as f32,
+            elevation + (pitch_sin * self.distance) as f32,
+            (self.target[1] + yaw_cos * pitch_cos * self.distance) as f32,
+        ]
+    }
+
+    fn view_projection(&self, elevation: f32, aspect: f32, extent: f32) -> [[f32; 4]; 4] {
+        let eye = self.eye(elevation);
+        let target = [self.target[0] as f32, elevation, self.target[1] as f32];
+        let distance = self.distance as f32;
+        let near = (distance * 0.01).max(0.01);
+        let far = distance * 4.0 + extent * 2.0;
+        multiply(
+            perspective(self.fov.to_radians() as f32, aspect, near, far),
+            look_at(eye, target),
+        )
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct TerrainUniforms {
+    view_projection: [[f32; 4]; 4],
+    light: [f32; 4],
+    colors: [[f32; 4]; SPLAT_LAYERS],
+    /// Lowest and highest elevation, tiling, and 1 if there's an elevation band.
+    bands: [[f32; 4]; SPLAT_LAYERS],
+    /// The number of layers, 1 if the splat map weights them, and how far bands blend.
+    params: [f32; 4],
+}
+
+/// One square of the terrain, with its own vertices so it can be drawn at its own level
+/// of detail. Chunks at the terrain's far edges repeat the edge samples to fill out.
+struct Chunk {
+    vertices: wgpu::Buffer,
+    /// The corners of the box around the chunk and its skirts, lowest first.
+    bounds: [[f32; 3]; 2],
+    lod: u32,
+    /// Whether any of the chunk is in view, as of the last `prepare`.
+    visible: bool,
+}
+
+struct Terrain {
+    descriptor: TerrainDescriptor,
+    /// Index buffers for each level of detail, shared by every chunk, and their lengths.
+    levels: Vec<(wgpu::Buffer, u32)>,
+    chunks: Vec<Chunk>,
+    /// How far a chunk's cells span on their longer side, at the finest level.
+    cell_width: f32,
+    /// Lowest and highest.
+    elevation: [f32; 2],
+    /// The layer images and then the splat map, for rebinding them when they're reloaded.
+    bound: Vec<Option<Arc<Asset>>>,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Where terrain is drawn, before it's composited over the background.
+struct Target {
+    view: wgpu::TextureView,
+    depth: wgpu::TextureView,
+    source: wgpu::BindGroup,
+}
+
+/// A heightmap drawn in 3D, and models placed on it, under everything else but the
+/// background, for maps and scenes that need terrain beneath the page's controls.
+pub struct TerrainLayer {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    uniforms: wgpu::Buffer,
+    tiled_sampler: wgpu::Sampler,
+    clamped_sampler: wgpu::Sampler,
+    /// Bound in place of images layers don't have.
+    white: wgpu::TextureView,
+    format: wgpu::TextureFormat,
+    size: tauri::PhysicalSize<u32>,
+    target: Option<Target>,
+    terrain: Option<Terrain>,
+    camera: Option<TerrainCamera>,
+    models: SceneModels,
+}
+
+impl TerrainLayer {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color_format: wgpu::TextureFormat,
+        size: tauri::PhysicalSize<u32>,
+        pipelines: &Pipelines,
+    ) -> Self {
+        let shader = pipelines.shader(
+            device,
+            "Terrain Shader",
+            include_str!("shaders/terrain.wgsl"),
+        );
+
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Terrain Uniforms"),
+            size: std::mem::size_of::<TerrainUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let bind_group_layout = pipelines.bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Terrain Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    sampler_entry(1),
+                    sampler_entry(2),
+                    texture_entry(3),
+                    texture_entry(4),
+                    texture_entry(5),
+                    texture_entry(6),
+                    texture_entry(7),
+                ],
+            },
+        );
+
+        let layout = pipelines.pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Terrain Pipeline Layout"),
+                bind_group_layouts: &[Some(&bind_group_layout)],
+                immediate_size: 0,
+            },
+        );
+
+        let pipeline = pipelines.render_pipeline(
+            device,
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Terrain Pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[Some(ModelVertex::layout())],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(color_format.into())],
+                    compilation_options: Default::default(),
+                }),
+                // Skirts face either way
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: Some(true),
+                    depth_compare: Some(wgpu::CompareFunction::Less),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview_mask: None,
+                cache: pipelines.cache(),
+            },
+        );
+
+        let models = SceneModels::new(device, color_format, DEPTH_FORMAT, &uniforms, pipelines);
+
+        let tiled_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Terrain Tiled Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let clamped_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Terrain Clamped Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let white = device
+            .create_texture_with_data(
+                queue,
+                &wgpu::TextureDescriptor {
+                    label: Some("Terrain White"),
+                    size: wgpu::Extent3d {
+                        width: 1,
+                        height: 1,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                },
+                wgpu::util::TextureDataOrder::LayerMajor,
+                &[255; 4],
+            )
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        TerrainLayer {
+            pipeline,
+            bind_group_layout,
+            uniforms,
+            tiled_sampler,
+            clamped_sampler,
+            white,
+            format: color_format,
+            size,
+            target: None,
+            terrain: None,
+            camera: None,
+            models,
+        }
+    }
+
+    /// Build terrain from `descriptor`, replacing any there was. Its layer images and splat
+    /// map come from `assets`.
+    pub fn load(
+        &mut self,
+        device: &wgpu::Device,
+        images: &ImagePipeline,
+        assets: &Assets,
+        descriptor: TerrainDescriptor,
+    ) -> Result<(), String> {
+        if descriptor.layers.len() > SPLAT_LAYERS {
+            return Err(format!("terrain can have at most {} layers", SPLAT_LAYERS));
+        }
+        if !(descriptor.size[0] > 0.0 && descriptor.size[1] > 0.0) {
+            return Err("terrain needs a size".into());
+        }
+        let (heights, columns, rows) = load_heights(&descriptor)?;
+        // Only needed to build the chunks
+        let descriptor = TerrainDescriptor {
+            heights: None,
+            ..descriptor
+        };
+
+        let bound = bound_assets(&descriptor, assets)?;
+        let bind_group = self.bind(device, &bound);
+        let elevation = heights
+            .iter()
+            .fold([f32::MAX, f32::MIN], |[low, high], &h| {
+                [low.min(h), high.max(h)]
+            });
+        let chunks = build_chunks(device, &descriptor, &heights, columns, rows, elevation);
+        let levels = (0..LOD_LEVELS)
+            .map(|level| {
+                let indices = chunk_indices(1 << level);
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Terrain Indices"),
+                    contents: bytemuck::cast_slice(&indices),
+                    usage: wgpu::BufferUsages::INDEX,
+                });
+                (buffer, indices.len() as u32)
+            })
+            .collect();
+
+        let cell_width = (descriptor.size[0] / (columns - 1) as f64)
+            .max(descriptor.size[1] / (rows - 1) as f64) as f32;
+        self.terrain = Some(Terrain {
+            descriptor,
+            levels,
+            chunks,
+            cell_width,
+            elevation,
+            bound,
+            bind_group,
+        });
+        if self.target.is_none() {
+            self.target = Some(self.create_target(device, images));
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.terrain = None;
+        self.target = None;
+        self.models.clear();
+    }
+
+    /// Place `models` on the terrain, replacing any there were. They're dropped along with
+    /// the terrain.
+    pub fn set_models(
+        &mut self,
+        device: &wgpu::Device,
+        assets: &Assets,
+        models: Vec<TerrainModel>,
+    ) -> Result<(), String> {
+        if self.terrain.is_none() {
+            return Err("there's no terrain to place models on".into());
+        }
+        self.models.set(device, assets, models)
+    }
+
+    /// Look at the terrain from `camera`, or from the south over all of it with `None`.
+    pub fn set_camera(&mut self, camera: Option<TerrainCamera>) {
+        self.camera = camera;
+    }
+
+    /// The camera before navigation moves it, if there's terrain to look at.
+    pub fn camera(&self) -> Option<TerrainCamera> {
+        let terrain = self.terrain.as_ref()?;
+        Some(
+            self.camera
+                .unwrap_or_else(|| TerrainCamera::fitting(terrain.descriptor.size)),
+        )
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        images: &ImagePipeline,
+        size: tauri::PhysicalSize<u32>,
+    ) {
+        self.size = size;
+        if self.target.is_some() {
+            self.target = Some(self.create_target(device, images));
+        }
+    }
+
+    /// Rebind layer images and the splat map, and swap in models, if their assets have been
+    /// reloaded. Keeps the old ones if any have been unloaded.
+    pub fn refresh_assets(&mut self, device: &wgpu::Device, assets: &Assets) {
+        self.models.refresh(assets);
+        let terrain = match &self.terrain {
+            Some(terrain) => terrain,
+            None => return,
+        };
+        let bound = match bound_assets(&terrain.descriptor, assets) {
+            Ok(bound) => bound,
+            Err(e) => {
+                println!("Failed to reload terrain images: {}", e);
+                return;
+            }
+        };
+        let current = bound.iter().zip(&terrain.bound).all(|pair| match pair {
+            (Some(new), Some(old)) => Arc::ptr_eq(new, old),
+            _ => true,
+        });
+        if !current {
+            let bind_group = self.bind(device, &bound);
+            if let Some(terrain) = &mut self.terrain {
+                terrain.bind_group = bind_group;
+                terrain.bound = bound;
+            }
+        }
+    }
+
+    /// Upload the camera, navigated by `navigation`, pick each chunk's and model mesh's
+    /// level of detail and leave out the ones that are out of view.
+    pub fn prepare(
+        &mut self,
+        uploads: &mut Uploads,
+        navigation: &Navigation,
+        logical_size: [f32; 2],
+    ) {
+        let camera = match self.camera() {
+            Some(camera) => camera.navigated(navigation, logical_size),
+            None => return,
+        };
+        let terrain = match &mut self.terrain {
+            Some(terrain) => terrain,
+            None => return,
+        };
+        let size = terrain.descriptor.size;
+        let extent = size[0].max(size[1]) as f32;
+        let middle = (terrain.elevation[0] + terrain.elevation[1]) / 2.0;
+        let aspect = logical_size[0] / logical_size[1].max(1.0);
+
+        let view_projection = camera.view_projection(middle, aspect, extent);
+        let frustum = frustum(&view_projection);
+        let eye = camera.eye(middle);
+        // How many logical pixels something a unit across is, a unit from the camera
+        let pixels_per_unit =
+            logical_size[1] / (2.0 * (camera.fov.to_radians() as f32 / 2.0).tan());
+        for chunk in &mut terrain.chunks {
+            chunk.visible = in_frustum(&frustum, chunk.bounds);
+            // The chunk's closest point sets how big its cells look
+            let distance = (0..3)
+                .map(|i| {
+                    let closest = eye[i].clamp(chunk.bounds[0][i], chunk.bounds[1][i]);
+                    (closest - eye[i]).powi(2)
+                })
+                .sum::<f32>()
+                .sqrt()
+                .max(f32::EPSILON);
+            let cell_pixels = terrain.cell_width * pixels_per_unit / distance;
+            // Each level doubles the size of cells, so this is the level where they'd be
+            // exactly the target size
+            let level = (lod::CELL_PIXELS / cell_pixels).max(1.0).log2();
+            chunk.lod = lod::pick(chunk.lod, level, LOD_LEVELS);
+        }
+        self.models.prepare(&frustum, eye, pixels_per_unit);
+
+        let mut uniforms = TerrainUniforms {
+            view_projection,
+            light: [SUN[0], SUN[1], SUN[2], 0.0],
+            colors: [[0.0; 4]; SPLAT_LAYERS],
+            bands: [[0.0; 4]; SPLAT_LAYERS],
+            params: [
+                terrain.descriptor.layers.len().max(1) as f32,
+                terrain.descriptor.splat_map.is_some() as u8 as f32,
+                terrain.descriptor.blend.max(0.0) as f32,
+                0.0,
+            ],
+        };
+        uniforms.colors[0] = to_array(DEFAULT_COLOR);
+        for (i, layer) in terrain.descriptor.layers.iter().enumerate() {
+            uniforms.colors[i] = to_array(layer.color);
+            let [low, high] = layer.elevation.unwrap_or([0.0, 0.0]);
+            uniforms.bands[i] = [
+                low as f32,
+                high as f32,
+                layer.tiling as f32,
+                layer.elevation.is_some() as u8 as f32,
+            ];
+        }
+        uploads.write(&self.uniforms, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    /// The uniforms and target, the terrain's vertices and indices, and the models' instances.
+    /// Images and models are assets, so they're counted with those.
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        usage.add_buffer(&self.uniforms);
+        self.models.memory(usage);
+        usage.add_view(&self.white);
+        if let Some(target) = &self.target {
+            usage.add_view(&target.view);
+            usage.add_view(&target.depth);
+        }
+        if let Some(terrain) = &self.terrain {
+            for (indices, _) in &terrain.levels {
+                usage.add_buffer(indices);
+            }
+            for chunk in &terrain.chunks {
+                usage.add_buffer(&chunk.vertices);
+            }
+        }
+    }
+
+    /// Draw the terrain and its models into their own target, ready to be composited with
+    /// [`Self::draw`].
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        let (terrain, target) = match (&self.terrain, &self.target) {
+            (Some(terrain), Some(target)) => (terrain, target),
+            _ => return,
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Terrain Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &target.view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &target.depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &terrain.bind_group, &[]);
+        for chunk in terrain.chunks.iter().filter(|chunk| chunk.visible) {
+            let (indices, count) = &terrain.levels[chunk.lod as usize];
+            render_pass.set_vertex_buffer(0, chunk.vertices.slice(..));
+            render_pass.set_index_buffer(indices.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.draw_indexed(0..*count, 0, 0..1);
+        }
+        self.models.draw(&mut render_pass);
+    }
+
+    /// Composite the terrain drawn by [`Self::render`] over what's been drawn so far.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, images: &'a ImagePipeline) {
+        if let Some(target) = &self.target {
+            images.draw_bound(render_pass, &target.source);
+        }
+    }
+
+    fn bind(&self, device: &wgpu::Device, bound: &[Option<Arc<Asset>>]) -> wgpu::BindGroup {
+        let views: Vec<&wgpu::TextureView> = bound
+            .iter()
+            .map(
+                |asset| match asset.as_ref().and_then(|asset| asset.texture()) {
+                    Some(texture) => &texture.view,
+                    None => &self.white,
+                },
+            )
+            .collect();
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: self.uniforms.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&self.tiled_sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(&self.clamped_sampler),
+            },
+        ];
+        for (i, view) in views.into_iter().enumerate() {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 3 + i as u32,
+                resource: wgpu::BindingResource::TextureView(view),
+            });
+        }
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Terrain Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &entries,
+        })
+    }
+
+    fn create_target(&self, device: &wgpu::Device, images: &ImagePipeline) -> Target {
+        let texture = |label, format, usage| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: self.size.width,
+                        height: self.size.height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let view = texture(
+            "Terrain Target",
+            self.format,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+        let depth = texture(
+            "Terrain Depth",
+            DEPTH_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        );
+        // Terrain is opaque, so it's the same in straight alpha
+        let source = images.bind(device, &view);
+        Target {
+            view,
+            depth,
+            source,
+        }
+    }
+}
+
+/// The elevations, scaled, with how many columns and rows of them there are.
+fn load_heights(descriptor: &TerrainDescriptor) -> Result<(Vec<f32>, u32, u32), String> {
+    let scale = descriptor.elevation_scale as f32;
+    let (heights, columns, rows) = match (&descriptor.heightmap, &descriptor.heights) {
+        (Some(path), _) => {
+            let image = image::open(path)
+                .map_err(|e| format!("failed to read heightmap {:?}: {}", path, e))?
+                .to_luma16();
+            let (columns, rows) = image.dimensions();
+            let heights = image
+                .pixels()
+                .map(|pixel| pixel.0[0] as f32 / u16::MAX as f32 * scale)
+                .collect();
+            (heights, columns, rows)
+        }
+        (None, Some(heights)) => {
+            let columns = descriptor.columns.unwrap_or(0);
+            if columns == 0 || heights.len() % columns as usize != 0 {
+                return Err("terrain heights need a number of columns that fits them".into());
+            }
+            let rows = (heights.len() / columns as usize) as u32;
+            (heights.iter().map(|h| h * scale).collect(), columns, rows)
+        }
+        (None, None) => return Err("terrain needs a heightmap or heights".into()),
+    };
+    if columns < 2 || rows < 2 {
+        return Err("terrain needs at least two rows and columns of elevations".into());
+    }
+    if columns > MAX_SAMPLES || rows > MAX_SAMPLES {
+        return Err(format!(
+            "terrain can have at most {} elevations on a side",
+            MAX_SAMPLES
+        ));
+    }
+    Ok((heights, columns, rows))
+}
+
+/// The assets of each layer's image and then the splat map, bound in that order.
+fn bound_assets(
+    descriptor: &TerrainDescriptor,
+    assets: &Assets,
+) -> Result<Vec<Option<Arc<Asset>>>, String> {
+    let lookup = |id: &Option<String>| -> Result<Option<Arc<Asset>>, String> {
+        let id = match id {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        let asset = assets
+            .get(id)
+            .ok_or_else(|| format!("no asset '{}' has been loaded", id))?;
+        if asset.texture().is_none() {
+            return Err(format!("asset '{}' is not a texture", id));
+        }
+        Ok(Some(asset))
+    };
+    let mut bound = Vec::with_capacity(SPLAT_LAYERS + 1);
+    for i in 0..SPLAT_LAYERS {
+        let layer = descriptor.layers.get(i);
+        bound.push(lookup(&layer.and_then(|layer| layer.image.clone()))?);
+    }
+    bound.push(lookup(&descriptor.splat_map)?);
+    Ok(bound)
+}
+
+fn build_chunks(
+    device: &wgpu::Device,
+    descriptor: &TerrainDescriptor,
+    heights: &[f32],
+    columns: u32,
+    rows: u32,
+    elevation: [f32; 2],
+) -> Vec<Chunk> {
+    let [width, depth] = [descriptor.size[0] as f32, descriptor.size[1] as f32];
+    let cell = [width / (columns - 1) as f32, depth / (rows - 1) as f32];
+    let height =
+        |x: u32, y: u32| heights[(y.min(rows - 1) * columns + x.min(columns - 1)) as usize];
+    let vertex = |x: u32, y: u32, drop: f32| {
+        let (x, y) = (x.min(columns - 1), y.min(rows - 1));
+        // Central differences, or one-sided ones at the edges
+        let (left, right) = (x.saturating_sub(1), (x + 1).min(columns - 1));
+        let (up, down) = (y.saturating_sub(1), (y + 1).min(rows - 1));
+        let dx = (height(right, y) - height(left, y)) / ((right - left) as f32 * cell[0]);
+        let dz = (height(x, down) - height(x, up)) / ((down - up) as f32 * cell[1]);
+        let length = (dx * dx + 1.0 + dz * dz).sqrt();
+        ModelVertex {
+            position: [
+                x as f32 * cell[0] - width / 2.0,
+                height(x, y) - drop,
+                y as f32 * cell[1] - depth / 2.0,
+            ],
+            normal: [-dx / length, 1.0 / length, -dz / length],
+            uv: [
+                x as f32 / (columns - 1) as f32,
+                y as f32 / (rows - 1) as f32,
+            ],
+        }
+    };
+    // Deep enough to hide the gaps between chunks at different levels
+    let skirt = (elevation[1] - elevation[0]) * 0.05 + cell[0].max(cell[1]) * 2.0;
+
+    let across = (columns - 1).div_ceil(CHUNK_CELLS);
+    let down = (rows - 1).div_ceil(CHUNK_CELLS);
+    let mut chunks = Vec::with_capacity((across * down) as usize);
+    for chunk_y in 0..down {
+        for chunk_x in 0..across {
+            let (left, top) = (chunk_x * CHUNK_CELLS, chunk_y * CHUNK_CELLS);
+            let mut vertices = Vec::new();
+            for y in 0..=CHUNK_CELLS {
+                for x in 0..=CHUNK_CELLS {
+                    vertices.push(vertex(left + x, top + y, 0.0));
+                }
+            }
+            // Skirts along the top, bottom, left and right edges, in that order
+            for edge in 0..4 {
+                for i in 0..=CHUNK_CELLS {
+                    let (x, y) = match edge {
+                        0 => (i, 0),
+                        1 => (i, CHUNK_CELLS),
+                        2 => (0, i),
+                        _ => (CHUNK_CELLS, i),
+                    };
+                    vertices.push(vertex(left + x, top + y, skirt));
+                }
+            }
+            let bounds =
+                vertices
+                    .iter()
+                    .fold([[f32::MAX; 3], [f32::MIN; 3]], |[low, high], vertex| {
+                        let p = vertex.position;
+                        [
+                            [low[0].min(p[0]), low[1].min(p[1]), low[2].min(p[2])],
+                            [high[0].max(p[0]), high[1].max(p[1]), high[2].max(p[2])],
+                        ]
+                    });
+            chunks.push(Chunk {
+                vertices: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Terrain Vertices"),
+                    contents: bytemuck::cast_slice(&vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                }),
+                bounds,
+                lod: 0,
+                visible: true,
+            });
+        }
+    }
+    chunks
+}
+
+/// A chunk's triangles using every `step`th sample, with its skirts.
+fn chunk_indices(step: u32) -> Vec<u16> {
+    let side = CHUNK_CELLS + 1;
+    let grid = |x: u32, y: u32| (y * side + x) as u16;
+    let skirt = |edge: u32, i: u32| (side * side + edge * side + i) as u16;
+    let mut indices = Vec::new();
+    for y in (0..CHUNK_CELLS).step_by(step as usize) {
+        for x in (0..CHUNK_CELLS).step_by(step as usize) {
+            let (a, b) = (grid(x, y), grid(x + step, y));
+            let (c, d) = (grid(x, y + step), grid(x + step, y + step));
+            indices.extend_from_slice(&[a, c, b, b, c, d]);
+        }
+    }
+    for i in (0..CHUNK_CELLS).step_by(step as usize) {
+        let edges = [
+            (grid(i, 0), grid(i + step, 0)),
+            (grid(i, CHUNK_CELLS), grid(i + step, CHUNK_CELLS)),
+            (grid(0, i), grid(0, i + step)),
+            (grid(CHUNK_CELLS, i), grid(CHUNK_CELLS, i + step)),
+        ];
+        for (edge, &(a, b)) in edges.iter().enumerate() {
+            let edge = edge as u32;
+            let (a_low, b_low) = (skirt(edge, i), skirt(edge, i + step));
+            indices.extend_from_slice(&[a, a_low, b, b, a_low, b_low]);
+        }
+    }
+    indices
+}
+
+fn to_array(color: [f64; 4]) -> [f32; 4] {
+    [
+        color[0] as f32,
+        color[1] as f32,
+        color[2] as f32,
+        color[3] as f32,
+    ]
+}
+
+/// The planes bounding what `view_projection` shows, as (a, b, c, d) with a, b and c
+/// pointing inward, so a point is inside when a * x + b * y + c * z + d isn't negative.
+fn frustum(view_projection: &[[f32; 4]; 4]) -> [[f32; 4]; 6] {
+    let m = view_projection;
+    let row = |i: usize| [m[0][i], m[1][i], m[2][i], m[3][i]];
+    let add = |a: [f32; 4], b: [f32; 4]| [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]];
+    let sub = |a: [f32; 4], b: [f32; 4]| [a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3] - b[3]];
+    let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+    // Clip space depth goes from 0 to w, so the near plane is just z
+    [add(w, x), sub(w, x), add(w, y), sub(w, y), z, sub(w, z)]
+}
+
+/// Whether any of the box from `bounds[0]` to `bounds[1]` might be inside `frustum`. Boxes
+/// near a corner of it can pass without being in view, which only costs drawing them.
+pub(super) fn in_frustum(frustum: &[[f32; 4]; 6], bounds: [[f32; 3]; 2]) -> bool {
+    frustum.iter().all(|plane| {
+        // The corner furthest along the plane's normal
+        let corner = |i: usize| bounds[(plane[i] > 0.0) as usize][i];
+        plane[0] * corner(0) + plane[1] * corner(1) + plane[2] * corner(2) + plane[3] >= 0.0
+    })
+}
+
+/// Column-major, with depth from 0 to 1 as wgpu has it.
+fn perspective(fov: f32, aspect: f32, near: f32, far: f32) -> [[f32; 4]; 4] {
+    let f = 1.0 / (fov / 2.0).tan();
+    [
+        [f / aspect, 0.0, 0.0, 0.0],
+        [0.0, f, 0.0, 0.0],
+        [0.0, 0.0, far / (near - far), -1.0],
+        [0.0, 0.0, near * far / (near - far), 0.0],
+    ]
+}
+
+/// A right-handed view from `eye` toward `target`, with y up.
+fn look_at(eye: [f32; 3], target: [f32; 3]) -> [[f32; 4]; 4] {
+    let normalize = |v: [f32; 3]| {
+        let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+        [v[0] / length, v[1] / length, v[2] / length]
+    };
+    let cross = |a: [f32; 3], b: [f32; 3]| {
+        [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]
+    };
+    let dot = |a: [f32; 3], b: [f32; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+    let forward = normalize([target[0] - eye[0], target[1] - eye[1], target[2] - eye[2]]);
+    let side = normalize(cross(forward, [0.0, 1.0, 0.0]));
+    let up = cross(side, forward);
+    [
+        [side[0], up[0], -forward[0], 0.0],
+        [side[1], up[1], -forward[1], 0.0],
+        [side[2], up[2], -forward[2], 0.0],
+        [-dot(side, eye), -dot(up, eye), dot(forward, eye), 1.0],
+    ]
+}
+
+fn multiply(a: [[f32; 4]; 4], b: [[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut product = [[0.0; 4]; 4];
+    for (column, b_column) in product.iter_mut().zip(&b) {
+        for (row, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b_column[k]).sum();
+        }
+    }
+    product
+}
@@ -0,0 +1,267 @@
+//! Sets text with font assets. Text is rasterized on the CPU when it changes and uploaded
+//! as a texture, which suits text that changes now and then rather than every frame.
+
+use ab_glyph::{point, Font, FontArc, Glyph, GlyphId, PxScaleFont, ScaleFont};
+use wgpu::util::DeviceExt;
+
+use super::image::ImagePipeline;
+use super::panes;
+use super::MemoryUsage;
+
+/// How to set a block of text. Sizes are physical pixels, and colors straight alpha.
+pub struct TextStyle {
+    pub font: FontArc,
+    pub size: f32,
+    pub color: [f64; 4],
+    /// Drawn `outline_width` around each glyph.
+    pub outline: Option<[f64; 4]>,
+    pub outline_width: f32,
+    /// Fills the whole block, `padding` out from the text.
+    pub background: Option<[f64; 4]>,
+    pub padding: f32,
+}
+
+/// Rasterized text: straight alpha sRGB rows, top to bottom.
+pub struct TextImage {
+    pub pixels: Vec<u8>,
+    pub size: [u32; 2],
+}
+
+#[derive(Default)]
+struct Line {
+    /// Positioned along the line, from its start.
+    glyphs: Vec<Glyph>,
+    width: f32,
+    last: Option<GlyphId>,
+}
+
+impl Line {
+    fn push(&mut self, font: &PxScaleFont<&FontArc>, text: &str) {
+        for c in text.chars() {
+            let mut glyph = font.scaled_glyph(c);
+            if let Some(last) = self.last {
+                self.width += font.kern(last, glyph.id);
+            }
+            glyph.position = point(self.width, 0.0);
+            self.width += font.h_advance(glyph.id);
+            self.last = Some(glyph.id);
+            self.glyphs.push(glyph);
+        }
+    }
+
+    fn measure(font: &PxScaleFont<&FontArc>, text: &str) -> f32 {
+        let mut line = Line::default();
+        line.push(font, text);
+        line.width
+    }
+}
+
+/// Break `text` into lines no wider than `max_width`, at spaces where possible and at
+/// every newline.
+fn layout(font: &PxScaleFont<&FontArc>, text: &str, max_width: f32) -> Vec<Line> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = Line::default();
+        for word in paragraph.split_whitespace() {
+            if !line.glyphs.is_empty() {
+                let wrapped = line.width + Line::measure(font, " ") + Line::measure(font, word);
+                if wrapped > max_width {
+                    lines.push(std::mem::take(&mut line));
+                } else {
+                    line.push(font, " ");
+                }
+            }
+            line.push(font, word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Set `text` in centered lines no wider than `max_width`, or `None` if there's nothing
+/// to draw.
+pub fn rasterize(text: &str, style: &TextStyle, max_width: f32) -> Option<TextImage> {
+    let font = style.font.as_scaled(style.size);
+    let lines = layout(&font, text, max_width);
+    if lines.iter().all(|line| line.glyphs.is_empty()) {
+        return None;
+    }
+
+    let line_height = font.height() + font.line_gap();
+    let margin = style.padding + style.outline.map_or(0.0, |_| style.outline_width);
+    let text_width = lines.iter().map(|line| line.width).fold(0.0, f32::max);
+    let text_height = line_height * lines.len() as f32 - font.line_gap();
+    let width = (text_width + margin * 2.0).ceil() as usize;
+    let height = (text_height + margin * 2.0).ceil() as usize;
+
+    let mut coverage = vec![0.0f32; width * height];
+    for (row, line) in lines.iter().enumerate() {
+        let left = margin + (text_width - line.width) / 2.0;
+        let baseline = margin + line_height * row as f32 + font.ascent();
+        for glyph in &line.glyphs {
+            let mut glyph = glyph.clone();
+            glyph.position = point(left + glyph.position.x, baseline);
+            let outlined = match style.font.outline_glyph(glyph) {
+                Some(outlined) => outlined,
+                None => continue,
+            };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|x, y, c| {
+                let x = bounds.min.x as i32 + x as i32;
+                let y = bounds.min.y as i32 + y as i32;
+                if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+                    let covered = &mut coverage[y as usize * width + x as usize];
+                    *covered = (*covered + c).min(1.0);
+                }
+            });
+        }
+    }
+
+    let outline = style
+        .outline
+        .filter(|_| style.outline_width > 0.0)
+        .map(|color| (color, dilate(&coverage, width, style.outline_width)));
+    let background = style.background.map_or([0.0; 4], to_f32);
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for (i, &covered) in coverage.iter().enumerate() {
+        let mut pixel = background;
+        if let Some((color, outline)) = &outline {
+            pixel = over(with_alpha(*color, outline[i]), pixel);
+        }
+        pixel = over(with_alpha(style.color, covered), pixel);
+        pixels.extend(pixel.iter().map(|c| (c * 255.0).round() as u8));
+    }
+
+    Some(TextImage {
+        pixels,
+        size: [width as u32, height as u32],
+    })
+}
+
+/// Spread coverage `radius` pixels outwards, for outlines.
+fn dilate(coverage: &[f32], width: usize, radius: f32) -> Vec<f32> {
+    let height = coverage.len() / width;
+    let reach = radius.ceil() as i32;
+    let mut dilated = vec![0.0f32; coverage.len()];
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            let mut strongest = 0.0f32;
+            for dy in -reach..=reach {
+                for dx in -reach..=reach {
+                    let (sx, sy) = (x + dx, y + dy);
+                    if sx < 0 || sy < 0 || sx >= width as i32 || sy >= height as i32 {
+                        continue;
+                    }
+                    // Soften the outline's edge over a pixel
+                    let distance = ((dx * dx + dy * dy) as f32).sqrt();
+                    let weight = (radius + 0.5 - distance).clamp(0.0, 1.0);
+                    strongest = strongest.max(coverage[sy as usize * width + sx as usize] * weight);
+                }
+            }
+            dilated[y as usize * width + x as usize] = strongest;
+        }
+    }
+    dilated
+}
+
+fn to_f32(color: [f64; 4]) -> [f32; 4] {
+    [
+        color[0] as f32,
+        color[1] as f32,
+        color[2] as f32,
+        color[3] as f32,
+    ]
+}
+
+fn with_alpha(color: [f64; 4], coverage: f32) -> [f32; 4] {
+    let [r, g, b, a] = to_f32(color);
+    [r, g, b, a * coverage]
+}
+
+/// Straight alpha `source` over `destination`.
+fn over(source: [f32; 4], destination: [f32; 4]) -> [f32; 4] {
+    let alpha = source[3] + destination[3] * (1.0 - source[3]);
+    if alpha <= 0.0 {
+        return [0.0; 4];
+    }
+    let mix = |s: f32, d: f32| (s * source[3] + d * destination[3] * (1.0 - source[3])) / alpha;
+    [
+        mix(source[0], destination[0]),
+        mix(source[1], destination[1]),
+        mix(source[2], destination[2]),
+        alpha,
+    ]
+}
+
+/// Rasterized text, uploaded for drawing with the image pipeline.
+pub struct TextSurface {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    size: [u32; 2],
+}
+
+impl TextSurface {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        images: &ImagePipeline,
+        image: &TextImage,
+    ) -> Self {
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Text"),
+                size: wgpu::Extent3d {
+                    width: image.size[0],
+                    height: image.size[1],
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            &image.pixels,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = images.bind(device, &view);
+        TextSurface {
+            texture,
+            bind_group,
+            size: image.size,
+        }
+    }
+
+    /// Width and height in physical pixels.
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        usage.add_texture(&self.texture);
+    }
+
+    /// Draw the text with its top-left at (`x`, `y`) in physical pixels.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        images: &'a ImagePipeline,
+        x: f32,
+        y: f32,
+        target: tauri::PhysicalSize<u32>,
+    ) {
+        render_pass.set_viewport(
+            x.round(),
+            y.round(),
+            self.size[0] as f32,
+            self.size[1] as f32,
+            0.0,
+            1.0,
+        );
+        images.draw_bound(render_pass, &self.bind_group);
+        panes::reset(render_pass, target);
+    }
+}
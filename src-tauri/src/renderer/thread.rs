@@ -0,0 +1,191 @@
+//! The thread each overlay renders on. It owns the overlay's [`WgpuState`], and everything
+//! else changes it by sending [`RenderCommand`]s through a [`RenderHandle`], so window
+//! events, input and commands never wait for a frame to finish drawing.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tauri::{AppHandle, Manager, PhysicalSize};
+
+use super::WgpuState;
+use crate::assets::Assets;
+use crate::overlay::{self, OverlayView};
+
+/// How often frames are drawn, unless a redraw is asked for sooner.
+pub(super) const FRAME_INTERVAL: Duration = Duration::from_millis(15);
+
+type RenderTask = Box<dyn FnOnce(&mut WgpuState, &Assets) + Send>;
+
+/// Something for the render thread to do. Commands run between frames, in the order they
+/// were sent.
+pub enum RenderCommand {
+    /// Reconfigure the surface for the view's new size.
+    Resize(PhysicalSize<u32>),
+    SetScaleFactor(f64),
+    /// Set an input of the ISF shader on a layer, sending back whether it could be.
+    SetUniform {
+        layer: String,
+        name: String,
+        value: serde_json::Value,
+        done: Sender<Result<(), String>>,
+    },
+    /// Draw a frame now, rather than when the next one is due.
+    Redraw,
+    /// Change or read anything else about the renderer.
+    Run(RenderTask),
+    /// Stop rendering, dropping the renderer.
+    Shutdown,
+}
+
+/// Sends commands to an overlay's render thread. Those that only change something return
+/// right away; those that read something back wait for the thread to get to them, so they
+/// mustn't be used while holding the overlay's view, which the thread presents to.
+#[derive(Clone)]
+pub struct RenderHandle {
+    sender: Sender<RenderCommand>,
+    /// Kept here as well, since placing the view needs it on the main thread.
+    scale_factor: Arc<AtomicU64>,
+}
+
+/// The receiving end of a [`RenderHandle`], until the render thread starts with it.
+/// Anything sent before then waits for it.
+pub struct RenderCommands(Receiver<RenderCommand>);
+
+impl RenderHandle {
+    pub fn new(scale_factor: f64) -> (Self, RenderCommands) {
+        let (sender, receiver) = mpsc::channel();
+        let handle = RenderHandle {
+            sender,
+            scale_factor: Arc::new(AtomicU64::new(scale_factor.to_bits())),
+        };
+        (handle, RenderCommands(receiver))
+    }
+
+    /// Send a command, which is dropped if the thread has stopped.
+    fn send(&self, command: RenderCommand) {
+        let _ = self.sender.send(command);
+    }
+
+    pub fn resize(&self, size: PhysicalSize<u32>) {
+        self.send(RenderCommand::Resize(size));
+    }
+
+    pub fn scale_factor(&self) -> f64 {
+        f64::from_bits(self.scale_factor.load(Ordering::Relaxed))
+    }
+
+    pub fn set_scale_factor(&self, scale_factor: f64) {
+        self.scale_factor
+            .store(scale_factor.to_bits(), Ordering::Relaxed);
+        self.send(RenderCommand::SetScaleFactor(scale_factor));
+    }
+
+    pub fn set_uniform(
+        &self,
+        layer: String,
+        name: String,
+        value: serde_json::Value,
+    ) -> Result<(), String> {
+        let (done, result) = mpsc::channel();
+        self.send(RenderCommand::SetUniform {
+            layer,
+            name,
+            value,
+            done,
+        });
+        result.recv().map_err(|_| stopped())?
+    }
+
+    pub fn redraw(&self) {
+        self.send(RenderCommand::Redraw);
+    }
+
+    pub fn shutdown(&self) {
+        self.send(RenderCommand::Shutdown);
+    }
+
+    /// Change the renderer without waiting for it to happen.
+    pub fn post(&self, f: impl FnOnce(&mut WgpuState) + Send + 'static) {
+        self.send(RenderCommand::Run(Box::new(move |wgpu, _| f(wgpu))));
+    }
+
+    /// Run `f` against the renderer and wait for what it returns.
+    pub fn call<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut WgpuState) -> T + Send + 'static,
+    ) -> Result<T, String> {
+        self.call_with_assets(move |wgpu, _| f(wgpu))
+    }
+
+    /// Like [`RenderHandle::call`], for changes that can use loaded assets.
+    pub fn call_with_assets<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut WgpuState, &Assets) -> T + Send + 'static,
+    ) -> Result<T, String> {
+        let (sender, receiver) = mpsc::channel();
+        self.send(RenderCommand::Run(Box::new(move |wgpu, assets| {
+            let _ = sender.send(f(wgpu, assets));
+        })));
+        receiver.recv().map_err(|_| stopped())
+    }
+}
+
+fn stopped() -> String {
+    "the overlay has stopped rendering".into()
+}
+
+impl RenderCommands {
+    /// Start the render thread, which owns `wgpu` from now on. `frame` draws a frame every
+    /// [`FRAME_INTERVAL`] or on a redraw, until a [`RenderCommand::Shutdown`].
+    pub fn start(
+        self,
+        mut wgpu: WgpuState,
+        view: Arc<Mutex<dyn OverlayView + Send>>,
+        handle: AppHandle,
+        mut frame: impl FnMut(&mut WgpuState) + Send + 'static,
+    ) {
+        std::thread::spawn(move || {
+            let mut next_frame = Instant::now();
+            loop {
+                let now = Instant::now();
+                if now >= next_frame {
+                    frame(&mut wgpu);
+                    next_frame = now + FRAME_INTERVAL;
+                }
+                let timeout = next_frame.saturating_duration_since(Instant::now());
+                let command = match self.0.recv_timeout(timeout) {
+                    Ok(command) => command,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                match command {
+                    RenderCommand::Resize(size) => {
+                        wgpu.resize(size);
+                        // Configuring the surface can undo the view's layer options
+                        let view = view.clone();
+                        overlay::run_on_main_thread(&handle, move || {
+                            view.lock().unwrap().surface_configured();
+                        });
+                    }
+                    RenderCommand::SetScaleFactor(scale_factor) => {
+                        wgpu.set_scale_factor(scale_factor)
+                    }
+                    RenderCommand::SetUniform {
+                        layer,
+                        name,
+                        value,
+                        done,
+                    } => {
+                        let assets = handle.state::<Assets>();
+                        let _ = done.send(wgpu.set_uniform(&layer, &name, value, &assets));
+                    }
+                    RenderCommand::Redraw => next_frame = Instant::now(),
+                    RenderCommand::Run(task) => task(&mut wgpu, &handle.state::<Assets>()),
+                    RenderCommand::Shutdown => break,
+                }
+            }
+        });
+    }
+}
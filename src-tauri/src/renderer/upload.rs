@@ -0,0 +1,63 @@
+//! Uploads of what changes every frame, like uniforms and instances. Rather than staging each
+//! write on its own the way `queue.write_buffer` does, writes are packed into a staging belt
+//! whose buffers are reused from frame to frame, so large uploads don't allocate and map a
+//! new staging buffer each time.
+
+use std::num::NonZeroU64;
+
+use wgpu::util::StagingBelt;
+
+/// The size the belt's buffers are allocated in. Writes bigger than this get a buffer of
+/// their own, which is reused like the others.
+const CHUNK_SIZE: wgpu::BufferAddress = 256 * 1024;
+
+pub struct Uploads {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    belt: StagingBelt,
+    /// Records the copies out of the belt, from the first write of a frame until they're
+    /// submitted.
+    encoder: Option<wgpu::CommandEncoder>,
+}
+
+impl Uploads {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        Uploads {
+            device: device.clone(),
+            queue: queue.clone(),
+            belt: StagingBelt::new(device.clone(), CHUNK_SIZE),
+            encoder: None,
+        }
+    }
+
+    /// Write `data` into `buffer` at `offset`, once the frame's uploads are submitted.
+    pub fn write(&mut self, buffer: &wgpu::Buffer, offset: wgpu::BufferAddress, data: &[u8]) {
+        let size = match NonZeroU64::new(data.len() as u64) {
+            Some(size) if size.get() % wgpu::COPY_BUFFER_ALIGNMENT == 0 => size,
+            // The belt can only copy whole words, so anything else is staged by the queue
+            _ => return self.queue.write_buffer(buffer, offset, data),
+        };
+        let device = &self.device;
+        let encoder = self.encoder.get_or_insert_with(|| {
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Upload Encoder"),
+            })
+        });
+        self.belt
+            .write_buffer(encoder, buffer, offset, size)
+            .copy_from_slice(data);
+    }
+
+    /// The copies written since the last frame, to submit ahead of the frame's own commands.
+    pub fn finish(&mut self) -> Option<wgpu::CommandBuffer> {
+        let encoder = self.encoder.take()?;
+        self.belt.finish();
+        Some(encoder.finish())
+    }
+
+    /// Get the belt's buffers back for reuse, once what [`Uploads::finish`] returned has
+    /// been submitted.
+    pub fn recall(&mut self) {
+        self.belt.recall();
+    }
+}
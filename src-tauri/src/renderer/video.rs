@@ -0,0 +1,347 @@
+use wgpu::util::DeviceExt;
+
+use super::image::ImagePipeline;
+use super::panes;
+use super::pipelines::Pipelines;
+use super::MemoryUsage;
+use crate::video::{FrameImage, VideoFrame, YuvColor, YuvMatrix};
+
+/// The format frames are stored in once they're RGB.
+const FRAME_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Converts NV12 frames to RGB on the GPU, so hardware decoded video never goes through the
+/// CPU's hands as pixels.
+pub struct VideoPipeline {
+    pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+}
+
+impl VideoPipeline {
+    pub fn new(device: &wgpu::Device, pipelines: &Pipelines) -> Self {
+        let shader = pipelines.shader(device, "YUV Shader", include_str!("shaders/yuv.wgsl"));
+        let pipeline = pipelines.render_pipeline(
+            device,
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("YUV Pipeline"),
+                layout: None,
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(FRAME_FORMAT.into())],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview_mask: None,
+                cache: pipelines.cache(),
+            },
+        );
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("YUV Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        VideoPipeline { pipeline, sampler }
+    }
+
+    /// Convert the `luma` and `chroma` planes into `target`.
+    fn convert(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        luma: &wgpu::Texture,
+        chroma: &wgpu::Texture,
+        color: YuvColor,
+        target: &wgpu::TextureView,
+    ) {
+        let conversion = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("YUV Conversion"),
+            contents: bytemuck::cast_slice(&conversion_matrix(color)),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let luma = luma.create_view(&wgpu::TextureViewDescriptor::default());
+        let chroma = chroma.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("YUV Bind Group"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&luma),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&chroma),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: conversion.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("YUV Encoder"),
+        });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("YUV Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+                multiview_mask: None,
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+/// The columns of a matrix taking (Y, Cb, Cr, 1) to RGB.
+fn conversion_matrix(color: YuvColor) -> [[f32; 4]; 4] {
+    let (kr, kb) = match color.matrix {
+        YuvMatrix::Bt601 => (0.299, 0.114),
+        YuvMatrix::Bt709 => (0.2126, 0.0722),
+    };
+    let kg = 1.0 - kr - kb;
+    // Stretch luma to 0-1 and center chroma on 0
+    let (luma_offset, luma_scale, chroma_scale) = match color.full_range {
+        true => (0.0, 1.0, 1.0),
+        false => (16.0 / 255.0, 255.0 / 219.0, 255.0 / 224.0),
+    };
+    let chroma_offset = 128.0 / 255.0;
+    let channel = |y: f32, cb: f32, cr: f32| {
+        let (y, cb, cr) = (y * luma_scale, cb * chroma_scale, cr * chroma_scale);
+        [y, cb, cr, -(y * luma_offset + (cb + cr) * chroma_offset)]
+    };
+    let r = channel(1.0, 0.0, 2.0 * (1.0 - kr));
+    let g = channel(
+        1.0,
+        -2.0 * kb * (1.0 - kb) / kg,
+        -2.0 * kr * (1.0 - kr) / kg,
+    );
+    let b = channel(1.0, 2.0 * (1.0 - kb), 0.0);
+    [
+        [r[0], g[0], b[0], 0.0],
+        [r[1], g[1], b[1], 0.0],
+        [r[2], g[2], b[2], 0.0],
+        [r[3], g[3], b[3], 1.0],
+    ]
+}
+
+/// Textures for NV12 frames that arrive in memory.
+struct Planes {
+    luma: wgpu::Texture,
+    chroma: wgpu::Texture,
+}
+
+/// The overlay's video, drawn over the background and under everything else, letterboxed
+/// to keep its aspect ratio.
+pub struct VideoSurface {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+    size: [u32; 2],
+    planes: Option<Planes>,
+    /// The frame being shown, kept so the decoder doesn't reuse its surface.
+    #[cfg(target_os = "macos")]
+    _pixel_buffer: Option<crate::video::PixelBuffer>,
+}
+
+impl VideoSurface {
+    pub fn new(device: &wgpu::Device, images: &ImagePipeline, size: [u32; 2]) -> Self {
+        let texture = create_texture(
+            device,
+            "Video Frame",
+            FRAME_FORMAT,
+            size,
+            wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = images.bind(device, &view);
+        VideoSurface {
+            texture,
+            view,
+            bind_group,
+            size,
+            planes: None,
+            #[cfg(target_os = "macos")]
+            _pixel_buffer: None,
+        }
+    }
+
+    /// Whether frames of `size` fit the texture.
+    pub fn fits(&self, size: [u32; 2]) -> bool {
+        self.size == size
+    }
+
+    pub fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipeline: &VideoPipeline,
+        frame: VideoFrame,
+    ) {
+        let (width, height) = (frame.width, frame.height);
+        match frame.image {
+            FrameImage::Rgba(pixels) => {
+                write_plane(queue, &self.texture, &pixels, 4, [width, height]);
+            }
+            FrameImage::Nv12 {
+                luma,
+                chroma,
+                color,
+            } => {
+                let chroma_size = [width.div_ceil(2), height.div_ceil(2)];
+                let size = self.size;
+                let planes = self.planes.get_or_insert_with(|| {
+                    let usage =
+                        wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+                    Planes {
+                        luma: create_texture(
+                            device,
+                            "Video Luma",
+                            wgpu::TextureFormat::R8Unorm,
+                            size,
+                            usage,
+                        ),
+                        chroma: create_texture(
+                            device,
+                            "Video Chroma",
+                            wgpu::TextureFormat::Rg8Unorm,
+                            chroma_size,
+                            usage,
+                        ),
+                    }
+                });
+                write_plane(queue, &planes.luma, &luma, 1, [width, height]);
+                write_plane(queue, &planes.chroma, &chroma, 2, chroma_size);
+                pipeline.convert(
+                    device,
+                    queue,
+                    &planes.luma,
+                    &planes.chroma,
+                    color,
+                    &self.view,
+                );
+            }
+            #[cfg(target_os = "macos")]
+            FrameImage::PixelBuffer(pixel_buffer, color) => {
+                match super::iosurface::import_planes(device, &pixel_buffer) {
+                    Some((luma, chroma)) => {
+                        pipeline.convert(device, queue, &luma, &chroma, color, &self.view);
+                        self._pixel_buffer = Some(pixel_buffer);
+                    }
+                    None => println!("Failed to show a video frame: it isn't on the overlay's GPU"),
+                }
+            }
+        }
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        usage.add_texture(&self.texture);
+        if let Some(planes) = &self.planes {
+            usage.add_texture(&planes.luma);
+            usage.add_texture(&planes.chroma);
+        }
+    }
+
+    /// Where the video goes in a target of `target`'s size, as [x, y, width, height] in
+    /// physical pixels: as large as fits, keeping its aspect ratio, and centered.
+    pub fn fitted(&self, target: tauri::PhysicalSize<u32>) -> [f32; 4] {
+        let (width, height) = (target.width as f32, target.height as f32);
+        let scale = (width / self.size[0] as f32).min(height / self.size[1] as f32);
+        let (fitted_width, fitted_height) =
+            (self.size[0] as f32 * scale, self.size[1] as f32 * scale);
+        [
+            (width - fitted_width) / 2.0,
+            (height - fitted_height) / 2.0,
+            fitted_width,
+            fitted_height,
+        ]
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        images: &'a ImagePipeline,
+        target: tauri::PhysicalSize<u32>,
+    ) {
+        let [x, y, width, height] = self.fitted(target);
+        render_pass.set_viewport(x, y, width, height, 0.0, 1.0);
+        images.draw_bound(render_pass, &self.bind_group);
+        panes::reset(render_pass, target);
+    }
+}
+
+fn create_texture(
+    device: &wgpu::Device,
+    label: &str,
+    format: wgpu::TextureFormat,
+    size: [u32; 2],
+    usage: wgpu::TextureUsages,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size[0],
+            height: size[1],
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage,
+        view_formats: &[],
+    })
+}
+
+/// Upload tightly packed rows of `bytes_per_pixel` to `texture`.
+fn write_plane(
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    data: &[u8],
+    bytes_per_pixel: u32,
+    size: [u32; 2],
+) {
+    queue.write_texture(
+        texture.as_image_copy(),
+        data,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(size[0] * bytes_per_pixel),
+            rows_per_image: None,
+        },
+        wgpu::Extent3d {
+            width: size[0],
+            height: size[1],
+            depth_or_array_layers: 1,
+        },
+    );
+}
@@ -0,0 +1,565 @@
+use serde::{Deserialize, Serialize};
+
+use super::clip;
+use super::navigation::Navigation;
+use super::pipelines::Pipelines;
+use super::upload::Uploads;
+use super::MemoryUsage;
+
+/// Entries in the transfer function's lookup table.
+const TRANSFER_SIZE: u32 = 256;
+/// How far a drag across the whole overlay turns the volume, in radians.
+const DRAG_TURN: f64 = std::f64::consts::PI;
+/// Keeps the camera from flipping over the top or bottom of the volume.
+const MAX_PITCH: f64 = 1.5;
+
+/// A 3D grid of scalar values, such as a CT scan or a simulation's density field. Values
+/// are uploaded afterwards, a slab of slices at a time.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeDescriptor {
+    /// Columns, rows and slices.
+    pub dimensions: [u32; 3],
+    /// The size of a voxel along each dimension, in any units, for volumes that aren't
+    /// sampled evenly.
+    #[serde(default = "default_spacing")]
+    pub spacing: [f64; 3],
+    /// The values the transfer function spans. Values outside it are clamped.
+    pub range: [f64; 2],
+}
+
+fn default_spacing() -> [f64; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+/// A point on the transfer function, which colors values in between by interpolating
+/// the points on either side.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferPoint {
+    pub value: f64,
+    /// Straight alpha. Alpha is the opacity through one voxel.
+    pub color: [f64; 4],
+}
+
+/// An orbit around the volume's middle. The overlay's navigation moves it further: panning
+/// turns the volume, and zooming and rotating do as they say.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeCamera {
+    /// Radians clockwise around the vertical axis, from looking at the first slice.
+    #[serde(default)]
+    pub yaw: f64,
+    /// Radians above the middle.
+    #[serde(default)]
+    pub pitch: f64,
+    /// From the middle, in lengths of the volume's longest side.
+    #[serde(default = "default_distance")]
+    pub distance: f64,
+    /// The vertical field of view, in degrees.
+    #[serde(default = "default_fov")]
+    pub fov: f64,
+}
+
+fn default_distance() -> f64 {
+    2.0
+}
+
+fn default_fov() -> f64 {
+    45.0
+}
+
+impl Default for VolumeCamera {
+    fn default() -> Self {
+        VolumeCamera {
+            yaw: 0.0,
+            pitch: 0.0,
+            distance: default_distance(),
+            fov: default_fov(),
+        }
+    }
+}
+
+impl VolumeCamera {
+    fn navigated(&self, navigation: &Navigation, logical_size: [f32; 2]) -> Self {
+        let height = (logical_size[1] as f64).max(1.0);
+        VolumeCamera {
+            yaw: self.yaw - navigation.rotation - navigation.offset[0] / height * DRAG_TURN,
+            pitch: (self.pitch + navigation.offset[1] / height * DRAG_TURN)
+                .clamp(-MAX_PITCH, MAX_PITCH),
+            distance: self.distance / navigation.zoom,
+            fov: self.fov,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct VolumeUniforms {
+    /// The camera's position, and the tangent of half its field of view.
+    eye: [f32; 4],
+    /// The camera's axes. Right has the aspect ratio after it, and forward how far apart
+    /// samples are.
+    right: [f32; 4],
+    up: [f32; 4],
+    forward: [f32; 4],
+    /// Half the volume's size on each side, with the longest being a half.
+    extent: [f32; 4],
+}
+
+struct Volume {
+    descriptor: VolumeDescriptor,
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Scalar fields drawn by raymarching through them, for scientific and medical data that's
+/// too big for the page to draw. Volumes go over terrain and under everything else.
+pub struct VolumeLayer {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    uniforms: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    transfer: wgpu::Texture,
+    transfer_view: wgpu::TextureView,
+    transfer_points: Vec<TransferPoint>,
+    camera: VolumeCamera,
+    volume: Option<Volume>,
+}
+
+impl VolumeLayer {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color_format: wgpu::TextureFormat,
+        pipelines: &Pipelines,
+    ) -> Self {
+        let shader = pipelines.shader(device, "Volume Shader", include_str!("shaders/volume.wgsl"));
+
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Volume Uniforms"),
+            size: std::mem::size_of::<VolumeUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let texture_entry = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = pipelines.bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Volume Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    texture_entry(1, wgpu::TextureViewDimension::D3),
+                    texture_entry(2, wgpu::TextureViewDimension::D2),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let layout = pipelines.pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Volume Pipeline Layout"),
+                bind_group_layouts: &[Some(&bind_group_layout)],
+                immediate_size: 0,
+            },
+        );
+
+        let pipeline = pipelines.render_pipeline(
+            device,
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Volume Pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: color_format,
+                        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(clip::clipped_depth_stencil()),
+                multisample: wgpu::MultisampleState::default(),
+                multiview_mask: None,
+                cache: pipelines.cache(),
+            },
+        );
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Volume Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let transfer = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Volume Transfer Function"),
+            size: wgpu::Extent3d {
+                width: TRANSFER_SIZE,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let transfer_view = transfer.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut layer = VolumeLayer {
+            pipeline,
+            bind_group_layout,
+            uniforms,
+            sampler,
+            transfer,
+            transfer_view,
+            transfer_points: Vec::new(),
+            camera: VolumeCamera::default(),
+            volume: None,
+        };
+        layer.set_transfer_function(queue, Vec::new());
+        layer
+    }
+
+    /// Make room for a volume, replacing any there was. It's empty until slabs of it are
+    /// uploaded.
+    pub fn create(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        descriptor: VolumeDescriptor,
+    ) -> Result<(), String> {
+        let [width, height, depth] = descriptor.dimensions;
+        let max = device.limits().max_texture_dimension_3d;
+        if width == 0 || height == 0 || depth == 0 {
+            return Err("volumes need at least one voxel on each side".into());
+        }
+        if width > max || height > max || depth > max {
+            return Err(format!("volumes can be at most {} voxels on a side", max));
+        }
+        let [low, high] = descriptor.range;
+        if !low.is_finite() || !high.is_finite() || high <= low {
+            return Err("a volume's range has to go from lower to higher values".into());
+        }
+        if descriptor
+            .spacing
+            .iter()
+            .any(|&spacing| !spacing.is_finite() || spacing <= 0.0)
+        {
+            return Err("a volume's spacing has to be positive".into());
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Volume"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: depth,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            // Values are stored normalized to the range, and half floats keep about three
+            // digits of that while still being filterable
+            format: wgpu::TextureFormat::R16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Volume Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniforms.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&self.transfer_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        self.volume = Some(Volume {
+            descriptor,
+            texture,
+            bind_group,
+        });
+        // The transfer function is laid out over the range
+        let points = std::mem::take(&mut self.transfer_points);
+        self.set_transfer_function(queue, points);
+        Ok(())
+    }
+
+    /// Fill in slices from `first_slice` on with `values`, a whole number of slices of
+    /// them, each row by row.
+    pub fn upload_slab(
+        &mut self,
+        queue: &wgpu::Queue,
+        first_slice: u32,
+        values: &[f32],
+    ) -> Result<(), String> {
+        let volume = self
+            .volume
+            .as_ref()
+            .ok_or("there's no volume to upload to")?;
+        let [width, height, depth] = volume.descriptor.dimensions;
+        let slice = (width * height) as usize;
+        if values.is_empty() || !values.len().is_multiple_of(slice) {
+            return Err(format!("slabs have to be whole slices of {} values", slice));
+        }
+        let slices = (values.len() / slice) as u32;
+        if first_slice + slices > depth {
+            return Err(format!(
+                "the volume only has {} slices, not {}",
+                depth,
+                first_slice + slices
+            ));
+        }
+
+        let [low, high] = volume.descriptor.range;
+        let scale = 1.0 / (high - low);
+        let halves: Vec<u16> = values
+            .iter()
+            .map(|&value| to_half(((value as f64 - low) * scale).clamp(0.0, 1.0) as f32))
+            .collect();
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &volume.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: first_slice,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&halves),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(width * 2),
+                rows_per_image: Some(height),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: slices,
+            },
+        );
+        Ok(())
+    }
+
+    /// Color values with `points`, or with a ramp from transparent black to opaque white
+    /// across the volume's range if there aren't any.
+    pub fn set_transfer_function(&mut self, queue: &wgpu::Queue, mut points: Vec<TransferPoint>) {
+        points.sort_by(|a, b| {
+            a.value
+                .partial_cmp(&b.value)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        self.transfer_points = points;
+        let table = self.transfer_table();
+        queue.write_texture(
+            self.transfer.as_image_copy(),
+            &table,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(TRANSFER_SIZE * 4),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: TRANSFER_SIZE,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    pub fn transfer_function(&self) -> &[TransferPoint] {
+        &self.transfer_points
+    }
+
+    pub fn clear(&mut self) {
+        self.volume = None;
+    }
+
+    pub fn set_camera(&mut self, camera: Option<VolumeCamera>) {
+        self.camera = camera.unwrap_or_default();
+    }
+
+    pub fn camera(&self) -> VolumeCamera {
+        self.camera
+    }
+
+    /// Upload the camera, navigated by `navigation`.
+    pub fn prepare(&self, uploads: &mut Uploads, navigation: &Navigation, logical_size: [f32; 2]) {
+        let volume = match &self.volume {
+            Some(volume) => volume,
+            None => return,
+        };
+        let camera = self.camera.navigated(navigation, logical_size);
+        let (yaw_sin, yaw_cos) = camera.yaw.sin_cos();
+        let (pitch_sin, pitch_cos) = camera.pitch.sin_cos();
+        let distance = camera.distance;
+        // Looking at the middle, from in front of the first slice at a yaw of zero
+        let eye = [
+            yaw_sin * pitch_cos * distance,
+            pitch_sin * distance,
+            -yaw_cos * pitch_cos * distance,
+        ];
+        let forward = [-eye[0] / distance, -eye[1] / distance, -eye[2] / distance];
+        let right = [-yaw_cos, 0.0, -yaw_sin];
+        let up = cross(right, forward);
+
+        let [width, height, depth] = volume.descriptor.dimensions;
+        let spacing = volume.descriptor.spacing;
+        let size = [
+            width as f64 * spacing[0],
+            height as f64 * spacing[1],
+            depth as f64 * spacing[2],
+        ];
+        let longest = size[0].max(size[1]).max(size[2]);
+        // A step for each voxel along the longest side
+        let step = 1.0 / width.max(height).max(depth) as f64;
+        let aspect = logical_size[0] as f64 / (logical_size[1] as f64).max(1.0);
+        let uniforms = VolumeUniforms {
+            eye: to_vec4(eye, (camera.fov.to_radians() / 2.0).tan()),
+            right: to_vec4(right, aspect),
+            up: to_vec4(up, 0.0),
+            forward: to_vec4(forward, step),
+            extent: to_vec4(
+                [
+                    size[0] / longest / 2.0,
+                    size[1] / longest / 2.0,
+                    size[2] / longest / 2.0,
+                ],
+                0.0,
+            ),
+        };
+        uploads.write(&self.uniforms, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    pub fn memory(&self, usage: &mut MemoryUsage) {
+        usage.add_buffer(&self.uniforms);
+        usage.add_texture(&self.transfer);
+        if let Some(volume) = &self.volume {
+            usage.add_texture(&volume.texture);
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if let Some(volume) = &self.volume {
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_stencil_reference(clip::STENCIL_REFERENCE);
+            render_pass.set_bind_group(0, &volume.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    /// The transfer function sampled across the range, as RGBA bytes.
+    fn transfer_table(&self) -> Vec<u8> {
+        let (low, high) = match &self.volume {
+            Some(volume) => (volume.descriptor.range[0], volume.descriptor.range[1]),
+            None => (0.0, 1.0),
+        };
+        let points = &self.transfer_points;
+        let mut table = Vec::with_capacity(TRANSFER_SIZE as usize * 4);
+        for i in 0..TRANSFER_SIZE {
+            let t = i as f64 / (TRANSFER_SIZE - 1) as f64;
+            let value = low + t * (high - low);
+            let color = match points.iter().position(|point| point.value > value) {
+                _ if points.is_empty() => [1.0, 1.0, 1.0, t],
+                Some(0) => points[0].color,
+                None => points[points.len() - 1].color,
+                Some(next) => {
+                    let (a, b) = (&points[next - 1], &points[next]);
+                    let mix = (value - a.value) / (b.value - a.value);
+                    let mut color = [0.0; 4];
+                    for (channel, value) in color.iter_mut().enumerate() {
+                        *value = a.color[channel] + (b.color[channel] - a.color[channel]) * mix;
+                    }
+                    color
+                }
+            };
+            table.extend(
+                color
+                    .iter()
+                    .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8),
+            );
+        }
+        table
+    }
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn to_vec4(v: [f64; 3], w: f64) -> [f32; 4] {
+    [v[0] as f32, v[1] as f32, v[2] as f32, w as f32]
+}
+
+/// A half float with the nearest value to `value`, which is between 0 and 1.
+fn to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent <= 0 {
+        // Subnormal, or too small for a half at all
+        if exponent < -10 {
+            return 0;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        return ((mantissa + (1 << (shift - 1))) >> shift) as u16;
+    }
+    // Rounding can carry into the exponent, which is what it should do
+    (((exponent as u32) << 10) + ((mantissa + 0x1000) >> 13)) as u16
+}
@@ -0,0 +1,197 @@
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+/// Tiles fetched at once. Most tile servers ask clients to keep this low.
+const FETCH_THREADS: usize = 4;
+/// Tile servers like OpenStreetMap's turn away requests that don't say what app they're from.
+const USER_AGENT: &str = concat!("wgpu-tauri-experiment/", env!("CARGO_PKG_VERSION"));
+
+/// How a tile server numbers its rows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TileScheme {
+    /// Row 0 is the northernmost, as with most web maps.
+    #[default]
+    Xyz,
+    /// Row 0 is the southernmost.
+    Tms,
+}
+
+/// One tile of the map, numbered from the northwest corner at every zoom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileKey {
+    pub zoom: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+/// Where tiles come from: `url` with `{z}`, `{x}` and `{y}` in it, such as
+/// `https://tile.openstreetmap.org/{z}/{x}/{y}.png`.
+#[derive(Debug, Clone)]
+pub struct TileSource {
+    pub url: String,
+    pub scheme: TileScheme,
+    /// Tiles that aren't this many pixels on a side are resized to it.
+    pub tile_size: u32,
+}
+
+impl TileSource {
+    fn url(&self, key: TileKey) -> String {
+        let y = match self.scheme {
+            TileScheme::Xyz => key.y,
+            TileScheme::Tms => (1 << key.zoom) - 1 - key.y,
+        };
+        self.url
+            .replace("{z}", &key.zoom.to_string())
+            .replace("{x}", &key.x.to_string())
+            .replace("{y}", &y.to_string())
+    }
+}
+
+/// A fetched tile, or why it couldn't be.
+pub type FetchedTile = (TileKey, Result<image::RgbaImage, String>);
+
+#[derive(Default)]
+struct Queue {
+    /// Most wanted first.
+    wanted: VecDeque<TileKey>,
+    in_flight: HashSet<TileKey>,
+    closed: bool,
+}
+
+/// Fetches tiles on background threads, keeping a copy of each on disk so they're only
+/// downloaded once. The threads stop when this is dropped.
+pub struct TileFetcher {
+    queue: Arc<(Mutex<Queue>, Condvar)>,
+    fetched: Receiver<FetchedTile>,
+}
+
+impl TileFetcher {
+    /// Fetch tiles from `source`, caching them in `cache_dir` if there is one.
+    pub fn new(source: TileSource, cache_dir: Option<PathBuf>) -> Self {
+        let queue = Arc::new((Mutex::new(Queue::default()), Condvar::new()));
+        let (sender, fetched) = channel();
+        let source = Arc::new(source);
+        for _ in 0..FETCH_THREADS {
+            let queue = queue.clone();
+            let sender = sender.clone();
+            let source = source.clone();
+            let cache_dir = cache_dir.clone();
+            std::thread::spawn(move || work(&queue, &sender, &source, cache_dir.as_ref()));
+        }
+        TileFetcher { queue, fetched }
+    }
+
+    /// Fetch `keys`, most wanted first, instead of whatever was wanted before. Tiles that
+    /// are already being fetched aren't fetched again.
+    pub fn want(&self, keys: Vec<TileKey>) {
+        let (queue, wake) = &*self.queue;
+        let mut queue = queue.lock().unwrap();
+        queue.wanted = keys
+            .into_iter()
+            .filter(|key| !queue.in_flight.contains(key))
+            .collect();
+        wake.notify_all();
+    }
+
+    /// Tiles fetched since this was last called.
+    pub fn take_fetched(&self) -> Vec<FetchedTile> {
+        self.fetched.try_iter().collect()
+    }
+}
+
+impl Drop for TileFetcher {
+    fn drop(&mut self) {
+        let (queue, wake) = &*self.queue;
+        queue.lock().unwrap().closed = true;
+        wake.notify_all();
+    }
+}
+
+fn work(
+    queue: &(Mutex<Queue>, Condvar),
+    sender: &Sender<FetchedTile>,
+    source: &TileSource,
+    cache_dir: Option<&PathBuf>,
+) {
+    let (queue, wake) = queue;
+    loop {
+        let key = {
+            let mut queue = queue.lock().unwrap();
+            loop {
+                if queue.closed {
+                    return;
+                }
+                if let Some(key) = queue.wanted.pop_front() {
+                    queue.in_flight.insert(key);
+                    break key;
+                }
+                queue = wake.wait(queue).unwrap();
+            }
+        };
+        let tile = fetch(source, cache_dir, key);
+        // Sent before it's no longer in flight, so it's never fetched twice
+        let sent = sender.send((key, tile));
+        queue.lock().unwrap().in_flight.remove(&key);
+        if sent.is_err() {
+            return;
+        }
+    }
+}
+
+fn fetch(
+    source: &TileSource,
+    cache_dir: Option<&PathBuf>,
+    key: TileKey,
+) -> Result<image::RgbaImage, String> {
+    let url = source.url(key);
+    let cached = cache_dir.map(|dir| dir.join(blake3::hash(url.as_bytes()).to_hex().as_str()));
+    let bytes = match cached.as_ref().and_then(|path| fs::read(path).ok()) {
+        Some(bytes) => bytes,
+        None => {
+            let bytes = download(&url)?;
+            if let Some(path) = &cached {
+                let written = fs::create_dir_all(path.parent().unwrap())
+                    .and_then(|_| fs::write(path, &bytes));
+                if let Err(e) = written {
+                    println!("Failed to cache map tile {}: {}", url, e);
+                }
+            }
+            bytes
+        }
+    };
+
+    let tile = image::load_from_memory(&bytes)
+        .map_err(|e| format!("failed to decode tile {}: {}", url, e))?
+        .to_rgba8();
+    let size = source.tile_size;
+    if tile.dimensions() == (size, size) {
+        Ok(tile)
+    } else {
+        Ok(image::imageops::resize(
+            &tile,
+            size,
+            size,
+            image::imageops::FilterType::Triangle,
+        ))
+    }
+}
+
+fn download(url: &str) -> Result<Vec<u8>, String> {
+    let response = ureq::get(url)
+        .set("User-Agent", USER_AGENT)
+        .call()
+        .map_err(|e| format!("failed to fetch tile {}: {}", url, e))?;
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("failed to read tile {}: {}", url, e))?;
+    Ok(bytes)
+}
@@ -0,0 +1,70 @@
+//! A tray icon whose menu controls the overlays, so overlay-style utilities can be driven
+//! while their main window is hidden or behind other apps.
+
+use tauri::menu::{Menu, MenuItem, PredefinedMenuItem, Submenu};
+use tauri::tray::{TrayIcon, TrayIconBuilder};
+use tauri::{AppHandle, Manager, Wry};
+
+use crate::menu::MenuAction;
+use crate::{OverlayHandle, Overlays};
+
+const QUIT_ID: &str = "quit";
+
+/// The tray icon, kept to give it a new menu whenever the overlays change.
+pub struct Tray(TrayIcon<Wry>);
+
+/// Add the tray icon. Its items are handled by `menu::handle_event` with the app menu's.
+pub fn build(handle: &AppHandle) -> tauri::Result<()> {
+    let mut tray = TrayIconBuilder::new().menu(&menu(handle)?);
+    if let Some(icon) = handle.default_window_icon() {
+        tray = tray.icon(icon.clone());
+    }
+    handle.manage(Tray(tray.build(handle)?));
+    Ok(())
+}
+
+/// Give the tray a menu listing the overlays there are now, checked as they are now.
+pub fn rebuild(handle: &AppHandle) {
+    let tray = match handle.try_state::<Tray>() {
+        Some(tray) => tray,
+        None => return,
+    };
+    if let Err(e) = menu(handle).and_then(|menu| tray.0.set_menu(Some(menu))) {
+        println!("Failed to rebuild the tray menu: {:?}", e);
+    }
+}
+
+/// A submenu per overlay to show or hide it and make it click-through, then Quit.
+fn menu(handle: &AppHandle) -> tauri::Result<Menu<Wry>> {
+    // Copied out, since checking the overlays' toggles locks their views
+    let mut overlays: Vec<OverlayHandle> = match handle.try_state::<Overlays>() {
+        Some(overlays) => overlays.0.lock().unwrap().values().cloned().collect(),
+        None => Vec::new(),
+    };
+    overlays.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let menu = Menu::new(handle)?;
+    for overlay in &overlays {
+        let toggles = overlay.toggles();
+        let items = Submenu::new(handle, &overlay.id, true)?;
+        items.append(&MenuAction::ToggleVisible.item(handle, &overlay.id, toggles)?)?;
+        items.append(&MenuAction::ToggleClickThrough.item(handle, &overlay.id, toggles)?)?;
+        menu.append(&items)?;
+    }
+    menu.append(&PredefinedMenuItem::separator(handle)?)?;
+    menu.append(&MenuItem::with_id(
+        handle,
+        QUIT_ID,
+        "Quit",
+        true,
+        None::<&str>,
+    )?)?;
+    Ok(menu)
+}
+
+/// Do what one of the tray's own items says.
+pub fn handle_item(handle: &AppHandle, item_id: &str) {
+    if item_id == QUIT_ID {
+        handle.exit(0);
+    }
+}
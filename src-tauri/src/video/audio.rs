@@ -0,0 +1,242 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+/// Decoded audio is resampled to interleaved stereo at the output's rate.
+pub const CHANNELS: usize = 2;
+
+/// Queued samples, and where they fall in the video.
+#[derive(Default)]
+struct Buffer {
+    /// Which seek samples are taken from, so ones decoded before it aren't played.
+    serial: u64,
+    samples: VecDeque<f32>,
+    /// The time of the first queued sample, in seconds of the video.
+    next_pts: Option<f64>,
+    /// What was audible as of the last time the output asked for samples: the time in the
+    /// video, when it was, and how many seconds of samples it was given.
+    heard: Option<(f64, Instant, f64)>,
+}
+
+/// Samples waiting to be played, shared between the decoder and the output.
+pub struct AudioQueue {
+    buffer: Mutex<Buffer>,
+    sample_rate: u32,
+    playing: AtomicBool,
+    /// Decoded audio is dropped rather than queued while this is off.
+    enabled: AtomicBool,
+    /// An `f32`, stored as its bits.
+    volume: AtomicU32,
+}
+
+impl AudioQueue {
+    fn new(sample_rate: u32) -> Self {
+        AudioQueue {
+            buffer: Mutex::new(Buffer::default()),
+            sample_rate,
+            playing: AtomicBool::new(false),
+            enabled: AtomicBool::new(true),
+            volume: AtomicU32::new(1.0f32.to_bits()),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Queue samples that start `pts` seconds into the video, decoded after the seek
+    /// numbered `serial`.
+    pub fn push(&self, samples: &[f32], pts: f64, serial: u64) {
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.serial != serial {
+            return;
+        }
+        if buffer.samples.is_empty() {
+            buffer.next_pts = Some(pts);
+        }
+        buffer.samples.extend(samples);
+    }
+
+    pub fn clear(&self) {
+        let mut buffer = self.buffer.lock().unwrap();
+        let serial = buffer.serial;
+        *buffer = Buffer {
+            serial,
+            ..Buffer::default()
+        };
+    }
+
+    /// Drop everything queued, and only take samples decoded after the seek numbered
+    /// `serial` from now on.
+    pub fn reset(&self, serial: u64) {
+        *self.buffer.lock().unwrap() = Buffer {
+            serial,
+            ..Buffer::default()
+        };
+    }
+
+    /// How many seconds of audio are waiting to be played.
+    pub fn buffered(&self) -> f64 {
+        let samples = self.buffer.lock().unwrap().samples.len();
+        samples as f64 / (self.sample_rate as usize * CHANNELS) as f64
+    }
+
+    /// The time in the video that's coming out of the speakers now, or `None` if nothing
+    /// has been played since the queue was last cleared.
+    pub fn position(&self) -> Option<f64> {
+        let (pts, at, span) = self.buffer.lock().unwrap().heard?;
+        Some(pts + at.elapsed().as_secs_f64().min(span))
+    }
+
+    pub fn set_playing(&self, playing: bool) {
+        self.playing.store(playing, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.clear();
+        }
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Fill `output`, which has `channels` interleaved channels, with queued samples, or
+    /// with silence while paused or once the queue runs dry. `latency` is how long until
+    /// `output` is heard.
+    fn fill<T: cpal::Sample>(&self, output: &mut [T], channels: usize, latency: f64) {
+        let volume = f32::from_bits(self.volume.load(Ordering::Relaxed));
+        let playing = self.playing.load(Ordering::Relaxed);
+        let mut buffer = self.buffer.lock().unwrap();
+        if playing {
+            let frames = (buffer.samples.len() / CHANNELS).min(output.len() / channels);
+            buffer.heard = match (buffer.next_pts, frames) {
+                // Nothing's heard once the queue runs dry, so there's nothing to follow
+                (None, _) | (_, 0) => None,
+                (Some(pts), _) => {
+                    let span = frames as f64 / self.sample_rate as f64;
+                    buffer.next_pts = Some(pts + span);
+                    Some((pts - latency, Instant::now(), span))
+                }
+            };
+        }
+        let samples = &mut buffer.samples;
+        for frame in output.chunks_mut(channels) {
+            let (left, right) = match playing {
+                true => (
+                    samples.pop_front().unwrap_or(0.0),
+                    samples.pop_front().unwrap_or(0.0),
+                ),
+                false => (0.0, 0.0),
+            };
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let value = match (channels, channel) {
+                    (1, _) => (left + right) / 2.0,
+                    (_, 0) => left,
+                    (_, 1) => right,
+                    _ => 0.0,
+                };
+                *sample = T::from(&(value * volume));
+            }
+        }
+    }
+}
+
+/// Plays an `AudioQueue` on the default output device until dropped.
+pub struct AudioOutput {
+    queue: Arc<AudioQueue>,
+    stop: mpsc::Sender<()>,
+}
+
+impl AudioOutput {
+    /// Open the default output device. Streams can't always move between threads, so
+    /// this one lives on a thread of its own.
+    pub fn open() -> Result<Self, String> {
+        let (opened_tx, opened_rx) = mpsc::channel();
+        let (stop, stop_rx) = mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            let stream = match build_stream() {
+                Ok((stream, queue)) => {
+                    let _ = opened_tx.send(Ok(queue));
+                    stream
+                }
+                Err(e) => {
+                    let _ = opened_tx.send(Err(e));
+                    return;
+                }
+            };
+            // Returns once the output is dropped
+            let _ = stop_rx.recv();
+            drop(stream);
+        });
+        let queue = opened_rx
+            .recv()
+            .map_err(|_| "the audio thread stopped".to_string())??;
+        Ok(AudioOutput { queue, stop })
+    }
+
+    pub fn queue(&self) -> Arc<AudioQueue> {
+        self.queue.clone()
+    }
+}
+
+impl Drop for AudioOutput {
+    fn drop(&mut self) {
+        let _ = self.stop.send(());
+    }
+}
+
+fn build_stream() -> Result<(cpal::Stream, Arc<AudioQueue>), String> {
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or("there is no audio output device")?;
+    let supported = device
+        .default_output_config()
+        .map_err(|e| format!("failed to get the audio output config: {}", e))?;
+    let config: cpal::StreamConfig = supported.config();
+    let queue = Arc::new(AudioQueue::new(config.sample_rate.0));
+
+    let stream = match supported.sample_format() {
+        cpal::SampleFormat::F32 => build::<f32>(&device, &config, queue.clone()),
+        cpal::SampleFormat::I16 => build::<i16>(&device, &config, queue.clone()),
+        cpal::SampleFormat::U16 => build::<u16>(&device, &config, queue.clone()),
+    }
+    .map_err(|e| format!("failed to open the audio output: {}", e))?;
+    stream
+        .play()
+        .map_err(|e| format!("failed to start the audio output: {}", e))?;
+    Ok((stream, queue))
+}
+
+fn build<T: cpal::Sample>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    queue: Arc<AudioQueue>,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    let channels = config.channels as usize;
+    device.build_output_stream(
+        config,
+        move |output: &mut [T], info: &cpal::OutputCallbackInfo| {
+            let timestamp = info.timestamp();
+            let latency = timestamp
+                .playback
+                .duration_since(&timestamp.callback)
+                .map_or(0.0, |latency| latency.as_secs_f64());
+            queue.fill(output, channels, latency)
+        },
+        |e| println!("Audio output error: {}", e),
+    )
+}
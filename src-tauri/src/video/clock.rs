@@ -0,0 +1,196 @@
+//! The clock that video frames, audio and subtitles are all timed against.
+
+use std::time::Instant;
+
+use serde::Serialize;
+
+/// Drift beyond this is corrected at once rather than eased out, in seconds.
+const RESYNC_THRESHOLD: f64 = 0.25;
+/// Drift within this is left alone, in seconds.
+const SYNC_TOLERANCE: f64 = 0.005;
+/// How much of the drift is corrected each time the clock is synced.
+const SLEW: f64 = 0.1;
+
+/// What the clock is following.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ClockSource {
+    /// The system clock, while there's no audio playing.
+    System,
+    /// The audio output, since drift against what's heard is what viewers notice.
+    Audio,
+}
+
+/// Playback time, in seconds of the video. It runs off the system clock, and is nudged
+/// towards the audio output whenever it's synced.
+#[derive(Debug, Clone, Copy)]
+pub struct Clock {
+    /// The position as of `started`, or for good while paused.
+    base: f64,
+    started: Option<Instant>,
+    rate: f64,
+    source: ClockSource,
+    /// How far the audio was ahead of the clock when it was last synced.
+    sync_error: f64,
+    /// The largest sync error since playback was last seeked.
+    max_sync_error: f64,
+    /// How many times the clock jumped to the audio, rather than easing towards it.
+    resyncs: u64,
+}
+
+impl Clock {
+    /// A paused clock at the start of the video.
+    pub fn new() -> Self {
+        Clock {
+            base: 0.0,
+            started: None,
+            rate: 1.0,
+            source: ClockSource::System,
+            sync_error: 0.0,
+            max_sync_error: 0.0,
+            resyncs: 0,
+        }
+    }
+
+    pub fn position(&self) -> f64 {
+        match self.started {
+            Some(started) => self.base + started.elapsed().as_secs_f64() * self.rate,
+            None => self.base,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.started.is_some()
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn source(&self) -> ClockSource {
+        self.source
+    }
+
+    /// The latest, and the largest since the last seek, sync error in seconds.
+    pub fn sync_error(&self) -> (f64, f64) {
+        (self.sync_error, self.max_sync_error)
+    }
+
+    pub fn resyncs(&self) -> u64 {
+        self.resyncs
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.base = self.position();
+        self.started = playing.then(Instant::now);
+    }
+
+    pub fn seek(&mut self, position: f64) {
+        self.base = position;
+        if self.started.is_some() {
+            self.started = Some(Instant::now());
+        }
+        self.sync_error = 0.0;
+        self.max_sync_error = 0.0;
+    }
+
+    pub fn set_rate(&mut self, rate: f64) {
+        self.base = self.position();
+        if self.started.is_some() {
+            self.started = Some(Instant::now());
+        }
+        self.rate = rate;
+    }
+
+    /// Follow `audio`, the position that's being heard, if there is any. Small drift is
+    /// eased out so the picture doesn't jump, and large drift is corrected at once.
+    pub fn sync(&mut self, audio: Option<f64>) {
+        let audio = match audio {
+            Some(audio) => audio,
+            None => {
+                self.source = ClockSource::System;
+                return;
+            }
+        };
+        if self.started.is_none() {
+            return;
+        }
+        self.source = ClockSource::Audio;
+        let error = audio - self.position();
+        self.sync_error = error;
+        self.max_sync_error = self.max_sync_error.max(error.abs());
+        if error.abs() > RESYNC_THRESHOLD {
+            self.base += error;
+            self.resyncs += 1;
+        } else if error.abs() > SYNC_TOLERANCE {
+            self.base += error * SLEW;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The clock runs off the system clock, so positions are compared this loosely.
+    const EPSILON: f64 = 0.02;
+
+    fn playing_at(position: f64) -> Clock {
+        let mut clock = Clock::new();
+        clock.seek(position);
+        clock.set_playing(true);
+        clock
+    }
+
+    #[test]
+    fn jumps_to_audio_that_has_drifted_far() {
+        let mut clock = playing_at(10.0);
+        clock.sync(Some(11.0));
+        assert!((clock.position() - 11.0).abs() < EPSILON);
+        assert_eq!(clock.resyncs(), 1);
+        assert_eq!(clock.source(), ClockSource::Audio);
+    }
+
+    #[test]
+    fn eases_towards_audio_that_has_drifted_a_little() {
+        let mut clock = playing_at(10.0);
+        clock.sync(Some(clock.position() + 0.1));
+        let position = clock.position();
+        assert!(position > 10.0 + 0.1 * SLEW - EPSILON && position < 10.0 + 0.1 - EPSILON);
+        assert_eq!(clock.resyncs(), 0);
+        assert!((clock.sync_error().0 - 0.1).abs() < EPSILON);
+    }
+
+    #[test]
+    fn keeps_the_largest_error_until_seeked() {
+        let mut clock = playing_at(0.0);
+        clock.sync(Some(clock.position() - 0.2));
+        clock.sync(Some(clock.position() + 0.01));
+        assert!((clock.sync_error().1 - 0.2).abs() < EPSILON);
+        clock.seek(5.0);
+        assert_eq!(clock.sync_error(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn ignores_audio_while_paused_and_falls_back_without_it() {
+        let mut clock = Clock::new();
+        clock.seek(3.0);
+        clock.sync(Some(8.0));
+        assert_eq!(clock.position(), 3.0);
+        assert_eq!(clock.source(), ClockSource::System);
+
+        let mut clock = playing_at(3.0);
+        clock.sync(Some(3.0));
+        clock.sync(None);
+        assert_eq!(clock.source(), ClockSource::System);
+    }
+
+    #[test]
+    fn keeps_its_position_when_the_rate_changes() {
+        let mut clock = Clock::new();
+        clock.seek(4.0);
+        clock.set_rate(2.0);
+        assert_eq!(clock.position(), 4.0);
+        assert_eq!(clock.rate(), 2.0);
+    }
+}
@@ -0,0 +1,422 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+    time::Duration,
+};
+
+use ffmpeg_next as ffmpeg;
+
+use super::audio::{AudioQueue, CHANNELS};
+use super::hwaccel;
+use super::{FrameImage, VideoFrame, YuvColor, YuvMatrix};
+
+/// Decoded frames waiting to be shown. Decoding pauses while the queue is full.
+const FRAME_QUEUE: usize = 8;
+/// How far ahead of playback audio is decoded, in seconds.
+const AUDIO_AHEAD: f64 = 1.0;
+/// How long to wait before checking again while the queues are full.
+const BACKOFF: Duration = Duration::from_millis(5);
+
+pub enum DecoderCommand {
+    /// Jump to a position in seconds. Frames decoded after this have the next serial.
+    Seek(f64),
+}
+
+/// What's known about a file once it's open.
+#[derive(Debug, Clone, Copy)]
+pub struct MediaInfo {
+    /// In seconds.
+    pub duration: f64,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A file being decoded on a thread of its own, which stops when this is dropped.
+pub struct Decoder {
+    pub info: MediaInfo,
+    pub frames: mpsc::Receiver<VideoFrame>,
+    commands: mpsc::Sender<DecoderCommand>,
+    /// One more than the serial that's been decoded up to the end of the file, or 0.
+    finished: Arc<AtomicU64>,
+}
+
+impl Decoder {
+    /// Open `path` and start decoding it, with its audio going to `audio` if it has any.
+    pub fn open(path: PathBuf, audio: Option<Arc<AudioQueue>>) -> Result<Self, String> {
+        let (opened_tx, opened_rx) = mpsc::channel();
+        let (frames_tx, frames) = mpsc::sync_channel(FRAME_QUEUE);
+        let (commands, commands_rx) = mpsc::channel();
+        let finished = Arc::new(AtomicU64::new(0));
+
+        let thread_finished = finished.clone();
+        std::thread::spawn(move || {
+            // The decoding state stays on this thread, so it never has to be Send
+            let mut decoding = match Decoding::open(&path, audio) {
+                Ok(decoding) => decoding,
+                Err(e) => {
+                    let _ = opened_tx.send(Err(e));
+                    return;
+                }
+            };
+            let _ = opened_tx.send(Ok(decoding.info()));
+            decoding.run(frames_tx, commands_rx, thread_finished);
+        });
+
+        let info = opened_rx
+            .recv()
+            .map_err(|_| "the decoder stopped".to_string())??;
+        Ok(Decoder {
+            info,
+            frames,
+            commands,
+            finished,
+        })
+    }
+
+    pub fn seek(&self, position: f64) {
+        let _ = self.commands.send(DecoderCommand::Seek(position));
+    }
+
+    /// Whether everything after the seek numbered `serial` has been decoded.
+    pub fn is_finished(&self, serial: u64) -> bool {
+        self.finished.load(Ordering::Relaxed) == serial + 1
+    }
+}
+
+struct AudioDecoding {
+    stream: usize,
+    time_base: f64,
+    decoder: ffmpeg::decoder::Audio,
+    resampler: ffmpeg::software::resampling::Context,
+    queue: Arc<AudioQueue>,
+}
+
+struct Decoding {
+    input: ffmpeg::format::context::Input,
+    video_stream: usize,
+    time_base: f64,
+    decoder: ffmpeg::decoder::Video,
+    /// Converts frames that aren't NV12 to RGBA, set up for the format and size of the
+    /// first one that needs it.
+    scaler: Option<ffmpeg::software::scaling::Context>,
+    audio: Option<AudioDecoding>,
+    /// Bumped on every seek, so frames from before it can be told apart.
+    serial: u64,
+    /// Frames before this position are decoded but not kept, since seeking lands on the
+    /// keyframe before the one asked for.
+    skip_until: Option<f64>,
+    pending: VecDeque<VideoFrame>,
+}
+
+impl Decoding {
+    fn open(path: &Path, audio: Option<Arc<AudioQueue>>) -> Result<Self, String> {
+        ffmpeg::init().map_err(|e| format!("failed to initialize ffmpeg: {}", e))?;
+        let input = ffmpeg::format::input(&path)
+            .map_err(|e| format!("failed to open {:?}: {}", path, e))?;
+
+        let stream = input
+            .streams()
+            .best(ffmpeg::media::Type::Video)
+            .ok_or_else(|| format!("{:?} has no video", path))?;
+        let video_stream = stream.index();
+        let time_base = f64::from(stream.time_base());
+        let mut context = ffmpeg::codec::context::Context::from_parameters(stream.parameters())
+            .map_err(|e| format!("failed to open the video decoder: {}", e))?;
+        if !hwaccel::enable(&mut context) {
+            println!("Hardware video decoding isn't available, decoding in software");
+        }
+        let decoder = context
+            .decoder()
+            .video()
+            .map_err(|e| format!("failed to open the video decoder: {}", e))?;
+
+        let audio = match (audio, input.streams().best(ffmpeg::media::Type::Audio)) {
+            (Some(queue), Some(stream)) => match open_audio(&stream, queue) {
+                Ok(audio) => Some(audio),
+                Err(e) => {
+                    println!("Failed to open audio, playing without it: {}", e);
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        Ok(Decoding {
+            input,
+            video_stream,
+            time_base,
+            decoder,
+            scaler: None,
+            audio,
+            serial: 0,
+            skip_until: None,
+            pending: VecDeque::new(),
+        })
+    }
+
+    fn info(&self) -> MediaInfo {
+        MediaInfo {
+            duration: (self.input.duration() as f64 / f64::from(ffmpeg::ffi::AV_TIME_BASE))
+                .max(0.0),
+            width: self.decoder.width(),
+            height: self.decoder.height(),
+        }
+    }
+
+    fn run(
+        &mut self,
+        frames: mpsc::SyncSender<VideoFrame>,
+        commands: mpsc::Receiver<DecoderCommand>,
+        finished: Arc<AtomicU64>,
+    ) {
+        loop {
+            match commands.try_recv() {
+                Ok(DecoderCommand::Seek(position)) => self.seek(position),
+                Err(mpsc::TryRecvError::Disconnected) => return,
+                Err(mpsc::TryRecvError::Empty) => {}
+            }
+
+            if let Some(frame) = self.pending.pop_front() {
+                match frames.try_send(frame) {
+                    Ok(()) => {}
+                    Err(mpsc::TrySendError::Full(frame)) => {
+                        self.pending.push_front(frame);
+                        std::thread::sleep(BACKOFF);
+                    }
+                    Err(mpsc::TrySendError::Disconnected(_)) => return,
+                }
+                continue;
+            }
+
+            let audio_full = self
+                .audio
+                .as_ref()
+                .is_some_and(|audio| audio.queue.buffered() > AUDIO_AHEAD);
+            let at_end = finished.load(Ordering::Relaxed) == self.serial + 1;
+            if audio_full || at_end {
+                std::thread::sleep(BACKOFF);
+                continue;
+            }
+
+            let mut packet = ffmpeg::Packet::empty();
+            match packet.read(&mut self.input) {
+                Ok(()) => self.decode(&packet),
+                Err(ffmpeg::Error::Eof) => {
+                    self.finish();
+                    finished.store(self.serial + 1, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    println!("Failed to read video: {}", e);
+                    finished.store(self.serial + 1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    fn seek(&mut self, position: f64) {
+        let timestamp = (position * f64::from(ffmpeg::ffi::AV_TIME_BASE)) as i64;
+        if let Err(e) = self.input.seek(timestamp, ..timestamp) {
+            println!("Failed to seek video: {}", e);
+        }
+        self.decoder.flush();
+        if let Some(audio) = &mut self.audio {
+            audio.decoder.flush();
+            audio.queue.clear();
+        }
+        self.serial += 1;
+        self.skip_until = Some(position);
+        self.pending.clear();
+    }
+
+    fn decode(&mut self, packet: &ffmpeg::Packet) {
+        if packet.stream() == self.video_stream {
+            if let Err(e) = self.decoder.send_packet(packet) {
+                println!("Failed to decode video: {}", e);
+            }
+            self.receive_video();
+        } else if let Some(audio) = &mut self.audio {
+            if packet.stream() == audio.stream && audio.queue.is_enabled() {
+                if let Err(e) = audio.decoder.send_packet(packet) {
+                    println!("Failed to decode audio: {}", e);
+                }
+                receive_audio(audio, self.skip_until, self.serial);
+            }
+        }
+    }
+
+    /// Flush out the frames held back at the end of the file.
+    fn finish(&mut self) {
+        let _ = self.decoder.send_eof();
+        self.receive_video();
+        if let Some(audio) = &mut self.audio {
+            let _ = audio.decoder.send_eof();
+            receive_audio(audio, self.skip_until, self.serial);
+        }
+    }
+
+    fn receive_video(&mut self) {
+        let mut decoded = ffmpeg::frame::Video::empty();
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            let pts = decoded.timestamp().unwrap_or(0) as f64 * self.time_base;
+            if self.skip_until.is_some_and(|until| pts < until) {
+                continue;
+            }
+            self.skip_until = None;
+
+            let image = match self.convert(&decoded) {
+                Ok(image) => image,
+                Err(e) => {
+                    println!("Failed to convert video frame: {}", e);
+                    continue;
+                }
+            };
+            self.pending.push_back(VideoFrame {
+                serial: self.serial,
+                pts,
+                width: decoded.width(),
+                height: decoded.height(),
+                image,
+            });
+        }
+    }
+
+    /// Hand frames to the renderer with as little copying as the platform allows: still on
+    /// the GPU with VideoToolbox, as NV12 planes for the renderer to convert, or as RGBA
+    /// converted here when nothing better fits.
+    fn convert(&mut self, decoded: &ffmpeg::frame::Video) -> Result<FrameImage, ffmpeg::Error> {
+        let color = yuv_color(decoded);
+        let downloaded;
+        let frame = match hwaccel::is_hardware(decoded.format()) {
+            true => {
+                #[cfg(target_os = "macos")]
+                if let Some(pixel_buffer) = super::PixelBuffer::from_frame(decoded) {
+                    return Ok(FrameImage::PixelBuffer(pixel_buffer, color));
+                }
+                downloaded = hwaccel::download(decoded)?;
+                &downloaded
+            }
+            false => decoded,
+        };
+
+        let (width, height) = (frame.width(), frame.height());
+        if frame.format() == ffmpeg::format::Pixel::NV12 {
+            // Chroma is subsampled both ways, with its two channels interleaved
+            return Ok(FrameImage::Nv12 {
+                luma: packed_plane(frame, 0, width as usize, height),
+                chroma: packed_plane(
+                    frame,
+                    1,
+                    (width as usize).div_ceil(2) * 2,
+                    height.div_ceil(2),
+                ),
+                color,
+            });
+        }
+
+        let stale = self.scaler.as_ref().is_none_or(|scaler| {
+            let input = scaler.input();
+            (input.format, input.width, input.height) != (frame.format(), width, height)
+        });
+        if stale {
+            self.scaler = Some(ffmpeg::software::scaling::Context::get(
+                frame.format(),
+                width,
+                height,
+                ffmpeg::format::Pixel::RGBA,
+                width,
+                height,
+                ffmpeg::software::scaling::Flags::BILINEAR,
+            )?);
+        }
+        let mut rgba = ffmpeg::frame::Video::empty();
+        if let Some(scaler) = &mut self.scaler {
+            scaler.run(frame, &mut rgba)?;
+        }
+        Ok(FrameImage::Rgba(packed_plane(
+            &rgba,
+            0,
+            width as usize * 4,
+            height,
+        )))
+    }
+}
+
+/// The rows of one of a frame's planes without their padding.
+fn packed_plane(
+    frame: &ffmpeg::frame::Video,
+    plane: usize,
+    row_bytes: usize,
+    rows: u32,
+) -> Vec<u8> {
+    let mut packed = Vec::with_capacity(row_bytes * rows as usize);
+    for row in frame
+        .data(plane)
+        .chunks(frame.stride(plane))
+        .take(rows as usize)
+    {
+        packed.extend_from_slice(&row[..row_bytes]);
+    }
+    packed
+}
+
+/// How to turn the frame's YUV back into RGB. Untagged video is assumed to be limited range,
+/// and BT.709 when it's HD.
+fn yuv_color(frame: &ffmpeg::frame::Video) -> YuvColor {
+    let matrix = match frame.color_space() {
+        ffmpeg::color::Space::BT709 => YuvMatrix::Bt709,
+        ffmpeg::color::Space::Unspecified if frame.height() >= 720 => YuvMatrix::Bt709,
+        _ => YuvMatrix::Bt601,
+    };
+    YuvColor {
+        matrix,
+        full_range: frame.color_range() == ffmpeg::color::Range::JPEG,
+    }
+}
+
+fn open_audio(stream: &ffmpeg::Stream, queue: Arc<AudioQueue>) -> Result<AudioDecoding, String> {
+    let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())
+        .and_then(|context| context.decoder().audio())
+        .map_err(|e| format!("failed to open the audio decoder: {}", e))?;
+    let layout = match decoder.channel_layout().is_empty() {
+        true => ffmpeg::ChannelLayout::default(decoder.channels() as i32),
+        false => decoder.channel_layout(),
+    };
+    let resampler = ffmpeg::software::resampling::Context::get(
+        decoder.format(),
+        layout,
+        decoder.rate(),
+        ffmpeg::format::Sample::F32(ffmpeg::format::sample::Type::Packed),
+        ffmpeg::ChannelLayout::STEREO,
+        queue.sample_rate(),
+    )
+    .map_err(|e| format!("failed to set up audio conversion: {}", e))?;
+    Ok(AudioDecoding {
+        stream: stream.index(),
+        time_base: f64::from(stream.time_base()),
+        decoder,
+        resampler,
+        queue,
+    })
+}
+
+fn receive_audio(audio: &mut AudioDecoding, skip_until: Option<f64>, serial: u64) {
+    let mut decoded = ffmpeg::frame::Audio::empty();
+    while audio.decoder.receive_frame(&mut decoded).is_ok() {
+        let pts = decoded.timestamp().unwrap_or(0) as f64 * audio.time_base;
+        if skip_until.is_some_and(|until| pts < until) {
+            continue;
+        }
+        let mut resampled = ffmpeg::frame::Audio::empty();
+        if let Err(e) = audio.resampler.run(&decoded, &mut resampled) {
+            println!("Failed to convert audio: {}", e);
+            continue;
+        }
+        let len = resampled.samples() * CHANNELS;
+        let samples: &[f32] = bytemuck::cast_slice(&resampled.data(0)[..len * 4]);
+        audio.queue.push(samples, pts, serial);
+    }
+}
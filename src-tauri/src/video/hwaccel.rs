@@ -0,0 +1,84 @@
+//! Hardware decoding through FFmpeg's hwaccels: VideoToolbox on macOS and D3D11VA on
+//! Windows. Streams the hardware can't decode fall back to software.
+//!
+//! D3D11VA frames are downloaded and uploaded again as NV12 planes, since wgpu's D3D12
+//! device can't open D3D11 textures here. Decoding still happens on the GPU.
+
+use std::ptr;
+
+use ffmpeg::ffi;
+use ffmpeg_next as ffmpeg;
+
+#[cfg(target_os = "macos")]
+const DEVICE: Option<(ffi::AVHWDeviceType, ffi::AVPixelFormat)> = Some((
+    ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_VIDEOTOOLBOX,
+    ffi::AVPixelFormat::AV_PIX_FMT_VIDEOTOOLBOX,
+));
+#[cfg(target_os = "windows")]
+const DEVICE: Option<(ffi::AVHWDeviceType, ffi::AVPixelFormat)> = Some((
+    ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_D3D11VA,
+    ffi::AVPixelFormat::AV_PIX_FMT_D3D11,
+));
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const DEVICE: Option<(ffi::AVHWDeviceType, ffi::AVPixelFormat)> = None;
+
+/// Ask for hardware decoding before the decoder is opened, returning whether there's a
+/// hardware device to decode with.
+pub fn enable(context: &mut ffmpeg::codec::context::Context) -> bool {
+    let (device_type, _) = match DEVICE {
+        Some(device) => device,
+        None => return false,
+    };
+    unsafe {
+        let mut device = ptr::null_mut();
+        if ffi::av_hwdevice_ctx_create(&mut device, device_type, ptr::null(), ptr::null_mut(), 0)
+            < 0
+        {
+            return false;
+        }
+        // The codec context takes over the device's reference
+        let raw = context.as_mut_ptr();
+        (*raw).hw_device_ctx = device;
+        (*raw).get_format = Some(get_format);
+    }
+    true
+}
+
+/// Whether frames of `format` are still on the GPU.
+pub fn is_hardware(format: ffmpeg::format::Pixel) -> bool {
+    match DEVICE {
+        Some((_, pixel_format)) => ffi::AVPixelFormat::from(format) == pixel_format,
+        None => false,
+    }
+}
+
+/// Copy a hardware frame into memory, for frames that can't be handed over on the GPU.
+/// Hardware decoders hand back NV12, or P010 for 10-bit video.
+pub fn download(frame: &ffmpeg::frame::Video) -> Result<ffmpeg::frame::Video, ffmpeg::Error> {
+    let mut software = ffmpeg::frame::Video::empty();
+    unsafe {
+        let result = ffi::av_hwframe_transfer_data(software.as_mut_ptr(), frame.as_ptr(), 0);
+        if result < 0 {
+            return Err(ffmpeg::Error::from(result));
+        }
+        ffi::av_frame_copy_props(software.as_mut_ptr(), frame.as_ptr());
+    }
+    Ok(software)
+}
+
+unsafe extern "C" fn get_format(
+    context: *mut ffi::AVCodecContext,
+    formats: *const ffi::AVPixelFormat,
+) -> ffi::AVPixelFormat {
+    if let Some((_, pixel_format)) = DEVICE {
+        let mut format = formats;
+        while *format != ffi::AVPixelFormat::AV_PIX_FMT_NONE {
+            if *format == pixel_format {
+                return pixel_format;
+            }
+            format = format.add(1);
+        }
+    }
+    // The hardware can't decode this stream
+    ffi::avcodec_default_get_format(context, formats)
+}
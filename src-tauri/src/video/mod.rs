@@ -0,0 +1,379 @@
+//! Video playback for overlays: files are decoded with FFmpeg on a thread of their own,
+//! their audio goes to the default output device, and frames are handed to the renderer
+//! as they come due, along with the text of any subtitle track that's showing. Frames and
+//! subtitles are timed by a clock that follows the audio, so they stay in sync with it.
+
+mod audio;
+mod clock;
+mod decoder;
+mod hwaccel;
+#[cfg(target_os = "macos")]
+mod pixel_buffer;
+mod subtitles;
+
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use audio::AudioOutput;
+use clock::Clock;
+pub use clock::ClockSource;
+use decoder::Decoder;
+#[cfg(target_os = "macos")]
+pub use pixel_buffer::PixelBuffer;
+use subtitles::SubtitleTrack;
+pub use subtitles::SubtitleTrackInfo;
+
+/// How often `video://position` is emitted while playing.
+const STATUS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A decoded frame.
+pub struct VideoFrame {
+    /// Which seek the frame was decoded after.
+    serial: u64,
+    /// When to show the frame, in seconds.
+    pts: f64,
+    pub width: u32,
+    pub height: u32,
+    pub image: FrameImage,
+}
+
+/// A frame's pixels, in whichever form the decoder left them.
+pub enum FrameImage {
+    /// Tightly packed RGBA rows, top to bottom, from the software decoder.
+    Rgba(Vec<u8>),
+    /// A full size plane of luma and a half size plane of interleaved chroma, both tightly
+    /// packed. Hardware decoded frames that can't stay on the GPU come back like this, and
+    /// are converted to RGB by the renderer.
+    Nv12 {
+        luma: Vec<u8>,
+        chroma: Vec<u8>,
+        color: YuvColor,
+    },
+    /// A VideoToolbox frame that never left the GPU.
+    #[cfg(target_os = "macos")]
+    PixelBuffer(PixelBuffer, YuvColor),
+}
+
+/// The coefficients used to turn luma and chroma back into RGB.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum YuvMatrix {
+    Bt601,
+    Bt709,
+}
+
+/// How a YUV frame's values map to colors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct YuvColor {
+    pub matrix: YuvMatrix,
+    /// Whether values span 0-255, rather than 16-235 for luma and 16-240 for chroma.
+    pub full_range: bool,
+}
+
+/// Where playback is, as returned by `get_video_status`.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoStatus {
+    /// In seconds.
+    pub position: f64,
+    /// In seconds.
+    pub duration: f64,
+    pub playing: bool,
+    pub rate: f64,
+    pub volume: f32,
+    /// The video's size in pixels.
+    pub width: u32,
+    pub height: u32,
+    /// Index of the subtitle track being shown, if any.
+    pub subtitle_track: Option<usize>,
+}
+
+/// Emitted as `video://position` every so often while playing and after any change, and
+/// as `video://ended` when playback reaches the end.
+#[derive(Debug, Clone, Serialize)]
+pub struct VideoPayload {
+    pub overlay: String,
+    #[serde(flatten)]
+    pub status: VideoStatus,
+}
+
+/// How well playback is keeping in sync, as returned by `get_playback_stats`. Times are
+/// seconds.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackStats {
+    /// What frames, audio and subtitles are being timed against.
+    pub clock: ClockSource,
+    /// How far the audio was ahead of the clock, or behind if negative, before it was last
+    /// corrected.
+    pub sync_error: f64,
+    /// The largest sync error, either way, since the last seek.
+    pub max_sync_error: f64,
+    /// How many times the clock jumped to the audio because it had drifted too far to
+    /// ease back.
+    pub resyncs: u64,
+    /// How late the last frame was shown.
+    pub frame_delay: f64,
+    pub frames_shown: u64,
+    /// Frames skipped because a later one was already due.
+    pub frames_dropped: u64,
+}
+
+/// The video playing in an overlay, with its transport controls.
+pub struct VideoOverlay {
+    decoder: Decoder,
+    /// Kept open while the video is. `None` for videos without audio, or when there's no
+    /// output device.
+    audio: Option<AudioOutput>,
+    clock: Clock,
+    volume: f32,
+    /// Which seek frames should come from.
+    serial: u64,
+    /// The next frame, once it's been taken from the decoder but isn't due yet.
+    next: Option<VideoFrame>,
+    /// Show the next frame whenever it arrives, so seeking while paused updates the picture.
+    show_next: bool,
+    ended: bool,
+    ended_reported: bool,
+    /// When `video://position` was last emitted, or `None` to emit it on the next frame.
+    status_emitted: Option<Instant>,
+    subtitle_tracks: Vec<SubtitleTrack>,
+    subtitle_track: Option<usize>,
+    /// The subtitle text last handed to the renderer, or `None` to hand it over again.
+    subtitle_shown: Option<Option<String>>,
+    frame_delay: f64,
+    frames_shown: u64,
+    frames_dropped: u64,
+}
+
+impl VideoOverlay {
+    /// Open a video file, paused at its start.
+    pub fn open(path: PathBuf) -> Result<Self, String> {
+        let audio = match AudioOutput::open() {
+            Ok(audio) => Some(audio),
+            Err(e) => {
+                println!("Failed to open audio output, playing without sound: {}", e);
+                None
+            }
+        };
+        let decoder = Decoder::open(path, audio.as_ref().map(|audio| audio.queue()))?;
+        Ok(VideoOverlay {
+            decoder,
+            audio,
+            clock: Clock::new(),
+            volume: 1.0,
+            serial: 0,
+            next: None,
+            show_next: true,
+            ended: false,
+            ended_reported: false,
+            status_emitted: None,
+            subtitle_tracks: Vec::new(),
+            subtitle_track: None,
+            subtitle_shown: None,
+            frame_delay: 0.0,
+            frames_shown: 0,
+            frames_dropped: 0,
+        })
+    }
+
+    pub fn play(&mut self) {
+        if self.ended {
+            self.seek(0.0);
+        }
+        self.set_playing(true);
+    }
+
+    pub fn pause(&mut self) {
+        self.set_playing(false);
+    }
+
+    fn set_playing(&mut self, playing: bool) {
+        self.clock.set_playing(playing);
+        if let Some(audio) = &self.audio {
+            audio.queue().set_playing(playing);
+        }
+        self.status_emitted = None;
+    }
+
+    /// Jump to `position` in seconds, clamped to the video.
+    pub fn seek(&mut self, position: f64) {
+        let position = position.clamp(0.0, self.decoder.info.duration);
+        self.decoder.seek(position);
+        self.clock.seek(position);
+        self.serial += 1;
+        if let Some(audio) = &self.audio {
+            audio.queue().reset(self.serial);
+        }
+        self.next = None;
+        self.show_next = true;
+        self.ended = false;
+        self.ended_reported = false;
+        self.status_emitted = None;
+    }
+
+    /// Play at `rate` times normal speed. Audio only plays at normal speed, and is
+    /// silent otherwise.
+    pub fn set_rate(&mut self, rate: f64) -> Result<(), String> {
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err("the playback rate must be positive".into());
+        }
+        self.clock.set_rate(rate);
+        if let Some(audio) = &self.audio {
+            audio.queue().set_enabled(rate == 1.0);
+        }
+        self.status_emitted = None;
+        Ok(())
+    }
+
+    /// Set the audio's volume, from 0 for silent to 1 for full.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+        if let Some(audio) = &self.audio {
+            audio.queue().set_volume(self.volume);
+        }
+        self.status_emitted = None;
+    }
+
+    pub fn status(&self) -> VideoStatus {
+        let info = self.decoder.info;
+        VideoStatus {
+            position: self.clock.position().clamp(0.0, info.duration),
+            duration: info.duration,
+            playing: self.clock.is_playing(),
+            rate: self.clock.rate(),
+            volume: self.volume,
+            width: info.width,
+            height: info.height,
+            subtitle_track: self.subtitle_track,
+        }
+    }
+
+    /// Add a SubRip or WebVTT track parsed from `source`, and show it.
+    pub fn add_subtitles(
+        &mut self,
+        label: String,
+        source: &str,
+    ) -> Result<SubtitleTrackInfo, String> {
+        let track = SubtitleTrack::parse(label, source)?;
+        let index = self.subtitle_tracks.len();
+        self.subtitle_tracks.push(track);
+        self.select_subtitle_track(Some(index))?;
+        Ok(self.subtitle_tracks[index].info(index))
+    }
+
+    pub fn subtitle_tracks(&self) -> Vec<SubtitleTrackInfo> {
+        self.subtitle_tracks
+            .iter()
+            .enumerate()
+            .map(|(index, track)| track.info(index))
+            .collect()
+    }
+
+    /// Show the track at `index`, or no subtitles with `None`.
+    pub fn select_subtitle_track(&mut self, index: Option<usize>) -> Result<(), String> {
+        if let Some(index) = index {
+            if index >= self.subtitle_tracks.len() {
+                return Err(format!("there's no subtitle track {}", index));
+            }
+        }
+        self.subtitle_track = index;
+        self.subtitle_shown = None;
+        self.status_emitted = None;
+        Ok(())
+    }
+
+    /// The subtitle text to show, if it's changed since the last call.
+    pub fn subtitle_due(&mut self) -> Option<Option<String>> {
+        let position = self.clock.position();
+        let text = self
+            .subtitle_track
+            .and_then(|index| self.subtitle_tracks[index].text_at(position));
+        if self.subtitle_shown.as_ref() == Some(&text) {
+            return None;
+        }
+        self.subtitle_shown = Some(text.clone());
+        Some(text)
+    }
+
+    pub fn playback_stats(&self) -> PlaybackStats {
+        let (sync_error, max_sync_error) = self.clock.sync_error();
+        PlaybackStats {
+            clock: self.clock.source(),
+            sync_error,
+            max_sync_error,
+            resyncs: self.clock.resyncs(),
+            frame_delay: self.frame_delay,
+            frames_shown: self.frames_shown,
+            frames_dropped: self.frames_dropped,
+        }
+    }
+
+    /// The latest frame that's come due since the last call, skipping any that are late.
+    /// This also syncs the clock, so it's called once per rendered frame.
+    pub fn frame_due(&mut self) -> Option<VideoFrame> {
+        let audio = self
+            .audio
+            .as_ref()
+            .and_then(|audio| audio.queue().position());
+        self.clock.sync(audio);
+        let position = self.clock.position();
+        let mut due = None;
+        loop {
+            if self.next.is_none() {
+                self.next = self.decoder.frames.try_recv().ok();
+            }
+            match &self.next {
+                Some(frame) if frame.serial != self.serial => self.next = None,
+                Some(frame) if self.show_next || frame.pts <= position => {
+                    self.show_next = false;
+                    if due.is_some() {
+                        self.frames_dropped += 1;
+                    }
+                    due = self.next.take();
+                }
+                Some(_) => break,
+                None => {
+                    if self.decoder.is_finished(self.serial) && self.clock.is_playing() {
+                        self.end();
+                    }
+                    break;
+                }
+            }
+        }
+        if let Some(frame) = &due {
+            self.frames_shown += 1;
+            self.frame_delay = (position - frame.pts).max(0.0);
+        }
+        due
+    }
+
+    fn end(&mut self) {
+        self.ended = true;
+        self.clock.seek(self.decoder.info.duration);
+        self.set_playing(false);
+    }
+
+    /// The status to emit as `video://position`, if it's time to.
+    pub fn status_due(&mut self) -> Option<VideoStatus> {
+        let due = match self.status_emitted {
+            Some(emitted) => self.clock.is_playing() && emitted.elapsed() >= STATUS_INTERVAL,
+            None => true,
+        };
+        if !due {
+            return None;
+        }
+        self.status_emitted = Some(Instant::now());
+        Some(self.status())
+    }
+
+    /// Whether the video has reached its end since this last returned true.
+    pub fn take_ended(&mut self) -> bool {
+        let ended = self.ended && !self.ended_reported;
+        self.ended_reported |= ended;
+        ended
+    }
+}
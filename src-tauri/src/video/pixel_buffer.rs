@@ -0,0 +1,68 @@
+//! VideoToolbox frames, which stay in IOSurfaces on the GPU.
+
+use std::ffi::c_void;
+
+use ffmpeg_next as ffmpeg;
+
+type CVPixelBufferRef = *mut c_void;
+pub type IOSurfaceRef = *mut c_void;
+
+/// '420v' and '420f': 8-bit NV12, in video or full range.
+const PIXEL_FORMAT_420V: u32 = 0x34323076;
+const PIXEL_FORMAT_420F: u32 = 0x34323066;
+
+#[link(name = "CoreVideo", kind = "framework")]
+extern "C" {
+    fn CVPixelBufferRetain(buffer: CVPixelBufferRef) -> CVPixelBufferRef;
+    fn CVPixelBufferRelease(buffer: CVPixelBufferRef);
+    fn CVPixelBufferGetIOSurface(buffer: CVPixelBufferRef) -> IOSurfaceRef;
+    fn CVPixelBufferGetPixelFormatType(buffer: CVPixelBufferRef) -> u32;
+    fn CVPixelBufferGetWidthOfPlane(buffer: CVPixelBufferRef, plane: usize) -> usize;
+    fn CVPixelBufferGetHeightOfPlane(buffer: CVPixelBufferRef, plane: usize) -> usize;
+}
+
+/// A retained NV12 pixel buffer backed by an IOSurface. Holding on to it keeps the decoder
+/// from reusing the surface.
+pub struct PixelBuffer(CVPixelBufferRef);
+
+// Pixel buffers are reference counted with atomics and can be used from any thread
+unsafe impl Send for PixelBuffer {}
+
+impl PixelBuffer {
+    /// The pixel buffer of a VideoToolbox frame, if it's NV12 in an IOSurface.
+    pub fn from_frame(frame: &ffmpeg::frame::Video) -> Option<Self> {
+        unsafe {
+            // VideoToolbox frames carry their pixel buffer in the last data pointer
+            let buffer = (*frame.as_ptr()).data[3] as CVPixelBufferRef;
+            if buffer.is_null() || CVPixelBufferGetIOSurface(buffer).is_null() {
+                return None;
+            }
+            match CVPixelBufferGetPixelFormatType(buffer) {
+                PIXEL_FORMAT_420V | PIXEL_FORMAT_420F => {
+                    Some(PixelBuffer(CVPixelBufferRetain(buffer)))
+                }
+                _ => None,
+            }
+        }
+    }
+
+    pub fn io_surface(&self) -> IOSurfaceRef {
+        unsafe { CVPixelBufferGetIOSurface(self.0) }
+    }
+
+    /// The width and height of plane 0 (luma) or 1 (chroma).
+    pub fn plane_size(&self, plane: usize) -> [u32; 2] {
+        unsafe {
+            [
+                CVPixelBufferGetWidthOfPlane(self.0, plane) as u32,
+                CVPixelBufferGetHeightOfPlane(self.0, plane) as u32,
+            ]
+        }
+    }
+}
+
+impl Drop for PixelBuffer {
+    fn drop(&mut self) {
+        unsafe { CVPixelBufferRelease(self.0) };
+    }
+}
@@ -0,0 +1,167 @@
+//! SubRip (.srt) and WebVTT (.vtt) subtitles.
+
+use serde::Serialize;
+
+/// One subtitle, shown from `start` until `end` seconds into the video.
+#[derive(Debug, Clone)]
+pub struct Cue {
+    pub start: f64,
+    pub end: f64,
+    /// Lines separated by newlines, with markup removed.
+    pub text: String,
+}
+
+/// A set of subtitles loaded from a file.
+pub struct SubtitleTrack {
+    pub label: String,
+    /// Sorted by start.
+    cues: Vec<Cue>,
+}
+
+/// A loaded subtitle track, as listed by `get_subtitle_tracks`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubtitleTrackInfo {
+    /// What `select_subtitle_track` takes to show the track.
+    pub index: usize,
+    pub label: String,
+    pub cues: usize,
+}
+
+impl SubtitleTrack {
+    /// Parse a SubRip or WebVTT file, telling them apart by WebVTT's header.
+    pub fn parse(label: String, source: &str) -> Result<Self, String> {
+        let source = source.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+        let webvtt = source.starts_with("WEBVTT");
+        let mut cues = Vec::new();
+        for block in source.split("\n\n") {
+            let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+            let timing = match lines.next() {
+                Some(timing) => timing,
+                // WebVTT's header, notes and styles, or stray blank lines
+                None => continue,
+            };
+            let (start, end) = parse_timing(timing)
+                .ok_or_else(|| format!("invalid cue timing {:?}", timing.trim()))?;
+            let text = lines.map(strip_markup).collect::<Vec<_>>().join("\n");
+            cues.push(Cue { start, end, text });
+        }
+        if cues.is_empty() {
+            let format = if webvtt { "WebVTT" } else { "SubRip" };
+            return Err(format!("no {} cues were found", format));
+        }
+        cues.sort_by(|a, b| {
+            a.start
+                .partial_cmp(&b.start)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        Ok(SubtitleTrack { label, cues })
+    }
+
+    pub fn info(&self, index: usize) -> SubtitleTrackInfo {
+        SubtitleTrackInfo {
+            index,
+            label: self.label.clone(),
+            cues: self.cues.len(),
+        }
+    }
+
+    /// The text showing at `position` seconds, with overlapping cues stacked in order.
+    pub fn text_at(&self, position: f64) -> Option<String> {
+        let started = self.cues.partition_point(|cue| cue.start <= position);
+        let showing: Vec<&str> = self.cues[..started]
+            .iter()
+            .filter(|cue| position < cue.end && !cue.text.is_empty())
+            .map(|cue| cue.text.as_str())
+            .collect();
+        match showing.is_empty() {
+            true => None,
+            false => Some(showing.join("\n")),
+        }
+    }
+}
+
+/// `00:01:02,345 --> 00:01:04,000`, with WebVTT's optional hours, dots and cue settings.
+fn parse_timing(line: &str) -> Option<(f64, f64)> {
+    let (start, end) = line.split_once("-->")?;
+    let end = end.split_whitespace().next()?;
+    Some((parse_timestamp(start.trim())?, parse_timestamp(end)?))
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<f64> {
+    let (clock, fraction) = timestamp.split_once([',', '.'])?;
+    let fraction: f64 = format!("0.{}", fraction).parse().ok()?;
+    let mut seconds = 0.0;
+    for part in clock.split(':') {
+        seconds = seconds * 60.0 + part.parse::<u32>().ok()? as f64;
+    }
+    Some(seconds + fraction)
+}
+
+/// Drop tags like `<i>` and `<c.yellow>`, SubRip's `{\an8}` overrides, and decode the
+/// entities WebVTT escapes.
+fn strip_markup(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut closing = None;
+    for c in line.chars() {
+        match (closing, c) {
+            (None, '<') => closing = Some('>'),
+            (None, '{') => closing = Some('}'),
+            (None, c) => text.push(c),
+            (Some(end), c) if c == end => closing = None,
+            (Some(_), _) => {}
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", "\u{a0}")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_subrip() {
+        let track = SubtitleTrack::parse(
+            "English".into(),
+            "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\n<i>Hello</i>\r\nthere\r\n\r\n\
+             2\r\n00:00:03,000 --> 00:00:04,000\r\n{\\an8}Bye &amp; thanks\r\n",
+        )
+        .unwrap();
+        assert_eq!(track.info(0).cues, 2);
+        assert_eq!(track.text_at(0.5), None);
+        assert_eq!(track.text_at(1.0).as_deref(), Some("Hello\nthere"));
+        assert_eq!(track.text_at(2.5), None);
+        assert_eq!(track.text_at(3.5).as_deref(), Some("Bye & thanks"));
+    }
+
+    #[test]
+    fn parses_webvtt() {
+        let track = SubtitleTrack::parse(
+            "Captions".into(),
+            "WEBVTT\n\nNOTE a comment\n\n\
+             01:02.000 --> 01:04.000 align:start\n<c.yellow>Later</c>\n\n\
+             00:00.500 --> 01:03.000\nFirst &lt;3\n",
+        )
+        .unwrap();
+        assert_eq!(track.text_at(1.0).as_deref(), Some("First <3"));
+        assert_eq!(track.text_at(62.5).as_deref(), Some("First <3\nLater"));
+        assert_eq!(track.text_at(63.5).as_deref(), Some("Later"));
+    }
+
+    #[test]
+    fn parses_timestamps_with_and_without_hours() {
+        assert_eq!(parse_timestamp("01:00:02,250"), Some(3602.25));
+        assert_eq!(parse_timestamp("01:02.5"), Some(62.5));
+        assert_eq!(parse_timestamp("01:02"), None);
+        assert_eq!(parse_timestamp("a:02.5"), None);
+    }
+
+    #[test]
+    fn rejects_files_without_cues() {
+        assert!(SubtitleTrack::parse("".into(), "WEBVTT\n\n").is_err());
+        assert!(SubtitleTrack::parse("".into(), "1\n00:00:01 --> 00:00:02\nHi\n").is_err());
+    }
+}
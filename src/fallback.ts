@@ -0,0 +1,659 @@
+// Draws the overlays that couldn't render natively, as in VMs without a usable GPU. The
+// backend announces them with `overlay://fallback`, and this draws each one's background,
+// panes, layers and HUD into a <canvas> over the page. Each of those is painted with the
+// 2D canvas API and then composited with WebGPU or, where there's none, WebGL, blending
+// as the native compositor does. Send overlay commands through `invokeOverlay` so the ones
+// that change what a fallback overlay shows are drawn here instead.
+
+import { listen } from "@tauri-apps/api/event";
+import { invoke } from "@tauri-apps/api/core";
+import { getCurrentWebviewWindow } from "@tauri-apps/api/webviewWindow";
+
+type Color = [number, number, number, number];
+type BlendMode = "normal" | "additive" | "multiply";
+
+interface OverlayFrame {
+	x: number;
+	y: number;
+	width: number;
+	height: number;
+}
+
+interface FallbackOverlay {
+	overlay: string;
+	window: string;
+	reason: string;
+	rect: OverlayFrame | null;
+}
+
+interface Layer {
+	id: string;
+	zIndex?: number;
+	visible?: boolean;
+	opacity?: number;
+	blend?: BlendMode;
+	color?: Color | null;
+	image?: string | null;
+	shader?: string | null;
+}
+
+type LayerUpdate = Pick<Layer, "zIndex" | "visible" | "opacity" | "blend">;
+
+// In physical pixels, relative to the overlay's top-left.
+interface Rect {
+	x: number;
+	y: number;
+	width: number;
+	height: number;
+}
+
+interface Pane {
+	id: string;
+	viewport: Rect;
+	scissor?: Rect | null;
+	clearColor?: Color;
+	image?: string | null;
+}
+
+// In physical pixels, relative to the overlay's top-left. SVG paths go straight to Path2D,
+// which reads the same syntax.
+type ClipPath = { type: "polygon"; points: [number, number][] } | { type: "svg"; d: string };
+
+// A painted canvas to blend over what's beneath it. Canvases hold straight alpha, which
+// the renderers premultiply as they upload it.
+interface Sprite {
+	source: HTMLCanvasElement;
+	opacity: number;
+	blend: BlendMode;
+}
+
+interface Renderer {
+	draw(sprites: Sprite[]): void;
+}
+
+// Matches the backend's
+const DEFAULT_CLEAR_COLOR: Color = [0.1, 0.2, 0.3, 1.0];
+// Where native overlays go when they aren't placed: 30% of the window's width and 10%
+// of its height, centered 100 physical pixels from the top
+const DEFAULT_WIDTH = 0.3;
+const DEFAULT_HEIGHT = 0.1;
+const DEFAULT_TOP = 100;
+// The HUD's placement and colors, in logical pixels, as the native one's
+const HUD_MARGIN = 8;
+const HUD_PADDING = 2;
+const HUD_FONT_SIZE = 10;
+const HUD_BACKGROUND = "rgba(0, 0, 0, 0.63)";
+const HUD_INTERVAL = 500;
+
+// Commands whose `id` names an asset rather than an overlay.
+const ASSET_COMMANDS = new Set([
+	"load_asset",
+	"load_shader",
+	"filter_asset",
+	"unload_asset",
+	"get_asset",
+]);
+
+const overlays = new Map<string, FallbackCanvas>();
+// Texture assets, as the page loaded them for its fallback overlays.
+const images = new Map<string, ImageBitmap>();
+const warnings = new Set<string>();
+
+// Start drawing this window's fallback overlays, including any announced before the page
+// was listening.
+export async function startFallbacks(): Promise<void> {
+	const label = getCurrentWebviewWindow().label;
+	const add = async (fallback: FallbackOverlay) => {
+		if (fallback.window !== label || overlays.has(fallback.overlay)) {
+			return;
+		}
+		console.warn(`Drawing overlay ${fallback.overlay} in the page: ${fallback.reason}`);
+		overlays.set(fallback.overlay, await FallbackCanvas.create(fallback));
+	};
+	await listen<FallbackOverlay>("overlay://fallback", (event) => add(event.payload));
+	for (const fallback of await invoke<FallbackOverlay[]>("get_fallback_overlays")) {
+		await add(fallback);
+	}
+}
+
+// `invoke` an overlay command, or carry it out here if the overlay is a fallback one.
+export function invokeOverlay<T>(command: string, args: Record<string, unknown> = {}): Promise<T> {
+	if (ASSET_COMMANDS.has(command)) {
+		return invokeAsset<T>(command, args);
+	}
+	const fallback = overlays.get((args.id as string | undefined) ?? "main");
+	if (!fallback) {
+		return invoke<T>(command, args);
+	}
+	try {
+		fallback.handle(command, args);
+		return Promise.resolve(undefined as unknown as T);
+	} catch (e) {
+		return Promise.reject(e instanceof Error ? e.message : String(e));
+	}
+}
+
+// Texture assets are loaded here as well while there are fallback overlays to draw them.
+// The backend may have no GPU to load them with, so only the page's load has to work then.
+async function invokeAsset<T>(command: string, args: Record<string, unknown>): Promise<T> {
+	const id = args.id as string;
+	if (command === "unload_asset" && images.delete(id)) {
+		redrawAll();
+	}
+	if (command !== "load_asset" || args.kind !== "texture" || overlays.size === 0) {
+		return invoke<T>(command, args);
+	}
+	const native = invoke<T>(command, args).catch((e) => {
+		console.warn(`Texture ${id} is only loaded for the page's overlays: ${e}`);
+		return undefined as unknown as T;
+	});
+	await loadImage(id, args.path as string);
+	return native;
+}
+
+async function loadImage(id: string, path: string) {
+	const bytes = await invoke<ArrayBuffer>("read_fallback_image", { path });
+	images.set(id, await createImageBitmap(new Blob([bytes])));
+	redrawAll();
+}
+
+function redrawAll() {
+	for (const overlay of overlays.values()) {
+		overlay.draw();
+	}
+}
+
+// Warn about something the page can't draw, once per `key`.
+function warnOnce(key: string, message: string) {
+	if (!warnings.has(key)) {
+		warnings.add(key);
+		console.warn(message);
+	}
+}
+
+class FallbackCanvas {
+	private clearColor: Color = DEFAULT_CLEAR_COLOR;
+	private panes: Pane[] = [];
+	// Sorted bottom to top.
+	private layers: Layer[] = [];
+	private clip: ClipPath | null = null;
+	// The page's frame rate, since it's what draws the overlay, or null while it's hidden.
+	private hud: { text: string; frames: number; since: number } | null = null;
+	// Painted canvases, reused from draw to draw.
+	private surfaces: HTMLCanvasElement[] = [];
+
+	private constructor(
+		private fallback: FallbackOverlay,
+		private canvas: HTMLCanvasElement,
+		private renderer: Renderer
+	) {
+		this.place();
+		window.addEventListener("resize", () => this.place());
+	}
+
+	static async create(fallback: FallbackOverlay): Promise<FallbackCanvas> {
+		const canvas = document.createElement("canvas");
+		canvas.dataset.overlay = fallback.overlay;
+		canvas.style.position = "fixed";
+		canvas.style.pointerEvents = "none";
+		document.body.appendChild(canvas);
+		const renderer = (await WebGpuRenderer.create(canvas)) ?? new WebGlRenderer(canvas);
+		return new FallbackCanvas(fallback, canvas, renderer);
+	}
+
+	handle(command: string, args: Record<string, unknown>) {
+		switch (command) {
+			case "set_clear_color":
+				this.clearColor = args.color as Color;
+				break;
+			case "set_panes":
+				this.panes = [...(args.panes as Pane[])];
+				break;
+			case "set_clip_path":
+				this.clip = (args.path as ClipPath | undefined) ?? null;
+				break;
+			case "set_layers": {
+				const layers = args.layers as Layer[];
+				const ids = new Set(layers.map((layer) => layer.id));
+				if (ids.size !== layers.length) {
+					throw new Error("duplicate layer id");
+				}
+				this.layers = [...layers];
+				this.sortLayers();
+				break;
+			}
+			case "update_layer": {
+				const layer = this.layers.find((layer) => layer.id === args.layer);
+				if (!layer) {
+					throw new Error(`no layer '${args.layer}'`);
+				}
+				Object.assign(layer, args.update as LayerUpdate);
+				this.sortLayers();
+				break;
+			}
+			case "set_overlay_hud":
+				this.setHudVisible(args.visible as boolean);
+				break;
+			case "set_overlay_visible":
+				this.canvas.style.display = args.visible ? "" : "none";
+				return;
+			case "set_overlay_position":
+				this.fallback.rect = {
+					...this.rect(),
+					x: args.x as number,
+					y: args.y as number,
+				};
+				this.place();
+				return;
+			default:
+				warnOnce(
+					command,
+					`${command} does nothing while overlay ${this.fallback.overlay} is drawn by the page`
+				);
+				return;
+		}
+		this.draw();
+	}
+
+	draw() {
+		const background = this.paint(0, (context) => this.paintBackground(context));
+		const sprites: Sprite[] = [{ source: background, opacity: 1, blend: "normal" }];
+		for (const layer of this.layers) {
+			if (!(layer.visible ?? true)) {
+				continue;
+			}
+			sprites.push({
+				source: this.paint(sprites.length, (context) => this.paintLayer(context, layer)),
+				opacity: clamp(layer.opacity ?? 1),
+				blend: layer.blend ?? "normal",
+			});
+		}
+		if (this.hud) {
+			const hud = this.hud.text;
+			sprites.push({
+				source: this.paint(sprites.length, (context) => paintHud(context, hud), false),
+				opacity: 1,
+				blend: "normal",
+			});
+		}
+		this.renderer.draw(sprites);
+	}
+
+	private sortLayers() {
+		// Stable, so ties keep the order they were given in
+		this.layers.sort((a, b) => (a.zIndex ?? 0) - (b.zIndex ?? 0));
+	}
+
+	// Paint the `index`th surface from scratch, confined to the clip path if `clipped`.
+	private paint(
+		index: number,
+		painter: (context: CanvasRenderingContext2D) => void,
+		clipped = true
+	): HTMLCanvasElement {
+		const surface = (this.surfaces[index] ??= document.createElement("canvas"));
+		if (surface.width !== this.canvas.width || surface.height !== this.canvas.height) {
+			surface.width = this.canvas.width;
+			surface.height = this.canvas.height;
+		}
+		const context = surface.getContext("2d")!;
+		context.clearRect(0, 0, surface.width, surface.height);
+		context.save();
+		if (clipped && this.clip) {
+			context.clip(clipPath(this.clip), "evenodd");
+		}
+		painter(context);
+		context.restore();
+		return surface;
+	}
+
+	private paintBackground(context: CanvasRenderingContext2D) {
+		context.fillStyle = cssColor(this.clearColor);
+		context.fillRect(0, 0, context.canvas.width, context.canvas.height);
+		for (const pane of this.panes) {
+			const { viewport } = pane;
+			const scissor = pane.scissor ?? viewport;
+			if (viewport.width <= 0 || viewport.height <= 0) {
+				continue;
+			}
+			context.save();
+			context.beginPath();
+			context.rect(scissor.x, scissor.y, scissor.width, scissor.height);
+			context.clip();
+			context.fillStyle = cssColor(pane.clearColor ?? [0, 0, 0, 0]);
+			context.fillRect(viewport.x, viewport.y, viewport.width, viewport.height);
+			const image = pane.image && this.image(pane.image);
+			if (image) {
+				context.drawImage(image, viewport.x, viewport.y, viewport.width, viewport.height);
+			}
+			context.restore();
+		}
+	}
+
+	private paintLayer(context: CanvasRenderingContext2D, layer: Layer) {
+		const { width, height } = context.canvas;
+		if (layer.color) {
+			context.fillStyle = cssColor(layer.color);
+			context.fillRect(0, 0, width, height);
+		}
+		const image = layer.image && this.image(layer.image);
+		if (image) {
+			context.drawImage(image, 0, 0, width, height);
+		}
+		if (layer.shader) {
+			warnOnce(
+				`shader:${layer.shader}`,
+				`Shader ${layer.shader} isn't drawn in overlay ${this.fallback.overlay}, ` +
+					"since the page can't run ISF shaders"
+			);
+		}
+	}
+
+	private image(id: string): ImageBitmap | undefined {
+		const image = images.get(id);
+		if (!image) {
+			warnOnce(
+				`image:${id}`,
+				`Texture ${id} isn't drawn in overlay ${this.fallback.overlay}: ` +
+					"load it through invokeOverlay once the overlay has fallen back"
+			);
+		}
+		return image;
+	}
+
+	// Count the page's frames while the HUD shows, redrawing it as its readout changes.
+	private setHudVisible(visible: boolean) {
+		if (!visible) {
+			this.hud = null;
+			return;
+		}
+		if (this.hud) {
+			return;
+		}
+		const hud = (this.hud = { text: "-- FPS", frames: 0, since: performance.now() });
+		const count = (now: number) => {
+			if (this.hud !== hud) {
+				return;
+			}
+			hud.frames += 1;
+			if (now - hud.since >= HUD_INTERVAL) {
+				hud.text = `${Math.round((hud.frames * 1000) / (now - hud.since))} FPS`;
+				hud.frames = 0;
+				hud.since = now;
+				this.draw();
+			}
+			requestAnimationFrame(count);
+		};
+		requestAnimationFrame(count);
+	}
+
+	// Where the overlay goes, in CSS pixels.
+	private rect(): OverlayFrame {
+		if (this.fallback.rect) {
+			return this.fallback.rect;
+		}
+		const width = window.innerWidth * DEFAULT_WIDTH;
+		return {
+			x: (window.innerWidth - width) / 2,
+			y: DEFAULT_TOP / window.devicePixelRatio,
+			width,
+			height: window.innerHeight * DEFAULT_HEIGHT,
+		};
+	}
+
+	private place() {
+		const rect = this.rect();
+		this.canvas.style.left = `${rect.x}px`;
+		this.canvas.style.top = `${rect.y}px`;
+		this.canvas.style.width = `${rect.width}px`;
+		this.canvas.style.height = `${rect.height}px`;
+		this.canvas.width = Math.max(1, Math.round(rect.width * window.devicePixelRatio));
+		this.canvas.height = Math.max(1, Math.round(rect.height * window.devicePixelRatio));
+		this.draw();
+	}
+}
+
+function paintHud(context: CanvasRenderingContext2D, text: string) {
+	const scale = window.devicePixelRatio;
+	const margin = HUD_MARGIN * scale;
+	const padding = HUD_PADDING * scale;
+	const size = HUD_FONT_SIZE * scale;
+	context.font = `bold ${size}px monospace`;
+	context.textBaseline = "top";
+	const width = context.measureText(text).width;
+	context.fillStyle = HUD_BACKGROUND;
+	context.fillRect(margin, margin, width + 2 * padding, size + 2 * padding);
+	context.fillStyle = "white";
+	context.fillText(text, margin + padding, margin + padding);
+}
+
+function clipPath(clip: ClipPath): Path2D {
+	if (clip.type === "svg") {
+		return new Path2D(clip.d);
+	}
+	const path = new Path2D();
+	clip.points.forEach(([x, y], i) => (i === 0 ? path.moveTo(x, y) : path.lineTo(x, y)));
+	path.closePath();
+	return path;
+}
+
+// A straight alpha `color` as CSS.
+function cssColor([r, g, b, a]: Color): string {
+	const channel = (value: number) => Math.round(clamp(value) * 255);
+	return `rgba(${channel(r)}, ${channel(g)}, ${channel(b)}, ${clamp(a)})`;
+}
+
+function clamp(value: number): number {
+	return Math.min(1, Math.max(0, value));
+}
+
+const SPRITE_WGSL = `
+@group(0) @binding(0) var image: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> opacity: vec4<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+	// One oversized triangle that covers the whole canvas
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+	// Sprites are as big as the canvas, so pixels line up one to one
+	return textureLoad(image, vec2<i32>(position.xy), 0) * opacity.x;
+}
+`;
+
+// The same blending as the native compositor's, over premultiplied colors.
+const WEBGPU_BLENDS = {
+	normal: {
+		color: { srcFactor: "one", dstFactor: "one-minus-src-alpha" },
+		alpha: { srcFactor: "one", dstFactor: "one-minus-src-alpha" },
+	},
+	additive: {
+		color: { srcFactor: "one", dstFactor: "one" },
+		alpha: { srcFactor: "one", dstFactor: "one-minus-src-alpha" },
+	},
+	multiply: {
+		color: { srcFactor: "dst", dstFactor: "one-minus-src-alpha" },
+		alpha: { srcFactor: "one", dstFactor: "one-minus-src-alpha" },
+	},
+};
+
+// GPUTextureUsage and GPUBufferUsage flags
+const COPY_DST = 0x02;
+const TEXTURE_BINDING = 0x04;
+const RENDER_ATTACHMENT = 0x10;
+const UNIFORM = 0x40;
+
+// The page's WebGPU types aren't installed, so it's used untyped
+class WebGpuRenderer implements Renderer {
+	private constructor(
+		private device: any,
+		private context: any,
+		private pipelines: Record<BlendMode, any>
+	) {}
+
+	// A renderer for `canvas`, or undefined if WebGPU isn't available.
+	static async create(canvas: HTMLCanvasElement): Promise<WebGpuRenderer | undefined> {
+		const gpu = (navigator as any).gpu;
+		try {
+			const adapter = await gpu?.requestAdapter();
+			const device = await adapter?.requestDevice();
+			const context = canvas.getContext("webgpu" as any) as any;
+			if (!device || !context) {
+				return undefined;
+			}
+			const format = gpu.getPreferredCanvasFormat();
+			context.configure({ device, format, alphaMode: "premultiplied" });
+			const module = device.createShaderModule({ code: SPRITE_WGSL });
+			const pipeline = (blend: BlendMode) =>
+				device.createRenderPipeline({
+					layout: "auto",
+					vertex: { module, entryPoint: "vs_main" },
+					fragment: {
+						module,
+						entryPoint: "fs_main",
+						targets: [{ format, blend: WEBGPU_BLENDS[blend] }],
+					},
+				});
+			return new WebGpuRenderer(device, context, {
+				normal: pipeline("normal"),
+				additive: pipeline("additive"),
+				multiply: pipeline("multiply"),
+			});
+		} catch (e) {
+			console.warn("WebGPU isn't available, so WebGL draws fallback overlays:", e);
+			return undefined;
+		}
+	}
+
+	draw(sprites: Sprite[]) {
+		const encoder = this.device.createCommandEncoder();
+		const pass = encoder.beginRenderPass({
+			colorAttachments: [
+				{
+					view: this.context.getCurrentTexture().createView(),
+					clearValue: { r: 0, g: 0, b: 0, a: 0 },
+					loadOp: "clear",
+					storeOp: "store",
+				},
+			],
+		});
+		const textures: any[] = [];
+		for (const sprite of sprites) {
+			const size = [sprite.source.width, sprite.source.height];
+			const texture = this.device.createTexture({
+				size,
+				format: "rgba8unorm",
+				usage: TEXTURE_BINDING | COPY_DST | RENDER_ATTACHMENT,
+			});
+			this.device.queue.copyExternalImageToTexture(
+				{ source: sprite.source },
+				{ texture, premultipliedAlpha: true },
+				size
+			);
+			textures.push(texture);
+
+			const pipeline = this.pipelines[sprite.blend];
+			const buffer = this.device.createBuffer({ size: 16, usage: UNIFORM | COPY_DST });
+			this.device.queue.writeBuffer(buffer, 0, new Float32Array([sprite.opacity, 0, 0, 0]));
+			const bindGroup = this.device.createBindGroup({
+				layout: pipeline.getBindGroupLayout(0),
+				entries: [
+					{ binding: 0, resource: texture.createView() },
+					{ binding: 1, resource: { buffer } },
+				],
+			});
+			pass.setPipeline(pipeline);
+			pass.setBindGroup(0, bindGroup);
+			pass.draw(3);
+		}
+		pass.end();
+		this.device.queue.submit([encoder.finish()]);
+		// They're only freed once the work that uses them is done
+		for (const texture of textures) {
+			texture.destroy();
+		}
+	}
+}
+
+const SPRITE_VERTEX_GLSL = `
+attribute vec2 position;
+varying vec2 uv;
+void main() {
+	uv = position * 0.5 + 0.5;
+	gl_Position = vec4(position, 0.0, 1.0);
+}
+`;
+
+const SPRITE_FRAGMENT_GLSL = `
+precision mediump float;
+uniform sampler2D image;
+uniform float opacity;
+varying vec2 uv;
+void main() {
+	gl_FragColor = texture2D(image, uv) * opacity;
+}
+`;
+
+class WebGlRenderer implements Renderer {
+	private gl: WebGLRenderingContext;
+	private opacity: WebGLUniformLocation;
+
+	constructor(canvas: HTMLCanvasElement) {
+		const gl = canvas.getContext("webgl", { premultipliedAlpha: true, alpha: true });
+		if (!gl) {
+			throw new Error("neither WebGPU nor WebGL is available to draw the overlay");
+		}
+		const program = gl.createProgram()!;
+		for (const [type, source] of [
+			[gl.VERTEX_SHADER, SPRITE_VERTEX_GLSL],
+			[gl.FRAGMENT_SHADER, SPRITE_FRAGMENT_GLSL],
+		] as const) {
+			const shader = gl.createShader(type)!;
+			gl.shaderSource(shader, source);
+			gl.compileShader(shader);
+			gl.attachShader(program, shader);
+		}
+		gl.linkProgram(program);
+		gl.useProgram(program);
+
+		// One oversized triangle that covers the whole canvas
+		gl.bindBuffer(gl.ARRAY_BUFFER, gl.createBuffer());
+		gl.bufferData(gl.ARRAY_BUFFER, new Float32Array([-1, -1, 3, -1, -1, 3]), gl.STATIC_DRAW);
+		const position = gl.getAttribLocation(program, "position");
+		gl.enableVertexAttribArray(position);
+		gl.vertexAttribPointer(position, 2, gl.FLOAT, false, 0, 0);
+		gl.enable(gl.BLEND);
+
+		// Sprites aren't powers of two, which WebGL can only sample without mipmaps or
+		// wrapping. Canvases upload top row first, where GL's textures start at the bottom.
+		gl.bindTexture(gl.TEXTURE_2D, gl.createTexture());
+		gl.texParameteri(gl.TEXTURE_2D, gl.TEXTURE_MIN_FILTER, gl.NEAREST);
+		gl.texParameteri(gl.TEXTURE_2D, gl.TEXTURE_MAG_FILTER, gl.NEAREST);
+		gl.texParameteri(gl.TEXTURE_2D, gl.TEXTURE_WRAP_S, gl.CLAMP_TO_EDGE);
+		gl.texParameteri(gl.TEXTURE_2D, gl.TEXTURE_WRAP_T, gl.CLAMP_TO_EDGE);
+		gl.pixelStorei(gl.UNPACK_PREMULTIPLY_ALPHA_WEBGL, true);
+		gl.pixelStorei(gl.UNPACK_FLIP_Y_WEBGL, true);
+
+		this.gl = gl;
+		this.opacity = gl.getUniformLocation(program, "opacity")!;
+	}
+
+	draw(sprites: Sprite[]) {
+		const gl = this.gl;
+		gl.viewport(0, 0, gl.drawingBufferWidth, gl.drawingBufferHeight);
+		gl.clearColor(0, 0, 0, 0);
+		gl.clear(gl.COLOR_BUFFER_BIT);
+		for (const sprite of sprites) {
+			gl.texImage2D(gl.TEXTURE_2D, 0, gl.RGBA, gl.RGBA, gl.UNSIGNED_BYTE, sprite.source);
+			// The same blending as the native compositor's, over premultiplied colors
+			const source = sprite.blend === "multiply" ? gl.DST_COLOR : gl.ONE;
+			const destination = sprite.blend === "additive" ? gl.ONE : gl.ONE_MINUS_SRC_ALPHA;
+			gl.blendFuncSeparate(source, destination, gl.ONE, gl.ONE_MINUS_SRC_ALPHA);
+			gl.uniform1f(this.opacity, sprite.opacity);
+			gl.drawArrays(gl.TRIANGLES, 0, 3);
+		}
+	}
+}